@@ -0,0 +1,63 @@
+//! Compares hashing socket addresses with looking them up in the identifier
+//! cache as done for every routing decision and measures the arithmetic on
+//! identifiers.
+//!
+//! Run with `cargo bench --bench identifier`.
+
+extern crate chord;
+
+use chord::routing::identifier::{Identifier, IdentifierCache, Identify};
+use std::hint::black_box;
+use std::net::{SocketAddr, SocketAddrV4};
+use std::time::Instant;
+
+const ITERATIONS: u32 = 1_000_000;
+
+fn bench<F: FnMut()>(name: &str, mut f: F) {
+    let start = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        f();
+    }
+
+    let elapsed = start.elapsed();
+
+    println!(
+        "{:<12} {:>8.1} ns/op",
+        name,
+        elapsed.as_nanos() as f64 / f64::from(ITERATIONS)
+    );
+}
+
+fn main() {
+    let v4: SocketAddrV4 = "10.0.0.1:8080".parse().unwrap();
+    let socket_addr = SocketAddr::V4(v4);
+    let cache = IdentifierCache::new(16);
+
+    bench("sha256", || {
+        black_box(black_box(v4).identifier());
+    });
+
+    bench("cached", || {
+        black_box(cache.identifier(black_box(socket_addr)));
+    });
+
+    let id1 = Identifier::new(&[0x5a; 32]);
+    let id2 = Identifier::new(&[0xc3; 32]);
+
+    bench("add", || {
+        black_box(black_box(id1) + black_box(id2));
+    });
+
+    bench("sub", || {
+        black_box(black_box(id1) - black_box(id2));
+    });
+
+    bench("compare", || {
+        black_box(black_box(id1) < black_box(id2));
+    });
+
+    bench("is_between", || {
+        black_box(black_box(id1).is_between(&id2, &id1));
+    });
+}
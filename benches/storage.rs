@@ -0,0 +1,178 @@
+//! Measures the local storage of a peer the way the handlers use it: puts
+//! which only replace older versions, reads which skip expired records and
+//! the batched snapshots taken for transfers, for several value sizes and
+//! with several threads sharing the storage lock.
+//!
+//! Run with `cargo bench --bench storage`.
+
+extern crate chord;
+extern crate criterion;
+
+use bytes::Bytes;
+use chord::storage::{self, Key, Record, Storage};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of records in the storage for reads and snapshots
+const RECORDS: u32 = 10_000;
+
+const VALUE_SIZES: [usize; 3] = [64, 1024, 16 * 1024];
+
+const THREADS: [u32; 4] = [1, 2, 4, 8];
+
+const TTL: Duration = Duration::from_secs(3600);
+
+fn key(i: u32) -> Key {
+    let mut raw_key = [0; 32];
+    raw_key[..4].copy_from_slice(&i.to_be_bytes());
+
+    Key {
+        raw_key,
+        replication_index: 0,
+    }
+}
+
+fn filled(value_size: usize) -> Mutex<Storage> {
+    let storage = (0..RECORDS)
+        .map(|i| (key(i), Record::new(vec![0; value_size], TTL, 1)))
+        .collect();
+
+    Mutex::new(storage)
+}
+
+fn get(storage: &Mutex<Storage>, key: &Key) -> Option<Bytes> {
+    let storage = storage.lock().unwrap();
+
+    storage
+        .get(key)
+        .filter(|record| !record.is_expired())
+        .map(|record| record.value.clone())
+}
+
+fn put(c: &mut Criterion) {
+    let mut group = c.benchmark_group("put");
+
+    for &value_size in &VALUE_SIZES {
+        group.throughput(Throughput::Bytes(value_size as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(value_size),
+            &value_size,
+            |b, &value_size| {
+                let storage = Mutex::new(Storage::new());
+                let value = vec![0; value_size];
+                let mut version = 0;
+
+                b.iter(|| {
+                    version += 1;
+                    let record = Record::new(value.clone(), TTL, version);
+                    let key = key(version as u32 % RECORDS);
+
+                    black_box(storage::put_newer(
+                        &mut storage.lock().unwrap(),
+                        key,
+                        record,
+                    ));
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get");
+
+    for &value_size in &VALUE_SIZES {
+        let storage = filled(value_size);
+        let mut i = 0;
+
+        group.throughput(Throughput::Bytes(value_size as u64));
+        group.bench_function(BenchmarkId::from_parameter(value_size), |b| {
+            b.iter(|| {
+                i = (i + 1) % RECORDS;
+
+                black_box(get(&storage, &key(i)));
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("snapshot");
+    group.sample_size(10);
+
+    for &value_size in &VALUE_SIZES {
+        let storage = filled(value_size);
+
+        group.throughput(Throughput::Elements(u64::from(RECORDS)));
+        group.bench_function(BenchmarkId::from_parameter(value_size), |b| {
+            b.iter(|| black_box(storage::snapshot(&storage, |_| true)));
+        });
+    }
+
+    group.finish();
+}
+
+/// Splits the iterations between `threads` threads which alternate between
+/// reads and puts on the same storage.
+///
+/// The puts take increasing versions from a shared counter such that they
+/// replace the stored records in every measurement.
+fn concurrent(c: &mut Criterion) {
+    let mut group = c.benchmark_group("concurrent");
+
+    for &threads in &THREADS {
+        let storage = Arc::new(filled(1024));
+        let versions = Arc::new(AtomicU64::new(1));
+
+        group.bench_function(BenchmarkId::from_parameter(threads), |b| {
+            b.iter_custom(|iters| {
+                let per_thread = iters / u64::from(threads) + 1;
+                let start = Instant::now();
+
+                let handles: Vec<_> = (0..threads)
+                    .map(|t| {
+                        let storage = Arc::clone(&storage);
+                        let versions = Arc::clone(&versions);
+
+                        thread::spawn(move || {
+                            for n in 0..per_thread {
+                                let i = (n as u32).wrapping_mul(threads).wrapping_add(t) % RECORDS;
+
+                                if n % 2 == 0 {
+                                    black_box(get(&storage, &key(i)));
+                                } else {
+                                    let version = versions.fetch_add(1, Ordering::Relaxed);
+                                    let record = Record::new(vec![0; 1024], TTL, version);
+                                    storage::put_newer(
+                                        &mut storage.lock().unwrap(),
+                                        key(i),
+                                        record,
+                                    );
+                                }
+                            }
+                        })
+                    })
+                    .collect();
+
+                for handle in handles {
+                    handle.join().unwrap();
+                }
+
+                start.elapsed()
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, put, read, scan, concurrent);
+criterion_main!(benches);
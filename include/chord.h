@@ -0,0 +1,66 @@
+/*
+ * C interface for embedding a node of the chord DHT
+ *
+ * Build the library with
+ *
+ *     cargo rustc --lib --release --features cdylib --crate-type cdylib
+ *
+ * and link against target/release/libchord.so. See the documentation of the
+ * `ffi` module for details.
+ */
+
+#ifndef CHORD_H
+#define CHORD_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Returned if the arguments are invalid or the operation failed */
+#define DHT_ERROR (-1)
+
+/* Returned by dht_node_get if no value is stored under the key */
+#define DHT_NOT_FOUND (-2)
+
+/* A running node */
+typedef struct DhtNode dht_node;
+
+/*
+ * Starts a node with the configuration file at config_path which joins the
+ * network via the peer at bootstrap, e.g. "127.0.0.1:8080", or creates a new
+ * network if bootstrap is NULL. Returns NULL if the node could not be
+ * started.
+ */
+dht_node *dht_node_start(const char *config_path, const char *bootstrap);
+
+/*
+ * Makes the node hand its records to its successor and leave the network,
+ * then releases the handle. Returns 0 or DHT_ERROR.
+ */
+int dht_node_stop(dht_node *node);
+
+/*
+ * Stores value under key, which is at most 32 bytes, for ttl seconds on
+ * replication replicas. Returns the number of replicas which confirmed the
+ * value or DHT_ERROR.
+ */
+int dht_node_put(const dht_node *node, const uint8_t *key, size_t key_len,
+                 const uint8_t *value, size_t value_len, uint16_t ttl,
+                 uint8_t replication);
+
+/*
+ * Copies up to buffer_len bytes of the value stored under key into buffer.
+ * Returns the length of the value, which may exceed buffer_len, DHT_NOT_FOUND
+ * or DHT_ERROR.
+ */
+int64_t dht_node_get(const dht_node *node, const uint8_t *key, size_t key_len,
+                     uint8_t *buffer, size_t buffer_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CHORD_H */
@@ -0,0 +1,237 @@
+//! Append-only log of all mutating operations
+//!
+//! Deployments which store regulated data can record who changed which key
+//! and when in an [`AuditLog`]. The [`ApiHandler`] logs every `DHT PUT` and
+//! `DHT DELETE` which passed its authorizer as well as the administrative
+//! requests which change the peer, i.e. `DHT FLUSH`, `DHT IMPORT`,
+//! `NODE DRAIN` and `NODE READ ONLY`. The [`P2PHandler`] logs every value it
+//! actually stores or removes on behalf of another peer, including the
+//! values handed over when peers join or leave.
+//!
+//! The log contains one JSON object per line:
+//!
+//! ```text
+//! {"time":1554980000123456,"requester":"client","address":"127.0.0.1:50712","operation":"PUT","key":"0303...","size":3}
+//! ```
+//!
+//! `time` is the number of microseconds since the unix epoch, `requester`
+//! is either `client` or `peer` and `size` the number of bytes of the
+//! stored values. Administrative requests are logged with the name of their
+//! message type as operation and without a key.
+//!
+//! Once the log exceeds its maximum size, it is rotated like `logrotate`
+//! does: `audit.log` is renamed to `audit.log.1`, `audit.log.1` to
+//! `audit.log.2` and so on, while the oldest file is removed.
+//!
+//! [`AuditLog`]: struct.AuditLog.html
+//! [`ApiHandler`]: ../handler/struct.ApiHandler.html
+//! [`P2PHandler`]: ../handler/struct.P2PHandler.html
+
+use crate::auth::{Operation, Requester};
+use crate::sync::MutexExt;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of rotated files kept by default
+pub const DEFAULT_FILES: usize = 10;
+
+/// The open log file and its current size
+struct Writer {
+    file: LineWriter<File>,
+    size: u64,
+}
+
+/// An append-only log of mutating operations
+///
+/// See the [module documentation] for the format.
+///
+/// [module documentation]: index.html
+pub struct AuditLog {
+    path: PathBuf,
+    max_size: Option<u64>,
+    files: usize,
+    writer: Mutex<Writer>,
+}
+
+impl AuditLog {
+    /// Opens the log at `path` and appends to it if it exists already.
+    ///
+    /// The log is not rotated unless [`with_rotation`] is used.
+    ///
+    /// [`with_rotation`]: #method.with_rotation
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let writer = Mutex::new(Self::open_writer(&path)?);
+
+        Ok(Self {
+            path,
+            max_size: None,
+            files: DEFAULT_FILES,
+            writer,
+        })
+    }
+
+    /// Rotates the log once it exceeds `max_size` bytes and keeps `files`
+    /// rotated files.
+    ///
+    /// With `files` set to zero, the log is truncated instead.
+    pub fn with_rotation(mut self, max_size: Option<u64>, files: usize) -> Self {
+        self.max_size = max_size;
+        self.files = files;
+        self
+    }
+
+    fn open_writer(path: &Path) -> io::Result<Writer> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Writer {
+            file: LineWriter::new(file),
+            size,
+        })
+    }
+
+    /// Returns the path of the rotated file with the given `index`.
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(format!(".{}", index));
+
+        PathBuf::from(path)
+    }
+
+    fn rotate(&self, writer: &mut Writer) -> io::Result<()> {
+        if self.files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.files).rev() {
+                match fs::rename(self.rotated_path(index), self.rotated_path(index + 1)) {
+                    Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+                    result => result?,
+                }
+            }
+
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+
+        *writer = Self::open_writer(&self.path)?;
+
+        Ok(())
+    }
+
+    /// Appends an entry for `operation` of `requester` which stores `size`
+    /// bytes.
+    ///
+    /// Errors are only logged such that a full disk does not interrupt the
+    /// operation of the peer.
+    pub fn record(&self, requester: Requester, operation: Operation, size: usize) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_micros() as u64)
+            .unwrap_or(0);
+
+        let (requester, address) = match requester {
+            Requester::Client(address) => ("client", address),
+            Requester::Peer(address) => ("peer", address),
+        };
+
+        let (operation, key) = match operation {
+            Operation::Get(key) => ("GET", Some(key)),
+            Operation::Put(key) => ("PUT", Some(key)),
+            Operation::Delete(key) => ("DELETE", Some(key)),
+            Operation::Admin(name) => (name, None),
+        };
+
+        let key = match key {
+            Some(key) => format!("\"{}\"", key.to_hex()),
+            None => "null".to_string(),
+        };
+
+        let line = format!(
+            "{{\"time\":{},\"requester\":\"{}\",\"address\":\"{}\",\"operation\":\"{}\",\"key\":{},\"size\":{}}}\n",
+            time, requester, address, operation, key, size
+        );
+
+        let mut writer = self.writer.lock_or_recover();
+
+        if let Err(err) = writer.file.write_all(line.as_bytes()) {
+            error!("Could not write to audit log: {}", err);
+
+            return;
+        }
+
+        writer.size += line.len() as u64;
+
+        if self.max_size.is_some_and(|max_size| writer.size > max_size) {
+            if let Err(err) = self.rotate(&mut writer) {
+                error!("Could not rotate audit log: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::DhtKey;
+    use std::env;
+
+    fn client() -> Requester {
+        Requester::Client("127.0.0.1:50712".parse().unwrap())
+    }
+
+    fn remove_all(path: &Path) {
+        for name in ["", ".1", ".2", ".3"] {
+            let mut file = OsString::from(path.as_os_str());
+            file.push(name);
+            let _ = fs::remove_file(file);
+        }
+    }
+
+    #[test]
+    fn record() {
+        let path = env::temp_dir().join("chord-audit-record.log");
+        remove_all(&path);
+
+        let audit_log = AuditLog::open(&path).unwrap();
+        audit_log.record(client(), Operation::Put(DhtKey::from([3; 32])), 3);
+        audit_log.record(client(), Operation::Admin("NODE DRAIN"), 0);
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = content.lines().collect();
+
+        assert_eq!(2, lines.len());
+        assert!(lines[0].contains(&format!(
+            "\"requester\":\"client\",\"address\":\"127.0.0.1:50712\",\"operation\":\"PUT\",\"key\":\"{}\",\"size\":3}}",
+            "03".repeat(32)
+        )));
+        assert!(lines[1].ends_with("\"operation\":\"NODE DRAIN\",\"key\":null,\"size\":0}"));
+
+        remove_all(&path);
+    }
+
+    #[test]
+    fn rotation() {
+        let path = env::temp_dir().join("chord-audit-rotation.log");
+        remove_all(&path);
+
+        // every entry exceeds the maximum size and is rotated right away
+        let audit_log = AuditLog::open(&path).unwrap().with_rotation(Some(1), 2);
+
+        for size in 1..=3 {
+            audit_log.record(client(), Operation::Delete(DhtKey::from([1; 32])), size);
+        }
+
+        let read = |index: usize| fs::read_to_string(audit_log.rotated_path(index)).unwrap();
+
+        assert!(read(1).ends_with("\"size\":3}\n"));
+        assert!(read(2).ends_with("\"size\":2}\n"));
+        assert!(!audit_log.rotated_path(3).exists());
+        assert_eq!(0, fs::metadata(&path).unwrap().len());
+
+        remove_all(&path);
+    }
+}
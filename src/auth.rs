@@ -0,0 +1,84 @@
+//! Authorization of single requests
+//!
+//! The [`ApiHandler`] and the [`P2PHandler`] ask their [`Authorizer`] before
+//! they read, store or remove a value and before they execute an
+//! administrative api request. Embedders implement the trait to enforce
+//! their own access rules, for example by [`Namespace`], by key prefix or by
+//! the address of the client or peer, without touching the handlers. By
+//! default, all requests are allowed.
+//!
+//! Denied requests are answered like failed requests of the same type:
+//!
+//! * `DHT GET` and `DHT RESOLVE` with `DHT FAILURE`
+//! * `DHT PUT` with `DHT FAILURE` if acknowledgements were requested
+//! * `DHT DELETE` with a `DHT DELETE REPLY` without removed replicas
+//! * `STORAGE GET`, `STORAGE PUT` and `STORAGE DELETE` with a
+//!   `STORAGE FAILURE` whose reason is [`FailureReason::Denied`]
+//! * values of `STORAGE BULK PUT` are rejected one by one
+//!
+//! Administrative requests like `NODE DRAIN` are dropped without a reply.
+//! Messages which maintain the ring like `PEER FIND` or `JOIN LOCK` are not
+//! subject to authorization since the peers could not keep the ring intact
+//! otherwise.
+//!
+//! Values are handed over between peers with the same storage messages when
+//! peers join or leave. Rules by key should thus be the same on all peers of
+//! a network.
+//!
+//! [`ApiHandler`]: ../handler/struct.ApiHandler.html
+//! [`P2PHandler`]: ../handler/struct.P2PHandler.html
+//! [`Authorizer`]: trait.Authorizer.html
+//! [`Namespace`]: ../dht/struct.Namespace.html
+//! [`FailureReason::Denied`]: ../message/p2p/enum.FailureReason.html#variant.Denied
+
+use crate::dht::DhtKey;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Who sent a request
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Requester {
+    /// An api client connected from the given address
+    Client(SocketAddr),
+    /// Another peer connected from the given address
+    ///
+    /// This is the address of the connection, not the address the peer
+    /// listens on.
+    Peer(SocketAddr),
+}
+
+/// What a request asks for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    /// Read the value of a key or resolve the peer responsible for it
+    Get(DhtKey),
+    /// Store a value under a key
+    Put(DhtKey),
+    /// Remove the value of a key
+    Delete(DhtKey),
+    /// Inspect or change the peer itself with the api request of the given
+    /// message type, e.g. `"NODE DRAIN"`
+    Admin(&'static str),
+}
+
+/// Decides whether a request may be executed
+pub trait Authorizer: fmt::Debug + Send + Sync {
+    /// Returns whether `requester` may perform `operation`.
+    fn authorize(&self, requester: Requester, operation: Operation) -> bool;
+}
+
+/// Returns the authorizer used if none is configured.
+pub fn allow_all() -> Arc<dyn Authorizer> {
+    Arc::new(AllowAll)
+}
+
+/// Allows every request
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AllowAll;
+
+impl Authorizer for AllowAll {
+    fn authorize(&self, _: Requester, _: Operation) -> bool {
+        true
+    }
+}
@@ -1,16 +1,66 @@
+extern crate base64;
 extern crate chord;
+extern crate hex;
+extern crate rustyline;
+#[macro_use]
+extern crate serde_json;
 extern crate structopt;
 
+use chord::client::{ApiClient, Output};
 use chord::config::Config;
-use chord::message::api::{DhtGet, DhtPut};
-use chord::message::Message;
-use chord::network::Connection;
+use chord::dht::{DhtKey, DhtValue, Namespace};
+use chord::load::Load;
+use chord::message::api::{
+    DhtListLocalReply, DhtRingWalkReply, Encoding, FlushScope, NodePeersReply,
+};
+use chord::metrics::Summary;
+use chord::routing::identifier::Identifier;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::env;
+use std::fmt;
+use std::fs;
 use std::io;
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::process;
 use structopt::StructOpt;
 
+const HISTORY_FILE: &str = ".dht_api_history";
+
+/// Exit code if the DHT replied with a failure, e.g. no value was found
+const EXIT_FAILURE: i32 = 1;
+/// Exit code for invalid arguments
+const EXIT_USAGE: i32 = 2;
+/// Exit code if the DHT could not be reached or replied unexpectedly
+const EXIT_ERROR: i32 = 3;
+
+const HELP: &str = "\
+Commands:
+
+  put <key> <value> [--ttl <secs>] [--replication <n>]
+  get <key>
+  delete <key> [--replication <n>]
+  resolve <key>
+  trace <key>
+  stats
+  flush all|expired|namespace <prefix>
+  list-local [<limit>]
+  peers
+  load [<samples>]
+  ring [<limit>]
+  export <file> [<prefix>]
+  import <file>
+  drain
+  read-only on|off
+  help
+  quit
+
+Keys are names which are hashed with SHA-256 unless --raw-keys is given, in
+which case they are read as hex of at most 32 bytes padded with zeros.
+Values are read as text unless prefixed with 0x (hex) or base64: (base64).";
+
 #[derive(StructOpt, Debug)]
 #[structopt(
     name = "api",
@@ -22,6 +72,220 @@ struct Opt {
     /// Path to a custom config file
     #[structopt(short = "c", parse(from_os_str))]
     config: PathBuf,
+
+    /// Output format (text, json)
+    #[structopt(long = "output", default_value = "text")]
+    output: Output,
+
+    /// Interpret keys as raw hex instead of hashing them
+    #[structopt(long = "raw-keys")]
+    raw_keys: bool,
+
+    /// Talk to the peer in CBOR, which requires `api_cbor` in its config
+    #[structopt(long = "cbor")]
+    cbor: bool,
+
+    /// Command to run once instead of starting the interactive shell
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Store a value read from the command line, a file or stdin
+    #[structopt(name = "put")]
+    Put {
+        /// Key name or raw hex key with --raw-keys
+        key: String,
+
+        /// Value as text, hex (0x...) or base64 (base64:...)
+        value: Option<String>,
+
+        /// Read the value from a file instead
+        #[structopt(short = "f", long = "file", parse(from_os_str))]
+        file: Option<PathBuf>,
+
+        /// Time to live in seconds
+        #[structopt(long = "ttl", default_value = "10")]
+        ttl: u16,
+
+        /// Number of additional replicas
+        #[structopt(long = "replication", default_value = "2")]
+        replication: u8,
+    },
+
+    /// Write the value stored under a key to stdout or a file
+    #[structopt(name = "get")]
+    Get {
+        /// Key name or raw hex key with --raw-keys
+        key: String,
+
+        /// Write the value to a file instead
+        #[structopt(short = "o", long = "output", parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Remove the value stored under a key
+    #[structopt(name = "delete")]
+    Delete {
+        /// Key name or raw hex key with --raw-keys
+        key: String,
+
+        /// Number of additional replicas
+        #[structopt(long = "replication", default_value = "2")]
+        replication: u8,
+    },
+
+    /// Print the address of the peer responsible for a key
+    #[structopt(name = "resolve")]
+    Resolve {
+        /// Key name or raw hex key with --raw-keys
+        key: String,
+    },
+
+    /// Print the peers asked during the lookup for a key
+    #[structopt(name = "trace")]
+    Trace {
+        /// Key name or raw hex key with --raw-keys
+        key: String,
+    },
+
+    /// Print routing information and lookup telemetry of the peer
+    #[structopt(name = "stats")]
+    Stats,
+
+    /// Print the keys stored by the peer along with their sizes and TTLs
+    #[structopt(name = "list-local")]
+    ListLocal {
+        /// Maximum number of keys to print
+        #[structopt(default_value = "100")]
+        limit: usize,
+    },
+
+    /// Print the finger table of the peer
+    #[structopt(name = "peers")]
+    Peers,
+
+    /// Compare the load of the peer with the peers in its routing table
+    #[structopt(name = "load")]
+    Load {
+        /// Maximum number of peers to compare with
+        #[structopt(default_value = "8")]
+        samples: u16,
+    },
+
+    /// Print the peers on the ring by following the successors of the peer
+    #[structopt(name = "ring")]
+    Ring {
+        /// Maximum number of peers to print
+        #[structopt(default_value = "100")]
+        limit: usize,
+    },
+
+    /// Write the records stored by the peer into a CBOR export
+    #[structopt(name = "export")]
+    Export {
+        /// Write the export to a file instead of stdout
+        #[structopt(parse(from_os_str))]
+        file: Option<PathBuf>,
+
+        /// Only export keys starting with this prefix
+        #[structopt(long = "namespace")]
+        namespace: Option<String>,
+    },
+
+    /// Store the records of a CBOR export in the peer
+    #[structopt(name = "import")]
+    Import {
+        /// Read the export from a file instead of stdin
+        #[structopt(parse(from_os_str))]
+        file: Option<PathBuf>,
+    },
+
+    /// Hand all records of the peer to its successor and shut it down
+    #[structopt(name = "drain")]
+    Drain,
+
+    /// Make the peer refuse new values or accept them again
+    #[structopt(name = "read-only")]
+    ReadOnly {
+        /// Either on or off
+        mode: String,
+    },
+}
+
+/// Reasons for a subcommand to fail, mapped to distinct exit codes
+enum Failure {
+    Dht(String),
+    Usage(String),
+    Error(String),
+}
+
+impl Failure {
+    fn exit(self) -> ! {
+        let (message, code) = match self {
+            Failure::Dht(message) => (message, EXIT_FAILURE),
+            Failure::Usage(message) => (message, EXIT_USAGE),
+            Failure::Error(message) => (message, EXIT_ERROR),
+        };
+
+        eprintln!("{}", message);
+        process::exit(code)
+    }
+}
+
+/// A key along with the name it has been given on the command line
+struct KeyArg {
+    name: String,
+    key: DhtKey,
+}
+
+impl fmt::Display for KeyArg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// Arguments of a command split into positional arguments and options
+struct Args {
+    positional: Vec<String>,
+    ttl: u16,
+    replication: u8,
+    raw_keys: bool,
+}
+
+impl Args {
+    fn parse(args: &[String], raw_keys: bool) -> Result<Self, String> {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            ttl: 10,
+            replication: 2,
+            raw_keys,
+        };
+
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--ttl" => parsed.ttl = parse_option(arg, iter.next())?,
+                "--replication" => parsed.replication = parse_option(arg, iter.next())?,
+                _ => parsed.positional.push(arg.clone()),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    fn get(&self, index: usize, name: &str) -> Result<&str, String> {
+        self.positional
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| format!("Missing argument <{}>", name))
+    }
+
+    fn key(&self) -> Result<KeyArg, String> {
+        parse_key(self.get(0, "key")?, self.raw_keys)
+    }
 }
 
 fn main() {
@@ -32,82 +296,851 @@ fn main() {
         process::exit(2);
     });
 
+    let mut client = ApiClient::new(config.api_address, config.timeout);
+
+    if opt.cbor {
+        client = client.with_encoding(Encoding::Cbor);
+    }
+
+    if let Some(command) = opt.command {
+        if let Err(failure) = run_subcommand(&client, command, opt.output, opt.raw_keys) {
+            failure.exit();
+        }
+
+        return;
+    }
+
+    if let Err(err) = run_repl(&client, opt.output, opt.raw_keys) {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run_subcommand(
+    client: &ApiClient,
+    command: Command,
+    output: Output,
+    raw_keys: bool,
+) -> Result<(), Failure> {
+    let error = |err: Box<dyn std::error::Error>| Failure::Error(format!("Error: {}", err));
+
+    match command {
+        Command::Put {
+            key,
+            value,
+            file,
+            ttl,
+            replication,
+        } => {
+            let key = parse_key(&key, raw_keys).map_err(Failure::Usage)?;
+
+            let value = match (value, file) {
+                (Some(value), None) => parse_value(&value).map_err(Failure::Usage)?,
+                (None, Some(file)) => {
+                    let value = fs::read(&file).map_err(|err| {
+                        Failure::Usage(format!("Could not read {}: {}", file.display(), err))
+                    })?;
+                    DhtValue::new(value).map_err(|err| Failure::Usage(err.to_string()))?
+                }
+                (None, None) => {
+                    let mut value = Vec::new();
+                    io::stdin()
+                        .read_to_end(&mut value)
+                        .map_err(|err| Failure::Usage(format!("Could not read stdin: {}", err)))?;
+                    DhtValue::new(value).map_err(|err| Failure::Usage(err.to_string()))?
+                }
+                (Some(_), Some(_)) => {
+                    return Err(Failure::Usage(
+                        "Provide either a value or a file".to_string(),
+                    ))
+                }
+            };
+
+            client
+                .put(key.key, value, ttl, replication)
+                .map_err(error)?;
+
+            if output == Output::Json {
+                print_put(output, &key);
+            }
+
+            Ok(())
+        }
+        Command::Get { key, output: file } => {
+            let key = parse_key(&key, raw_keys).map_err(Failure::Usage)?;
+
+            let value = client
+                .get(key.key)
+                .map_err(error)?
+                .ok_or_else(|| Failure::Dht(format!("No value found for key {}", key)))?;
+
+            let result = match (file, output) {
+                (Some(file), _) => fs::write(file, value.as_bytes()),
+                (None, Output::Json) => {
+                    print_value(output, &key, &value);
+                    Ok(())
+                }
+                (None, Output::Text) => io::stdout().write_all(value.as_bytes()),
+            };
+
+            result.map_err(|err| Failure::Error(format!("Could not write value: {}", err)))
+        }
+        Command::Delete { key, replication } => {
+            let key = parse_key(&key, raw_keys).map_err(Failure::Usage)?;
+
+            match client.delete(key.key, replication).map_err(error)? {
+                0 => Err(Failure::Dht(format!("No value removed for key {}", key))),
+                replicas => {
+                    match output {
+                        Output::Text => println!("{}", replicas),
+                        Output::Json => print_delete(output, &key, replicas),
+                    }
+
+                    Ok(())
+                }
+            }
+        }
+        Command::Resolve { key } => {
+            let key = parse_key(&key, raw_keys).map_err(Failure::Usage)?;
+
+            let (socket_addr, identifier) = client
+                .resolve(key.key)
+                .map_err(error)?
+                .ok_or_else(|| Failure::Dht(format!("Failed to resolve key {}", key)))?;
+
+            match output {
+                Output::Text => println!("{}", socket_addr),
+                Output::Json => print_resolve(output, &key, socket_addr, identifier),
+            }
+
+            Ok(())
+        }
+        Command::Trace { key } => {
+            let key = parse_key(&key, raw_keys).map_err(Failure::Usage)?;
+
+            let path = client
+                .trace(key.key)
+                .map_err(error)?
+                .ok_or_else(|| Failure::Dht(format!("Failed to trace lookup for key {}", key)))?;
+
+            match output {
+                Output::Text => {
+                    for socket_addr in path {
+                        println!("{}", socket_addr);
+                    }
+                }
+                Output::Json => print_trace(output, &key, &path),
+            }
+
+            Ok(())
+        }
+        Command::Stats => handle_stats(client, output).map_err(Failure::Error),
+        Command::ListLocal { limit } => {
+            handle_list_local(client, limit, output).map_err(Failure::Error)
+        }
+        Command::Peers => handle_peers(client, output).map_err(Failure::Error),
+        Command::Load { samples } => handle_load(client, samples, output).map_err(Failure::Error),
+        Command::Ring { limit } => handle_ring(client, limit, output).map_err(Failure::Error),
+        Command::Export { file, namespace } => {
+            let namespace = match namespace {
+                Some(prefix) => Some(parse_namespace(&prefix).map_err(Failure::Usage)?),
+                None => None,
+            };
+
+            handle_export(client, file.as_deref(), namespace, output).map_err(Failure::Error)
+        }
+        Command::Import { file } => {
+            handle_import(client, file.as_deref(), output).map_err(Failure::Error)
+        }
+        Command::Drain => handle_drain(client, output).map_err(Failure::Error),
+        Command::ReadOnly { mode } => {
+            let args = Args::parse(&[mode], raw_keys).map_err(Failure::Usage)?;
+
+            handle_read_only(client, &args, output).map_err(Failure::Error)
+        }
+    }
+}
+
+fn run_repl(client: &ApiClient, output: Output, raw_keys: bool) -> Result<(), ReadlineError> {
     println!("Client to talk to the DHT api");
     println!("-----------------------------\n");
+    println!("Type help for a list of commands.\n");
+
+    let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+
+    let mut editor = DefaultEditor::new()?;
+
+    if let Some(history) = &history {
+        // the history does not exist when starting the shell for the first time
+        let _ = editor.load_history(history);
+    }
 
     loop {
-        let command = read_line("Enter a command").unwrap();
+        let line = match editor.readline("dht> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err),
+        };
 
-        if "put" == command {
-            handle_put(config);
+        let args = split_line(&line);
+
+        if args.is_empty() {
+            continue;
         }
 
-        if "get" == command {
-            handle_get(config);
+        editor.add_history_entry(line.as_str())?;
+
+        if args[0] == "quit" || args[0] == "exit" {
+            break;
+        }
+
+        if let Err(err) = run_command(client, &args, output, raw_keys) {
+            eprintln!("Error: {}", err);
         }
     }
+
+    if let Some(history) = &history {
+        editor.save_history(history)?;
+    }
+
+    Ok(())
 }
 
-fn read_line(question: &str) -> Option<String> {
-    print!("{}: ", question);
-    io::stdout().flush().unwrap();
+fn run_command(
+    client: &ApiClient,
+    command: &[String],
+    output: Output,
+    raw_keys: bool,
+) -> Result<(), String> {
+    let args = Args::parse(&command[1..], raw_keys)?;
 
-    let mut line = String::new();
+    match command[0].as_str() {
+        "put" => handle_put(client, &args, output),
+        "get" => handle_get(client, &args, output),
+        "delete" => handle_delete(client, &args, output),
+        "resolve" => handle_resolve(client, &args, output),
+        "trace" => handle_trace(client, &args, output),
+        "stats" => handle_stats(client, output),
+        "flush" => handle_flush(client, &args, output),
+        "list-local" => {
+            let limit = match args.positional.first() {
+                Some(limit) => parse_option("<limit>", Some(limit))?,
+                None => 100,
+            };
 
-    match io::stdin().read_line(&mut line) {
-        Ok(_) => Some(line.trim().to_string()),
-        Err(err) => {
-            eprintln!("Error: {}", err);
-            None
+            handle_list_local(client, limit, output)
+        }
+        "peers" => handle_peers(client, output),
+        "load" => {
+            let samples = match args.positional.first() {
+                Some(samples) => parse_option("<samples>", Some(samples))?,
+                None => 8,
+            };
+
+            handle_load(client, samples, output)
+        }
+        "ring" => {
+            let limit = match args.positional.first() {
+                Some(limit) => parse_option("<limit>", Some(limit))?,
+                None => 100,
+            };
+
+            handle_ring(client, limit, output)
+        }
+        "export" => {
+            let file = Path::new(args.get(0, "file")?);
+            let namespace = match args.positional.get(1) {
+                Some(prefix) => Some(parse_namespace(prefix)?),
+                None => None,
+            };
+
+            handle_export(client, Some(file), namespace, output)
+        }
+        "import" => handle_import(client, Some(Path::new(args.get(0, "file")?)), output),
+        "drain" => handle_drain(client, output),
+        "read-only" => handle_read_only(client, &args, output),
+        "help" => {
+            println!("{}", HELP);
+            Ok(())
+        }
+        command => Err(format!("Unknown command {}, type help for a list", command)),
+    }
+}
+
+/// Splits a line into whitespace separated arguments respecting double quotes.
+fn split_line(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut pending = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                pending = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if pending {
+                    args.push(current.clone());
+                    current.clear();
+                    pending = false;
+                }
+            }
+            c => {
+                current.push(c);
+                pending = true;
+            }
+        }
+    }
+
+    if pending {
+        args.push(current);
+    }
+
+    args
+}
+
+fn parse_option<T: std::str::FromStr>(name: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("Missing value for {}", name))?;
+
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value {} for {}", value, name))
+}
+
+/// Parses text, hex with prefix `0x` or base64 with prefix `base64:`.
+fn parse_value(input: &str) -> Result<DhtValue, String> {
+    let value = if input.starts_with("0x") {
+        DhtValue::from_hex(input)
+    } else if let Some(base64) = input.strip_prefix("base64:") {
+        DhtValue::from_base64(base64)
+    } else {
+        DhtValue::new(input.as_bytes().to_vec())
+    };
+
+    value.map_err(|err| err.to_string())
+}
+
+/// Parses the prefix of a namespace like a value.
+fn parse_namespace(input: &str) -> Result<Namespace, String> {
+    Namespace::new(&parse_value(input)?.into_vec()).map_err(|err| err.to_string())
+}
+
+/// Derives a key from a name or reads it as hex if `raw_keys` is set.
+fn parse_key(input: &str, raw_keys: bool) -> Result<KeyArg, String> {
+    let key = if raw_keys {
+        DhtKey::from_hex(input).map_err(|err| err.to_string())?
+    } else {
+        DhtKey::from_name(input)
+    };
+
+    Ok(KeyArg {
+        name: input.to_string(),
+        key,
+    })
+}
+
+/// Formats bytes as text if possible and as hex otherwise.
+fn format_bytes(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) if !text.chars().any(char::is_control) => text.to_string(),
+        _ => format!("0x{}", hex::encode(bytes)),
+    }
+}
+
+fn handle_put(client: &ApiClient, args: &Args, output: Output) -> Result<(), String> {
+    let key = args.key()?;
+    let value = parse_value(args.get(1, "value")?)?;
+
+    client
+        .put(key.key, value, args.ttl, args.replication)
+        .map_err(|err| err.to_string())?;
+
+    print_put(output, &key);
+
+    Ok(())
+}
+
+fn handle_get(client: &ApiClient, args: &Args, output: Output) -> Result<(), String> {
+    let key = args.key()?;
+
+    match client.get(key.key).map_err(|err| err.to_string())? {
+        Some(value) => print_value(output, &key, &value),
+        None => println!("No value found for key {}", key),
+    }
+
+    Ok(())
+}
+
+fn handle_delete(client: &ApiClient, args: &Args, output: Output) -> Result<(), String> {
+    let key = args.key()?;
+
+    let replicas = client
+        .delete(key.key, args.replication)
+        .map_err(|err| err.to_string())?;
+
+    print_delete(output, &key, replicas);
+
+    Ok(())
+}
+
+fn handle_resolve(client: &ApiClient, args: &Args, output: Output) -> Result<(), String> {
+    let key = args.key()?;
+
+    match client.resolve(key.key).map_err(|err| err.to_string())? {
+        Some((socket_addr, identifier)) => print_resolve(output, &key, socket_addr, identifier),
+        None => println!("Failed to resolve key {}", key),
+    }
+
+    Ok(())
+}
+
+fn handle_trace(client: &ApiClient, args: &Args, output: Output) -> Result<(), String> {
+    let key = args.key()?;
+
+    match client.trace(key.key).map_err(|err| err.to_string())? {
+        Some(path) => print_trace(output, &key, &path),
+        None => println!("Failed to trace lookup for key {}", key),
+    }
+
+    Ok(())
+}
+
+fn handle_stats(client: &ApiClient, output: Output) -> Result<(), String> {
+    let node_info = client.node_info().map_err(|err| err.to_string())?;
+    let stats = node_info.lookup_stats;
+
+    if output == Output::Json {
+        let summary = |summary: Summary| {
+            json!({
+                "count": summary.count,
+                "mean": summary.mean,
+                "p50": summary.p50,
+                "p90": summary.p90,
+                "p99": summary.p99,
+                "max": summary.max,
+            })
+        };
+
+        print_json(json!({
+            "peer": node_info.socket_addr.to_string(),
+            "identifier": format!("{:x}", node_info.identifier),
+            "predecessor": node_info.predecessor.to_string(),
+            "successor": node_info.successor.to_string(),
+            "lookups": {
+                "count": stats.lookups,
+                "failures": stats.failures,
+                "hops": summary(stats.hops),
+                "latency_us": summary(stats.latency),
+            },
+            "traffic": node_info.traffic.map(|traffic| json!({
+                "bytes_received": traffic.bytes_received,
+                "bytes_sent": traffic.bytes_sent,
+                "throttled": traffic.throttled,
+            })),
+        }));
+
+        return Ok(());
+    }
+
+    println!(
+        "Peer {} with identifier {}",
+        node_info.socket_addr, node_info.identifier
+    );
+    println!("Predecessor: {}", node_info.predecessor);
+    println!("Successor: {}\n", node_info.successor);
+    println!("Lookups: {} ({} failed)", stats.lookups, stats.failures);
+    println!(
+        "Hops: mean {:.2}, p50 {}, p90 {}, p99 {}, max {}",
+        stats.hops.mean, stats.hops.p50, stats.hops.p90, stats.hops.p99, stats.hops.max
+    );
+    println!(
+        "Latency (µs): mean {:.0}, p50 {}, p90 {}, p99 {}, max {}",
+        stats.latency.mean,
+        stats.latency.p50,
+        stats.latency.p90,
+        stats.latency.p99,
+        stats.latency.max
+    );
+
+    if let Some(traffic) = node_info.traffic {
+        println!(
+            "Traffic: {} bytes received, {} bytes sent, {} messages throttled",
+            traffic.bytes_received, traffic.bytes_sent, traffic.throttled
+        );
+    }
+
+    Ok(())
+}
+
+fn handle_flush(client: &ApiClient, args: &Args, output: Output) -> Result<(), String> {
+    let scope = match args.get(0, "scope")? {
+        "all" => FlushScope::All,
+        "expired" => FlushScope::Expired,
+        "namespace" => FlushScope::Namespace(parse_value(args.get(1, "prefix")?)?.into_vec()),
+        scope => return Err(format!("Unknown scope {}", scope)),
+    };
+
+    let records = client.flush(scope).map_err(|err| err.to_string())?;
+
+    match output {
+        Output::Text => println!("Removed {} records from local storage", records),
+        Output::Json => print_json(json!({ "records": records })),
+    }
+
+    Ok(())
+}
+
+fn handle_list_local(client: &ApiClient, limit: usize, output: Output) -> Result<(), String> {
+    let mut records = Vec::new();
+    let mut after = None;
+
+    // fetch pages until enough records have been listed
+    let reply = loop {
+        let page = (limit - records.len()).min(DhtListLocalReply::MAX_RECORDS) as u16;
+        let mut reply = client
+            .list_local(page, after)
+            .map_err(|err| err.to_string())?;
+
+        after = reply.next_cursor();
+        records.append(&mut reply.records);
+
+        if after.is_none() || records.len() >= limit {
+            break reply;
         }
+    };
+
+    if output == Output::Json {
+        print_json(json!({
+            "total_records": reply.total_records,
+            "total_bytes": reply.total_bytes,
+            "records": records.iter().map(|record| json!({
+                "key": record.key.to_base64(),
+                "replication_index": record.replication_index,
+                "ttl": record.ttl,
+                "size": record.size,
+                "version": record.version,
+            })).collect::<Vec<_>>(),
+        }));
+
+        return Ok(());
     }
+
+    for record in &records {
+        println!(
+            "{}:{}  {} bytes  ttl {} ms  version {}",
+            record.key.to_hex(),
+            record.replication_index,
+            record.size,
+            record.ttl,
+            record.version
+        );
+    }
+
+    println!(
+        "Listed {} of {} records, {} bytes in total",
+        records.len(),
+        reply.total_records,
+        reply.total_bytes
+    );
+
+    Ok(())
 }
 
-fn handle_put(config: Config) {
-    let key = read_line("Enter a key").unwrap();
-    let value = read_line("Enter a value").unwrap();
+fn handle_peers(client: &ApiClient, output: Output) -> Result<(), String> {
+    let mut peers = Vec::new();
+    let mut after = None;
 
-    let len = std::cmp::min(32, key.len());
+    // fetch pages until the whole finger table has been listed
+    loop {
+        let mut reply = client
+            .peers(NodePeersReply::MAX_PEERS as u16, after)
+            .map_err(|err| err.to_string())?;
+
+        after = reply.next_cursor();
+        peers.append(&mut reply.peers);
 
-    let mut raw_key = [0; 32];
-    raw_key[..len].copy_from_slice(&key.as_bytes()[..len]);
+        if after.is_none() {
+            break;
+        }
+    }
 
-    let dht_put = DhtPut {
-        ttl: 10,
-        replication: 2,
-        key: raw_key,
-        value: value.as_bytes().to_vec(),
+    if output == Output::Json {
+        print_json(json!(peers
+            .iter()
+            .map(|peer| json!({
+                "index": peer.index,
+                "age": peer.age,
+                "identifier": format!("{:x}", peer.identifier),
+                "peer": peer.socket_addr.to_string(),
+            }))
+            .collect::<Vec<_>>()));
+
+        return Ok(());
+    }
+
+    for peer in &peers {
+        println!(
+            "{:>3}  {:>5}s  {}  {}",
+            peer.index, peer.age, peer.socket_addr, peer.identifier
+        );
+    }
+
+    Ok(())
+}
+
+fn handle_load(client: &ApiClient, samples: u16, output: Output) -> Result<(), String> {
+    let reply = client.node_load(samples).map_err(|err| err.to_string())?;
+
+    if output == Output::Json {
+        let load = |load: Load| {
+            json!({
+                "share": load.share_fraction(),
+                "records": load.records,
+                "bytes": load.bytes,
+                "requests": load.requests,
+            })
+        };
+
+        print_json(json!({
+            "sampled": reply.sampled,
+            "local": load(reply.local),
+            "average": load(reply.average),
+            "ratio": reply.local.ratio(&reply.average),
+            "weight": reply.weight,
+            "target_weight": reply.target_weight,
+        }));
+
+        return Ok(());
+    }
+
+    println!("Compared with {} peers\n", reply.sampled);
+    println!("{:>10}  {:>10}  {:>10}", "", "local", "average");
+    println!(
+        "{:>10}  {:>9.4}%  {:>9.4}%",
+        "share",
+        reply.local.share_fraction() * 100.0,
+        reply.average.share_fraction() * 100.0
+    );
+    println!(
+        "{:>10}  {:>10}  {:>10}",
+        "records", reply.local.records, reply.average.records
+    );
+    println!(
+        "{:>10}  {:>10}  {:>10}",
+        "bytes", reply.local.bytes, reply.average.bytes
+    );
+    println!(
+        "{:>10}  {:>10}  {:>10}\n",
+        "requests", reply.local.requests, reply.average.requests
+    );
+
+    if reply.target_weight == reply.weight {
+        println!("Balanced with {} virtual peers", reply.weight);
+    } else {
+        println!(
+            "Load is {:.2} times the average, run {} instead of {} virtual peers",
+            reply.local.ratio(&reply.average),
+            reply.target_weight,
+            reply.weight
+        );
+    }
+
+    Ok(())
+}
+
+fn handle_ring(client: &ApiClient, limit: usize, output: Output) -> Result<(), String> {
+    let mut peers = Vec::new();
+    let mut after = None;
+
+    // fetch pages until the walk returns to the peer or enough peers have
+    // been listed
+    let complete = loop {
+        let page = (limit - peers.len()).min(DhtRingWalkReply::MAX_PEERS) as u16;
+        let mut reply = client
+            .ring_walk(page, after)
+            .map_err(|err| err.to_string())?;
+
+        after = reply.next_cursor();
+        peers.append(&mut reply.peers);
+
+        if after.is_none() || peers.len() >= limit {
+            break reply.complete;
+        }
+    };
+
+    if output == Output::Json {
+        print_json(json!({
+            "complete": complete,
+            "peers": peers.iter().map(|peer| json!({
+                "identifier": format!("{:x}", peer.identifier),
+                "peer": peer.socket_addr.to_string(),
+            })).collect::<Vec<_>>(),
+        }));
+
+        return Ok(());
+    }
+
+    for peer in &peers {
+        println!("{}  {}", peer.socket_addr, peer.identifier);
+    }
+
+    if complete {
+        println!("Walked the whole ring of {} peers", peers.len());
+    } else {
+        println!("Listed {} peers, the ring continues", peers.len());
+    }
+
+    Ok(())
+}
+
+fn handle_export(
+    client: &ApiClient,
+    file: Option<&Path>,
+    namespace: Option<Namespace>,
+    output: Output,
+) -> Result<(), String> {
+    let records = match file {
+        Some(file) => {
+            let file = fs::File::create(file).map_err(|err| err.to_string())?;
+            client.export_to(io::BufWriter::new(file), namespace.as_ref())
+        }
+        None => client.export_to(io::BufWriter::new(io::stdout()), namespace.as_ref()),
+    }
+    .map_err(|err| err.to_string())?;
+
+    // the export itself may be written to stdout
+    let summary = match output {
+        Output::Text => format!("Exported {} records", records),
+        Output::Json => json!({ "records": records }).to_string(),
     };
 
-    let mut con = Connection::open(config.api_address, config.timeout).unwrap();
-    con.send(&Message::DhtPut(dht_put)).unwrap();
+    if file.is_some() {
+        println!("{}", summary);
+    } else {
+        eprintln!("{}", summary);
+    }
 
-    println!("Sent a DHT PUT message to {}", config.api_address);
+    Ok(())
 }
 
-fn handle_get(config: Config) {
-    let key = read_line("Enter a key").unwrap();
+fn handle_import(client: &ApiClient, file: Option<&Path>, output: Output) -> Result<(), String> {
+    let reply = match file {
+        Some(file) => {
+            let file = fs::File::open(file).map_err(|err| err.to_string())?;
+            client.import_from(io::BufReader::new(file))
+        }
+        None => client.import_from(io::stdin().lock()),
+    }
+    .map_err(|err| err.to_string())?;
 
-    let len = std::cmp::min(32, key.len());
+    match output {
+        Output::Text => println!(
+            "Imported {} records, skipped {} records",
+            reply.stored, reply.skipped
+        ),
+        Output::Json => print_json(json!({
+            "stored": reply.stored,
+            "skipped": reply.skipped,
+        })),
+    }
+
+    Ok(())
+}
 
-    let mut raw_key = [0; 32];
-    raw_key[..len].copy_from_slice(&key.as_bytes()[..len]);
+fn handle_drain(client: &ApiClient, output: Output) -> Result<(), String> {
+    let records = client.drain().map_err(|err| err.to_string())?;
 
-    let dht_get = DhtGet { key: raw_key };
+    match output {
+        Output::Text => println!("Transferred {} records to the successor", records),
+        Output::Json => print_json(json!({ "records": records })),
+    }
 
-    let mut con = Connection::open(config.api_address, config.timeout).unwrap();
-    con.send(&Message::DhtGet(dht_get)).unwrap();
+    Ok(())
+}
+
+fn handle_read_only(client: &ApiClient, args: &Args, output: Output) -> Result<(), String> {
+    let read_only = match args.get(0, "mode")? {
+        "on" => true,
+        "off" => false,
+        mode => return Err(format!("Unknown mode {}, expected on or off", mode)),
+    };
+
+    let read_only = client
+        .set_read_only(read_only)
+        .map_err(|err| err.to_string())?;
+
+    match output {
+        Output::Text if read_only => println!("Peer refuses new values"),
+        Output::Text => println!("Peer accepts new values"),
+        Output::Json => print_json(json!({ "read_only": read_only })),
+    }
+
+    Ok(())
+}
 
-    match con.receive().unwrap() {
-        Message::DhtSuccess(dht_success) => {
-            let key = std::str::from_utf8(&dht_success.key).unwrap();
-            let value = std::str::from_utf8(&dht_success.value).unwrap();
-            println!("Received value for key {}:\n\n{}", key, value);
+fn print_json(value: serde_json::Value) {
+    println!("{}", value);
+}
+
+fn print_put(output: Output, key: &KeyArg) {
+    match output {
+        Output::Text => println!("Sent a DHT PUT message"),
+        Output::Json => print_json(json!({ "key": key.key.to_base64() })),
+    }
+}
+
+fn print_value(output: Output, key: &KeyArg, value: &DhtValue) {
+    match output {
+        Output::Text => println!("{}", format_bytes(value.as_bytes())),
+        Output::Json => print_json(json!({
+            "key": key.key.to_base64(),
+            "value": value.to_base64(),
+        })),
+    }
+}
+
+fn print_delete(output: Output, key: &KeyArg, replicas: u8) {
+    match output {
+        Output::Text => println!("Removed {} replicas of key {}", replicas, key),
+        Output::Json => print_json(json!({
+            "key": key.key.to_base64(),
+            "replicas": replicas,
+        })),
+    }
+}
+
+fn print_resolve(output: Output, key: &KeyArg, socket_addr: SocketAddr, identifier: Identifier) {
+    match output {
+        Output::Text => println!(
+            "Peer {} with identifier {} is responsible for key {}",
+            socket_addr, identifier, key
+        ),
+        Output::Json => print_json(json!({
+            "key": key.key.to_base64(),
+            "peer": socket_addr.to_string(),
+            "identifier": format!("{:x}", identifier),
+        })),
+    }
+}
+
+fn print_trace(output: Output, key: &KeyArg, path: &[SocketAddr]) {
+    match output {
+        Output::Text => {
+            println!("Lookup for key {} took {} hops:\n", key, path.len());
+
+            for (hop, socket_addr) in path.iter().enumerate() {
+                println!("{}. {}", hop + 1, socket_addr);
+            }
         }
-        Message::DhtFailure(dht_failure) => {
-            let key = std::str::from_utf8(&dht_failure.key).unwrap();
-            println!("Failed to retrieve value for key {}", key);
+        Output::Json => {
+            let path: Vec<String> = path.iter().map(SocketAddr::to_string).collect();
+
+            print_json(json!({
+                "key": key.key.to_base64(),
+                "path": path,
+            }));
         }
-        msg => eprintln!("Unexpected message of type {}", msg),
     }
 }
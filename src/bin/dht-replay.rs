@@ -0,0 +1,129 @@
+extern crate chord;
+#[macro_use]
+extern crate log;
+extern crate stderrlog;
+extern crate structopt;
+
+use chord::capture::{self, Direction};
+use chord::handler::P2PHandler;
+use chord::network::Server;
+use chord::replay;
+use chord::routing::Routing;
+use chord::storage::Storage;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Arc, Mutex};
+use structopt::StructOpt;
+
+/// Exit code if a reply differs from the recorded one
+const EXIT_DIVERGENCE: i32 = 1;
+/// Exit code if the capture could not be read or replayed
+const EXIT_ERROR: i32 = 3;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "dht-replay",
+    version = "0.1",
+    author = "Benedikt Seidl, Stefan Su",
+    about = "Replay a capture of dht --capture and report diverging replies"
+)]
+struct Opt {
+    /// Path to the capture file
+    #[structopt(parse(from_os_str))]
+    file: PathBuf,
+
+    /// Address of the recorded peer, defaults to the first one which received a message
+    #[structopt(long = "peer")]
+    peer: Option<SocketAddr>,
+
+    /// Address of a running peer to replay against instead of a fresh in-process handler
+    #[structopt(long = "target")]
+    target: Option<SocketAddr>,
+
+    /// Time to wait for each reply in milliseconds
+    #[structopt(long = "timeout", default_value = "5000")]
+    timeout: u64,
+
+    /// Silence all output
+    #[structopt(short = "q", long = "quiet")]
+    quiet: bool,
+
+    /// Level of verbosity (v, vv, vvv)
+    #[structopt(short = "v", parse(from_occurrences))]
+    verbose: usize,
+}
+
+fn main() {
+    let opt = Opt::from_args();
+
+    // init logger with verbosity flag
+    stderrlog::new()
+        .quiet(opt.quiet)
+        .verbosity(opt.verbose)
+        .init()
+        .expect("Failed to initialize logger");
+
+    let records = capture::read(&opt.file).unwrap_or_else(|err| {
+        error!("Could not read capture: {}", err);
+        process::exit(EXIT_ERROR);
+    });
+
+    let peer_addr = opt
+        .peer
+        .or_else(|| {
+            records
+                .iter()
+                .find(|record| record.direction == Direction::Received)
+                .map(|record| record.local_addr)
+        })
+        .unwrap_or_else(|| {
+            error!("The capture does not contain any received message");
+            process::exit(EXIT_ERROR);
+        });
+
+    let target = match opt.target {
+        Some(target) => target,
+        None => start_handler(peer_addr, opt.timeout).unwrap_or_else(|err| {
+            error!("Could not start handler: {}", err);
+            process::exit(EXIT_ERROR);
+        }),
+    };
+
+    info!("Replaying messages of {} against {}", peer_addr, target);
+
+    let report = replay::replay(&records, peer_addr, target, opt.timeout);
+
+    for divergence in &report.divergences {
+        println!("{}", divergence);
+    }
+
+    println!(
+        "{} connections, {} requests, {} divergences",
+        report.connections,
+        report.requests,
+        report.divergences.len()
+    );
+
+    if !report.divergences.is_empty() {
+        process::exit(EXIT_DIVERGENCE);
+    }
+}
+
+/// Starts a handler without any other peers or records which identifies as
+/// `peer_addr` but listens on an arbitrary local port.
+fn start_handler(peer_addr: SocketAddr, timeout: u64) -> std::io::Result<SocketAddr> {
+    let routing = Routing::from_addrs(peer_addr, peer_addr, peer_addr, vec![peer_addr; 256]);
+    let handler = P2PHandler::new(
+        Arc::new(Mutex::new(routing)),
+        Arc::new(Mutex::new(Storage::new())),
+        timeout,
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let local_addr = listener.local_addr()?;
+
+    Server::new(handler).listen_on(listener, 1)?;
+
+    Ok(local_addr)
+}
@@ -0,0 +1,268 @@
+extern crate chord;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_json;
+extern crate stderrlog;
+extern crate structopt;
+
+mod service;
+
+use chord::capture::{self, Direction};
+use chord::chaos::{Chaos, ChaosConfig};
+use chord::client::Output;
+use chord::config::Config;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "dht",
+    version = "0.1",
+    author = "Benedikt Seidl, Stefan Su",
+    about = "Distributed Hash Table based on Chord"
+)]
+struct Opt {
+    /// Path to a custom config file, repeat to run several nodes in this process
+    #[structopt(short = "c", parse(from_os_str))]
+    config: Vec<PathBuf>,
+
+    /// Address of a bootstrapping peer
+    #[structopt(short = "b")]
+    bootstrap: Option<SocketAddr>,
+
+    /// Silence all output
+    #[structopt(short = "q", long = "quiet")]
+    quiet: bool,
+
+    /// Level of verbosity (v, vv, vvv)
+    #[structopt(short = "v", parse(from_occurrences))]
+    verbose: usize,
+
+    /// Timestamp (sec, ms, ns, none)
+    #[structopt(short = "t")]
+    timestamp: Option<stderrlog::Timestamp>,
+
+    /// Run under launchd, systemd or the Windows service control manager
+    #[structopt(long = "service")]
+    service: bool,
+
+    /// Name of the Windows service
+    #[structopt(long = "service-name", default_value = "chord-dht")]
+    service_name: String,
+
+    /// Append all sent and received messages to this file
+    #[structopt(long = "capture", parse(from_os_str))]
+    capture: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Launch a local ring and apply churn, printing invariant violations
+    #[structopt(name = "chaos")]
+    Chaos {
+        /// Number of peers in the ring
+        #[structopt(long = "nodes", default_value = "20")]
+        nodes: usize,
+
+        /// Probability that a peer is killed in a round
+        #[structopt(long = "kill-rate", default_value = "0.1")]
+        kill_rate: f64,
+
+        /// Number of rounds
+        #[structopt(long = "rounds", default_value = "10")]
+        rounds: usize,
+
+        /// Number of values stored in every round
+        #[structopt(long = "puts", default_value = "10")]
+        puts: usize,
+
+        /// Number of stabilization passes in every round
+        #[structopt(long = "stabilizations", default_value = "3")]
+        stabilizations: usize,
+
+        /// Seed for the random churn
+        #[structopt(long = "seed")]
+        seed: Option<u64>,
+
+        /// Output format (text, json)
+        #[structopt(long = "output", default_value = "text")]
+        output: Output,
+    },
+
+    /// Print the messages of a file written with --capture
+    #[structopt(name = "decode")]
+    Decode {
+        /// Path to the capture file
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+}
+
+fn main() {
+    let opt = Opt::from_args();
+
+    // service managers collect stderr without timestamps of their own
+    let timestamp = match opt.timestamp {
+        Some(timestamp) => timestamp,
+        None if opt.service => stderrlog::Timestamp::Millisecond,
+        None => stderrlog::Timestamp::Off,
+    };
+
+    // init logger with verbosity flag
+    stderrlog::new()
+        .quiet(opt.quiet)
+        .verbosity(opt.verbose)
+        .timestamp(timestamp)
+        .init()
+        .expect("Failed to initialize logger");
+
+    if let Some(Command::Decode { ref file }) = opt.command {
+        if let Err(err) = decode_capture(file) {
+            error!("Could not decode capture: {}", err);
+            process::exit(1);
+        }
+
+        return;
+    }
+
+    if let Some(ref capture) = opt.capture {
+        if let Err(err) = capture::start(capture) {
+            error!("Could not open capture file: {}", err);
+            process::exit(2);
+        }
+    }
+
+    if let Some(Command::Chaos {
+        nodes,
+        kill_rate,
+        rounds,
+        puts,
+        stabilizations,
+        seed,
+        output,
+    }) = opt.command
+    {
+        let seed = seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_nanos() as u64)
+                .unwrap_or(1)
+        });
+
+        let config = ChaosConfig {
+            nodes,
+            kill_rate,
+            puts,
+            stabilizations,
+            fingers: 16,
+            seed,
+            timeout: 5000,
+            stabilization_interval: 60,
+        };
+
+        run_chaos(config, rounds, output);
+
+        return;
+    }
+
+    if opt.config.is_empty() {
+        error!("No config file provided");
+        process::exit(2);
+    }
+
+    let mut configs: Vec<Config> = opt
+        .config
+        .iter()
+        .map(|config_path| {
+            Config::load_from_file(config_path).unwrap_or_else(|err| {
+                error!("Error while loading config file: {}", err);
+                process::exit(2);
+            })
+        })
+        .collect();
+
+    let result = if opt.service {
+        service::run(&opt.service_name, configs, opt.bootstrap)
+    } else if configs.len() == 1 {
+        chord::run(configs.remove(0), opt.bootstrap)
+    } else {
+        chord::run_all(configs, opt.bootstrap)
+    };
+
+    if let Err(e) = result {
+        error!("Fatal application error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run_chaos(config: ChaosConfig, rounds: usize, output: Output) {
+    let seed = config.seed;
+
+    if output == Output::Text {
+        println!(
+            "Launching {} peers with kill rate {} and seed {}",
+            config.nodes, config.kill_rate, config.seed
+        );
+    }
+
+    let mut chaos = Chaos::new(config).unwrap_or_else(|err| {
+        error!("Could not launch ring: {}", err);
+        process::exit(1);
+    });
+
+    let mut total = 0;
+
+    for round in 1..=rounds {
+        let violations = chaos.round();
+
+        for violation in &violations {
+            match output {
+                Output::Text => println!("round {}: {}", round, violation),
+                Output::Json => println!("{}", json!({ "round": round, "violation": violation })),
+            }
+        }
+
+        total += violations.len();
+    }
+
+    match output {
+        Output::Text => println!("Found {} invariant violations in {} rounds", total, rounds),
+        Output::Json => println!(
+            "{}",
+            json!({ "seed": seed, "rounds": rounds, "violations": total })
+        ),
+    }
+
+    if total > 0 {
+        process::exit(1);
+    }
+}
+
+/// Prints every message of a capture file along with its time and direction.
+fn decode_capture(path: &Path) -> Result<(), Box<dyn Error>> {
+    for record in capture::read(path)? {
+        let (from, to) = match record.direction {
+            Direction::Sent => (record.local_addr, record.peer_addr),
+            Direction::Received => (record.peer_addr, record.local_addr),
+        };
+
+        println!(
+            "{}.{:06} {} -> {} {:?}",
+            record.time / 1_000_000,
+            record.time % 1_000_000,
+            from,
+            to,
+            record.message()?
+        );
+    }
+
+    Ok(())
+}
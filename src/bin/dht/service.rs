@@ -0,0 +1,258 @@
+//! Running the nodes as a service managed by the operating system
+//!
+//! With `--service`, launchd on macOS and systemd on Linux supervise the
+//! process in the foreground. It logs with timestamps to stderr, which both
+//! collect, and drains its nodes when it receives `SIGTERM` or `SIGINT` such
+//! that their records are handed to their successors before the process
+//! exits.
+//!
+//! On Windows, the binary registers with the service control manager under
+//! the name given to `--service-name` instead and drains its nodes when the
+//! service is stopped or the system shuts down.
+
+use chord::client::ApiClient;
+use chord::config::Config;
+use chord::{Node, Runtime};
+use std::error::Error;
+use std::net::SocketAddr;
+#[cfg(not(windows))]
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+#[cfg(windows)]
+pub use self::windows::run;
+
+/// Timeout in milliseconds of the request draining a node
+const DRAIN_TIMEOUT: u64 = 60_000;
+
+/// What ends a service
+enum Event {
+    /// The service manager asked the service to stop.
+    Stop,
+    /// All nodes have terminated on their own, e.g. after `NODE DRAIN`.
+    Exited,
+}
+
+/// Starts one node for each configuration in a shared runtime like
+/// `chord::run_all` but without printing a banner.
+fn start(configs: Vec<Config>, bootstrap: Option<SocketAddr>) -> Result<Vec<Node>, Box<dyn Error>> {
+    let runtime = Runtime::for_configs(&configs);
+    let mut nodes: Vec<Node> = Vec::new();
+
+    for config in configs {
+        let bootstrap = bootstrap.or_else(|| nodes.first().map(Node::listen_address));
+        nodes.push(Node::start_in(config, bootstrap, &runtime)?);
+    }
+
+    Ok(nodes)
+}
+
+/// Sends `Event::Exited` to `events` once all `nodes` have terminated and
+/// returns the addresses of their api servers.
+fn supervise(nodes: Vec<Node>, events: Sender<Event>) -> Vec<SocketAddr> {
+    let api_addresses = nodes.iter().map(Node::api_address).collect();
+
+    thread::spawn(move || {
+        for node in nodes {
+            node.join();
+        }
+
+        let _ = events.send(Event::Exited);
+    });
+
+    api_addresses
+}
+
+/// Waits for the first event and drains all nodes if it asks to stop.
+#[cfg(not(windows))]
+fn wait(events: Receiver<Event>, api_addresses: &[SocketAddr]) {
+    if let Ok(Event::Stop) = events.recv() {
+        drain(api_addresses);
+    }
+}
+
+/// Drains the nodes serving api requests at `api_addresses` one after the
+/// other.
+///
+/// A node which is the only peer of the network has nobody to hand its
+/// records to and is left as it is.
+fn drain(api_addresses: &[SocketAddr]) {
+    for &api_address in api_addresses {
+        let client = ApiClient::new(api_address, DRAIN_TIMEOUT);
+
+        let result = client.node_info().and_then(|node_info| {
+            if node_info.successor == node_info.socket_addr {
+                Ok(None)
+            } else {
+                client.drain().map(Some)
+            }
+        });
+
+        match result {
+            Ok(Some(records)) => info!(
+                "Drained node {}, handed off {} records",
+                api_address, records
+            ),
+            Ok(None) => info!("Node {} is the only peer of the network", api_address),
+            Err(err) => warn!("Could not drain node {}: {}", api_address, err),
+        }
+    }
+}
+
+/// Runs the nodes until `SIGTERM` or `SIGINT` is received or all of them
+/// have terminated.
+#[cfg(unix)]
+pub fn run(
+    _name: &str,
+    configs: Vec<Config>,
+    bootstrap: Option<SocketAddr>,
+) -> Result<(), Box<dyn Error>> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    // register before starting such that early signals are not lost
+    let mut signals = Signals::new([SIGTERM, SIGINT])?;
+
+    let nodes = start(configs, bootstrap)?;
+    let (events, received) = mpsc::channel();
+    let api_addresses = supervise(nodes, events.clone());
+
+    thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            info!("Received signal {}, stopping", signal);
+
+            let _ = events.send(Event::Stop);
+        }
+    });
+
+    wait(received, &api_addresses);
+
+    Ok(())
+}
+
+/// Runs the nodes in the foreground until all of them have terminated.
+#[cfg(not(any(unix, windows)))]
+pub fn run(
+    _name: &str,
+    configs: Vec<Config>,
+    bootstrap: Option<SocketAddr>,
+) -> Result<(), Box<dyn Error>> {
+    let nodes = start(configs, bootstrap)?;
+    let (events, received) = mpsc::channel();
+    let api_addresses = supervise(nodes, events);
+
+    wait(received, &api_addresses);
+
+    Ok(())
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::*;
+    use std::ffi::OsString;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use windows_service::service::{
+        ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
+        ServiceType,
+    };
+    use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+    use windows_service::{define_windows_service, service_dispatcher};
+
+    /// Time the service control manager waits for the nodes to start or to
+    /// be drained
+    const WAIT_HINT: Duration = Duration::from_secs(120);
+
+    /// Arguments of the service until the dispatcher calls `service_main`
+    type Arguments = (String, Vec<Config>, Option<SocketAddr>);
+
+    static ARGUMENTS: Mutex<Option<Arguments>> = Mutex::new(None);
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    /// Runs the nodes as the service `name` until the service control manager
+    /// stops it or all of them have terminated.
+    pub fn run(
+        name: &str,
+        configs: Vec<Config>,
+        bootstrap: Option<SocketAddr>,
+    ) -> Result<(), Box<dyn Error>> {
+        *ARGUMENTS.lock().unwrap() = Some((name.to_string(), configs, bootstrap));
+
+        // blocks until the service has stopped
+        service_dispatcher::start(name, ffi_service_main)?;
+
+        Ok(())
+    }
+
+    fn service_main(_: Vec<OsString>) {
+        if let Err(err) = run_service() {
+            error!("Service failed: {}", err);
+        }
+    }
+
+    fn run_service() -> Result<(), Box<dyn Error>> {
+        let (name, configs, bootstrap) = ARGUMENTS
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or("service has already been started")?;
+
+        let (events, received) = mpsc::channel();
+        let stop = events.clone();
+
+        let status_handle =
+            service_control_handler::register(&name, move |control| match control {
+                ServiceControl::Stop | ServiceControl::Shutdown => {
+                    let _ = stop.send(Event::Stop);
+                    ServiceControlHandlerResult::NoError
+                }
+                ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+                _ => ServiceControlHandlerResult::NotImplemented,
+            })?;
+
+        status_handle.set_service_status(status(ServiceState::StartPending))?;
+
+        let nodes = match start(configs, bootstrap) {
+            Ok(nodes) => nodes,
+            Err(err) => {
+                status_handle.set_service_status(ServiceStatus {
+                    exit_code: ServiceExitCode::ServiceSpecific(1),
+                    ..status(ServiceState::Stopped)
+                })?;
+
+                return Err(err);
+            }
+        };
+
+        let api_addresses = supervise(nodes, events);
+        status_handle.set_service_status(status(ServiceState::Running))?;
+
+        if let Ok(Event::Stop) = received.recv() {
+            status_handle.set_service_status(status(ServiceState::StopPending))?;
+            drain(&api_addresses);
+        }
+
+        status_handle.set_service_status(status(ServiceState::Stopped))?;
+
+        Ok(())
+    }
+
+    fn status(state: ServiceState) -> ServiceStatus {
+        let controls_accepted = match state {
+            ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            _ => ServiceControlAccept::empty(),
+        };
+
+        ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted,
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint: 0,
+            wait_hint: WAIT_HINT,
+            process_id: None,
+        }
+    }
+}
@@ -0,0 +1,425 @@
+//! Cached copies of hot keys at the peers requesting them
+//!
+//! Consistent hashing assigns every key to a single peer, so a key which
+//! many clients read at the same time overloads the peer responsible for
+//! it. That peer counts the `STORAGE GET` requests for every key in its
+//! [`HotKeys`]. Once a key is requested more than `threshold` times within a
+//! second, the peer pushes a copy of its value with `STORAGE CACHE PUSH` to
+//! the peers which requested it most. They keep the copy in their [`Cache`]
+//! for at most [`CACHE_TTL`] and answer `DHT GET` requests from it without
+//! a lookup.
+//!
+//! Only peers with a cache tell the responsible peer their address in
+//! `STORAGE GET`. A copy may miss a newer value stored at another peer for
+//! the rest of its time to live, while the peer storing it discards its own
+//! copy right away.
+//!
+//! # Examples
+//!
+//! ```
+//! # use chord::cache::Cache;
+//! # use chord::storage::{Key, Record};
+//! # use std::time::Duration;
+//! #
+//! let cache = Cache::new();
+//! let key = Key {
+//!     raw_key: [3; 32],
+//!     replication_index: 0,
+//! };
+//!
+//! cache.insert(key, Record::new(vec![1, 2, 3], Duration::from_secs(60), 1));
+//!
+//! let (cached_key, storage_success) = cache.get(key.raw_key).unwrap();
+//! assert_eq!(key, cached_key);
+//! assert_eq!([1, 2, 3], *storage_success.value);
+//! ```
+//!
+//! [`HotKeys`]: struct.HotKeys.html
+//! [`Cache`]: struct.Cache.html
+//! [`CACHE_TTL`]: constant.CACHE_TTL.html
+
+use crate::clock::{self, Clock};
+use crate::framing::Framing;
+use crate::message::p2p::StorageGetSuccess;
+use crate::network::SocketOptions;
+use crate::procedures::Procedures;
+use crate::proxy::Proxy;
+use crate::storage::{Key, Record};
+use crate::sync::MutexExt;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Maximum time for which a pushed copy is kept
+pub const CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// Time over which the requests for a key are counted
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Number of requesting peers which receive a copy of a hot key
+const PUSH_TARGETS: usize = 3;
+
+/// Maximum number of keys whose requests are counted at the same time
+const MAX_TRACKED_KEYS: usize = 4096;
+
+/// Maximum number of copies kept at the same time
+const CACHE_CAPACITY: usize = 1024;
+
+/// Requests for a single key in the current window
+struct KeyRate {
+    started: Instant,
+    requests: u32,
+    requesters: HashMap<SocketAddr, u32>,
+    /// Peers holding a copy which is not due for a refresh yet
+    pushed: HashMap<SocketAddr, Instant>,
+}
+
+impl KeyRate {
+    fn new(now: Instant) -> Self {
+        Self {
+            started: now,
+            requests: 0,
+            requesters: HashMap::new(),
+            pushed: HashMap::new(),
+        }
+    }
+
+    fn is_idle(&self, now: Instant) -> bool {
+        now.duration_since(self.started) >= WINDOW && self.pushed.values().all(|&due| due <= now)
+    }
+}
+
+/// Request rates of the keys a peer is responsible for
+///
+/// Copies are refreshed after half of [`CACHE_TTL`] while the key stays hot.
+///
+/// [`CACHE_TTL`]: constant.CACHE_TTL.html
+pub struct HotKeys {
+    threshold: u32,
+    keys: Mutex<HashMap<Key, KeyRate>>,
+    procedures: Procedures,
+    clock: Arc<dyn Clock>,
+}
+
+impl HotKeys {
+    /// Creates a new `HotKeys` instance which considers keys hot once they
+    /// are requested `threshold` times within a second.
+    ///
+    /// `timeout` is the timeout in milliseconds used to push copies.
+    pub fn new(threshold: u32, timeout: u64) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            keys: Mutex::new(HashMap::new()),
+            procedures: Procedures::new(timeout),
+            clock: clock::system(),
+        }
+    }
+
+    /// Pushes the copies through `proxy` if it is given.
+    pub fn with_proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.procedures = self.procedures.with_proxy(proxy);
+        self
+    }
+
+    /// Pushes the copies in frames of `framing`.
+    pub fn with_framing(mut self, framing: Arc<dyn Framing>) -> Self {
+        self.procedures = self.procedures.with_framing(framing);
+        self
+    }
+
+    /// Pushes the copies over sockets with `socket_options`.
+    pub fn with_socket_options(mut self, socket_options: SocketOptions) -> Self {
+        self.procedures = self.procedures.with_socket_options(socket_options);
+        self
+    }
+
+    /// Takes the time from `clock` to count the requests per second.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Counts a request for `key` from the peer listening on `requester` if
+    /// it caches values.
+    ///
+    /// Returns the peers which should receive a copy of the value now. These
+    /// are the peers requesting the key most while it is hot, except those
+    /// which received a copy recently.
+    pub fn record(&self, key: Key, requester: Option<SocketAddr>) -> Vec<SocketAddr> {
+        let now = self.clock.now();
+        let mut keys = self.keys.lock_or_recover();
+
+        if !keys.contains_key(&key) && keys.len() >= MAX_TRACKED_KEYS {
+            keys.retain(|_, rate| !rate.is_idle(now));
+
+            if keys.len() >= MAX_TRACKED_KEYS {
+                return Vec::new();
+            }
+        }
+
+        let rate = keys.entry(key).or_insert_with(|| KeyRate::new(now));
+
+        if now.duration_since(rate.started) >= WINDOW {
+            rate.started = now;
+            rate.requests = 0;
+            rate.requesters.clear();
+        }
+
+        rate.requests = rate.requests.saturating_add(1);
+
+        if let Some(requester) = requester {
+            *rate.requesters.entry(requester).or_insert(0) += 1;
+        }
+
+        if rate.requests < self.threshold {
+            return Vec::new();
+        }
+
+        let mut requesters: Vec<(SocketAddr, u32)> = rate
+            .requesters
+            .iter()
+            .map(|(&requester, &requests)| (requester, requests))
+            .collect();
+        requesters.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        rate.pushed.retain(|_, due| *due > now);
+
+        let targets: Vec<SocketAddr> = requesters
+            .into_iter()
+            .take(PUSH_TARGETS)
+            .map(|(requester, _)| requester)
+            .filter(|requester| !rate.pushed.contains_key(requester))
+            .collect();
+
+        for &target in &targets {
+            rate.pushed.insert(target, now + CACHE_TTL / 2);
+        }
+
+        targets
+    }
+
+    /// Forgets which peers hold a copy of `key` after its value changed,
+    /// such that the next request while it is hot pushes the new value.
+    pub fn invalidate(&self, key: Key) {
+        if let Some(rate) = self.keys.lock_or_recover().get_mut(&key) {
+            rate.pushed.clear();
+        }
+    }
+
+    /// Pushes a copy of `record` stored under `key` to every peer of
+    /// `targets` and returns the number of peers which cached it.
+    pub fn push(&self, targets: &[SocketAddr], key: Key, record: &Record) -> usize {
+        let ttl = record.ttl_at(self.clock.now()).min(CACHE_TTL);
+        let mut pushed = 0;
+
+        for &target in targets {
+            match self.procedures.push_cached_value(target, key, record, ttl) {
+                Ok(true) => {
+                    info!("Pushed copy of hot key {} to peer {}", key, target);
+
+                    pushed += 1;
+                }
+                Ok(false) => {}
+                Err(err) => warn!(
+                    "Could not push copy of hot key {} to peer {}: {}",
+                    key, target, err
+                ),
+            }
+        }
+
+        pushed
+    }
+}
+
+/// Copies of values pushed by the peers responsible for them
+///
+/// The copies are shared by the api handler which answers from them and the
+/// peer-to-peer handler which receives them. Each copy expires after the time
+/// to live it was pushed with, but at most after [`CACHE_TTL`].
+///
+/// [`CACHE_TTL`]: constant.CACHE_TTL.html
+pub struct Cache {
+    copies: Mutex<HashMap<[u8; 32], (Key, Record)>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Cache {
+    /// Creates a new empty `Cache` instance.
+    pub fn new() -> Self {
+        Self {
+            copies: Mutex::new(HashMap::new()),
+            clock: clock::system(),
+        }
+    }
+
+    /// Takes the time from `clock` to decide when copies expire.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Keeps a copy of `record` stored under `key` unless the cache is full.
+    ///
+    /// The copy replaces an older copy of the same raw key.
+    pub fn insert(&self, key: Key, mut record: Record) {
+        let now = self.clock.now();
+        record.expires = record.expires.min(now + CACHE_TTL);
+
+        let mut copies = self.copies.lock_or_recover();
+
+        if let Some((_, cached)) = copies.get(&key.raw_key) {
+            if cached.version > record.version {
+                return;
+            }
+        }
+
+        if !copies.contains_key(&key.raw_key) && copies.len() >= CACHE_CAPACITY {
+            copies.retain(|_, (_, record)| !record.is_expired_at(now));
+
+            if copies.len() >= CACHE_CAPACITY {
+                return;
+            }
+        }
+
+        copies.insert(key.raw_key, (key, record));
+    }
+
+    /// Returns the copy of the value stored under `raw_key` along with the
+    /// key of the replica it was taken from unless it expired already.
+    pub fn get(&self, raw_key: [u8; 32]) -> Option<(Key, StorageGetSuccess)> {
+        let now = self.clock.now();
+        let mut copies = self.copies.lock_or_recover();
+
+        match copies.get(&raw_key) {
+            Some((_, record)) if record.is_expired_at(now) => {
+                copies.remove(&raw_key);
+
+                None
+            }
+            Some((key, record)) => Some((
+                *key,
+                StorageGetSuccess {
+                    raw_key,
+                    version: record.version,
+                    ttl: record.ttl_at(now).as_millis() as u64,
+                    stored: record.stored,
+                    value: record.value.clone(),
+                },
+            )),
+            None => None,
+        }
+    }
+
+    /// Discards the copy of the value stored under `raw_key`.
+    pub fn remove(&self, raw_key: [u8; 32]) {
+        self.copies.lock_or_recover().remove(&raw_key);
+    }
+
+    /// Returns the number of copies including expired ones.
+    pub fn len(&self) -> usize {
+        self.copies.lock_or_recover().len()
+    }
+
+    /// Returns whether the cache holds no copies.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn key(byte: u8) -> Key {
+        Key {
+            raw_key: [byte; 32],
+            replication_index: 0,
+        }
+    }
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::new([127, 0, 0, 1].into(), port)
+    }
+
+    #[test]
+    fn hot_key_pushes_to_top_requesters() {
+        let clock = Arc::new(ManualClock::new());
+        let hot_keys = HotKeys::new(6, 1000).with_clock(clock.clone());
+
+        for port in [1, 1, 2, 2, 3] {
+            assert!(hot_keys.record(key(1), Some(addr(port))).is_empty());
+        }
+
+        // the sixth request makes the key hot
+        assert_eq!(
+            vec![addr(1), addr(2), addr(3)],
+            hot_keys.record(key(1), None)
+        );
+
+        // peers are not pushed to again until their copy is due for a refresh
+        assert!(hot_keys.record(key(1), Some(addr(1))).is_empty());
+
+        clock.advance(CACHE_TTL / 2);
+
+        for _ in 0..5 {
+            assert!(hot_keys.record(key(1), Some(addr(1))).is_empty());
+        }
+
+        assert_eq!(vec![addr(1)], hot_keys.record(key(1), Some(addr(1))));
+    }
+
+    #[test]
+    fn cold_keys_are_not_pushed() {
+        let clock = Arc::new(ManualClock::new());
+        let hot_keys = HotKeys::new(3, 1000).with_clock(clock.clone());
+
+        for _ in 0..5 {
+            assert!(hot_keys.record(key(1), Some(addr(1))).is_empty());
+            assert!(hot_keys.record(key(2), Some(addr(1))).is_empty());
+
+            clock.advance(WINDOW / 2);
+        }
+    }
+
+    #[test]
+    fn invalidate_pushes_again() {
+        let hot_keys = HotKeys::new(1, 1000);
+
+        assert_eq!(vec![addr(1)], hot_keys.record(key(1), Some(addr(1))));
+        assert!(hot_keys.record(key(1), Some(addr(1))).is_empty());
+
+        hot_keys.invalidate(key(1));
+        assert_eq!(vec![addr(1)], hot_keys.record(key(1), Some(addr(1))));
+    }
+
+    #[test]
+    fn cached_copies_expire() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = Cache::new().with_clock(clock.clone());
+
+        let record = Record::new_at(vec![1, 2, 3], Duration::from_secs(60), 2, clock.now());
+        cache.insert(key(1), record);
+
+        let (_, storage_success) = cache.get([1; 32]).unwrap();
+        assert_eq!(CACHE_TTL.as_millis() as u64, storage_success.ttl);
+
+        // older versions do not replace the copy
+        cache.insert(
+            key(1),
+            Record::new_at(vec![4], Duration::from_secs(60), 1, clock.now()),
+        );
+        assert_eq!(2, cache.get([1; 32]).unwrap().1.version);
+
+        clock.advance(CACHE_TTL);
+        assert_eq!(None, cache.get([1; 32]));
+        assert!(cache.is_empty());
+    }
+}
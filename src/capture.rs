@@ -0,0 +1,174 @@
+//! Capture of all messages exchanged by this process
+//!
+//! After [`start`] has been called, every message sent or received over a
+//! [`Connection`] is appended to the capture file such that protocol-level
+//! issues between peers can be replayed and analyzed offline, for example
+//! with `dht decode`.
+//!
+//! The capture file contains one JSON object per line:
+//!
+//! ```text
+//! {"time":1554980000123456,"direction":"sent","local":"127.0.0.1:50712","peer":"127.0.0.1:8080","type":"PEER FIND","message":"0028041a..."}
+//! ```
+//!
+//! `time` is the number of microseconds since the unix epoch and `message`
+//! contains the hex encoded bytes of the message as sent over the wire,
+//! including the header. See the [`codec`] module for the encoding.
+//!
+//! The [`read`] function parses a capture file back into [`Record`]s.
+//!
+//! [`start`]: fn.start.html
+//! [`read`]: fn.read.html
+//! [`Record`]: struct.Record.html
+//! [`Connection`]: ../network/struct.Connection.html
+//! [`codec`]: ../message/codec/index.html
+
+#[cfg(feature = "node")]
+use crate::message::Message;
+use crate::sync::MutexExt;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+#[cfg(feature = "node")]
+use std::io::{BufRead, BufReader, Cursor};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static CAPTURE: OnceLock<Mutex<LineWriter<File>>> = OnceLock::new();
+
+/// Whether a message has been sent or received
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Direction::Sent => "sent".fmt(f),
+            Direction::Received => "received".fmt(f),
+        }
+    }
+}
+
+/// Starts to append all messages of this process to the file at `path`.
+///
+/// The capture cannot be stopped or moved to another file, thus calling this
+/// function a second time fails.
+pub fn start<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    CAPTURE
+        .set(Mutex::new(LineWriter::new(file)))
+        .map_err(|_| io::Error::new(io::ErrorKind::AlreadyExists, "Capture already started"))
+}
+
+/// Returns whether messages are being captured.
+pub fn is_active() -> bool {
+    CAPTURE.get().is_some()
+}
+
+/// Appends a message to the capture file if the capture has been started.
+///
+/// Errors are only logged such that a full disk does not interrupt the
+/// operation of the peer.
+pub(crate) fn record(
+    direction: Direction,
+    local_addr: SocketAddr,
+    peer_addr: SocketAddr,
+    name: &str,
+    bytes: &[u8],
+) {
+    let capture = match CAPTURE.get() {
+        Some(capture) => capture,
+        None => return,
+    };
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_micros() as u64)
+        .unwrap_or(0);
+
+    let line = format!(
+        "{{\"time\":{},\"direction\":\"{}\",\"local\":\"{}\",\"peer\":\"{}\",\"type\":\"{}\",\"message\":\"{}\"}}\n",
+        time,
+        direction,
+        local_addr,
+        peer_addr,
+        name,
+        hex::encode(bytes)
+    );
+
+    if let Err(err) = capture.lock_or_recover().write_all(line.as_bytes()) {
+        warn!("Could not capture message: {}", err);
+    }
+}
+
+/// A message read back from a capture file
+#[cfg(feature = "node")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// Microseconds since the unix epoch
+    pub time: u64,
+    pub direction: Direction,
+    pub local_addr: SocketAddr,
+    pub peer_addr: SocketAddr,
+    /// The message as sent over the wire including its header
+    pub bytes: Vec<u8>,
+}
+
+#[cfg(feature = "node")]
+impl Record {
+    /// Parses a single line of a capture file.
+    pub fn parse(line: &str) -> crate::Result<Self> {
+        let record: serde_json::Value = serde_json::from_str(line)?;
+
+        let field = |name: &str| {
+            record[name]
+                .as_str()
+                .ok_or_else(|| format!("missing field `{}`", name))
+        };
+
+        let direction = match field("direction")? {
+            "sent" => Direction::Sent,
+            "received" => Direction::Received,
+            direction => return Err(format!("unknown direction `{}`", direction).into()),
+        };
+
+        Ok(Record {
+            time: record["time"].as_u64().ok_or("missing field `time`")?,
+            direction,
+            local_addr: field("local")?.parse()?,
+            peer_addr: field("peer")?.parse()?,
+            bytes: hex::decode(field("message")?)?,
+        })
+    }
+
+    /// Decodes the captured message.
+    pub fn message(&self) -> io::Result<Message> {
+        Message::parse(Cursor::new(&self.bytes[..]))
+    }
+}
+
+/// Reads all records of the capture file at `path`.
+#[cfg(feature = "node")]
+pub fn read<P: AsRef<Path>>(path: P) -> crate::Result<Vec<Record>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record = Record::parse(&line).map_err(|err| format!("line {}: {}", number + 1, err))?;
+        records.push(record);
+    }
+
+    Ok(records)
+}
@@ -0,0 +1,402 @@
+//! Controlled churn testing on a local ring
+//!
+//! The [`Chaos`] struct launches a ring of peers within a single process, each
+//! listening on its own loopback address. Every round some peers are killed
+//! and replaced by fresh peers joining the network, a workload of PUT and GET
+//! operations is applied and the routing invariants of the ring are checked.
+//!
+//! Killed peers keep their listener but drop every incoming connection such
+//! that other peers observe them exactly like crashed peers.
+//!
+//! All peers of the ring share a [`ManualClock`] which only moves when
+//! [`Chaos::advance`] is called. Advancing the virtual time runs the
+//! stabilization passes which would have happened in the meantime such that
+//! the expiry of values or the cadence of stabilization can be tested without
+//! waiting in real time.
+//!
+//! The churn and the workload are drawn from a [`SeededRandom`] such that a
+//! run with the same seed kills the same peers and stores the same keys.
+//!
+//! [`Chaos`]: struct.Chaos.html
+//! [`ManualClock`]: ../clock/struct.ManualClock.html
+//! [`Chaos::advance`]: struct.Chaos.html#method.advance
+//! [`SeededRandom`]: ../random/struct.SeededRandom.html
+
+use crate::clock::{Clock, ManualClock};
+use crate::handler::P2PHandler;
+use crate::metrics::Metrics;
+use crate::network::{Connection, Server, ServerHandler};
+use crate::procedures::Procedures;
+use crate::random::{Random, SeededRandom};
+use crate::routing::identifier::{Identifier, IdentifierInterval, Identify};
+use crate::routing::peer::PeerInfo;
+use crate::routing::Routing;
+use crate::stabilization::{Bootstrap, Stabilization};
+use crate::storage::{self, Key, Record, Storage};
+use crate::sync::MutexExt;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Port used by all peers of the ring
+const CHAOS_PORT: u16 = 38200;
+
+/// Parameters of a chaos run
+#[derive(Clone, Debug)]
+pub struct ChaosConfig {
+    /// Number of peers in the ring
+    pub nodes: usize,
+    /// Probability that a peer is killed in a round
+    pub kill_rate: f64,
+    /// Number of values stored in every round
+    pub puts: usize,
+    /// Number of stabilization passes over all peers in every round
+    pub stabilizations: usize,
+    /// Number of fingers of every peer
+    pub fingers: usize,
+    /// Seed for the random churn
+    pub seed: u64,
+    /// Timeout in milliseconds for all connections
+    pub timeout: u64,
+    /// Virtual time in seconds between two stabilization passes when the
+    /// time is advanced
+    pub stabilization_interval: u64,
+}
+
+/// A handler which drops all connections once its peer has been killed
+struct Killable<T> {
+    handler: T,
+    alive: Arc<AtomicBool>,
+}
+
+impl<T: ServerHandler> ServerHandler for Killable<T> {
+    fn handle_connection(&self, connection: Connection) {
+        if self.alive.load(Ordering::SeqCst) {
+            self.handler.handle_connection(connection);
+        }
+    }
+
+    fn handle_error(&self, error: io::Error) {
+        self.handler.handle_error(error)
+    }
+}
+
+struct Node {
+    addr: SocketAddr,
+    alive: Arc<AtomicBool>,
+    routing: Arc<Mutex<Routing<PeerInfo>>>,
+    storage: Arc<Mutex<Storage>>,
+    stabilization: Stabilization,
+}
+
+/// A local ring of peers exposed to scripted churn
+pub struct Chaos {
+    config: ChaosConfig,
+    nodes: Vec<Node>,
+    /// Stored keys along with the instant their values expire
+    keys: Vec<(Key, Instant)>,
+    random: SeededRandom,
+    next_host: u32,
+    clock: Arc<ManualClock>,
+    /// Virtual time passed since the last stabilization pass
+    since_stabilization: Duration,
+}
+
+impl Chaos {
+    /// Launches a new ring with `config.nodes` peers.
+    pub fn new(config: ChaosConfig) -> crate::Result<Self> {
+        let mut chaos = Self {
+            random: SeededRandom::new(config.seed),
+            config,
+            nodes: Vec::new(),
+            keys: Vec::new(),
+            next_host: 1,
+            clock: Arc::new(ManualClock::new()),
+            since_stabilization: Duration::from_secs(0),
+        };
+
+        let addr = chaos.next_addr();
+        let routing = Routing::from_addrs(addr, addr, addr, vec![addr; chaos.config.fingers]);
+        chaos.launch(addr, routing, Storage::new())?;
+
+        for _ in 1..chaos.config.nodes {
+            chaos.join()?;
+            chaos.stabilize();
+        }
+
+        Ok(chaos)
+    }
+
+    /// Returns the addresses of all peers which are currently alive.
+    pub fn alive(&self) -> Vec<SocketAddr> {
+        self.nodes.iter().map(|node| node.addr).collect()
+    }
+
+    /// Returns the virtual clock shared by all peers.
+    pub fn clock(&self) -> Arc<ManualClock> {
+        Arc::clone(&self.clock)
+    }
+
+    /// Advances the virtual time by `duration`.
+    ///
+    /// A stabilization pass over all peers is run for every
+    /// `stabilization_interval` which passes, just like the peers of a real
+    /// network would do in the meantime.
+    pub fn advance(&mut self, duration: Duration) {
+        let interval = Duration::from_secs(self.config.stabilization_interval);
+        let mut remaining = duration;
+
+        while self.since_stabilization + remaining >= interval {
+            let step = interval - self.since_stabilization;
+
+            self.clock.advance(step);
+            remaining -= step;
+            self.since_stabilization = Duration::from_secs(0);
+
+            self.stabilize_once();
+        }
+
+        self.clock.advance(remaining);
+        self.since_stabilization += remaining;
+    }
+
+    /// Returns the number of values stored on alive peers whose time to
+    /// live has passed.
+    pub fn expired(&self) -> usize {
+        let now = self.clock.now();
+
+        self.nodes
+            .iter()
+            .map(|node| {
+                let storage = node.storage.lock_or_recover();
+
+                storage
+                    .values()
+                    .filter(|record| record.is_expired_at(now))
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Runs a single round of churn, workload and invariant checks.
+    ///
+    /// Returns descriptions of all invariant violations found in this round.
+    pub fn round(&mut self) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        if let Err(err) = self.churn() {
+            violations.push(format!("churn failed: {}", err));
+        }
+
+        self.stabilize();
+        self.workload(&mut violations);
+        self.check_successors(&mut violations);
+        self.check_lookups(&mut violations);
+
+        violations
+    }
+
+    fn next_addr(&mut self) -> SocketAddr {
+        let host = self.next_host;
+        self.next_host += 1;
+
+        let ip = Ipv4Addr::new(127, 100, (host >> 8) as u8, host as u8);
+
+        SocketAddr::new(ip.into(), CHAOS_PORT)
+    }
+
+    fn launch(
+        &mut self,
+        addr: SocketAddr,
+        routing: Routing<PeerInfo>,
+        storage: Storage,
+    ) -> crate::Result<()> {
+        let routing = Arc::new(Mutex::new(routing));
+        let storage = Arc::new(Mutex::new(storage));
+        let alive = Arc::new(AtomicBool::new(true));
+
+        let clock = Arc::clone(&self.clock) as Arc<dyn Clock>;
+
+        let handler = Killable {
+            handler: P2PHandler::new(
+                Arc::clone(&routing),
+                Arc::clone(&storage),
+                self.config.timeout,
+            )
+            .with_clock(Arc::clone(&clock)),
+            alive: Arc::clone(&alive),
+        };
+        Server::new(handler).listen(addr, 4)?;
+
+        let stabilization = Stabilization::new(
+            Arc::clone(&routing),
+            Arc::new(Metrics::new()),
+            self.config.timeout,
+        )
+        .with_clock(clock);
+
+        self.nodes.push(Node {
+            addr,
+            alive,
+            routing,
+            storage,
+            stabilization,
+        });
+
+        Ok(())
+    }
+
+    fn join(&mut self) -> crate::Result<()> {
+        let addr = self.next_addr();
+        let boot_addr = self.nodes[self.random.below(self.nodes.len())].addr;
+
+        let (routing, storage) =
+            Bootstrap::new(addr, boot_addr, self.config.fingers).bootstrap(self.config.timeout)?;
+
+        self.launch(addr, routing, storage)
+    }
+
+    fn churn(&mut self) -> crate::Result<()> {
+        let mut killed = 0;
+
+        // keep at least one peer alive such that new peers can join
+        for i in (0..self.nodes.len()).rev() {
+            if self.nodes.len() > 1 && self.random.next_f64() < self.config.kill_rate {
+                let node = self.nodes.remove(i);
+                node.alive.store(false, Ordering::SeqCst);
+
+                info!("Killed peer {}", node.addr);
+
+                killed += 1;
+            }
+        }
+
+        for _ in 0..killed {
+            self.join()?;
+            self.stabilize();
+        }
+
+        Ok(())
+    }
+
+    fn stabilize(&mut self) {
+        for _ in 0..self.config.stabilizations {
+            self.stabilize_once();
+        }
+    }
+
+    fn stabilize_once(&mut self) {
+        for node in &mut self.nodes {
+            if let Err(err) = node.stabilization.stabilize() {
+                debug!("Stabilization of peer {} failed: {}", node.addr, err);
+            }
+        }
+    }
+
+    /// Returns the alive peer responsible for `identifier`.
+    fn responsible(&self, identifier: Identifier) -> SocketAddr {
+        let peers = self.alive();
+
+        *peers
+            .iter()
+            .find(|peer| {
+                let interval = IdentifierInterval::new(identifier, peer.identifier());
+
+                peers.iter().all(|other| {
+                    other == *peer || !interval.contains_open_closed(other.identifier())
+                })
+            })
+            .unwrap()
+    }
+
+    fn workload(&mut self, violations: &mut Vec<String>) {
+        let procedures = Procedures::new(self.config.timeout);
+
+        for _ in 0..self.config.puts {
+            let mut raw_key = [0; 32];
+            raw_key[..8].copy_from_slice(&self.random.next_u64().to_be_bytes());
+
+            let key = Key {
+                raw_key,
+                replication_index: 0,
+            };
+
+            let peer_addr = self.responsible(key.identifier());
+            let ttl = Duration::from_secs(u64::from(u16::MAX));
+            let record =
+                Record::new(raw_key.to_vec(), ttl, storage::current_version()).with_replicas(1);
+            let result = procedures.put_value(peer_addr, key, record);
+
+            match result {
+                Ok(true) => self.keys.push((key, self.clock.now() + ttl)),
+                Ok(false) => violations.push(format!("peer {} rejected key {}", peer_addr, key)),
+                Err(err) => violations.push(format!("could not put key {}: {}", key, err)),
+            }
+        }
+
+        // expired values are not found anymore
+        let now = self.clock.now();
+        self.keys.retain(|(_, expiry)| *expiry > now);
+
+        let lost = self
+            .keys
+            .iter()
+            .filter(|(key, _)| {
+                let peer_addr = self.responsible(key.identifier());
+
+                match procedures.get_value(peer_addr, *key) {
+                    Ok(Some(value)) => *value != key.raw_key[..],
+                    _ => true,
+                }
+            })
+            .count();
+
+        if lost > 0 {
+            violations.push(format!("{} of {} values lost", lost, self.keys.len()));
+        }
+    }
+
+    fn check_successors(&self, violations: &mut Vec<String>) {
+        for node in &self.nodes {
+            let successor = {
+                let routing = node.routing.lock_or_recover();
+
+                routing.successor.socket_addr()
+            };
+
+            let current_id = node.addr.identifier();
+            let expected = self.responsible(current_id.successor_id(0));
+
+            if successor != expected {
+                violations.push(format!(
+                    "peer {} has successor {} instead of {}",
+                    node.addr, successor, expected
+                ));
+            }
+        }
+    }
+
+    fn check_lookups(&mut self, violations: &mut Vec<String>) {
+        let procedures = Procedures::new(self.config.timeout);
+
+        for _ in 0..self.nodes.len() {
+            let mut bytes = [0; 32];
+            bytes[..8].copy_from_slice(&self.random.next_u64().to_be_bytes());
+
+            let identifier = Identifier::new(&bytes);
+            let start = self.nodes[self.random.below(self.nodes.len())].addr;
+            let expected = self.responsible(identifier);
+
+            match procedures.find_peer(identifier, start) {
+                Ok(peer_addr) if peer_addr == expected => {}
+                Ok(peer_addr) => violations.push(format!(
+                    "lookup from {} found {} instead of {}",
+                    start, peer_addr, expected
+                )),
+                Err(err) => violations.push(format!("lookup from {} failed: {}", start, err)),
+            }
+        }
+    }
+}
@@ -0,0 +1,921 @@
+//! Client for the api interface of a DHT peer
+//!
+//! The [`ApiClient`] struct wraps the api messages in simple method calls
+//! which open a [`Connection`] to the api address of a peer for every
+//! operation.
+//!
+//! Interactive applications which have to see their own writes wrap the
+//! client in a [`Session`].
+//!
+//! [`ApiClient`]: struct.ApiClient.html
+//! [`Connection`]: ../network/struct.Connection.html
+//! [`Session`]: struct.Session.html
+
+use crate::deadline::Deadline;
+use crate::dht::{DhtKey, DhtValue, Namespace};
+use crate::error::{MessageError, StaleError};
+use crate::export::{ExportReader, ExportRecord, ExportWriter};
+use crate::message::api::{
+    ApiEncoding, DhtCancel, DhtDelete, DhtExport, DhtExportReply, DhtFlush, DhtGet, DhtImport,
+    DhtImportReply, DhtListLocal, DhtListLocalReply, DhtPrefetch, DhtPut, DhtPutSuccess,
+    DhtResolve, DhtResolveReply, DhtRingWalk, DhtRingWalkReply, DhtSuccess, Encoding, FlushScope,
+    ListCursor, NodeDrain, NodeInfo, NodeInfoReply, NodeLoad, NodeLoadReply, NodePeers,
+    NodePeersReply, NodeReadOnly, Quorum, RecordMetadata, StoredRecord,
+};
+use crate::message::Message;
+use crate::network::Connection;
+use crate::routing::identifier::Identifier;
+use crate::sync::MutexExt;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of records read from an export before they are sent to the peer
+const IMPORT_BATCH: usize = 1024;
+
+/// Number of written keys a [`Session`] remembers by default
+///
+/// [`Session`]: struct.Session.html
+pub const SESSION_CAPACITY: usize = 1024;
+
+/// The reply to a conditional read with [`ApiClient::get_if_newer`]
+///
+/// [`ApiClient::get_if_newer`]: struct.ApiClient.html#method.get_if_newer
+#[derive(Debug, PartialEq)]
+pub enum Conditional {
+    /// A value newer than the given version along with its metadata
+    Modified(DhtValue, Option<RecordMetadata>),
+    /// No value newer than the given version is stored.
+    NotModified,
+    /// No value is stored under the key.
+    NotFound,
+}
+
+/// A client talking to the api interface of a DHT peer
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chord::client::ApiClient;
+/// # use chord::dht::{DhtKey, DhtValue};
+/// #
+/// let client = ApiClient::new("127.0.0.1:8080".parse().unwrap(), 3600);
+/// let key = DhtKey::from_name("greeting");
+/// let value = DhtValue::new(b"value".to_vec()).unwrap();
+///
+/// client.put(key, value, 60, 2)
+///     .expect("could not store value");
+///
+/// let value = client.get(key).expect("could not retrieve value");
+/// ```
+pub struct ApiClient {
+    api_address: SocketAddr,
+    timeout: u64,
+    encoding: Encoding,
+    token: Option<String>,
+    /// Connections of the running cancellable searches by their request ID
+    searches: Mutex<HashMap<u32, Connection>>,
+}
+
+impl ApiClient {
+    /// Creates a new client for the peer listening on `api_address`.
+    ///
+    /// `timeout` is the timeout in milliseconds used for all connections.
+    pub fn new(api_address: SocketAddr, timeout: u64) -> Self {
+        Self {
+            api_address,
+            timeout,
+            encoding: Encoding::Binary,
+            token: None,
+            searches: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Exchanges the messages with the peer in `encoding` instead of the
+    /// binary encoding.
+    ///
+    /// Every connection negotiates the encoding with an `API ENCODING`
+    /// message first and fails if the peer does not accept it.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Authenticates every connection as the [tenant] with `token`.
+    ///
+    /// Requests fail if the peer does not know the token.
+    ///
+    /// [tenant]: ../tenant/index.html
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    fn open(&self, timeout: u64) -> crate::Result<Connection> {
+        let mut con = Connection::open(self.api_address, timeout)?;
+
+        if self.encoding == Encoding::Binary && self.token.is_none() {
+            return Ok(con);
+        }
+
+        let api_encoding = ApiEncoding {
+            encoding: self.encoding,
+            token: self.token.clone(),
+        };
+        con.send(&Message::ApiEncoding(api_encoding))?;
+
+        match con.receive()? {
+            Message::ApiEncoding(ApiEncoding { encoding, .. }) if encoding == self.encoding => {
+                con.set_encoding(encoding);
+                Ok(con)
+            }
+            Message::ApiEncoding(_) => Err(format!(
+                "{} refused the {:?} encoding",
+                self.api_address, self.encoding
+            )
+            .into()),
+            msg => self.unexpected(msg, "API ENCODING", "API ENCODING"),
+        }
+    }
+
+    /// Stores `value` under `key` in the DHT for `ttl` seconds.
+    ///
+    /// The DHT does not confirm the operation such that this method returns
+    /// as soon as the `DHT PUT` message has been sent.
+    pub fn put(
+        &self,
+        key: DhtKey,
+        value: DhtValue,
+        ttl: u16,
+        replication: u8,
+    ) -> crate::Result<()> {
+        let dht_put = DhtPut {
+            ttl: u64::from(ttl) * 1000,
+            replication,
+            acks: 0,
+            key,
+            value,
+        };
+
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::DhtPut(dht_put))?;
+
+        Ok(())
+    }
+
+    /// Stores `value` under `key` in the DHT for `ttl` seconds and waits
+    /// until at least `acks` replicas confirmed the operation.
+    ///
+    /// Returns the number of confirmed replicas or `None` if the DHT replied
+    /// with a `DHT FAILURE` message because too few replicas confirmed.
+    pub fn put_acknowledged(
+        &self,
+        key: DhtKey,
+        value: DhtValue,
+        ttl: u16,
+        replication: u8,
+        acks: u8,
+    ) -> crate::Result<Option<u8>> {
+        let dht_put = DhtPut {
+            ttl: u64::from(ttl) * 1000,
+            replication,
+            acks,
+            key,
+            value,
+        };
+
+        let dht_put_success = self.send_put(dht_put)?;
+
+        Ok(dht_put_success.map(|dht_put_success| dht_put_success.acks))
+    }
+
+    /// Sends `dht_put` as is, for instance to store a value for less than a
+    /// second with a message from [`DhtPut::builder`].
+    ///
+    /// Waits for the confirmation if `dht_put` requests acknowledgements and
+    /// returns `None` if the DHT replied with a `DHT FAILURE` message.
+    ///
+    /// [`DhtPut::builder`]: ../message/api/struct.DhtPut.html#method.builder
+    pub fn put_message(&self, dht_put: DhtPut) -> crate::Result<Option<DhtPutSuccess>> {
+        if dht_put.acks > 0 {
+            return self.send_put(dht_put);
+        }
+
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::DhtPut(dht_put))?;
+
+        Ok(None)
+    }
+
+    fn send_put(&self, dht_put: DhtPut) -> crate::Result<Option<DhtPutSuccess>> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::DhtPut(dht_put))?;
+
+        match con.receive()? {
+            Message::DhtPutSuccess(dht_put_success) => Ok(Some(dht_put_success)),
+            Message::DhtFailure(_) => Ok(None),
+            msg => self.unexpected(msg, "DHT PUT SUCCESS or DHT FAILURE", "DHT PUT"),
+        }
+    }
+
+    /// Obtains the value stored under `key` in the DHT.
+    ///
+    /// Returns `None` if the DHT replied with a `DHT FAILURE` message.
+    pub fn get(&self, key: DhtKey) -> crate::Result<Option<DhtValue>> {
+        self.send_get(DhtGet {
+            key,
+            quorum: None,
+            budget: None,
+            request_id: None,
+            if_none_match: None,
+        })
+    }
+
+    /// Obtains the value stored under `key` in the DHT but gives up after
+    /// `budget`.
+    ///
+    /// The budget is passed on to the DHT which abandons the search once it
+    /// is spent. Returns `None` if the DHT replied with a `DHT FAILURE`
+    /// message and fails if no reply arrived in time.
+    pub fn get_within(&self, key: DhtKey, budget: Duration) -> crate::Result<Option<DhtValue>> {
+        let deadline = Deadline::after(budget);
+
+        let mut con = self.open(deadline.limit_timeout(self.timeout))?;
+        con.send(&Message::DhtGet(DhtGet {
+            key,
+            quorum: None,
+            budget: Some(deadline.budget()),
+            request_id: None,
+            if_none_match: None,
+        }))?;
+
+        self.receive_get(con)
+    }
+
+    /// Obtains the newest value stored under `key` among the first `replicas`
+    /// replicas of which at least `reads` have to reply.
+    ///
+    /// Returns `None` if the DHT replied with a `DHT FAILURE` message.
+    pub fn get_quorum(
+        &self,
+        key: DhtKey,
+        replicas: u8,
+        reads: u8,
+    ) -> crate::Result<Option<DhtValue>> {
+        let quorum = Quorum { replicas, reads };
+
+        self.send_get(DhtGet {
+            key,
+            quorum: Some(quorum),
+            budget: None,
+            request_id: None,
+            if_none_match: None,
+        })
+    }
+
+    /// Obtains the value stored under `key` in the DHT with a search which
+    /// can be aborted by calling [`cancel`] with the same `request_id` from
+    /// another thread.
+    ///
+    /// Returns `None` if the DHT replied with a `DHT FAILURE` message which
+    /// is also the case if the search has been cancelled.
+    ///
+    /// [`cancel`]: #method.cancel
+    pub fn get_cancellable(&self, key: DhtKey, request_id: u32) -> crate::Result<Option<DhtValue>> {
+        let dht_get = DhtGet {
+            key,
+            quorum: None,
+            budget: None,
+            request_id: Some(request_id),
+            if_none_match: None,
+        };
+
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::DhtGet(dht_get))?;
+
+        // the peer only accepts a DHT CANCEL over the same connection
+        self.searches
+            .lock_or_recover()
+            .insert(request_id, con.try_clone()?);
+
+        let result = self.receive_get(con);
+
+        self.searches.lock_or_recover().remove(&request_id);
+
+        result
+    }
+
+    /// Asks the peer to look up the values of `keys` in the background such
+    /// that following [`get`] calls for them return faster.
+    ///
+    /// The peer does not confirm the request such that this method returns
+    /// as soon as the `DHT PREFETCH` messages have been sent.
+    ///
+    /// [`get`]: #method.get
+    pub fn prefetch(&self, keys: &[DhtKey]) -> crate::Result<()> {
+        let mut con = self.open(self.timeout)?;
+
+        for keys in keys.chunks(DhtPrefetch::MAX_KEYS) {
+            let dht_prefetch = DhtPrefetch {
+                keys: keys.to_vec(),
+            };
+            con.send(&Message::DhtPrefetch(dht_prefetch))?;
+        }
+
+        Ok(())
+    }
+
+    /// Aborts the search started by [`get_cancellable`] with `request_id`.
+    ///
+    /// The DHT does not confirm the cancellation. Cancelling a search which
+    /// already finished or has not been started by this client has no effect.
+    ///
+    /// [`get_cancellable`]: #method.get_cancellable
+    pub fn cancel(&self, request_id: u32) -> crate::Result<()> {
+        let mut searches = self.searches.lock_or_recover();
+
+        if let Some(con) = searches.get_mut(&request_id) {
+            con.send(&Message::DhtCancel(DhtCancel { request_id }))?;
+        }
+
+        Ok(())
+    }
+
+    /// Obtains the value stored under `key` along with the metadata of the
+    /// replica it has been read from.
+    ///
+    /// The metadata is only available with the CBOR encoding, see
+    /// [`with_encoding`]. Returns `None` if the DHT replied with a
+    /// `DHT FAILURE` message.
+    ///
+    /// [`with_encoding`]: #method.with_encoding
+    pub fn get_with_metadata(
+        &self,
+        key: DhtKey,
+    ) -> crate::Result<Option<(DhtValue, Option<RecordMetadata>)>> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::DhtGet(DhtGet {
+            key,
+            quorum: None,
+            budget: None,
+            request_id: None,
+            if_none_match: None,
+        }))?;
+
+        let dht_success = self.receive_success(con)?;
+
+        Ok(dht_success.map(|dht_success| (dht_success.value, dht_success.metadata)))
+    }
+
+    /// Obtains the value stored under `key` only if it is newer than
+    /// `version`.
+    ///
+    /// Applications which poll large values pass the version of their last
+    /// read, see [`get_with_metadata`], such that the same value is not
+    /// transferred again.
+    ///
+    /// [`get_with_metadata`]: #method.get_with_metadata
+    pub fn get_if_newer(&self, key: DhtKey, version: u64) -> crate::Result<Conditional> {
+        self.send_conditional(DhtGet {
+            key,
+            quorum: None,
+            budget: None,
+            request_id: None,
+            if_none_match: Some(version),
+        })
+    }
+
+    fn send_conditional(&self, dht_get: DhtGet) -> crate::Result<Conditional> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::DhtGet(dht_get))?;
+
+        match con.receive()? {
+            Message::DhtSuccess(dht_success) => Ok(Conditional::Modified(
+                dht_success.value,
+                dht_success.metadata,
+            )),
+            Message::DhtNotModified(_) => Ok(Conditional::NotModified),
+            Message::DhtFailure(_) => Ok(Conditional::NotFound),
+            msg => self.unexpected(
+                msg,
+                "DHT SUCCESS, DHT NOT MODIFIED or DHT FAILURE",
+                "DHT GET",
+            ),
+        }
+    }
+
+    fn send_get(&self, dht_get: DhtGet) -> crate::Result<Option<DhtValue>> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::DhtGet(dht_get))?;
+
+        self.receive_get(con)
+    }
+
+    fn receive_get(&self, con: Connection) -> crate::Result<Option<DhtValue>> {
+        let dht_success = self.receive_success(con)?;
+
+        Ok(dht_success.map(|dht_success| dht_success.value))
+    }
+
+    fn receive_success(&self, mut con: Connection) -> crate::Result<Option<DhtSuccess>> {
+        match con.receive()? {
+            Message::DhtSuccess(dht_success) => Ok(Some(dht_success)),
+            Message::DhtFailure(_) => Ok(None),
+            msg => self.unexpected(msg, "DHT SUCCESS or DHT FAILURE", "DHT GET"),
+        }
+    }
+
+    /// Removes the value stored under `key` from the DHT.
+    ///
+    /// `replication` should match the value used when storing the value such
+    /// that all replicas are removed. Returns the number of removed replicas.
+    pub fn delete(&self, key: DhtKey, replication: u8) -> crate::Result<u8> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::DhtDelete(DhtDelete { replication, key }))?;
+
+        match con.receive()? {
+            Message::DhtDeleteReply(dht_delete_reply) => Ok(dht_delete_reply.replicas),
+            msg => self.unexpected(msg, "DHT DELETE REPLY", "DHT DELETE"),
+        }
+    }
+
+    /// Finds the address and identifier of the peer responsible for `key`
+    /// without fetching the value.
+    ///
+    /// Only the first replica with replication index 0 is resolved. Returns
+    /// `None` if the DHT replied with a `DHT FAILURE` message.
+    pub fn resolve(&self, key: DhtKey) -> crate::Result<Option<(SocketAddr, Identifier)>> {
+        let reply = self.send_resolve(key, false)?;
+
+        Ok(reply.map(|reply| (reply.socket_addr, reply.identifier)))
+    }
+
+    /// Traces the lookup for the peer responsible for `key`.
+    ///
+    /// Returns the addresses of all peers asked during the lookup in order.
+    /// The last address belongs to the peer responsible for `key`.
+    pub fn trace(&self, key: DhtKey) -> crate::Result<Option<Vec<SocketAddr>>> {
+        let reply = self.send_resolve(key, true)?;
+
+        Ok(reply.map(|reply| reply.path))
+    }
+
+    /// Obtains routing information and telemetry of the peer.
+    pub fn node_info(&self) -> crate::Result<NodeInfoReply> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::NodeInfo(NodeInfo))?;
+
+        match con.receive()? {
+            Message::NodeInfoReply(node_info_reply) => Ok(node_info_reply),
+            msg => self.unexpected(msg, "NODE INFO REPLY", "NODE INFO"),
+        }
+    }
+
+    /// Compares the load of the peer with the average of up to `samples`
+    /// peers from its routing table.
+    pub fn node_load(&self, samples: u16) -> crate::Result<NodeLoadReply> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::NodeLoad(NodeLoad { samples }))?;
+
+        match con.receive()? {
+            Message::NodeLoadReply(node_load_reply) => Ok(node_load_reply),
+            msg => self.unexpected(msg, "NODE LOAD REPLY", "NODE LOAD"),
+        }
+    }
+
+    /// Removes the records matching `scope` from the local storage of the
+    /// peer.
+    ///
+    /// Returns the number of removed records.
+    pub fn flush(&self, scope: FlushScope) -> crate::Result<u32> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::DhtFlush(DhtFlush { scope }))?;
+
+        match con.receive()? {
+            Message::DhtFlushReply(dht_flush_reply) => Ok(dht_flush_reply.records),
+            msg => self.unexpected(msg, "DHT FLUSH REPLY", "DHT FLUSH"),
+        }
+    }
+
+    /// Lists at most `limit` records in the local storage of the peer,
+    /// starting after the cursor `after`.
+    ///
+    /// The reply also contains the totals of the local storage and the
+    /// cursor of the next page.
+    pub fn list_local(
+        &self,
+        limit: u16,
+        after: Option<ListCursor>,
+    ) -> crate::Result<DhtListLocalReply> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::DhtListLocal(DhtListLocal { limit, after }))?;
+
+        match con.receive()? {
+            Message::DhtListLocalReply(dht_list_local_reply) => Ok(dht_list_local_reply),
+            msg => self.unexpected(msg, "DHT LIST LOCAL REPLY", "DHT LIST LOCAL"),
+        }
+    }
+
+    /// Exports at most `limit` records in the local storage of the peer
+    /// including their values, starting after the cursor `after`.
+    ///
+    /// The reply may contain fewer records such that it fits into a single
+    /// message. See [`export_to`] to export all records into a file.
+    ///
+    /// [`export_to`]: #method.export_to
+    pub fn export(&self, limit: u16, after: Option<ListCursor>) -> crate::Result<DhtExportReply> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::DhtExport(DhtExport { limit, after }))?;
+
+        match con.receive()? {
+            Message::DhtExportReply(dht_export_reply) => Ok(dht_export_reply),
+            msg => self.unexpected(msg, "DHT EXPORT REPLY", "DHT EXPORT"),
+        }
+    }
+
+    /// Writes all records in the local storage of the peer into an export.
+    ///
+    /// Only records whose key lies in `namespace` are exported if it is
+    /// given. Returns the number of records written. See the [`export`]
+    /// module for the format.
+    ///
+    /// [`export`]: ../export/index.html
+    pub fn export_to<W: Write>(
+        &self,
+        writer: W,
+        namespace: Option<&Namespace>,
+    ) -> crate::Result<usize> {
+        let mut writer = ExportWriter::new(writer)?;
+        let mut after = None;
+
+        loop {
+            let reply = self.export(DhtListLocalReply::MAX_RECORDS as u16, after)?;
+            after = reply.next_cursor();
+
+            for record in reply.records {
+                if namespace.is_some_and(|namespace| !namespace.contains(&record.key)) {
+                    continue;
+                }
+
+                writer.write(&ExportRecord {
+                    namespace: namespace.copied(),
+                    ..ExportRecord::from(record)
+                })?;
+            }
+
+            if after.is_none() {
+                break;
+            }
+        }
+
+        Ok(writer.finish()?)
+    }
+
+    /// Stores the given records in the local storage of the peer.
+    ///
+    /// Returns the number of records stored and skipped.
+    pub fn import(&self, records: Vec<StoredRecord>) -> crate::Result<DhtImportReply> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::DhtImport(DhtImport { records }))?;
+
+        match con.receive()? {
+            Message::DhtImportReply(dht_import_reply) => Ok(dht_import_reply),
+            msg => self.unexpected(msg, "DHT IMPORT REPLY", "DHT IMPORT"),
+        }
+    }
+
+    /// Stores all records of an export in the local storage of the peer.
+    ///
+    /// Records are sent in batches which fit into a single message. Returns
+    /// the number of records stored and skipped in total.
+    pub fn import_from<R: Read>(&self, reader: R) -> crate::Result<DhtImportReply> {
+        let mut records = ExportReader::new(reader)?.map(|record| record.map(StoredRecord::from));
+
+        let mut total = DhtImportReply {
+            stored: 0,
+            skipped: 0,
+        };
+
+        // only a batch of records is read into memory at once
+        loop {
+            let batch = records
+                .by_ref()
+                .take(IMPORT_BATCH)
+                .collect::<io::Result<Vec<_>>>()?;
+
+            if batch.is_empty() {
+                break;
+            }
+
+            for dht_import in DhtImport::pack_encoded(batch, self.encoding) {
+                let reply = self.import(dht_import.records)?;
+
+                total.stored += reply.stored;
+                total.skipped += reply.skipped;
+            }
+        }
+
+        Ok(total)
+    }
+
+    /// Lists at most `limit` fingers of the peer, starting after the finger
+    /// with index `after`.
+    pub fn peers(&self, limit: u16, after: Option<u16>) -> crate::Result<NodePeersReply> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::NodePeers(NodePeers { limit, after }))?;
+
+        match con.receive()? {
+            Message::NodePeersReply(node_peers_reply) => Ok(node_peers_reply),
+            msg => self.unexpected(msg, "NODE PEERS REPLY", "NODE PEERS"),
+        }
+    }
+
+    /// Walks the ring along the successors of the peer and lists at most
+    /// `limit` peers, starting after the peer `after`.
+    ///
+    /// Each page requires a lookup for every peer listed, thus the timeout
+    /// of the client should allow for that.
+    pub fn ring_walk(
+        &self,
+        limit: u16,
+        after: Option<SocketAddr>,
+    ) -> crate::Result<DhtRingWalkReply> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::DhtRingWalk(DhtRingWalk { limit, after }))?;
+
+        match con.receive()? {
+            Message::DhtRingWalkReply(dht_ring_walk_reply) => Ok(dht_ring_walk_reply),
+            msg => self.unexpected(msg, "DHT RING WALK REPLY", "DHT RING WALK"),
+        }
+    }
+
+    /// Makes the peer hand all its records to its successor and leave the
+    /// network.
+    ///
+    /// Returns the number of transferred records.
+    pub fn drain(&self) -> crate::Result<u32> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::NodeDrain(NodeDrain))?;
+
+        match con.receive()? {
+            Message::NodeDrainReply(node_drain_reply) => Ok(node_drain_reply.records),
+            msg => self.unexpected(msg, "NODE DRAIN REPLY", "NODE DRAIN"),
+        }
+    }
+
+    /// Makes the peer refuse new values if `read_only` is set or accept them
+    /// again otherwise while it keeps serving reads and routing requests.
+    ///
+    /// Returns whether the peer is read-only now.
+    pub fn set_read_only(&self, read_only: bool) -> crate::Result<bool> {
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::NodeReadOnly(NodeReadOnly { read_only }))?;
+
+        match con.receive()? {
+            Message::NodeReadOnlyReply(node_read_only_reply) => Ok(node_read_only_reply.read_only),
+            msg => self.unexpected(msg, "NODE READ ONLY REPLY", "NODE READ ONLY"),
+        }
+    }
+
+    /// Fails with a [`MessageError`] describing the unexpected reply `msg`.
+    ///
+    /// [`MessageError`]: ../error/struct.MessageError.html
+    fn unexpected<T>(
+        &self,
+        msg: Message,
+        expected: &'static str,
+        operation: &'static str,
+    ) -> crate::Result<T> {
+        let error = MessageError::new(msg)
+            .with_expected(expected)
+            .with_peer(self.api_address)
+            .with_operation(operation);
+
+        Err(Box::new(error))
+    }
+
+    fn send_resolve(&self, key: DhtKey, trace: bool) -> crate::Result<Option<DhtResolveReply>> {
+        let dht_resolve = DhtResolve {
+            replication_index: 0,
+            trace,
+            key,
+        };
+
+        let mut con = self.open(self.timeout)?;
+        con.send(&Message::DhtResolve(dht_resolve))?;
+
+        match con.receive()? {
+            Message::DhtResolveReply(reply) => Ok(Some(reply)),
+            Message::DhtFailure(_) => Ok(None),
+            msg => self.unexpected(msg, "DHT RESOLVE REPLY or DHT FAILURE", "DHT RESOLVE"),
+        }
+    }
+}
+
+/// Format of everything the command line tools print to stdout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Output {
+    /// Human readable text
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Output::Text),
+            "json" => Ok(Output::Json),
+            _ => Err(format!("Unknown output format {}", s)),
+        }
+    }
+}
+
+/// A value stored through a [`Session`]
+///
+/// [`Session`]: struct.Session.html
+#[derive(Clone, Copy)]
+struct Written {
+    version: u64,
+    replicas: u8,
+    expires: Instant,
+}
+
+/// Read-your-writes consistency on top of an [`ApiClient`]
+///
+/// A session remembers the version the DHT assigned to every value stored
+/// through it. Reads of these keys ask all replicas for a value at least as
+/// new and fail with a [`StaleError`] if only older replicas replied, for
+/// example because a replica missed the write while it was unreachable. The
+/// application may retry the read or fall back to [`ApiClient::get`].
+///
+/// The guarantee is best-effort: keys are forgotten once their value
+/// expires, when they are deleted through the session or when more keys
+/// than the capacity have been written. Peers which do not report the
+/// version of a stored value are read like with a plain client.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chord::client::{ApiClient, Session};
+/// # use chord::dht::{DhtKey, DhtValue};
+/// #
+/// let client = ApiClient::new("127.0.0.1:8080".parse().unwrap(), 3600);
+/// let session = Session::new(client);
+/// let key = DhtKey::from_name("draft");
+///
+/// session.put(key, DhtValue::new(b"v2".to_vec()).unwrap(), 60, 2)
+///     .expect("could not store value");
+///
+/// // either the value just written, a newer one or an error
+/// let value = session.get(key);
+/// ```
+///
+/// [`ApiClient`]: struct.ApiClient.html
+/// [`StaleError`]: ../error/struct.StaleError.html
+/// [`ApiClient::get`]: struct.ApiClient.html#method.get
+pub struct Session {
+    client: ApiClient,
+    writes: Mutex<HashMap<DhtKey, Written>>,
+    capacity: usize,
+}
+
+impl Session {
+    /// Creates a new session which sends its requests with `client`.
+    pub fn new(client: ApiClient) -> Self {
+        Self {
+            client,
+            writes: Mutex::new(HashMap::new()),
+            capacity: SESSION_CAPACITY,
+        }
+    }
+
+    /// Remembers at most `capacity` written keys instead of
+    /// [`SESSION_CAPACITY`].
+    ///
+    /// [`SESSION_CAPACITY`]: constant.SESSION_CAPACITY.html
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Returns the client for requests which do not depend on earlier
+    /// writes.
+    pub fn client(&self) -> &ApiClient {
+        &self.client
+    }
+
+    /// Stores `value` under `key` and waits until a replica confirmed it.
+    ///
+    /// Returns `false` if the DHT replied with a `DHT FAILURE` message.
+    pub fn put(
+        &self,
+        key: DhtKey,
+        value: DhtValue,
+        ttl: u16,
+        replication: u8,
+    ) -> crate::Result<bool> {
+        let dht_put = DhtPut {
+            ttl: u64::from(ttl) * 1000,
+            replication,
+            acks: 1,
+            key,
+            value,
+        };
+
+        let version = match self.client.send_put(dht_put)? {
+            Some(dht_put_success) => dht_put_success.version,
+            None => return Ok(false),
+        };
+
+        if let Some(version) = version {
+            let written = Written {
+                version,
+                replicas: replication.saturating_add(1),
+                expires: Instant::now() + Duration::from_secs(u64::from(ttl)),
+            };
+
+            self.remember(key, written);
+        }
+
+        Ok(true)
+    }
+
+    /// Obtains the value stored under `key`, which is at least as new as the
+    /// last value stored through this session.
+    ///
+    /// Returns `None` if no value is stored and fails with a [`StaleError`]
+    /// if only older values were found.
+    ///
+    /// [`StaleError`]: ../error/struct.StaleError.html
+    pub fn get(&self, key: DhtKey) -> crate::Result<Option<DhtValue>> {
+        let written = match self.written(key) {
+            Some(written) => written,
+            None => return self.client.get(key),
+        };
+
+        // replicas only reply with values at least as new as the write
+        let dht_get = DhtGet {
+            key,
+            quorum: Some(Quorum {
+                replicas: written.replicas,
+                reads: 1,
+            }),
+            budget: None,
+            request_id: None,
+            if_none_match: Some(written.version.saturating_sub(1)),
+        };
+
+        match self.client.send_conditional(dht_get)? {
+            Conditional::Modified(value, _) => Ok(Some(value)),
+            Conditional::NotModified => Err(Box::new(StaleError::new(written.version))),
+            Conditional::NotFound => Ok(None),
+        }
+    }
+
+    /// Removes the value stored under `key` and forgets the last write.
+    ///
+    /// See [`ApiClient::delete`].
+    ///
+    /// [`ApiClient::delete`]: struct.ApiClient.html#method.delete
+    pub fn delete(&self, key: DhtKey, replication: u8) -> crate::Result<u8> {
+        self.writes.lock_or_recover().remove(&key);
+
+        self.client.delete(key, replication)
+    }
+
+    fn written(&self, key: DhtKey) -> Option<Written> {
+        let writes = self.writes.lock_or_recover();
+
+        writes
+            .get(&key)
+            .filter(|written| written.expires > Instant::now())
+            .copied()
+    }
+
+    fn remember(&self, key: DhtKey, written: Written) {
+        let mut writes = self.writes.lock_or_recover();
+
+        let now = Instant::now();
+        writes.retain(|_, written| written.expires > now);
+
+        // forget the key which expires first to make room
+        if writes.len() >= self.capacity && !writes.contains_key(&key) {
+            let first = writes
+                .iter()
+                .min_by_key(|(_, written)| written.expires)
+                .map(|(key, _)| *key);
+
+            if let Some(first) = first {
+                writes.remove(&first);
+            }
+        }
+
+        writes.insert(key, written);
+    }
+}
@@ -0,0 +1,116 @@
+//! Monotonic time for timeouts and expiry
+//!
+//! All timeout logic of a peer works with deadlines of the monotonic clock,
+//! i.e. an [`Instant`] at which something expires, instead of the wall clock
+//! which may jump. Components which check deadlines take the current time
+//! from a shared [`Clock`], by default the [`SystemClock`].
+//!
+//! Tests replace it with a [`ManualClock`] which only moves when it is
+//! advanced such that the expiry of records, joins or unreachable peers can
+//! be checked without waiting.
+//!
+//! # Examples
+//!
+//! ```
+//! # use chord::clock::{Clock, ManualClock};
+//! # use std::time::Duration;
+//! #
+//! let clock = ManualClock::new();
+//! let deadline = clock.deadline(Duration::from_secs(60));
+//!
+//! assert!(deadline > clock.now());
+//!
+//! clock.advance(Duration::from_secs(60));
+//! assert!(deadline <= clock.now());
+//! ```
+//!
+//! [`Instant`]: https://doc.rust-lang.org/std/time/struct.Instant.html
+//! [`Clock`]: trait.Clock.html
+//! [`SystemClock`]: struct.SystemClock.html
+//! [`ManualClock`]: struct.ManualClock.html
+
+use crate::sync::MutexExt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A source of monotonic time
+pub trait Clock: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+
+    /// Returns the deadline `duration` from now.
+    fn deadline(&self, duration: Duration) -> Instant {
+        self.now() + duration
+    }
+
+    /// Returns whether `deadline` has passed.
+    fn is_past(&self, deadline: Instant) -> bool {
+        deadline <= self.now()
+    }
+}
+
+/// The monotonic clock of the operating system
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock which stands still until it is advanced
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<Instant>,
+}
+
+impl ManualClock {
+    /// Creates a clock starting at the current time.
+    pub fn new() -> Self {
+        Self {
+            now: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock_or_recover() += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock_or_recover()
+    }
+}
+
+/// Returns the shared system clock.
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_advances() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+
+        assert_eq!(start, clock.now());
+
+        clock.advance(Duration::from_millis(1500));
+
+        assert_eq!(Duration::from_millis(1500), clock.now() - start);
+        assert!(clock.is_past(start + Duration::from_millis(1500)));
+        assert!(!clock.is_past(start + Duration::from_millis(1501)));
+    }
+}
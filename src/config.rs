@@ -1,15 +1,102 @@
+use crate::audit;
+use crate::auth::{self, Authorizer};
+use crate::dht::Namespace;
+use crate::framing::{self, Framing};
+use crate::network::SocketOptions;
+use crate::policy::{Policies, Policy};
+use crate::proxy::Proxy;
+use crate::puzzle::{self, Puzzle};
+use crate::routing::identifier::IdentifierScheme;
+use crate::tenant::{Tenant, Tenants};
 use ini::Ini;
+use std::env;
 use std::net::SocketAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Config {
     pub listen_address: SocketAddr,
     pub api_address: SocketAddr,
     pub worker_threads: usize,
+    /// Number of worker threads each pool grows to while requests are
+    /// queued, starting with `worker_threads`
+    pub max_worker_threads: Option<usize>,
     pub timeout: u64,
     pub fingers: usize,
     pub stabilization_interval: u64,
+    /// Seconds between attempts to deliver the values hinted to unreachable
+    /// replicas, the stabilization interval unless set
+    pub handoff_interval: u64,
+    /// Seconds between sweeps which remove expired records from the storage
+    pub sweep_interval: u64,
+    /// Number of attempts to join the network via the bootstrap peer
+    pub bootstrap_attempts: u32,
+    /// Milliseconds to wait after the first failed attempt to join, doubling
+    /// with every further attempt
+    pub bootstrap_backoff: u64,
+    /// Whether the peer creates a new network if it could not join via the
+    /// bootstrap peer, which splits the network if the bootstrap peer was
+    /// only unreachable for a while
+    pub bootstrap_fallback: bool,
+    pub websocket_address: Option<SocketAddr>,
+    pub read_only: bool,
+    pub weight: u16,
+    pub identifier_scheme: IdentifierScheme,
+    pub port_range: u16,
+    /// Bytes per second a remote peer may exchange with this peer before it
+    /// is throttled
+    pub rate_limit: Option<u64>,
+    /// Prefix of the keys reserved for internal records
+    pub system_namespace: Namespace,
+    /// Time in milliseconds after which lookups, storage operations and
+    /// stabilization rounds are logged as slow
+    pub slow_threshold: Option<u64>,
+    /// Requests per second for a single key above which the peer pushes
+    /// copies of its value to the peers requesting it most, which also
+    /// enables the cache for copies pushed by other peers
+    pub hot_key_threshold: Option<u32>,
+    /// Whether api clients may switch their connections to CBOR payloads
+    pub api_cbor: bool,
+    /// File caching the neighbours of the peer such that it rejoins through
+    /// them after a restart
+    pub state_file: Option<PathBuf>,
+    /// Directory of the lock file which keeps a second node with the same
+    /// listen address from starting, the temporary directory unless set and
+    /// no lock if set to an empty value
+    pub lock_dir: Option<PathBuf>,
+    /// Proxy which outbound peer-to-peer connections are tunneled through
+    pub proxy: Option<Proxy>,
+    /// Framing of the messages exchanged with other peers, which has to be
+    /// the same for all peers of a network
+    ///
+    /// With a `puzzle_difficulty`, the framing is wrapped in a [`Puzzle`].
+    ///
+    /// [`Puzzle`]: ../puzzle/struct.Puzzle.html
+    pub framing: Arc<dyn Framing>,
+    /// Options of all TCP sockets, read from `tcp_nodelay`, `tcp_keepalive`
+    /// in seconds, `tcp_send_buffer` and `tcp_recv_buffer` in bytes
+    pub socket_options: SocketOptions,
+    /// Decides which requests of clients and peers are executed, which is
+    /// not read from the configuration file but set by embedders
+    pub authorizer: Arc<dyn Authorizer>,
+    /// File recording all mutating operations
+    pub audit_log: Option<PathBuf>,
+    /// Size in bytes after which the audit log is rotated
+    pub audit_log_max_size: Option<u64>,
+    /// Number of rotated audit logs which are kept
+    pub audit_log_files: usize,
+    /// Seed of the random decisions of the peer, which uses the randomness
+    /// of the operating system if none is given
+    pub seed: Option<u64>,
+    /// Limits of the values in each namespace, read from the sections
+    /// `[namespace.<prefix>]` with the prefix in hex digits
+    pub policies: Arc<Policies>,
+    /// Applications sharing the api interface, read from the sections
+    /// `[tenant.<name>]` with their `token` and the hex prefix of their
+    /// `namespace`
+    pub tenants: Arc<Tenants>,
 }
 
 impl Config {
@@ -18,7 +105,7 @@ impl Config {
 
         let dht = conf.section(Some("dht")).ok_or("missing section `dht`")?;
 
-        let listen_address = dht
+        let listen_address: SocketAddr = dht
             .get("listen_address")
             .ok_or("missing value `listen_address`")?
             .parse()?;
@@ -33,6 +120,11 @@ impl Config {
             .unwrap_or(&"4".to_string())
             .parse()?;
 
+        let max_worker_threads = match dht.get("max_worker_threads") {
+            Some(max_worker_threads) => Some(max_worker_threads.parse()?),
+            None => None,
+        };
+
         let timeout = dht
             .get("timeout")
             .unwrap_or(&"300000".to_string())
@@ -45,13 +137,272 @@ impl Config {
             .unwrap_or(&"60".to_string())
             .parse()?;
 
+        let handoff_interval = match dht.get("handoff_interval") {
+            Some(handoff_interval) => handoff_interval.parse()?,
+            None => stabilization_interval,
+        };
+
+        let sweep_interval = dht
+            .get("sweep_interval")
+            .unwrap_or(&"60".to_string())
+            .parse()?;
+
+        let bootstrap_attempts = dht
+            .get("bootstrap_attempts")
+            .unwrap_or(&"10".to_string())
+            .parse()?;
+
+        let bootstrap_backoff = dht
+            .get("bootstrap_backoff")
+            .unwrap_or(&"100".to_string())
+            .parse()?;
+
+        let bootstrap_fallback = dht
+            .get("bootstrap_fallback")
+            .unwrap_or(&"false".to_string())
+            .parse()?;
+
+        let websocket_address = match dht.get("websocket_address") {
+            Some(websocket_address) => Some(websocket_address.parse()?),
+            None => None,
+        };
+
+        let read_only = dht
+            .get("read_only")
+            .unwrap_or(&"false".to_string())
+            .parse()?;
+
+        let weight: u16 = dht.get("weight").unwrap_or(&"1".to_string()).parse()?;
+
+        if weight == 0 || listen_address.port().checked_add(weight - 1).is_none() {
+            return Err("value `weight` must be positive and fit into the port range".into());
+        }
+
+        let identifier_scheme = match dht.get("identifier_scheme") {
+            Some(identifier_scheme) => identifier_scheme.parse::<IdentifierScheme>()?,
+            None => IdentifierScheme::default(),
+        };
+
+        if weight > 1 && identifier_scheme == IdentifierScheme::Ip {
+            return Err(
+                "value `weight` requires the `ip-port` identifier scheme since the \
+                        virtual peers share the ip address"
+                    .into(),
+            );
+        }
+        let port_range = dht.get("port_range").unwrap_or(&"0".to_string()).parse()?;
+
+        let rate_limit = match dht.get("rate_limit") {
+            Some(rate_limit) => Some(rate_limit.parse()?),
+            None => None,
+        };
+
+        let system_namespace = match dht.get("system_namespace") {
+            Some(system_namespace) => Namespace::from_hex(system_namespace)?,
+            None => Namespace::SYSTEM,
+        };
+
+        let slow_threshold = match dht.get("slow_threshold") {
+            Some(slow_threshold) => Some(slow_threshold.parse()?),
+            None => None,
+        };
+
+        let hot_key_threshold = match dht.get("hot_key_threshold") {
+            Some(hot_key_threshold) => Some(hot_key_threshold.parse()?),
+            None => None,
+        };
+
+        let api_cbor = dht
+            .get("api_cbor")
+            .unwrap_or(&"false".to_string())
+            .parse()?;
+
+        let state_file = dht.get("state_file").map(PathBuf::from);
+
+        let lock_dir = match dht.get("lock_dir") {
+            Some(lock_dir) if lock_dir.is_empty() => None,
+            Some(lock_dir) => Some(PathBuf::from(lock_dir)),
+            None => Some(env::temp_dir()),
+        };
+
+        let proxy = match dht.get("proxy") {
+            Some(proxy) => Some(proxy.parse::<Proxy>()?),
+            None => None,
+        };
+
+        let framing = match dht.get("framing") {
+            Some(name) => {
+                framing::from_name(name).ok_or_else(|| format!("unknown framing `{}`", name))?
+            }
+            None => framing::plain(),
+        };
+
+        let tcp_keepalive = match dht.get("tcp_keepalive") {
+            Some(tcp_keepalive) => Some(Duration::from_secs(tcp_keepalive.parse()?)),
+            None => None,
+        };
+
+        let tcp_send_buffer = match dht.get("tcp_send_buffer") {
+            Some(tcp_send_buffer) => Some(tcp_send_buffer.parse()?),
+            None => None,
+        };
+
+        let tcp_recv_buffer = match dht.get("tcp_recv_buffer") {
+            Some(tcp_recv_buffer) => Some(tcp_recv_buffer.parse()?),
+            None => None,
+        };
+
+        let socket_options = SocketOptions {
+            nodelay: dht
+                .get("tcp_nodelay")
+                .unwrap_or(&"true".to_string())
+                .parse()?,
+            keepalive: tcp_keepalive,
+            send_buffer_size: tcp_send_buffer,
+            recv_buffer_size: tcp_recv_buffer,
+        };
+
+        let audit_log = dht.get("audit_log").map(PathBuf::from);
+
+        let audit_log_max_size = match dht.get("audit_log_max_size") {
+            Some(audit_log_max_size) => Some(audit_log_max_size.parse()?),
+            None => None,
+        };
+
+        let audit_log_files = match dht.get("audit_log_files") {
+            Some(audit_log_files) => audit_log_files.parse()?,
+            None => audit::DEFAULT_FILES,
+        };
+
+        let seed = match dht.get("seed") {
+            Some(seed) => Some(seed.parse()?),
+            None => None,
+        };
+
+        let framing = match dht.get("puzzle_difficulty") {
+            Some(difficulty) => {
+                let difficulty = difficulty.parse()?;
+
+                if difficulty > puzzle::MAX_DIFFICULTY {
+                    return Err(format!(
+                        "value `puzzle_difficulty` must not exceed {}",
+                        puzzle::MAX_DIFFICULTY
+                    )
+                    .into());
+                }
+
+                Arc::new(Puzzle::new(framing, difficulty))
+            }
+            None => framing,
+        };
+
+        let mut policies = Policies::new();
+
+        for (name, section) in conf.iter() {
+            let prefix = match name
+                .as_ref()
+                .and_then(|name| name.strip_prefix("namespace."))
+            {
+                Some(prefix) => prefix,
+                None => continue,
+            };
+
+            let policy = Policy {
+                max_ttl: match section.get("max_ttl") {
+                    Some(max_ttl) => Some(Duration::from_secs(max_ttl.parse()?)),
+                    None => None,
+                },
+                max_value_size: match section.get("max_value_size") {
+                    Some(max_value_size) => Some(max_value_size.parse()?),
+                    None => None,
+                },
+                quota: match section.get("quota") {
+                    Some(quota) => Some(quota.parse()?),
+                    None => None,
+                },
+                replication: match section.get("replication") {
+                    Some(replication) => Some(replication.parse()?),
+                    None => None,
+                },
+            };
+
+            policies = policies.with_policy(Namespace::from_hex(prefix)?, policy);
+        }
+
+        let api_token_required = dht
+            .get("api_token_required")
+            .unwrap_or(&"false".to_string())
+            .parse()?;
+
+        let mut tenants = Tenants::new().with_required(api_token_required);
+
+        for (name, section) in conf.iter() {
+            let name = match name.as_ref().and_then(|name| name.strip_prefix("tenant.")) {
+                Some(name) => name,
+                None => continue,
+            };
+
+            let token = section
+                .get("token")
+                .ok_or_else(|| format!("missing value `token` of tenant `{}`", name))?;
+            let namespace = section
+                .get("namespace")
+                .ok_or_else(|| format!("missing value `namespace` of tenant `{}`", name))?;
+
+            tenants =
+                tenants.with_tenant(token, Tenant::new(name, Namespace::from_hex(namespace)?));
+        }
+
         Ok(Config {
             listen_address,
             api_address,
             worker_threads,
+            max_worker_threads,
             timeout,
             fingers,
             stabilization_interval,
+            handoff_interval,
+            sweep_interval,
+            bootstrap_attempts,
+            bootstrap_backoff,
+            bootstrap_fallback,
+            websocket_address,
+            read_only,
+            weight,
+            identifier_scheme,
+            port_range,
+            rate_limit,
+            system_namespace,
+            slow_threshold,
+            hot_key_threshold,
+            api_cbor,
+            state_file,
+            lock_dir,
+            proxy,
+            framing,
+            socket_options,
+            authorizer: auth::allow_all(),
+            audit_log,
+            audit_log_max_size,
+            audit_log_files,
+            seed,
+            policies: Arc::new(policies),
+            tenants: Arc::new(tenants),
         })
     }
+
+    /// Returns the addresses of the additional virtual peers.
+    ///
+    /// A peer with weight `n` registers `n - 1` virtual peers listening on
+    /// the ports following `listen_address` such that it owns about `n`
+    /// times as much of the identifier circle as a peer with weight 1.
+    pub fn virtual_addresses(&self) -> Vec<SocketAddr> {
+        (1..self.weight)
+            .map(|i| {
+                let mut socket_addr = self.listen_address;
+                socket_addr.set_port(self.listen_address.port() + i);
+                socket_addr
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,465 @@
+//! Protocol conformance checks against a listening peer
+//!
+//! The [`Conformance`] struct runs a scripted sequence of peer-to-peer
+//! messages against any peer and reports every reply which deviates from
+//! the protocol. This allows to verify that other implementations or future
+//! versions of this crate interoperate with this one.
+//!
+//! The script joins the network as a probe peer in front of the peer under
+//! test, stores, reads and deletes a value in the remaining range of the peer
+//! under test, notifies it of its predecessor and finally leaves the network
+//! again, handing back all values it took over. Nobody has to listen at the
+//! address of the probe, but the peer under test has to be responsible for
+//! it. The peer under test ends up in its previous state, however other
+//! requests are affected while the probe is joined, therefore the script
+//! should only be run against peers of test networks.
+//!
+//! This module is only available with the `conformance` feature.
+//!
+//! [`Conformance`]: struct.Conformance.html
+
+use crate::message::p2p::*;
+use crate::message::Message;
+use crate::network::Connection;
+use crate::routing::identifier::{IdentifierInterval, Identify};
+use crate::storage::{self, Key};
+use bytes::Bytes;
+use std::fmt;
+use std::net::SocketAddr;
+
+/// Maximum number of raw keys tried to find a key in the range of the peer
+/// under test
+const MAX_KEY_ATTEMPTS: u64 = 1 << 20;
+
+/// A step of the conformance script
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Step {
+    /// `PEER FIND` for the identifier of the peer under test
+    Lookup,
+    /// `JOIN LOCK` and `JOIN PUBLISH` of the probe
+    Join,
+    /// `STORAGE PUT` of a new value
+    Put,
+    /// `STORAGE GET` of the stored and of a missing value
+    Get,
+    /// `STORAGE DELETE` of the stored value
+    Delete,
+    /// `PREDECESSOR NOTIFY` with the probe
+    Notify,
+    /// `PEER LEAVE` of the probe
+    Leave,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Step::Lookup => "lookup",
+            Step::Join => "join",
+            Step::Put => "put",
+            Step::Get => "get",
+            Step::Delete => "delete",
+            Step::Notify => "notify",
+            Step::Leave => "leave",
+        };
+
+        name.fmt(f)
+    }
+}
+
+/// A reply of the peer under test which deviates from the protocol
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Violation {
+    pub step: Step,
+    pub description: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.step, self.description)
+    }
+}
+
+/// The outcome of a conformance run
+///
+/// The script stops at the first violation since the following steps depend
+/// on the state established by the previous ones. Only the leave is still
+/// attempted once the probe joined such that the peer under test is restored.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// Steps which completed as expected
+    pub passed: Vec<Step>,
+    /// Violations found, in the order of the steps
+    pub violations: Vec<Violation>,
+}
+
+impl Report {
+    /// Returns whether the peer under test followed the protocol in all
+    /// steps.
+    pub fn is_conformant(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    fn record(&mut self, step: Step, result: Result<(), String>) -> bool {
+        match result {
+            Ok(()) => {
+                self.passed.push(step);
+
+                true
+            }
+            Err(description) => {
+                warn!("Conformance step {} failed: {}", step, description);
+
+                self.violations.push(Violation { step, description });
+
+                false
+            }
+        }
+    }
+}
+
+/// State established by the join of the probe
+struct Joined {
+    predecessor_addr: SocketAddr,
+    values: Vec<StoragePut>,
+}
+
+/// Scripted conformance checks against the peer at `peer_addr`
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chord::conformance::Conformance;
+/// #
+/// let peer_addr = "127.0.0.1:8080".parse().unwrap();
+/// let probe_addr = "127.0.0.1:8081".parse().unwrap();
+///
+/// let report = Conformance::new(peer_addr, probe_addr, 5000).run();
+///
+/// for violation in &report.violations {
+///     println!("{}", violation);
+/// }
+/// ```
+pub struct Conformance {
+    peer_addr: SocketAddr,
+    probe_addr: SocketAddr,
+    timeout: u64,
+}
+
+impl Conformance {
+    /// Creates a new `Conformance` instance which joins the peer at
+    /// `peer_addr` as the peer `probe_addr`.
+    ///
+    /// `timeout` is the timeout in milliseconds of every connection.
+    pub fn new(peer_addr: SocketAddr, probe_addr: SocketAddr, timeout: u64) -> Self {
+        Self {
+            peer_addr,
+            probe_addr,
+            timeout,
+        }
+    }
+
+    /// Runs the script and reports all violations.
+    pub fn run(&self) -> Report {
+        let mut report = Report::default();
+
+        if !report.record(Step::Lookup, self.lookup()) {
+            return report;
+        }
+
+        let joined = match self.join() {
+            Ok(joined) => {
+                report.record(Step::Join, Ok(()));
+
+                joined
+            }
+            Err(description) => {
+                report.record(Step::Join, Err(description));
+
+                return report;
+            }
+        };
+
+        if self.storage(&mut report) {
+            report.record(Step::Notify, self.notify());
+        }
+
+        report.record(Step::Leave, self.leave(joined));
+
+        report
+    }
+
+    fn connect(&self) -> Result<Connection, String> {
+        Connection::open(self.peer_addr, self.timeout)
+            .map_err(|err| format!("could not connect to {}: {}", self.peer_addr, err))
+    }
+
+    fn request(&self, msg: &Message) -> Result<Message, String> {
+        let mut con = self.connect()?;
+
+        con.send(msg)
+            .map_err(|err| format!("could not send {}: {}", msg, err))?;
+
+        con.receive()
+            .map_err(|err| format!("no valid reply to {}: {}", msg, err))
+    }
+
+    fn lookup(&self) -> Result<(), String> {
+        let peer_find = PeerFind {
+            identifier: self.peer_addr.identifier(),
+            trace: None,
+            budget: None,
+        };
+
+        match self.request(&Message::PeerFind(peer_find))? {
+            Message::PeerFound(ref peer_found) if peer_found.socket_addr == self.peer_addr => {
+                Ok(())
+            }
+            Message::PeerFound(peer_found) => Err(format!(
+                "PEER FOUND for its own identifier names {}",
+                peer_found.socket_addr
+            )),
+            msg => Err(format!("expected PEER FOUND but got {}", msg)),
+        }
+    }
+
+    fn join(&self) -> Result<Joined, String> {
+        let mut con = self.connect()?;
+
+        let join_lock = JoinLock {
+            socket_addr: self.probe_addr,
+            resume_after: None,
+        };
+        con.send(&Message::JoinLock(join_lock))
+            .map_err(|err| format!("could not send JOIN LOCK: {}", err))?;
+
+        let join_ack = match con.receive() {
+            Ok(Message::JoinAck(join_ack)) => join_ack,
+            Ok(Message::JoinNack(join_nack)) => {
+                return Err(format!(
+                    "JOIN NACK with {}, the peer is not responsible for the probe {}",
+                    join_nack.socket_addr, self.probe_addr
+                ))
+            }
+            Ok(msg) => return Err(format!("expected JOIN ACK but got {}", msg)),
+            Err(err) => return Err(format!("no valid reply to JOIN LOCK: {}", err)),
+        };
+
+        // all transferred values have to fall into the range of the probe
+        let range = IdentifierInterval::new(
+            join_ack.socket_addr.identifier(),
+            self.probe_addr.identifier(),
+        );
+        let mut values = Vec::new();
+
+        while values.len() < join_ack.records as usize {
+            let msg = con
+                .receive()
+                .map_err(|err| format!("transfer broke off: {}", err))?;
+            let batch = msg.into_values().map_err(|msg| {
+                format!("expected STORAGE PUT or STORAGE BULK PUT but got {}", msg)
+            })?;
+
+            values.extend(batch);
+        }
+
+        if values.len() != join_ack.records as usize {
+            return Err(format!(
+                "JOIN ACK announced {} values but {} were transferred",
+                join_ack.records,
+                values.len()
+            ));
+        }
+
+        if let Some(value) = values.iter().find(|value| {
+            let key = Key {
+                raw_key: value.raw_key,
+                replication_index: value.replication_index,
+            };
+
+            !range.contains_open_closed(key.identifier())
+        }) {
+            return Err(format!(
+                "transferred value {:?} outside of the range of the probe",
+                value.raw_key
+            ));
+        }
+
+        let join_publish = JoinPublish {
+            socket_addr: self.probe_addr,
+        };
+        con.send(&Message::JoinPublish(join_publish))
+            .map_err(|err| format!("could not send JOIN PUBLISH: {}", err))?;
+
+        match con.receive() {
+            Ok(Message::PredecessorReply(ref reply)) if reply.socket_addr == self.probe_addr => {
+                Ok(Joined {
+                    predecessor_addr: join_ack.socket_addr,
+                    values,
+                })
+            }
+            Ok(Message::PredecessorReply(reply)) => Err(format!(
+                "PREDECESSOR REPLY after the join names {} instead of the probe",
+                reply.socket_addr
+            )),
+            Ok(msg) => Err(format!("expected PREDECESSOR REPLY but got {}", msg)),
+            Err(err) => Err(format!("no valid reply to JOIN PUBLISH: {}", err)),
+        }
+    }
+
+    /// Runs the put, get and delete steps and returns whether all passed.
+    fn storage(&self, report: &mut Report) -> bool {
+        let range =
+            IdentifierInterval::new(self.probe_addr.identifier(), self.peer_addr.identifier());
+        let mut keys = (0..MAX_KEY_ATTEMPTS)
+            .map(|i| {
+                // unlikely to collide with the keys stored already
+                let mut raw_key = [0; 32];
+                raw_key[..11].copy_from_slice(b"conformance");
+                raw_key[24..].copy_from_slice(&i.to_be_bytes());
+
+                Key {
+                    raw_key,
+                    replication_index: 0,
+                }
+            })
+            .filter(|key| range.contains_open_closed(key.identifier()));
+
+        let (key, missing) = match (keys.next(), keys.next()) {
+            (Some(key), Some(missing)) => (key, missing),
+            _ => {
+                return report.record(
+                    Step::Put,
+                    Err("no keys found in the range of the peer".to_string()),
+                )
+            }
+        };
+
+        let value = b"conformance".to_vec();
+        let version = storage::current_version();
+
+        report.record(Step::Put, self.put(key, version, &value))
+            && report.record(Step::Get, self.get(key, missing, version, &value))
+            && report.record(Step::Delete, self.delete(key))
+    }
+
+    fn put(&self, key: Key, version: u64, value: &[u8]) -> Result<(), String> {
+        let storage_put = StoragePut {
+            ttl: 60_000,
+            replication_index: key.replication_index,
+            replicas: 1,
+            namespace_len: 0,
+            raw_key: key.raw_key,
+            version,
+            value: Bytes::copy_from_slice(value),
+        };
+
+        match self.request(&Message::StoragePut(storage_put))? {
+            Message::StoragePutSuccess(ref success) if success.raw_key == key.raw_key => Ok(()),
+            Message::StoragePutSuccess(_) => {
+                Err("STORAGE PUT SUCCESS names another key".to_string())
+            }
+            Message::StorageFailure(failure) => Err(format!(
+                "expected STORAGE PUT SUCCESS but got STORAGE FAILURE ({:?})",
+                failure.reason
+            )),
+            msg => Err(format!("expected STORAGE PUT SUCCESS but got {}", msg)),
+        }
+    }
+
+    fn get(&self, key: Key, missing: Key, version: u64, value: &[u8]) -> Result<(), String> {
+        let storage_get = StorageGet {
+            replication_index: key.replication_index,
+            raw_key: key.raw_key,
+            budget: None,
+            if_none_match: None,
+            requester: None,
+        };
+
+        match self.request(&Message::StorageGet(storage_get))? {
+            Message::StorageGetSuccess(ref success)
+                if success.raw_key == key.raw_key
+                    && success.version == version
+                    && *success.value == *value => {}
+            Message::StorageGetSuccess(_) => {
+                return Err("STORAGE GET SUCCESS differs from the stored value".to_string())
+            }
+            msg => return Err(format!("expected STORAGE GET SUCCESS but got {}", msg)),
+        }
+
+        let storage_get = StorageGet {
+            replication_index: missing.replication_index,
+            raw_key: missing.raw_key,
+            budget: None,
+            if_none_match: None,
+            requester: None,
+        };
+
+        match self.request(&Message::StorageGet(storage_get))? {
+            Message::StorageFailure(ref failure) if failure.raw_key == missing.raw_key => Ok(()),
+            msg => Err(format!(
+                "expected STORAGE FAILURE for a missing key but got {}",
+                msg
+            )),
+        }
+    }
+
+    fn delete(&self, key: Key) -> Result<(), String> {
+        let storage_delete = StorageDelete {
+            replication_index: key.replication_index,
+            raw_key: key.raw_key,
+        };
+
+        match self.request(&Message::StorageDelete(storage_delete))? {
+            Message::StorageDeleteSuccess(_) => Ok(()),
+            msg => Err(format!("expected STORAGE DELETE SUCCESS but got {}", msg)),
+        }
+    }
+
+    fn notify(&self) -> Result<(), String> {
+        let predecessor_notify = PredecessorNotify {
+            socket_addr: self.probe_addr,
+        };
+
+        match self.request(&Message::PredecessorNotify(predecessor_notify))? {
+            Message::PredecessorReply(ref reply) if reply.socket_addr == self.probe_addr => Ok(()),
+            Message::PredecessorReply(reply) => Err(format!(
+                "PREDECESSOR REPLY names {} instead of the probe",
+                reply.socket_addr
+            )),
+            msg => Err(format!("expected PREDECESSOR REPLY but got {}", msg)),
+        }
+    }
+
+    fn leave(&self, joined: Joined) -> Result<(), String> {
+        let mut con = self.connect()?;
+
+        let peer_leave = PeerLeave {
+            socket_addr: self.probe_addr,
+            predecessor_addr: joined.predecessor_addr,
+            successor_addr: self.peer_addr,
+            records: joined.values.len() as u32,
+            resume: false,
+        };
+        con.send(&Message::PeerLeave(peer_leave))
+            .map_err(|err| format!("could not send PEER LEAVE: {}", err))?;
+
+        for msg in StorageBulkPut::pack(joined.values) {
+            con.send(&msg)
+                .map_err(|err| format!("could not hand back values: {}", err))?;
+        }
+
+        match con.receive() {
+            Ok(Message::PredecessorReply(ref reply))
+                if reply.socket_addr == joined.predecessor_addr =>
+            {
+                Ok(())
+            }
+            Ok(Message::PredecessorReply(reply)) => Err(format!(
+                "PREDECESSOR REPLY after the leave names {} instead of {}",
+                reply.socket_addr, joined.predecessor_addr
+            )),
+            Ok(msg) => Err(format!("expected PREDECESSOR REPLY but got {}", msg)),
+            Err(err) => Err(format!("no valid reply to PEER LEAVE: {}", err)),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Deadlines and cancellation for operations spanning several peers
+//!
+//! Peers do not share a clock, therefore requests only carry the remaining
+//! budget in milliseconds. Each peer converts the budget into a local
+//! [`Deadline`] on receipt and passes the remaining budget on to the next hop.
+//! Work whose deadline has passed is dropped since nobody waits for its
+//! result anymore.
+//!
+//! Clients can also abort an operation explicitly which sets its
+//! [`CancelToken`].
+//!
+//! [`Deadline`]: struct.Deadline.html
+//! [`CancelToken`]: struct.CancelToken.html
+
+use crate::error::{CancelledError, DeadlineError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A point in time after which an operation is abandoned
+///
+/// # Examples
+///
+/// ```
+/// # use chord::deadline::Deadline;
+/// #
+/// let deadline = Deadline::from_budget(500);
+///
+/// assert!(!deadline.is_expired());
+/// assert!(deadline.budget() <= 500);
+/// assert!(Deadline::from_budget(0).check().is_err());
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Deadline {
+    expires: Instant,
+}
+
+impl Deadline {
+    /// Creates a deadline which expires after `duration`.
+    pub fn after(duration: Duration) -> Self {
+        Self {
+            expires: Instant::now() + duration,
+        }
+    }
+
+    /// Creates a deadline from a budget in milliseconds as carried in
+    /// messages.
+    pub fn from_budget(budget: u32) -> Self {
+        Self::after(Duration::from_millis(u64::from(budget)))
+    }
+
+    /// Returns the time left until the deadline expires.
+    pub fn remaining(&self) -> Duration {
+        self.expires.saturating_duration_since(Instant::now())
+    }
+
+    /// Returns the remaining budget in milliseconds to pass on to the next
+    /// hop.
+    pub fn budget(&self) -> u32 {
+        self.remaining().as_millis().min(u128::from(u32::MAX)) as u32
+    }
+
+    /// Returns whether the deadline has passed.
+    pub fn is_expired(&self) -> bool {
+        self.remaining() == Duration::from_secs(0)
+    }
+
+    /// Fails with a [`DeadlineError`] if the deadline has passed.
+    ///
+    /// [`DeadlineError`]: ../error/struct.DeadlineError.html
+    pub fn check(&self) -> Result<(), DeadlineError> {
+        if self.is_expired() {
+            Err(DeadlineError)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Limits a timeout in milliseconds to the remaining time.
+    ///
+    /// The result is at least one millisecond since a timeout of zero is
+    /// not allowed for sockets.
+    pub fn limit_timeout(&self, timeout: u64) -> u64 {
+        let remaining = self.remaining().as_millis().min(u128::from(u64::MAX)) as u64;
+
+        timeout.min(remaining).max(1)
+    }
+}
+
+/// A flag shared between an operation and whoever may abort it
+///
+/// # Examples
+///
+/// ```
+/// # use chord::deadline::CancelToken;
+/// #
+/// let token = CancelToken::new();
+/// let operation = token.clone();
+///
+/// token.cancel();
+///
+/// assert!(operation.check().is_err());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Creates a new token which has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the operation holding a clone of this token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether the operation has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fails with a [`CancelledError`] if the operation has been cancelled.
+    ///
+    /// [`CancelledError`]: ../error/struct.CancelledError.html
+    pub fn check(&self) -> Result<(), CancelledError> {
+        if self.is_cancelled() {
+            Err(CancelledError)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expired_budget() {
+        let deadline = Deadline::from_budget(0);
+
+        assert!(deadline.is_expired());
+        assert_eq!(0, deadline.budget());
+        assert_eq!(1, deadline.limit_timeout(3600));
+    }
+
+    #[test]
+    fn limit_timeout() {
+        let deadline = Deadline::after(Duration::from_secs(60));
+
+        assert_eq!(3600, deadline.limit_timeout(3600));
+        assert!(deadline.limit_timeout(120_000) <= 60_000);
+    }
+}
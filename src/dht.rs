@@ -0,0 +1,423 @@
+//! Typed keys and values of the distributed hash table
+//!
+//! The api interface stores values of type [`DhtValue`] under keys of type
+//! [`DhtKey`]. Both types validate their contents on construction and offer
+//! conversions from and to hex and base64 such that applications do not need
+//! to handle the raw bytes on their own.
+//!
+//! Keys starting with the prefix of a reserved [`Namespace`] belong to the
+//! peers themselves and cannot be written through the api interface.
+//!
+//! [`DhtKey`]: struct.DhtKey.html
+//! [`DhtValue`]: struct.DhtValue.html
+//! [`Namespace`]: struct.Namespace.html
+
+use crate::error::ConversionError;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bytes::Bytes;
+use core::fmt;
+use sha2::{Digest, Sha256};
+
+/// A key under which values are stored in the DHT
+///
+/// Keys have a fixed size of 32 bytes. Applications usually derive them from
+/// arbitrary names using [`from_name`] which avoids collisions between names
+/// sharing a common prefix. Shorter raw keys are padded with zeros.
+///
+/// # Examples
+///
+/// ```
+/// # use chord::dht::DhtKey;
+/// #
+/// let key = DhtKey::from_name("alice/profile");
+///
+/// assert_eq!(key, DhtKey::from_name("alice/profile"));
+/// assert_ne!(key, DhtKey::from_name("alice/profile.bak"));
+///
+/// let key = DhtKey::from_hex("0x01ab").unwrap();
+/// assert_eq!(&[1, 0xab, 0], &key.raw()[..3]);
+/// ```
+///
+/// [`from_name`]: #method.from_name
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct DhtKey([u8; 32]);
+
+impl DhtKey {
+    /// Derives a key from `name` by hashing it with SHA-256.
+    pub fn from_name(name: &str) -> Self {
+        DhtKey(Sha256::digest(name.as_bytes()).into())
+    }
+
+    /// Uses `bytes` as key after padding them with zeros to 32 bytes.
+    ///
+    /// Fails if `bytes` is longer than 32 bytes.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, ConversionError> {
+        if bytes.len() > 32 {
+            return Err(ConversionError::new(format!(
+                "key of {} bytes exceeds 32 bytes",
+                bytes.len()
+            )));
+        }
+
+        let mut raw_key = [0; 32];
+        raw_key[..bytes.len()].copy_from_slice(bytes);
+
+        Ok(DhtKey(raw_key))
+    }
+
+    /// Reads a key from hex digits with an optional `0x` prefix.
+    pub fn from_hex(input: &str) -> Result<Self, ConversionError> {
+        let digits = input.strip_prefix("0x").unwrap_or(input);
+        let bytes = hex::decode(digits)
+            .map_err(|err| ConversionError::new(format!("invalid hex {}: {}", input, err)))?;
+
+        Self::from_slice(&bytes)
+    }
+
+    /// Reads a key from standard base64.
+    pub fn from_base64(input: &str) -> Result<Self, ConversionError> {
+        let bytes = BASE64
+            .decode(input)
+            .map_err(|err| ConversionError::new(format!("invalid base64 {}: {}", input, err)))?;
+
+        Self::from_slice(&bytes)
+    }
+
+    /// Returns the raw bytes of this key.
+    pub fn raw(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// Returns the key as lowercase hex digits.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+
+    /// Returns the key as standard base64.
+    pub fn to_base64(&self) -> String {
+        BASE64.encode(self.0)
+    }
+}
+
+impl From<[u8; 32]> for DhtKey {
+    fn from(raw_key: [u8; 32]) -> Self {
+        DhtKey(raw_key)
+    }
+}
+
+impl From<DhtKey> for [u8; 32] {
+    fn from(key: DhtKey) -> Self {
+        key.0
+    }
+}
+
+impl fmt::Display for DhtKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+/// A prefix of keys reserved for records of the peers themselves
+///
+/// Internal features like leases, watch registrations or hints store their
+/// records under keys derived with [`key`] such that they cannot collide with
+/// the keys of applications. The api interface rejects `DHT PUT` and
+/// `DHT DELETE` requests for keys in the reserved namespace, while internal
+/// features write them directly to the responsible peers.
+///
+/// The prefix is at most [`MAX_LEN`] bytes long such that the remaining bytes
+/// of a key still tell names apart. An empty prefix reserves no keys at all.
+///
+/// # Examples
+///
+/// ```
+/// # use chord::dht::{DhtKey, Namespace};
+/// #
+/// let namespace = Namespace::SYSTEM;
+/// let key = namespace.key("lease/alice");
+///
+/// assert!(namespace.contains(&key));
+/// assert!(!namespace.contains(&DhtKey::from_name("lease/alice")));
+/// ```
+///
+/// [`key`]: #method.key
+/// [`MAX_LEN`]: #associatedconstant.MAX_LEN
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+pub struct Namespace {
+    prefix: [u8; Namespace::MAX_LEN],
+    len: usize,
+}
+
+impl Namespace {
+    /// Maximum size of a prefix in bytes
+    pub const MAX_LEN: usize = 16;
+
+    /// The default namespace of internal records with the prefix `ff737973`
+    pub const SYSTEM: Namespace = Namespace {
+        prefix: [0xff, b's', b'y', b's', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        len: 4,
+    };
+
+    /// Reserves the keys starting with `prefix`.
+    ///
+    /// Fails if `prefix` is longer than [`MAX_LEN`] bytes.
+    ///
+    /// [`MAX_LEN`]: #associatedconstant.MAX_LEN
+    pub fn new(prefix: &[u8]) -> Result<Self, ConversionError> {
+        if prefix.len() > Self::MAX_LEN {
+            return Err(ConversionError::new(format!(
+                "namespace of {} bytes exceeds {} bytes",
+                prefix.len(),
+                Self::MAX_LEN
+            )));
+        }
+
+        let mut namespace = Namespace {
+            prefix: [0; Self::MAX_LEN],
+            len: prefix.len(),
+        };
+        namespace.prefix[..prefix.len()].copy_from_slice(prefix);
+
+        Ok(namespace)
+    }
+
+    /// Reads a prefix from hex digits with an optional `0x` prefix.
+    pub fn from_hex(input: &str) -> Result<Self, ConversionError> {
+        let digits = input.strip_prefix("0x").unwrap_or(input);
+        let bytes = hex::decode(digits)
+            .map_err(|err| ConversionError::new(format!("invalid hex {}: {}", input, err)))?;
+
+        Self::new(&bytes)
+    }
+
+    /// Returns the prefix of all keys in this namespace.
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix[..self.len]
+    }
+
+    /// Returns whether `key` belongs to this namespace.
+    ///
+    /// An empty namespace does not contain any key.
+    pub fn contains(&self, key: &DhtKey) -> bool {
+        self.len > 0 && key.0.starts_with(self.prefix())
+    }
+
+    /// Derives a key in this namespace from `name`.
+    ///
+    /// The key starts with the prefix followed by the SHA-256 hash of `name`
+    /// truncated to the remaining bytes.
+    pub fn key(&self, name: &str) -> DhtKey {
+        let mut raw_key = DhtKey::from_name(name).raw();
+        raw_key.copy_within(..32 - self.len, self.len);
+        raw_key[..self.len].copy_from_slice(self.prefix());
+
+        DhtKey(raw_key)
+    }
+}
+
+impl Default for Namespace {
+    fn default() -> Self {
+        Namespace::SYSTEM
+    }
+}
+
+impl fmt::Debug for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Namespace({})", self)
+    }
+}
+
+impl fmt::Display for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.prefix()))
+    }
+}
+
+/// A value stored in the DHT
+///
+/// Values have to fit into a single `DHT PUT` message which limits their size
+/// to [`MAX_LEN`] bytes. Clones of a value share its bytes.
+///
+/// # Examples
+///
+/// ```
+/// # use chord::dht::DhtValue;
+/// #
+/// let value = DhtValue::new(b"hello".to_vec()).unwrap();
+///
+/// assert_eq!("aGVsbG8=", value.to_base64());
+/// assert!(DhtValue::new(vec![0; DhtValue::MAX_LEN + 1]).is_err());
+/// ```
+///
+/// [`MAX_LEN`]: #associatedconstant.MAX_LEN
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct DhtValue(Bytes);
+
+impl DhtValue {
+    /// Maximum size of a value in bytes
+    ///
+    /// This is the maximum message size minus the header, the fixed fields
+    /// and the key of a `DHT PUT` message.
+    pub const MAX_LEN: usize = u16::MAX as usize - 40;
+
+    /// Wraps `value` after checking that it does not exceed [`MAX_LEN`].
+    ///
+    /// [`MAX_LEN`]: #associatedconstant.MAX_LEN
+    pub fn new<V: Into<Bytes>>(value: V) -> Result<Self, ConversionError> {
+        let value = value.into();
+
+        if value.len() > Self::MAX_LEN {
+            return Err(ConversionError::new(format!(
+                "value of {} bytes exceeds {} bytes",
+                value.len(),
+                Self::MAX_LEN
+            )));
+        }
+
+        Ok(DhtValue(value))
+    }
+
+    /// Reads a value from hex digits with an optional `0x` prefix.
+    pub fn from_hex(input: &str) -> Result<Self, ConversionError> {
+        let digits = input.strip_prefix("0x").unwrap_or(input);
+        let bytes = hex::decode(digits)
+            .map_err(|err| ConversionError::new(format!("invalid hex {}: {}", input, err)))?;
+
+        Self::new(bytes)
+    }
+
+    /// Reads a value from standard base64.
+    pub fn from_base64(input: &str) -> Result<Self, ConversionError> {
+        let bytes = BASE64
+            .decode(input)
+            .map_err(|err| ConversionError::new(format!("invalid base64 {}: {}", input, err)))?;
+
+        Self::new(bytes)
+    }
+
+    /// Returns the bytes of this value.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Consumes the value and returns a copy of its bytes.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    /// Consumes the value and returns its bytes without copying them.
+    pub fn into_shared(self) -> Bytes {
+        self.0
+    }
+
+    /// Returns the size of this value in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns whether this value is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the value as lowercase hex digits.
+    pub fn to_hex(&self) -> String {
+        hex::encode(&self.0)
+    }
+
+    /// Returns the value as standard base64.
+    pub fn to_base64(&self) -> String {
+        BASE64.encode(&self.0)
+    }
+}
+
+impl From<DhtValue> for Vec<u8> {
+    fn from(value: DhtValue) -> Self {
+        value.into_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_from_name() {
+        let key = DhtKey::from_name("");
+
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            key.to_string()
+        );
+    }
+
+    #[test]
+    fn key_from_slice_pads() {
+        let key = DhtKey::from_slice(&[1, 2, 3]).unwrap();
+
+        let mut raw_key = [0; 32];
+        raw_key[..3].copy_from_slice(&[1, 2, 3]);
+
+        assert_eq!(raw_key, key.raw());
+    }
+
+    #[test]
+    fn key_from_slice_too_long() {
+        assert!(DhtKey::from_slice(&[0; 33]).is_err());
+    }
+
+    #[test]
+    fn key_hex_and_base64() {
+        let key = DhtKey::from([7; 32]);
+
+        assert_eq!(key, DhtKey::from_hex(&key.to_hex()).unwrap());
+        assert_eq!(key, DhtKey::from_base64(&key.to_base64()).unwrap());
+        assert!(DhtKey::from_hex("0xzz").is_err());
+    }
+
+    #[test]
+    fn namespace_key() {
+        let namespace = Namespace::from_hex("0xff737973").unwrap();
+        assert_eq!(Namespace::SYSTEM, namespace);
+
+        let key = namespace.key("");
+        assert_eq!(
+            "ff737973e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b",
+            key.to_string()
+        );
+        assert!(namespace.contains(&key));
+        assert!(!namespace.contains(&DhtKey::from([0xff; 32])));
+    }
+
+    #[test]
+    fn namespace_empty() {
+        let namespace = Namespace::new(&[]).unwrap();
+
+        assert!(!namespace.contains(&DhtKey::from([0; 32])));
+        assert_eq!(DhtKey::from_name("lease"), namespace.key("lease"));
+    }
+
+    #[test]
+    fn namespace_too_long() {
+        assert!(Namespace::new(&[1; Namespace::MAX_LEN]).is_ok());
+        assert!(Namespace::new(&[1; Namespace::MAX_LEN + 1]).is_err());
+    }
+
+    #[test]
+    fn value_hex_and_base64() {
+        let value = DhtValue::from_hex("0x0102").unwrap();
+
+        assert_eq!(&[1, 2], value.as_bytes());
+        assert_eq!(value, DhtValue::from_base64(&value.to_base64()).unwrap());
+    }
+
+    #[test]
+    fn value_too_long() {
+        assert!(DhtValue::new(vec![0; DhtValue::MAX_LEN]).is_ok());
+        assert!(DhtValue::new(vec![0; DhtValue::MAX_LEN + 1]).is_err());
+    }
+}
@@ -1,13 +1,37 @@
 //! Custom error types
 //!
-//! The [`MessageError`] is currently the only custom error type. It can be used
-//! when an unexpected message has been received.
+//! The [`MessageError`] can be used when an unexpected message has been
+//! received while the [`ConversionError`] indicates invalid input for a typed
+//! key or value. The [`DeadlineError`] and [`CancelledError`] signal that an
+//! operation has been abandoned because its deadline passed or a client
+//! cancelled it. A [`PeerError`] tells a peer which is down from a peer which
+//! is only slow. A [`BootstrapError`] explains why a peer could not join the
+//! network, for example because of a [`CollisionError`]. A [`StaleError`]
+//! rejects a read which missed an earlier write of the same client. A
+//! [`LockedError`] stops a node from starting twice with the same listen
+//! address.
 //!
 //! [`MessageError`]: struct.MessageError.html
+//! [`ConversionError`]: struct.ConversionError.html
+//! [`DeadlineError`]: struct.DeadlineError.html
+//! [`CancelledError`]: struct.CancelledError.html
+//! [`PeerError`]: enum.PeerError.html
+//! [`BootstrapError`]: struct.BootstrapError.html
+//! [`CollisionError`]: struct.CollisionError.html
+//! [`StaleError`]: struct.StaleError.html
+//! [`LockedError`]: struct.LockedError.html
 
+#[cfg(feature = "network")]
 use crate::message::Message;
-use std::error::Error;
-use std::fmt;
+use alloc::string::String;
+use core::error::Error;
+use core::fmt;
+#[cfg(feature = "network")]
+use std::io;
+#[cfg(feature = "std")]
+use std::net::SocketAddr;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
 
 /// Error type to use when an unexpected message has been received
 ///
@@ -15,13 +39,22 @@ use std::fmt;
 /// If no valid message has been received yet, one should use a different
 /// error type like [`io::Error`].
 ///
+/// The error can optionally describe which message was expected instead,
+/// from which peer the message came and during which operation it was
+/// received such that the log points to the cause.
+///
 /// [`Message`]: message/enum.Message.html
 /// [`io::Error`]: ../std/io/struct.Error.html
+#[cfg(feature = "network")]
 #[derive(Debug)]
 pub struct MessageError {
     msg: Message,
+    expected: Option<&'static str>,
+    peer_addr: Option<SocketAddr>,
+    operation: Option<&'static str>,
 }
 
+#[cfg(feature = "network")]
 impl MessageError {
     /// Creates a new message error from an existing message as well as an
     /// arbitrary error payload.
@@ -41,22 +74,523 @@ impl MessageError {
     /// };
     /// ```
     pub fn new(msg: Message) -> Self {
-        MessageError { msg }
+        MessageError {
+            msg,
+            expected: None,
+            peer_addr: None,
+            operation: None,
+        }
+    }
+
+    /// Records the name of the message type which was expected instead,
+    /// e.g. `"PEER FOUND"`.
+    pub fn with_expected(mut self, expected: &'static str) -> Self {
+        self.expected = Some(expected);
+        self
+    }
+
+    /// Records the address of the peer which sent the message.
+    pub fn with_peer(mut self, peer_addr: SocketAddr) -> Self {
+        self.peer_addr = Some(peer_addr);
+        self
+    }
+
+    /// Records the operation which was in progress, e.g. `"lookup"`.
+    pub fn with_operation(mut self, operation: &'static str) -> Self {
+        self.operation = Some(operation);
+        self
+    }
+
+    /// Returns the unexpected message.
+    pub fn message(&self) -> &Message {
+        &self.msg
+    }
+
+    /// Returns the name of the expected message type if it is known.
+    pub fn expected(&self) -> Option<&'static str> {
+        self.expected
+    }
+
+    /// Returns the address of the peer which sent the message if it is known.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Returns the operation during which the message was received if it is
+    /// known.
+    pub fn operation(&self) -> Option<&'static str> {
+        self.operation
     }
 }
 
+#[cfg(feature = "network")]
 impl fmt::Display for MessageError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Unexpected message type {}", self.msg)
+        write!(f, "Unexpected message type {}", self.msg)?;
+
+        if let Some(peer_addr) = self.peer_addr {
+            write!(f, " from {}", peer_addr)?;
+        }
+
+        if let Some(operation) = self.operation {
+            write!(f, " during {}", operation)?;
+        }
+
+        if let Some(expected) = self.expected {
+            write!(f, ", expected {}", expected)?;
+        }
+
+        Ok(())
     }
 }
 
+#[cfg(feature = "network")]
 impl Error for MessageError {
     fn description(&self) -> &str {
         "Unexpected message type"
     }
 
-    fn cause(&self) -> Option<&Error> {
+    fn cause(&self) -> Option<&dyn Error> {
         None
     }
 }
+
+/// Error type to use when a peer did not reply to a request
+///
+/// A peer which refuses or drops the connection is most likely down and
+/// should be routed around, while a peer which does not reply in time may
+/// only be overloaded and is kept.
+#[cfg(feature = "network")]
+#[derive(Debug)]
+pub enum PeerError {
+    /// The peer refused or dropped the connection.
+    Unreachable(SocketAddr, io::Error),
+    /// The peer did not reply in time.
+    TimedOut(SocketAddr, io::Error),
+}
+
+#[cfg(feature = "network")]
+impl PeerError {
+    /// Classifies an error which occurred while talking to `peer_addr`.
+    ///
+    /// Errors which do not tell anything about the state of the peer, for
+    /// example invalid messages, are returned unchanged.
+    pub fn classify(peer_addr: SocketAddr, error: io::Error) -> Box<dyn Error> {
+        match error.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                Box::new(PeerError::TimedOut(peer_addr, error))
+            }
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::HostUnreachable
+            | io::ErrorKind::NetworkUnreachable => {
+                Box::new(PeerError::Unreachable(peer_addr, error))
+            }
+            _ => Box::new(error),
+        }
+    }
+
+    /// Returns the address of the peer.
+    pub fn peer_addr(&self) -> SocketAddr {
+        match self {
+            PeerError::Unreachable(peer_addr, _) | PeerError::TimedOut(peer_addr, _) => *peer_addr,
+        }
+    }
+}
+
+#[cfg(feature = "network")]
+impl fmt::Display for PeerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PeerError::Unreachable(peer_addr, error) => {
+                write!(f, "Peer {} is unreachable: {}", peer_addr, error)
+            }
+            PeerError::TimedOut(peer_addr, error) => {
+                write!(f, "Peer {} did not reply in time: {}", peer_addr, error)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "network")]
+impl Error for PeerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PeerError::Unreachable(_, error) | PeerError::TimedOut(_, error) => Some(error),
+        }
+    }
+}
+
+/// The reason why a peer could not join the network
+#[cfg(feature = "network")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BootstrapFailure {
+    /// The peers refused the connection or could not be reached at all,
+    /// usually because the bootstrap peer is not running.
+    Unreachable,
+    /// The peers did not reply in time.
+    TimedOut,
+    /// The peers accepted the connection but did not answer like peers of
+    /// this network, usually because they use a different framing or puzzle
+    /// difficulty or are no DHT peers at all.
+    WrongNetwork,
+    /// Another peer has the same identifier, which does not change when the
+    /// join is retried.
+    Collision,
+    /// The join failed for another reason.
+    Other,
+}
+
+#[cfg(feature = "network")]
+impl BootstrapFailure {
+    /// Classifies the error of a join attempt.
+    pub fn classify(error: &(dyn Error + 'static)) -> Self {
+        if error.is::<CollisionError>() {
+            return BootstrapFailure::Collision;
+        }
+
+        if let Some(peer_error) = error.downcast_ref::<PeerError>() {
+            return match peer_error {
+                PeerError::TimedOut(..) => BootstrapFailure::TimedOut,
+                PeerError::Unreachable(_, error) => match error.kind() {
+                    io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::HostUnreachable
+                    | io::ErrorKind::NetworkUnreachable => BootstrapFailure::Unreachable,
+                    // peers hang up on connections whose handshake fails
+                    _ => BootstrapFailure::WrongNetwork,
+                },
+            };
+        }
+
+        if error.is::<MessageError>() {
+            return BootstrapFailure::WrongNetwork;
+        }
+
+        match error.downcast_ref::<io::Error>().map(io::Error::kind) {
+            Some(io::ErrorKind::InvalidData) => BootstrapFailure::WrongNetwork,
+            _ => BootstrapFailure::Other,
+        }
+    }
+}
+
+/// Error type to use when a peer could not join the network via its
+/// bootstrap peer
+///
+/// The error of the last attempt is kept as source and classified as a
+/// [`BootstrapFailure`] such that the log tells whether the bootstrap peer is
+/// down, slow or part of another network.
+///
+/// [`BootstrapFailure`]: enum.BootstrapFailure.html
+#[cfg(feature = "network")]
+#[derive(Debug)]
+pub struct BootstrapError {
+    bootstrap: SocketAddr,
+    attempts: u32,
+    failure: BootstrapFailure,
+    source: Box<dyn Error>,
+}
+
+#[cfg(feature = "network")]
+impl BootstrapError {
+    /// Creates a new error for joining via `bootstrap` which failed
+    /// `attempts` times, the last time with `source`.
+    pub fn new(bootstrap: SocketAddr, attempts: u32, source: Box<dyn Error>) -> Self {
+        Self {
+            bootstrap,
+            attempts,
+            failure: BootstrapFailure::classify(source.as_ref()),
+            source,
+        }
+    }
+
+    /// Returns the reason why the last attempt failed.
+    pub fn failure(&self) -> BootstrapFailure {
+        self.failure
+    }
+
+    /// Returns the number of failed attempts.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+}
+
+#[cfg(feature = "network")]
+impl fmt::Display for BootstrapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self.failure {
+            BootstrapFailure::Unreachable => {
+                "the peers are unreachable, check that the bootstrap peer is running"
+            }
+            BootstrapFailure::TimedOut => "the peers did not reply in time",
+            BootstrapFailure::WrongNetwork => {
+                "the peers do not belong to this network, check that framing and puzzle \
+                 difficulty match"
+            }
+            BootstrapFailure::Collision => "another peer has the same identifier",
+            BootstrapFailure::Other => "the join failed",
+        };
+
+        write!(
+            f,
+            "Could not join the network via {} after {} attempts, {}: {}",
+            self.bootstrap, self.attempts, reason, self.source
+        )
+    }
+}
+
+#[cfg(feature = "network")]
+impl Error for BootstrapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// Error type to use when a peer cannot join since another peer has the
+/// same identifier
+///
+/// With the default identifier scheme, this happens if two peers share an ip
+/// address, e.g. because they are behind the same NAT.
+#[cfg(feature = "network")]
+#[derive(Debug)]
+pub struct CollisionError {
+    joining: SocketAddr,
+    existing: SocketAddr,
+}
+
+#[cfg(feature = "network")]
+impl CollisionError {
+    /// Creates a new error for the peer at `joining` whose identifier is the
+    /// one of the peer at `existing`.
+    pub fn new(joining: SocketAddr, existing: SocketAddr) -> Self {
+        Self { joining, existing }
+    }
+
+    /// Returns the address of the peer which has the identifier already.
+    pub fn existing(&self) -> SocketAddr {
+        self.existing
+    }
+}
+
+#[cfg(feature = "network")]
+impl fmt::Display for CollisionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The identifier of {} is taken by {} already, give the peers distinct ip \
+             addresses or use the `ip-port` identifier scheme on all peers",
+            self.joining, self.existing
+        )
+    }
+}
+
+#[cfg(feature = "network")]
+impl Error for CollisionError {}
+
+/// Error type to use when input cannot be converted into a typed key or value
+///
+/// This error is used by the typed keys and values of the `dht` module as
+/// well as for parsing identifiers.
+#[derive(Debug)]
+pub struct ConversionError {
+    description: String,
+}
+
+impl ConversionError {
+    /// Creates a new conversion error with the given description.
+    pub fn new(description: String) -> Self {
+        ConversionError { description }
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid input: {}", self.description)
+    }
+}
+
+impl Error for ConversionError {}
+
+/// Error type to use when the deadline of an operation has passed
+#[derive(Debug)]
+pub struct DeadlineError;
+
+impl fmt::Display for DeadlineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Deadline exceeded")
+    }
+}
+
+impl Error for DeadlineError {}
+
+/// Error type to use when a client cancelled an operation
+#[derive(Debug)]
+pub struct CancelledError;
+
+impl fmt::Display for CancelledError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Operation cancelled")
+    }
+}
+
+impl Error for CancelledError {}
+
+/// Error type to use when a read only found values older than a version
+/// written before
+#[derive(Debug)]
+pub struct StaleError {
+    version: u64,
+}
+
+impl StaleError {
+    /// Creates a new error for a read which missed `version`.
+    pub fn new(version: u64) -> Self {
+        Self { version }
+    }
+
+    /// Returns the version which the read missed.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl fmt::Display for StaleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Only values older than version {} found", self.version)
+    }
+}
+
+impl Error for StaleError {}
+
+/// Error type to use when another node with the same listen address holds
+/// the lock file
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct LockedError {
+    listen_address: SocketAddr,
+    pid: Option<u32>,
+    path: PathBuf,
+}
+
+#[cfg(feature = "std")]
+impl LockedError {
+    /// Creates a new error for the lock file at `path` held by the process
+    /// `pid` if it is known.
+    pub fn new(listen_address: SocketAddr, pid: Option<u32>, path: &Path) -> Self {
+        Self {
+            listen_address,
+            pid,
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Returns the process holding the lock if it is known.
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for LockedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Another node with listen address {} is already running",
+            self.listen_address
+        )?;
+
+        if let Some(pid) = self.pid {
+            write!(f, " as process {}", pid)?;
+        }
+
+        write!(f, " (lock file {})", self.path.display())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for LockedError {}
+
+#[cfg(all(test, feature = "network"))]
+mod tests {
+    use super::*;
+    use crate::message::p2p::PredecessorNotify;
+
+    #[test]
+    fn message_error_context() {
+        let socket_addr = "127.0.0.1:8080".parse().unwrap();
+        let msg = Message::PredecessorNotify(PredecessorNotify { socket_addr });
+
+        let error = MessageError::new(msg)
+            .with_expected("PEER FOUND")
+            .with_peer(socket_addr)
+            .with_operation("lookup");
+
+        assert_eq!(
+            "Unexpected message type PREDECESSOR GET from 127.0.0.1:8080 during lookup, \
+             expected PEER FOUND",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn peer_error_classify() {
+        let peer_addr = "127.0.0.1:8080".parse().unwrap();
+
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        let error = PeerError::classify(peer_addr, refused);
+        assert!(matches!(
+            error.downcast_ref::<PeerError>(),
+            Some(PeerError::Unreachable(..))
+        ));
+
+        let timed_out = io::Error::from(io::ErrorKind::WouldBlock);
+        let error = PeerError::classify(peer_addr, timed_out);
+        assert!(matches!(
+            error.downcast_ref::<PeerError>(),
+            Some(PeerError::TimedOut(..))
+        ));
+
+        let invalid = io::Error::from(io::ErrorKind::InvalidData);
+        let error = PeerError::classify(peer_addr, invalid);
+        assert!(error.is::<io::Error>());
+    }
+
+    #[test]
+    fn bootstrap_failure_classify() {
+        let peer_addr = "127.0.0.1:8080".parse().unwrap();
+        let failure = |kind| {
+            BootstrapFailure::classify(
+                PeerError::classify(peer_addr, io::Error::from(kind)).as_ref(),
+            )
+        };
+
+        assert_eq!(
+            BootstrapFailure::Unreachable,
+            failure(io::ErrorKind::ConnectionRefused)
+        );
+        assert_eq!(BootstrapFailure::TimedOut, failure(io::ErrorKind::TimedOut));
+        assert_eq!(
+            BootstrapFailure::WrongNetwork,
+            failure(io::ErrorKind::UnexpectedEof)
+        );
+        assert_eq!(
+            BootstrapFailure::WrongNetwork,
+            failure(io::ErrorKind::InvalidData)
+        );
+        assert_eq!(BootstrapFailure::Other, failure(io::ErrorKind::Other));
+
+        let msg = Message::PredecessorNotify(PredecessorNotify {
+            socket_addr: peer_addr,
+        });
+        let error = BootstrapError::new(peer_addr, 3, Box::new(MessageError::new(msg)));
+        assert_eq!(BootstrapFailure::WrongNetwork, error.failure());
+        assert_eq!(3, error.attempts());
+
+        let collision = CollisionError::new("127.0.0.1:8081".parse().unwrap(), peer_addr);
+        let error = BootstrapError::new(peer_addr, 1, Box::new(collision));
+        assert_eq!(BootstrapFailure::Collision, error.failure());
+    }
+}
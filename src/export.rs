@@ -0,0 +1,562 @@
+//! Portable export format for the records of a peer
+//!
+//! Exports allow to back up the local storage of a peer outside of the ring
+//! and to migrate records between storage backends. They are [CBOR]
+//! documents such that they can be inspected and produced by other tools.
+//!
+//! An export is a map with the entries `format`, `version` and `records` in
+//! this order. The records are an array of indefinite length such that an
+//! export can be written while the records are still fetched from a peer:
+//!
+//! ```text
+//! {
+//!   "format": "chord-export",
+//!   "version": 2,
+//!   "records": [_
+//!     {
+//!       "key": h'0303...03',
+//!       "replication_index": 1,
+//!       "replicas": 2,
+//!       "value": h'010203',
+//!       "ttl": 60000,
+//!       "version": 1554980000123,
+//!       "namespace": h'73797374656d'
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! `key` contains the 32 bytes of the key, `ttl` the remaining time to live
+//! in milliseconds at the time of the export and `version` the version of the
+//! value in milliseconds since the unix epoch. Exports of version 1 carried
+//! the `ttl` in whole seconds and are still read. The optional `replicas` is the
+//! total number of replicas the value was written with. The optional `namespace`
+//! contains the prefix of the [`Namespace`] the export has been restricted
+//! to. Readers skip entries they do not know.
+//!
+//! [CBOR]: https://cbor.io
+//! [`Namespace`]: ../dht/struct.Namespace.html
+
+use crate::dht::{DhtKey, Namespace};
+use crate::message::api::StoredRecord;
+use crate::message::cbor::{
+    read_bytes, read_head, read_number, read_text, read_unsigned, skip, write_bytes, write_head,
+    write_text, ARRAY, BREAK, MAP, SIMPLE, UNSIGNED,
+};
+use crate::storage::{self, Key, Record, Storage};
+use crate::sync::MutexExt;
+use byteorder::WriteBytesExt;
+use bytes::Bytes;
+use std::io::{self, Read, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Name of the format in the `format` entry of an export
+pub const FORMAT: &str = "chord-export";
+/// Version of the format written by this module
+pub const VERSION: u64 = 2;
+
+/// Initial byte of an array of indefinite length
+const INDEFINITE_ARRAY: u8 = 0x9f;
+
+/// A record of an export
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportRecord {
+    pub key: DhtKey,
+    pub replication_index: u8,
+    pub replicas: u8,
+    pub value: Bytes,
+    pub ttl: u64,
+    pub version: u64,
+    pub namespace: Option<Namespace>,
+}
+
+impl From<StoredRecord> for ExportRecord {
+    fn from(record: StoredRecord) -> Self {
+        ExportRecord {
+            key: record.key,
+            replication_index: record.replication_index,
+            replicas: record.replicas,
+            value: record.value,
+            ttl: record.ttl,
+            version: record.version,
+            namespace: None,
+        }
+    }
+}
+
+impl From<ExportRecord> for StoredRecord {
+    fn from(record: ExportRecord) -> Self {
+        StoredRecord {
+            key: record.key,
+            replication_index: record.replication_index,
+            replicas: record.replicas,
+            ttl: record.ttl,
+            version: record.version,
+            value: record.value,
+        }
+    }
+}
+
+/// Writes records into an export.
+///
+/// The export is only complete after [`finish`] has been called.
+///
+/// [`finish`]: #method.finish
+pub struct ExportWriter<W: Write> {
+    writer: W,
+    records: usize,
+}
+
+impl<W: Write> ExportWriter<W> {
+    /// Starts a new export by writing its header to `writer`.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        write_head(&mut writer, MAP, 3)?;
+        write_text(&mut writer, "format")?;
+        write_text(&mut writer, FORMAT)?;
+        write_text(&mut writer, "version")?;
+        write_head(&mut writer, UNSIGNED, VERSION)?;
+        write_text(&mut writer, "records")?;
+        writer.write_u8(INDEFINITE_ARRAY)?;
+
+        Ok(Self { writer, records: 0 })
+    }
+
+    /// Appends a record to the export.
+    pub fn write(&mut self, record: &ExportRecord) -> io::Result<()> {
+        let entries = 5 + u64::from(record.replicas > 0) + u64::from(record.namespace.is_some());
+        write_head(&mut self.writer, MAP, entries)?;
+
+        write_text(&mut self.writer, "key")?;
+        write_bytes(&mut self.writer, &record.key.raw())?;
+        write_text(&mut self.writer, "replication_index")?;
+        write_head(
+            &mut self.writer,
+            UNSIGNED,
+            u64::from(record.replication_index),
+        )?;
+
+        if record.replicas > 0 {
+            write_text(&mut self.writer, "replicas")?;
+            write_head(&mut self.writer, UNSIGNED, u64::from(record.replicas))?;
+        }
+
+        write_text(&mut self.writer, "value")?;
+        write_bytes(&mut self.writer, &record.value)?;
+        write_text(&mut self.writer, "ttl")?;
+        write_head(&mut self.writer, UNSIGNED, record.ttl)?;
+        write_text(&mut self.writer, "version")?;
+        write_head(&mut self.writer, UNSIGNED, record.version)?;
+
+        if let Some(ref namespace) = record.namespace {
+            write_text(&mut self.writer, "namespace")?;
+            write_bytes(&mut self.writer, namespace.prefix())?;
+        }
+
+        self.records += 1;
+
+        Ok(())
+    }
+
+    /// Completes the export and returns the number of records written.
+    pub fn finish(mut self) -> io::Result<usize> {
+        self.writer.write_u8(BREAK)?;
+        self.writer.flush()?;
+
+        Ok(self.records)
+    }
+}
+
+/// Reads the records of an export one after another.
+pub struct ExportReader<R: Read> {
+    reader: R,
+    /// Number of records left or `None` until the break of an array of
+    /// indefinite length
+    remaining: Option<u64>,
+    /// Version of the format the export has been written with
+    version: u64,
+    done: bool,
+}
+
+impl<R: Read> ExportReader<R> {
+    /// Reads the header of an export from `reader`.
+    ///
+    /// Fails if the document is not an export or has been written by a newer
+    /// version of this format.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let entries = match read_head(&mut reader)? {
+            (MAP, entries) => entries,
+            _ => return Err(invalid_data("Export must be a map")),
+        };
+
+        let mut format = None;
+        let mut version = 1;
+        let mut read = 0;
+
+        while entries.is_none_or(|entries| read < entries) {
+            read += 1;
+
+            match read_text(&mut reader)?.as_str() {
+                "format" => format = Some(read_text(&mut reader)?),
+                "version" => {
+                    version = read_unsigned(&mut reader)?;
+
+                    if version > VERSION {
+                        return Err(invalid_data(format!(
+                            "Export version {} is not supported",
+                            version
+                        )));
+                    }
+                }
+                "records" => {
+                    if format.as_deref() != Some(FORMAT) {
+                        return Err(invalid_data("Missing export format"));
+                    }
+
+                    let remaining = match read_head(&mut reader)? {
+                        (ARRAY, remaining) => remaining,
+                        _ => return Err(invalid_data("Records must be an array")),
+                    };
+
+                    return Ok(Self {
+                        reader,
+                        remaining,
+                        version,
+                        done: false,
+                    });
+                }
+                _ => skip(&mut reader)?,
+            }
+        }
+
+        Err(invalid_data("Missing records"))
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<ExportRecord>> {
+        let entries = match self.remaining {
+            Some(0) => return Ok(None),
+            Some(ref mut remaining) => {
+                *remaining -= 1;
+
+                match read_head(&mut self.reader)? {
+                    (MAP, Some(entries)) => entries,
+                    _ => return Err(invalid_data("Record must be a map")),
+                }
+            }
+            None => match read_head(&mut self.reader)? {
+                (MAP, Some(entries)) => entries,
+                (SIMPLE, None) => return Ok(None),
+                _ => return Err(invalid_data("Record must be a map")),
+            },
+        };
+
+        let mut key = None;
+        let mut replication_index = None;
+        let mut replicas = None;
+        let mut value = None;
+        let mut ttl = None;
+        let mut version = None;
+        let mut namespace = None;
+
+        for _ in 0..entries {
+            match read_text(&mut self.reader)?.as_str() {
+                "key" => {
+                    let bytes = read_bytes(&mut self.reader)?;
+                    key = Some(DhtKey::from_slice(&bytes).map_err(invalid_data)?);
+                }
+                "replication_index" => {
+                    replication_index = Some(read_number(&mut self.reader, "replication_index")?)
+                }
+                "replicas" => replicas = Some(read_number(&mut self.reader, "replicas")?),
+                "value" => value = Some(read_bytes(&mut self.reader)?),
+                "ttl" => ttl = Some(read_unsigned(&mut self.reader)?),
+                "version" => version = Some(read_unsigned(&mut self.reader)?),
+                "namespace" => {
+                    let prefix = read_bytes(&mut self.reader)?;
+                    namespace = Some(Namespace::new(&prefix).map_err(invalid_data)?);
+                }
+                _ => skip(&mut self.reader)?,
+            }
+        }
+
+        let missing = |name| invalid_data(format!("Record without {}", name));
+        let mut ttl = ttl.ok_or_else(|| missing("ttl"))?;
+
+        // version 1 carried whole seconds
+        if self.version < 2 {
+            ttl = ttl.saturating_mul(1000);
+        }
+
+        let record = ExportRecord {
+            key: key.ok_or_else(|| missing("key"))?,
+            replication_index: replication_index.ok_or_else(|| missing("replication_index"))?,
+            replicas: replicas.unwrap_or(0),
+            value: value.ok_or_else(|| missing("value"))?.into(),
+            ttl,
+            version: version.ok_or_else(|| missing("version"))?,
+            namespace,
+        };
+
+        if let Some(ref namespace) = record.namespace {
+            if !namespace.contains(&record.key) {
+                return Err(invalid_data(format!(
+                    "Key {} is not in namespace {}",
+                    record.key.to_hex(),
+                    namespace
+                )));
+            }
+        }
+
+        Ok(Some(record))
+    }
+}
+
+impl<R: Read> Iterator for ExportReader<R> {
+    type Item = io::Result<ExportRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let record = self.read_record().transpose();
+
+        // stop after the last record or the first error
+        self.done = !matches!(record, Some(Ok(_)));
+
+        record
+    }
+}
+
+/// Writes all records in `storage` into an export.
+///
+/// Only records whose key lies in `namespace` are exported if it is given.
+/// Returns the number of records written.
+pub fn export<W: Write>(
+    storage: &Mutex<Storage>,
+    namespace: Option<&Namespace>,
+    writer: W,
+) -> io::Result<usize> {
+    let mut records = storage::snapshot(storage, |key| {
+        namespace.is_none_or(|namespace| namespace.contains(&DhtKey::from(key.raw_key)))
+    });
+    records.sort_unstable_by_key(|(key, _)| *key);
+
+    let mut writer = ExportWriter::new(writer)?;
+
+    for (key, record) in records {
+        writer.write(&ExportRecord {
+            key: DhtKey::from(key.raw_key),
+            replication_index: key.replication_index,
+            replicas: record.replicas,
+            ttl: record.ttl_millis(),
+            version: record.version,
+            value: record.value.clone(),
+            namespace: record.namespace.or(namespace.copied()),
+        })?;
+    }
+
+    writer.finish()
+}
+
+/// Stores all records of an export in `storage`.
+///
+/// A record is skipped if `storage` holds the same or a newer version of it
+/// already. Returns the number of records stored.
+pub fn import<R: Read>(storage: &Mutex<Storage>, reader: R) -> io::Result<usize> {
+    let mut stored = 0;
+
+    for record in ExportReader::new(reader)? {
+        let record = record?;
+        let namespace = record.namespace;
+        let (key, record) = into_storage(record.into());
+        let record = record.with_namespace(namespace);
+
+        if storage::put_newer(&mut storage.lock_or_recover(), key, record) {
+            stored += 1;
+        }
+    }
+
+    Ok(stored)
+}
+
+/// Converts an exported record into a record of the local storage.
+pub fn into_storage(record: StoredRecord) -> (Key, Record) {
+    let key = Key {
+        raw_key: record.key.raw(),
+        replication_index: record.replication_index,
+    };
+
+    let ttl = Duration::from_millis(record.ttl);
+
+    let record = Record::new(record.value, ttl, record.version).with_replicas(record.replicas);
+
+    (key, record)
+}
+
+fn invalid_data<E: ToString>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(byte: u8, namespace: Option<Namespace>) -> ExportRecord {
+        ExportRecord {
+            key: DhtKey::from([byte; 32]),
+            replication_index: 1,
+            replicas: byte,
+            value: vec![byte; 3].into(),
+            // beyond the 18 hours a u16 of seconds allows
+            ttl: 100_000_000,
+            version: 1_554_980_000_123,
+            namespace,
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let namespace = Namespace::new(&[7]).unwrap();
+
+        let mut buf = Vec::new();
+        let mut writer = ExportWriter::new(&mut buf).unwrap();
+        writer.write(&record(7, Some(namespace))).unwrap();
+        writer.write(&record(8, None)).unwrap();
+        assert_eq!(2, writer.finish().unwrap());
+
+        let records: Vec<ExportRecord> = ExportReader::new(&buf[..])
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+
+        assert_eq!(vec![record(7, Some(namespace)), record(8, None)], records);
+    }
+
+    #[test]
+    fn documented_header() {
+        let mut buf = Vec::new();
+        ExportWriter::new(&mut buf).unwrap().finish().unwrap();
+
+        #[rustfmt::skip]
+        let expected = [
+            // map of 3 entries
+            0xa3,
+            // "format": "chord-export"
+            0x66, b'f', b'o', b'r', b'm', b'a', b't',
+            0x6c, b'c', b'h', b'o', b'r', b'd', b'-', b'e', b'x', b'p', b'o', b'r', b't',
+            // "version": 2
+            0x67, b'v', b'e', b'r', b's', b'i', b'o', b'n', 0x02,
+            // "records": [_ ]
+            0x67, b'r', b'e', b'c', b'o', b'r', b'd', b's', 0x9f, 0xff,
+        ];
+
+        assert_eq!(&expected[..], &buf[..]);
+    }
+
+    #[test]
+    fn skips_unknown_entries() {
+        #[rustfmt::skip]
+        let buf = [
+            // map of 4 entries
+            0xa4,
+            // "format": "chord-export"
+            0x66, b'f', b'o', b'r', b'm', b'a', b't',
+            0x6c, b'c', b'h', b'o', b'r', b'd', b'-', b'e', b'x', b'p', b'o', b'r', b't',
+            // "peer": {_ "port": -1 }
+            0x64, b'p', b'e', b'e', b'r', 0xbf, 0x64, b'p', b'o', b'r', b't', 0x20, 0xff,
+            // "version": 1
+            0x67, b'v', b'e', b'r', b's', b'i', b'o', b'n', 0x01,
+            // "records": [{"key": h'0000..00', "ttl": 5, "value": h'', "version": 2, "replication_index": 0, "x": [1]}]
+            0x67, b'r', b'e', b'c', b'o', b'r', b'd', b's', 0x81, 0xa6,
+            0x63, b'k', b'e', b'y', 0x58, 0x20,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0x63, b't', b't', b'l', 0x05,
+            0x65, b'v', b'a', b'l', b'u', b'e', 0x40,
+            0x67, b'v', b'e', b'r', b's', b'i', b'o', b'n', 0x02,
+            0x71, b'r', b'e', b'p', b'l', b'i', b'c', b'a', b't', b'i', b'o', b'n', b'_',
+            b'i', b'n', b'd', b'e', b'x', 0x00,
+            0x61, b'x', 0x81, 0x01,
+        ];
+
+        let records: Vec<ExportRecord> = ExportReader::new(&buf[..])
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+
+        assert_eq!(1, records.len());
+        // version 1 carried the ttl in seconds
+        assert_eq!(5000, records[0].ttl);
+        assert_eq!(2, records[0].version);
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        let mut buf = Vec::new();
+        write_head(&mut buf, MAP, 3).unwrap();
+        write_text(&mut buf, "format").unwrap();
+        write_text(&mut buf, FORMAT).unwrap();
+        write_text(&mut buf, "version").unwrap();
+        write_head(&mut buf, UNSIGNED, VERSION + 1).unwrap();
+
+        let err = ExportReader::new(&buf[..]).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn rejects_key_outside_namespace() {
+        let record = record(8, Some(Namespace::new(&[7]).unwrap()));
+
+        let mut buf = Vec::new();
+        let mut writer = ExportWriter::new(&mut buf).unwrap();
+        writer.write(&record).unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = ExportReader::new(&buf[..]).unwrap();
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn export_import_storage() {
+        let source = Mutex::new(Storage::new());
+
+        for byte in 1..=3 {
+            let key = Key {
+                raw_key: [byte; 32],
+                replication_index: 0,
+            };
+
+            source.lock().unwrap().insert(
+                key,
+                Record::new(vec![byte], Duration::from_secs(60), u64::from(byte)),
+            );
+        }
+
+        let mut buf = Vec::new();
+        assert_eq!(3, export(&source, None, &mut buf).unwrap());
+
+        // a newer version is kept while an older one is replaced
+        let target = Mutex::new(Storage::new());
+        let key = |byte| Key {
+            raw_key: [byte; 32],
+            replication_index: 0,
+        };
+        target
+            .lock()
+            .unwrap()
+            .insert(key(1), Record::new(vec![0], Duration::from_secs(60), 10));
+        target
+            .lock()
+            .unwrap()
+            .insert(key(2), Record::new(vec![0], Duration::from_secs(60), 0));
+
+        assert_eq!(2, import(&target, &buf[..]).unwrap());
+
+        let target = target.lock().unwrap();
+        assert_eq!([0], *target[&key(1)].value);
+        assert_eq!([2], *target[&key(2)].value);
+        assert_eq!([3], *target[&key(3)].value);
+    }
+}
@@ -0,0 +1,252 @@
+//! C interface for embedding a node
+//!
+//! The `cdylib` feature exports functions around [`Node`] such that C and
+//! C++ applications can run a peer in their process and access the DHT
+//! through it. Build the shared library with
+//!
+//! ```text
+//! cargo rustc --lib --release --features cdylib --crate-type cdylib
+//! ```
+//!
+//! and include `include/chord.h` which declares the following functions:
+//!
+//! ```text
+//! dht_node *node = dht_node_start("config.ini", NULL);
+//!
+//! uint8_t key[32] = { 1, 2, 3 };
+//! dht_node_put(node, key, sizeof(key), (const uint8_t *) "hello", 5, 60, 2);
+//!
+//! uint8_t value[64];
+//! int64_t len = dht_node_get(node, key, sizeof(key), value, sizeof(value));
+//!
+//! dht_node_stop(node);
+//! ```
+//!
+//! Keys are at most 32 bytes and padded with zeros like
+//! [`DhtKey::from_slice`]. The functions return negative status codes on
+//! failure and log the cause. Panics are caught at the boundary and reported
+//! as [`DHT_ERROR`] as well.
+//!
+//! The servers of a node listen until the process exits. Stopping a node
+//! therefore hands its records to its successor and leaves the network, see
+//! [`ApiClient::drain`], before the handle is released.
+//!
+//! [`Node`]: ../struct.Node.html
+//! [`DhtKey::from_slice`]: ../dht/struct.DhtKey.html#method.from_slice
+//! [`DHT_ERROR`]: constant.DHT_ERROR.html
+//! [`ApiClient::drain`]: ../client/struct.ApiClient.html#method.drain
+
+use crate::client::ApiClient;
+use crate::config::Config;
+use crate::dht::{DhtKey, DhtValue};
+use crate::sync::MutexExt;
+use crate::Node;
+use std::ffi::CStr;
+use std::net::SocketAddr;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice};
+
+/// Returned if the arguments are invalid or the operation failed
+pub const DHT_ERROR: c_int = -1;
+
+/// Returned by [`dht_node_get`] if no value is stored under the key
+///
+/// [`dht_node_get`]: fn.dht_node_get.html
+pub const DHT_NOT_FOUND: c_int = -2;
+
+/// A running node owned by a C application
+pub struct DhtNode {
+    node: Node,
+    client: ApiClient,
+}
+
+/// Starts a node with the configuration file at `config_path` which joins
+/// the network via the peer at `bootstrap` or creates a new network if
+/// `bootstrap` is null.
+///
+/// Returns null if the node could not be started. The returned handle has to
+/// be released with [`dht_node_stop`].
+///
+/// # Safety
+///
+/// `config_path` and `bootstrap` must be null or point to null-terminated
+/// strings.
+///
+/// [`dht_node_stop`]: fn.dht_node_stop.html
+#[no_mangle]
+pub unsafe extern "C" fn dht_node_start(
+    config_path: *const c_char,
+    bootstrap: *const c_char,
+) -> *mut DhtNode {
+    guard(ptr::null_mut(), || {
+        let config_path = str_arg(config_path)?.ok_or("missing config path")?;
+        let bootstrap = match str_arg(bootstrap)? {
+            Some(bootstrap) => Some(bootstrap.parse::<SocketAddr>()?),
+            None => None,
+        };
+
+        let config = Config::load_from_file(config_path)?;
+        let timeout = config.timeout;
+        let node = Node::start(config, bootstrap)?;
+        let client = ApiClient::new(node.api_address(), timeout);
+
+        Ok(Box::into_raw(Box::new(DhtNode { node, client })))
+    })
+}
+
+/// Makes `node` leave the network and releases its handle.
+///
+/// The node hands its records to its successor first unless it is the only
+/// peer of the network. Returns 0 or [`DHT_ERROR`] if the records could not
+/// be handed over, the handle is released in either case.
+///
+/// # Safety
+///
+/// `node` must be null or a handle returned by [`dht_node_start`] which has
+/// not been stopped yet.
+///
+/// [`DHT_ERROR`]: constant.DHT_ERROR.html
+/// [`dht_node_start`]: fn.dht_node_start.html
+#[no_mangle]
+pub unsafe extern "C" fn dht_node_stop(node: *mut DhtNode) -> c_int {
+    if node.is_null() {
+        return DHT_ERROR;
+    }
+
+    let node = Box::from_raw(node);
+
+    guard(DHT_ERROR, || {
+        let alone = {
+            let routing = node.node.routing();
+            let routing = routing.lock_or_recover();
+
+            routing.successor.socket_addr() == routing.current.socket_addr()
+        };
+
+        if !alone {
+            let records = node.client.drain()?;
+            info!("Handed {} records to the successor", records);
+        }
+
+        Ok(0)
+    })
+}
+
+/// Stores the value of `value_len` bytes at `value` under the key of
+/// `key_len` bytes at `key` for `ttl` seconds on `replication` replicas.
+///
+/// Waits until a replica confirmed the value and returns the number of
+/// confirmed replicas or [`DHT_ERROR`] if no replica stored it.
+///
+/// # Safety
+///
+/// `node` must be a handle returned by [`dht_node_start`], `key` and `value`
+/// must point to at least `key_len` and `value_len` readable bytes.
+///
+/// [`DHT_ERROR`]: constant.DHT_ERROR.html
+/// [`dht_node_start`]: fn.dht_node_start.html
+#[no_mangle]
+pub unsafe extern "C" fn dht_node_put(
+    node: *const DhtNode,
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+    ttl: u16,
+    replication: u8,
+) -> c_int {
+    guard(DHT_ERROR, || {
+        let node = node.as_ref().ok_or("missing node")?;
+        let key = DhtKey::from_slice(bytes_arg(key, key_len).ok_or("missing key")?)?;
+        let value = DhtValue::new(bytes_arg(value, value_len).ok_or("missing value")?.to_vec())?;
+
+        match node
+            .client
+            .put_acknowledged(key, value, ttl, replication, 1)?
+        {
+            Some(acks) => Ok(c_int::from(acks)),
+            None => Err("too few replicas stored the value".into()),
+        }
+    })
+}
+
+/// Obtains the value stored under the key of `key_len` bytes at `key` and
+/// copies up to `buffer_len` bytes of it into `buffer`.
+///
+/// Returns the length of the value, which may exceed `buffer_len` such that
+/// the call can be repeated with a larger buffer, [`DHT_NOT_FOUND`] if no
+/// value is stored under the key or [`DHT_ERROR`] if the lookup failed.
+///
+/// # Safety
+///
+/// `node` must be a handle returned by [`dht_node_start`], `key` must point
+/// to at least `key_len` readable bytes and `buffer` to at least
+/// `buffer_len` writable bytes.
+///
+/// [`DHT_NOT_FOUND`]: constant.DHT_NOT_FOUND.html
+/// [`DHT_ERROR`]: constant.DHT_ERROR.html
+/// [`dht_node_start`]: fn.dht_node_start.html
+#[no_mangle]
+pub unsafe extern "C" fn dht_node_get(
+    node: *const DhtNode,
+    key: *const u8,
+    key_len: usize,
+    buffer: *mut u8,
+    buffer_len: usize,
+) -> i64 {
+    guard(i64::from(DHT_ERROR), || {
+        let node = node.as_ref().ok_or("missing node")?;
+        let key = DhtKey::from_slice(bytes_arg(key, key_len).ok_or("missing key")?)?;
+
+        let value = match node.client.get(key)? {
+            Some(value) => value,
+            None => return Ok(i64::from(DHT_NOT_FOUND)),
+        };
+
+        let len = value.len().min(buffer_len);
+        if len > 0 {
+            if buffer.is_null() {
+                return Err("missing buffer".into());
+            }
+
+            ptr::copy_nonoverlapping(value.as_bytes().as_ptr(), buffer, len);
+        }
+
+        Ok(value.len() as i64)
+    })
+}
+
+/// Runs `f` and returns `default` if it fails or panics.
+fn guard<T, F>(default: T, f: F) -> T
+where
+    F: FnOnce() -> crate::Result<T>,
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(result)) => result,
+        Ok(Err(err)) => {
+            error!("Error in embedded node: {}", err);
+            default
+        }
+        Err(_) => {
+            error!("Panic in embedded node");
+            default
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> crate::Result<Option<&'a str>> {
+    if s.is_null() {
+        return Ok(None);
+    }
+
+    Ok(Some(CStr::from_ptr(s).to_str()?))
+}
+
+unsafe fn bytes_arg<'a>(bytes: *const u8, len: usize) -> Option<&'a [u8]> {
+    match (bytes.is_null(), len) {
+        (_, 0) => Some(&[]),
+        (true, _) => None,
+        (false, _) => Some(slice::from_raw_parts(bytes, len)),
+    }
+}
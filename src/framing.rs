@@ -0,0 +1,247 @@
+//! Framing of the messages on the wire
+//!
+//! A [`Connection`] hands every encoded message to its [`Framing`] which
+//! writes it to the TCP stream and reads it back on the other side. Both
+//! peers of a connection have to use the same framing.
+//!
+//! * [`Plain`] writes the messages as they are. Their header already carries
+//!   the size, thus no additional bytes are needed.
+//! * [`LengthObfuscated`] pads every message with random bytes to a multiple
+//!   of its block size such that the types of the messages cannot be told
+//!   apart by their size on the wire.
+//!
+//! Wrappers like encryption or compression of single messages implement
+//! [`Framing`] as well and are configured in the same place. A framing may
+//! also exchange a handshake right after the connection has been
+//! established, like the client puzzles of the [`puzzle`] module.
+//!
+//! [`Connection`]: ../network/struct.Connection.html
+//! [`Framing`]: trait.Framing.html
+//! [`Plain`]: struct.Plain.html
+//! [`LengthObfuscated`]: struct.LengthObfuscated.html
+//! [`puzzle`]: ../puzzle/index.html
+
+use crate::message::codec::MAX_MESSAGE_SIZE;
+use byteorder::{ByteOrder, NetworkEndian, ReadBytesExt, WriteBytesExt};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::sync::Arc;
+
+/// Writes encoded messages to a stream and reads them back
+pub trait Framing: fmt::Debug + Send + Sync {
+    /// Writes the encoded message `message` to `writer`.
+    fn write_frame(&self, writer: &mut dyn Write, message: &[u8]) -> io::Result<()>;
+
+    /// Reads the next encoded message from `reader` into `buffer` and returns
+    /// its size.
+    ///
+    /// `buffer` holds at least [`MAX_MESSAGE_SIZE`] bytes.
+    ///
+    /// [`MAX_MESSAGE_SIZE`]: ../message/codec/constant.MAX_MESSAGE_SIZE.html
+    fn read_frame(&self, reader: &mut dyn Read, buffer: &mut [u8]) -> io::Result<usize>;
+
+    /// Performs the handshake of the peer which opened `stream` before any
+    /// message is sent.
+    fn connect(&self, _stream: &mut TcpStream) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Performs the handshake of the peer which accepted `stream` before any
+    /// message is received.
+    fn accept(&self, _stream: &mut TcpStream) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the framing with the given name as used in the configuration.
+pub fn from_name(name: &str) -> Option<Arc<dyn Framing>> {
+    match name {
+        "plain" => Some(Arc::new(Plain)),
+        "obfuscated" => Some(Arc::new(LengthObfuscated::default())),
+        _ => None,
+    }
+}
+
+/// Returns the framing used if none is configured.
+pub fn plain() -> Arc<dyn Framing> {
+    Arc::new(Plain)
+}
+
+/// Messages as they are
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Plain;
+
+impl Framing for Plain {
+    fn write_frame(&self, writer: &mut dyn Write, message: &[u8]) -> io::Result<()> {
+        writer.write_all(message)
+    }
+
+    fn read_frame(&self, reader: &mut dyn Read, buffer: &mut [u8]) -> io::Result<usize> {
+        // read header from tcp stream to obtain the message size
+        reader.read_exact(&mut buffer[..4])?;
+
+        let size = NetworkEndian::read_u16(&buffer[..2]) as usize;
+
+        if size > MAX_MESSAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Message exceeds maximum size",
+            ));
+        }
+
+        // read remaining bytes of the message from tcp stream
+        if size > 4 {
+            reader.read_exact(&mut buffer[4..size])?;
+        }
+
+        Ok(size.max(4))
+    }
+}
+
+/// Messages padded with random bytes to a multiple of the block size
+///
+/// Every frame starts with the size of the padded message followed by the
+/// message and the padding. Messages close to [`MAX_MESSAGE_SIZE`] are only
+/// padded up to that size.
+///
+/// [`MAX_MESSAGE_SIZE`]: ../message/codec/constant.MAX_MESSAGE_SIZE.html
+#[derive(Clone, Copy, Debug)]
+pub struct LengthObfuscated {
+    block_size: usize,
+}
+
+impl LengthObfuscated {
+    /// Block size used by default
+    pub const DEFAULT_BLOCK_SIZE: usize = 256;
+
+    /// Creates a framing which pads messages to a multiple of `block_size`.
+    pub fn new(block_size: usize) -> Self {
+        Self {
+            block_size: block_size.max(1),
+        }
+    }
+}
+
+impl Default for LengthObfuscated {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_BLOCK_SIZE)
+    }
+}
+
+impl Framing for LengthObfuscated {
+    fn write_frame(&self, writer: &mut dyn Write, message: &[u8]) -> io::Result<()> {
+        let blocks = message.len().div_ceil(self.block_size);
+        let padded = (blocks * self.block_size).clamp(message.len(), MAX_MESSAGE_SIZE);
+
+        let mut frame = Vec::with_capacity(2 + padded);
+        frame.write_u16::<NetworkEndian>(padded as u16)?;
+        frame.extend_from_slice(message);
+        frame.resize(2 + padded, 0);
+
+        SystemRandom::new()
+            .fill(&mut frame[2 + message.len()..])
+            .map_err(|_| io::Error::other("could not generate random bytes"))?;
+
+        writer.write_all(&frame)
+    }
+
+    fn read_frame(&self, reader: &mut dyn Read, buffer: &mut [u8]) -> io::Result<usize> {
+        let padded = reader.read_u16::<NetworkEndian>()? as usize;
+
+        if padded > MAX_MESSAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Frame exceeds maximum size",
+            ));
+        }
+
+        reader.read_exact(&mut buffer[..padded])?;
+
+        let size = match padded {
+            0..=3 => 0,
+            _ => NetworkEndian::read_u16(&buffer[..2]) as usize,
+        };
+
+        if size < 4 || size > padded {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Message size does not match frame",
+            ));
+        }
+
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn roundtrip(framing: &dyn Framing, message: &[u8]) -> Vec<u8> {
+        let mut wire = Vec::new();
+        framing.write_frame(&mut wire, message).unwrap();
+
+        let mut buffer = vec![0; MAX_MESSAGE_SIZE];
+        let mut reader = Cursor::new(&wire);
+        let size = framing.read_frame(&mut reader, &mut buffer).unwrap();
+
+        assert_eq!(message, &buffer[..size]);
+        assert_eq!(wire.len() as u64, reader.position());
+
+        wire
+    }
+
+    #[test]
+    fn plain() {
+        let message = [0, 6, 2, 1, 9, 9];
+
+        assert_eq!(&message[..], &roundtrip(&Plain, &message)[..]);
+    }
+
+    #[test]
+    fn length_obfuscated() {
+        let framing = LengthObfuscated::new(16);
+
+        let short = roundtrip(&framing, &[0, 6, 2, 1, 9, 9]);
+        let long = roundtrip(&framing, &[0, 12, 2, 1, 9, 9, 9, 9, 9, 9, 9, 9]);
+
+        // both messages take a whole block
+        assert_eq!(2 + 16, short.len());
+        assert_eq!(short.len(), long.len());
+    }
+
+    #[test]
+    fn length_obfuscated_maximum_size() {
+        let mut message = vec![0; MAX_MESSAGE_SIZE];
+        NetworkEndian::write_u16(&mut message, MAX_MESSAGE_SIZE as u16);
+
+        let wire = roundtrip(&LengthObfuscated::default(), &message);
+
+        assert_eq!(2 + MAX_MESSAGE_SIZE, wire.len());
+    }
+
+    #[test]
+    fn length_obfuscated_invalid_size() {
+        // a message of 32 bytes in a frame of 16
+        let mut wire = vec![0, 16, 0, 32];
+        wire.resize(18, 0);
+
+        let mut buffer = vec![0; MAX_MESSAGE_SIZE];
+        let err = LengthObfuscated::default()
+            .read_frame(&mut Cursor::new(wire), &mut buffer)
+            .unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn from_name() {
+        assert!(super::from_name("plain").is_some());
+        assert!(super::from_name("obfuscated").is_some());
+        assert!(super::from_name("tls").is_none());
+    }
+}
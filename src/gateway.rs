@@ -0,0 +1,196 @@
+//! Api requests for clients of the WebSocket gateway of a node
+//!
+//! The WebSocket gateway of a node forwards api messages sent in binary
+//! frames to its api interface and returns the reply in a binary frame as
+//! well. Clients without the standard library, like the [`wasm`] client in a
+//! browser, encode their requests with the functions of this module and
+//! decode the replies into a [`Reply`]. Only `alloc` is needed.
+//!
+//! Puts always request an acknowledgement such that every request is
+//! answered and replies can be matched with requests by their order.
+//!
+//! # Examples
+//!
+//! ```
+//! # use chord::dht::{DhtKey, DhtValue};
+//! # use chord::gateway::{self, Reply};
+//! #
+//! let key = DhtKey::from_name("alice/profile");
+//! let request = gateway::encode_get(&key);
+//!
+//! // DHT SUCCESS carrying the value
+//! let mut reply = vec![0, 39, 2, 140];
+//! reply.extend_from_slice(&key.raw());
+//! reply.extend_from_slice(b"abc");
+//!
+//! assert_eq!(
+//!     Reply::Value(DhtValue::new(b"abc".to_vec()).unwrap()),
+//!     gateway::decode_reply(&reply).unwrap()
+//! );
+//! ```
+//!
+//! [`wasm`]: ../wasm/index.html
+//! [`Reply`]: enum.Reply.html
+
+use crate::dht::{DhtKey, DhtValue};
+use crate::error::ConversionError;
+use crate::message::codec;
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// The reply of the gateway to a request
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    /// `DHT SUCCESS` with the value stored under the requested key
+    Value(DhtValue),
+    /// `DHT PUT SUCCESS` with the number of replicas which stored the value
+    /// and the version the DHT assigned to it if the node reports it
+    Stored { acks: u8, version: Option<u64> },
+    /// `DHT FAILURE` if no value was found or too few replicas stored it
+    Failure,
+}
+
+/// Encodes a `DHT GET` request for `key`.
+pub fn encode_get(key: &DhtKey) -> Vec<u8> {
+    codec::encode_message(codec::DHT_GET, &key.raw()).expect("key fits into a message")
+}
+
+/// Encodes a `DHT PUT` request which stores `value` under `key` for `ttl`
+/// seconds on `replication` replicas and is acknowledged by one of them.
+///
+/// Fails if the value does not fit into a single message.
+pub fn encode_put(
+    key: &DhtKey,
+    value: &DhtValue,
+    ttl: u16,
+    replication: u8,
+) -> Result<Vec<u8>, ConversionError> {
+    let mut payload = Vec::with_capacity(36 + value.len());
+    payload.extend_from_slice(&ttl.to_be_bytes());
+    payload.push(replication);
+    payload.push(1);
+    payload.extend_from_slice(&key.raw());
+    payload.extend_from_slice(value.as_bytes());
+
+    codec::encode_message(codec::DHT_PUT, &payload)
+}
+
+/// Decodes the reply of the gateway to a `DHT GET` or `DHT PUT` request.
+///
+/// Fails if `bytes` do not contain one of these replies.
+pub fn decode_reply(bytes: &[u8]) -> Result<Reply, ConversionError> {
+    let (size, message_type) = codec::decode_header(bytes)
+        .ok_or_else(|| ConversionError::new("incomplete header".to_string()))?;
+
+    let payload = bytes
+        .get(codec::HEADER_SIZE..usize::from(size))
+        .ok_or_else(|| ConversionError::new(format!("incomplete message of {} bytes", size)))?;
+
+    let incomplete = || ConversionError::new(format!("incomplete payload of {}", message_type));
+
+    match message_type {
+        codec::DHT_SUCCESS => {
+            let value = payload.get(32..).ok_or_else(incomplete)?;
+
+            Ok(Reply::Value(DhtValue::new(value.to_vec())?))
+        }
+        codec::DHT_PUT_SUCCESS => {
+            if payload.len() < 36 {
+                return Err(incomplete());
+            }
+
+            let version = match payload.get(36..44) {
+                Some(version) => {
+                    let mut bytes = [0; 8];
+                    bytes.copy_from_slice(version);
+                    Some(u64::from_be_bytes(bytes))
+                }
+                None => None,
+            };
+
+            Ok(Reply::Stored {
+                acks: payload[0],
+                version,
+            })
+        }
+        codec::DHT_FAILURE => Ok(Reply::Failure),
+        other => Err(ConversionError::new(format!(
+            "unexpected message type {}",
+            other
+        ))),
+    }
+}
+
+#[cfg(all(test, feature = "network"))]
+mod tests {
+    use super::*;
+    use crate::message::api::{DhtFailure, DhtGet, DhtPut, DhtPutSuccess, DhtSuccess};
+    use crate::message::Message;
+
+    fn key() -> DhtKey {
+        DhtKey::from([3; 32])
+    }
+
+    fn value() -> DhtValue {
+        DhtValue::new(vec![1, 2, 3]).unwrap()
+    }
+
+    #[test]
+    fn requests_match_messages() {
+        let dht_get = DhtGet::builder().key(key()).build();
+        assert_eq!(
+            Message::DhtGet(dht_get).to_bytes().unwrap(),
+            encode_get(&key())
+        );
+
+        let dht_put = DhtPut {
+            ttl: 60_000,
+            replication: 2,
+            acks: 1,
+            key: key(),
+            value: value(),
+        };
+        assert_eq!(
+            Message::DhtPut(dht_put).to_bytes().unwrap(),
+            encode_put(&key(), &value(), 60, 2).unwrap()
+        );
+    }
+
+    #[test]
+    fn replies() {
+        let reply = |msg: Message| decode_reply(&msg.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(
+            Reply::Value(value()),
+            reply(Message::DhtSuccess(DhtSuccess {
+                key: key(),
+                value: value(),
+                metadata: None,
+            }))
+        );
+        assert_eq!(
+            Reply::Stored {
+                acks: 2,
+                version: Some(7),
+            },
+            reply(Message::DhtPutSuccess(DhtPutSuccess {
+                acks: 2,
+                key: key(),
+                version: Some(7),
+            }))
+        );
+        assert_eq!(
+            Reply::Failure,
+            reply(Message::DhtFailure(DhtFailure { key: key() }))
+        );
+    }
+
+    #[test]
+    fn invalid_replies() {
+        assert!(decode_reply(&[0, 4]).is_err());
+        assert!(decode_reply(&[0, 8, 2, 140]).is_err());
+        assert!(decode_reply(&[0, 6, 2, 141, 0, 0]).is_ok());
+        assert!(decode_reply(&[0, 4, 2, 138]).is_err());
+    }
+}
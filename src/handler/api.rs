@@ -1,112 +1,1599 @@
-use crate::error::MessageError;
+use crate::audit::AuditLog;
+use crate::auth::{self, Authorizer, Operation, Requester};
+use crate::cache::Cache;
+use crate::deadline::{CancelToken, Deadline};
+use crate::dht::{DhtKey, DhtValue, Namespace};
+use crate::error::{CancelledError, DeadlineError, MessageError};
+use crate::export;
+use crate::framing::Framing;
+use crate::handoff::Handoff;
+use crate::load::{self, Load};
 use crate::message::api::*;
+use crate::message::p2p::StorageGetSuccess;
 use crate::message::Message;
-use crate::network::{Connection, ServerHandler};
-use crate::procedures::Procedures;
+use crate::metrics::Metrics;
+use crate::network::{Connection, Multiplexer, ServerHandler, SocketOptions};
+use crate::procedures::{Procedures, ReadOutcome};
+use crate::proxy::Proxy;
 use crate::routing::identifier::{Identifier, Identify};
-use crate::routing::Routing;
-use crate::storage::Key;
+use crate::routing::peer::PeerInfo;
+use crate::routing::{Routing, Slot};
+use crate::storage::{self, Key, Record, Storage};
+use crate::sync::MutexExt;
+use crate::tenant::{Tenant, Tenants};
+use std::collections::HashMap;
 use std::error::Error;
 use std::io;
+use std::iter;
 use std::net::SocketAddr;
-use std::sync::{Arc, Mutex};
-use std::u8;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time for which a prefetched value is kept
+const PREFETCH_TTL: Duration = Duration::from_secs(10);
+
+/// Maximum number of prefetched values kept at the same time
+const PREFETCH_CAPACITY: usize = 4096;
+
+/// Number of keys of a `DHT PREFETCH` which are looked up in parallel
+const PREFETCH_PARALLELISM: usize = 8;
+
+/// Maximum number of peers asked for their load by `NODE LOAD`
+const MAX_LOAD_SAMPLES: usize = 32;
 
 /// Handler for api requests
 ///
-/// The supported incoming api messages are `DHT GET` and `DHT PUT`.
+/// The supported incoming api messages are `DHT GET`, `DHT PUT`,
+/// `DHT DELETE`, `DHT RESOLVE`, `NODE INFO`, `DHT FLUSH`, `DHT CANCEL`,
+/// `NODE DRAIN`, `NODE READ ONLY`, `DHT LIST LOCAL`, `NODE PEERS`,
+/// `DHT RING WALK`, `DHT EXPORT`, `DHT IMPORT`, `DHT PREFETCH`,
+/// `NODE LOAD` and `API ENCODING`.
+///
+/// Each connection is served by a session such that clients can send several
+/// requests over the same connection. A `DHT CANCEL` aborts the running
+/// `DHT GET` search with its request ID of the same connection, such that
+/// clients cannot abort the searches of others.
+///
+/// `DHT PREFETCH` looks up the values of its keys without replying. A found
+/// value is kept for a few seconds and answers the next `DHT GET` of its
+/// key which asks neither for a quorum nor for a newer version. `DHT PUT`
+/// and `DHT DELETE` discard the prefetched value of their key.
+///
+/// The same `DHT GET` requests are answered from the copies of hot keys
+/// which other peers pushed into the cache given to [`with_cache`], see the
+/// [`cache`] module. `DHT PUT` and `DHT DELETE` discard these copies as
+/// well.
+///
+/// `NODE DRAIN` makes the peer read-only, hands its records to the successor
+/// and notifies the receiver given to [`with_drain_notifier`] afterwards.
+/// `NODE READ ONLY` toggles the same read-only mode at runtime.
+///
+/// `NODE LOAD` asks the successor and the fingers for their load and
+/// recommends the number of virtual peers for the node running the
+/// [`with_weight`] peers, see the [`load`] module.
+///
+/// `DHT PUT` and `DHT DELETE` requests for keys in the namespace given to
+/// [`with_system_namespace`] are rejected since these keys are reserved for
+/// internal records.
+///
+/// `API ENCODING` switches a connection to CBOR payloads if enabled with
+/// [`with_cbor`] and keeps the binary encoding otherwise.
+///
+/// Connections which authenticate as one of the tenants given to
+/// [`with_tenants`] may only access the keys of their tenant. All other
+/// requests are only executed if the authorizer given to
+/// [`with_authorizer`] allows them. Accepted puts, deletes and administrative
+/// requests which change the peer are recorded in the log given to
+/// [`with_audit_log`].
+///
+/// [`with_cache`]: #method.with_cache
+/// [`cache`]: ../cache/index.html
+/// [`with_drain_notifier`]: #method.with_drain_notifier
+/// [`with_weight`]: #method.with_weight
+/// [`load`]: ../load/index.html
+/// [`with_system_namespace`]: #method.with_system_namespace
+/// [`with_cbor`]: #method.with_cbor
+/// [`with_tenants`]: #method.with_tenants
+/// [`with_authorizer`]: #method.with_authorizer
+/// [`with_audit_log`]: #method.with_audit_log
 pub struct ApiHandler {
-    routing: Arc<Mutex<Routing<SocketAddr>>>,
+    routing: Arc<Mutex<Routing<PeerInfo>>>,
+    storage: Arc<Mutex<Storage>>,
+    handoff: Arc<Handoff>,
+    metrics: Arc<Metrics>,
     procedures: Procedures,
+    timeout: u64,
+    prefetched: Mutex<HashMap<DhtKey, (DhtSuccess, Instant)>>,
+    cache: Option<Arc<Cache>>,
+    read_only: Arc<AtomicBool>,
+    drained: Option<Sender<()>>,
+    system_namespace: Namespace,
+    cbor: bool,
+    tenants: Arc<Tenants>,
+    authorizer: Arc<dyn Authorizer>,
+    audit_log: Option<Arc<AuditLog>>,
+    weight: u16,
 }
 
 impl ApiHandler {
     /// Creates a new `ApiHandler` instance.
-    pub fn new(routing: Arc<Mutex<Routing<SocketAddr>>>, timeout: u64) -> Self {
-        let procedures = Procedures::new(timeout);
+    pub fn new(
+        routing: Arc<Mutex<Routing<PeerInfo>>>,
+        storage: Arc<Mutex<Storage>>,
+        handoff: Arc<Handoff>,
+        metrics: Arc<Metrics>,
+        timeout: u64,
+    ) -> Self {
+        let procedures = Procedures::with_metrics(timeout, Arc::clone(&metrics));
+
+        let read_only = Arc::new(AtomicBool::new(false));
 
         Self {
             routing,
+            storage,
+            handoff,
+            metrics,
             procedures,
+            timeout,
+            prefetched: Mutex::new(HashMap::new()),
+            cache: None,
+            read_only,
+            drained: None,
+            system_namespace: Namespace::SYSTEM,
+            cbor: false,
+            tenants: Arc::new(Tenants::new()),
+            authorizer: auth::allow_all(),
+            audit_log: None,
+            weight: 1,
         }
     }
 
-    fn closest_peer(&self, identifier: Identifier) -> SocketAddr {
-        let routing = self.routing.lock().unwrap();
+    /// Sets `read_only` while the peer is drained or when toggled by
+    /// `NODE READ ONLY`.
+    ///
+    /// The flag should be shared with the [`P2PHandler`] of this peer which
+    /// refuses new values while it is set.
+    ///
+    /// [`P2PHandler`]: struct.P2PHandler.html
+    pub fn with_read_only(mut self, read_only: Arc<AtomicBool>) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Answers `DHT GET` requests from the copies of hot keys in `cache` if
+    /// it is given.
+    ///
+    /// The peers responsible for the keys learn the address of this peer
+    /// from its `STORAGE GET` requests such that they can push copies to it.
+    /// The cache should be shared with the [`P2PHandler`] of this peer which
+    /// receives them.
+    ///
+    /// [`P2PHandler`]: struct.P2PHandler.html
+    pub fn with_cache(mut self, cache: Option<Arc<Cache>>) -> Self {
+        if cache.is_some() {
+            let current = self.routing.lock_or_recover().current.socket_addr();
+
+            self.procedures = self.procedures.with_requester(current);
+        }
 
-        **routing.closest_peer(identifier)
+        self.cache = cache;
+        self
+    }
+
+    /// Sends a notification to `drained` once the peer has been drained and
+    /// may shut down.
+    pub fn with_drain_notifier(mut self, drained: Sender<()>) -> Self {
+        self.drained = Some(drained);
+        self
+    }
+
+    /// Reserves the keys of `system_namespace` for internal records instead
+    /// of the default [`Namespace::SYSTEM`].
+    ///
+    /// [`Namespace::SYSTEM`]: ../dht/struct.Namespace.html#associatedconstant.SYSTEM
+    pub fn with_system_namespace(mut self, system_namespace: Namespace) -> Self {
+        self.system_namespace = system_namespace;
+        self
+    }
+
+    /// Allows clients to switch their connections to CBOR payloads with
+    /// `API ENCODING`.
+    pub fn with_cbor(mut self, cbor: bool) -> Self {
+        self.cbor = cbor;
+        self
+    }
+
+    /// Restricts connections which authenticate with the token of one of
+    /// `tenants` to the keys of that tenant.
+    ///
+    /// See the [`tenant`] module for details.
+    ///
+    /// [`tenant`]: ../tenant/index.html
+    pub fn with_tenants(mut self, tenants: Arc<Tenants>) -> Self {
+        self.tenants = tenants;
+        self
+    }
+
+    /// Asks `authorizer` before executing a request.
+    ///
+    /// See the [`auth`] module for details.
+    ///
+    /// [`auth`]: ../auth/index.html
+    pub fn with_authorizer(mut self, authorizer: Arc<dyn Authorizer>) -> Self {
+        self.authorizer = authorizer;
+        self
+    }
+
+    /// Records accepted puts, deletes and administrative requests which
+    /// change the peer in `audit_log` if it is given.
+    pub fn with_audit_log(mut self, audit_log: Option<Arc<AuditLog>>) -> Self {
+        self.audit_log = audit_log;
+        self
+    }
+
+    /// Reports in `NODE LOAD` that the node runs `weight` peers including
+    /// its virtual peers.
+    pub fn with_weight(mut self, weight: u16) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Sends the requests to other peers over the shared connections of
+    /// `multiplexer`.
+    pub fn with_multiplexer(mut self, multiplexer: Arc<Multiplexer>) -> Self {
+        self.procedures = self.procedures.with_multiplexer(multiplexer);
+        self
+    }
+
+    /// Opens the dedicated connections to other peers through `proxy` if it
+    /// is given.
+    pub fn with_proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.procedures = self.procedures.with_proxy(proxy);
+        self
+    }
+
+    /// Exchanges the messages of the dedicated connections to other peers in
+    /// frames of `framing`.
+    pub fn with_framing(mut self, framing: Arc<dyn Framing>) -> Self {
+        self.procedures = self.procedures.with_framing(framing);
+        self
+    }
+
+    /// Applies `socket_options` to the dedicated connections to other peers.
+    pub fn with_socket_options(mut self, socket_options: SocketOptions) -> Self {
+        self.procedures = self.procedures.with_socket_options(socket_options);
+        self
+    }
+
+    fn closest_peer(&self, identifier: Identifier) -> (Slot, SocketAddr) {
+        let routing = self.routing.lock_or_recover();
+        let (slot, peer) = routing.route(identifier);
+
+        (slot, peer.socket_addr())
+    }
+
+    /// Counts a lookup which started at the peer in `slot` and resolved to
+    /// `peer_addr` for the finger it was routed through.
+    fn record_route(&self, slot: Slot, closest_peer: SocketAddr, peer_addr: SocketAddr) {
+        if let Slot::Finger(index) = slot {
+            self.metrics.record_route(index, peer_addr == closest_peer);
+        }
     }
 
     fn find_peer(&self, identifier: Identifier) -> crate::Result<SocketAddr> {
-        let closest_peer = self.closest_peer(identifier);
+        let (slot, closest_peer) = self.closest_peer(identifier);
+        let peer_addr = self.procedures.find_peer(identifier, closest_peer)?;
+        self.record_route(slot, closest_peer, peer_addr);
 
-        self.procedures.find_peer(identifier, closest_peer)
+        Ok(peer_addr)
     }
 
-    fn handle_dht_get(&self, mut api_con: Connection, dht_get: DhtGet) -> crate::Result<()> {
+    fn trace_peer(&self, identifier: Identifier) -> crate::Result<(SocketAddr, Vec<SocketAddr>)> {
+        let (slot, closest_peer) = self.closest_peer(identifier);
+        let (peer_addr, path) = self.procedures.trace_peer(identifier, closest_peer)?;
+        self.record_route(slot, closest_peer, peer_addr);
+
+        Ok((peer_addr, path))
+    }
+
+    fn handle_dht_get(
+        &self,
+        session: &Session,
+        api_con: &mut Connection,
+        dht_get: DhtGet,
+    ) -> crate::Result<()> {
+        if dht_get.quorum.is_none() && dht_get.if_none_match.is_none() {
+            if let Some(dht_success) = self.take_prefetched(dht_get.key) {
+                info!("Replying with prefetched value for key {}", dht_get.key);
+
+                api_con.send(&Message::DhtSuccess(dht_success))?;
+
+                return Ok(());
+            }
+
+            if let Some((key, storage_success)) = self.get_cached(dht_get.key) {
+                info!("Replying with cached copy of hot key {}", dht_get.key);
+
+                api_con.send(&found_value(dht_get.key, key, storage_success)?)?;
+
+                return Ok(());
+            }
+        }
+
+        // give up once the budget of the client is spent
+        let procedures = match dht_get.budget {
+            Some(budget) => self.procedures.with_deadline(Deadline::from_budget(budget)),
+            None => self.procedures.clone(),
+        };
+
+        // the search is aborted if the client cancels it or goes away
+        let cancel_token = session.start_search(dht_get.request_id);
+        let procedures = procedures.with_cancel_token(cancel_token);
+
+        let result = self.find_value(&procedures, &dht_get);
+
+        session.finish_search();
+
+        // send failure if the search was cancelled
+        let msg = match result {
+            Ok(msg) => msg,
+            Err(ref err) if err.is::<CancelledError>() => {
+                info!("Cancelled DHT GET for key {}", dht_get.key);
+
+                Message::DhtFailure(DhtFailure { key: dht_get.key })
+            }
+            Err(err) => return Err(err),
+        };
+
+        api_con.send(&msg)?;
+
+        Ok(())
+    }
+
+    /// Searches the value of `dht_get` and returns the reply to the client.
+    fn find_value(&self, procedures: &Procedures, dht_get: &DhtGet) -> crate::Result<Message> {
+        if let Some(quorum) = dht_get.quorum {
+            let msg = self.quorum_read(procedures, dht_get, quorum);
+
+            // nobody waits for the reply anymore if the search was aborted
+            procedures.check_aborted()?;
+
+            return Ok(msg);
+        }
+
         // iterate through all replication indices
         for i in 0..u8::MAX {
             let key = Key {
-                raw_key: dht_get.key,
+                raw_key: dht_get.key.raw(),
                 replication_index: i,
             };
 
-            let peer_addr = self.find_peer(key.identifier())?;
+            match self.get_replica(procedures, key, dht_get.if_none_match)? {
+                ReadOutcome::Found(storage_success) => {
+                    return found_value(dht_get.key, key, storage_success);
+                }
+                ReadOutcome::NotModified => {
+                    return Ok(Message::DhtNotModified(DhtNotModified { key: dht_get.key }));
+                }
+                ReadOutcome::NotFound => {}
+            }
+        }
 
-            if let Some(value) = self.procedures.get_value(peer_addr, key)? {
-                let dht_success = DhtSuccess {
-                    key: dht_get.key,
-                    value,
-                };
-                api_con.send(&Message::DhtSuccess(dht_success))?;
+        Ok(Message::DhtFailure(DhtFailure { key: dht_get.key }))
+    }
 
-                return Ok(());
-            }
+    /// Returns the prefetched value of `key` unless it expired already.
+    ///
+    /// The value is handed out only once.
+    fn take_prefetched(&self, key: DhtKey) -> Option<DhtSuccess> {
+        let mut prefetched = self.prefetched.lock_or_recover();
+
+        prefetched
+            .remove(&key)
+            .filter(|(_, expires)| *expires > Instant::now())
+            .map(|(dht_success, _)| dht_success)
+    }
+
+    fn discard_prefetched(&self, key: DhtKey) {
+        self.prefetched.lock_or_recover().remove(&key);
+    }
+
+    fn get_cached(&self, key: DhtKey) -> Option<(Key, StorageGetSuccess)> {
+        self.cache.as_ref().and_then(|cache| cache.get(key.raw()))
+    }
+
+    fn discard_cached(&self, key: DhtKey) {
+        if let Some(ref cache) = self.cache {
+            cache.remove(key.raw());
         }
+    }
 
-        // send failure if no value was found throughout the iteration
-        let dht_failure = DhtFailure { key: dht_get.key };
-        api_con.send(&Message::DhtFailure(dht_failure))?;
+    fn handle_dht_prefetch(
+        &self,
+        session: &Session,
+        api_con: &Connection,
+        dht_prefetch: DhtPrefetch,
+    ) -> crate::Result<()> {
+        let requester = Requester::Client(api_con.peer_addr()?);
+
+        // every key is read like with a separate DHT GET
+        let keys: Vec<DhtKey> = dht_prefetch
+            .keys
+            .into_iter()
+            .take(DhtPrefetch::MAX_KEYS)
+            .filter(|&key| {
+                let operation = Operation::Get(key);
+
+                self.permitted(session, operation)
+                    && self.authorizer.authorize(requester, operation)
+            })
+            .collect();
+
+        info!("Prefetching the values of {} keys", keys.len());
+
+        for batch in keys.chunks(PREFETCH_PARALLELISM) {
+            thread::scope(|scope| {
+                for &key in batch {
+                    scope.spawn(move || self.prefetch(key));
+                }
+            });
+        }
 
         Ok(())
     }
 
-    fn handle_dht_put(&self, _con: Connection, dht_put: DhtPut) -> crate::Result<()> {
+    fn prefetch(&self, key: DhtKey) {
+        let dht_get = DhtGet {
+            key,
+            quorum: None,
+            budget: None,
+            request_id: None,
+            if_none_match: None,
+        };
+
+        let dht_success = match self.find_value(&self.procedures, &dht_get) {
+            Ok(Message::DhtSuccess(dht_success)) => dht_success,
+            Ok(_) => {
+                debug!("No value to prefetch for key {}", key);
+
+                return;
+            }
+            Err(err) => {
+                debug!("Could not prefetch key {}: {}", key, err);
+
+                return;
+            }
+        };
+
+        let mut prefetched = self.prefetched.lock_or_recover();
+
+        let now = Instant::now();
+        prefetched.retain(|_, (_, expires)| *expires > now);
+
+        if prefetched.len() < PREFETCH_CAPACITY {
+            prefetched.insert(key, (dht_success, now + PREFETCH_TTL));
+        }
+    }
+
+    fn get_replica(
+        &self,
+        procedures: &Procedures,
+        key: Key,
+        if_none_match: Option<u64>,
+    ) -> crate::Result<ReadOutcome> {
+        let (slot, closest_peer) = self.closest_peer(key.identifier());
+        let peer_addr = procedures.find_peer(key.identifier(), closest_peer)?;
+        self.record_route(slot, closest_peer, peer_addr);
+
+        procedures.read_value(peer_addr, key, if_none_match)
+    }
+
+    fn put_replica(&self, key: Key, record: Record) -> crate::Result<bool> {
+        let peer_addr = self.find_peer(key.identifier())?;
+
+        let result = self.procedures.put_value(peer_addr, key, record.clone());
+
+        if let Err(err) = result {
+            warn!(
+                "Peer {} is unreachable for key {}, thus keeping a hint: {}",
+                peer_addr, key, err
+            );
+
+            self.handoff.hint(peer_addr, key, record);
+
+            return Ok(false);
+        }
+
+        result
+    }
+
+    fn quorum_read(&self, procedures: &Procedures, dht_get: &DhtGet, quorum: Quorum) -> Message {
+        let if_none_match = dht_get.if_none_match;
+
+        // query all replicas in parallel
+        let replies: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..quorum.replicas)
+                .map(|i| {
+                    let key = Key {
+                        raw_key: dht_get.key.raw(),
+                        replication_index: i,
+                    };
+
+                    // errors are not sendable, thus only their messages are returned
+                    scope.spawn(move || {
+                        let reply = self.get_replica(procedures, key, if_none_match);
+
+                        (key, reply.map_err(|err| err.to_string()))
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().ok())
+                .collect()
+        });
+
+        let mut reads = 0;
+        let mut newest: Option<(Key, StorageGetSuccess)> = None;
+        let mut not_modified = false;
+
+        for (key, reply) in replies {
+            match reply {
+                Ok(outcome) => {
+                    reads += 1;
+
+                    match outcome {
+                        ReadOutcome::Found(value) => {
+                            if newest
+                                .as_ref()
+                                .is_none_or(|(_, newest)| value.version > newest.version)
+                            {
+                                newest = Some((key, value));
+                            }
+                        }
+                        ReadOutcome::NotModified => not_modified = true,
+                        ReadOutcome::NotFound => {}
+                    }
+                }
+                Err(err) => warn!("Could not read replica for key {}: {}", key, err),
+            }
+        }
+
+        let failure = Message::DhtFailure(DhtFailure { key: dht_get.key });
+
+        if reads < quorum.reads {
+            warn!(
+                "Only {} of {} required replicas replied to quorum read",
+                reads, quorum.reads
+            );
+
+            return failure;
+        }
+
+        // replicas only return values newer than the version of the client
+        match newest {
+            Some((key, value)) => found_value(dht_get.key, key, value).unwrap_or(failure),
+            None if not_modified => Message::DhtNotModified(DhtNotModified { key: dht_get.key }),
+            None => failure,
+        }
+    }
+
+    fn handle_dht_put(&self, api_con: &mut Connection, dht_put: DhtPut) -> crate::Result<()> {
+        if self.system_namespace.contains(&dht_put.key) {
+            warn!(
+                "Rejecting DHT PUT for key {} in the reserved namespace",
+                dht_put.key
+            );
+
+            // only reply if the application asked for acknowledgements
+            if dht_put.acks > 0 {
+                api_con.send(&Message::DhtFailure(DhtFailure { key: dht_put.key }))?;
+            }
+
+            return Ok(());
+        }
+
+        self.discard_prefetched(dht_put.key);
+        self.discard_cached(dht_put.key);
+
+        // every replica knows how many replicas there are in total
+        let record = Record::new(
+            dht_put.value.clone().into_shared(),
+            Duration::from_millis(dht_put.ttl),
+            storage::current_version(),
+        )
+        .with_replicas(dht_put.replication.saturating_add(1));
+        let mut acks = 0;
+
         // iterate through all replication indices
         for i in 0..=dht_put.replication {
             let key = Key {
-                raw_key: dht_put.key,
+                raw_key: dht_put.key.raw(),
+                replication_index: i,
+            };
+
+            match self.put_replica(key, record.clone()) {
+                Ok(true) => acks += 1,
+                Ok(false) => {}
+                Err(err) if dht_put.acks > 0 => {
+                    warn!("Could not store replica for key {}: {}", key, err)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        // only reply if the application asked for acknowledgements
+        if dht_put.acks == 0 {
+            return Ok(());
+        }
+
+        let msg = if acks >= dht_put.acks {
+            Message::DhtPutSuccess(DhtPutSuccess {
+                acks,
+                key: dht_put.key,
+                version: Some(record.version),
+            })
+        } else {
+            warn!(
+                "Only {} of {} required replicas confirmed, thus replying with DHT FAILURE",
+                acks, dht_put.acks
+            );
+
+            Message::DhtFailure(DhtFailure { key: dht_put.key })
+        };
+
+        api_con.send(&msg)?;
+
+        Ok(())
+    }
+
+    fn handle_dht_delete(
+        &self,
+        api_con: &mut Connection,
+        dht_delete: DhtDelete,
+    ) -> crate::Result<()> {
+        if self.system_namespace.contains(&dht_delete.key) {
+            warn!(
+                "Rejecting DHT DELETE for key {} in the reserved namespace",
+                dht_delete.key
+            );
+
+            let dht_delete_reply = DhtDeleteReply {
+                replicas: 0,
+                key: dht_delete.key,
+            };
+            api_con.send(&Message::DhtDeleteReply(dht_delete_reply))?;
+
+            return Ok(());
+        }
+
+        self.discard_prefetched(dht_delete.key);
+        self.discard_cached(dht_delete.key);
+
+        let mut replicas = 0;
+
+        // iterate through all replication indices
+        for i in 0..=dht_delete.replication {
+            let key = Key {
+                raw_key: dht_delete.key.raw(),
                 replication_index: i,
             };
 
             let peer_addr = self.find_peer(key.identifier())?;
 
-            self.procedures
-                .put_value(peer_addr, key, dht_put.ttl, dht_put.value.clone())?;
+            if self.procedures.delete_value(peer_addr, key)? {
+                replicas += 1;
+            }
         }
 
+        let dht_delete_reply = DhtDeleteReply {
+            replicas,
+            key: dht_delete.key,
+        };
+        api_con.send(&Message::DhtDeleteReply(dht_delete_reply))?;
+
+        Ok(())
+    }
+
+    fn handle_dht_resolve(
+        &self,
+        api_con: &mut Connection,
+        dht_resolve: DhtResolve,
+    ) -> crate::Result<()> {
+        let key = Key {
+            raw_key: dht_resolve.key.raw(),
+            replication_index: dht_resolve.replication_index,
+        };
+
+        let result = if dht_resolve.trace {
+            self.trace_peer(key.identifier())
+        } else {
+            self.find_peer(key.identifier())
+                .map(|socket_addr| (socket_addr, Vec::new()))
+        };
+
+        let msg = match result {
+            Ok((socket_addr, path)) => Message::DhtResolveReply(DhtResolveReply {
+                replication_index: dht_resolve.replication_index,
+                key: dht_resolve.key,
+                identifier: socket_addr.identifier(),
+                socket_addr,
+                path,
+            }),
+            Err(err) => {
+                warn!("Could not resolve key {}: {}", key, err);
+
+                Message::DhtFailure(DhtFailure {
+                    key: dht_resolve.key,
+                })
+            }
+        };
+
+        api_con.send(&msg)?;
+
+        Ok(())
+    }
+
+    fn handle_node_info(
+        &self,
+        api_con: &mut Connection,
+        _node_info: NodeInfo,
+    ) -> crate::Result<()> {
+        let node_info_reply = {
+            let routing = self.routing.lock_or_recover();
+
+            NodeInfoReply {
+                identifier: routing.current.identifier(),
+                socket_addr: routing.current.socket_addr(),
+                predecessor: routing.predecessor.socket_addr(),
+                successor: routing.successor.socket_addr(),
+                lookup_stats: self.metrics.lookup_stats(),
+                traffic: Some(self.metrics.bandwidth().traffic()),
+            }
+        };
+
+        api_con.send(&Message::NodeInfoReply(node_info_reply))?;
+
         Ok(())
     }
 
+    fn handle_dht_flush(&self, api_con: &mut Connection, dht_flush: DhtFlush) -> crate::Result<()> {
+        let records = {
+            let mut storage = self.storage.lock_or_recover();
+            let size = storage.len();
+
+            match dht_flush.scope {
+                FlushScope::All => storage.clear(),
+                FlushScope::Namespace(prefix) => {
+                    storage.retain(|key, _| !key.raw_key.starts_with(&prefix))
+                }
+                FlushScope::Expired => storage.retain(|_, record| !record.is_expired()),
+            }
+
+            size - storage.len()
+        };
+
+        info!("Flushed {} records from local storage", records);
+
+        let dht_flush_reply = DhtFlushReply {
+            records: records as u32,
+        };
+        api_con.send(&Message::DhtFlushReply(dht_flush_reply))?;
+
+        Ok(())
+    }
+
+    fn handle_node_drain(&self, api_con: &mut Connection, _: NodeDrain) -> crate::Result<()> {
+        let (current_addr, predecessor_addr, successor_addr) = {
+            let routing = self.routing.lock_or_recover();
+
+            (
+                routing.current.socket_addr(),
+                routing.predecessor.socket_addr(),
+                routing.successor.socket_addr(),
+            )
+        };
+
+        if successor_addr == current_addr {
+            return Err("Cannot drain the only peer of the network".into());
+        }
+
+        info!("Draining peer, refusing new values");
+
+        // 1. stop accepting new values
+        let was_read_only = self.read_only.swap(true, Ordering::SeqCst);
+
+        let records = storage::snapshot(&self.storage, |_| true);
+
+        // 2. hand all values to the successor which takes over our range
+        let result = self.procedures.leave(
+            current_addr,
+            predecessor_addr,
+            successor_addr,
+            successor_addr,
+            &records,
+        );
+
+        if let Err(err) = result {
+            self.read_only.store(was_read_only, Ordering::SeqCst);
+
+            return Err(err);
+        }
+
+        // 3. make the predecessor bypass us
+        if predecessor_addr != successor_addr {
+            let result = self.procedures.leave(
+                current_addr,
+                predecessor_addr,
+                successor_addr,
+                predecessor_addr,
+                &[],
+            );
+
+            if let Err(err) = result {
+                warn!("Could not notify predecessor about leaving: {}", err);
+            }
+        }
+
+        self.storage.lock_or_recover().clear();
+
+        info!("Transferred {} records to successor", records.len());
+
+        // 4. reply with NODE DRAIN REPLY and shut down
+        let node_drain_reply = NodeDrainReply {
+            records: records.len() as u32,
+        };
+        api_con.send(&Message::NodeDrainReply(node_drain_reply))?;
+
+        if let Some(ref drained) = self.drained {
+            let _ = drained.send(());
+        }
+
+        Ok(())
+    }
+
+    fn handle_node_read_only(
+        &self,
+        api_con: &mut Connection,
+        node_read_only: NodeReadOnly,
+    ) -> crate::Result<()> {
+        let read_only = node_read_only.read_only;
+
+        self.read_only.store(read_only, Ordering::SeqCst);
+
+        if read_only {
+            info!("Switched peer to read-only mode, refusing new values");
+        } else {
+            info!("Switched peer back to accepting new values");
+        }
+
+        let node_read_only_reply = NodeReadOnlyReply { read_only };
+        api_con.send(&Message::NodeReadOnlyReply(node_read_only_reply))?;
+
+        Ok(())
+    }
+
+    fn negotiate_encoding(&self, requested: Encoding) -> Encoding {
+        match requested {
+            Encoding::Cbor if self.cbor => Encoding::Cbor,
+            _ => Encoding::Binary,
+        }
+    }
+
+    fn handle_api_encoding(
+        &self,
+        api_con: &mut Connection,
+        api_encoding: ApiEncoding,
+    ) -> crate::Result<()> {
+        let encoding = api_encoding.encoding;
+
+        // the reply is still sent in the encoding of the request
+        api_con.send(&Message::ApiEncoding(ApiEncoding {
+            encoding,
+            token: None,
+        }))?;
+        api_con.set_encoding(encoding);
+
+        debug!("Switched api connection to {:?} encoding", encoding);
+
+        Ok(())
+    }
+
+    fn handle_dht_list_local(
+        &self,
+        api_con: &mut Connection,
+        dht_list_local: DhtListLocal,
+    ) -> crate::Result<()> {
+        let limit = (dht_list_local.limit as usize)
+            .min(page_limit(api_con, DhtListLocalReply::MAX_RECORDS));
+        let after = dht_list_local.after.map(|after| Key {
+            raw_key: after.key.raw(),
+            replication_index: after.replication_index,
+        });
+
+        // only the keys are sorted such that the storage is not locked for
+        // long, records removed in the meantime are skipped
+        let (total_records, total_bytes, mut keys) = {
+            let storage = self.storage.lock_or_recover();
+
+            let total_bytes = storage
+                .values()
+                .map(|record| record.value.len() as u64)
+                .sum();
+
+            let keys: Vec<Key> = storage
+                .keys()
+                .filter(|key| after.is_none_or(|after| **key > after))
+                .cloned()
+                .collect();
+
+            (storage.len() as u32, total_bytes, keys)
+        };
+
+        keys.sort_unstable();
+
+        let remaining = keys.len().saturating_sub(limit) as u32;
+
+        let records = {
+            let storage = self.storage.lock_or_recover();
+
+            keys.iter()
+                .take(limit)
+                .filter_map(|key| {
+                    storage.get(key).map(|record| LocalRecord {
+                        key: DhtKey::from(key.raw_key),
+                        replication_index: key.replication_index,
+                        ttl: record.ttl_millis(),
+                        size: record.value.len() as u32,
+                        version: record.version,
+                    })
+                })
+                .collect()
+        };
+
+        let dht_list_local_reply = DhtListLocalReply {
+            total_records,
+            total_bytes,
+            remaining,
+            records,
+        };
+
+        info!(
+            "Listing {} of {} records in local storage",
+            dht_list_local_reply.records.len(),
+            dht_list_local_reply.total_records
+        );
+
+        api_con.send(&Message::DhtListLocalReply(dht_list_local_reply))?;
+
+        Ok(())
+    }
+
+    fn handle_dht_export(
+        &self,
+        api_con: &mut Connection,
+        dht_export: DhtExport,
+    ) -> crate::Result<()> {
+        let limit = dht_export.limit as usize;
+        let after = dht_export.after.map(|after| Key {
+            raw_key: after.key.raw(),
+            replication_index: after.replication_index,
+        });
+
+        let mut keys: Vec<Key> = self
+            .storage
+            .lock_or_recover()
+            .keys()
+            .filter(|key| after.is_none_or(|after| **key > after))
+            .cloned()
+            .collect();
+
+        keys.sort_unstable();
+
+        let mut records = Vec::new();
+        let mut size = 4;
+        let mut exported = 0;
+
+        // values are copied one after another such that the storage is not
+        // locked for long, records removed in the meantime are skipped
+        for key in &keys {
+            let record = match self.storage.lock_or_recover().get(key) {
+                Some(record) => StoredRecord {
+                    key: DhtKey::from(key.raw_key),
+                    replication_index: key.replication_index,
+                    replicas: record.replicas,
+                    ttl: record.ttl_millis(),
+                    version: record.version,
+                    value: record.value.clone(),
+                },
+                None => {
+                    exported += 1;
+                    continue;
+                }
+            };
+
+            let record_size = record.encoded_size(api_con.encoding());
+
+            if 4 + record_size > DhtExportReply::MAX_PAYLOAD_SIZE {
+                warn!("Record {} is too large to be exported", key);
+
+                exported += 1;
+                continue;
+            }
+
+            if records.len() >= limit || size + record_size > DhtExportReply::MAX_PAYLOAD_SIZE {
+                break;
+            }
+
+            size += record_size;
+            records.push(record);
+            exported += 1;
+        }
+
+        let dht_export_reply = DhtExportReply {
+            remaining: (keys.len() - exported) as u32,
+            records,
+        };
+
+        info!(
+            "Exporting {} records from local storage, {} remaining",
+            dht_export_reply.records.len(),
+            dht_export_reply.remaining
+        );
+
+        api_con.send(&Message::DhtExportReply(dht_export_reply))?;
+
+        Ok(())
+    }
+
+    fn handle_dht_import(
+        &self,
+        api_con: &mut Connection,
+        dht_import: DhtImport,
+    ) -> crate::Result<()> {
+        let total = dht_import.records.len() as u32;
+
+        let stored = if self.read_only.load(Ordering::SeqCst) {
+            warn!("Refusing to import {} records while read-only", total);
+
+            0
+        } else {
+            let mut storage = self.storage.lock_or_recover();
+            let mut stored = 0;
+
+            for record in dht_import.records {
+                let (key, record) = export::into_storage(record);
+
+                if storage::put_newer(&mut storage, key, record) {
+                    stored += 1;
+                }
+            }
+
+            stored
+        };
+
+        info!(
+            "Imported {} of {} records into local storage",
+            stored, total
+        );
+
+        let dht_import_reply = DhtImportReply {
+            stored,
+            skipped: total - stored,
+        };
+        api_con.send(&Message::DhtImportReply(dht_import_reply))?;
+
+        Ok(())
+    }
+
+    fn handle_node_peers(
+        &self,
+        api_con: &mut Connection,
+        node_peers: NodePeers,
+    ) -> crate::Result<()> {
+        let limit = (node_peers.limit as usize).min(page_limit(api_con, NodePeersReply::MAX_PEERS));
+        let start = node_peers.after.map_or(0, |after| after as usize + 1);
+
+        let node_peers_reply = {
+            let routing = self.routing.lock_or_recover();
+            let now = Instant::now();
+
+            let peers: Vec<PeerEntry> = (start..routing.fingers())
+                .take(limit)
+                .filter_map(|index| {
+                    let verified = routing.finger_verified(index)?;
+                    let age = now.saturating_duration_since(verified).as_secs();
+
+                    routing.finger(index).map(|finger| PeerEntry {
+                        index: index as u16,
+                        age: age.min(u64::from(u16::MAX)) as u16,
+                        identifier: finger.identifier(),
+                        socket_addr: finger.socket_addr(),
+                    })
+                })
+                .collect();
+
+            NodePeersReply {
+                total_peers: routing.fingers() as u16,
+                remaining: routing.fingers().saturating_sub(start + peers.len()) as u16,
+                peers,
+            }
+        };
+
+        api_con.send(&Message::NodePeersReply(node_peers_reply))?;
+
+        Ok(())
+    }
+
+    fn handle_node_load(&self, api_con: &mut Connection, node_load: NodeLoad) -> crate::Result<()> {
+        let (local, peers) = {
+            let routing = self.routing.lock_or_recover();
+            let storage = self.storage.lock_or_recover();
+
+            let local = Load::local(&routing, &storage, &self.metrics.stats().snapshot());
+
+            // the successor followed by the fingers spread over the ring
+            let current = routing.current.socket_addr();
+            let mut peers: Vec<SocketAddr> = Vec::new();
+
+            for peer in iter::once(&routing.successor)
+                .chain((0..routing.fingers()).filter_map(|i| routing.finger(i)))
+                .map(|peer| peer.socket_addr())
+            {
+                if peer != current && !peers.contains(&peer) {
+                    peers.push(peer);
+                }
+            }
+
+            peers.truncate((node_load.samples as usize).min(MAX_LOAD_SAMPLES));
+
+            (local, peers)
+        };
+
+        let mut loads = vec![local];
+
+        for peer_addr in peers {
+            match self.procedures.peer_load(peer_addr) {
+                Ok(load) => loads.push(load),
+                Err(err) => warn!("Could not obtain load of peer {}: {}", peer_addr, err),
+            }
+        }
+
+        let average = Load::average(&loads).unwrap_or(local);
+        let target_weight = load::target_weight(&local, &average, self.weight);
+
+        info!(
+            "Load is {:.2} times the average of {} peers, recommending {} instead of {} peers",
+            local.ratio(&average),
+            loads.len() - 1,
+            target_weight,
+            self.weight
+        );
+
+        let node_load_reply = NodeLoadReply {
+            sampled: (loads.len() - 1) as u16,
+            weight: self.weight,
+            target_weight,
+            local,
+            average,
+        };
+        api_con.send(&Message::NodeLoadReply(node_load_reply))?;
+
+        Ok(())
+    }
+
+    fn handle_dht_ring_walk(
+        &self,
+        api_con: &mut Connection,
+        dht_ring_walk: DhtRingWalk,
+    ) -> crate::Result<()> {
+        let limit =
+            (dht_ring_walk.limit as usize).min(page_limit(api_con, DhtRingWalkReply::MAX_PEERS));
+        let start = self.routing.lock_or_recover().current.socket_addr();
+
+        let mut peers = Vec::new();
+        let mut complete = false;
+
+        // the first page begins with this peer itself
+        let mut last = match dht_ring_walk.after {
+            Some(after) => after,
+            None if limit > 0 => {
+                peers.push(RingPeer {
+                    identifier: start.identifier(),
+                    socket_addr: start,
+                });
+
+                start
+            }
+            None => start,
+        };
+
+        while peers.len() < limit {
+            let successor = match self.find_peer(last.identifier().successor_id(0)) {
+                Ok(successor) => successor,
+                // return the peers found so far, the walk can be resumed
+                Err(err) if !peers.is_empty() => {
+                    warn!("Ring walk stopped after {}: {}", last, err);
+
+                    break;
+                }
+                Err(err) => return Err(err),
+            };
+
+            if successor == start {
+                complete = true;
+
+                break;
+            }
+
+            peers.push(RingPeer {
+                identifier: successor.identifier(),
+                socket_addr: successor,
+            });
+
+            last = successor;
+        }
+
+        info!(
+            "Walked {} peers on the ring, complete: {}",
+            peers.len(),
+            complete
+        );
+
+        let dht_ring_walk_reply = DhtRingWalkReply { complete, peers };
+        api_con.send(&Message::DhtRingWalkReply(dht_ring_walk_reply))?;
+
+        Ok(())
+    }
+
+    fn handle_dht_cancel(&self, session: &Session, dht_cancel: DhtCancel) {
+        if !session.cancel_search(dht_cancel.request_id) {
+            info!(
+                "No running DHT GET with request ID {} to cancel",
+                dht_cancel.request_id
+            );
+        }
+    }
+
     fn handle_connection(&self, mut con: Connection) -> crate::Result<()> {
-        let msg = con.receive()?;
+        let session = Session::new();
+        let reader = con.try_clone()?;
+
+        // stop waiting for idle clients such that they do not occupy a worker
+        con.set_read_timeout(Some(self.timeout))?;
+
+        let (sender, queue) = mpsc::channel();
+
+        thread::scope(|scope| {
+            scope.spawn(|| self.receive_requests(&session, reader, sender));
+
+            // process the requests in order such that the replies are too
+            for (request_id, msg) in queue {
+                con.set_request_id(request_id);
+
+                if let Err(err) = self.handle_message(&session, &mut con, msg) {
+                    self.handle_error(&*err);
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn receive_requests(
+        &self,
+        session: &Session,
+        mut con: Connection,
+        queue: Sender<(Option<u32>, Message)>,
+    ) {
+        loop {
+            let msg = match con.receive() {
+                Ok(msg) => msg,
+                Err(ref err)
+                    if err.kind() == io::ErrorKind::TimedOut
+                        || err.kind() == io::ErrorKind::WouldBlock =>
+                {
+                    debug!("Closing idle api session");
+
+                    return;
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    debug!("Api session closed by client");
+
+                    break;
+                }
+                Err(err) => {
+                    self.handle_error(&err);
+
+                    break;
+                }
+            };
+
+            info!("Api handler received message of type {}", msg);
+
+            match msg {
+                // cancel right away since the search runs in the other thread
+                Message::DhtCancel(dht_cancel) => self.handle_dht_cancel(session, dht_cancel),
+                // the following requests are already sent in the new encoding
+                // and belong to the tenant
+                Message::ApiEncoding(api_encoding) => {
+                    if let Some(ref token) = api_encoding.token {
+                        if !self.authenticate(session, token) {
+                            warn!("Closing api session with an unknown token");
+
+                            break;
+                        }
+                    }
+
+                    let encoding = self.negotiate_encoding(api_encoding.encoding);
+                    con.set_encoding(encoding);
+
+                    let msg = Message::ApiEncoding(ApiEncoding {
+                        encoding,
+                        token: None,
+                    });
+
+                    if queue.send((con.request_id(), msg)).is_err() {
+                        return;
+                    }
+                }
+                msg => {
+                    if queue.send((con.request_id(), msg)).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+
+        // nobody waits for the reply to a running search anymore
+        session.close();
+    }
+
+    /// Binds `session` to the tenant with `token`.
+    ///
+    /// Fails if the token is unknown or the session belongs to a tenant
+    /// already.
+    fn authenticate(&self, session: &Session, token: &str) -> bool {
+        match self.tenants.authenticate(token) {
+            Some(tenant) => {
+                info!("Api session authenticated as tenant {}", tenant.name);
+
+                session.tenant.set(tenant.clone()).is_ok()
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether the tenant of `session` may perform `operation`.
+    fn permitted(&self, session: &Session, operation: Operation) -> bool {
+        match session.tenant.get() {
+            Some(tenant) => tenant.permits(operation),
+            None => !self.tenants.is_required(),
+        }
+    }
+
+    /// Answers a request which the authorizer denied like a failed request.
+    fn handle_denied(&self, con: &mut Connection, msg: Message) -> crate::Result<()> {
+        warn!("Denied {} request from {}", msg, con.peer_addr()?);
+
+        let reply = match msg {
+            Message::DhtGet(DhtGet { key, .. }) | Message::DhtResolve(DhtResolve { key, .. }) => {
+                Message::DhtFailure(DhtFailure { key })
+            }
+            Message::DhtPut(dht_put) if dht_put.acks > 0 => {
+                Message::DhtFailure(DhtFailure { key: dht_put.key })
+            }
+            Message::DhtDelete(dht_delete) => Message::DhtDeleteReply(DhtDeleteReply {
+                replicas: 0,
+                key: dht_delete.key,
+            }),
+            // administrative requests and unacknowledged puts have no failure reply
+            _ => return Ok(()),
+        };
 
-        info!("Api handler received message of type {}", msg);
+        con.send(&reply)?;
+
+        Ok(())
+    }
+
+    fn handle_message(
+        &self,
+        session: &Session,
+        con: &mut Connection,
+        msg: Message,
+    ) -> crate::Result<()> {
+        let operation = match msg {
+            Message::DhtGet(ref dht_get) => Some(Operation::Get(dht_get.key)),
+            Message::DhtResolve(ref dht_resolve) => Some(Operation::Get(dht_resolve.key)),
+            Message::DhtPut(ref dht_put) => Some(Operation::Put(dht_put.key)),
+            Message::DhtDelete(ref dht_delete) => Some(Operation::Delete(dht_delete.key)),
+            // the keys are authorized one by one
+            Message::ApiEncoding(_) | Message::DhtPrefetch(_) => None,
+            ref msg => Some(Operation::Admin(msg.name())),
+        };
+
+        if let Some(operation) = operation {
+            let requester = Requester::Client(con.peer_addr()?);
+
+            if !self.permitted(session, operation)
+                || !self.authorizer.authorize(requester, operation)
+            {
+                return self.handle_denied(con, msg);
+            }
+
+            if let Some(ref audit_log) = self.audit_log {
+                let size = match msg {
+                    Message::DhtPut(ref dht_put) => Some(dht_put.value.len()),
+                    Message::DhtImport(ref dht_import) => Some(
+                        dht_import
+                            .records
+                            .iter()
+                            .map(|record| record.value.len())
+                            .sum(),
+                    ),
+                    Message::DhtDelete(_)
+                    | Message::DhtFlush(_)
+                    | Message::NodeDrain(_)
+                    | Message::NodeReadOnly(_) => Some(0),
+                    _ => None,
+                };
+
+                if let Some(size) = size {
+                    audit_log.record(requester, operation, size);
+                }
+            }
+        }
 
         match msg {
-            Message::DhtGet(dht_get) => self.handle_dht_get(con, dht_get),
+            Message::DhtGet(dht_get) => self.handle_dht_get(session, con, dht_get),
             Message::DhtPut(dht_put) => self.handle_dht_put(con, dht_put),
-            _ => Err(Box::new(MessageError::new(msg))),
+            Message::DhtDelete(dht_delete) => self.handle_dht_delete(con, dht_delete),
+            Message::DhtResolve(dht_resolve) => self.handle_dht_resolve(con, dht_resolve),
+            Message::NodeInfo(node_info) => self.handle_node_info(con, node_info),
+            Message::DhtFlush(dht_flush) => self.handle_dht_flush(con, dht_flush),
+            Message::DhtListLocal(dht_list_local) => {
+                self.handle_dht_list_local(con, dht_list_local)
+            }
+            Message::DhtExport(dht_export) => self.handle_dht_export(con, dht_export),
+            Message::DhtImport(dht_import) => self.handle_dht_import(con, dht_import),
+            Message::NodePeers(node_peers) => self.handle_node_peers(con, node_peers),
+            Message::DhtRingWalk(dht_ring_walk) => self.handle_dht_ring_walk(con, dht_ring_walk),
+            Message::NodeLoad(node_load) => self.handle_node_load(con, node_load),
+            Message::NodeDrain(node_drain) => self.handle_node_drain(con, node_drain),
+            Message::NodeReadOnly(node_read_only) => {
+                self.handle_node_read_only(con, node_read_only)
+            }
+            Message::ApiEncoding(api_encoding) => self.handle_api_encoding(con, api_encoding),
+            Message::DhtPrefetch(dht_prefetch) => {
+                self.handle_dht_prefetch(session, con, dht_prefetch)
+            }
+            _ => Err(Box::new(
+                MessageError::new(msg)
+                    .with_expected("an api request")
+                    .with_peer(con.peer_addr()?),
+            )),
         }
     }
 
-    fn handle_error(&self, error: &Error) {
+    fn handle_error(&self, error: &(dyn Error + 'static)) {
+        if error.is::<DeadlineError>() {
+            self.metrics.stats().record_timeout();
+
+            debug!("Dropped api request after its deadline passed");
+
+            return;
+        }
+
+        self.metrics.stats().record_handler_error();
+
         error!("Error in ApiHandler: {}", error)
     }
 }
 
+/// Returns the maximum number of entries in a page such that the reply fits
+/// into a single message in the encoding of `con`.
+fn page_limit(con: &Connection, max_entries: usize) -> usize {
+    match con.encoding() {
+        Encoding::Binary => max_entries,
+        // the names of the fields take about as much space as their values
+        Encoding::Cbor => max_entries / 2,
+    }
+}
+
+/// Converts the reply of the replica under `key` into a `DHT SUCCESS` for
+/// `dht_key` which carries the metadata of the replica.
+fn found_value(
+    dht_key: DhtKey,
+    key: Key,
+    storage_success: StorageGetSuccess,
+) -> crate::Result<Message> {
+    let metadata = RecordMetadata {
+        ttl: storage_success.ttl,
+        version: storage_success.version,
+        replication_index: key.replication_index,
+        stored: storage_success.stored,
+    };
+
+    Ok(Message::DhtSuccess(DhtSuccess {
+        key: dht_key,
+        value: DhtValue::new(storage_success.value)?,
+        metadata: Some(metadata),
+    }))
+}
+
+/// State of a single api connection
+///
+/// A client may keep its connection open and send several requests one after
+/// another. They are received into the queue of the session by a separate
+/// thread and processed in order, while a `DHT CANCEL` is applied to the
+/// running search as soon as it arrives. Once the client closes the
+/// connection, its running search is aborted.
+///
+/// A session belongs to a tenant once the client authenticated it.
+struct Session {
+    tenant: OnceLock<Tenant>,
+    /// Running search along with its request ID
+    search: Mutex<Option<(Option<u32>, CancelToken)>>,
+    closed: AtomicBool,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            tenant: OnceLock::new(),
+            search: Mutex::new(None),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Registers a new search which is aborted once the session is closed
+    /// or a `DHT CANCEL` with its `request_id` arrives.
+    fn start_search(&self, request_id: Option<u32>) -> CancelToken {
+        let cancel_token = CancelToken::new();
+        let mut search = self.search.lock_or_recover();
+
+        if self.closed.load(Ordering::SeqCst) {
+            cancel_token.cancel();
+        }
+
+        *search = Some((request_id, cancel_token.clone()));
+
+        cancel_token
+    }
+
+    /// Aborts the running search if it has been started with `request_id`
+    /// and returns whether it has.
+    fn cancel_search(&self, request_id: u32) -> bool {
+        match *self.search.lock_or_recover() {
+            Some((Some(id), ref cancel_token)) if id == request_id => {
+                cancel_token.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn finish_search(&self) {
+        *self.search.lock_or_recover() = None;
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+
+        if let Some((_, ref cancel_token)) = *self.search.lock_or_recover() {
+            cancel_token.cancel();
+        }
+    }
+}
+
 impl ServerHandler for ApiHandler {
     fn handle_connection(&self, connection: Connection) {
+        let connection = connection.with_metrics(Arc::clone(&self.metrics));
+
         if let Err(err) = self.handle_connection(connection) {
             self.handle_error(&*err);
         }
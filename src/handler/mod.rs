@@ -2,15 +2,21 @@
 //!
 //! The structs [`ApiHandler`] and [`P2PHandler`] implement the
 //! [`ServerHandler`] trait and can be used as handlers for an instance of the
-//! [`Server`] struct.
+//! [`Server`] struct. With the `node` feature, the [`WebSocketHandler`]
+//! additionally exposes the api interface to WebSocket clients.
 //!
 //! [`ApiHandler`]: struct.ApiHandler.html
 //! [`P2PHandler`]: struct.P2PHandler.html
+//! [`WebSocketHandler`]: struct.WebSocketHandler.html
 //! [`ServerHandler`]: ../network/trait.ServerHandler.html
 //! [`Server`]: ../network/struct.Server.html
 
 pub use self::api::ApiHandler;
 pub use self::p2p::P2PHandler;
+#[cfg(feature = "node")]
+pub use self::websocket::WebSocketHandler;
 
 mod api;
 mod p2p;
+#[cfg(feature = "node")]
+mod websocket;
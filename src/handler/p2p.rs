@@ -1,56 +1,362 @@
-use crate::error::MessageError;
+use crate::audit::AuditLog;
+use crate::auth::{self, Authorizer, Operation, Requester};
+use crate::cache::{Cache, HotKeys};
+use crate::clock::{self, Clock};
+use crate::deadline::Deadline;
+use crate::dht::DhtKey;
+use crate::error::{DeadlineError, MessageError};
+use crate::load::Load;
 use crate::message::p2p::*;
 use crate::message::Message;
-use crate::network::{Connection, ServerHandler};
-use crate::routing::identifier::{Identifier, Identify};
+use crate::metrics::Metrics;
+use crate::network::{self, Connection, ServerHandler, ThreadPool};
+use crate::policy::{Policies, Violation};
+use crate::routing::identifier::{Identifier, IdentifierInterval, Identify};
+use crate::routing::peer::PeerInfo;
 use crate::routing::Routing;
-use crate::storage::Key;
-use std::collections::HashMap;
+use crate::storage::{self, Key, ReadCoalescer, Record, Storage};
+use crate::sync::MutexExt;
 use std::error::Error;
 use std::io;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-type Storage = HashMap<Key, Vec<u8>>;
+/// Values of a leaving peer received before the transfer broke off
+///
+/// The leaving peer resumes the transfer on a new connection before the
+/// values expire.
+struct PartialLeave {
+    leaving_addr: SocketAddr,
+    records: Vec<(Key, Record)>,
+    expires: Instant,
+}
+
+/// A join which is currently in progress
+///
+/// While a join is pending, the range between the old predecessor and the
+/// joining peer is locked.
+struct PendingJoin {
+    joining_addr: SocketAddr,
+    predecessor_addr: SocketAddr,
+    expires: Instant,
+}
+
+impl PendingJoin {
+    fn locks(&self, identifier: Identifier) -> bool {
+        self.range().contains_open_closed(identifier)
+    }
+
+    /// Returns the interval `(predecessor, joining]` the joining peer takes over.
+    fn range(&self) -> IdentifierInterval {
+        IdentifierInterval::new(
+            self.predecessor_addr.identifier(),
+            self.joining_addr.identifier(),
+        )
+    }
+}
+
+/// Handles to reply over a multiplexed connection
+///
+/// Every request takes a handle of its own and puts it back once replied,
+/// such that handles are only created for requests running at the same
+/// time. The connection writes the frames of all handles one at a time.
+struct Writers {
+    con: Connection,
+    spare: Mutex<Vec<Connection>>,
+}
+
+impl Writers {
+    fn new(con: Connection) -> Self {
+        Self {
+            con,
+            spare: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn take(&self) -> io::Result<Connection> {
+        match self.spare.lock_or_recover().pop() {
+            Some(con) => Ok(con),
+            None => self.con.try_clone(),
+        }
+    }
+
+    fn put_back(&self, con: Connection) {
+        self.spare.lock_or_recover().push(con);
+    }
+}
+
+/// Returns whether `msg` transfers values and belongs to the data lane.
+fn is_data(msg: &Message) -> bool {
+    matches!(
+        msg,
+        Message::StorageGet(_)
+            | Message::StoragePut(_)
+            | Message::StorageBulkPut(_)
+            | Message::StorageDelete(_)
+            | Message::StorageCachePush(_)
+    )
+}
 
 /// Handler for peer-to-peer requests
 ///
 /// The supported incoming peer-to-peer messages are `STORAGE GET`,
-/// `STORAGE PUT`, `PEER FIND`, `PREDECESSOR GET` and `PREDECESSOR SET`.
+/// `STORAGE PUT`, `STORAGE BULK PUT`, `STORAGE DELETE`, `PEER FIND`,
+/// `PREDECESSOR NOTIFY`, `JOIN LOCK`, `JOIN PUBLISH`, `PEER LEAVE`,
+/// `PEER LOAD` and `STORAGE CACHE PUSH`.
+///
+/// While the peer is read-only, for example because it is being drained,
+/// `STORAGE PUT` and `STORAGE DELETE` are answered with `STORAGE FAILURE`
+/// and the values of `STORAGE BULK PUT` are rejected.
+///
+/// A connection whose first request is wrapped in `CORRELATED` is kept open
+/// for further requests and served by a dedicated thread until it is closed
+/// or idle for the timeout.
+///
+/// Storage requests can be moved to a separate pool of workers with
+/// [`with_data_lane`] such that bulk transfers do not delay the messages
+/// which maintain the ring like `PEER FIND` and `PREDECESSOR NOTIFY`.
+/// Concurrent `STORAGE GET` requests for the same key share a single read of
+/// the storage, see [`ReadCoalescer`].
+///
+/// Storage requests which the authorizer given to [`with_authorizer`] denies
+/// are answered with `STORAGE FAILURE`. All values which are stored or
+/// removed are recorded in the log given to [`with_audit_log`]. Values
+/// which violate the policy of their namespace given to [`with_policies`]
+/// are rejected the same way.
+///
+/// With [`with_hot_keys`], the peer pushes copies of the values it is
+/// requested most to the requesting peers. It keeps the copies pushed by
+/// other peers in the cache given to [`with_cache`] and refuses them
+/// otherwise. See the [`cache`] module for details.
+///
+/// [`with_data_lane`]: #method.with_data_lane
+/// [`with_authorizer`]: #method.with_authorizer
+/// [`with_audit_log`]: #method.with_audit_log
+/// [`with_policies`]: #method.with_policies
+/// [`with_hot_keys`]: #method.with_hot_keys
+/// [`with_cache`]: #method.with_cache
+/// [`cache`]: ../cache/index.html
+/// [`ReadCoalescer`]: ../storage/struct.ReadCoalescer.html
+#[derive(Clone)]
 pub struct P2PHandler {
-    routing: Arc<Mutex<Routing<SocketAddr>>>,
-    storage: Mutex<Storage>,
+    routing: Arc<Mutex<Routing<PeerInfo>>>,
+    storage: Arc<Mutex<Storage>>,
+    pending_join: Arc<Mutex<Option<PendingJoin>>>,
+    partial_leave: Arc<Mutex<Option<PartialLeave>>>,
+    read_only: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+    rate_limit: Option<u64>,
+    data_lane: Option<ThreadPool>,
+    clock: Arc<dyn Clock>,
+    authorizer: Arc<dyn Authorizer>,
+    audit_log: Option<Arc<AuditLog>>,
+    policies: Arc<Policies>,
+    hot_keys: Option<Arc<HotKeys>>,
+    cache: Option<Arc<Cache>>,
+    reads: Arc<ReadCoalescer>,
+    timeout: u64,
 }
 
 impl P2PHandler {
     /// Creates a new `P2PHandler` instance.
-    pub fn new(routing: Arc<Mutex<Routing<SocketAddr>>>) -> Self {
-        let storage = Mutex::new(Storage::new());
+    ///
+    /// `timeout` is the time in milliseconds after which a pending join
+    /// expires if the joining peer does not publish it.
+    pub fn new(
+        routing: Arc<Mutex<Routing<PeerInfo>>>,
+        storage: Arc<Mutex<Storage>>,
+        timeout: u64,
+    ) -> Self {
+        Self::with_metrics(routing, storage, timeout, Arc::new(Metrics::new()))
+    }
+
+    /// Creates a new `P2PHandler` instance which counts its messages and
+    /// storage operations in `metrics`.
+    pub fn with_metrics(
+        routing: Arc<Mutex<Routing<PeerInfo>>>,
+        storage: Arc<Mutex<Storage>>,
+        timeout: u64,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        let pending_join = Arc::new(Mutex::new(None));
+        let read_only = Arc::new(AtomicBool::new(false));
+
+        Self {
+            routing,
+            storage,
+            pending_join,
+            partial_leave: Arc::new(Mutex::new(None)),
+            read_only,
+            metrics,
+            rate_limit: None,
+            data_lane: None,
+            clock: clock::system(),
+            authorizer: auth::allow_all(),
+            audit_log: None,
+            policies: Arc::new(Policies::new()),
+            hot_keys: None,
+            cache: None,
+            reads: Arc::new(ReadCoalescer::new()),
+            timeout,
+        }
+    }
+
+    /// Takes the time from `clock` to decide when pending joins, partial
+    /// leaves and stored records expire.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Refuses new values and deletions whenever `read_only` is set.
+    ///
+    /// The flag is shared with the [`ApiHandler`] which sets it while the
+    /// peer is drained.
+    ///
+    /// [`ApiHandler`]: struct.ApiHandler.html
+    pub fn with_read_only(mut self, read_only: Arc<AtomicBool>) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Slows down peers which exchange more than `rate_limit` bytes per
+    /// second with this peer.
+    ///
+    /// See [`Connection::with_rate_limit`] for details.
+    ///
+    /// [`Connection::with_rate_limit`]:
+    /// ../network/struct.Connection.html#method.with_rate_limit
+    pub fn with_rate_limit(mut self, rate_limit: Option<u64>) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Handles `STORAGE GET`, `STORAGE PUT`, `STORAGE BULK PUT` and
+    /// `STORAGE DELETE` on the workers of `data_lane` while all other
+    /// requests are handled on the workers of the server.
+    ///
+    /// On multiplexed connections, storage requests are handled concurrently
+    /// to the other requests such that their replies may arrive out of order.
+    pub fn with_data_lane(mut self, data_lane: ThreadPool) -> Self {
+        self.data_lane = Some(data_lane);
+        self
+    }
+
+    /// Asks `authorizer` before reading, storing or removing a value.
+    ///
+    /// See the [`auth`] module for details.
+    ///
+    /// [`auth`]: ../auth/index.html
+    pub fn with_authorizer(mut self, authorizer: Arc<dyn Authorizer>) -> Self {
+        self.authorizer = authorizer;
+        self
+    }
 
-        Self { routing, storage }
+    /// Records every value which is stored or removed in `audit_log` if it
+    /// is given.
+    pub fn with_audit_log(mut self, audit_log: Option<Arc<AuditLog>>) -> Self {
+        self.audit_log = audit_log;
+        self
+    }
+
+    /// Limits the values stored in a namespace by its policy in `policies`.
+    ///
+    /// See the [`policy`] module for details.
+    ///
+    /// [`policy`]: ../policy/index.html
+    pub fn with_policies(mut self, policies: Arc<Policies>) -> Self {
+        self.policies = policies;
+        self
+    }
+
+    /// Counts the requests for every key in `hot_keys` if it is given and
+    /// pushes copies of the hot ones to the requesting peers.
+    pub fn with_hot_keys(mut self, hot_keys: Option<Arc<HotKeys>>) -> Self {
+        self.hot_keys = hot_keys;
+        self
+    }
+
+    /// Keeps the copies of hot keys pushed by other peers in `cache` if it
+    /// is given.
+    pub fn with_cache(mut self, cache: Option<Arc<Cache>>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::SeqCst)
+    }
+
+    fn authorized(&self, con: &Connection, operation: Operation) -> bool {
+        match con.peer_addr() {
+            Ok(peer_addr) => self
+                .authorizer
+                .authorize(Requester::Peer(peer_addr), operation),
+            Err(_) => false,
+        }
+    }
+
+    fn audit(&self, con: &Connection, operation: Operation, size: usize) {
+        if let (Some(audit_log), Ok(peer_addr)) = (&self.audit_log, con.peer_addr()) {
+            audit_log.record(Requester::Peer(peer_addr), operation, size);
+        }
     }
 
     fn responsible_for(&self, identifier: Identifier) -> bool {
-        let routing = self.routing.lock().unwrap();
+        let routing = self.routing.lock_or_recover();
 
         routing.responsible_for(identifier)
     }
 
-    fn closest_peer(&self, identifier: Identifier) -> SocketAddr {
-        let routing = self.routing.lock().unwrap();
+    fn locked_for(&self, identifier: Identifier) -> bool {
+        let pending_join = self.pending_join.lock_or_recover();
+
+        pending_join
+            .as_ref()
+            .filter(|join| !self.clock.is_past(join.expires))
+            .is_some_and(|join| join.locks(identifier))
+    }
+
+    fn join_pending(&self) -> bool {
+        let pending_join = self.pending_join.lock_or_recover();
+
+        pending_join
+            .as_ref()
+            .is_some_and(|join| !self.clock.is_past(join.expires))
+    }
+
+    fn find_successor(&self, identifier: Identifier) -> (SocketAddr, bool) {
+        let routing = self.routing.lock_or_recover();
+        let (peer, authoritative) = routing.find_successor(identifier);
+
+        (peer.socket_addr(), authoritative)
+    }
+
+    fn current_addr(&self) -> SocketAddr {
+        let routing = self.routing.lock_or_recover();
 
-        **routing.closest_peer(identifier)
+        routing.current.socket_addr()
     }
 
     fn notify_predecessor(&self, predecessor_addr: SocketAddr) -> SocketAddr {
-        let mut routing = self.routing.lock().unwrap();
+        let mut routing = self.routing.lock_or_recover();
 
-        let old_predecessor_addr = *routing.predecessor;
+        let old_predecessor_addr = routing.predecessor.socket_addr();
+
+        // a pending join will update the predecessor when it is published
+        if self.join_pending() {
+            return old_predecessor_addr;
+        }
+
+        let peer = routing.peer(predecessor_addr);
 
         // 1. check if the predecessor is closer than the previous predecessor
-        if routing.responsible_for(predecessor_addr.identifier()) {
+        if routing.responsible_for(peer.identifier()) {
             // 2. update the predecessor if necessary
-            routing.set_predecessor(predecessor_addr);
+            routing.set_predecessor(peer.clone());
 
             info!("Updated predecessor to new address {}", predecessor_addr);
 
@@ -58,16 +364,16 @@ impl P2PHandler {
             // TODO give data to new predecessor!!!
         }
 
-        if *routing.predecessor == *routing.current {
+        if routing.predecessor.socket_addr() == routing.current.socket_addr() {
             // if predecessor points to ourselves, update it to this peer.
-            routing.set_predecessor(predecessor_addr);
+            routing.set_predecessor(peer.clone());
 
             info!("Updated predecessor to new address {}", predecessor_addr);
         }
 
-        if *routing.successor == *routing.current {
+        if routing.successor.socket_addr() == routing.current.socket_addr() {
             // If successor points to ourselves, update it to this peer.
-            routing.set_successor(predecessor_addr);
+            routing.set_successor(peer);
 
             info!("Updated successor to new address {}", predecessor_addr);
         }
@@ -75,27 +381,71 @@ impl P2PHandler {
         old_predecessor_addr
     }
 
-    fn get_from_storage(&self, key: Key) -> Option<Vec<u8>> {
-        let storage = self.storage.lock().unwrap();
+    fn get_from_storage(&self, key: Key) -> Option<Record> {
+        let (record, coalesced) = self.reads.get(&self.storage, key);
+
+        self.metrics.stats().record_storage_get();
+
+        if coalesced {
+            debug!("Shared the read of key {} with another request", key);
+        }
 
-        storage.get(&key).map(Vec::clone)
+        // expired records are only removed by the next sweep
+        let now = self.clock.now();
+        record.filter(|record| !record.is_expired_at(now))
     }
 
-    fn put_to_storage(&self, key: Key, value: Vec<u8>) -> bool {
-        let mut storage = self.storage.lock().unwrap();
+    /// Applies the policy of the namespace of `key` to `storage_put` and
+    /// stores the resulting record unless an unexpired value exists already.
+    ///
+    /// The quota is checked while holding the same storage lock as for the
+    /// insert such that concurrent writes cannot exceed it together.
+    fn put_to_storage(&self, key: Key, mut storage_put: StoragePut) -> Result<bool, Violation> {
+        let now = self.clock.now();
+        let policy = self.policies.policy_for(&DhtKey::from(key.raw_key));
+        let mut storage = self.storage.lock_or_recover();
+
+        if let Some((namespace, policy)) = policy {
+            let used = match policy.quota {
+                Some(_) => {
+                    // the value about to be replaced does not count
+                    let replaced = storage.get(&key).map_or(0, |stored| stored.value.len());
+
+                    storage
+                        .namespace_size(namespace)
+                        .saturating_sub(replaced as u64)
+                }
+                None => 0,
+            };
+
+            policy.check(key.replication_index, storage_put.value.len(), used)?;
+
+            let ttl = policy.cap_ttl(Duration::from_millis(storage_put.ttl));
+            storage_put.ttl = ttl.as_millis() as u64;
+            storage_put.replicas = policy.cap_replicas(storage_put.replicas);
+        }
 
-        if storage.contains_key(&key) {
-            return false;
+        if storage
+            .get(&key)
+            .is_some_and(|stored| !stored.is_expired_at(now))
+        {
+            return Ok(false);
         }
 
-        storage.insert(key, value);
+        storage.insert(key, Record::from_storage_put(storage_put, now));
+
+        self.metrics.stats().record_storage_put();
+
+        if let Some(ref hot_keys) = self.hot_keys {
+            hot_keys.invalidate(key);
+        }
 
-        true
+        Ok(true)
     }
 
     fn handle_storage_get(
         &self,
-        mut con: Connection,
+        con: &mut Connection,
         storage_get: StorageGet,
     ) -> crate::Result<()> {
         let raw_key = storage_get.raw_key;
@@ -108,29 +458,144 @@ impl P2PHandler {
 
         info!("Received STORAGE GET request for key {}", key);
 
+        // drop the request if the budget is already spent
+        if let Some(budget) = storage_get.budget {
+            Deadline::from_budget(budget).check()?;
+        }
+
         // 1. check if given key falls into range
         if self.responsible_for(key.identifier()) {
-            // 2. find value for given key
-            let value_opt = self.get_from_storage(key);
+            let targets = match self.hot_keys {
+                Some(ref hot_keys) => hot_keys.record(key, storage_get.requester),
+                None => Vec::new(),
+            };
+            let mut hot_record = None;
 
-            let msg = if let Some(value) = value_opt {
+            // 2. find value for given key if the peer may read it
+            let msg = if !self.authorized(con, Operation::Get(DhtKey::from(raw_key))) {
                 info!(
-                    "Found value for key {} and replying with STORAGE GET SUCCESS",
+                    "Reading key {} is denied, thus replying with STORAGE FAILURE",
                     key
                 );
 
-                Message::StorageGetSuccess(StorageGetSuccess { raw_key, value })
+                Message::StorageFailure(StorageFailure {
+                    raw_key,
+                    reason: Some(FailureReason::Denied),
+                })
+            } else if let Some(record) = self.get_from_storage(key) {
+                if storage_get
+                    .if_none_match
+                    .is_some_and(|version| record.version <= version)
+                {
+                    info!(
+                        "Value for key {} is not modified and replying with STORAGE FAILURE",
+                        key
+                    );
+
+                    Message::StorageFailure(StorageFailure {
+                        raw_key,
+                        reason: Some(FailureReason::NotModified),
+                    })
+                } else {
+                    info!(
+                        "Found value for key {} and replying with STORAGE GET SUCCESS",
+                        key
+                    );
+
+                    if !targets.is_empty() {
+                        hot_record = Some(record.clone());
+                    }
+
+                    Message::StorageGetSuccess(StorageGetSuccess {
+                        raw_key,
+                        version: record.version,
+                        ttl: record.ttl_at(self.clock.now()).as_millis() as u64,
+                        stored: record.stored,
+                        value: record.value.clone(),
+                    })
+                }
             } else {
                 info!(
                     "Did not find value for key {} and replying with STORAGE FAILURE",
                     key
                 );
 
-                Message::StorageFailure(StorageFailure { raw_key })
+                Message::StorageFailure(StorageFailure {
+                    raw_key,
+                    reason: Some(FailureReason::NotFound),
+                })
             };
 
             // 3. reply with STORAGE GET SUCCESS or STORAGE FAILURE
-            con.send(&msg)?
+            con.send(&msg)?;
+
+            // 4. push copies of a hot key once the requester has its reply
+            if let (Some(hot_keys), Some(record)) = (&self.hot_keys, hot_record) {
+                hot_keys.push(&targets, key, &record);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_storage_delete(
+        &self,
+        con: &mut Connection,
+        storage_delete: StorageDelete,
+    ) -> crate::Result<()> {
+        let raw_key = storage_delete.raw_key;
+        let replication_index = storage_delete.replication_index;
+
+        let key = Key {
+            raw_key,
+            replication_index,
+        };
+
+        info!("Received STORAGE DELETE request for key {}", key);
+
+        // 1. check if given key falls into range
+        if self.responsible_for(key.identifier()) {
+            // 2. remove value for given key unless its range is being transferred
+            let reason = if !self.authorized(con, Operation::Delete(DhtKey::from(raw_key))) {
+                Some(FailureReason::Denied)
+            } else if self.locked_for(key.identifier()) {
+                Some(FailureReason::Locked)
+            } else if self.is_read_only() {
+                Some(FailureReason::ReadOnly)
+            } else if self.storage.lock_or_recover().remove(&key).is_none() {
+                Some(FailureReason::NotFound)
+            } else {
+                None
+            };
+
+            let msg = if let Some(reason) = reason {
+                info!(
+                    "Could not remove value for key {} ({}) and replying with STORAGE FAILURE",
+                    key, reason
+                );
+
+                Message::StorageFailure(StorageFailure {
+                    raw_key,
+                    reason: Some(reason),
+                })
+            } else {
+                self.metrics.stats().record_storage_delete();
+                self.audit(con, Operation::Delete(DhtKey::from(raw_key)), 0);
+
+                if let Some(ref hot_keys) = self.hot_keys {
+                    hot_keys.invalidate(key);
+                }
+
+                info!(
+                    "Removed value for key {} and replying with STORAGE DELETE SUCCESS",
+                    key
+                );
+
+                Message::StorageDeleteSuccess(StorageDeleteSuccess { raw_key })
+            };
+
+            // 3. reply with STORAGE DELETE SUCCESS or STORAGE FAILURE
+            con.send(&msg)?;
         }
 
         Ok(())
@@ -138,7 +603,7 @@ impl P2PHandler {
 
     fn handle_storage_put(
         &self,
-        mut con: Connection,
+        con: &mut Connection,
         storage_put: StoragePut,
     ) -> crate::Result<()> {
         let raw_key = storage_put.raw_key;
@@ -151,23 +616,76 @@ impl P2PHandler {
 
         info!("Received STORAGE PUT request for key {}", key);
 
+        let size = storage_put.value.len();
+
         // 1. check if given key falls into range
         if self.responsible_for(key.identifier()) {
-            // 2. save value for given key
-            let msg = if self.put_to_storage(key, storage_put.value) {
+            // 2. save value for given key unless its range is being transferred
+            let msg = if !self.authorized(con, Operation::Put(DhtKey::from(raw_key))) {
                 info!(
-                    "Stored value for key {} and replying with STORAGE PUT SUCCESS",
+                    "Storing key {} is denied, thus replying with STORAGE FAILURE",
                     key
                 );
 
-                Message::StoragePutSuccess(StoragePutSuccess { raw_key })
-            } else {
+                Message::StorageFailure(StorageFailure {
+                    raw_key,
+                    reason: Some(FailureReason::Denied),
+                })
+            } else if self.locked_for(key.identifier()) {
                 info!(
-                    "Value for key {} already exists, thus replying with STORAGE FAILURE",
+                    "Key {} is locked by a pending join, thus replying with STORAGE FAILURE",
                     key
                 );
 
-                Message::StorageFailure(StorageFailure { raw_key })
+                Message::StorageFailure(StorageFailure {
+                    raw_key,
+                    reason: Some(FailureReason::Locked),
+                })
+            } else if self.is_read_only() {
+                info!(
+                    "Peer is read-only, thus replying with STORAGE FAILURE for key {}",
+                    key
+                );
+
+                Message::StorageFailure(StorageFailure {
+                    raw_key,
+                    reason: Some(FailureReason::ReadOnly),
+                })
+            } else {
+                match self.put_to_storage(key, storage_put) {
+                    Err(violation) => {
+                        info!(
+                            "Rejecting key {} by its namespace policy ({}) with STORAGE FAILURE",
+                            key, violation
+                        );
+
+                        Message::StorageFailure(StorageFailure {
+                            raw_key,
+                            reason: Some(FailureReason::PolicyViolated),
+                        })
+                    }
+                    Ok(true) => {
+                        self.audit(con, Operation::Put(DhtKey::from(raw_key)), size);
+
+                        info!(
+                            "Stored value for key {} and replying with STORAGE PUT SUCCESS",
+                            key
+                        );
+
+                        Message::StoragePutSuccess(StoragePutSuccess { raw_key })
+                    }
+                    Ok(false) => {
+                        info!(
+                            "Value for key {} already exists, thus replying with STORAGE FAILURE",
+                            key
+                        );
+
+                        Message::StorageFailure(StorageFailure {
+                            raw_key,
+                            reason: Some(FailureReason::Exists),
+                        })
+                    }
+                }
             };
 
             // 3. reply with STORAGE PUT SUCCESS or STORAGE FAILURE
@@ -177,20 +695,95 @@ impl P2PHandler {
         Ok(())
     }
 
-    fn handle_peer_find(&self, mut con: Connection, peer_find: PeerFind) -> crate::Result<()> {
+    fn handle_storage_bulk_put(
+        &self,
+        con: &mut Connection,
+        storage_bulk_put: StorageBulkPut,
+    ) -> crate::Result<()> {
+        info!(
+            "Received STORAGE BULK PUT request with {} values",
+            storage_bulk_put.values.len()
+        );
+
+        let mut rejected = Vec::new();
+
+        for storage_put in storage_bulk_put.values {
+            let key = Key {
+                raw_key: storage_put.raw_key,
+                replication_index: storage_put.replication_index,
+            };
+
+            // 1. reject values outside of the range, in a locked range, while
+            // the peer is read-only or if storing them is denied
+            if !self.responsible_for(key.identifier())
+                || self.locked_for(key.identifier())
+                || self.is_read_only()
+                || !self.authorized(con, Operation::Put(DhtKey::from(storage_put.raw_key)))
+            {
+                debug!("Rejecting value for key {}", key);
+
+                rejected.push(TransferCursor::from(key));
+                continue;
+            }
+
+            // 2. save value unless it violates the policy of its namespace
+            // or a value exists already
+            let size = storage_put.value.len();
+
+            match self.put_to_storage(key, storage_put) {
+                Ok(true) => self.audit(con, Operation::Put(DhtKey::from(key.raw_key)), size),
+                Ok(false) => debug!("Value for key {} already exists", key),
+                Err(violation) => {
+                    debug!("Rejecting value for key {} ({})", key, violation);
+
+                    rejected.push(TransferCursor::from(key));
+                }
+            }
+        }
+
+        info!(
+            "Replying with STORAGE BULK PUT REPLY rejecting {} values",
+            rejected.len()
+        );
+
+        // 3. reply with the keys of all rejected values
+        con.send(&Message::StorageBulkPutReply(StorageBulkPutReply {
+            rejected,
+        }))?;
+
+        Ok(())
+    }
+
+    fn handle_peer_find(&self, con: &mut Connection, peer_find: PeerFind) -> crate::Result<()> {
         let identifier = peer_find.identifier;
 
         info!("Received PEER FIND request for identifier {}", identifier);
 
-        // 1. check if given key falls into range
-        let socket_addr = self.closest_peer(identifier);
+        // drop the request if the budget is already spent
+        if let Some(budget) = peer_find.budget {
+            Deadline::from_budget(budget).check()?;
+        }
 
-        info!("Replying with PEER FOUND with address {}", socket_addr);
+        // 1. find the successor of the identifier as far as this node knows
+        let (socket_addr, authoritative) = self.find_successor(identifier);
 
-        // 2. reply with PEER FOUND either with this node or the best next node
+        info!(
+            "Replying with PEER FOUND with address {} (authoritative: {})",
+            socket_addr, authoritative
+        );
+
+        // 2. append this node to the trace if the lookup is traced
+        let trace = peer_find.trace.map(|mut trace| {
+            trace.push(self.current_addr());
+            trace
+        });
+
+        // 3. reply with PEER FOUND either with this node or the best next node
         let peer_found = PeerFound {
             identifier,
             socket_addr,
+            authoritative,
+            trace,
         };
         con.send(&Message::PeerFound(peer_found))?;
 
@@ -199,7 +792,7 @@ impl P2PHandler {
 
     fn handle_predecessor_notify(
         &self,
-        mut con: Connection,
+        con: &mut Connection,
         predecessor_notify: PredecessorNotify,
     ) -> crate::Result<()> {
         let predecessor_addr = predecessor_notify.socket_addr;
@@ -220,29 +813,545 @@ impl P2PHandler {
         Ok(())
     }
 
+    fn handle_storage_cache_push(
+        &self,
+        con: &mut Connection,
+        storage_cache_push: StorageCachePush,
+    ) -> crate::Result<()> {
+        let raw_key = storage_cache_push.raw_key;
+        let key = Key {
+            raw_key,
+            replication_index: storage_cache_push.replication_index,
+        };
+
+        info!("Received STORAGE CACHE PUSH for key {}", key);
+
+        let msg = match self.cache {
+            Some(ref cache) if self.authorized(con, Operation::Put(DhtKey::from(raw_key))) => {
+                let mut record = Record::new_at(
+                    storage_cache_push.value,
+                    Duration::from_millis(storage_cache_push.ttl),
+                    storage_cache_push.version,
+                    self.clock.now(),
+                );
+                record.stored = storage_cache_push.stored;
+
+                cache.insert(key, record);
+
+                Message::StoragePutSuccess(StoragePutSuccess { raw_key })
+            }
+            _ => {
+                info!(
+                    "Not caching key {}, thus replying with STORAGE FAILURE",
+                    key
+                );
+
+                Message::StorageFailure(StorageFailure {
+                    raw_key,
+                    reason: Some(FailureReason::Denied),
+                })
+            }
+        };
+
+        con.send(&msg)?;
+
+        Ok(())
+    }
+
+    fn handle_peer_load(&self, con: &mut Connection, _peer_load: PeerLoad) -> crate::Result<()> {
+        let load = {
+            let routing = self.routing.lock_or_recover();
+            let storage = self.storage.lock_or_recover();
+
+            Load::local(&routing, &storage, &self.metrics.stats().snapshot())
+        };
+
+        info!("Replying with PEER LOAD REPLY");
+
+        con.send(&Message::PeerLoadReply(PeerLoadReply { load }))?;
+
+        Ok(())
+    }
+
+    fn handle_join_lock(&self, con: &mut Connection, join_lock: JoinLock) -> crate::Result<()> {
+        let joining_addr = join_lock.socket_addr;
+
+        info!("Received JOIN LOCK request from {}", joining_addr);
+
+        // 1. check if the joining peer falls into our range
+        let (current_addr, predecessor_addr) = {
+            let routing = self.routing.lock_or_recover();
+            let identifier = joining_addr.identifier();
+
+            if !routing.responsible_for(identifier) {
+                let mut socket_addr = routing.closest_peer(identifier).socket_addr();
+
+                if socket_addr == routing.current.socket_addr() {
+                    socket_addr = routing.predecessor.socket_addr();
+                }
+
+                info!(
+                    "Not responsible, replying with JOIN NACK and address {}",
+                    socket_addr
+                );
+
+                con.send(&Message::JoinNack(JoinNack {
+                    socket_addr,
+                    collision: false,
+                }))?;
+
+                return Ok(());
+            }
+
+            // the joining peer would take over our identifier
+            if routing.current.identifier() == identifier
+                && routing.current.socket_addr() != joining_addr
+            {
+                let socket_addr = routing.current.socket_addr();
+
+                warn!(
+                    "Identifier of {} collides with this peer, replying with JOIN NACK",
+                    joining_addr
+                );
+
+                con.send(&Message::JoinNack(JoinNack {
+                    socket_addr,
+                    collision: true,
+                }))?;
+
+                return Ok(());
+            }
+
+            (
+                routing.current.socket_addr(),
+                routing.predecessor.socket_addr(),
+            )
+        };
+
+        // 2. lock the range unless another peer is joining right now
+        {
+            let mut pending_join = self.pending_join.lock_or_recover();
+
+            let busy = pending_join.as_ref().is_some_and(|join| {
+                join.joining_addr != joining_addr && !self.clock.is_past(join.expires)
+            });
+
+            if busy {
+                info!("Another join is pending, replying with JOIN NACK");
+
+                let socket_addr = current_addr;
+                con.send(&Message::JoinNack(JoinNack {
+                    socket_addr,
+                    collision: false,
+                }))?;
+
+                return Ok(());
+            }
+
+            *pending_join = Some(PendingJoin {
+                joining_addr,
+                predecessor_addr,
+                expires: self.clock.deadline(Duration::from_millis(self.timeout)),
+            });
+        }
+
+        // 3. collect all values the joining peer becomes responsible for
+        let range =
+            IdentifierInterval::new(predecessor_addr.identifier(), joining_addr.identifier());
+
+        let mut records = storage::snapshot(&self.storage, |key| {
+            range.contains_open_closed(key.identifier())
+        });
+
+        // transfer in the order of the keys such that a broken transfer can
+        // be resumed after the last key received
+        records.sort_unstable_by_key(|(key, _)| *key);
+
+        if let Some(resume_after) = join_lock.resume_after {
+            let resume_after = Key::from(resume_after);
+            records.retain(|(key, _)| *key > resume_after);
+
+            info!(
+                "Resuming transfer to {} after key {}",
+                joining_addr, resume_after
+            );
+        }
+
+        info!(
+            "Replying with JOIN ACK and transferring {} values",
+            records.len()
+        );
+
+        // 4. reply with JOIN ACK and transfer values with STORAGE BULK PUT
+        let join_ack = JoinAck {
+            socket_addr: predecessor_addr,
+            records: records.len() as u32,
+        };
+        con.send(&Message::JoinAck(join_ack))?;
+
+        let now = self.clock.now();
+        let values = records
+            .into_iter()
+            .map(|(key, record)| record.into_storage_put(key, now));
+
+        for msg in StorageBulkPut::pack(values) {
+            con.send(&msg)?;
+        }
+
+        // 5. wait for JOIN PUBLISH on the same connection
+        let msg = con.receive()?;
+
+        if let Message::JoinPublish(join_publish) = msg {
+            self.handle_join_publish(con, join_publish)
+        } else {
+            Err(Box::new(
+                MessageError::new(msg)
+                    .with_expected("JOIN PUBLISH")
+                    .with_peer(joining_addr)
+                    .with_operation("join"),
+            ))
+        }
+    }
+
+    fn handle_join_publish(
+        &self,
+        con: &mut Connection,
+        join_publish: JoinPublish,
+    ) -> crate::Result<()> {
+        let joining_addr = join_publish.socket_addr;
+
+        info!("Received JOIN PUBLISH request from {}", joining_addr);
+
+        // 1. release the lock if it is still held by the joining peer
+        let pending_join = {
+            let mut pending_join = self.pending_join.lock_or_recover();
+
+            match pending_join.take() {
+                Some(join) if join.joining_addr == joining_addr => Some(join),
+                other => {
+                    *pending_join = other;
+                    None
+                }
+            }
+        };
+
+        let join = match pending_join {
+            Some(ref join) if !self.clock.is_past(join.expires) => join,
+            _ => {
+                warn!(
+                    "Join of {} is not pending, replying with JOIN NACK",
+                    joining_addr
+                );
+
+                let socket_addr = self.routing.lock_or_recover().current.socket_addr();
+                con.send(&Message::JoinNack(JoinNack {
+                    socket_addr,
+                    collision: false,
+                }))?;
+
+                return Ok(());
+            }
+        };
+
+        // 2. update routing information
+        {
+            let mut routing = self.routing.lock_or_recover();
+            let peer = routing.peer(joining_addr);
+
+            routing.set_predecessor(peer.clone());
+
+            if routing.successor.socket_addr() == routing.current.socket_addr() {
+                routing.set_successor(peer);
+            }
+        }
+
+        info!("Updated predecessor to new address {}", joining_addr);
+
+        // 3. remove the values which have been transferred
+        {
+            let mut storage = self.storage.lock_or_recover();
+
+            storage.retain(|key, _| !join.locks(key.identifier()));
+        }
+
+        // 4. confirm the join with PREDECESSOR REPLY
+        let predecessor_reply = PredecessorReply {
+            socket_addr: joining_addr,
+        };
+        con.send(&Message::PredecessorReply(predecessor_reply))?;
+
+        Ok(())
+    }
+
+    fn handle_peer_leave(&self, con: &mut Connection, peer_leave: PeerLeave) -> crate::Result<()> {
+        let leaving_addr = peer_leave.socket_addr;
+
+        info!("Received PEER LEAVE request from {}", leaving_addr);
+
+        // 1. continue a transfer of the same peer which broke off
+        let mut records = Vec::new();
+
+        if peer_leave.resume {
+            let partial_leave = self
+                .partial_leave
+                .lock_or_recover()
+                .take()
+                .filter(|partial| partial.leaving_addr == leaving_addr)
+                .filter(|partial| !self.clock.is_past(partial.expires));
+
+            if let Some(partial_leave) = partial_leave {
+                records = partial_leave.records;
+            }
+
+            info!("Resuming transfer after {} values", records.len());
+
+            let resume_after = records.last().map(|(key, _)| TransferCursor::from(*key));
+            con.send(&Message::TransferAck(TransferAck { resume_after }))?;
+        }
+
+        // 2. receive the remaining values of the leaving peer
+        while records.len() < peer_leave.records as usize {
+            let msg = match con.receive() {
+                Ok(msg) => msg,
+                Err(err) => {
+                    // keep the values for a resumed transfer
+                    *self.partial_leave.lock_or_recover() = Some(PartialLeave {
+                        leaving_addr,
+                        records,
+                        expires: self.clock.deadline(Duration::from_millis(self.timeout)),
+                    });
+
+                    return Err(Box::new(err));
+                }
+            };
+
+            let values = msg.into_values().map_err(|msg| {
+                MessageError::new(*msg)
+                    .with_expected("STORAGE PUT or STORAGE BULK PUT")
+                    .with_peer(leaving_addr)
+                    .with_operation("leave")
+            })?;
+
+            for storage_put in values {
+                let key = Key {
+                    raw_key: storage_put.raw_key,
+                    replication_index: storage_put.replication_index,
+                };
+
+                let record = Record::from_storage_put(storage_put, self.clock.now());
+
+                records.push((key, record));
+            }
+        }
+
+        // 3. take over the values unless a newer version is stored already,
+        // versions from clocks running ahead have been clamped on receipt
+        {
+            let mut storage = self.storage.lock_or_recover();
+
+            for (key, record) in records {
+                if storage
+                    .get(&key)
+                    .is_none_or(|stored| stored.version < record.version)
+                {
+                    storage.insert(key, record);
+
+                    self.metrics.stats().record_storage_put();
+                }
+            }
+        }
+
+        // 4. bypass the leaving peer in the routing table
+        let socket_addr = {
+            let mut routing = self.routing.lock_or_recover();
+
+            if routing.predecessor.socket_addr() == leaving_addr {
+                info!(
+                    "Updating predecessor to address {}",
+                    peer_leave.predecessor_addr
+                );
+
+                let peer = routing.peer(peer_leave.predecessor_addr);
+                routing.set_predecessor(peer);
+            }
+
+            if routing.successor.socket_addr() == leaving_addr {
+                info!(
+                    "Updating successor to address {}",
+                    peer_leave.successor_addr
+                );
+
+                let peer = routing.peer(peer_leave.successor_addr);
+                routing.set_successor(peer);
+            }
+
+            routing.replace_finger(leaving_addr, peer_leave.successor_addr);
+
+            routing.predecessor.socket_addr()
+        };
+
+        // 5. confirm with PREDECESSOR REPLY
+        let predecessor_reply = PredecessorReply { socket_addr };
+        con.send(&Message::PredecessorReply(predecessor_reply))?;
+
+        Ok(())
+    }
+
     fn handle_connection(&self, mut con: Connection) -> crate::Result<()> {
         let msg = con.receive()?;
 
+        // multiplexed connections stay open and would block a worker
+        if con.request_id().is_some() {
+            let handler = self.clone();
+            let context = con.local_addr()?.to_string();
+            thread::Builder::new()
+                .name("p2p-multiplexed".to_string())
+                .spawn(move || {
+                    network::set_thread_context(Some(context));
+                    handler.handle_multiplexed(con, msg)
+                })?;
+
+            return Ok(());
+        }
+
+        match self.data_lane {
+            Some(ref data_lane) if is_data(&msg) => {
+                let handler = self.clone();
+                let context = con.local_addr()?.to_string();
+                data_lane.execute(move || {
+                    network::set_thread_context(Some(context));
+
+                    if let Err(err) = handler.handle_message(&mut con, msg) {
+                        handler.handle_error(&*err);
+                    }
+                });
+
+                Ok(())
+            }
+            _ => self.handle_message(&mut con, msg),
+        }
+    }
+
+    /// Handles requests on a multiplexed connection until the remote peer
+    /// closes it or it has been idle for the timeout.
+    ///
+    /// Requests are read one after another and handled in order unless they
+    /// are passed to the data lane. Replies are sent over handles of their
+    /// own such that a slow request does not hold up the replies of others.
+    fn handle_multiplexed(&self, mut con: Connection, mut msg: Message) {
+        if let Err(err) = con.set_read_timeout(Some(self.timeout)) {
+            self.handle_error(&err);
+
+            return;
+        }
+
+        let writer = match con.try_clone() {
+            Ok(writer) => Arc::new(Writers::new(writer)),
+            Err(err) => {
+                self.handle_error(&err);
+
+                return;
+            }
+        };
+
+        loop {
+            let request_id = con.request_id();
+
+            match self.data_lane {
+                Some(ref data_lane) if is_data(&msg) => {
+                    let handler = self.clone();
+                    let writer = Arc::clone(&writer);
+                    let context = con.local_addr().map(|addr| addr.to_string()).ok();
+                    data_lane.execute(move || {
+                        network::set_thread_context(context);
+                        handler.handle_correlated(&writer, request_id, msg);
+                    });
+                }
+                _ => self.handle_correlated(&writer, request_id, msg),
+            }
+
+            msg = match con.receive() {
+                Ok(msg) => msg,
+                Err(err) => {
+                    debug!("Multiplexed connection closed: {}", err);
+
+                    return;
+                }
+            };
+        }
+    }
+
+    /// Handles a request of a multiplexed connection and replies with its
+    /// `request_id`.
+    fn handle_correlated(&self, writer: &Writers, request_id: Option<u32>, msg: Message) {
+        let mut con = match writer.take() {
+            Ok(con) => con,
+            Err(err) => {
+                self.handle_error(&err);
+
+                return;
+            }
+        };
+        con.set_request_id(request_id);
+
+        if let Err(err) = self.handle_message(&mut con, msg) {
+            self.handle_error(&*err);
+        }
+
+        writer.put_back(con);
+    }
+
+    fn handle_message(&self, con: &mut Connection, msg: Message) -> crate::Result<()> {
         info!("P2P handler received message of type {}", msg);
 
         match msg {
             Message::StorageGet(storage_get) => self.handle_storage_get(con, storage_get),
             Message::StoragePut(storage_put) => self.handle_storage_put(con, storage_put),
+            Message::StorageBulkPut(storage_bulk_put) => {
+                self.handle_storage_bulk_put(con, storage_bulk_put)
+            }
+            Message::StorageDelete(storage_delete) => {
+                self.handle_storage_delete(con, storage_delete)
+            }
             Message::PeerFind(peer_find) => self.handle_peer_find(con, peer_find),
             Message::PredecessorNotify(predecessor_notify) => {
                 self.handle_predecessor_notify(con, predecessor_notify)
             }
-            _ => Err(Box::new(MessageError::new(msg))),
+            Message::JoinLock(join_lock) => self.handle_join_lock(con, join_lock),
+            Message::PeerLeave(peer_leave) => self.handle_peer_leave(con, peer_leave),
+            Message::PeerLoad(peer_load) => self.handle_peer_load(con, peer_load),
+            Message::StorageCachePush(storage_cache_push) => {
+                self.handle_storage_cache_push(con, storage_cache_push)
+            }
+            _ => Err(Box::new(
+                MessageError::new(msg)
+                    .with_expected("a peer-to-peer request")
+                    .with_peer(con.peer_addr()?),
+            )),
         }
     }
 
-    fn handle_error(&self, error: &Error) {
+    fn handle_error(&self, error: &(dyn Error + 'static)) {
+        if error.is::<DeadlineError>() {
+            self.metrics.stats().record_timeout();
+
+            debug!("Dropped p2p request after its deadline passed");
+
+            return;
+        }
+
+        self.metrics.stats().record_handler_error();
+
         error!("Error in P2PHandler: {}", error)
     }
 }
 
 impl ServerHandler for P2PHandler {
     fn handle_connection(&self, connection: Connection) {
+        let connection = connection
+            .with_metrics(Arc::clone(&self.metrics))
+            .with_rate_limit(self.rate_limit);
+
         if let Err(err) = self.handle_connection(connection) {
             self.handle_error(&*err);
         }
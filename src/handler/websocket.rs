@@ -0,0 +1,189 @@
+use crate::client::ApiClient;
+use crate::dht::{DhtKey, DhtValue};
+use crate::error::MessageError;
+use crate::message::Message;
+use crate::network::{Connection, ServerHandler};
+use crate::websocket::{Opcode, WebSocket};
+use serde_json::{json, Value};
+use std::io;
+use std::io::Cursor;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Handler for api requests over WebSocket
+///
+/// Binary frames carry exactly one api message in the usual binary format
+/// which is forwarded to the api interface of the peer. The reply, if the
+/// request has one, is returned in a binary frame as well.
+///
+/// Text frames carry a JSON object with a `type` of `put`, `get`, `delete`
+/// or `resolve`. Keys and values are encoded as standard base64:
+///
+/// ```text
+/// {"type": "put", "key": "AQI=", "value": "aGVsbG8=", "ttl": 60, "replication": 2}
+/// {"type": "get", "key": "AQI="}
+/// {"type": "delete", "key": "AQI=", "replication": 2}
+/// {"type": "resolve", "key": "AQI="}
+/// ```
+///
+/// Each request is answered with a JSON object whose `type` is `success`,
+/// `failure` if the DHT could not complete it, or `error` if the request
+/// itself was invalid.
+pub struct WebSocketHandler {
+    client: ApiClient,
+    api_address: SocketAddr,
+    timeout: u64,
+}
+
+impl WebSocketHandler {
+    /// Creates a new `WebSocketHandler` forwarding requests to the api
+    /// interface listening on `api_address`.
+    pub fn new(api_address: SocketAddr, timeout: u64) -> Self {
+        let client = ApiClient::new(api_address, timeout);
+
+        Self {
+            client,
+            api_address,
+            timeout,
+        }
+    }
+
+    fn handle_binary(&self, payload: &[u8]) -> crate::Result<Option<Vec<u8>>> {
+        let msg = Message::parse(Cursor::new(payload))?;
+
+        let expects_reply = match msg {
+            Message::DhtPut(ref dht_put) => dht_put.acks > 0,
+            Message::DhtCancel(_) | Message::DhtPrefetch(_) => false,
+            Message::DhtGet(_)
+            | Message::DhtDelete(_)
+            | Message::DhtResolve(_)
+            | Message::NodeInfo(_)
+            | Message::NodeLoad(_)
+            | Message::DhtFlush(_)
+            | Message::DhtListLocal(_)
+            | Message::NodePeers(_)
+            | Message::DhtRingWalk(_)
+            | Message::DhtExport(_)
+            | Message::DhtImport(_)
+            | Message::NodeDrain(_)
+            | Message::NodeReadOnly(_) => true,
+            _ => {
+                return Err(Box::new(
+                    MessageError::new(msg).with_expected("an api request"),
+                ))
+            }
+        };
+
+        let mut api_con = Connection::open(self.api_address, self.timeout)?;
+        api_con.send(&msg)?;
+
+        if !expects_reply {
+            return Ok(None);
+        }
+
+        let reply = api_con.receive()?;
+
+        let mut buffer = Cursor::new(Vec::new());
+        reply.write_to(&mut buffer)?;
+
+        Ok(Some(buffer.into_inner()))
+    }
+
+    fn handle_text(&self, payload: &[u8]) -> Value {
+        match self.handle_json(payload) {
+            Ok(reply) => reply,
+            Err(err) => json!({ "type": "error", "message": err.to_string() }),
+        }
+    }
+
+    fn handle_json(&self, payload: &[u8]) -> crate::Result<Value> {
+        let request: Value = serde_json::from_slice(payload)?;
+
+        let key = match request["key"].as_str() {
+            Some(key) => DhtKey::from_base64(key)?,
+            None => return Err("missing field `key`".into()),
+        };
+
+        let replication = request["replication"].as_u64().unwrap_or(1) as u8;
+
+        let reply = match request["type"].as_str() {
+            Some("put") => {
+                let value = match request["value"].as_str() {
+                    Some(value) => DhtValue::from_base64(value)?,
+                    None => return Err("missing field `value`".into()),
+                };
+                let ttl = request["ttl"].as_u64().unwrap_or(60) as u16;
+
+                self.client.put(key, value, ttl, replication)?;
+
+                json!({ "type": "success" })
+            }
+            Some("get") => match self.client.get(key)? {
+                Some(value) => json!({ "type": "success", "value": value.to_base64() }),
+                None => json!({ "type": "failure" }),
+            },
+            Some("delete") => {
+                let replicas = self.client.delete(key, replication)?;
+
+                json!({ "type": "success", "replicas": replicas })
+            }
+            Some("resolve") => match self.client.resolve(key)? {
+                Some((socket_addr, identifier)) => json!({
+                    "type": "success",
+                    "address": socket_addr.to_string(),
+                    "identifier": format!("{:x}", identifier),
+                }),
+                None => json!({ "type": "failure" }),
+            },
+            Some(other) => return Err(format!("unknown request type `{}`", other).into()),
+            None => return Err("missing field `type`".into()),
+        };
+
+        Ok(reply)
+    }
+
+    fn handle_websocket(&self, connection: Connection) -> crate::Result<()> {
+        let stream = connection.into_stream();
+
+        let timeout = Duration::from_millis(self.timeout);
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        let mut ws = WebSocket::accept(stream)?;
+
+        loop {
+            let (opcode, payload) = match ws.receive() {
+                Ok(message) => message,
+                Err(ref err) if err.kind() == io::ErrorKind::ConnectionAborted => return Ok(()),
+                Err(err) => return Err(Box::new(err)),
+            };
+
+            match opcode {
+                Opcode::Binary => match self.handle_binary(&payload) {
+                    Ok(Some(reply)) => ws.send(Opcode::Binary, reply)?,
+                    Ok(None) => {}
+                    Err(err) => {
+                        let reply = json!({ "type": "error", "message": err.to_string() });
+                        ws.send(Opcode::Text, reply.to_string().into_bytes())?;
+                    }
+                },
+                _ => {
+                    let reply = self.handle_text(&payload);
+                    ws.send(Opcode::Text, reply.to_string().into_bytes())?;
+                }
+            }
+        }
+    }
+}
+
+impl ServerHandler for WebSocketHandler {
+    fn handle_connection(&self, connection: Connection) {
+        if let Err(err) = self.handle_websocket(connection) {
+            error!("Error in WebSocket connection:\n\n{:?}", err);
+        }
+    }
+
+    fn handle_error(&self, error: io::Error) {
+        error!("Error for incoming WebSocket connection:\n\n{:?}", error);
+    }
+}
@@ -0,0 +1,183 @@
+//! Hinted handoff for writes to temporarily unreachable replicas
+//!
+//! If the peer responsible for a replica cannot be reached during a PUT
+//! operation, the record is kept locally together with a [`Hint`] naming the
+//! intended peer. The [`Handoff`] struct should be used in regular intervals
+//! to retry the delivery of all hinted records such that the replication
+//! factor is restored once the peer is reachable again.
+//!
+//! [`Hint`]: struct.Hint.html
+//! [`Handoff`]: struct.Handoff.html
+
+use crate::clock::{self, Clock};
+use crate::framing::Framing;
+use crate::network::SocketOptions;
+use crate::procedures::Procedures;
+use crate::proxy::Proxy;
+use crate::storage::{Key, Record};
+use crate::sync::MutexExt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+/// Maximum number of hints kept before the oldest ones are dropped
+const MAX_HINTS: usize = 1024;
+
+/// A record which still has to be delivered to its replica
+#[derive(Clone, Debug)]
+pub struct Hint {
+    pub target: SocketAddr,
+    pub key: Key,
+    pub record: Record,
+}
+
+/// Local store of hinted records which are delivered in the background
+pub struct Handoff {
+    hints: Mutex<Vec<Hint>>,
+    procedures: Procedures,
+    clock: Arc<dyn Clock>,
+}
+
+impl Handoff {
+    /// Creates a new `Handoff` instance without any hints.
+    ///
+    /// `timeout` is the timeout in milliseconds used to deliver records.
+    pub fn new(timeout: u64) -> Self {
+        Self {
+            hints: Mutex::new(Vec::new()),
+            procedures: Procedures::new(timeout),
+            clock: clock::system(),
+        }
+    }
+
+    /// Delivers the records through `proxy` if it is given.
+    pub fn with_proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.procedures = self.procedures.with_proxy(proxy);
+        self
+    }
+
+    /// Delivers the records in frames of `framing`.
+    pub fn with_framing(mut self, framing: Arc<dyn Framing>) -> Self {
+        self.procedures = self.procedures.with_framing(framing);
+        self
+    }
+
+    /// Delivers the records over sockets with `socket_options`.
+    pub fn with_socket_options(mut self, socket_options: SocketOptions) -> Self {
+        self.procedures = self.procedures.with_socket_options(socket_options);
+        self
+    }
+
+    /// Takes the time from `clock` to decide when hinted records expire.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Keeps `record` until it can be delivered to `target` under `key`.
+    pub fn hint(&self, target: SocketAddr, key: Key, record: Record) {
+        let mut hints = self.hints.lock_or_recover();
+
+        if hints.len() == MAX_HINTS {
+            let hint = hints.remove(0);
+
+            warn!(
+                "Dropping hint for key {} to peer {} since too many hints are pending",
+                hint.key, hint.target
+            );
+        }
+
+        info!("Keeping hint for key {} to peer {}", key, target);
+
+        hints.push(Hint {
+            target,
+            key,
+            record,
+        });
+    }
+
+    /// Returns the number of records which still have to be delivered.
+    pub fn pending(&self) -> usize {
+        self.hints.lock_or_recover().len()
+    }
+
+    /// Clears the lock of the hints after a delivery panicked.
+    ///
+    /// Hints are only ever added or removed as a whole, thus the remaining
+    /// hints are still valid.
+    pub fn recover(&self) {
+        if self.hints.is_poisoned() {
+            warn!("Clearing poisoned hints");
+
+            self.hints.clear_poison();
+        }
+    }
+
+    /// Tries to deliver all hinted records to their replicas.
+    ///
+    /// The records of each replica are delivered together in as few
+    /// messages as possible. Records which could not be delivered are kept
+    /// for the next attempt unless they expired in the meantime. Returns the
+    /// number of delivered records.
+    pub fn deliver(&self) -> usize {
+        let hints: Vec<Hint> = self.hints.lock_or_recover().drain(..).collect();
+        let now = self.clock.now();
+        let mut delivered = 0;
+        let mut remaining = Vec::new();
+
+        // group the hints by their target
+        let mut targets: Vec<(SocketAddr, Vec<Hint>)> = Vec::new();
+
+        for hint in hints {
+            if hint.record.is_expired_at(now) {
+                debug!("Dropping expired hint for key {}", hint.key);
+
+                continue;
+            }
+
+            match targets
+                .iter_mut()
+                .find(|(target, _)| *target == hint.target)
+            {
+                Some((_, hints)) => hints.push(hint),
+                None => targets.push((hint.target, vec![hint])),
+            }
+        }
+
+        for (target, hints) in targets {
+            let values = hints
+                .iter()
+                .map(|hint| (hint.key, hint.record.clone()))
+                .collect();
+
+            match self.procedures.put_values(target, values) {
+                Ok(rejected) => {
+                    let (kept, sent): (Vec<Hint>, Vec<Hint>) = hints
+                        .into_iter()
+                        .partition(|hint| rejected.contains(&hint.key));
+
+                    info!("Delivered {} hints to peer {}", sent.len(), target);
+
+                    delivered += sent.len();
+                    remaining.extend(kept);
+                }
+                Err(err) => {
+                    debug!(
+                        "Peer {} is still unreachable for {} hints: {}",
+                        target,
+                        hints.len(),
+                        err
+                    );
+
+                    remaining.extend(hints);
+                }
+            }
+        }
+
+        // keep hints which were added while delivering
+        let mut hints = self.hints.lock_or_recover();
+        remaining.append(&mut hints);
+        *hints = remaining;
+
+        delivered
+    }
+}
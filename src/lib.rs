@@ -54,97 +54,142 @@
 //! allows us to work concurrently while not having the overhead of spawning
 //! too many threads.
 //!
+//! # Features
+//!
+//! The crate is split into cargo features which build upon each other:
+//!
+//! * Without any features, the crate does not depend on the standard library
+//!   but only on `alloc`. It contains the [`Identifier`] math and intervals,
+//!   the typed keys and values of the [`dht`] module and the message types
+//!   and layouts of the [`codec`] such that embedded or WebAssembly clients
+//!   can compute keys and encode api messages on their own.
+//! * `std` adds everything which needs the standard library. All other
+//!   features enable it.
+//! * `routing` adds the routing table to the [`routing`] module. It does not
+//!   depend on any transport such that it can be embedded into other
+//!   networking stacks which provide identifiers on their own.
+//! * `network` adds the messages, the TCP server and client, the peer
+//!   procedures as well as the identifiers of socket addresses and keys.
+//! * `node` adds the configuration, the [`websocket`] transport, [`Node`] and
+//!   [`run`] to operate a complete peer along with the command line tools.
+//!   This feature is enabled by default.
+//! * `conformance` adds the [`conformance`] module which checks whether
+//!   another peer follows the protocol.
+//! * `wasm` adds the [`wasm`] client for browsers on top of the crate
+//!   without the standard library.
+//! * `cdylib` adds the [`ffi`] functions which start and stop a node and
+//!   access the DHT through it from C and C++ applications.
+//! * `python` adds the [`python`] extension module which wraps the api client
+//!   for Python scripts.
+//!
+//! [`codec`]: message/codec/index.html
+//! [`conformance`]: conformance/index.html
+//! [`dht`]: dht/index.html
+//! [`ffi`]: ffi/index.html
+//! [`Identifier`]: routing/identifier/struct.Identifier.html
+//! [`Node`]: struct.Node.html
+//! [`routing`]: routing/index.html
+//! [`python`]: python/index.html
+//! [`run`]: fn.run.html
+//! [`wasm`]: wasm/index.html
+//! [`websocket`]: websocket/index.html
 //! [w:dht]: https://en.wikipedia.org/wiki/Distributed_hash_table
 //! [w:chord]: https://en.wikipedia.org/wiki/Chord_(peer-to-peer)
 //! [w:cons]: https://en.wikipedia.org/wiki/Consistent_hashing
 
-extern crate bigint;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+#[cfg(feature = "network")]
 extern crate byteorder;
+#[cfg(feature = "node")]
 extern crate ini;
-#[macro_use]
+#[cfg_attr(feature = "network", macro_use)]
 extern crate log;
+#[cfg(feature = "network")]
 extern crate ring;
-extern crate threadpool;
 
-use crate::config::Config;
-use crate::handler::{ApiHandler, P2PHandler};
-use crate::network::Server;
-use crate::routing::Routing;
-use crate::stabilization::{Bootstrap, Stabilization};
+#[cfg(feature = "network")]
 use std::error::Error;
-use std::net::SocketAddr;
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
 
+#[cfg(feature = "network")]
+pub mod audit;
+#[cfg(feature = "network")]
+pub mod auth;
+#[cfg(feature = "network")]
+pub mod cache;
+#[cfg(feature = "network")]
+pub mod capture;
+#[cfg(feature = "network")]
+pub mod chaos;
+#[cfg(feature = "network")]
+pub mod client;
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "node")]
 pub mod config;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+#[cfg(feature = "network")]
+pub mod deadline;
+pub mod dht;
 pub mod error;
+#[cfg(feature = "network")]
+pub mod export;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+#[cfg(feature = "network")]
+pub mod framing;
+pub mod gateway;
+#[cfg(feature = "network")]
 pub mod handler;
+#[cfg(feature = "network")]
+pub mod handoff;
+#[cfg(feature = "network")]
+pub mod load;
+#[cfg(feature = "node")]
+pub mod lock;
 pub mod message;
+#[cfg(feature = "network")]
+pub mod metrics;
+#[cfg(feature = "network")]
 pub mod network;
+#[cfg(feature = "node")]
+mod node;
+#[cfg(feature = "network")]
+pub mod policy;
+#[cfg(feature = "network")]
 pub mod procedures;
+#[cfg(feature = "network")]
+pub mod proxy;
+#[cfg(feature = "network")]
+pub mod puzzle;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "network")]
+pub mod random;
+#[cfg(feature = "node")]
+pub mod replay;
 pub mod routing;
+#[cfg(feature = "network")]
+pub mod scheduler;
+#[cfg(feature = "network")]
 pub mod stabilization;
+#[cfg(feature = "node")]
+pub mod state;
+#[cfg(feature = "network")]
 pub mod storage;
-
-type Result<T> = std::result::Result<T, Box<Error>>;
-
-pub fn run(config: Config, bootstrap: Option<SocketAddr>) -> Result<()> {
-    println!("Distributed Hash Table based on CHORD");
-    println!("-------------------------------------\n");
-
-    debug!(
-        "The current configuration is as follows.\n\n{:#?}\n",
-        &config
-    );
-
-    let routing = if let Some(bootstrap_address) = bootstrap {
-        println!("Connecting to bootstrap peer {}...", bootstrap_address);
-
-        let bootstrap = Bootstrap::new(config.listen_address, bootstrap_address, config.fingers);
-        bootstrap.bootstrap(config.timeout)?
-    } else {
-        println!("No bootstrapping peer provided, creating new network...");
-
-        let finger_table = vec![config.listen_address; config.fingers];
-        Routing::new(
-            config.listen_address,
-            config.listen_address,
-            config.listen_address,
-            finger_table,
-        )
-    };
-
-    let routing = Arc::new(Mutex::new(routing));
-
-    let p2p_handler = P2PHandler::new(Arc::clone(&routing));
-    let p2p_server = Server::new(p2p_handler);
-    let p2p_handle = p2p_server.listen(config.listen_address, config.worker_threads)?;
-
-    let api_handler = ApiHandler::new(Arc::clone(&routing), config.timeout);
-    let api_server = Server::new(api_handler);
-    let api_handle = api_server.listen(config.api_address, 1)?;
-
-    let mut stabilization = Stabilization::new(Arc::clone(&routing), config.timeout);
-    let stabilization_handle = thread::spawn(move || loop {
-        if let Err(err) = stabilization.stabilize() {
-            error!("Error during stabilization:\n\n{:?}", err);
-        }
-
-        thread::sleep(Duration::from_secs(config.stabilization_interval));
-    });
-
-    if let Err(err) = p2p_handle.join() {
-        error!("Error joining p2p handler:\n\n{:?}", err);
-    }
-
-    if let Err(err) = api_handle.join() {
-        error!("Error joining api handler:\n\n{:?}", err);
-    }
-
-    if let Err(err) = stabilization_handle.join() {
-        error!("Error joining stabilization:\n\n{:?}", err);
-    }
-
-    Ok(())
-}
+#[cfg(feature = "std")]
+pub mod sync;
+#[cfg(feature = "network")]
+pub mod tenant;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "node")]
+pub mod websocket;
+
+#[cfg(feature = "network")]
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+#[cfg(feature = "node")]
+pub use crate::node::{run, run_all, Node, Runtime};
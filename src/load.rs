@@ -0,0 +1,253 @@
+//! Load of a peer compared with the rest of the ring
+//!
+//! Identifiers are hashes of the addresses of the peers, so some peers own
+//! a much larger range of the identifier space than others and thus store
+//! and serve more keys. A [`Load`] describes what a peer carries: its share
+//! of the identifier space, the records and bytes in its storage and the
+//! storage requests it has handled.
+//!
+//! `NODE LOAD` compares the load of a peer with the average over the peers
+//! in its routing table, which answer `PEER LOAD` with their own load. Since
+//! the fingers are spread over the whole ring, they are a fair sample of it.
+//! The reply recommends a [`target_weight`], the number of virtual peers the
+//! node should run to carry about as much as the other peers.
+//!
+//! # Examples
+//!
+//! ```
+//! # use chord::load::{self, Load};
+//! #
+//! let local = Load {
+//!     share: u64::MAX / 4,
+//!     records: 400,
+//!     bytes: 40_000,
+//!     requests: 1000,
+//! };
+//! let average = Load {
+//!     share: u64::MAX / 16,
+//!     records: 100,
+//!     bytes: 10_000,
+//!     requests: 250,
+//! };
+//!
+//! assert_eq!(4.0, local.ratio(&average));
+//! assert_eq!(2, load::target_weight(&local, &average, 4));
+//! ```
+//!
+//! [`Load`]: struct.Load.html
+//! [`target_weight`]: fn.target_weight.html
+
+use crate::metrics::StatsSnapshot;
+use crate::routing::peer::PeerInfo;
+use crate::routing::Routing;
+use crate::storage::Storage;
+
+/// Deviation from the average load within which a peer is balanced
+pub const TOLERANCE: f64 = 0.25;
+
+/// What a peer carries for the ring
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Load {
+    /// Share of the identifier space the peer is responsible for as a
+    /// fraction of `2^64`
+    pub share: u64,
+    /// Number of records in the storage of the peer
+    pub records: u32,
+    /// Number of bytes of the values in the storage of the peer
+    pub bytes: u64,
+    /// Number of storage requests the peer has handled since it started
+    pub requests: u64,
+}
+
+impl Load {
+    /// Returns the load of the peer with `routing` and `storage` which has
+    /// counted its requests in `stats`.
+    ///
+    /// A peer without other peers owns the whole identifier space.
+    pub fn local(routing: &Routing<PeerInfo>, storage: &Storage, stats: &StatsSnapshot) -> Self {
+        let current = routing.current.identifier();
+        let predecessor = routing.predecessor.identifier();
+
+        let share = if predecessor == current {
+            u64::MAX
+        } else {
+            let distance = predecessor.distance_to(current).as_bytes();
+            let mut high = [0; 8];
+            high.copy_from_slice(&distance[..8]);
+
+            u64::from_be_bytes(high)
+        };
+
+        Self {
+            share,
+            records: storage.len() as u32,
+            bytes: storage
+                .values()
+                .map(|record| record.value.len() as u64)
+                .sum(),
+            requests: stats.storage_gets + stats.storage_puts + stats.storage_deletes,
+        }
+    }
+
+    /// Returns the share of the identifier space as a number in `[0, 1]`.
+    pub fn share_fraction(&self) -> f64 {
+        self.share as f64 / u64::MAX as f64
+    }
+
+    /// Returns the average of `loads` or `None` if there are none.
+    pub fn average(loads: &[Load]) -> Option<Load> {
+        if loads.is_empty() {
+            return None;
+        }
+
+        let count = loads.len() as u128;
+        let mean = |value: fn(&Load) -> u64| {
+            (loads
+                .iter()
+                .map(|load| u128::from(value(load)))
+                .sum::<u128>()
+                / count) as u64
+        };
+
+        Some(Load {
+            share: mean(|load| load.share),
+            records: mean(|load| u64::from(load.records)) as u32,
+            bytes: mean(|load| load.bytes),
+            requests: mean(|load| load.requests),
+        })
+    }
+
+    /// Returns how many times this load is the `average`.
+    ///
+    /// The stored bytes are compared if the average peer stores any,
+    /// otherwise the shares of the identifier space which decide how many
+    /// values a peer will store.
+    pub fn ratio(&self, average: &Load) -> f64 {
+        if average.bytes > 0 {
+            self.bytes as f64 / average.bytes as f64
+        } else if average.share > 0 {
+            self.share as f64 / average.share as f64
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Returns the number of virtual peers a node running `weight` peers which
+/// carry `local` each should run such that they carry about the `average`.
+///
+/// Balanced nodes keep their weight. Otherwise the weight is scaled by the
+/// inverse of the load ratio but at most halved or doubled at once, since
+/// the load only settles after the values have been handed over.
+pub fn target_weight(local: &Load, average: &Load, weight: u16) -> u16 {
+    let ratio = local.ratio(average);
+
+    if (ratio - 1.0).abs() <= TOLERANCE {
+        return weight;
+    }
+
+    let weight = f64::from(weight.max(1));
+    let target = (weight / ratio).clamp((weight / 2.0).ceil(), weight * 2.0);
+
+    target.round().clamp(1.0, f64::from(u16::MAX)) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Key, Record};
+    use std::time::Duration;
+
+    fn load(bytes: u64) -> Load {
+        Load {
+            bytes,
+            ..Load::default()
+        }
+    }
+
+    #[test]
+    fn local() {
+        let addr = "127.0.0.1:8080".parse().unwrap();
+        let routing = Routing::from_addrs(addr, addr, addr, vec![addr; 4]);
+
+        let mut storage = Storage::new();
+        let key = Key {
+            raw_key: [3; 32],
+            replication_index: 0,
+        };
+        storage.insert(key, Record::new(vec![1, 2, 3], Duration::from_secs(60), 1));
+
+        let stats = StatsSnapshot {
+            storage_gets: 2,
+            storage_puts: 1,
+            ..StatsSnapshot::default()
+        };
+
+        let load = Load::local(&routing, &storage, &stats);
+
+        assert_eq!(u64::MAX, load.share);
+        assert_eq!(1, load.records);
+        assert_eq!(3, load.bytes);
+        assert_eq!(3, load.requests);
+    }
+
+    #[test]
+    fn average() {
+        let loads = [
+            Load {
+                share: u64::MAX,
+                records: 3,
+                bytes: 10,
+                requests: 1,
+            },
+            Load {
+                share: u64::MAX,
+                records: 1,
+                bytes: 20,
+                requests: 2,
+            },
+        ];
+
+        assert_eq!(
+            Some(Load {
+                share: u64::MAX,
+                records: 2,
+                bytes: 15,
+                requests: 1,
+            }),
+            Load::average(&loads)
+        );
+        assert_eq!(None, Load::average(&[]));
+    }
+
+    #[test]
+    fn ratio_falls_back_to_share() {
+        let local = Load {
+            share: 300,
+            ..Load::default()
+        };
+        let average = Load {
+            share: 100,
+            ..Load::default()
+        };
+
+        assert_eq!(3.0, local.ratio(&average));
+        assert_eq!(1.0, local.ratio(&Load::default()));
+    }
+
+    #[test]
+    fn target_weights() {
+        // balanced
+        assert_eq!(4, target_weight(&load(110), &load(100), 4));
+        assert_eq!(4, target_weight(&load(80), &load(100), 4));
+
+        // overloaded nodes run fewer peers, but at least one
+        assert_eq!(3, target_weight(&load(150), &load(100), 4));
+        assert_eq!(2, target_weight(&load(1000), &load(100), 4));
+        assert_eq!(1, target_weight(&load(1000), &load(100), 1));
+
+        // underloaded nodes run more peers
+        assert_eq!(2, target_weight(&load(50), &load(100), 1));
+        assert_eq!(8, target_weight(&load(0), &load(100), 4));
+    }
+}
@@ -0,0 +1,151 @@
+//! Single instance of a peer per listen address
+//!
+//! The identifier of a peer is derived from its listen address. Two
+//! processes started with the same configuration would claim the same
+//! identity and fight over its range of the ring. A [`Node`] therefore locks
+//! the file `chord-<address>.lock` in the configured [`lock_dir`] before it
+//! binds its listen address and fails right away with a [`LockedError`] if
+//! another process holds the lock. With a `port_range`, the address is only
+//! known after binding and locked then.
+//!
+//! The operating system releases the lock when the process exits or
+//! crashes, so a lock file left behind does not prevent the next start. The
+//! file contains the id of the process holding the lock, which is reported
+//! in the error.
+//!
+//! # Examples
+//!
+//! ```
+//! # use chord::lock::InstanceLock;
+//! # use std::env;
+//! #
+//! let addr = "127.0.0.1:8080".parse().unwrap();
+//! let lock = InstanceLock::acquire(&env::temp_dir(), addr).unwrap();
+//!
+//! assert!(InstanceLock::acquire(&env::temp_dir(), addr).is_err());
+//!
+//! drop(lock);
+//! assert!(InstanceLock::acquire(&env::temp_dir(), addr).is_ok());
+//! ```
+//!
+//! [`Node`]: ../struct.Node.html
+//! [`lock_dir`]: ../config/struct.Config.html#structfield.lock_dir
+//! [`LockedError`]: ../error/struct.LockedError.html
+
+use crate::error::LockedError;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// An exclusive lock on the listen address of a peer
+///
+/// The lock is released when this value is dropped.
+#[derive(Debug)]
+pub struct InstanceLock {
+    // only held for its lock
+    _file: File,
+    path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Locks the file for `listen_address` in `dir` and writes the id of
+    /// this process to it.
+    ///
+    /// Fails with a [`LockedError`] if another process or another node of
+    /// this process holds the lock.
+    ///
+    /// [`LockedError`]: ../error/struct.LockedError.html
+    pub fn acquire(dir: &Path, listen_address: SocketAddr) -> crate::Result<Self> {
+        let path = dir.join(file_name(listen_address));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut contents = String::new();
+                let pid = file
+                    .read_to_string(&mut contents)
+                    .ok()
+                    .and_then(|_| contents.trim().parse().ok());
+
+                return Err(Box::new(LockedError::new(listen_address, pid, &path)));
+            }
+            Err(TryLockError::Error(err)) => return Err(Box::new(err)),
+        }
+
+        file.set_len(0)?;
+        write!(file, "{}", process::id())?;
+
+        Ok(Self { _file: file, path })
+    }
+
+    /// Returns the path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn file_name(listen_address: SocketAddr) -> String {
+    let address: String = listen_address
+        .to_string()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    format!("chord-{}.lock", address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn file_names() {
+        assert_eq!(
+            "chord-127.0.0.1_8080.lock",
+            file_name("127.0.0.1:8080".parse().unwrap())
+        );
+        assert_eq!(
+            "chord-___1__8080.lock",
+            file_name("[::1]:8080".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn second_lock_fails() {
+        let dir = env::temp_dir().join("chord-lock-second");
+        fs::create_dir_all(&dir).unwrap();
+        let addr = "127.0.7.9:8080".parse().unwrap();
+
+        let lock = InstanceLock::acquire(&dir, addr).unwrap();
+        assert_eq!(
+            process::id().to_string(),
+            fs::read_to_string(lock.path()).unwrap()
+        );
+
+        let err = InstanceLock::acquire(&dir, addr).unwrap_err();
+        let locked = err.downcast_ref::<LockedError>().unwrap();
+        assert_eq!(Some(process::id()), locked.pid());
+
+        // other addresses are not affected
+        InstanceLock::acquire(&dir, "127.0.7.9:8081".parse().unwrap()).unwrap();
+
+        drop(lock);
+        InstanceLock::acquire(&dir, addr).unwrap();
+    }
+}
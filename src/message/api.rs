@@ -1,16 +1,29 @@
+use super::codec::{
+    self, read_load, read_optional_u32, read_optional_u64, read_socket_addr, read_socket_addrs,
+    write_load, write_optional_u32, write_socket_addr, write_socket_addrs, HEADER_SIZE,
+    MAX_MESSAGE_SIZE, TRACE_FLAG,
+};
 use super::MessagePayload;
+use crate::dht::{DhtKey, DhtValue};
+use crate::load::Load;
+use crate::metrics::{LookupStats, Summary, Traffic};
+use crate::routing::identifier::Identifier;
 use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use bytes::Bytes;
 use std::io;
 use std::io::prelude::*;
+use std::io::Cursor;
+use std::mem;
+use std::net::SocketAddr;
 
 /// This message is used to ask the DHT module that the given key-value pair
 /// should be stored.
 ///
-/// The field TTL indicates the time in seconds this key-value pair should be
-/// stored in the network before it is considered as expired. Note that this is
-/// just a hint. The peers may have their own timeouts configured which may be
-/// shorter than the value in TTL. In those cases the content could be expired
-/// before hand. The DHT does not make any guarantees about the content
+/// The field TTL indicates the time in milliseconds this key-value pair should
+/// be stored in the network before it is considered as expired. Note that this
+/// is just a hint. The peers may have their own timeouts configured which may
+/// be shorter than the value in TTL. In those cases the content could be
+/// expired before hand. The DHT does not make any guarantees about the content
 /// availability; however it should exercise best effort to store it for that
 /// long. Similarly, the replication field indicates how many times (by storing
 /// the content on different peers, or under different keys, etc) this content
@@ -19,199 +32,2912 @@ use std::io::prelude::*;
 ///
 /// It is expected that the DHT module upon receiving this message does its best
 /// effort in storing the given key-value pair. No confirmation is needed for
-/// the PUT operation.
+/// the PUT operation unless the acks field is set. In that case, the DHT module
+/// replies with a [`DhtPutSuccess`] message once at least that many replicas
+/// confirmed the operation and with a [`DhtFailure`] message otherwise. This
+/// allows applications to choose between durability and latency.
+///
+/// [`DhtPutSuccess`]: struct.DhtPutSuccess.html
+/// [`DhtFailure`]: struct.DhtFailure.html
 #[derive(Debug, PartialEq)]
 pub struct DhtPut {
-    pub ttl: u16,
+    pub ttl: u64,
+    pub replication: u8,
+    pub acks: u8,
+    pub key: DhtKey,
+    pub value: DhtValue,
+}
+
+/// This message is sent when a previous [`DhtPut`] operation requested
+/// acknowledgements and enough replicas confirmed the operation.
+///
+/// The field acks contains the number of replicas which stored the value
+/// and version the version the DHT assigned to it. Older peers do not report
+/// the version.
+///
+/// [`DhtPut`]: struct.DhtPut.html
+#[derive(Debug, PartialEq)]
+pub struct DhtPutSuccess {
+    pub acks: u8,
+    pub key: DhtKey,
+    pub version: Option<u64>,
+}
+
+/// This message is used to ask the DHT method to search for a given key and
+/// provide the value if a value for the corresponding is found in the network.
+///
+/// No immediate is reply is expected after sending this message to the DHT
+/// module. The module should however start with its best effort to search for
+/// the given key.
+///
+/// If a [`Quorum`] is given, the DHT module queries several replicas in
+/// parallel and replies with the newest value among them.
+///
+/// The optional `budget` is the time in milliseconds the client is willing
+/// to wait for a reply. The DHT module abandons the search once the budget is
+/// spent and does not reply at all in that case.
+///
+/// The optional `request_id` is chosen by the client and allows to abort the
+/// search with a [`DhtCancel`] message over the same connection.
+///
+/// If the client already knows the value of a certain version, it passes
+/// that version as `if_none_match`. The DHT module then replies with a
+/// [`DhtNotModified`] message instead of shipping the value again unless a
+/// newer version is stored.
+///
+/// [`Quorum`]: struct.Quorum.html
+/// [`DhtCancel`]: struct.DhtCancel.html
+/// [`DhtNotModified`]: struct.DhtNotModified.html
+#[derive(Debug, PartialEq)]
+pub struct DhtGet {
+    pub key: DhtKey,
+    pub quorum: Option<Quorum>,
+    pub budget: Option<u32>,
+    pub request_id: Option<u32>,
+    pub if_none_match: Option<u64>,
+}
+
+/// The replicas which should be queried by a quorum read
+///
+/// The replication indices `0` to `replicas - 1` are queried and at least
+/// `reads` of them have to reply for the read to succeed. If none of the
+/// replying replicas stores a value, the read fails.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quorum {
+    pub replicas: u8,
+    pub reads: u8,
+}
+
+/// This message is sent when a previous [`DhtGet`] operation found a value
+/// corresponding to the requested key in the network.
+///
+/// The optional [`RecordMetadata`] describes the replica the value was read
+/// from. It is only exchanged with clients which negotiated the CBOR
+/// encoding since the value takes the rest of the binary payload.
+///
+/// [`DhtGet`]: struct.DhtGet.html
+/// [`RecordMetadata`]: struct.RecordMetadata.html
+#[derive(Debug, PartialEq)]
+pub struct DhtSuccess {
+    pub key: DhtKey,
+    pub value: DhtValue,
+    pub metadata: Option<RecordMetadata>,
+}
+
+/// Details about the replica a [`DhtSuccess`] has been served from
+///
+/// `ttl` is the remaining time to live in milliseconds, `version` the
+/// version of the value and `stored` the time the serving peer stored it in
+/// milliseconds since the unix epoch. Clients can cache the value for at
+/// most the remaining time to live without asking the DHT again.
+///
+/// [`DhtSuccess`]: struct.DhtSuccess.html
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RecordMetadata {
+    pub ttl: u64,
+    pub version: u64,
+    pub replication_index: u8,
+    pub stored: u64,
+}
+
+/// This message is sent when a previous [`DhtGet`] operation did not find any
+/// value for the requested key.
+///
+/// [`DhtGet`]: struct.DhtGet.html
+#[derive(Debug, PartialEq)]
+pub struct DhtFailure {
+    pub key: DhtKey,
+}
+
+/// This message is sent when a previous conditional [`DhtGet`] operation
+/// found no value newer than the version the client knows already.
+///
+/// [`DhtGet`]: struct.DhtGet.html
+#[derive(Debug, PartialEq)]
+pub struct DhtNotModified {
+    pub key: DhtKey,
+}
+
+/// This message is used to ask the DHT module to look up the values of the
+/// given keys in the background such that following [`DhtGet`] requests for
+/// them are answered right away, for example before loading the chunks of a
+/// manifest.
+///
+/// The DHT module does not reply. Values it found are kept for a short time
+/// and handed out to the next `DhtGet` of their key.
+///
+/// [`DhtGet`]: struct.DhtGet.html
+#[derive(Debug, PartialEq)]
+pub struct DhtPrefetch {
+    pub keys: Vec<DhtKey>,
+}
+
+impl DhtPrefetch {
+    /// Maximum number of keys of a single request
+    pub const MAX_KEYS: usize = 1024;
+}
+
+/// This message is used to ask the DHT module which peer is responsible for
+/// the given key and replication index without fetching the value.
+///
+/// The DHT module replies with a [`DhtResolveReply`] message or with a
+/// [`DhtFailure`] message if the lookup failed. This allows upper layers to
+/// implement their own transfer protocols with the responsible peer.
+///
+/// If `trace` is set, the lookup records the address of every peer on its
+/// path which is included in the reply.
+///
+/// [`DhtResolveReply`]: struct.DhtResolveReply.html
+/// [`DhtFailure`]: struct.DhtFailure.html
+#[derive(Debug, PartialEq)]
+pub struct DhtResolve {
+    pub replication_index: u8,
+    pub trace: bool,
+    pub key: DhtKey,
+}
+
+/// This message is sent after a [`DhtResolve`] operation and contains the
+/// identifier and address of the peer responsible for the requested key.
+///
+/// If the lookup has been traced, `path` contains the addresses of all peers
+/// which have been asked during the lookup in order.
+///
+/// [`DhtResolve`]: struct.DhtResolve.html
+#[derive(Debug, PartialEq)]
+pub struct DhtResolveReply {
+    pub replication_index: u8,
+    pub key: DhtKey,
+    pub identifier: Identifier,
+    pub socket_addr: SocketAddr,
+    pub path: Vec<SocketAddr>,
+}
+
+/// This message is used to ask the DHT module for information about the
+/// peer itself, for example for monitoring purposes.
+///
+/// The DHT module replies with a [`NodeInfoReply`] message.
+///
+/// [`NodeInfoReply`]: struct.NodeInfoReply.html
+#[derive(Debug, PartialEq)]
+pub struct NodeInfo;
+
+/// This message is sent after a [`NodeInfo`] request and contains the routing
+/// information of the peer as well as rolling aggregates about its lookups.
+///
+/// The traffic of the peer is missing in replies of older peers.
+///
+/// [`NodeInfo`]: struct.NodeInfo.html
+#[derive(Debug, PartialEq)]
+pub struct NodeInfoReply {
+    pub identifier: Identifier,
+    pub socket_addr: SocketAddr,
+    pub predecessor: SocketAddr,
+    pub successor: SocketAddr,
+    pub lookup_stats: LookupStats,
+    pub traffic: Option<Traffic>,
+}
+
+/// This message is used to ask the DHT module to remove the value stored under
+/// the given key from the network.
+///
+/// The replication field indicates how many replicas have been requested when
+/// storing the value such that all of them can be removed. The DHT module
+/// replies with a [`DhtDeleteReply`] message.
+///
+/// [`DhtDeleteReply`]: struct.DhtDeleteReply.html
+#[derive(Debug, PartialEq)]
+pub struct DhtDelete {
     pub replication: u8,
-    pub key: [u8; 32],
-    pub value: Vec<u8>,
+    pub key: DhtKey,
+}
+
+/// This message is sent after a [`DhtDelete`] operation and contains the
+/// number of replicas which have been removed.
+///
+/// [`DhtDelete`]: struct.DhtDelete.html
+#[derive(Debug, PartialEq)]
+pub struct DhtDeleteReply {
+    pub replicas: u8,
+    pub key: DhtKey,
+}
+
+/// This message asks the DHT module to abort a running [`DhtGet`] operation
+/// which has been started with the given request ID over the same
+/// connection.
+///
+/// No reply is sent for this message. The aborted operation is answered with
+/// a [`DhtFailure`] message instead.
+///
+/// [`DhtGet`]: struct.DhtGet.html
+/// [`DhtFailure`]: struct.DhtFailure.html
+#[derive(Debug, PartialEq)]
+pub struct DhtCancel {
+    pub request_id: u32,
+}
+
+/// The records which should be removed by a [`DhtFlush`] operation
+///
+/// [`DhtFlush`]: struct.DhtFlush.html
+#[derive(Debug, PartialEq)]
+pub enum FlushScope {
+    /// Remove all records.
+    All,
+    /// Remove all records whose key starts with the given prefix.
+    Namespace(Vec<u8>),
+    /// Remove all records whose time to live has passed.
+    Expired,
+}
+
+/// This admin message is used to ask the DHT module to remove records from
+/// its local storage.
+///
+/// Only the storage of the receiving peer is affected, other peers are not
+/// contacted. This is useful for testing, for deleting all keys of a
+/// namespace and for recovering from bad data. The DHT module replies with a
+/// [`DhtFlushReply`] message.
+///
+/// [`DhtFlushReply`]: struct.DhtFlushReply.html
+#[derive(Debug, PartialEq)]
+pub struct DhtFlush {
+    pub scope: FlushScope,
+}
+
+/// This message is sent after a [`DhtFlush`] operation and contains the
+/// number of records which have been removed.
+///
+/// [`DhtFlush`]: struct.DhtFlush.html
+#[derive(Debug, PartialEq)]
+pub struct DhtFlushReply {
+    pub records: u32,
+}
+
+/// This admin message is used to take a peer out of the network for planned
+/// maintenance.
+///
+/// The peer stops accepting new values, hands all records in its storage to
+/// its successor and tells its neighbours to bypass it. Afterwards it replies
+/// with a [`NodeDrainReply`] message and shuts down.
+///
+/// [`NodeDrainReply`]: struct.NodeDrainReply.html
+#[derive(Debug, PartialEq)]
+pub struct NodeDrain;
+
+/// This message is sent after a [`NodeDrain`] operation and contains the
+/// number of records which have been transferred to the successor.
+///
+/// [`NodeDrain`]: struct.NodeDrain.html
+#[derive(Debug, PartialEq)]
+pub struct NodeDrainReply {
+    pub records: u32,
+}
+
+/// This admin message is used to switch a peer into read-only mode or back.
+///
+/// A read-only peer refuses new values while it still serves reads and
+/// routes requests, for example during upgrades or when it runs out of disk
+/// space. The DHT module replies with a [`NodeReadOnlyReply`] message.
+///
+/// [`NodeReadOnlyReply`]: struct.NodeReadOnlyReply.html
+#[derive(Debug, PartialEq)]
+pub struct NodeReadOnly {
+    pub read_only: bool,
+}
+
+/// This message is sent after a [`NodeReadOnly`] operation and contains
+/// whether the peer is read-only now.
+///
+/// [`NodeReadOnly`]: struct.NodeReadOnly.html
+#[derive(Debug, PartialEq)]
+pub struct NodeReadOnlyReply {
+    pub read_only: bool,
+}
+
+/// This admin message is used to compare the load of a peer with the rest
+/// of the ring.
+///
+/// The peer asks up to `samples` peers from its routing table for their load
+/// and replies with a [`NodeLoadReply`] message.
+///
+/// [`NodeLoadReply`]: struct.NodeLoadReply.html
+#[derive(Debug, PartialEq)]
+pub struct NodeLoad {
+    pub samples: u16,
+}
+
+/// This message is sent after a [`NodeLoad`] operation and contains the load
+/// of the peer along with the average load of the `sampled` peers including
+/// itself.
+///
+/// The node runs `weight` peers including its virtual peers and should run
+/// `target_weight` peers to carry about the average load, see the [`load`]
+/// module.
+///
+/// [`NodeLoad`]: struct.NodeLoad.html
+/// [`load`]: ../../load/index.html
+#[derive(Debug, PartialEq)]
+pub struct NodeLoadReply {
+    pub sampled: u16,
+    pub weight: u16,
+    pub target_weight: u16,
+    pub local: Load,
+    pub average: Load,
+}
+
+/// Position in a listing of local records, the key of the last record listed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ListCursor {
+    pub key: DhtKey,
+    pub replication_index: u8,
+}
+
+/// This admin message is used to list the records in the local storage of
+/// the receiving peer.
+///
+/// Other peers are not contacted. Records are listed in the order of their
+/// keys, at most `limit` at once and starting after the cursor `after` if it
+/// is given. The DHT module replies with a [`DhtListLocalReply`] message.
+/// This allows operators to find out which records take up the storage of a
+/// peer.
+///
+/// [`DhtListLocalReply`]: struct.DhtListLocalReply.html
+#[derive(Debug, PartialEq)]
+pub struct DhtListLocal {
+    pub limit: u16,
+    pub after: Option<ListCursor>,
+}
+
+/// A record in the local storage of a peer as listed by [`DhtListLocal`]
+///
+/// The `size` is the length of the value in bytes and `ttl` the remaining
+/// time to live in milliseconds.
+///
+/// [`DhtListLocal`]: struct.DhtListLocal.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalRecord {
+    pub key: DhtKey,
+    pub replication_index: u8,
+    pub ttl: u64,
+    pub size: u32,
+    pub version: u64,
+}
+
+/// This message is sent after a [`DhtListLocal`] operation and contains the
+/// listed records.
+///
+/// Additionally, it contains the total number of records and bytes stored by
+/// the peer as well as the number of records following this page. The next
+/// page can be requested with the key of the last record as cursor.
+///
+/// [`DhtListLocal`]: struct.DhtListLocal.html
+#[derive(Debug, PartialEq)]
+pub struct DhtListLocalReply {
+    pub total_records: u32,
+    pub total_bytes: u64,
+    pub remaining: u32,
+    pub records: Vec<LocalRecord>,
+}
+
+impl DhtListLocalReply {
+    /// Maximum number of records which fit into a single reply
+    pub const MAX_RECORDS: usize = 1024;
+
+    /// Returns the cursor to request the next page with or `None` if this is
+    /// the last page.
+    pub fn next_cursor(&self) -> Option<ListCursor> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.records.last().map(|record| ListCursor {
+            key: record.key,
+            replication_index: record.replication_index,
+        })
+    }
+}
+
+/// This admin message is used to list the finger table of the receiving
+/// peer.
+///
+/// Fingers are listed in the order of their index, at most `limit` at once
+/// and starting after the index `after` if it is given. The DHT module
+/// replies with a [`NodePeersReply`] message.
+///
+/// [`NodePeersReply`]: struct.NodePeersReply.html
+#[derive(Debug, PartialEq)]
+pub struct NodePeers {
+    pub limit: u16,
+    pub after: Option<u16>,
+}
+
+/// A finger in the routing table of a peer as listed by [`NodePeers`]
+///
+/// [`NodePeers`]: struct.NodePeers.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeerEntry {
+    pub index: u16,
+    /// Seconds since the finger was last verified, saturating at `u16::MAX`
+    pub age: u16,
+    pub identifier: Identifier,
+    pub socket_addr: SocketAddr,
+}
+
+/// This message is sent after a [`NodePeers`] operation and contains the
+/// listed fingers.
+///
+/// Additionally, it contains the total number of fingers as well as the
+/// number of fingers following this page. The next page can be requested
+/// with the index of the last finger as cursor.
+///
+/// [`NodePeers`]: struct.NodePeers.html
+#[derive(Debug, PartialEq)]
+pub struct NodePeersReply {
+    pub total_peers: u16,
+    pub remaining: u16,
+    pub peers: Vec<PeerEntry>,
+}
+
+impl NodePeersReply {
+    /// Maximum number of fingers which fit into a single reply
+    pub const MAX_PEERS: usize = 1024;
+
+    /// Returns the cursor to request the next page with or `None` if this is
+    /// the last page.
+    pub fn next_cursor(&self) -> Option<u16> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.peers.last().map(|peer| peer.index)
+    }
+}
+
+/// This admin message is used to walk the ring along the successors of the
+/// receiving peer.
+///
+/// The walk starts with the receiving peer itself, or with the successor of
+/// the peer `after` if it is given, and lists at most `limit` peers. Each
+/// successor is found with a lookup, thus the walk also covers peers the
+/// receiving peer does not know about. The DHT module replies with a
+/// [`DhtRingWalkReply`] message.
+///
+/// [`DhtRingWalkReply`]: struct.DhtRingWalkReply.html
+#[derive(Debug, PartialEq)]
+pub struct DhtRingWalk {
+    pub limit: u16,
+    pub after: Option<SocketAddr>,
+}
+
+/// A peer on the ring as listed by [`DhtRingWalk`]
+///
+/// [`DhtRingWalk`]: struct.DhtRingWalk.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct RingPeer {
+    pub identifier: Identifier,
+    pub socket_addr: SocketAddr,
+}
+
+/// This message is sent after a [`DhtRingWalk`] operation and contains the
+/// peers visited on the ring.
+///
+/// The walk is `complete` once the successor of the last peer is the peer
+/// where the walk started. Otherwise, the next page can be requested with the
+/// address of the last peer as cursor.
+///
+/// [`DhtRingWalk`]: struct.DhtRingWalk.html
+#[derive(Debug, PartialEq)]
+pub struct DhtRingWalkReply {
+    pub complete: bool,
+    pub peers: Vec<RingPeer>,
+}
+
+impl DhtRingWalkReply {
+    /// Maximum number of peers which fit into a single reply
+    pub const MAX_PEERS: usize = 1024;
+
+    /// Returns the cursor to request the next page with or `None` if the
+    /// walk is complete.
+    pub fn next_cursor(&self) -> Option<SocketAddr> {
+        if self.complete {
+            return None;
+        }
+
+        self.peers.last().map(|peer| peer.socket_addr)
+    }
+}
+
+/// A record in the local storage of a peer including its value as exported
+/// by [`DhtExport`] and imported by [`DhtImport`]
+///
+/// The `ttl` is the remaining time to live in milliseconds and `replicas`
+/// the total number of replicas the value was written with or zero if
+/// unknown.
+///
+/// [`DhtExport`]: struct.DhtExport.html
+/// [`DhtImport`]: struct.DhtImport.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredRecord {
+    pub key: DhtKey,
+    pub replication_index: u8,
+    pub replicas: u8,
+    pub ttl: u64,
+    pub version: u64,
+    pub value: Bytes,
+}
+
+impl StoredRecord {
+    /// Size of a record within a message besides the value itself
+    const OVERHEAD: usize = 54;
+
+    /// Additional size of a record within a CBOR message, which also covers
+    /// its share of the other fields of the message
+    const CBOR_OVERHEAD: usize = 93;
+
+    /// Returns the size of the record within a message.
+    pub fn size(&self) -> usize {
+        Self::OVERHEAD + self.value.len()
+    }
+
+    /// Returns the size of the record within a message of the given
+    /// encoding.
+    ///
+    /// The size is an upper bound for [`Encoding::Cbor`].
+    ///
+    /// [`Encoding::Cbor`]: enum.Encoding.html#variant.Cbor
+    pub fn encoded_size(&self, encoding: Encoding) -> usize {
+        match encoding {
+            Encoding::Binary => self.size(),
+            Encoding::Cbor => self.size() + Self::CBOR_OVERHEAD,
+        }
+    }
+}
+
+/// This admin message is used to export the records in the local storage of
+/// the receiving peer including their values.
+///
+/// Records are exported in the order of their keys like with
+/// [`DhtListLocal`]. Each page contains at most `limit` records and is cut
+/// short such that the reply fits into a single message. The DHT module
+/// replies with a [`DhtExportReply`] message.
+///
+/// [`DhtListLocal`]: struct.DhtListLocal.html
+/// [`DhtExportReply`]: struct.DhtExportReply.html
+#[derive(Debug, PartialEq)]
+pub struct DhtExport {
+    pub limit: u16,
+    pub after: Option<ListCursor>,
+}
+
+/// This message is sent after a [`DhtExport`] operation and contains the
+/// exported records along with the number of records following this page.
+///
+/// [`DhtExport`]: struct.DhtExport.html
+#[derive(Debug, PartialEq)]
+pub struct DhtExportReply {
+    pub remaining: u32,
+    pub records: Vec<StoredRecord>,
+}
+
+impl DhtExportReply {
+    /// Maximum size of the payload such that the reply fits into
+    /// [`MAX_MESSAGE_SIZE`]
+    ///
+    /// [`MAX_MESSAGE_SIZE`]: ../codec/constant.MAX_MESSAGE_SIZE.html
+    pub const MAX_PAYLOAD_SIZE: usize = MAX_MESSAGE_SIZE - HEADER_SIZE;
+
+    /// Returns the cursor to request the next page with or `None` if this is
+    /// the last page.
+    pub fn next_cursor(&self) -> Option<ListCursor> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.records.last().map(|record| ListCursor {
+            key: record.key,
+            replication_index: record.replication_index,
+        })
+    }
+}
+
+/// This admin message is used to restore exported records into the local
+/// storage of the receiving peer.
+///
+/// The records keep their versions such that a record is only stored if the
+/// peer does not have the same or a newer version already. Records are
+/// stored as they are even if the peer is not responsible for them, thus they
+/// should be imported into the peer they have been exported from. The DHT
+/// module replies with a [`DhtImportReply`] message.
+///
+/// Use [`pack`] to split records into messages which fit into
+/// [`MAX_MESSAGE_SIZE`].
+///
+/// [`DhtImportReply`]: struct.DhtImportReply.html
+/// [`pack`]: #method.pack
+/// [`MAX_MESSAGE_SIZE`]: ../codec/constant.MAX_MESSAGE_SIZE.html
+#[derive(Debug, PartialEq)]
+pub struct DhtImport {
+    pub records: Vec<StoredRecord>,
+}
+
+impl DhtImport {
+    /// Packs the given records into as few messages as possible.
+    ///
+    /// A record which is too large to fit into a message on its own is
+    /// dropped with a warning.
+    pub fn pack<I>(records: I) -> Vec<DhtImport>
+    where
+        I: IntoIterator<Item = StoredRecord>,
+    {
+        Self::pack_encoded(records, Encoding::Binary)
+    }
+
+    /// Packs the given records into as few messages of the given encoding as
+    /// possible.
+    ///
+    /// See [`pack`] for further documentation.
+    ///
+    /// [`pack`]: #method.pack
+    pub fn pack_encoded<I>(records: I, encoding: Encoding) -> Vec<DhtImport>
+    where
+        I: IntoIterator<Item = StoredRecord>,
+    {
+        let max_size = DhtExportReply::MAX_PAYLOAD_SIZE;
+
+        let mut messages = Vec::new();
+        let mut records_size = 0;
+        let mut batch = Vec::new();
+
+        for record in records {
+            let size = record.encoded_size(encoding);
+
+            if size > max_size {
+                warn!(
+                    "Dropping record of {} bytes which exceeds the message size",
+                    record.value.len()
+                );
+
+                continue;
+            }
+
+            if records_size + size > max_size {
+                let records = mem::take(&mut batch);
+                messages.push(DhtImport { records });
+                records_size = 0;
+            }
+
+            records_size += size;
+            batch.push(record);
+        }
+
+        if !batch.is_empty() {
+            messages.push(DhtImport { records: batch });
+        }
+
+        messages
+    }
+}
+
+/// This message is sent after a [`DhtImport`] operation and contains the
+/// number of records which have been stored and skipped.
+///
+/// Records are skipped if the peer has the same or a newer version already or
+/// if it is read-only.
+///
+/// [`DhtImport`]: struct.DhtImport.html
+#[derive(Debug, PartialEq)]
+pub struct DhtImportReply {
+    pub stored: u32,
+    pub skipped: u32,
+}
+
+/// Encoding of the payloads exchanged over an api connection
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Encoding {
+    /// The compact binary encoding documented in the [`codec`] module
+    ///
+    /// [`codec`]: ../codec/index.html
+    Binary,
+    /// Self-describing maps as documented in the [`cbor`] module
+    ///
+    /// [`cbor`]: ../cbor/index.html
+    Cbor,
+}
+
+/// This message is used to negotiate the encoding of the payloads exchanged
+/// over an api connection.
+///
+/// The message itself is sent in the current encoding of the connection,
+/// which is binary for a new connection. The DHT module replies with an
+/// `ApiEncoding` message containing the encoding it has chosen, which is
+/// [`Encoding::Binary`] if the requested encoding is not enabled. Both sides
+/// use the chosen encoding for all following messages on the connection.
+///
+/// A client which belongs to a [tenant] authenticates the connection with
+/// its `token`. The reply never contains a token and the connection is
+/// closed if the token is unknown.
+///
+/// [`Encoding::Binary`]: enum.Encoding.html#variant.Binary
+/// [tenant]: ../../tenant/index.html
+#[derive(Debug, PartialEq)]
+pub struct ApiEncoding {
+    pub encoding: Encoding,
+    pub token: Option<String>,
+}
+
+fn read_list_cursor(reader: &mut dyn Read) -> io::Result<Option<ListCursor>> {
+    // the cursor is omitted for the first page
+    let mut replication_index = [0; 1];
+
+    if reader.read(&mut replication_index)? == 0 {
+        return Ok(None);
+    }
+
+    // Skip reserved fields
+    reader.read_u8()?;
+    reader.read_u8()?;
+    reader.read_u8()?;
+
+    Ok(Some(ListCursor {
+        key: read_key(reader)?,
+        replication_index: replication_index[0],
+    }))
 }
 
-/// This message is used to ask the DHT method to search for a given key and
-/// provide the value if a value for the corresponding is found in the network.
-///
-/// No immediate is reply is expected after sending this message to the DHT
-/// module. The module should however start with its best effort to search for
-/// the given key.
-#[derive(Debug, PartialEq)]
-pub struct DhtGet {
-    pub key: [u8; 32],
-}
+fn write_list_cursor(writer: &mut dyn Write, cursor: Option<ListCursor>) -> io::Result<()> {
+    if let Some(cursor) = cursor {
+        writer.write_u8(cursor.replication_index)?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        writer.write_all(&cursor.key.raw())?;
+    }
+
+    Ok(())
+}
+
+fn read_stored_records(reader: &mut dyn Read) -> io::Result<Vec<StoredRecord>> {
+    let mut records = Vec::new();
+    let mut replication_index = [0; 1];
+
+    while reader.read(&mut replication_index)? > 0 {
+        let replicas = reader.read_u8()?;
+        let ttl = reader.read_u64::<NetworkEndian>()?;
+        let size = reader.read_u32::<NetworkEndian>()?;
+        let version = reader.read_u64::<NetworkEndian>()?;
+        let key = read_key(reader)?;
+
+        let mut value = Vec::new();
+        reader.take(u64::from(size)).read_to_end(&mut value)?;
+
+        if value.len() != size as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Incomplete value",
+            ));
+        }
+
+        records.push(StoredRecord {
+            key,
+            replication_index: replication_index[0],
+            replicas,
+            ttl,
+            version,
+            value: value.into(),
+        });
+    }
+
+    Ok(records)
+}
+
+fn write_stored_records(writer: &mut dyn Write, records: &[StoredRecord]) -> io::Result<()> {
+    for record in records {
+        writer.write_u8(record.replication_index)?;
+        writer.write_u8(record.replicas)?;
+        writer.write_u64::<NetworkEndian>(record.ttl)?;
+        writer.write_u32::<NetworkEndian>(record.value.len() as u32)?;
+        writer.write_u64::<NetworkEndian>(record.version)?;
+        writer.write_all(&record.key.raw())?;
+        writer.write_all(&record.value)?;
+    }
+
+    Ok(())
+}
+
+fn read_bool(reader: &mut dyn Read) -> io::Result<bool> {
+    let value = reader.read_u8()? != 0;
+
+    // Skip reserved fields
+    reader.read_u8()?;
+    reader.read_u8()?;
+    reader.read_u8()?;
+
+    Ok(value)
+}
+
+fn write_bool(writer: &mut dyn Write, value: bool) -> io::Result<()> {
+    writer.write_u8(value as u8)?;
+
+    // Fill reserved fields
+    writer.write_u8(0)?;
+    writer.write_u8(0)?;
+    writer.write_u8(0)?;
+
+    Ok(())
+}
+
+fn read_key(reader: &mut dyn Read) -> io::Result<DhtKey> {
+    let mut raw_key = [0; 32];
+    reader.read_exact(&mut raw_key)?;
+
+    Ok(DhtKey::from(raw_key))
+}
+
+fn read_value(payload: &Bytes, reader: &mut Cursor<&[u8]>) -> io::Result<DhtValue> {
+    DhtValue::new(codec::read_remaining(payload, reader))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn read_summary(reader: &mut dyn Read) -> io::Result<Summary> {
+    Ok(Summary {
+        count: reader.read_u32::<NetworkEndian>()?,
+        mean: reader.read_f32::<NetworkEndian>()?,
+        p50: reader.read_u32::<NetworkEndian>()?,
+        p90: reader.read_u32::<NetworkEndian>()?,
+        p99: reader.read_u32::<NetworkEndian>()?,
+        max: reader.read_u32::<NetworkEndian>()?,
+    })
+}
+
+fn write_summary(writer: &mut dyn Write, summary: &Summary) -> io::Result<()> {
+    writer.write_u32::<NetworkEndian>(summary.count)?;
+    writer.write_f32::<NetworkEndian>(summary.mean)?;
+    writer.write_u32::<NetworkEndian>(summary.p50)?;
+    writer.write_u32::<NetworkEndian>(summary.p90)?;
+    writer.write_u32::<NetworkEndian>(summary.p99)?;
+    writer.write_u32::<NetworkEndian>(summary.max)?;
+
+    Ok(())
+}
+
+impl DhtPut {
+    /// Returns the message type this message is transmitted with.
+    ///
+    /// A time to live of whole seconds which fits into the `u16` of the
+    /// original layout is sent as [`DHT_PUT`] such that older peers still
+    /// understand it, any other time to live as [`DHT_PUT_V2`].
+    ///
+    /// [`DHT_PUT`]: ../codec/constant.DHT_PUT.html
+    /// [`DHT_PUT_V2`]: ../codec/constant.DHT_PUT_V2.html
+    pub fn message_type(&self) -> u16 {
+        if self.ttl.is_multiple_of(1000) && self.ttl / 1000 <= u64::from(u16::MAX) {
+            codec::DHT_PUT
+        } else {
+            codec::DHT_PUT_V2
+        }
+    }
+
+    /// Parses the payload of a [`DHT_PUT_V2`] message which carries the time
+    /// to live in milliseconds.
+    ///
+    /// [`DHT_PUT_V2`]: ../codec/constant.DHT_PUT_V2.html
+    pub fn parse_v2(payload: Bytes) -> io::Result<Self> {
+        let mut reader = Cursor::new(&payload[..]);
+
+        let ttl = reader.read_u64::<NetworkEndian>()?;
+        let replication = reader.read_u8()?;
+        let acks = reader.read_u8()?;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        let key = read_key(&mut reader)?;
+        let value = read_value(&payload, &mut reader)?;
+
+        Ok(DhtPut {
+            ttl,
+            replication,
+            acks,
+            key,
+            value,
+        })
+    }
+}
+
+impl MessagePayload for DhtPut {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        Self::parse_bytes(codec::read_payload(reader)?)
+    }
+
+    fn parse_bytes(payload: Bytes) -> io::Result<Self> {
+        let mut reader = Cursor::new(&payload[..]);
+
+        let ttl = reader.read_u16::<NetworkEndian>()?;
+        let replication = reader.read_u8()?;
+        let acks = reader.read_u8()?;
+
+        let key = read_key(&mut reader)?;
+        let value = read_value(&payload, &mut reader)?;
+
+        Ok(DhtPut {
+            ttl: u64::from(ttl) * 1000,
+            replication,
+            acks,
+            key,
+            value,
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        if self.message_type() == codec::DHT_PUT {
+            writer.write_u16::<NetworkEndian>((self.ttl / 1000) as u16)?;
+            writer.write_u8(self.replication)?;
+            writer.write_u8(self.acks)?;
+        } else {
+            writer.write_u64::<NetworkEndian>(self.ttl)?;
+            writer.write_u8(self.replication)?;
+            writer.write_u8(self.acks)?;
+
+            // Fill reserved fields
+            writer.write_u8(0)?;
+            writer.write_u8(0)?;
+        }
+        writer.write_all(&self.key.raw())?;
+        writer.write_all(self.value.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtPutSuccess {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let acks = reader.read_u8()?;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        let key = read_key(reader)?;
+        let version = read_optional_u64(reader)?;
+
+        Ok(DhtPutSuccess { acks, key, version })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u8(self.acks)?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        writer.write_all(&self.key.raw())?;
+
+        if let Some(version) = self.version {
+            writer.write_u64::<NetworkEndian>(version)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtGet {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let key = read_key(reader)?;
+
+        let mut quorum = [0; 2];
+
+        // the quorum is optional for compatibility with older clients
+        let quorum = if reader.read(&mut quorum[..1])? == 0 {
+            None
+        } else {
+            reader.read_exact(&mut quorum[1..])?;
+
+            // zero replicas are sent if only a budget is given
+            Some(Quorum {
+                replicas: quorum[0],
+                reads: quorum[1],
+            })
+            .filter(|quorum| quorum.replicas > 0)
+        };
+
+        // a budget of u32::MAX is sent if only a request ID or version is given
+        let budget = read_optional_u32(reader)?.filter(|&budget| budget != u32::MAX);
+
+        // a request ID of u32::MAX is sent if only a version is given
+        let request_id = read_optional_u32(reader)?.filter(|&request_id| request_id != u32::MAX);
+        let if_none_match = read_optional_u64(reader)?;
+
+        Ok(DhtGet {
+            key,
+            quorum,
+            budget,
+            request_id,
+            if_none_match,
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&self.key.raw())?;
+
+        if let Some(quorum) = self.quorum {
+            writer.write_u8(quorum.replicas)?;
+            writer.write_u8(quorum.reads)?;
+        } else if self.budget.is_some() || self.request_id.is_some() || self.if_none_match.is_some()
+        {
+            writer.write_u8(0)?;
+            writer.write_u8(0)?;
+        }
+
+        if self.request_id.is_some() || self.if_none_match.is_some() {
+            writer.write_u32::<NetworkEndian>(self.budget.unwrap_or(u32::MAX))?;
+        } else {
+            write_optional_u32(writer, self.budget)?;
+        }
+
+        if let Some(version) = self.if_none_match {
+            writer.write_u32::<NetworkEndian>(self.request_id.unwrap_or(u32::MAX))?;
+            writer.write_u64::<NetworkEndian>(version)?;
+        } else {
+            write_optional_u32(writer, self.request_id)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtSuccess {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        Self::parse_bytes(codec::read_payload(reader)?)
+    }
+
+    fn parse_bytes(payload: Bytes) -> io::Result<Self> {
+        let mut reader = Cursor::new(&payload[..]);
+
+        let key = read_key(&mut reader)?;
+        let value = read_value(&payload, &mut reader)?;
+
+        Ok(DhtSuccess {
+            key,
+            value,
+            metadata: None,
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&self.key.raw())?;
+        writer.write_all(self.value.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtFailure {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let key = read_key(reader)?;
+
+        Ok(DhtFailure { key })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&self.key.raw())?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtNotModified {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let key = read_key(reader)?;
+
+        Ok(DhtNotModified { key })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&self.key.raw())?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtPrefetch {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let mut keys = Vec::new();
+        let mut key = [0; 32];
+
+        while reader.read(&mut key[..1])? > 0 {
+            reader.read_exact(&mut key[1..])?;
+
+            keys.push(DhtKey::from(key));
+        }
+
+        Ok(DhtPrefetch { keys })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        for key in &self.keys {
+            writer.write_all(&key.raw())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtResolve {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let replication_index = reader.read_u8()?;
+        let trace = reader.read_u8()? & TRACE_FLAG != 0;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        let key = read_key(reader)?;
+
+        Ok(DhtResolve {
+            replication_index,
+            trace,
+            key,
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u8(self.replication_index)?;
+        writer.write_u8(if self.trace { TRACE_FLAG } else { 0 })?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        writer.write_all(&self.key.raw())?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtResolveReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let replication_index = reader.read_u8()?;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        let key = read_key(reader)?;
+
+        let mut id_arr = [0; 32];
+        reader.read_exact(&mut id_arr)?;
+        let identifier = Identifier::new(&id_arr);
+        let socket_addr = read_socket_addr(reader)?;
+        let path = read_socket_addrs(reader)?;
+
+        Ok(DhtResolveReply {
+            replication_index,
+            key,
+            identifier,
+            socket_addr,
+            path,
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u8(self.replication_index)?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        writer.write_all(&self.key.raw())?;
+        writer.write_all(&self.identifier.as_bytes())?;
+        write_socket_addr(writer, self.socket_addr)?;
+        write_socket_addrs(writer, &self.path)?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for NodeInfo {
+    fn parse(_reader: &mut dyn Read) -> io::Result<Self> {
+        Ok(NodeInfo)
+    }
+
+    fn write_to(&self, _writer: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl MessagePayload for NodeInfoReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let mut id_arr = [0; 32];
+        reader.read_exact(&mut id_arr)?;
+        let identifier = Identifier::new(&id_arr);
+
+        let socket_addr = read_socket_addr(reader)?;
+        let predecessor = read_socket_addr(reader)?;
+        let successor = read_socket_addr(reader)?;
+
+        let lookup_stats = LookupStats {
+            lookups: reader.read_u64::<NetworkEndian>()?,
+            failures: reader.read_u64::<NetworkEndian>()?,
+            hops: read_summary(reader)?,
+            latency: read_summary(reader)?,
+        };
+
+        let traffic = match read_optional_u64(reader)? {
+            Some(bytes_received) => Some(Traffic {
+                bytes_received,
+                bytes_sent: reader.read_u64::<NetworkEndian>()?,
+                throttled: reader.read_u64::<NetworkEndian>()?,
+            }),
+            None => None,
+        };
+
+        Ok(NodeInfoReply {
+            identifier,
+            socket_addr,
+            predecessor,
+            successor,
+            lookup_stats,
+            traffic,
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&self.identifier.as_bytes())?;
+
+        write_socket_addr(writer, self.socket_addr)?;
+        write_socket_addr(writer, self.predecessor)?;
+        write_socket_addr(writer, self.successor)?;
+
+        writer.write_u64::<NetworkEndian>(self.lookup_stats.lookups)?;
+        writer.write_u64::<NetworkEndian>(self.lookup_stats.failures)?;
+        write_summary(writer, &self.lookup_stats.hops)?;
+        write_summary(writer, &self.lookup_stats.latency)?;
+
+        if let Some(traffic) = self.traffic {
+            writer.write_u64::<NetworkEndian>(traffic.bytes_received)?;
+            writer.write_u64::<NetworkEndian>(traffic.bytes_sent)?;
+            writer.write_u64::<NetworkEndian>(traffic.throttled)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtDelete {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let replication = reader.read_u8()?;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        let key = read_key(reader)?;
+
+        Ok(DhtDelete { replication, key })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u8(self.replication)?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        writer.write_all(&self.key.raw())?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtDeleteReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let replicas = reader.read_u8()?;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        let key = read_key(reader)?;
+
+        Ok(DhtDeleteReply { replicas, key })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u8(self.replicas)?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        writer.write_all(&self.key.raw())?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtCancel {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let request_id = reader.read_u32::<NetworkEndian>()?;
+
+        Ok(DhtCancel { request_id })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u32::<NetworkEndian>(self.request_id)?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtFlush {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let scope = reader.read_u8()?;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        let scope = match scope {
+            codec::FLUSH_ALL => FlushScope::All,
+            codec::FLUSH_NAMESPACE => {
+                let mut prefix = Vec::new();
+                reader.read_to_end(&mut prefix)?;
+
+                FlushScope::Namespace(prefix)
+            }
+            codec::FLUSH_EXPIRED => FlushScope::Expired,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid flush scope",
+                ))
+            }
+        };
+
+        Ok(DhtFlush { scope })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let scope = match self.scope {
+            FlushScope::All => codec::FLUSH_ALL,
+            FlushScope::Namespace(_) => codec::FLUSH_NAMESPACE,
+            FlushScope::Expired => codec::FLUSH_EXPIRED,
+        };
+
+        writer.write_u8(scope)?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        if let FlushScope::Namespace(prefix) = &self.scope {
+            writer.write_all(prefix)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtFlushReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let records = reader.read_u32::<NetworkEndian>()?;
+
+        Ok(DhtFlushReply { records })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u32::<NetworkEndian>(self.records)?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for NodeDrain {
+    fn parse(_reader: &mut dyn Read) -> io::Result<Self> {
+        Ok(NodeDrain)
+    }
+
+    fn write_to(&self, _writer: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl MessagePayload for NodeDrainReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let records = reader.read_u32::<NetworkEndian>()?;
+
+        Ok(NodeDrainReply { records })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u32::<NetworkEndian>(self.records)?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for NodeReadOnly {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let read_only = read_bool(reader)?;
+
+        Ok(NodeReadOnly { read_only })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_bool(writer, self.read_only)
+    }
+}
+
+impl MessagePayload for NodeReadOnlyReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let read_only = read_bool(reader)?;
+
+        Ok(NodeReadOnlyReply { read_only })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_bool(writer, self.read_only)
+    }
+}
+
+impl MessagePayload for NodeLoad {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let samples = reader.read_u16::<NetworkEndian>()?;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        Ok(NodeLoad { samples })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u16::<NetworkEndian>(self.samples)?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for NodeLoadReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let sampled = reader.read_u16::<NetworkEndian>()?;
+        let weight = reader.read_u16::<NetworkEndian>()?;
+        let target_weight = reader.read_u16::<NetworkEndian>()?;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        let local = read_load(reader)?;
+        let average = read_load(reader)?;
+
+        Ok(NodeLoadReply {
+            sampled,
+            weight,
+            target_weight,
+            local,
+            average,
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u16::<NetworkEndian>(self.sampled)?;
+        writer.write_u16::<NetworkEndian>(self.weight)?;
+        writer.write_u16::<NetworkEndian>(self.target_weight)?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        write_load(writer, &self.local)?;
+        write_load(writer, &self.average)
+    }
+}
+
+impl MessagePayload for DhtListLocal {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let limit = reader.read_u16::<NetworkEndian>()?;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        let after = read_list_cursor(reader)?;
+
+        Ok(DhtListLocal { limit, after })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u16::<NetworkEndian>(self.limit)?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        write_list_cursor(writer, self.after)
+    }
+}
+
+impl MessagePayload for DhtListLocalReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let total_records = reader.read_u32::<NetworkEndian>()?;
+        let total_bytes = reader.read_u64::<NetworkEndian>()?;
+        let remaining = reader.read_u32::<NetworkEndian>()?;
+
+        let mut records = Vec::new();
+        let mut replication_index = [0; 1];
+
+        while reader.read(&mut replication_index)? > 0 {
+            // Skip reserved field
+            reader.read_u8()?;
+
+            let ttl = reader.read_u64::<NetworkEndian>()?;
+            let size = reader.read_u32::<NetworkEndian>()?;
+            let version = reader.read_u64::<NetworkEndian>()?;
+            let key = read_key(reader)?;
+
+            records.push(LocalRecord {
+                key,
+                replication_index: replication_index[0],
+                ttl,
+                size,
+                version,
+            });
+        }
+
+        Ok(DhtListLocalReply {
+            total_records,
+            total_bytes,
+            remaining,
+            records,
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u32::<NetworkEndian>(self.total_records)?;
+        writer.write_u64::<NetworkEndian>(self.total_bytes)?;
+        writer.write_u32::<NetworkEndian>(self.remaining)?;
+
+        for record in &self.records {
+            writer.write_u8(record.replication_index)?;
+
+            // Fill reserved field
+            writer.write_u8(0)?;
+
+            writer.write_u64::<NetworkEndian>(record.ttl)?;
+            writer.write_u32::<NetworkEndian>(record.size)?;
+            writer.write_u64::<NetworkEndian>(record.version)?;
+            writer.write_all(&record.key.raw())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for NodePeers {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let limit = reader.read_u16::<NetworkEndian>()?;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        // the cursor is omitted for the first page
+        let mut after = [0; 2];
+
+        let after = if reader.read(&mut after[..1])? == 0 {
+            None
+        } else {
+            reader.read_exact(&mut after[1..])?;
+
+            // Skip reserved fields
+            reader.read_u8()?;
+            reader.read_u8()?;
+
+            Some(u16::from_be_bytes(after))
+        };
+
+        Ok(NodePeers { limit, after })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u16::<NetworkEndian>(self.limit)?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        if let Some(after) = self.after {
+            writer.write_u16::<NetworkEndian>(after)?;
+
+            // Fill reserved fields
+            writer.write_u8(0)?;
+            writer.write_u8(0)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for NodePeersReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let total_peers = reader.read_u16::<NetworkEndian>()?;
+        let remaining = reader.read_u16::<NetworkEndian>()?;
+
+        let mut peers = Vec::new();
+        let mut index = [0; 2];
+
+        while reader.read(&mut index[..1])? > 0 {
+            reader.read_exact(&mut index[1..])?;
+
+            let age = reader.read_u16::<NetworkEndian>()?;
+
+            let mut id_arr = [0; 32];
+            reader.read_exact(&mut id_arr)?;
+
+            peers.push(PeerEntry {
+                index: u16::from_be_bytes(index),
+                age,
+                identifier: Identifier::new(&id_arr),
+                socket_addr: read_socket_addr(reader)?,
+            });
+        }
+
+        Ok(NodePeersReply {
+            total_peers,
+            remaining,
+            peers,
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u16::<NetworkEndian>(self.total_peers)?;
+        writer.write_u16::<NetworkEndian>(self.remaining)?;
+
+        for peer in &self.peers {
+            writer.write_u16::<NetworkEndian>(peer.index)?;
+            writer.write_u16::<NetworkEndian>(peer.age)?;
+
+            writer.write_all(&peer.identifier.as_bytes())?;
+            write_socket_addr(writer, peer.socket_addr)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtRingWalk {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let limit = reader.read_u16::<NetworkEndian>()?;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        // the cursor is omitted for the first page
+        let after = read_socket_addrs(reader)?.into_iter().next();
+
+        Ok(DhtRingWalk { limit, after })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u16::<NetworkEndian>(self.limit)?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        if let Some(after) = self.after {
+            write_socket_addr(writer, after)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtRingWalkReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let complete = read_bool(reader)?;
+
+        let mut peers = Vec::new();
+        let mut id_arr = [0; 32];
+
+        while reader.read(&mut id_arr[..1])? > 0 {
+            reader.read_exact(&mut id_arr[1..])?;
+
+            peers.push(RingPeer {
+                identifier: Identifier::new(&id_arr),
+                socket_addr: read_socket_addr(reader)?,
+            });
+        }
+
+        Ok(DhtRingWalkReply { complete, peers })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_bool(writer, self.complete)?;
+
+        for peer in &self.peers {
+            writer.write_all(&peer.identifier.as_bytes())?;
+            write_socket_addr(writer, peer.socket_addr)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for DhtExport {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let limit = reader.read_u16::<NetworkEndian>()?;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        let after = read_list_cursor(reader)?;
+
+        Ok(DhtExport { limit, after })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u16::<NetworkEndian>(self.limit)?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        write_list_cursor(writer, self.after)
+    }
+}
+
+impl MessagePayload for DhtExportReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let remaining = reader.read_u32::<NetworkEndian>()?;
+        let records = read_stored_records(reader)?;
+
+        Ok(DhtExportReply { remaining, records })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u32::<NetworkEndian>(self.remaining)?;
+
+        write_stored_records(writer, &self.records)
+    }
+}
+
+impl MessagePayload for DhtImport {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let records = read_stored_records(reader)?;
+
+        Ok(DhtImport { records })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_stored_records(writer, &self.records)
+    }
+}
+
+impl MessagePayload for DhtImportReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let stored = reader.read_u32::<NetworkEndian>()?;
+        let skipped = reader.read_u32::<NetworkEndian>()?;
+
+        Ok(DhtImportReply { stored, skipped })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u32::<NetworkEndian>(self.stored)?;
+        writer.write_u32::<NetworkEndian>(self.skipped)?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for ApiEncoding {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let encoding = match reader.read_u8()? {
+            codec::ENCODING_BINARY => Encoding::Binary,
+            codec::ENCODING_CBOR => Encoding::Cbor,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unknown encoding",
+                ))
+            }
+        };
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        // the token is omitted without a tenant
+        let mut token = Vec::new();
+        reader.read_to_end(&mut token)?;
+
+        let token =
+            match token.len() {
+                0 => None,
+                _ => Some(String::from_utf8(token).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "Token is not UTF-8")
+                })?),
+            };
+
+        Ok(ApiEncoding { encoding, token })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u8(match self.encoding {
+            Encoding::Binary => codec::ENCODING_BINARY,
+            Encoding::Cbor => codec::ENCODING_CBOR,
+        })?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        if let Some(ref token) = self.token {
+            writer.write_all(token.as_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::test_message_payload;
+    use super::*;
+
+    #[test]
+    fn dht_put() {
+        #[rustfmt::skip]
+        let buf = [
+            // TTL, replication and acks
+            0, 12, 4, 2,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // value
+            1, 2, 3, 4, 5,
+        ];
+
+        let msg = DhtPut {
+            ttl: 12_000,
+            replication: 4,
+            acks: 2,
+            key: DhtKey::from([3; 32]),
+            value: DhtValue::new(vec![1, 2, 3, 4, 5]).unwrap(),
+        };
+
+        assert_eq!(codec::DHT_PUT, msg.message_type());
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_put_v2() {
+        #[rustfmt::skip]
+        let buf = [
+            // TTL in milliseconds
+            0, 0, 0, 0, 0, 0, 1, 244,
+            // replication, acks and reserved
+            4, 2, 0, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // value
+            1, 2, 3, 4, 5,
+        ];
+
+        let msg = DhtPut {
+            ttl: 500,
+            replication: 4,
+            acks: 2,
+            key: DhtKey::from([3; 32]),
+            value: DhtValue::new(vec![1, 2, 3, 4, 5]).unwrap(),
+        };
+
+        assert_eq!(codec::DHT_PUT_V2, msg.message_type());
+        assert_eq!(msg, DhtPut::parse_v2(Bytes::copy_from_slice(&buf)).unwrap());
+
+        let mut vec = Vec::new();
+        msg.write_to(&mut vec).unwrap();
+        assert_eq!(&buf[..], &vec[..]);
+    }
+
+    #[test]
+    fn dht_put_ttl_beyond_u16_seconds() {
+        let msg = DhtPut {
+            ttl: (u64::from(u16::MAX) + 1) * 1000,
+            replication: 4,
+            acks: 0,
+            key: DhtKey::from([3; 32]),
+            value: DhtValue::new(vec![1, 2, 3]).unwrap(),
+        };
+
+        assert_eq!(codec::DHT_PUT_V2, msg.message_type());
+
+        let mut vec = Vec::new();
+        msg.write_to(&mut vec).unwrap();
+        assert_eq!(msg, DhtPut::parse_v2(Bytes::from(vec)).unwrap());
+    }
+
+    #[test]
+    fn dht_put_success() {
+        #[rustfmt::skip]
+        let buf = [
+            // acks and reserved
+            2, 0, 0, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        ];
+
+        let msg = DhtPutSuccess {
+            acks: 2,
+            key: DhtKey::from([3; 32]),
+            version: None,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_prefetch() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for each key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+            4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+        ];
+
+        let msg = DhtPrefetch {
+            keys: vec![DhtKey::from([3; 32]), DhtKey::from([4; 32])],
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_put_success_version() {
+        #[rustfmt::skip]
+        let buf = [
+            // acks and reserved
+            1, 0, 0, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // version
+            0, 0, 1, 106, 12, 7, 209, 123,
+        ];
+
+        let msg = DhtPutSuccess {
+            acks: 1,
+            key: DhtKey::from([3; 32]),
+            version: Some(1_554_980_000_123),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_get() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        ];
+
+        let msg = DhtGet {
+            key: DhtKey::from([3; 32]),
+            quorum: None,
+            budget: None,
+            request_id: None,
+            if_none_match: None,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_get_budget() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // no quorum
+            0, 0,
+            // budget
+            0, 0, 1, 244,
+        ];
+
+        let msg = DhtGet {
+            key: DhtKey::from([3; 32]),
+            quorum: None,
+            budget: Some(500),
+            request_id: None,
+            if_none_match: None,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_get_request_id() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // no quorum
+            0, 0,
+            // no budget
+            255, 255, 255, 255,
+            // request id
+            0, 0, 0, 7,
+        ];
+
+        let msg = DhtGet {
+            key: DhtKey::from([3; 32]),
+            quorum: None,
+            budget: None,
+            request_id: Some(7),
+            if_none_match: None,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_get_quorum() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // replicas and reads
+            3, 2,
+        ];
+
+        let msg = DhtGet {
+            key: DhtKey::from([3; 32]),
+            quorum: Some(Quorum {
+                replicas: 3,
+                reads: 2,
+            }),
+            budget: None,
+            request_id: None,
+            if_none_match: None,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_get_if_none_match() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // no quorum
+            0, 0,
+            // no budget
+            255, 255, 255, 255,
+            // no request id
+            255, 255, 255, 255,
+            // version
+            0, 0, 0, 0, 0, 0, 0, 9,
+        ];
+
+        let msg = DhtGet {
+            key: DhtKey::from([3; 32]),
+            quorum: None,
+            budget: None,
+            request_id: None,
+            if_none_match: Some(9),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_success() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // value
+            1, 2, 3, 4, 5,
+        ];
+
+        let msg = DhtSuccess {
+            key: DhtKey::from([3; 32]),
+            value: DhtValue::new(vec![1, 2, 3, 4, 5]).unwrap(),
+            metadata: None,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_failure() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        ];
+
+        let msg = DhtFailure {
+            key: DhtKey::from([3; 32]),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_not_modified() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        ];
+
+        let msg = DhtNotModified {
+            key: DhtKey::from([3; 32]),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_resolve() {
+        #[rustfmt::skip]
+        let buf = [
+            // replication index, flags and reserved
+            2, 1, 0, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        ];
+
+        let msg = DhtResolve {
+            replication_index: 2,
+            trace: true,
+            key: DhtKey::from([3; 32]),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_resolve_reply() {
+        #[rustfmt::skip]
+        let buf = [
+            // replication index and reserved
+            2, 0, 0, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // 32 bytes for identifier
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            // 16 bytes for ip address
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
+            // port
+            31, 144,
+            // 16 bytes for ip address of first hop
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
+            // port of first hop
+            31, 144,
+        ];
+
+        let msg = DhtResolveReply {
+            replication_index: 2,
+            key: DhtKey::from([3; 32]),
+            identifier: Identifier::new(&[5; 32]),
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            path: vec!["127.0.0.1:8080".parse().unwrap()],
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn node_info() {
+        test_message_payload(&[], NodeInfo);
+    }
+
+    #[test]
+    fn node_info_reply() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for identifier
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            // socket address
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1, 31, 144,
+            // predecessor
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 2, 31, 144,
+            // successor
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 3, 31, 144,
+            // lookups and failures
+            0, 0, 0, 0, 0, 0, 0, 12,
+            0, 0, 0, 0, 0, 0, 0, 1,
+            // hops: count, mean, p50, p90, p99 and max
+            0, 0, 0, 12, 64, 32, 0, 0, 0, 0, 0, 2, 0, 0, 0, 4, 0, 0, 0, 5, 0, 0, 0, 5,
+            // latency: count, mean, p50, p90, p99 and max
+            0, 0, 0, 12, 67, 250, 0, 0, 0, 0, 1, 244, 0, 0, 3, 232, 0, 0, 7, 208, 0, 0, 7, 208,
+            // bytes received, bytes sent and throttled messages
+            0, 0, 0, 0, 0, 1, 0, 0,
+            0, 0, 0, 0, 0, 0, 16, 0,
+            0, 0, 0, 0, 0, 0, 0, 3,
+        ];
+
+        let msg = NodeInfoReply {
+            identifier: Identifier::new(&[5; 32]),
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            predecessor: "127.0.0.2:8080".parse().unwrap(),
+            successor: "127.0.0.3:8080".parse().unwrap(),
+            lookup_stats: LookupStats {
+                lookups: 12,
+                failures: 1,
+                hops: Summary {
+                    count: 12,
+                    mean: 2.5,
+                    p50: 2,
+                    p90: 4,
+                    p99: 5,
+                    max: 5,
+                },
+                latency: Summary {
+                    count: 12,
+                    mean: 500.0,
+                    p50: 500,
+                    p90: 1000,
+                    p99: 2000,
+                    max: 2000,
+                },
+            },
+            traffic: Some(Traffic {
+                bytes_received: 65536,
+                bytes_sent: 4096,
+                throttled: 3,
+            }),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_delete() {
+        #[rustfmt::skip]
+        let buf = [
+            // replication and reserved
+            2, 0, 0, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        ];
+
+        let msg = DhtDelete {
+            replication: 2,
+            key: DhtKey::from([3; 32]),
+        };
 
-/// This message is sent when a previous [`DhtGet`] operation found a value
-/// corresponding to the requested key in the network.
-///
-/// [`DhtGet`]: struct.DhtGet.html
-#[derive(Debug, PartialEq)]
-pub struct DhtSuccess {
-    pub key: [u8; 32],
-    pub value: Vec<u8>,
-}
+        test_message_payload(&buf, msg);
+    }
 
-/// This message is sent when a previous [`DhtGet`] operation did not find any
-/// value for the requested key.
-///
-/// [`DhtGet`]: struct.DhtGet.html
-#[derive(Debug, PartialEq)]
-pub struct DhtFailure {
-    pub key: [u8; 32],
-}
+    #[test]
+    fn dht_delete_reply() {
+        #[rustfmt::skip]
+        let buf = [
+            // replicas and reserved
+            3, 0, 0, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        ];
 
-impl MessagePayload for DhtPut {
-    fn parse(reader: &mut Read) -> io::Result<Self> {
-        let ttl = reader.read_u16::<NetworkEndian>()?;
-        let replication = reader.read_u8()?;
+        let msg = DhtDeleteReply {
+            replicas: 3,
+            key: DhtKey::from([3; 32]),
+        };
 
-        // Skip reserved field
-        reader.read_u8()?;
+        test_message_payload(&buf, msg);
+    }
 
-        let mut key = [0; 32];
-        reader.read_exact(&mut key)?;
+    #[test]
+    fn dht_cancel() {
+        let buf = [0, 0, 1, 0];
 
-        let mut value = Vec::new();
-        reader.read_to_end(&mut value)?;
+        let msg = DhtCancel { request_id: 256 };
 
-        Ok(DhtPut {
-            ttl,
-            replication,
-            key,
-            value,
-        })
+        test_message_payload(&buf, msg);
     }
 
-    fn write_to(&self, writer: &mut Write) -> io::Result<()> {
-        writer.write_u16::<NetworkEndian>(self.ttl)?;
-        writer.write_u8(self.replication)?;
-        writer.write_u8(0)?;
-        writer.write_all(&self.key)?;
-        writer.write_all(&self.value)?;
+    #[test]
+    fn dht_flush_all() {
+        #[rustfmt::skip]
+        let buf = [
+            // scope and reserved
+            0, 0, 0, 0,
+        ];
 
-        Ok(())
+        let msg = DhtFlush {
+            scope: FlushScope::All,
+        };
+
+        test_message_payload(&buf, msg);
     }
-}
 
-impl MessagePayload for DhtGet {
-    fn parse(reader: &mut Read) -> io::Result<Self> {
-        let mut key = [0; 32];
-        reader.read_exact(&mut key)?;
+    #[test]
+    fn dht_flush_namespace() {
+        #[rustfmt::skip]
+        let buf = [
+            // scope and reserved
+            1, 0, 0, 0,
+            // prefix
+            1, 2, 3,
+        ];
+
+        let msg = DhtFlush {
+            scope: FlushScope::Namespace(vec![1, 2, 3]),
+        };
 
-        Ok(DhtGet { key })
+        test_message_payload(&buf, msg);
     }
 
-    fn write_to(&self, writer: &mut Write) -> io::Result<()> {
-        writer.write_all(&self.key)?;
+    #[test]
+    fn dht_flush_expired() {
+        #[rustfmt::skip]
+        let buf = [
+            // scope and reserved
+            2, 0, 0, 0,
+        ];
 
-        Ok(())
+        let msg = DhtFlush {
+            scope: FlushScope::Expired,
+        };
+
+        test_message_payload(&buf, msg);
     }
-}
 
-impl MessagePayload for DhtSuccess {
-    fn parse(reader: &mut Read) -> io::Result<Self> {
-        let mut key = [0; 32];
-        reader.read_exact(&mut key)?;
+    #[test]
+    fn dht_flush_invalid_scope() {
+        let buf = [3, 0, 0, 0];
 
-        let mut value = Vec::new();
-        reader.read_to_end(&mut value)?;
+        let err = DhtFlush::parse(&mut &buf[..]).err().unwrap();
 
-        Ok(DhtSuccess { key, value })
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
     }
 
-    fn write_to(&self, writer: &mut Write) -> io::Result<()> {
-        writer.write_all(&self.key)?;
-        writer.write_all(&self.value)?;
+    #[test]
+    fn dht_flush_reply() {
+        #[rustfmt::skip]
+        let buf = [
+            // number of removed records
+            0, 0, 1, 2,
+        ];
+
+        let msg = DhtFlushReply { records: 258 };
 
-        Ok(())
+        test_message_payload(&buf, msg);
     }
-}
 
-impl MessagePayload for DhtFailure {
-    fn parse(reader: &mut Read) -> io::Result<Self> {
-        let mut key = [0; 32];
-        reader.read_exact(&mut key)?;
+    #[test]
+    fn node_drain() {
+        test_message_payload(&[], NodeDrain);
+    }
 
-        Ok(DhtFailure { key })
+    #[test]
+    fn node_drain_reply() {
+        #[rustfmt::skip]
+        let buf = [
+            // number of transferred records
+            0, 0, 1, 2,
+        ];
+
+        let msg = NodeDrainReply { records: 258 };
+
+        test_message_payload(&buf, msg);
     }
 
-    fn write_to(&self, writer: &mut Write) -> io::Result<()> {
-        writer.write_all(&self.key)?;
+    #[test]
+    fn node_read_only() {
+        #[rustfmt::skip]
+        let buf = [
+            // read-only flag and reserved
+            1, 0, 0, 0,
+        ];
 
-        Ok(())
+        let msg = NodeReadOnly { read_only: true };
+
+        test_message_payload(&buf, msg);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::super::tests::test_message_payload;
-    use super::*;
+    #[test]
+    fn node_read_only_reply() {
+        #[rustfmt::skip]
+        let buf = [
+            // read-only flag and reserved
+            0, 0, 0, 0,
+        ];
+
+        let msg = NodeReadOnlyReply { read_only: false };
+
+        test_message_payload(&buf, msg);
+    }
 
     #[test]
-    fn dht_put() {
+    fn node_load() {
         #[rustfmt::skip]
         let buf = [
-            // TTL, replication and reserved
-            0, 12, 4, 0,
+            // samples and reserved
+            0, 16, 0, 0,
+        ];
+
+        let msg = NodeLoad { samples: 16 };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn node_load_reply() {
+        #[rustfmt::skip]
+        let buf = [
+            // sampled, weight, target weight and reserved
+            0, 8, 0, 2, 0, 1, 0, 0,
+            // local share, bytes, requests and records
+            64, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 16, 0,
+            0, 0, 0, 0, 0, 0, 0, 100,
+            0, 0, 0, 40,
+            // average share, bytes, requests and records
+            16, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 4, 0,
+            0, 0, 0, 0, 0, 0, 0, 25,
+            0, 0, 0, 10,
+        ];
+
+        let msg = NodeLoadReply {
+            sampled: 8,
+            weight: 2,
+            target_weight: 1,
+            local: Load {
+                share: 1 << 62,
+                records: 40,
+                bytes: 4096,
+                requests: 100,
+            },
+            average: Load {
+                share: 1 << 60,
+                records: 10,
+                bytes: 1024,
+                requests: 25,
+            },
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_list_local() {
+        #[rustfmt::skip]
+        let buf = [
+            // limit and reserved
+            0, 100, 0, 0,
+            // replication index and reserved
+            1, 0, 0, 0,
             // 32 bytes for key
             3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
             3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
-            // value
-            1, 2, 3, 4, 5,
         ];
 
-        let msg = DhtPut {
-            ttl: 12,
-            replication: 4,
-            key: [3; 32],
-            value: vec![1, 2, 3, 4, 5],
+        let msg = DhtListLocal {
+            limit: 100,
+            after: Some(ListCursor {
+                key: DhtKey::from([3; 32]),
+                replication_index: 1,
+            }),
         };
 
         test_message_payload(&buf, msg);
+        test_message_payload(
+            &[0, 100, 0, 0],
+            DhtListLocal {
+                limit: 100,
+                after: None,
+            },
+        );
     }
 
     #[test]
-    fn dht_get() {
+    fn dht_list_local_reply() {
         #[rustfmt::skip]
         let buf = [
+            // total records
+            0, 0, 0, 5,
+            // total bytes
+            0, 0, 0, 0, 0, 0, 1, 0,
+            // remaining
+            0, 0, 0, 4,
+            // replication index and reserved
+            1, 0,
+            // ttl of more than a day
+            0, 0, 0, 0, 5, 245, 225, 0,
+            // size
+            0, 0, 0, 3,
+            // version
+            0, 0, 0, 0, 0, 0, 0, 9,
             // 32 bytes for key
             3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
             3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
         ];
 
-        let msg = DhtGet { key: [3; 32] };
+        let msg = DhtListLocalReply {
+            total_records: 5,
+            total_bytes: 256,
+            remaining: 4,
+            records: vec![LocalRecord {
+                key: DhtKey::from([3; 32]),
+                replication_index: 1,
+                ttl: 100_000_000,
+                size: 3,
+                version: 9,
+            }],
+        };
+
+        assert_eq!(
+            Some(ListCursor {
+                key: DhtKey::from([3; 32]),
+                replication_index: 1,
+            }),
+            msg.next_cursor()
+        );
 
         test_message_payload(&buf, msg);
     }
 
     #[test]
-    fn dht_success() {
+    fn node_peers() {
+        #[rustfmt::skip]
+        let buf = [
+            // limit and reserved
+            0, 10, 0, 0,
+            // index and reserved
+            0, 9, 0, 0,
+        ];
+
+        let msg = NodePeers {
+            limit: 10,
+            after: Some(9),
+        };
+
+        test_message_payload(&buf, msg);
+        test_message_payload(
+            &[0, 10, 0, 0],
+            NodePeers {
+                limit: 10,
+                after: None,
+            },
+        );
+    }
+
+    #[test]
+    fn node_peers_reply() {
+        #[rustfmt::skip]
+        let buf = [
+            // total peers
+            0, 16,
+            // remaining
+            0, 6,
+            // index and age
+            0, 9, 0, 30,
+            // 32 bytes for identifier
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            // socket address
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1, 31, 144,
+        ];
+
+        let msg = NodePeersReply {
+            total_peers: 16,
+            remaining: 6,
+            peers: vec![PeerEntry {
+                index: 9,
+                age: 30,
+                identifier: Identifier::new(&[5; 32]),
+                socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            }],
+        };
+
+        assert_eq!(Some(9), msg.next_cursor());
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_ring_walk() {
+        #[rustfmt::skip]
+        let buf = [
+            // limit and reserved
+            0, 10, 0, 0,
+            // socket address
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1, 31, 144,
+        ];
+
+        let msg = DhtRingWalk {
+            limit: 10,
+            after: Some("127.0.0.1:8080".parse().unwrap()),
+        };
+
+        test_message_payload(&buf, msg);
+        test_message_payload(
+            &[0, 10, 0, 0],
+            DhtRingWalk {
+                limit: 10,
+                after: None,
+            },
+        );
+    }
+
+    #[test]
+    fn dht_ring_walk_reply() {
+        #[rustfmt::skip]
+        let buf = [
+            // complete and reserved
+            0, 0, 0, 0,
+            // 32 bytes for identifier
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            // socket address
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1, 31, 144,
+        ];
+
+        let msg = DhtRingWalkReply {
+            complete: false,
+            peers: vec![RingPeer {
+                identifier: Identifier::new(&[5; 32]),
+                socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            }],
+        };
+
+        assert_eq!(Some("127.0.0.1:8080".parse().unwrap()), msg.next_cursor());
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_export() {
+        #[rustfmt::skip]
+        let buf = [
+            // limit and reserved
+            0, 100, 0, 0,
+            // replication index and reserved
+            1, 0, 0, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        ];
+
+        let msg = DhtExport {
+            limit: 100,
+            after: Some(ListCursor {
+                key: DhtKey::from([3; 32]),
+                replication_index: 1,
+            }),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_export_reply() {
         #[rustfmt::skip]
         let buf = [
+            // remaining
+            0, 0, 0, 4,
+            // replication index and replicas
+            1, 2,
+            // ttl of more than a day
+            0, 0, 0, 0, 5, 245, 225, 0,
+            // size
+            0, 0, 0, 3,
+            // version
+            0, 0, 0, 0, 0, 0, 0, 9,
             // 32 bytes for key
             3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
             3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
             // value
-            1, 2, 3, 4, 5,
+            1, 2, 3,
         ];
 
-        let msg = DhtSuccess {
-            key: [3; 32],
-            value: vec![1, 2, 3, 4, 5],
+        let msg = DhtExportReply {
+            remaining: 4,
+            records: vec![StoredRecord {
+                key: DhtKey::from([3; 32]),
+                replication_index: 1,
+                replicas: 2,
+                ttl: 100_000_000,
+                version: 9,
+                value: vec![1, 2, 3].into(),
+            }],
         };
 
+        assert_eq!(
+            Some(ListCursor {
+                key: DhtKey::from([3; 32]),
+                replication_index: 1,
+            }),
+            msg.next_cursor()
+        );
+
         test_message_payload(&buf, msg);
     }
 
     #[test]
-    fn dht_failure() {
+    fn dht_import() {
         #[rustfmt::skip]
         let buf = [
+            // replication index and replicas
+            1, 2,
+            // ttl of more than a day
+            0, 0, 0, 0, 5, 245, 225, 0,
+            // size
+            0, 0, 0, 3,
+            // version
+            0, 0, 0, 0, 0, 0, 0, 9,
             // 32 bytes for key
             3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
             3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // value
+            1, 2, 3,
+        ];
+
+        let msg = DhtImport {
+            records: vec![StoredRecord {
+                key: DhtKey::from([3; 32]),
+                replication_index: 1,
+                replicas: 2,
+                ttl: 100_000_000,
+                version: 9,
+                value: vec![1, 2, 3].into(),
+            }],
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn dht_import_reply() {
+        let buf = [0, 0, 0, 5, 0, 0, 0, 2];
+
+        let msg = DhtImportReply {
+            stored: 5,
+            skipped: 2,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn api_encoding() {
+        #[rustfmt::skip]
+        let buf = [
+            // encoding and reserved
+            1, 0, 0, 0,
+        ];
+
+        let msg = ApiEncoding {
+            encoding: Encoding::Cbor,
+            token: None,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn api_encoding_token() {
+        #[rustfmt::skip]
+        let buf = [
+            // encoding and reserved
+            0, 0, 0, 0,
+            // token
+            b'a', b'p', b'p',
         ];
 
-        let msg = DhtFailure { key: [3; 32] };
+        let msg = ApiEncoding {
+            encoding: Encoding::Binary,
+            token: Some("app".to_string()),
+        };
 
         test_message_payload(&buf, msg);
     }
+
+    #[test]
+    fn api_encoding_unknown() {
+        let buf = [7, 0, 0, 0];
+
+        let err = ApiEncoding::parse(&mut &buf[..]).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn dht_import_pack() {
+        let record = |byte: u8, size: usize| StoredRecord {
+            key: DhtKey::from([byte; 32]),
+            replication_index: 0,
+            replicas: 1,
+            ttl: 60,
+            version: 1,
+            value: vec![byte; size].into(),
+        };
+
+        let records = vec![
+            record(1, 40000),
+            record(2, 40000),
+            record(3, MAX_MESSAGE_SIZE),
+            record(4, 10),
+        ];
+
+        let messages = DhtImport::pack(records);
+
+        // the oversized record is dropped and the order is kept
+        assert_eq!(2, messages.len());
+        assert_eq!(1, messages[0].records.len());
+        assert_eq!(
+            vec![DhtKey::from([2; 32]), DhtKey::from([4; 32])],
+            messages[1]
+                .records
+                .iter()
+                .map(|record| record.key)
+                .collect::<Vec<_>>()
+        );
+    }
 }
@@ -0,0 +1,399 @@
+//! Builders to construct messages in tests and tools
+//!
+//! The payloads of the messages are plain structs whose fields follow the
+//! wire format, e.g. times to live in milliseconds in peer-to-peer messages
+//! but in seconds in api messages. The builders take typed values instead
+//! and fill in defaults for all fields which are not set, such that harnesses
+//! and fuzzers can construct valid messages with a few calls.
+//!
+//! Use [`Message::to_bytes`] to encode a message including its header.
+//!
+//! # Examples
+//!
+//! ```
+//! # use chord::message::Message;
+//! # use chord::message::codec;
+//! # use chord::message::p2p::StoragePut;
+//! # use chord::storage::Key;
+//! # use std::time::Duration;
+//! #
+//! let storage_put = StoragePut::builder()
+//!     .ttl(Duration::from_secs(60))
+//!     .key(Key {
+//!         raw_key: [3; 32],
+//!         replication_index: 1,
+//!     })
+//!     .value(vec![1, 2, 3])
+//!     .build();
+//!
+//! assert_eq!(60_000, storage_put.ttl);
+//!
+//! let bytes = Message::StoragePut(storage_put).to_bytes().unwrap();
+//!
+//! assert_eq!(codec::STORAGE_PUT, u16::from_be_bytes([bytes[2], bytes[3]]));
+//! ```
+//!
+//! [`Message::to_bytes`]: ../enum.Message.html#method.to_bytes
+
+use super::api::{DhtGet, DhtPut, Quorum};
+use super::p2p::{StorageGet, StoragePut};
+use crate::dht::{DhtKey, DhtValue};
+use crate::storage::Key;
+use bytes::Bytes;
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Time to live of values built without an explicit one
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+/// Builder for [`StoragePut`] messages
+///
+/// By default, the value is empty, stored under the zero key with version
+/// one and lives for [`DEFAULT_TTL`].
+///
+/// [`StoragePut`]: ../p2p/struct.StoragePut.html
+/// [`DEFAULT_TTL`]: constant.DEFAULT_TTL.html
+#[derive(Clone, Debug)]
+pub struct StoragePutBuilder {
+    ttl: Duration,
+    key: Key,
+    replicas: u8,
+    namespace_len: u8,
+    version: u64,
+    value: Bytes,
+}
+
+impl StoragePut {
+    /// Returns a builder for a `StoragePut` message.
+    pub fn builder() -> StoragePutBuilder {
+        StoragePutBuilder {
+            ttl: DEFAULT_TTL,
+            key: Key {
+                raw_key: [0; 32],
+                replication_index: 0,
+            },
+            replicas: 0,
+            namespace_len: 0,
+            version: 1,
+            value: Vec::new().into(),
+        }
+    }
+}
+
+impl StoragePutBuilder {
+    /// Sets the time to live, saturating at `u64::MAX` milliseconds.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn key(mut self, key: Key) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Sets the total number of replicas of the value.
+    pub fn replicas(mut self, replicas: u8) -> Self {
+        self.replicas = replicas;
+        self
+    }
+
+    /// Sets the length of the prefix of the key which is its namespace.
+    pub fn namespace_len(mut self, namespace_len: u8) -> Self {
+        self.namespace_len = namespace_len;
+        self
+    }
+
+    pub fn version(mut self, version: u64) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn value<V: Into<Bytes>>(mut self, value: V) -> Self {
+        self.value = value.into();
+        self
+    }
+
+    pub fn build(self) -> StoragePut {
+        StoragePut {
+            ttl: u64::try_from(self.ttl.as_millis()).unwrap_or(u64::MAX),
+            replication_index: self.key.replication_index,
+            replicas: self.replicas,
+            namespace_len: self.namespace_len,
+            raw_key: self.key.raw_key,
+            version: self.version,
+            value: self.value,
+        }
+    }
+}
+
+/// Builder for [`StorageGet`] messages
+///
+/// By default, the zero key is read without any of the optional fields.
+///
+/// [`StorageGet`]: ../p2p/struct.StorageGet.html
+#[derive(Clone, Debug)]
+pub struct StorageGetBuilder {
+    key: Key,
+    budget: Option<Duration>,
+    if_none_match: Option<u64>,
+    requester: Option<SocketAddr>,
+}
+
+impl StorageGet {
+    /// Returns a builder for a `StorageGet` message.
+    pub fn builder() -> StorageGetBuilder {
+        StorageGetBuilder {
+            key: Key {
+                raw_key: [0; 32],
+                replication_index: 0,
+            },
+            budget: None,
+            if_none_match: None,
+            requester: None,
+        }
+    }
+}
+
+impl StorageGetBuilder {
+    pub fn key(mut self, key: Key) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Sets the time the peer may spend on the request, saturating at
+    /// `u32::MAX` milliseconds.
+    pub fn budget(mut self, budget: Duration) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Only requests the value if its version differs from `version`.
+    pub fn if_none_match(mut self, version: u64) -> Self {
+        self.if_none_match = Some(version);
+        self
+    }
+
+    /// Sets the peer on whose behalf the value is read.
+    pub fn requester(mut self, requester: SocketAddr) -> Self {
+        self.requester = Some(requester);
+        self
+    }
+
+    pub fn build(self) -> StorageGet {
+        StorageGet {
+            replication_index: self.key.replication_index,
+            raw_key: self.key.raw_key,
+            budget: self.budget.map(millis_u32),
+            if_none_match: self.if_none_match,
+            requester: self.requester,
+        }
+    }
+}
+
+/// Builder for [`DhtPut`] messages
+///
+/// By default, the value is empty, stored under the zero key on a single
+/// replica without acknowledgements and lives for [`DEFAULT_TTL`].
+///
+/// [`DhtPut`]: ../api/struct.DhtPut.html
+/// [`DEFAULT_TTL`]: constant.DEFAULT_TTL.html
+#[derive(Clone, Debug)]
+pub struct DhtPutBuilder {
+    ttl: Duration,
+    replication: u8,
+    acks: u8,
+    key: DhtKey,
+    value: DhtValue,
+}
+
+impl DhtPut {
+    /// Returns a builder for a `DhtPut` message.
+    pub fn builder() -> DhtPutBuilder {
+        DhtPutBuilder {
+            ttl: DEFAULT_TTL,
+            replication: 1,
+            acks: 0,
+            key: DhtKey::from([0; 32]),
+            value: DhtValue::default(),
+        }
+    }
+}
+
+impl DhtPutBuilder {
+    /// Sets the time to live, saturating at `u64::MAX` milliseconds.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets the number of replicas to store.
+    pub fn replication(mut self, replication: u8) -> Self {
+        self.replication = replication;
+        self
+    }
+
+    /// Sets the number of replicas which have to acknowledge the value.
+    pub fn acks(mut self, acks: u8) -> Self {
+        self.acks = acks;
+        self
+    }
+
+    pub fn key(mut self, key: DhtKey) -> Self {
+        self.key = key;
+        self
+    }
+
+    pub fn value(mut self, value: DhtValue) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn build(self) -> DhtPut {
+        DhtPut {
+            ttl: u64::try_from(self.ttl.as_millis()).unwrap_or(u64::MAX),
+            replication: self.replication,
+            acks: self.acks,
+            key: self.key,
+            value: self.value,
+        }
+    }
+}
+
+/// Builder for [`DhtGet`] messages
+///
+/// By default, the zero key is read without any of the optional fields.
+///
+/// [`DhtGet`]: ../api/struct.DhtGet.html
+#[derive(Clone, Debug)]
+pub struct DhtGetBuilder {
+    key: DhtKey,
+    quorum: Option<Quorum>,
+    budget: Option<Duration>,
+    request_id: Option<u32>,
+    if_none_match: Option<u64>,
+}
+
+impl DhtGet {
+    /// Returns a builder for a `DhtGet` message.
+    pub fn builder() -> DhtGetBuilder {
+        DhtGetBuilder {
+            key: DhtKey::from([0; 32]),
+            quorum: None,
+            budget: None,
+            request_id: None,
+            if_none_match: None,
+        }
+    }
+}
+
+impl DhtGetBuilder {
+    pub fn key(mut self, key: DhtKey) -> Self {
+        self.key = key;
+        self
+    }
+
+    pub fn quorum(mut self, quorum: Quorum) -> Self {
+        self.quorum = Some(quorum);
+        self
+    }
+
+    /// Sets the time the peer may spend on the request, saturating at
+    /// `u32::MAX` milliseconds.
+    pub fn budget(mut self, budget: Duration) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    pub fn request_id(mut self, request_id: u32) -> Self {
+        self.request_id = Some(request_id);
+        self
+    }
+
+    /// Only requests the value if its version differs from `version`.
+    pub fn if_none_match(mut self, version: u64) -> Self {
+        self.if_none_match = Some(version);
+        self
+    }
+
+    pub fn build(self) -> DhtGet {
+        DhtGet {
+            key: self.key,
+            quorum: self.quorum,
+            budget: self.budget.map(millis_u32),
+            request_id: self.request_id,
+            if_none_match: self.if_none_match,
+        }
+    }
+}
+
+fn millis_u32(duration: Duration) -> u32 {
+    u32::try_from(duration.as_millis()).unwrap_or(u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+
+    #[test]
+    fn storage_put_defaults() {
+        let storage_put = StoragePut::builder().build();
+
+        assert_eq!(
+            StoragePut {
+                ttl: 60_000,
+                replication_index: 0,
+                replicas: 0,
+                namespace_len: 0,
+                raw_key: [0; 32],
+                version: 1,
+                value: Vec::new().into(),
+            },
+            storage_put
+        );
+    }
+
+    #[test]
+    fn saturating_durations() {
+        let storage_get = StorageGet::builder()
+            .budget(Duration::from_secs(u64::MAX))
+            .build();
+        assert_eq!(Some(u32::MAX), storage_get.budget);
+
+        let dht_put = DhtPut::builder().ttl(Duration::from_secs(u64::MAX)).build();
+        assert_eq!(u64::MAX, dht_put.ttl);
+    }
+
+    #[test]
+    fn built_messages_round_trip() {
+        let key = Key {
+            raw_key: [7; 32],
+            replication_index: 2,
+        };
+        let messages = vec![
+            Message::StoragePut(StoragePut::builder().key(key).value(vec![1, 2]).build()),
+            Message::StorageGet(
+                StorageGet::builder()
+                    .key(key)
+                    .if_none_match(3)
+                    .requester("127.0.0.1:8080".parse().unwrap())
+                    .build(),
+            ),
+            Message::DhtPut(DhtPut::builder().replication(3).acks(2).build()),
+            Message::DhtGet(DhtGet::builder().request_id(5).build()),
+        ];
+
+        for msg in messages {
+            let bytes = msg.to_bytes().unwrap();
+
+            assert_eq!(
+                bytes.len(),
+                usize::from(u16::from_be_bytes([bytes[0], bytes[1]]))
+            );
+            assert_eq!(msg.message_type(), u16::from_be_bytes([bytes[2], bytes[3]]));
+            assert_eq!(msg, Message::parse(&bytes[..]).unwrap());
+        }
+    }
+}
@@ -0,0 +1,1732 @@
+//! Self-describing encoding of the api messages
+//!
+//! Api clients which negotiated [`Encoding::Cbor`] with an `API ENCODING`
+//! message exchange the payloads of all following messages as [CBOR] maps
+//! instead of the binary layouts documented in the [`codec`] module. Clients
+//! in other languages can thus rely on a CBOR library instead of
+//! implementing the binary layouts. The header of each message stays the
+//! same and peer-to-peer messages are always encoded in binary.
+//!
+//! The entries of a map are named like the fields of the message structs in
+//! the [`api`] module. Keys, identifiers and values are byte strings, socket
+//! addresses are texts like `"127.0.0.1:8080"` and nested structs are maps
+//! again. Optional fields are omitted or `null` if absent and unknown entries
+//! are ignored. For example, a `DHT GET` with a budget looks like this:
+//!
+//! ```text
+//! {
+//!   "key": h'0303...03',
+//!   "budget": 500
+//! }
+//! ```
+//!
+//! The `scope` of `DHT FLUSH` is one of the texts `"all"`, `"expired"` and
+//! `"namespace"`, the latter along with the byte string `prefix`. The
+//! `encoding` of `API ENCODING` is either `"binary"` or `"cbor"`. A
+//! `DHT PUT` and the records of `DHT LIST LOCAL REPLY`, `DHT EXPORT REPLY`
+//! and `DHT IMPORT` carry their time to live as `ttl_ms` in milliseconds and
+//! as `ttl` in whole seconds for older peers, which only read the latter.
+//!
+//! [CBOR]: https://cbor.io
+//! [`Encoding::Cbor`]: ../api/enum.Encoding.html#variant.Cbor
+//! [`codec`]: ../codec/index.html
+//! [`api`]: ../api/index.html
+
+use super::api::*;
+use super::codec::{self, HEADER_SIZE};
+use super::Message;
+use crate::dht::{DhtKey, DhtValue};
+use crate::load::Load;
+use crate::metrics::{LookupStats, Summary, Traffic};
+use crate::routing::identifier::Identifier;
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use bytes::Bytes;
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, Write};
+use std::net::SocketAddr;
+
+pub(crate) const UNSIGNED: u8 = 0;
+pub(crate) const NEGATIVE: u8 = 1;
+pub(crate) const BYTES: u8 = 2;
+pub(crate) const TEXT: u8 = 3;
+pub(crate) const ARRAY: u8 = 4;
+pub(crate) const MAP: u8 = 5;
+pub(crate) const TAG: u8 = 6;
+pub(crate) const SIMPLE: u8 = 7;
+
+/// Initial byte which ends an item of indefinite length
+pub(crate) const BREAK: u8 = 0xff;
+
+const FALSE: u8 = 20;
+const TRUE: u8 = 21;
+const NULL: u8 = 22;
+const HALF: u8 = 25;
+const SINGLE: u8 = 26;
+const DOUBLE: u8 = 27;
+
+/// Maximum nesting of arrays and maps within a message
+const MAX_DEPTH: usize = 8;
+
+/// Parses a message whose payload is encoded in CBOR.
+///
+/// Fails for message types which are not part of the api interface.
+pub fn parse<T: Read>(mut reader: T) -> io::Result<Message> {
+    let (size, msg_type) = codec::read_header(&mut reader)?;
+
+    let mut payload = Vec::new();
+    reader
+        .take(u64::from(size) - HEADER_SIZE as u64)
+        .read_to_end(&mut payload)?;
+
+    // an empty payload stands for an empty map
+    let fields = &mut if payload.is_empty() {
+        Fields::default()
+    } else {
+        Fields::from_value(read_value(&mut &payload[..], 0)?)?
+    };
+
+    match msg_type {
+        codec::DHT_PUT => CborPayload::from_fields(fields).map(Message::DhtPut),
+        codec::DHT_GET => CborPayload::from_fields(fields).map(Message::DhtGet),
+        codec::DHT_SUCCESS => CborPayload::from_fields(fields).map(Message::DhtSuccess),
+        codec::DHT_FAILURE => CborPayload::from_fields(fields).map(Message::DhtFailure),
+        codec::DHT_RESOLVE => CborPayload::from_fields(fields).map(Message::DhtResolve),
+        codec::DHT_RESOLVE_REPLY => CborPayload::from_fields(fields).map(Message::DhtResolveReply),
+        codec::NODE_INFO => Ok(Message::NodeInfo(NodeInfo)),
+        codec::NODE_INFO_REPLY => CborPayload::from_fields(fields).map(Message::NodeInfoReply),
+        codec::DHT_FLUSH => CborPayload::from_fields(fields).map(Message::DhtFlush),
+        codec::DHT_FLUSH_REPLY => CborPayload::from_fields(fields).map(Message::DhtFlushReply),
+        codec::DHT_PUT_SUCCESS => CborPayload::from_fields(fields).map(Message::DhtPutSuccess),
+        codec::DHT_DELETE => CborPayload::from_fields(fields).map(Message::DhtDelete),
+        codec::DHT_DELETE_REPLY => CborPayload::from_fields(fields).map(Message::DhtDeleteReply),
+        codec::DHT_CANCEL => CborPayload::from_fields(fields).map(Message::DhtCancel),
+        codec::NODE_DRAIN => Ok(Message::NodeDrain(NodeDrain)),
+        codec::NODE_DRAIN_REPLY => CborPayload::from_fields(fields).map(Message::NodeDrainReply),
+        codec::NODE_READ_ONLY => CborPayload::from_fields(fields).map(Message::NodeReadOnly),
+        codec::NODE_READ_ONLY_REPLY => {
+            CborPayload::from_fields(fields).map(Message::NodeReadOnlyReply)
+        }
+        codec::DHT_LIST_LOCAL => CborPayload::from_fields(fields).map(Message::DhtListLocal),
+        codec::DHT_LIST_LOCAL_REPLY => {
+            CborPayload::from_fields(fields).map(Message::DhtListLocalReply)
+        }
+        codec::NODE_PEERS => CborPayload::from_fields(fields).map(Message::NodePeers),
+        codec::NODE_PEERS_REPLY => CborPayload::from_fields(fields).map(Message::NodePeersReply),
+        codec::DHT_RING_WALK => CborPayload::from_fields(fields).map(Message::DhtRingWalk),
+        codec::DHT_RING_WALK_REPLY => {
+            CborPayload::from_fields(fields).map(Message::DhtRingWalkReply)
+        }
+        codec::DHT_EXPORT => CborPayload::from_fields(fields).map(Message::DhtExport),
+        codec::DHT_EXPORT_REPLY => CborPayload::from_fields(fields).map(Message::DhtExportReply),
+        codec::DHT_IMPORT => CborPayload::from_fields(fields).map(Message::DhtImport),
+        codec::DHT_IMPORT_REPLY => CborPayload::from_fields(fields).map(Message::DhtImportReply),
+        codec::API_ENCODING => CborPayload::from_fields(fields).map(Message::ApiEncoding),
+        codec::DHT_NOT_MODIFIED => CborPayload::from_fields(fields).map(Message::DhtNotModified),
+        codec::DHT_PREFETCH => CborPayload::from_fields(fields).map(Message::DhtPrefetch),
+        codec::NODE_LOAD => CborPayload::from_fields(fields).map(Message::NodeLoad),
+        codec::NODE_LOAD_REPLY => CborPayload::from_fields(fields).map(Message::NodeLoadReply),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Invalid message type",
+        )),
+    }
+}
+
+/// Writes a message with its payload encoded in CBOR and returns its size.
+///
+/// Fails for message types which are not part of the api interface.
+pub fn write_to<T: Write + Seek>(msg: &Message, mut writer: T) -> io::Result<usize> {
+    let (msg_type, fields) = match msg {
+        Message::DhtPut(dht_put) => (codec::DHT_PUT, dht_put.to_fields()),
+        Message::DhtGet(dht_get) => (codec::DHT_GET, dht_get.to_fields()),
+        Message::DhtSuccess(dht_success) => (codec::DHT_SUCCESS, dht_success.to_fields()),
+        Message::DhtFailure(dht_failure) => (codec::DHT_FAILURE, dht_failure.to_fields()),
+        Message::DhtResolve(dht_resolve) => (codec::DHT_RESOLVE, dht_resolve.to_fields()),
+        Message::DhtResolveReply(dht_resolve_reply) => {
+            (codec::DHT_RESOLVE_REPLY, dht_resolve_reply.to_fields())
+        }
+        Message::NodeInfo(_) => (codec::NODE_INFO, Fields::default()),
+        Message::NodeInfoReply(node_info_reply) => {
+            (codec::NODE_INFO_REPLY, node_info_reply.to_fields())
+        }
+        Message::DhtFlush(dht_flush) => (codec::DHT_FLUSH, dht_flush.to_fields()),
+        Message::DhtFlushReply(dht_flush_reply) => {
+            (codec::DHT_FLUSH_REPLY, dht_flush_reply.to_fields())
+        }
+        Message::DhtPutSuccess(dht_put_success) => {
+            (codec::DHT_PUT_SUCCESS, dht_put_success.to_fields())
+        }
+        Message::DhtDelete(dht_delete) => (codec::DHT_DELETE, dht_delete.to_fields()),
+        Message::DhtDeleteReply(dht_delete_reply) => {
+            (codec::DHT_DELETE_REPLY, dht_delete_reply.to_fields())
+        }
+        Message::DhtCancel(dht_cancel) => (codec::DHT_CANCEL, dht_cancel.to_fields()),
+        Message::NodeDrain(_) => (codec::NODE_DRAIN, Fields::default()),
+        Message::NodeDrainReply(node_drain_reply) => {
+            (codec::NODE_DRAIN_REPLY, node_drain_reply.to_fields())
+        }
+        Message::NodeReadOnly(node_read_only) => {
+            (codec::NODE_READ_ONLY, node_read_only.to_fields())
+        }
+        Message::NodeReadOnlyReply(node_read_only_reply) => (
+            codec::NODE_READ_ONLY_REPLY,
+            node_read_only_reply.to_fields(),
+        ),
+        Message::DhtListLocal(dht_list_local) => {
+            (codec::DHT_LIST_LOCAL, dht_list_local.to_fields())
+        }
+        Message::DhtListLocalReply(dht_list_local_reply) => (
+            codec::DHT_LIST_LOCAL_REPLY,
+            dht_list_local_reply.to_fields(),
+        ),
+        Message::NodePeers(node_peers) => (codec::NODE_PEERS, node_peers.to_fields()),
+        Message::NodePeersReply(node_peers_reply) => {
+            (codec::NODE_PEERS_REPLY, node_peers_reply.to_fields())
+        }
+        Message::DhtRingWalk(dht_ring_walk) => (codec::DHT_RING_WALK, dht_ring_walk.to_fields()),
+        Message::DhtRingWalkReply(dht_ring_walk_reply) => {
+            (codec::DHT_RING_WALK_REPLY, dht_ring_walk_reply.to_fields())
+        }
+        Message::DhtExport(dht_export) => (codec::DHT_EXPORT, dht_export.to_fields()),
+        Message::DhtExportReply(dht_export_reply) => {
+            (codec::DHT_EXPORT_REPLY, dht_export_reply.to_fields())
+        }
+        Message::DhtImport(dht_import) => (codec::DHT_IMPORT, dht_import.to_fields()),
+        Message::DhtImportReply(dht_import_reply) => {
+            (codec::DHT_IMPORT_REPLY, dht_import_reply.to_fields())
+        }
+        Message::ApiEncoding(api_encoding) => (codec::API_ENCODING, api_encoding.to_fields()),
+        Message::DhtNotModified(dht_not_modified) => {
+            (codec::DHT_NOT_MODIFIED, dht_not_modified.to_fields())
+        }
+        Message::DhtPrefetch(dht_prefetch) => (codec::DHT_PREFETCH, dht_prefetch.to_fields()),
+        Message::NodeLoad(node_load) => (codec::NODE_LOAD, node_load.to_fields()),
+        Message::NodeLoadReply(node_load_reply) => {
+            (codec::NODE_LOAD_REPLY, node_load_reply.to_fields())
+        }
+        msg => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not an api message", msg),
+            ))
+        }
+    };
+
+    // reserve two bytes for size
+    writer.write_u16::<NetworkEndian>(0)?;
+    writer.write_u16::<NetworkEndian>(msg_type)?;
+    write_value(&mut writer, &Value::from(fields))?;
+
+    // write size at beginning of writer
+    let size = writer.stream_position()?;
+
+    writer.seek(io::SeekFrom::Start(0))?;
+    writer.write_u16::<NetworkEndian>(size as u16)?;
+
+    Ok(size as usize)
+}
+
+/// A decoded data item
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Unsigned(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+    Bool(bool),
+    Float(f64),
+    Null,
+}
+
+macro_rules! unsigned_value {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for Value {
+                fn from(value: $ty) -> Self {
+                    Value::Unsigned(u64::from(value))
+                }
+            }
+
+            impl FromValue for $ty {
+                fn from_value(value: Value) -> Option<Self> {
+                    match value {
+                        Value::Unsigned(value) => <$ty>::try_from(value).ok(),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+unsigned_value!(u8, u16, u32, u64);
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Value::Float(f64::from(value))
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Text(value.to_string())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(value: Vec<u8>) -> Self {
+        Value::Bytes(value)
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(value: &[u8]) -> Self {
+        Value::Bytes(value.to_vec())
+    }
+}
+
+impl From<DhtKey> for Value {
+    fn from(key: DhtKey) -> Self {
+        Value::Bytes(key.raw().to_vec())
+    }
+}
+
+impl From<Identifier> for Value {
+    fn from(identifier: Identifier) -> Self {
+        Value::Bytes(identifier.as_bytes().to_vec())
+    }
+}
+
+impl From<SocketAddr> for Value {
+    fn from(socket_addr: SocketAddr) -> Self {
+        Value::Text(socket_addr.to_string())
+    }
+}
+
+impl From<Fields> for Value {
+    fn from(fields: Fields) -> Self {
+        Value::Map(fields.0)
+    }
+}
+
+/// Conversion of a decoded data item into a field of a message
+trait FromValue: Sized {
+    fn from_value(value: Value) -> Option<Self>;
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: Value) -> Option<Self> {
+        // encoders may pick the shortest representation of whole numbers
+        match value {
+            Value::Float(value) => Some(value as f32),
+            Value::Unsigned(value) => Some(value as f32),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for Vec<u8> {
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for Bytes {
+    fn from_value(value: Value) -> Option<Self> {
+        Vec::from_value(value).map(Bytes::from)
+    }
+}
+
+impl FromValue for DhtKey {
+    fn from_value(value: Value) -> Option<Self> {
+        Vec::from_value(value).and_then(|bytes| DhtKey::from_slice(&bytes).ok())
+    }
+}
+
+impl FromValue for DhtValue {
+    fn from_value(value: Value) -> Option<Self> {
+        Vec::from_value(value).and_then(|bytes| DhtValue::new(bytes).ok())
+    }
+}
+
+impl FromValue for Identifier {
+    fn from_value(value: Value) -> Option<Self> {
+        match Vec::from_value(value) {
+            Some(ref bytes) if bytes.len() == 32 => Some(Identifier::new(bytes)),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for SocketAddr {
+    fn from_value(value: Value) -> Option<Self> {
+        String::from_value(value).and_then(|text| text.parse().ok())
+    }
+}
+
+impl FromValue for Encoding {
+    fn from_value(value: Value) -> Option<Self> {
+        match String::from_value(value)?.as_str() {
+            "binary" => Some(Encoding::Binary),
+            "cbor" => Some(Encoding::Cbor),
+            _ => None,
+        }
+    }
+}
+
+/// Entries of a map which are built up or taken out by their names
+#[derive(Debug, Default)]
+struct Fields(Vec<(String, Value)>);
+
+impl Fields {
+    fn from_value(value: Value) -> io::Result<Self> {
+        match value {
+            Value::Map(entries) => Ok(Fields(entries)),
+            _ => Err(invalid_data("Expected a map")),
+        }
+    }
+
+    fn with<V: Into<Value>>(mut self, name: &str, value: V) -> Self {
+        self.0.push((name.to_string(), value.into()));
+        self
+    }
+
+    fn with_optional<V: Into<Value>>(self, name: &str, value: Option<V>) -> Self {
+        match value {
+            Some(value) => self.with(name, value),
+            None => self,
+        }
+    }
+
+    /// Adds a time to live of `ttl` milliseconds as `ttl_ms` and in whole
+    /// seconds as `ttl` for peers which only know the latter.
+    fn with_ttl(self, ttl: u64) -> Self {
+        let ttl_secs = u16::try_from(ttl / 1000).unwrap_or(u16::MAX);
+
+        self.with("ttl", ttl_secs).with("ttl_ms", ttl)
+    }
+
+    fn with_list<T: CborPayload>(self, name: &str, items: &[T]) -> Self {
+        let items = items.iter().map(|item| item.to_fields().into()).collect();
+        self.with(name, Value::Array(items))
+    }
+
+    fn take_value(&mut self, name: &str) -> Option<Value> {
+        let position = self.0.iter().position(|(entry, _)| entry == name)?;
+
+        match self.0.swap_remove(position).1 {
+            Value::Null => None,
+            value => Some(value),
+        }
+    }
+
+    fn take<T: FromValue>(&mut self, name: &str) -> io::Result<T> {
+        self.take_optional(name)?
+            .ok_or_else(|| invalid_data(format!("Missing entry {}", name)))
+    }
+
+    fn take_optional<T: FromValue>(&mut self, name: &str) -> io::Result<Option<T>> {
+        match self.take_value(name) {
+            Some(value) => T::from_value(value)
+                .map(Some)
+                .ok_or_else(|| invalid_data(format!("Invalid entry {}", name))),
+            None => Ok(None),
+        }
+    }
+
+    /// Takes the time to live in milliseconds added by [`with_ttl`] or the
+    /// whole seconds sent by older peers.
+    ///
+    /// [`with_ttl`]: #method.with_ttl
+    fn take_ttl(&mut self) -> io::Result<u64> {
+        let ttl_secs: u16 = self.take("ttl")?;
+
+        match self.take_optional("ttl_ms")? {
+            Some(ttl) => Ok(ttl),
+            None => Ok(u64::from(ttl_secs) * 1000),
+        }
+    }
+
+    fn take_array<T: FromValue>(&mut self, name: &str) -> io::Result<Vec<T>> {
+        match self.take_value(name) {
+            Some(Value::Array(items)) => items
+                .into_iter()
+                .map(|item| {
+                    T::from_value(item)
+                        .ok_or_else(|| invalid_data(format!("Invalid entry {}", name)))
+                })
+                .collect(),
+            Some(_) => Err(invalid_data(format!("Invalid entry {}", name))),
+            None => Err(invalid_data(format!("Missing entry {}", name))),
+        }
+    }
+
+    fn take_nested<T: CborPayload>(&mut self, name: &str) -> io::Result<Option<T>> {
+        match self.take_value(name) {
+            Some(value) => T::from_fields(&mut Fields::from_value(value)?).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn take_list<T: CborPayload>(&mut self, name: &str) -> io::Result<Vec<T>> {
+        match self.take_value(name) {
+            Some(Value::Array(items)) => items
+                .into_iter()
+                .map(|item| T::from_fields(&mut Fields::from_value(item)?))
+                .collect(),
+            Some(_) => Err(invalid_data(format!("Invalid entry {}", name))),
+            None => Err(invalid_data(format!("Missing entry {}", name))),
+        }
+    }
+}
+
+/// Conversion of a message or a nested struct from and into a map
+trait CborPayload: Sized {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self>;
+
+    fn to_fields(&self) -> Fields;
+}
+
+impl CborPayload for DhtPut {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtPut {
+            ttl: fields.take_ttl()?,
+            replication: fields.take("replication")?,
+            acks: fields.take_optional("acks")?.unwrap_or(0),
+            key: fields.take("key")?,
+            value: fields.take("value")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with_ttl(self.ttl)
+            .with("replication", self.replication)
+            .with("acks", self.acks)
+            .with("key", self.key)
+            .with("value", self.value.as_bytes())
+    }
+}
+
+impl CborPayload for DhtPutSuccess {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtPutSuccess {
+            acks: fields.take("acks")?,
+            key: fields.take("key")?,
+            version: fields.take_optional("version")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("acks", self.acks)
+            .with("key", self.key)
+            .with_optional("version", self.version)
+    }
+}
+
+impl CborPayload for Quorum {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(Quorum {
+            replicas: fields.take("replicas")?,
+            reads: fields.take("reads")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("replicas", self.replicas)
+            .with("reads", self.reads)
+    }
+}
+
+impl CborPayload for DhtGet {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtGet {
+            key: fields.take("key")?,
+            quorum: fields.take_nested("quorum")?,
+            budget: fields.take_optional("budget")?,
+            request_id: fields.take_optional("request_id")?,
+            if_none_match: fields.take_optional("if_none_match")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("key", self.key)
+            .with_optional("quorum", self.quorum.map(|quorum| quorum.to_fields()))
+            .with_optional("budget", self.budget)
+            .with_optional("request_id", self.request_id)
+            .with_optional("if_none_match", self.if_none_match)
+    }
+}
+
+impl CborPayload for DhtSuccess {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtSuccess {
+            key: fields.take("key")?,
+            value: fields.take("value")?,
+            metadata: fields.take_nested("metadata")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("key", self.key)
+            .with("value", self.value.as_bytes())
+            .with_optional(
+                "metadata",
+                self.metadata.map(|metadata| metadata.to_fields()),
+            )
+    }
+}
+
+impl CborPayload for RecordMetadata {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(RecordMetadata {
+            ttl: fields.take("ttl")?,
+            version: fields.take("version")?,
+            replication_index: fields.take("replication_index")?,
+            stored: fields.take("stored")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("ttl", self.ttl)
+            .with("version", self.version)
+            .with("replication_index", self.replication_index)
+            .with("stored", self.stored)
+    }
+}
+
+impl CborPayload for DhtFailure {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtFailure {
+            key: fields.take("key")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default().with("key", self.key)
+    }
+}
+
+impl CborPayload for DhtNotModified {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtNotModified {
+            key: fields.take("key")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default().with("key", self.key)
+    }
+}
+
+impl CborPayload for DhtPrefetch {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtPrefetch {
+            keys: fields.take_array("keys")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        let keys = self.keys.iter().map(|&key| key.into()).collect();
+
+        Fields::default().with("keys", Value::Array(keys))
+    }
+}
+
+impl CborPayload for DhtResolve {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtResolve {
+            replication_index: fields.take_optional("replication_index")?.unwrap_or(0),
+            trace: fields.take_optional("trace")?.unwrap_or(false),
+            key: fields.take("key")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("replication_index", self.replication_index)
+            .with("trace", self.trace)
+            .with("key", self.key)
+    }
+}
+
+impl CborPayload for DhtResolveReply {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtResolveReply {
+            replication_index: fields.take("replication_index")?,
+            key: fields.take("key")?,
+            identifier: fields.take("identifier")?,
+            socket_addr: fields.take("socket_addr")?,
+            path: fields.take_array("path")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        let path = self.path.iter().map(|&addr| addr.into()).collect();
+
+        Fields::default()
+            .with("replication_index", self.replication_index)
+            .with("key", self.key)
+            .with("identifier", self.identifier)
+            .with("socket_addr", self.socket_addr)
+            .with("path", Value::Array(path))
+    }
+}
+
+impl CborPayload for Summary {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(Summary {
+            count: fields.take("count")?,
+            mean: fields.take("mean")?,
+            p50: fields.take("p50")?,
+            p90: fields.take("p90")?,
+            p99: fields.take("p99")?,
+            max: fields.take("max")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("count", self.count)
+            .with("mean", self.mean)
+            .with("p50", self.p50)
+            .with("p90", self.p90)
+            .with("p99", self.p99)
+            .with("max", self.max)
+    }
+}
+
+impl CborPayload for LookupStats {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(LookupStats {
+            lookups: fields.take("lookups")?,
+            failures: fields.take("failures")?,
+            hops: fields.take_nested("hops")?.unwrap_or_default(),
+            latency: fields.take_nested("latency")?.unwrap_or_default(),
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("lookups", self.lookups)
+            .with("failures", self.failures)
+            .with("hops", self.hops.to_fields())
+            .with("latency", self.latency.to_fields())
+    }
+}
+
+impl CborPayload for Traffic {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(Traffic {
+            bytes_received: fields.take("bytes_received")?,
+            bytes_sent: fields.take("bytes_sent")?,
+            throttled: fields.take("throttled")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("bytes_received", self.bytes_received)
+            .with("bytes_sent", self.bytes_sent)
+            .with("throttled", self.throttled)
+    }
+}
+
+impl CborPayload for NodeInfoReply {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(NodeInfoReply {
+            identifier: fields.take("identifier")?,
+            socket_addr: fields.take("socket_addr")?,
+            predecessor: fields.take("predecessor")?,
+            successor: fields.take("successor")?,
+            lookup_stats: fields.take_nested("lookup_stats")?.unwrap_or_default(),
+            traffic: fields.take_nested("traffic")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("identifier", self.identifier)
+            .with("socket_addr", self.socket_addr)
+            .with("predecessor", self.predecessor)
+            .with("successor", self.successor)
+            .with("lookup_stats", self.lookup_stats.to_fields())
+            .with_optional("traffic", self.traffic.map(|traffic| traffic.to_fields()))
+    }
+}
+
+impl CborPayload for DhtDelete {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtDelete {
+            replication: fields.take("replication")?,
+            key: fields.take("key")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("replication", self.replication)
+            .with("key", self.key)
+    }
+}
+
+impl CborPayload for DhtDeleteReply {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtDeleteReply {
+            replicas: fields.take("replicas")?,
+            key: fields.take("key")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("replicas", self.replicas)
+            .with("key", self.key)
+    }
+}
+
+impl CborPayload for DhtCancel {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtCancel {
+            request_id: fields.take("request_id")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default().with("request_id", self.request_id)
+    }
+}
+
+impl CborPayload for DhtFlush {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        let scope = match fields.take::<String>("scope")?.as_str() {
+            "all" => FlushScope::All,
+            "expired" => FlushScope::Expired,
+            "namespace" => FlushScope::Namespace(fields.take("prefix")?),
+            _ => return Err(invalid_data("Invalid entry scope")),
+        };
+
+        Ok(DhtFlush { scope })
+    }
+
+    fn to_fields(&self) -> Fields {
+        match self.scope {
+            FlushScope::All => Fields::default().with("scope", "all"),
+            FlushScope::Expired => Fields::default().with("scope", "expired"),
+            FlushScope::Namespace(ref prefix) => Fields::default()
+                .with("scope", "namespace")
+                .with("prefix", prefix.clone()),
+        }
+    }
+}
+
+impl CborPayload for DhtFlushReply {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtFlushReply {
+            records: fields.take("records")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default().with("records", self.records)
+    }
+}
+
+impl CborPayload for NodeDrainReply {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(NodeDrainReply {
+            records: fields.take("records")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default().with("records", self.records)
+    }
+}
+
+impl CborPayload for NodeReadOnly {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(NodeReadOnly {
+            read_only: fields.take("read_only")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default().with("read_only", self.read_only)
+    }
+}
+
+impl CborPayload for NodeReadOnlyReply {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(NodeReadOnlyReply {
+            read_only: fields.take("read_only")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default().with("read_only", self.read_only)
+    }
+}
+
+impl CborPayload for NodeLoad {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(NodeLoad {
+            samples: fields.take("samples")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default().with("samples", self.samples)
+    }
+}
+
+impl CborPayload for Load {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(Load {
+            share: fields.take("share")?,
+            records: fields.take("records")?,
+            bytes: fields.take("bytes")?,
+            requests: fields.take("requests")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("share", self.share)
+            .with("records", self.records)
+            .with("bytes", self.bytes)
+            .with("requests", self.requests)
+    }
+}
+
+impl CborPayload for NodeLoadReply {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(NodeLoadReply {
+            sampled: fields.take("sampled")?,
+            weight: fields.take("weight")?,
+            target_weight: fields.take("target_weight")?,
+            local: fields.take_nested("local")?.unwrap_or_default(),
+            average: fields.take_nested("average")?.unwrap_or_default(),
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("sampled", self.sampled)
+            .with("weight", self.weight)
+            .with("target_weight", self.target_weight)
+            .with("local", self.local.to_fields())
+            .with("average", self.average.to_fields())
+    }
+}
+
+impl CborPayload for ListCursor {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(ListCursor {
+            key: fields.take("key")?,
+            replication_index: fields.take("replication_index")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("key", self.key)
+            .with("replication_index", self.replication_index)
+    }
+}
+
+impl CborPayload for DhtListLocal {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtListLocal {
+            limit: fields.take("limit")?,
+            after: fields.take_nested("after")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("limit", self.limit)
+            .with_optional("after", self.after.map(|after| after.to_fields()))
+    }
+}
+
+impl CborPayload for LocalRecord {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(LocalRecord {
+            key: fields.take("key")?,
+            replication_index: fields.take("replication_index")?,
+            ttl: fields.take_ttl()?,
+            size: fields.take("size")?,
+            version: fields.take("version")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("key", self.key)
+            .with("replication_index", self.replication_index)
+            .with_ttl(self.ttl)
+            .with("size", self.size)
+            .with("version", self.version)
+    }
+}
+
+impl CborPayload for DhtListLocalReply {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtListLocalReply {
+            total_records: fields.take("total_records")?,
+            total_bytes: fields.take("total_bytes")?,
+            remaining: fields.take("remaining")?,
+            records: fields.take_list("records")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("total_records", self.total_records)
+            .with("total_bytes", self.total_bytes)
+            .with("remaining", self.remaining)
+            .with_list("records", &self.records)
+    }
+}
+
+impl CborPayload for NodePeers {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(NodePeers {
+            limit: fields.take("limit")?,
+            after: fields.take_optional("after")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("limit", self.limit)
+            .with_optional("after", self.after)
+    }
+}
+
+impl CborPayload for PeerEntry {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(PeerEntry {
+            index: fields.take("index")?,
+            age: fields.take("age")?,
+            identifier: fields.take("identifier")?,
+            socket_addr: fields.take("socket_addr")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("index", self.index)
+            .with("age", self.age)
+            .with("identifier", self.identifier)
+            .with("socket_addr", self.socket_addr)
+    }
+}
+
+impl CborPayload for NodePeersReply {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(NodePeersReply {
+            total_peers: fields.take("total_peers")?,
+            remaining: fields.take("remaining")?,
+            peers: fields.take_list("peers")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("total_peers", self.total_peers)
+            .with("remaining", self.remaining)
+            .with_list("peers", &self.peers)
+    }
+}
+
+impl CborPayload for DhtRingWalk {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtRingWalk {
+            limit: fields.take("limit")?,
+            after: fields.take_optional("after")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("limit", self.limit)
+            .with_optional("after", self.after)
+    }
+}
+
+impl CborPayload for RingPeer {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(RingPeer {
+            identifier: fields.take("identifier")?,
+            socket_addr: fields.take("socket_addr")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("identifier", self.identifier)
+            .with("socket_addr", self.socket_addr)
+    }
+}
+
+impl CborPayload for DhtRingWalkReply {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtRingWalkReply {
+            complete: fields.take("complete")?,
+            peers: fields.take_list("peers")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("complete", self.complete)
+            .with_list("peers", &self.peers)
+    }
+}
+
+impl CborPayload for StoredRecord {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(StoredRecord {
+            key: fields.take("key")?,
+            replication_index: fields.take("replication_index")?,
+            replicas: fields.take_optional("replicas")?.unwrap_or(0),
+            ttl: fields.take_ttl()?,
+            version: fields.take("version")?,
+            value: fields.take("value")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("key", self.key)
+            .with("replication_index", self.replication_index)
+            .with("replicas", self.replicas)
+            .with_ttl(self.ttl)
+            .with("version", self.version)
+            .with("value", &self.value[..])
+    }
+}
+
+impl CborPayload for DhtExport {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtExport {
+            limit: fields.take("limit")?,
+            after: fields.take_nested("after")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("limit", self.limit)
+            .with_optional("after", self.after.map(|after| after.to_fields()))
+    }
+}
+
+impl CborPayload for DhtExportReply {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtExportReply {
+            remaining: fields.take("remaining")?,
+            records: fields.take_list("records")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("remaining", self.remaining)
+            .with_list("records", &self.records)
+    }
+}
+
+impl CborPayload for DhtImport {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtImport {
+            records: fields.take_list("records")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default().with_list("records", &self.records)
+    }
+}
+
+impl CborPayload for DhtImportReply {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(DhtImportReply {
+            stored: fields.take("stored")?,
+            skipped: fields.take("skipped")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        Fields::default()
+            .with("stored", self.stored)
+            .with("skipped", self.skipped)
+    }
+}
+
+impl CborPayload for ApiEncoding {
+    fn from_fields(fields: &mut Fields) -> io::Result<Self> {
+        Ok(ApiEncoding {
+            encoding: fields.take("encoding")?,
+            token: fields.take_optional("token")?,
+        })
+    }
+
+    fn to_fields(&self) -> Fields {
+        let encoding = match self.encoding {
+            Encoding::Binary => "binary",
+            Encoding::Cbor => "cbor",
+        };
+
+        Fields::default()
+            .with("encoding", encoding)
+            .with_optional("token", self.token.as_deref())
+    }
+}
+
+fn invalid_data<E: ToString>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+fn write_value(writer: &mut dyn Write, value: &Value) -> io::Result<()> {
+    match value {
+        Value::Unsigned(value) => write_head(writer, UNSIGNED, *value),
+        Value::Bytes(bytes) => write_bytes(writer, bytes),
+        Value::Text(text) => write_text(writer, text),
+        Value::Array(items) => {
+            write_head(writer, ARRAY, items.len() as u64)?;
+            items.iter().try_for_each(|item| write_value(writer, item))
+        }
+        Value::Map(entries) => {
+            write_head(writer, MAP, entries.len() as u64)?;
+            entries.iter().try_for_each(|(name, value)| {
+                write_text(writer, name)?;
+                write_value(writer, value)
+            })
+        }
+        Value::Bool(false) => writer.write_u8(SIMPLE << 5 | FALSE),
+        Value::Bool(true) => writer.write_u8(SIMPLE << 5 | TRUE),
+        Value::Float(value) => {
+            writer.write_u8(SIMPLE << 5 | DOUBLE)?;
+            writer.write_f64::<NetworkEndian>(*value)
+        }
+        Value::Null => writer.write_u8(SIMPLE << 5 | NULL),
+    }
+}
+
+fn read_value(reader: &mut dyn Read, depth: usize) -> io::Result<Value> {
+    // nested items are parsed recursively
+    if depth > MAX_DEPTH {
+        return Err(invalid_data("Items nested too deeply"));
+    }
+
+    let initial = reader.read_u8()?;
+    let argument = read_argument(reader, initial)?;
+
+    match (initial >> 5, argument) {
+        (UNSIGNED, Some(value)) => Ok(Value::Unsigned(value)),
+        (BYTES, Some(len)) => read_exact_len(reader, len).map(Value::Bytes),
+        (TEXT, Some(len)) => String::from_utf8(read_exact_len(reader, len)?)
+            .map(Value::Text)
+            .map_err(invalid_data),
+        (ARRAY, len) => {
+            let mut items = Vec::new();
+
+            while let Some(item) = read_item(reader, len, items.len(), depth)? {
+                items.push(item);
+            }
+
+            Ok(Value::Array(items))
+        }
+        (MAP, len) => {
+            let mut entries = Vec::new();
+
+            while let Some(name) = read_item(reader, len, entries.len(), depth)? {
+                let name = String::from_value(name)
+                    .ok_or_else(|| invalid_data("Expected a text as map key"))?;
+
+                entries.push((name, read_value(reader, depth + 1)?));
+            }
+
+            Ok(Value::Map(entries))
+        }
+        // tags do not change the meaning of the fields
+        (TAG, Some(_)) => read_value(reader, depth + 1),
+        (SIMPLE, Some(argument)) => match initial & 0x1f {
+            FALSE => Ok(Value::Bool(false)),
+            TRUE => Ok(Value::Bool(true)),
+            NULL => Ok(Value::Null),
+            HALF => Ok(Value::Float(half_to_f64(argument as u16))),
+            SINGLE => Ok(Value::Float(f64::from(f32::from_bits(argument as u32)))),
+            DOUBLE => Ok(Value::Float(f64::from_bits(argument))),
+            _ => Err(invalid_data("Unsupported simple value")),
+        },
+        _ => Err(invalid_data("Unsupported data item")),
+    }
+}
+
+/// Reads the next item of an array or a map with `len` items, of which
+/// `read` have been read already, or `None` after the last item.
+///
+/// Items of indefinite length end with a break instead.
+fn read_item(
+    reader: &mut dyn Read,
+    len: Option<u64>,
+    read: usize,
+    depth: usize,
+) -> io::Result<Option<Value>> {
+    match len {
+        Some(len) if read as u64 >= len => Ok(None),
+        Some(_) => read_value(reader, depth + 1).map(Some),
+        None => {
+            let initial = [reader.read_u8()?];
+
+            if initial[0] == BREAK {
+                return Ok(None);
+            }
+
+            read_value(&mut (&initial[..]).chain(&mut *reader), depth + 1).map(Some)
+        }
+    }
+}
+
+/// Converts a half-precision float as used by some encoders for small
+/// numbers.
+fn half_to_f64(half: u16) -> f64 {
+    let exponent = i32::from((half >> 10) & 0x1f);
+    let mantissa = f64::from(half & 0x3ff);
+
+    let value = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (mantissa + 1024.0) * 2f64.powi(exponent - 25),
+    };
+
+    if half & 0x8000 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+pub(crate) fn write_head(writer: &mut dyn Write, major: u8, argument: u64) -> io::Result<()> {
+    let major = major << 5;
+
+    if argument < 24 {
+        writer.write_u8(major | argument as u8)
+    } else if argument <= u64::from(u8::MAX) {
+        writer.write_u8(major | 24)?;
+        writer.write_u8(argument as u8)
+    } else if argument <= u64::from(u16::MAX) {
+        writer.write_u8(major | 25)?;
+        writer.write_u16::<NetworkEndian>(argument as u16)
+    } else if argument <= u64::from(u32::MAX) {
+        writer.write_u8(major | 26)?;
+        writer.write_u32::<NetworkEndian>(argument as u32)
+    } else {
+        writer.write_u8(major | 27)?;
+        writer.write_u64::<NetworkEndian>(argument)
+    }
+}
+
+pub(crate) fn write_bytes(writer: &mut dyn Write, bytes: &[u8]) -> io::Result<()> {
+    write_head(writer, BYTES, bytes.len() as u64)?;
+    writer.write_all(bytes)
+}
+
+pub(crate) fn write_text(writer: &mut dyn Write, text: &str) -> io::Result<()> {
+    write_head(writer, TEXT, text.len() as u64)?;
+    writer.write_all(text.as_bytes())
+}
+
+/// Reads the head of a data item and returns its major type along with its
+/// argument or `None` for an indefinite length or a break.
+pub(crate) fn read_head(reader: &mut dyn Read) -> io::Result<(u8, Option<u64>)> {
+    let initial = reader.read_u8()?;
+
+    Ok((initial >> 5, read_argument(reader, initial)?))
+}
+
+fn read_argument(reader: &mut dyn Read, initial: u8) -> io::Result<Option<u64>> {
+    match initial & 0x1f {
+        info @ 0..=23 => Ok(Some(u64::from(info))),
+        24 => Ok(Some(u64::from(reader.read_u8()?))),
+        25 => Ok(Some(u64::from(reader.read_u16::<NetworkEndian>()?))),
+        26 => Ok(Some(u64::from(reader.read_u32::<NetworkEndian>()?))),
+        27 => Ok(Some(reader.read_u64::<NetworkEndian>()?)),
+        31 => Ok(None),
+        _ => Err(invalid_data("Reserved additional information")),
+    }
+}
+
+pub(crate) fn read_unsigned(reader: &mut dyn Read) -> io::Result<u64> {
+    match read_head(reader)? {
+        (UNSIGNED, Some(value)) => Ok(value),
+        _ => Err(invalid_data("Expected an unsigned integer")),
+    }
+}
+
+pub(crate) fn read_number<T: TryFrom<u64>>(reader: &mut dyn Read, name: &str) -> io::Result<T> {
+    T::try_from(read_unsigned(reader)?)
+        .map_err(|_| invalid_data(format!("Value of {} out of range", name)))
+}
+
+pub(crate) fn read_bytes(reader: &mut dyn Read) -> io::Result<Vec<u8>> {
+    let len = match read_head(reader)? {
+        (BYTES, Some(len)) => len,
+        _ => return Err(invalid_data("Expected a byte string")),
+    };
+
+    read_exact_len(reader, len)
+}
+
+pub(crate) fn read_text(reader: &mut dyn Read) -> io::Result<String> {
+    let len = match read_head(reader)? {
+        (TEXT, Some(len)) => len,
+        _ => return Err(invalid_data("Expected a text string")),
+    };
+
+    String::from_utf8(read_exact_len(reader, len)?).map_err(invalid_data)
+}
+
+fn read_exact_len(reader: &mut dyn Read, len: u64) -> io::Result<Vec<u8>> {
+    // the length is not trusted for the allocation
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+
+    if bytes.len() as u64 != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Incomplete string",
+        ));
+    }
+
+    Ok(bytes)
+}
+
+/// Skips a data item of any type.
+pub(crate) fn skip(reader: &mut dyn Read) -> io::Result<()> {
+    match read_head(reader)? {
+        (UNSIGNED, Some(_)) | (NEGATIVE, Some(_)) | (SIMPLE, Some(_)) => Ok(()),
+        (BYTES, Some(len)) | (TEXT, Some(len)) => read_exact_len(reader, len).map(|_| ()),
+        (ARRAY, Some(items)) => (0..items).try_for_each(|_| skip(reader)),
+        (MAP, Some(entries)) => (0..entries * 2).try_for_each(|_| skip(reader)),
+        (ARRAY, None) | (MAP, None) => loop {
+            // items of indefinite length end with a break
+            let mut initial = [0; 1];
+            reader.read_exact(&mut initial)?;
+
+            if initial[0] == BREAK {
+                return Ok(());
+            }
+
+            skip(&mut (&initial[..]).chain(&mut *reader))?;
+        },
+        (TAG, Some(_)) => skip(reader),
+        _ => Err(invalid_data("Unsupported data item")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn roundtrip(msg: Message) {
+        let mut buffer = [0; 64000];
+        let size = write_to(&msg, Cursor::new(&mut buffer[..])).unwrap();
+
+        assert_eq!(msg, parse(Cursor::new(&buffer[..size])).unwrap());
+    }
+
+    #[test]
+    fn dht_get_documented() {
+        #[rustfmt::skip]
+        let buf = [
+            // header
+            0, 53, 2, 139,
+            // map of two entries
+            0xa2,
+            // "key" and 32 bytes
+            0x63, b'k', b'e', b'y', 0x58, 32,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // "budget" and 500
+            0x66, b'b', b'u', b'd', b'g', b'e', b't', 0x19, 0x01, 0xf4,
+        ];
+
+        let msg = Message::DhtGet(DhtGet {
+            key: DhtKey::from([3; 32]),
+            quorum: None,
+            budget: Some(500),
+            request_id: None,
+            if_none_match: None,
+        });
+
+        assert_eq!(msg, parse(Cursor::new(&buf[..])).unwrap());
+
+        let mut buffer = [0; 64000];
+        let size = write_to(&msg, Cursor::new(&mut buffer[..])).unwrap();
+
+        assert_eq!(&buf[..], &buffer[..size]);
+    }
+
+    #[test]
+    fn ttl_in_seconds_from_older_peers() {
+        let mut fields = Fields::default().with("ttl", 60u16);
+
+        assert_eq!(60_000, fields.take_ttl().unwrap());
+    }
+
+    #[test]
+    fn api_messages_roundtrip() {
+        let key = DhtKey::from([3; 32]);
+        let identifier = Identifier::new(&[5; 32]);
+        let socket_addr = "127.0.0.1:8080".parse().unwrap();
+
+        roundtrip(Message::DhtPut(DhtPut {
+            ttl: 12_000,
+            replication: 4,
+            acks: 2,
+            key,
+            value: DhtValue::new(vec![1, 2, 3]).unwrap(),
+        }));
+        roundtrip(Message::DhtPut(DhtPut {
+            ttl: 100_000_500,
+            replication: 4,
+            acks: 2,
+            key,
+            value: DhtValue::new(vec![1, 2, 3]).unwrap(),
+        }));
+        roundtrip(Message::DhtGet(DhtGet {
+            key,
+            quorum: Some(Quorum {
+                replicas: 3,
+                reads: 2,
+            }),
+            budget: None,
+            request_id: Some(7),
+            if_none_match: None,
+        }));
+        roundtrip(Message::DhtSuccess(DhtSuccess {
+            key,
+            value: DhtValue::new(vec![1, 2, 3]).unwrap(),
+            metadata: Some(RecordMetadata {
+                ttl: 12_000,
+                version: 9,
+                replication_index: 1,
+                stored: 1_554_980_000_123,
+            }),
+        }));
+        roundtrip(Message::DhtResolveReply(DhtResolveReply {
+            replication_index: 1,
+            key,
+            identifier,
+            socket_addr,
+            path: vec![socket_addr, "[::1]:9090".parse().unwrap()],
+        }));
+        roundtrip(Message::NodeInfo(NodeInfo));
+        roundtrip(Message::NodeInfoReply(NodeInfoReply {
+            identifier,
+            socket_addr,
+            predecessor: socket_addr,
+            successor: socket_addr,
+            lookup_stats: LookupStats {
+                lookups: 10,
+                failures: 1,
+                hops: Summary {
+                    count: 9,
+                    mean: 1.5,
+                    p50: 1,
+                    p90: 2,
+                    p99: 3,
+                    max: 3,
+                },
+                latency: Summary::default(),
+            },
+            traffic: Some(Traffic {
+                bytes_received: 100,
+                bytes_sent: 200,
+                throttled: 0,
+            }),
+        }));
+        roundtrip(Message::DhtFlush(DhtFlush {
+            scope: FlushScope::Namespace(b"system".to_vec()),
+        }));
+        roundtrip(Message::DhtListLocalReply(DhtListLocalReply {
+            total_records: 1,
+            total_bytes: 3,
+            remaining: 0,
+            records: vec![LocalRecord {
+                key,
+                replication_index: 1,
+                ttl: 100_000_000,
+                size: 3,
+                version: 9,
+            }],
+        }));
+        roundtrip(Message::NodePeersReply(NodePeersReply {
+            total_peers: 1,
+            remaining: 0,
+            peers: vec![PeerEntry {
+                index: 0,
+                age: 30,
+                identifier,
+                socket_addr,
+            }],
+        }));
+        roundtrip(Message::DhtRingWalk(DhtRingWalk {
+            limit: 10,
+            after: Some(socket_addr),
+        }));
+        roundtrip(Message::DhtExport(DhtExport {
+            limit: 10,
+            after: Some(ListCursor {
+                key,
+                replication_index: 1,
+            }),
+        }));
+        roundtrip(Message::DhtImport(DhtImport {
+            records: vec![StoredRecord {
+                key,
+                replication_index: 1,
+                replicas: 2,
+                ttl: 100_000_000,
+                version: 9,
+                value: vec![1, 2, 3].into(),
+            }],
+        }));
+        roundtrip(Message::ApiEncoding(ApiEncoding {
+            encoding: Encoding::Cbor,
+            token: Some("secret".to_string()),
+        }));
+        roundtrip(Message::DhtPrefetch(DhtPrefetch {
+            keys: vec![DhtKey::from([3; 32]), DhtKey::from([4; 32])],
+        }));
+        roundtrip(Message::NodeLoad(NodeLoad { samples: 16 }));
+        roundtrip(Message::NodeLoadReply(NodeLoadReply {
+            sampled: 8,
+            weight: 2,
+            target_weight: 1,
+            local: Load {
+                share: 1 << 62,
+                records: 40,
+                bytes: 4096,
+                requests: 100,
+            },
+            average: Load {
+                share: 1 << 60,
+                records: 10,
+                bytes: 1024,
+                requests: 25,
+            },
+        }));
+    }
+
+    #[test]
+    fn parse_lenient() {
+        #[rustfmt::skip]
+        let buf = [
+            // header
+            0, 31, 2, 156,
+            // map of indefinite length
+            0xbf,
+            // "unknown" entry
+            0x67, b'u', b'n', b'k', b'n', b'o', b'w', b'n', 0x80,
+            // "limit" as tagged number
+            0x65, b'l', b'i', b'm', b'i', b't', 0xc2, 0x18, 100,
+            // "after" null
+            0x65, b'a', b'f', b't', b'e', b'r', 0xf6,
+            // break
+            0xff,
+        ];
+
+        let msg = Message::DhtListLocal(DhtListLocal {
+            limit: 100,
+            after: None,
+        });
+
+        assert_eq!(msg, parse(Cursor::new(&buf[..])).unwrap());
+    }
+
+    #[test]
+    fn parse_half_float() {
+        assert_eq!(1.5, half_to_f64(0x3e00));
+        assert_eq!(-2.0, half_to_f64(0xc000));
+        assert_eq!(65504.0, half_to_f64(0x7bff));
+    }
+
+    #[test]
+    fn parse_missing_entry() {
+        #[rustfmt::skip]
+        let buf = [
+            // header and empty map
+            0, 5, 2, 139, 0xa0,
+        ];
+
+        let err = parse(Cursor::new(&buf[..])).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert_eq!("Missing entry key", err.to_string());
+    }
+
+    #[test]
+    fn parse_nested_too_deeply() {
+        let mut buf = vec![0, 0, 2, 139];
+        buf.extend_from_slice(&[0x81; 100]);
+        buf.push(0);
+        buf[1] = buf.len() as u8;
+
+        let err = parse(Cursor::new(&buf[..])).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn write_p2p_message() {
+        let msg = Message::JoinPublish(super::super::p2p::JoinPublish {
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+        });
+
+        let mut buffer = [0; 64000];
+        let err = write_to(&msg, Cursor::new(&mut buffer[..])).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+}
@@ -0,0 +1,591 @@
+//! Binary encoding shared by all messages
+//!
+//! Every message starts with a header of two 16 bit numbers, the size of
+//! the whole message including the header and the message type, followed by
+//! the payload of that type. All numbers are transmitted in network byte
+//! order and reserved bytes are zero.
+//!
+//! ```text
+//! +-----------------+-----------------+
+//! |      size       |  message type   |
+//! +-----------------+-----------------+
+//! |              payload              |
+//! +-----------------------------------+
+//! ```
+//!
+//! A socket address takes [`SOCKET_ADDR_SIZE`] bytes, 16 bytes for the ip
+//! address followed by two bytes for the port. IPv4 addresses are
+//! transmitted as IPv4-mapped IPv6 addresses. Keys and identifiers take 32
+//! bytes each.
+//!
+//! Fields which have been added to a message later are appended at its end
+//! and are optional such that older peers can still parse the message.
+//!
+//! Times to live are milliseconds such that values can live for less than a
+//! second or longer than the 18 hours a `u16` of seconds allows. Only
+//! [`DHT_PUT`] carries whole seconds, as given by its specification, and is
+//! replaced by [`DHT_PUT_V2`] for any other time to live. A time to live of
+//! zero expires immediately.
+//!
+//! The layout of each payload is documented on its message type below. The
+//! test vectors in `tests/vectors/messages.txt` contain an encoded example
+//! of every message type to validate other implementations against.
+//!
+//! The constants and the functions working on byte slices only need
+//! `alloc`, such that clients without the standard library can encode api
+//! messages with [`encode_message`]. The functions reading from and writing
+//! to streams need the `network` feature.
+//!
+//! [`encode_message`]: fn.encode_message.html
+//! [`DHT_PUT`]: constant.DHT_PUT.html
+//! [`DHT_PUT_V2`]: constant.DHT_PUT_V2.html
+//! [`SOCKET_ADDR_SIZE`]: constant.SOCKET_ADDR_SIZE.html
+
+use crate::error::ConversionError;
+#[cfg(feature = "network")]
+use crate::load::Load;
+use alloc::format;
+use alloc::vec::Vec;
+#[cfg(feature = "network")]
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+#[cfg(feature = "network")]
+use bytes::Bytes;
+use core::net::{IpAddr, Ipv6Addr, SocketAddr};
+#[cfg(feature = "network")]
+use std::io;
+#[cfg(feature = "network")]
+use std::io::prelude::*;
+#[cfg(feature = "network")]
+use std::io::Cursor;
+
+/// Size of the message header in bytes
+pub const HEADER_SIZE: usize = 4;
+
+/// Maximum size of a message including its header in bytes
+pub const MAX_MESSAGE_SIZE: usize = 64000;
+
+/// Size of an encoded socket address in bytes
+pub const SOCKET_ADDR_SIZE: usize = 18;
+
+/// `ttl: u16, replication: u8, acks: u8, key: [u8; 32], value: [u8]`
+pub const DHT_PUT: u16 = 650;
+/// `key: [u8; 32]` optionally followed by `replicas: u8, reads: u8`,
+/// `budget: u32`, `request_id: u32` and `if_none_match: u64`
+pub const DHT_GET: u16 = 651;
+/// `key: [u8; 32], value: [u8]`
+pub const DHT_SUCCESS: u16 = 652;
+/// `key: [u8; 32]`
+pub const DHT_FAILURE: u16 = 653;
+/// `replication_index: u8, flags: u8, reserved: [u8; 2], key: [u8; 32]`
+pub const DHT_RESOLVE: u16 = 654;
+/// `replication_index: u8, reserved: [u8; 3], key: [u8; 32],
+/// identifier: [u8; 32], socket_addr` followed by the socket addresses of
+/// the path
+pub const DHT_RESOLVE_REPLY: u16 = 655;
+/// Empty payload
+pub const NODE_INFO: u16 = 656;
+/// `identifier: [u8; 32], socket_addr, predecessor, successor, lookups: u64,
+/// failures: u64` followed by the summaries of hops and latency, each
+/// `count: u32, mean: f32, p50: u32, p90: u32, p99: u32, max: u32`, and
+/// optionally `bytes_received: u64, bytes_sent: u64, throttled: u64`
+pub const NODE_INFO_REPLY: u16 = 657;
+/// `scope: u8, reserved: [u8; 3]` followed by the prefix for
+/// [`FLUSH_NAMESPACE`]
+///
+/// [`FLUSH_NAMESPACE`]: constant.FLUSH_NAMESPACE.html
+pub const DHT_FLUSH: u16 = 658;
+/// `records: u32`
+pub const DHT_FLUSH_REPLY: u16 = 659;
+/// `acks: u8, reserved: [u8; 3], key: [u8; 32]` optionally followed by
+/// `version: u64`
+pub const DHT_PUT_SUCCESS: u16 = 660;
+/// `replication: u8, reserved: [u8; 3], key: [u8; 32]`
+pub const DHT_DELETE: u16 = 661;
+/// `replicas: u8, reserved: [u8; 3], key: [u8; 32]`
+pub const DHT_DELETE_REPLY: u16 = 662;
+/// `request_id: u32`
+pub const DHT_CANCEL: u16 = 663;
+/// Empty payload
+pub const NODE_DRAIN: u16 = 664;
+/// `records: u32`
+pub const NODE_DRAIN_REPLY: u16 = 665;
+/// `read_only: u8, reserved: [u8; 3]`
+pub const NODE_READ_ONLY: u16 = 666;
+/// `read_only: u8, reserved: [u8; 3]`
+pub const NODE_READ_ONLY_REPLY: u16 = 667;
+/// `limit: u16, reserved: [u8; 2]` optionally followed by the cursor
+/// `replication_index: u8, reserved: [u8; 3], key: [u8; 32]`
+pub const DHT_LIST_LOCAL: u16 = 668;
+/// `total_records: u32, total_bytes: u64, remaining: u32` followed by records
+/// of `replication_index: u8, reserved: u8, ttl: u64, size: u32,
+/// version: u64, key: [u8; 32]`
+pub const DHT_LIST_LOCAL_REPLY: u16 = 669;
+/// `limit: u16, reserved: [u8; 2]` optionally followed by the cursor
+/// `index: u16, reserved: [u8; 2]`
+pub const NODE_PEERS: u16 = 670;
+/// `total_peers: u16, remaining: u16` followed by fingers of `index: u16,
+/// reserved: [u8; 2], identifier: [u8; 32], socket_addr`
+pub const NODE_PEERS_REPLY: u16 = 671;
+/// `limit: u16, reserved: [u8; 2]` optionally followed by the cursor
+/// `socket_addr`
+pub const DHT_RING_WALK: u16 = 672;
+/// `complete: u8, reserved: [u8; 3]` followed by peers of
+/// `identifier: [u8; 32], socket_addr`
+pub const DHT_RING_WALK_REPLY: u16 = 673;
+/// `limit: u16, reserved: [u8; 2]` optionally followed by the cursor
+/// `replication_index: u8, reserved: [u8; 3], key: [u8; 32]`
+pub const DHT_EXPORT: u16 = 674;
+/// `remaining: u32` followed by records of `replication_index: u8,
+/// replicas: u8, ttl: u64, size: u32, version: u64, key: [u8; 32]` and the
+/// value of `size` bytes
+pub const DHT_EXPORT_REPLY: u16 = 675;
+/// Records like in [`DHT_EXPORT_REPLY`]
+///
+/// [`DHT_EXPORT_REPLY`]: constant.DHT_EXPORT_REPLY.html
+pub const DHT_IMPORT: u16 = 676;
+/// `stored: u32, skipped: u32`
+pub const DHT_IMPORT_REPLY: u16 = 677;
+/// `encoding: u8, reserved: [u8; 3]` optionally followed by the UTF-8
+/// encoded `token: [u8]` of a tenant
+pub const API_ENCODING: u16 = 678;
+/// `key: [u8; 32]`
+pub const DHT_NOT_MODIFIED: u16 = 679;
+/// `keys: [[u8; 32]]`
+pub const DHT_PREFETCH: u16 = 680;
+/// `samples: u16, reserved: [u8; 2]`
+pub const NODE_LOAD: u16 = 681;
+/// `sampled: u16, weight: u16, target_weight: u16, reserved: [u8; 2]`
+/// followed by the load of the peer and the average load of the sampled
+/// peers, each in the layout of [`PEER_LOAD_REPLY`]
+///
+/// [`PEER_LOAD_REPLY`]: constant.PEER_LOAD_REPLY.html
+pub const NODE_LOAD_REPLY: u16 = 682;
+/// `ttl: u64, replication: u8, acks: u8, reserved: [u8; 2], key: [u8; 32],
+/// value: [u8]`
+///
+/// Same as [`DHT_PUT`] but with the time to live in milliseconds, used for
+/// times to live which [`DHT_PUT`] cannot carry.
+///
+/// [`DHT_PUT`]: constant.DHT_PUT.html
+pub const DHT_PUT_V2: u16 = 683;
+
+/// `replication_index: u8, flags: u8, reserved: [u8; 2], key: [u8; 32]`
+/// followed by `version: u64` if [`VERSION_FLAG`] is set, by the socket
+/// address `requester: [u8; 18]` if [`REQUESTER_FLAG`] is set and optionally
+/// by `budget: u32`
+///
+/// [`VERSION_FLAG`]: constant.VERSION_FLAG.html
+/// [`REQUESTER_FLAG`]: constant.REQUESTER_FLAG.html
+pub const STORAGE_GET: u16 = 1000;
+/// `ttl: u64, replication_index: u8, replicas: u8, namespace_len: u8,
+/// reserved: u8, key: [u8; 32], version: u64, value: [u8]`
+pub const STORAGE_PUT: u16 = 1001;
+/// `key: [u8; 32], version: u64, ttl: u64, stored: u64, value: [u8]`
+pub const STORAGE_GET_SUCCESS: u16 = 1002;
+/// `key: [u8; 32]`
+pub const STORAGE_PUT_SUCCESS: u16 = 1003;
+/// `key: [u8; 32]` optionally followed by `reason: u8`
+pub const STORAGE_FAILURE: u16 = 1004;
+/// `replication_index: u8, reserved: [u8; 3], key: [u8; 32]`
+pub const STORAGE_DELETE: u16 = 1005;
+/// `key: [u8; 32]`
+pub const STORAGE_DELETE_SUCCESS: u16 = 1006;
+
+/// `identifier: [u8; 32]` optionally followed by `flags: u8`, `budget: u32`
+/// if [`BUDGET_FLAG`] is set and the socket addresses of the trace if
+/// [`TRACE_FLAG`] is set
+///
+/// [`BUDGET_FLAG`]: constant.BUDGET_FLAG.html
+/// [`TRACE_FLAG`]: constant.TRACE_FLAG.html
+pub const PEER_FIND: u16 = 1050;
+/// `identifier: [u8; 32], socket_addr` optionally followed by `flags: u8`
+/// and, with [`TRACE_FLAG`], the socket addresses of the trace
+///
+/// [`AUTHORITATIVE_FLAG`] marks replies of the peer responsible for the
+/// identifier.
+///
+/// [`AUTHORITATIVE_FLAG`]: constant.AUTHORITATIVE_FLAG.html
+/// [`TRACE_FLAG`]: constant.TRACE_FLAG.html
+pub const PEER_FOUND: u16 = 1051;
+/// `socket_addr`
+pub const PREDECESSOR_NOTIFY: u16 = 1052;
+/// `socket_addr`
+pub const PREDECESSOR_REPLY: u16 = 1053;
+/// `socket_addr` optionally followed by the cursor of a resumed transfer,
+/// `replication_index: u8, reserved: [u8; 3], key: [u8; 32]`
+pub const JOIN_LOCK: u16 = 1054;
+/// `socket_addr, records: u32`
+pub const JOIN_ACK: u16 = 1055;
+/// `socket_addr` optionally followed by `flags: u8`
+///
+/// [`COLLISION_FLAG`] marks the refusal of a peer whose identifier is taken.
+///
+/// [`COLLISION_FLAG`]: constant.COLLISION_FLAG.html
+pub const JOIN_NACK: u16 = 1056;
+/// `socket_addr`
+pub const JOIN_PUBLISH: u16 = 1057;
+/// `request_id: u32` followed by another message including its header
+pub const CORRELATED: u16 = 1058;
+/// `socket_addr, predecessor_addr, successor_addr, records: u32` optionally
+/// followed by `flags: u8`
+pub const PEER_LEAVE: u16 = 1059;
+/// Optionally the cursor of the transfer, `replication_index: u8,
+/// reserved: [u8; 3], key: [u8; 32]`
+pub const TRANSFER_ACK: u16 = 1060;
+/// `values: u16, reserved: [u8; 2]` followed by each value as `ttl: u64,
+/// replication_index: u8, replicas: u8, namespace_len: u8, reserved: u8,
+/// key: [u8; 32], version: u64, size: u16, value: [u8]`
+pub const STORAGE_BULK_PUT: u16 = 1061;
+/// `rejected: u16, reserved: [u8; 2]` followed by each rejected key as
+/// `replication_index: u8, reserved: [u8; 3], key: [u8; 32]`
+pub const STORAGE_BULK_PUT_REPLY: u16 = 1062;
+/// Empty payload
+pub const PEER_LOAD: u16 = 1063;
+/// `share: u64, bytes: u64, requests: u64, records: u32`
+pub const PEER_LOAD_REPLY: u16 = 1064;
+/// `replication_index: u8, reserved: [u8; 3], key: [u8; 32], version: u64,
+/// ttl: u64, stored: u64, value: [u8]`
+pub const STORAGE_CACHE_PUSH: u16 = 1065;
+
+/// Flag indicating that a lookup should be traced
+pub const TRACE_FLAG: u8 = 0x01;
+/// Flag indicating that a lookup carries a budget
+pub const BUDGET_FLAG: u8 = 0x02;
+/// Flag indicating that `PEER FOUND` comes from the peer responsible for the
+/// identifier
+pub const AUTHORITATIVE_FLAG: u8 = 0x02;
+/// Flag indicating that `JOIN NACK` refuses a peer whose identifier belongs
+/// to another peer already
+pub const COLLISION_FLAG: u8 = 0x01;
+/// Flag indicating that `PEER LEAVE` resumes a broken transfer
+pub const RESUME_FLAG: u8 = 0x01;
+/// Flag indicating that `STORAGE GET` only asks for values newer than a
+/// version
+pub const VERSION_FLAG: u8 = 0x01;
+/// Flag indicating that `STORAGE GET` carries the address of a requesting
+/// peer which caches values
+pub const REQUESTER_FLAG: u8 = 0x02;
+
+/// Scope of `DHT FLUSH` removing all records
+pub const FLUSH_ALL: u8 = 0;
+/// Scope of `DHT FLUSH` removing the records whose key starts with a prefix
+pub const FLUSH_NAMESPACE: u8 = 1;
+/// Scope of `DHT FLUSH` removing the expired records
+pub const FLUSH_EXPIRED: u8 = 2;
+
+/// Encoding of `API ENCODING` for the binary payloads of this module
+pub const ENCODING_BINARY: u8 = 0;
+/// Encoding of `API ENCODING` for the CBOR payloads of the [`cbor`] module
+///
+/// [`cbor`]: ../cbor/index.html
+pub const ENCODING_CBOR: u8 = 1;
+
+/// Reason of `STORAGE FAILURE` if no value is stored for the key
+pub const FAILURE_NOT_FOUND: u8 = 1;
+/// Reason of `STORAGE FAILURE` if a newer value is stored already
+pub const FAILURE_EXISTS: u8 = 2;
+/// Reason of `STORAGE FAILURE` if the range of the key is locked by a join
+pub const FAILURE_LOCKED: u8 = 3;
+/// Reason of `STORAGE FAILURE` if the peer does not accept new values
+pub const FAILURE_READ_ONLY: u8 = 4;
+/// Reason of `STORAGE FAILURE` if the authorizer of the peer denied the
+/// request
+pub const FAILURE_DENIED: u8 = 5;
+/// Reason of `STORAGE FAILURE` if the stored value is not newer than the
+/// version of a conditional `STORAGE GET`
+pub const FAILURE_NOT_MODIFIED: u8 = 6;
+/// Reason of `STORAGE FAILURE` if the value violates the policy of its
+/// namespace
+pub const FAILURE_POLICY_VIOLATED: u8 = 7;
+
+/// Encodes a message of `message_type` with the given `payload`, including
+/// its header.
+///
+/// Fails if the message would exceed [`MAX_MESSAGE_SIZE`].
+///
+/// [`MAX_MESSAGE_SIZE`]: constant.MAX_MESSAGE_SIZE.html
+pub fn encode_message(message_type: u16, payload: &[u8]) -> Result<Vec<u8>, ConversionError> {
+    let size = HEADER_SIZE + payload.len();
+
+    if size > MAX_MESSAGE_SIZE {
+        return Err(ConversionError::new(format!(
+            "message of {} bytes exceeds {} bytes",
+            size, MAX_MESSAGE_SIZE
+        )));
+    }
+
+    let mut bytes = Vec::with_capacity(size);
+    bytes.extend_from_slice(&(size as u16).to_be_bytes());
+    bytes.extend_from_slice(&message_type.to_be_bytes());
+    bytes.extend_from_slice(payload);
+
+    Ok(bytes)
+}
+
+/// Decodes the header at the start of `bytes` and returns the size and type
+/// of the message.
+///
+/// Returns `None` if `bytes` are shorter than a header or the size does not
+/// even include the header itself.
+pub fn decode_header(bytes: &[u8]) -> Option<(u16, u16)> {
+    if bytes.len() < HEADER_SIZE {
+        return None;
+    }
+
+    let size = u16::from_be_bytes([bytes[0], bytes[1]]);
+    let message_type = u16::from_be_bytes([bytes[2], bytes[3]]);
+
+    if usize::from(size) < HEADER_SIZE {
+        return None;
+    }
+
+    Some((size, message_type))
+}
+
+/// Encodes a socket address as 16 bytes for the ip address followed by two
+/// bytes for the port.
+///
+/// IPv4 addresses are transmitted as IPv4-mapped IPv6 addresses.
+pub fn encode_socket_addr(socket_addr: SocketAddr) -> [u8; SOCKET_ADDR_SIZE] {
+    let ip_address = match socket_addr.ip() {
+        IpAddr::V4(ipv4) => ipv4.to_ipv6_mapped(),
+        IpAddr::V6(ipv6) => ipv6,
+    };
+
+    let mut bytes = [0; SOCKET_ADDR_SIZE];
+    bytes[..16].copy_from_slice(&ip_address.octets());
+    bytes[16..].copy_from_slice(&socket_addr.port().to_be_bytes());
+
+    bytes
+}
+
+/// Decodes a socket address encoded by [`encode_socket_addr`].
+///
+/// [`encode_socket_addr`]: fn.encode_socket_addr.html
+pub fn decode_socket_addr(bytes: &[u8; SOCKET_ADDR_SIZE]) -> SocketAddr {
+    let mut ip_arr = [0; 16];
+    ip_arr.copy_from_slice(&bytes[..16]);
+
+    let ipv6 = Ipv6Addr::from(ip_arr);
+
+    let ip_address = match ipv6.to_ipv4() {
+        Some(ipv4) => IpAddr::V4(ipv4),
+        None => IpAddr::V6(ipv6),
+    };
+
+    SocketAddr::new(ip_address, u16::from_be_bytes([bytes[16], bytes[17]]))
+}
+
+/// Reads the header of a message and returns its size and type.
+///
+/// Fails if the size does not even include the header itself.
+#[cfg(feature = "network")]
+pub fn read_header(reader: &mut dyn Read) -> io::Result<(u16, u16)> {
+    let mut header = [0; HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+
+    decode_header(&header)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Size must include header"))
+}
+
+/// Reads the remaining payload of a message from `reader`.
+#[cfg(feature = "network")]
+pub fn read_payload(reader: &mut dyn Read) -> io::Result<Bytes> {
+    let mut payload = Vec::new();
+    reader.read_to_end(&mut payload)?;
+
+    Ok(Bytes::from(payload))
+}
+
+/// Reads the next `len` bytes of `payload` at the position of `reader`
+/// without copying them.
+#[cfg(feature = "network")]
+pub fn read_bytes(payload: &Bytes, reader: &mut Cursor<&[u8]>, len: usize) -> io::Result<Bytes> {
+    let start = reader.position() as usize;
+
+    if payload.len().saturating_sub(start) < len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+
+    reader.set_position((start + len) as u64);
+
+    Ok(payload.slice(start..start + len))
+}
+
+/// Reads the rest of `payload` behind the position of `reader` without
+/// copying it.
+#[cfg(feature = "network")]
+pub fn read_remaining(payload: &Bytes, reader: &mut Cursor<&[u8]>) -> Bytes {
+    let start = (reader.position() as usize).min(payload.len());
+    reader.set_position(payload.len() as u64);
+
+    payload.slice(start..)
+}
+
+/// Reads a socket address encoded by [`encode_socket_addr`].
+///
+/// [`encode_socket_addr`]: fn.encode_socket_addr.html
+#[cfg(feature = "network")]
+pub fn read_socket_addr(reader: &mut dyn Read) -> io::Result<SocketAddr> {
+    let mut bytes = [0; SOCKET_ADDR_SIZE];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(decode_socket_addr(&bytes))
+}
+
+/// Writes a socket address in the format expected by [`read_socket_addr`].
+///
+/// [`read_socket_addr`]: fn.read_socket_addr.html
+#[cfg(feature = "network")]
+pub fn write_socket_addr(writer: &mut dyn Write, socket_addr: SocketAddr) -> io::Result<()> {
+    writer.write_all(&encode_socket_addr(socket_addr))
+}
+
+/// Reads the load of a peer in the layout of [`PEER_LOAD_REPLY`].
+///
+/// [`PEER_LOAD_REPLY`]: constant.PEER_LOAD_REPLY.html
+#[cfg(feature = "network")]
+pub fn read_load(reader: &mut dyn Read) -> io::Result<Load> {
+    let share = reader.read_u64::<NetworkEndian>()?;
+    let bytes = reader.read_u64::<NetworkEndian>()?;
+    let requests = reader.read_u64::<NetworkEndian>()?;
+    let records = reader.read_u32::<NetworkEndian>()?;
+
+    Ok(Load {
+        share,
+        records,
+        bytes,
+        requests,
+    })
+}
+
+/// Writes the load of a peer in the format expected by [`read_load`].
+///
+/// [`read_load`]: fn.read_load.html
+#[cfg(feature = "network")]
+pub fn write_load(writer: &mut dyn Write, load: &Load) -> io::Result<()> {
+    writer.write_u64::<NetworkEndian>(load.share)?;
+    writer.write_u64::<NetworkEndian>(load.bytes)?;
+    writer.write_u64::<NetworkEndian>(load.requests)?;
+    writer.write_u32::<NetworkEndian>(load.records)?;
+
+    Ok(())
+}
+
+/// Reads socket addresses until the end of the reader is reached.
+#[cfg(feature = "network")]
+pub fn read_socket_addrs(reader: &mut dyn Read) -> io::Result<Vec<SocketAddr>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    if bytes.len() % SOCKET_ADDR_SIZE != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Incomplete socket address",
+        ));
+    }
+
+    bytes
+        .chunks(SOCKET_ADDR_SIZE)
+        .map(|mut chunk| read_socket_addr(&mut chunk))
+        .collect()
+}
+
+/// Writes all given socket addresses one after another.
+#[cfg(feature = "network")]
+pub fn write_socket_addrs(writer: &mut dyn Write, socket_addrs: &[SocketAddr]) -> io::Result<()> {
+    for &socket_addr in socket_addrs {
+        write_socket_addr(writer, socket_addr)?;
+    }
+
+    Ok(())
+}
+
+/// Reads an optional trailing 32 bit number like a budget in milliseconds.
+///
+/// Such fields are optional for compatibility with older peers and clients.
+#[cfg(feature = "network")]
+pub fn read_optional_u32(reader: &mut dyn Read) -> io::Result<Option<u32>> {
+    let mut value = [0; 4];
+
+    if reader.read(&mut value[..1])? == 0 {
+        return Ok(None);
+    }
+
+    reader.read_exact(&mut value[1..])?;
+
+    Ok(Some(u32::from_be_bytes(value)))
+}
+
+/// Reads an optional trailing 64 bit number like a byte count.
+///
+/// See [`read_optional_u32`].
+///
+/// [`read_optional_u32`]: fn.read_optional_u32.html
+#[cfg(feature = "network")]
+pub fn read_optional_u64(reader: &mut dyn Read) -> io::Result<Option<u64>> {
+    let mut value = [0; 8];
+
+    if reader.read(&mut value[..1])? == 0 {
+        return Ok(None);
+    }
+
+    reader.read_exact(&mut value[1..])?;
+
+    Ok(Some(u64::from_be_bytes(value)))
+}
+
+/// Writes a number in the format expected by [`read_optional_u32`] if
+/// present.
+///
+/// [`read_optional_u32`]: fn.read_optional_u32.html
+#[cfg(feature = "network")]
+pub fn write_optional_u32(writer: &mut dyn Write, value: Option<u32>) -> io::Result<()> {
+    if let Some(value) = value {
+        writer.write_u32::<NetworkEndian>(value)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_message_with_header() {
+        let bytes = encode_message(DHT_GET, &[1, 2, 3]).unwrap();
+
+        assert_eq!(vec![0, 7, 2, 139, 1, 2, 3], bytes);
+        assert_eq!(Some((7, DHT_GET)), decode_header(&bytes));
+    }
+
+    #[test]
+    fn encode_message_too_large() {
+        let payload = vec![0; MAX_MESSAGE_SIZE - HEADER_SIZE + 1];
+
+        assert!(encode_message(DHT_PUT, &payload).is_err());
+    }
+
+    #[test]
+    fn decode_invalid_header() {
+        assert_eq!(None, decode_header(&[0, 4, 2]));
+        assert_eq!(None, decode_header(&[0, 3, 2, 139]));
+    }
+
+    #[test]
+    fn socket_addr_round_trip() {
+        for addr in ["127.0.0.1:8080", "[2001:db8::1]:443"].iter() {
+            let socket_addr: SocketAddr = addr.parse().unwrap();
+            let bytes = encode_socket_addr(socket_addr);
+
+            assert_eq!(socket_addr, decode_socket_addr(&bytes));
+        }
+
+        let bytes = encode_socket_addr("127.0.0.1:8080".parse().unwrap());
+        assert_eq!(
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 127, 0, 0, 1],
+            bytes[..16]
+        );
+    }
+}
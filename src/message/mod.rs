@@ -4,17 +4,39 @@
 //! The [`Message`] enum combines these messages and provides an abstraction
 //! for sending messages over a TCP stream using the [`Connection`] struct.
 //!
+//! The [`codec`] module documents the binary encoding of all messages. The
+//! [`builder`] module helps to construct messages in tests and tools.
+//!
+//! Only the [`codec`] module is available without the `network` feature.
+//!
 //! [`Message`]: enum.Message.html
+//! [`builder`]: builder/index.html
+//! [`codec`]: codec/index.html
 //! [`Connection`]: ../network/struct.Connection.html
 
+#[cfg(feature = "network")]
 use self::api::*;
+#[cfg(feature = "network")]
 use self::p2p::*;
-use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+#[cfg(feature = "network")]
+use byteorder::{NetworkEndian, WriteBytesExt};
+#[cfg(feature = "network")]
+use bytes::Bytes;
+#[cfg(feature = "network")]
 use std::fmt;
+#[cfg(feature = "network")]
 use std::io;
+#[cfg(feature = "network")]
 use std::io::prelude::*;
 
+#[cfg(feature = "network")]
 pub mod api;
+#[cfg(feature = "network")]
+pub mod builder;
+#[cfg(feature = "network")]
+pub mod cbor;
+pub mod codec;
+#[cfg(feature = "network")]
 pub mod p2p;
 
 /// This enum contains the different message types supported by this module.
@@ -30,6 +52,35 @@ pub mod p2p;
 /// * [`DhtGet`](#variant.DhtGet)
 /// * [`DhtSuccess`](#variant.DhtSuccess)
 /// * [`DhtFailure`](#variant.DhtFailure)
+/// * [`DhtResolve`](#variant.DhtResolve)
+/// * [`DhtResolveReply`](#variant.DhtResolveReply)
+/// * [`NodeInfo`](#variant.NodeInfo)
+/// * [`NodeInfoReply`](#variant.NodeInfoReply)
+/// * [`DhtFlush`](#variant.DhtFlush)
+/// * [`DhtFlushReply`](#variant.DhtFlushReply)
+/// * [`DhtPutSuccess`](#variant.DhtPutSuccess)
+/// * [`DhtDelete`](#variant.DhtDelete)
+/// * [`DhtDeleteReply`](#variant.DhtDeleteReply)
+/// * [`DhtCancel`](#variant.DhtCancel)
+/// * [`NodeDrain`](#variant.NodeDrain)
+/// * [`NodeDrainReply`](#variant.NodeDrainReply)
+/// * [`NodeReadOnly`](#variant.NodeReadOnly)
+/// * [`NodeReadOnlyReply`](#variant.NodeReadOnlyReply)
+/// * [`DhtListLocal`](#variant.DhtListLocal)
+/// * [`DhtListLocalReply`](#variant.DhtListLocalReply)
+/// * [`NodePeers`](#variant.NodePeers)
+/// * [`NodePeersReply`](#variant.NodePeersReply)
+/// * [`DhtRingWalk`](#variant.DhtRingWalk)
+/// * [`DhtRingWalkReply`](#variant.DhtRingWalkReply)
+/// * [`DhtExport`](#variant.DhtExport)
+/// * [`DhtExportReply`](#variant.DhtExportReply)
+/// * [`DhtImport`](#variant.DhtImport)
+/// * [`DhtImportReply`](#variant.DhtImportReply)
+/// * [`ApiEncoding`](#variant.ApiEncoding)
+/// * [`DhtNotModified`](#variant.DhtNotModified)
+/// * [`DhtPrefetch`](#variant.DhtPrefetch)
+/// * [`NodeLoad`](#variant.NodeLoad)
+/// * [`NodeLoadReply`](#variant.NodeLoadReply)
 ///
 /// # P2P message types
 ///
@@ -40,11 +91,25 @@ pub mod p2p;
 /// * [`StorageGetSuccess`](#variant.StorageGetSuccess)
 /// * [`StoragePutSuccess`](#variant.StoragePutSuccess)
 /// * [`StorageFailure`](#variant.StorageFailure)
+/// * [`StorageDelete`](#variant.StorageDelete)
+/// * [`StorageDeleteSuccess`](#variant.StorageDeleteSuccess)
 /// * [`PeerFind`](#variant.PeerFind)
 /// * [`PeerFound`](#variant.PeerFound)
-/// * [`PredecessorGet`](#variant.PredecessorGet)
+/// * [`PredecessorNotify`](#variant.PredecessorNotify)
 /// * [`PredecessorReply`](#variant.PredecessorReply)
-/// * [`PredecessorSet`](#variant.PredecessorSet)
+/// * [`JoinLock`](#variant.JoinLock)
+/// * [`JoinAck`](#variant.JoinAck)
+/// * [`JoinNack`](#variant.JoinNack)
+/// * [`JoinPublish`](#variant.JoinPublish)
+/// * [`Correlated`](#variant.Correlated)
+/// * [`PeerLeave`](#variant.PeerLeave)
+/// * [`TransferAck`](#variant.TransferAck)
+/// * [`StorageBulkPut`](#variant.StorageBulkPut)
+/// * [`StorageBulkPutReply`](#variant.StorageBulkPutReply)
+/// * [`PeerLoad`](#variant.PeerLoad)
+/// * [`PeerLoadReply`](#variant.PeerLoadReply)
+/// * [`StorageCachePush`](#variant.StorageCachePush)
+#[cfg(feature = "network")]
 #[derive(Debug, PartialEq)]
 pub enum Message {
     /// The given key-value pair should be stored in the network.
@@ -58,6 +123,64 @@ pub enum Message {
     /// A previous DHT GET operation did not find any value for the requested
     /// key.
     DhtFailure(DhtFailure),
+    /// Find the peer responsible for a given key without fetching its value.
+    DhtResolve(DhtResolve),
+    /// A previous `DHT RESOLVE` operation found the responsible peer.
+    DhtResolveReply(DhtResolveReply),
+    /// Request information about the peer itself.
+    NodeInfo(NodeInfo),
+    /// Reply to `NODE INFO` with routing information and telemetry.
+    NodeInfoReply(NodeInfoReply),
+    /// Remove records from the local storage of a peer.
+    DhtFlush(DhtFlush),
+    /// Reply to `DHT FLUSH` with the number of removed records.
+    DhtFlushReply(DhtFlushReply),
+    /// Enough replicas confirmed a `DHT PUT` which requested acknowledgements.
+    DhtPutSuccess(DhtPutSuccess),
+    /// Remove the value stored under a given key from the network.
+    DhtDelete(DhtDelete),
+    /// Reply to `DHT DELETE` with the number of removed replicas.
+    DhtDeleteReply(DhtDeleteReply),
+    /// Abort a running `DHT GET` with the given request ID.
+    DhtCancel(DhtCancel),
+    /// Hand all records to the successor and leave the network.
+    NodeDrain(NodeDrain),
+    /// Reply to `NODE DRAIN` with the number of transferred records.
+    NodeDrainReply(NodeDrainReply),
+    /// Make the peer refuse or accept new values again.
+    NodeReadOnly(NodeReadOnly),
+    /// Reply to `NODE READ ONLY` with the current mode of the peer.
+    NodeReadOnlyReply(NodeReadOnlyReply),
+    /// List the records in the local storage of a peer.
+    DhtListLocal(DhtListLocal),
+    /// Reply to `DHT LIST LOCAL` with a page of records.
+    DhtListLocalReply(DhtListLocalReply),
+    /// List the finger table of a peer.
+    NodePeers(NodePeers),
+    /// Reply to `NODE PEERS` with a page of fingers.
+    NodePeersReply(NodePeersReply),
+    /// Walk the ring along the successors of a peer.
+    DhtRingWalk(DhtRingWalk),
+    /// Reply to `DHT RING WALK` with a page of peers.
+    DhtRingWalkReply(DhtRingWalkReply),
+    /// Export the records in the local storage of a peer with their values.
+    DhtExport(DhtExport),
+    /// Reply to `DHT EXPORT` with a page of records.
+    DhtExportReply(DhtExportReply),
+    /// Restore exported records into the local storage of a peer.
+    DhtImport(DhtImport),
+    /// Reply to `DHT IMPORT` with the number of records stored.
+    DhtImportReply(DhtImportReply),
+    /// Negotiate the encoding of an api connection.
+    ApiEncoding(ApiEncoding),
+    /// Reply to a conditional `DHT GET` if the value has not changed.
+    DhtNotModified(DhtNotModified),
+    /// Look up the values of several keys ahead of their `DHT GET`s.
+    DhtPrefetch(DhtPrefetch),
+    /// Compare the load of a peer with the rest of the ring.
+    NodeLoad(NodeLoad),
+    /// Reply to `NODE LOAD` with the load of the peer and the ring average.
+    NodeLoadReply(NodeLoadReply),
     /// Obtain the value for the given key if the peer is responsible for.
     StorageGet(StorageGet),
     /// Store a message at a specific peer which is responsible for the key.
@@ -68,6 +191,10 @@ pub enum Message {
     StoragePutSuccess(StoragePutSuccess),
     /// An error occured during a previous `DHT GET` or `DHT PUT` message.
     StorageFailure(StorageFailure),
+    /// Remove the value stored under a given key at a specific peer.
+    StorageDelete(StorageDelete),
+    /// A previous `STORAGE DELETE` operation removed a value.
+    StorageDeleteSuccess(StorageDeleteSuccess),
     /// Initiates a lookup for a node responsible for the given identifier.
     PeerFind(PeerFind),
     /// A peer close to the given identifier has been found.
@@ -77,90 +204,503 @@ pub enum Message {
     PredecessorNotify(PredecessorNotify),
     /// Reply to `PREDECESSOR GET` with the predecessor's address.
     PredecessorReply(PredecessorReply),
+    /// Lock the identifier range of the successor before joining the network.
+    JoinLock(JoinLock),
+    /// The successor has locked its range and transfers the stored values.
+    JoinAck(JoinAck),
+    /// The successor refused a join request.
+    JoinNack(JoinNack),
+    /// Make the successor apply the join after the transfer has completed.
+    JoinPublish(JoinPublish),
+    /// Wraps a p2p request or its reply together with a request ID such that
+    /// several requests can share one connection.
+    Correlated(Correlated),
+    /// Notify a neighbour that a peer leaves the network.
+    PeerLeave(PeerLeave),
+    /// Tells where a resumed transfer of values continues.
+    TransferAck(TransferAck),
+    /// Store several values at a peer with a single message.
+    StorageBulkPut(StorageBulkPut),
+    /// Reply to `STORAGE BULK PUT` with the keys of the rejected values.
+    StorageBulkPutReply(StorageBulkPutReply),
+    /// Ask a peer for its load.
+    PeerLoad(PeerLoad),
+    /// Reply to `PEER LOAD` with the load of the peer.
+    PeerLoadReply(PeerLoadReply),
+    /// Push a copy of a frequently requested value to a peer.
+    StorageCachePush(StorageCachePush),
 }
 
+#[cfg(feature = "network")]
 impl Message {
-    const DHT_PUT: u16 = 650;
-    const DHT_GET: u16 = 651;
-    const DHT_SUCCESS: u16 = 652;
-    const DHT_FAILURE: u16 = 653;
-
-    const STORAGE_GET: u16 = 1000;
-    const STORAGE_PUT: u16 = 1001;
-    const STORAGE_GET_SUCCESS: u16 = 1002;
-    const STORAGE_PUT_SUCCESS: u16 = 1003;
-    const STORAGE_FAILURE: u16 = 1004;
-
-    const PEER_FIND: u16 = 1050;
-    const PEER_FOUND: u16 = 1051;
-    const PREDECESSOR_NOTIFY: u16 = 1052;
-    const PREDECESSOR_REPLY: u16 = 1053;
+    /// Number of different message types
+    pub const KINDS: usize = 56;
 
-    pub fn parse<T: Read>(mut reader: T) -> io::Result<Self> {
-        let size = reader.read_u16::<NetworkEndian>()?;
-        let msg_type = reader.read_u16::<NetworkEndian>()?;
+    /// Names of all message types indexed by [`kind`]
+    ///
+    /// [`kind`]: #method.kind
+    pub const NAMES: [&'static str; Self::KINDS] = [
+        "DHT PUT",
+        "DHT GET",
+        "DHT SUCCESS",
+        "DHT FAILURE",
+        "DHT RESOLVE",
+        "DHT RESOLVE REPLY",
+        "NODE INFO",
+        "NODE INFO REPLY",
+        "DHT FLUSH",
+        "DHT FLUSH REPLY",
+        "DHT PUT SUCCESS",
+        "DHT DELETE",
+        "DHT DELETE REPLY",
+        "DHT CANCEL",
+        "NODE DRAIN",
+        "NODE DRAIN REPLY",
+        "NODE READ ONLY",
+        "NODE READ ONLY REPLY",
+        "DHT LIST LOCAL",
+        "DHT LIST LOCAL REPLY",
+        "NODE PEERS",
+        "NODE PEERS REPLY",
+        "DHT RING WALK",
+        "DHT RING WALK REPLY",
+        "DHT EXPORT",
+        "DHT EXPORT REPLY",
+        "DHT IMPORT",
+        "DHT IMPORT REPLY",
+        "API ENCODING",
+        "DHT NOT MODIFIED",
+        "DHT PREFETCH",
+        "NODE LOAD",
+        "NODE LOAD REPLY",
+        "STORAGE GET",
+        "STORAGE PUT",
+        "STORAGE GET SUCCESS",
+        "STORAGE PUT SUCCESS",
+        "STORAGE FAILURE",
+        "STORAGE DELETE",
+        "STORAGE DELETE SUCCESS",
+        "PEER FIND",
+        "PEER FOUND",
+        "PREDECESSOR GET",
+        "PREDECESSOR REPLY",
+        "JOIN LOCK",
+        "JOIN ACK",
+        "JOIN NACK",
+        "JOIN PUBLISH",
+        "CORRELATED",
+        "PEER LEAVE",
+        "TRANSFER ACK",
+        "STORAGE BULK PUT",
+        "STORAGE BULK PUT REPLY",
+        "PEER LOAD",
+        "PEER LOAD REPLY",
+        "STORAGE CACHE PUSH",
+    ];
 
-        if size < 4 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Size must include header",
-            ));
+    /// Returns the index of the message type in [`NAMES`].
+    ///
+    /// [`NAMES`]: #associatedconstant.NAMES
+    pub fn kind(&self) -> usize {
+        match self {
+            Message::DhtPut(_) => 0,
+            Message::DhtGet(_) => 1,
+            Message::DhtSuccess(_) => 2,
+            Message::DhtFailure(_) => 3,
+            Message::DhtResolve(_) => 4,
+            Message::DhtResolveReply(_) => 5,
+            Message::NodeInfo(_) => 6,
+            Message::NodeInfoReply(_) => 7,
+            Message::DhtFlush(_) => 8,
+            Message::DhtFlushReply(_) => 9,
+            Message::DhtPutSuccess(_) => 10,
+            Message::DhtDelete(_) => 11,
+            Message::DhtDeleteReply(_) => 12,
+            Message::DhtCancel(_) => 13,
+            Message::NodeDrain(_) => 14,
+            Message::NodeDrainReply(_) => 15,
+            Message::NodeReadOnly(_) => 16,
+            Message::NodeReadOnlyReply(_) => 17,
+            Message::DhtListLocal(_) => 18,
+            Message::DhtListLocalReply(_) => 19,
+            Message::NodePeers(_) => 20,
+            Message::NodePeersReply(_) => 21,
+            Message::DhtRingWalk(_) => 22,
+            Message::DhtRingWalkReply(_) => 23,
+            Message::DhtExport(_) => 24,
+            Message::DhtExportReply(_) => 25,
+            Message::DhtImport(_) => 26,
+            Message::DhtImportReply(_) => 27,
+            Message::ApiEncoding(_) => 28,
+            Message::DhtNotModified(_) => 29,
+            Message::DhtPrefetch(_) => 30,
+            Message::NodeLoad(_) => 31,
+            Message::NodeLoadReply(_) => 32,
+            Message::StorageGet(_) => 33,
+            Message::StoragePut(_) => 34,
+            Message::StorageGetSuccess(_) => 35,
+            Message::StoragePutSuccess(_) => 36,
+            Message::StorageFailure(_) => 37,
+            Message::StorageDelete(_) => 38,
+            Message::StorageDeleteSuccess(_) => 39,
+            Message::PeerFind(_) => 40,
+            Message::PeerFound(_) => 41,
+            Message::PredecessorNotify(_) => 42,
+            Message::PredecessorReply(_) => 43,
+            Message::JoinLock(_) => 44,
+            Message::JoinAck(_) => 45,
+            Message::JoinNack(_) => 46,
+            Message::JoinPublish(_) => 47,
+            Message::Correlated(_) => 48,
+            Message::PeerLeave(_) => 49,
+            Message::TransferAck(_) => 50,
+            Message::StorageBulkPut(_) => 51,
+            Message::StorageBulkPutReply(_) => 52,
+            Message::PeerLoad(_) => 53,
+            Message::PeerLoadReply(_) => 54,
+            Message::StorageCachePush(_) => 55,
+        }
+    }
+
+    /// Returns the human-readable name of the message type.
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[self.kind()]
+    }
+
+    /// Returns the message type of this message as transmitted in its
+    /// header, one of the constants in the [`codec`] module.
+    ///
+    /// [`codec`]: codec/index.html
+    pub fn message_type(&self) -> u16 {
+        match self {
+            Message::DhtPut(dht_put) => dht_put.message_type(),
+            Message::DhtGet(_) => codec::DHT_GET,
+            Message::DhtSuccess(_) => codec::DHT_SUCCESS,
+            Message::DhtFailure(_) => codec::DHT_FAILURE,
+            Message::DhtResolve(_) => codec::DHT_RESOLVE,
+            Message::DhtResolveReply(_) => codec::DHT_RESOLVE_REPLY,
+            Message::NodeInfo(_) => codec::NODE_INFO,
+            Message::NodeInfoReply(_) => codec::NODE_INFO_REPLY,
+            Message::DhtFlush(_) => codec::DHT_FLUSH,
+            Message::DhtFlushReply(_) => codec::DHT_FLUSH_REPLY,
+            Message::DhtPutSuccess(_) => codec::DHT_PUT_SUCCESS,
+            Message::DhtDelete(_) => codec::DHT_DELETE,
+            Message::DhtDeleteReply(_) => codec::DHT_DELETE_REPLY,
+            Message::DhtCancel(_) => codec::DHT_CANCEL,
+            Message::NodeDrain(_) => codec::NODE_DRAIN,
+            Message::NodeDrainReply(_) => codec::NODE_DRAIN_REPLY,
+            Message::NodeReadOnly(_) => codec::NODE_READ_ONLY,
+            Message::NodeReadOnlyReply(_) => codec::NODE_READ_ONLY_REPLY,
+            Message::DhtListLocal(_) => codec::DHT_LIST_LOCAL,
+            Message::DhtListLocalReply(_) => codec::DHT_LIST_LOCAL_REPLY,
+            Message::NodePeers(_) => codec::NODE_PEERS,
+            Message::NodePeersReply(_) => codec::NODE_PEERS_REPLY,
+            Message::DhtRingWalk(_) => codec::DHT_RING_WALK,
+            Message::DhtRingWalkReply(_) => codec::DHT_RING_WALK_REPLY,
+            Message::DhtExport(_) => codec::DHT_EXPORT,
+            Message::DhtExportReply(_) => codec::DHT_EXPORT_REPLY,
+            Message::DhtImport(_) => codec::DHT_IMPORT,
+            Message::DhtImportReply(_) => codec::DHT_IMPORT_REPLY,
+            Message::ApiEncoding(_) => codec::API_ENCODING,
+            Message::DhtNotModified(_) => codec::DHT_NOT_MODIFIED,
+            Message::DhtPrefetch(_) => codec::DHT_PREFETCH,
+            Message::NodeLoad(_) => codec::NODE_LOAD,
+            Message::NodeLoadReply(_) => codec::NODE_LOAD_REPLY,
+            Message::StorageGet(_) => codec::STORAGE_GET,
+            Message::StoragePut(_) => codec::STORAGE_PUT,
+            Message::StorageGetSuccess(_) => codec::STORAGE_GET_SUCCESS,
+            Message::StoragePutSuccess(_) => codec::STORAGE_PUT_SUCCESS,
+            Message::StorageFailure(_) => codec::STORAGE_FAILURE,
+            Message::StorageDelete(_) => codec::STORAGE_DELETE,
+            Message::StorageDeleteSuccess(_) => codec::STORAGE_DELETE_SUCCESS,
+            Message::PeerFind(_) => codec::PEER_FIND,
+            Message::PeerFound(_) => codec::PEER_FOUND,
+            Message::PredecessorNotify(_) => codec::PREDECESSOR_NOTIFY,
+            Message::PredecessorReply(_) => codec::PREDECESSOR_REPLY,
+            Message::JoinLock(_) => codec::JOIN_LOCK,
+            Message::JoinAck(_) => codec::JOIN_ACK,
+            Message::JoinNack(_) => codec::JOIN_NACK,
+            Message::JoinPublish(_) => codec::JOIN_PUBLISH,
+            Message::Correlated(_) => codec::CORRELATED,
+            Message::PeerLeave(_) => codec::PEER_LEAVE,
+            Message::TransferAck(_) => codec::TRANSFER_ACK,
+            Message::StorageBulkPut(_) => codec::STORAGE_BULK_PUT,
+            Message::StorageBulkPutReply(_) => codec::STORAGE_BULK_PUT_REPLY,
+            Message::PeerLoad(_) => codec::PEER_LOAD,
+            Message::PeerLoadReply(_) => codec::PEER_LOAD_REPLY,
+            Message::StorageCachePush(_) => codec::STORAGE_CACHE_PUSH,
+        }
+    }
+
+    /// Returns this message encoded including its header.
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut writer = io::Cursor::new(Vec::new());
+        self.write_to(&mut writer)?;
+
+        Ok(writer.into_inner())
+    }
+
+    /// Returns the values transferred by a `STORAGE PUT` or a
+    /// `STORAGE BULK PUT` message or the message itself if it is of another
+    /// type.
+    pub fn into_values(self) -> Result<Vec<StoragePut>, Box<Self>> {
+        match self {
+            Message::StoragePut(storage_put) => Ok(vec![storage_put]),
+            Message::StorageBulkPut(storage_bulk_put) => Ok(storage_bulk_put.values),
+            msg => Err(Box::new(msg)),
         }
+    }
+
+    pub fn parse<T: Read>(mut reader: T) -> io::Result<Self> {
+        let (size, msg_type) = codec::read_header(&mut reader)?;
+
+        let reader = &mut reader.take(u64::from(size) - codec::HEADER_SIZE as u64);
+
+        Self::parse_payload(msg_type, codec::read_payload(reader)?)
+    }
+
+    /// Parses the message encoded in `frame` including its header.
+    ///
+    /// Unlike [`parse`], the values carried by the message refer to `frame`
+    /// instead of being copied.
+    ///
+    /// [`parse`]: #method.parse
+    pub fn from_bytes(frame: Bytes) -> io::Result<Self> {
+        let (size, msg_type) = codec::read_header(&mut &frame[..])?;
+        let end = usize::from(size).min(frame.len());
 
-        let reader = &mut reader.take(u64::from(size) - 4);
+        Self::parse_payload(msg_type, frame.slice(codec::HEADER_SIZE..end))
+    }
 
+    fn parse_payload(msg_type: u16, payload: Bytes) -> io::Result<Self> {
         match msg_type {
-            Self::DHT_PUT => {
+            codec::DHT_PUT => {
                 // parse DhtPut payload
-                MessagePayload::parse(reader).map(Message::DhtPut)
+                MessagePayload::parse_bytes(payload).map(Message::DhtPut)
+            }
+            codec::DHT_PUT_V2 => {
+                // parse DhtPut payload with a TTL in milliseconds
+                DhtPut::parse_v2(payload).map(Message::DhtPut)
             }
-            Self::DHT_GET => {
+            codec::DHT_GET => {
                 // parse DhtGet payload
-                MessagePayload::parse(reader).map(Message::DhtGet)
+                MessagePayload::parse_bytes(payload).map(Message::DhtGet)
             }
-            Self::DHT_SUCCESS => {
+            codec::DHT_SUCCESS => {
                 // parse DhtSuccess payload
-                MessagePayload::parse(reader).map(Message::DhtSuccess)
+                MessagePayload::parse_bytes(payload).map(Message::DhtSuccess)
             }
-            Self::DHT_FAILURE => {
+            codec::DHT_FAILURE => {
                 // parse DhtFailure payload
-                MessagePayload::parse(reader).map(Message::DhtFailure)
+                MessagePayload::parse_bytes(payload).map(Message::DhtFailure)
+            }
+            codec::DHT_RESOLVE => {
+                // parse DhtResolve payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtResolve)
+            }
+            codec::DHT_RESOLVE_REPLY => {
+                // parse DhtResolveReply payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtResolveReply)
+            }
+            codec::NODE_INFO => {
+                // parse NodeInfo payload
+                MessagePayload::parse_bytes(payload).map(Message::NodeInfo)
+            }
+            codec::NODE_INFO_REPLY => {
+                // parse NodeInfoReply payload
+                MessagePayload::parse_bytes(payload).map(Message::NodeInfoReply)
+            }
+            codec::DHT_FLUSH => {
+                // parse DhtFlush payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtFlush)
+            }
+            codec::DHT_FLUSH_REPLY => {
+                // parse DhtFlushReply payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtFlushReply)
+            }
+            codec::DHT_PUT_SUCCESS => {
+                // parse DhtPutSuccess payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtPutSuccess)
+            }
+            codec::DHT_DELETE => {
+                // parse DhtDelete payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtDelete)
+            }
+            codec::DHT_DELETE_REPLY => {
+                // parse DhtDeleteReply payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtDeleteReply)
+            }
+            codec::DHT_CANCEL => {
+                // parse DhtCancel payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtCancel)
+            }
+            codec::NODE_DRAIN => {
+                // parse NodeDrain payload
+                MessagePayload::parse_bytes(payload).map(Message::NodeDrain)
+            }
+            codec::NODE_DRAIN_REPLY => {
+                // parse NodeDrainReply payload
+                MessagePayload::parse_bytes(payload).map(Message::NodeDrainReply)
+            }
+            codec::NODE_READ_ONLY => {
+                // parse NodeReadOnly payload
+                MessagePayload::parse_bytes(payload).map(Message::NodeReadOnly)
             }
-            Self::STORAGE_GET => {
+            codec::NODE_READ_ONLY_REPLY => {
+                // parse NodeReadOnlyReply payload
+                MessagePayload::parse_bytes(payload).map(Message::NodeReadOnlyReply)
+            }
+            codec::DHT_LIST_LOCAL => {
+                // parse DhtListLocal payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtListLocal)
+            }
+            codec::DHT_LIST_LOCAL_REPLY => {
+                // parse DhtListLocalReply payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtListLocalReply)
+            }
+            codec::NODE_PEERS => {
+                // parse NodePeers payload
+                MessagePayload::parse_bytes(payload).map(Message::NodePeers)
+            }
+            codec::NODE_PEERS_REPLY => {
+                // parse NodePeersReply payload
+                MessagePayload::parse_bytes(payload).map(Message::NodePeersReply)
+            }
+            codec::DHT_RING_WALK => {
+                // parse DhtRingWalk payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtRingWalk)
+            }
+            codec::DHT_RING_WALK_REPLY => {
+                // parse DhtRingWalkReply payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtRingWalkReply)
+            }
+            codec::DHT_EXPORT => {
+                // parse DhtExport payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtExport)
+            }
+            codec::DHT_EXPORT_REPLY => {
+                // parse DhtExportReply payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtExportReply)
+            }
+            codec::DHT_IMPORT => {
+                // parse DhtImport payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtImport)
+            }
+            codec::DHT_IMPORT_REPLY => {
+                // parse DhtImportReply payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtImportReply)
+            }
+            codec::API_ENCODING => {
+                // parse ApiEncoding payload
+                MessagePayload::parse_bytes(payload).map(Message::ApiEncoding)
+            }
+            codec::DHT_NOT_MODIFIED => {
+                // parse DhtNotModified payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtNotModified)
+            }
+            codec::DHT_PREFETCH => {
+                // parse DhtPrefetch payload
+                MessagePayload::parse_bytes(payload).map(Message::DhtPrefetch)
+            }
+            codec::NODE_LOAD => {
+                // parse NodeLoad payload
+                MessagePayload::parse_bytes(payload).map(Message::NodeLoad)
+            }
+            codec::NODE_LOAD_REPLY => {
+                // parse NodeLoadReply payload
+                MessagePayload::parse_bytes(payload).map(Message::NodeLoadReply)
+            }
+            codec::STORAGE_GET => {
                 // parse StorageGet payload
-                MessagePayload::parse(reader).map(Message::StorageGet)
+                MessagePayload::parse_bytes(payload).map(Message::StorageGet)
             }
-            Self::STORAGE_PUT => {
+            codec::STORAGE_PUT => {
                 // parse StoragePut payload
-                MessagePayload::parse(reader).map(Message::StoragePut)
+                MessagePayload::parse_bytes(payload).map(Message::StoragePut)
             }
-            Self::STORAGE_GET_SUCCESS => {
+            codec::STORAGE_GET_SUCCESS => {
                 // parse StorageGetSuccess payload
-                MessagePayload::parse(reader).map(Message::StorageGetSuccess)
+                MessagePayload::parse_bytes(payload).map(Message::StorageGetSuccess)
             }
-            Self::STORAGE_PUT_SUCCESS => {
+            codec::STORAGE_PUT_SUCCESS => {
                 // parse StoragePutSuccess payload
-                MessagePayload::parse(reader).map(Message::StoragePutSuccess)
+                MessagePayload::parse_bytes(payload).map(Message::StoragePutSuccess)
             }
-            Self::STORAGE_FAILURE => {
+            codec::STORAGE_FAILURE => {
                 // parse StorageFailure payload
-                MessagePayload::parse(reader).map(Message::StorageFailure)
+                MessagePayload::parse_bytes(payload).map(Message::StorageFailure)
+            }
+            codec::STORAGE_DELETE => {
+                // parse StorageDelete payload
+                MessagePayload::parse_bytes(payload).map(Message::StorageDelete)
+            }
+            codec::STORAGE_DELETE_SUCCESS => {
+                // parse StorageDeleteSuccess payload
+                MessagePayload::parse_bytes(payload).map(Message::StorageDeleteSuccess)
             }
-            Self::PEER_FIND => {
+            codec::PEER_FIND => {
                 // parse PeerFind payload
-                MessagePayload::parse(reader).map(Message::PeerFind)
+                MessagePayload::parse_bytes(payload).map(Message::PeerFind)
             }
-            Self::PEER_FOUND => {
+            codec::PEER_FOUND => {
                 // parse PeerFound payload
-                MessagePayload::parse(reader).map(Message::PeerFound)
+                MessagePayload::parse_bytes(payload).map(Message::PeerFound)
             }
-            Self::PREDECESSOR_NOTIFY => {
+            codec::PREDECESSOR_NOTIFY => {
                 // parse PredecessorNotify payload
-                MessagePayload::parse(reader).map(Message::PredecessorNotify)
+                MessagePayload::parse_bytes(payload).map(Message::PredecessorNotify)
             }
-            Self::PREDECESSOR_REPLY => {
+            codec::PREDECESSOR_REPLY => {
                 // parse PredecessorReply payload
-                MessagePayload::parse(reader).map(Message::PredecessorReply)
+                MessagePayload::parse_bytes(payload).map(Message::PredecessorReply)
+            }
+            codec::JOIN_LOCK => {
+                // parse JoinLock payload
+                MessagePayload::parse_bytes(payload).map(Message::JoinLock)
+            }
+            codec::JOIN_ACK => {
+                // parse JoinAck payload
+                MessagePayload::parse_bytes(payload).map(Message::JoinAck)
+            }
+            codec::JOIN_NACK => {
+                // parse JoinNack payload
+                MessagePayload::parse_bytes(payload).map(Message::JoinNack)
+            }
+            codec::JOIN_PUBLISH => {
+                // parse JoinPublish payload
+                MessagePayload::parse_bytes(payload).map(Message::JoinPublish)
+            }
+            codec::CORRELATED => {
+                // parse Correlated payload
+                MessagePayload::parse_bytes(payload).map(Message::Correlated)
+            }
+            codec::PEER_LEAVE => {
+                // parse PeerLeave payload
+                MessagePayload::parse_bytes(payload).map(Message::PeerLeave)
+            }
+            codec::TRANSFER_ACK => {
+                // parse TransferAck payload
+                MessagePayload::parse_bytes(payload).map(Message::TransferAck)
+            }
+            codec::STORAGE_BULK_PUT => {
+                // parse StorageBulkPut payload
+                MessagePayload::parse_bytes(payload).map(Message::StorageBulkPut)
+            }
+            codec::STORAGE_BULK_PUT_REPLY => {
+                // parse StorageBulkPutReply payload
+                MessagePayload::parse_bytes(payload).map(Message::StorageBulkPutReply)
+            }
+            codec::PEER_LOAD => {
+                // parse PeerLoad payload
+                MessagePayload::parse_bytes(payload).map(Message::PeerLoad)
+            }
+            codec::PEER_LOAD_REPLY => {
+                // parse PeerLoadReply payload
+                MessagePayload::parse_bytes(payload).map(Message::PeerLoadReply)
+            }
+            codec::STORAGE_CACHE_PUSH => {
+                // parse StorageCachePush payload
+                MessagePayload::parse_bytes(payload).map(Message::StorageCachePush)
             }
             _ => Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
@@ -172,64 +712,204 @@ impl Message {
     pub fn write_to<T: Write + Seek>(&self, mut writer: T) -> io::Result<usize> {
         // reserve two bytes for size
         writer.write_u16::<NetworkEndian>(0)?;
+        writer.write_u16::<NetworkEndian>(self.message_type())?;
 
         match self {
             Message::DhtPut(dht_put) => {
-                writer.write_u16::<NetworkEndian>(Self::DHT_PUT)?;
                 dht_put.write_to(&mut writer)?;
             }
             Message::DhtGet(dht_get) => {
-                writer.write_u16::<NetworkEndian>(Self::DHT_GET)?;
                 dht_get.write_to(&mut writer)?;
             }
             Message::DhtSuccess(dht_success) => {
-                writer.write_u16::<NetworkEndian>(Self::DHT_SUCCESS)?;
                 dht_success.write_to(&mut writer)?;
             }
             Message::DhtFailure(dht_failure) => {
-                writer.write_u16::<NetworkEndian>(Self::DHT_FAILURE)?;
                 dht_failure.write_to(&mut writer)?;
             }
+            Message::DhtResolve(dht_resolve) => {
+                dht_resolve.write_to(&mut writer)?;
+            }
+            Message::DhtResolveReply(dht_resolve_reply) => {
+                dht_resolve_reply.write_to(&mut writer)?;
+            }
+            Message::NodeInfo(node_info) => {
+                node_info.write_to(&mut writer)?;
+            }
+            Message::NodeInfoReply(node_info_reply) => {
+                node_info_reply.write_to(&mut writer)?;
+            }
+            Message::DhtFlush(dht_flush) => {
+                dht_flush.write_to(&mut writer)?;
+            }
+            Message::DhtFlushReply(dht_flush_reply) => {
+                dht_flush_reply.write_to(&mut writer)?;
+            }
+            Message::DhtPutSuccess(dht_put_success) => {
+                dht_put_success.write_to(&mut writer)?;
+            }
+            Message::DhtDelete(dht_delete) => {
+                dht_delete.write_to(&mut writer)?;
+            }
+            Message::DhtDeleteReply(dht_delete_reply) => {
+                dht_delete_reply.write_to(&mut writer)?;
+            }
+            Message::DhtCancel(dht_cancel) => {
+                dht_cancel.write_to(&mut writer)?;
+            }
+            Message::NodeDrain(node_drain) => {
+                node_drain.write_to(&mut writer)?;
+            }
+            Message::NodeDrainReply(node_drain_reply) => {
+                node_drain_reply.write_to(&mut writer)?;
+            }
+            Message::NodeReadOnly(node_read_only) => {
+                node_read_only.write_to(&mut writer)?;
+            }
+            Message::NodeReadOnlyReply(node_read_only_reply) => {
+                node_read_only_reply.write_to(&mut writer)?;
+            }
+            Message::DhtListLocal(dht_list_local) => {
+                dht_list_local.write_to(&mut writer)?;
+            }
+            Message::DhtListLocalReply(dht_list_local_reply) => {
+                dht_list_local_reply.write_to(&mut writer)?;
+            }
+            Message::NodePeers(node_peers) => {
+                node_peers.write_to(&mut writer)?;
+            }
+            Message::NodePeersReply(node_peers_reply) => {
+                node_peers_reply.write_to(&mut writer)?;
+            }
+            Message::DhtRingWalk(dht_ring_walk) => {
+                dht_ring_walk.write_to(&mut writer)?;
+            }
+            Message::DhtRingWalkReply(dht_ring_walk_reply) => {
+                dht_ring_walk_reply.write_to(&mut writer)?;
+            }
+            Message::DhtExport(dht_export) => {
+                dht_export.write_to(&mut writer)?;
+            }
+            Message::DhtExportReply(dht_export_reply) => {
+                dht_export_reply.write_to(&mut writer)?;
+            }
+            Message::DhtImport(dht_import) => {
+                dht_import.write_to(&mut writer)?;
+            }
+            Message::DhtImportReply(dht_import_reply) => {
+                dht_import_reply.write_to(&mut writer)?;
+            }
+            Message::ApiEncoding(api_encoding) => {
+                api_encoding.write_to(&mut writer)?;
+            }
+            Message::DhtNotModified(dht_not_modified) => {
+                dht_not_modified.write_to(&mut writer)?;
+            }
+            Message::DhtPrefetch(dht_prefetch) => {
+                dht_prefetch.write_to(&mut writer)?;
+            }
+            Message::NodeLoad(node_load) => {
+                node_load.write_to(&mut writer)?;
+            }
+            Message::NodeLoadReply(node_load_reply) => {
+                node_load_reply.write_to(&mut writer)?;
+            }
             Message::StorageGet(storage_get) => {
-                writer.write_u16::<NetworkEndian>(Self::STORAGE_GET)?;
                 storage_get.write_to(&mut writer)?;
             }
             Message::StoragePut(storage_put) => {
-                writer.write_u16::<NetworkEndian>(Self::STORAGE_PUT)?;
                 storage_put.write_to(&mut writer)?;
             }
             Message::StorageGetSuccess(storage_get_success) => {
-                writer.write_u16::<NetworkEndian>(Self::STORAGE_GET_SUCCESS)?;
                 storage_get_success.write_to(&mut writer)?;
             }
             Message::StoragePutSuccess(storage_put_success) => {
-                writer.write_u16::<NetworkEndian>(Self::STORAGE_PUT_SUCCESS)?;
                 storage_put_success.write_to(&mut writer)?;
             }
             Message::StorageFailure(storage_failure) => {
-                writer.write_u16::<NetworkEndian>(Self::STORAGE_FAILURE)?;
                 storage_failure.write_to(&mut writer)?;
             }
+            Message::StorageDelete(storage_delete) => {
+                storage_delete.write_to(&mut writer)?;
+            }
+            Message::StorageDeleteSuccess(storage_delete_success) => {
+                storage_delete_success.write_to(&mut writer)?;
+            }
             Message::PeerFind(peer_find) => {
-                writer.write_u16::<NetworkEndian>(Self::PEER_FIND)?;
                 peer_find.write_to(&mut writer)?;
             }
             Message::PeerFound(peer_found) => {
-                writer.write_u16::<NetworkEndian>(Self::PEER_FOUND)?;
                 peer_found.write_to(&mut writer)?;
             }
             Message::PredecessorNotify(predecessor_get) => {
-                writer.write_u16::<NetworkEndian>(Self::PREDECESSOR_NOTIFY)?;
                 predecessor_get.write_to(&mut writer)?;
             }
             Message::PredecessorReply(predecessor_reply) => {
-                writer.write_u16::<NetworkEndian>(Self::PREDECESSOR_REPLY)?;
                 predecessor_reply.write_to(&mut writer)?;
             }
+            Message::JoinLock(join_lock) => {
+                join_lock.write_to(&mut writer)?;
+            }
+            Message::JoinAck(join_ack) => {
+                join_ack.write_to(&mut writer)?;
+            }
+            Message::JoinNack(join_nack) => {
+                join_nack.write_to(&mut writer)?;
+            }
+            Message::JoinPublish(join_publish) => {
+                join_publish.write_to(&mut writer)?;
+            }
+            Message::Correlated(correlated) => {
+                correlated.write_to(&mut writer)?;
+            }
+            Message::PeerLeave(peer_leave) => {
+                peer_leave.write_to(&mut writer)?;
+            }
+            Message::TransferAck(transfer_ack) => {
+                transfer_ack.write_to(&mut writer)?;
+            }
+            Message::StorageBulkPut(storage_bulk_put) => {
+                storage_bulk_put.write_to(&mut writer)?;
+            }
+            Message::StorageBulkPutReply(storage_bulk_put_reply) => {
+                storage_bulk_put_reply.write_to(&mut writer)?;
+            }
+            Message::PeerLoad(peer_load) => {
+                peer_load.write_to(&mut writer)?;
+            }
+            Message::PeerLoadReply(peer_load_reply) => {
+                peer_load_reply.write_to(&mut writer)?;
+            }
+            Message::StorageCachePush(storage_cache_push) => {
+                storage_cache_push.write_to(&mut writer)?;
+            }
         }
 
         // write size at beginning of writer
-        let size = writer.seek(io::SeekFrom::Current(0))?;
+        let size = writer.stream_position()?;
+
+        writer.seek(io::SeekFrom::Start(0))?;
+        writer.write_u16::<NetworkEndian>(size as u16)?;
+
+        Ok(size as usize)
+    }
+
+    /// Writes this message wrapped in a [`Correlated`] envelope carrying the
+    /// given `request_id`.
+    ///
+    /// [`Correlated`]: p2p/struct.Correlated.html
+    pub fn write_correlated_to<T: Write + Seek>(
+        &self,
+        request_id: u32,
+        mut writer: T,
+    ) -> io::Result<usize> {
+        // reserve two bytes for size
+        writer.write_u16::<NetworkEndian>(0)?;
+        writer.write_u16::<NetworkEndian>(codec::CORRELATED)?;
+        p2p::write_correlated(&mut writer, request_id, self)?;
+
+        // write size at beginning of writer
+        let size = writer.stream_position()?;
 
         writer.seek(io::SeekFrom::Start(0))?;
         writer.write_u16::<NetworkEndian>(size as u16)?;
@@ -238,38 +918,31 @@ impl Message {
     }
 }
 
+#[cfg(feature = "network")]
 impl fmt::Display for Message {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let name = match self {
-            Message::DhtPut(_) => "DHT PUT",
-            Message::DhtGet(_) => "DHT GET",
-            Message::DhtSuccess(_) => "DHT SUCCESS",
-            Message::DhtFailure(_) => "DHT FAILURE",
-            Message::StorageGet(_) => "STORAGE GET",
-            Message::StoragePut(_) => "STORAGE PUT",
-            Message::StorageGetSuccess(_) => "STORAGE GET SUCCESS",
-            Message::StoragePutSuccess(_) => "STORAGE PUT SUCCESS",
-            Message::StorageFailure(_) => "STORAGE FAILURE",
-            Message::PeerFind(_) => "PEER FIND",
-            Message::PeerFound(_) => "PEER FOUND",
-            Message::PredecessorNotify(_) => "PREDECESSOR GET",
-            Message::PredecessorReply(_) => "PREDECESSOR REPLY",
-        };
-
-        name.fmt(f)
+        self.name().fmt(f)
     }
 }
 
+#[cfg(feature = "network")]
 pub trait MessagePayload: Sized {
-    fn parse(reader: &mut Read) -> io::Result<Self>;
+    fn parse(reader: &mut dyn Read) -> io::Result<Self>;
 
-    fn write_to(&self, writer: &mut Write) -> io::Result<()>;
+    /// Parses the payload `payload` which the values of the message may refer
+    /// to instead of copying them.
+    fn parse_bytes(payload: Bytes) -> io::Result<Self> {
+        Self::parse(&mut &payload[..])
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()>;
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "network"))]
 mod tests {
     use super::*;
-    use std::error::Error;
+    use crate::dht::{DhtKey, DhtValue};
+    use crate::storage;
     use std::fmt::Debug;
     use std::io::{self, Cursor};
 
@@ -277,13 +950,16 @@ mod tests {
     where
         T: MessagePayload + Debug + PartialEq,
     {
-        let mut cursor = Cursor::new(&buf[..]);
+        let mut cursor = Cursor::new(buf);
         let parsed = T::parse(&mut cursor).unwrap();
         assert_eq!(msg, parsed);
 
+        let parsed = T::parse_bytes(Bytes::copy_from_slice(buf)).unwrap();
+        assert_eq!(msg, parsed);
+
         let mut vec = Vec::new();
         msg.write_to(&mut vec).unwrap();
-        assert_eq!(&buf[..], &vec[..]);
+        assert_eq!(buf, &vec[..]);
     }
 
     #[test]
@@ -304,10 +980,11 @@ mod tests {
         ];
 
         let msg = Message::DhtPut(DhtPut {
-            ttl: 12,
+            ttl: 12_000,
             replication: 4,
-            key: [3; 32],
-            value: vec![1, 2, 3, 4, 5],
+            acks: 0,
+            key: DhtKey::from([3; 32]),
+            value: DhtValue::new(vec![1, 2, 3, 4, 5]).unwrap(),
         });
 
         let parsed = Message::parse(Cursor::new(&buf[..])).unwrap();
@@ -315,6 +992,43 @@ mod tests {
         assert_eq!(msg, parsed);
     }
 
+    #[test]
+    fn message_from_bytes() {
+        let raw_key = [3; 32];
+        let msg = StoragePut::builder()
+            .key(storage::Key {
+                raw_key,
+                replication_index: 1,
+            })
+            .value(vec![1, 2, 3, 4, 5])
+            .build();
+
+        let mut buffer = Vec::new();
+        Message::StoragePut(msg)
+            .write_correlated_to(7, Cursor::new(&mut buffer))
+            .unwrap();
+        // some noise
+        buffer.extend_from_slice(&[6, 7, 8]);
+
+        let frame = Bytes::from(buffer);
+        let msg = match Message::from_bytes(frame.clone()).unwrap() {
+            Message::Correlated(correlated) => *correlated.message,
+            msg => panic!("expected CORRELATED but got {}", msg),
+        };
+
+        let storage_put = match msg {
+            Message::StoragePut(storage_put) => storage_put,
+            msg => panic!("expected STORAGE PUT but got {}", msg),
+        };
+
+        assert_eq!(raw_key, storage_put.raw_key);
+        assert_eq!([1, 2, 3, 4, 5], *storage_put.value);
+
+        // the value refers to the frame
+        let offset = frame.len() - 8;
+        assert_eq!(frame[offset..].as_ptr(), storage_put.value.as_ptr());
+    }
+
     #[test]
     fn message_parse_empty_buffer() {
         let buf = [];
@@ -350,7 +1064,7 @@ mod tests {
         let err = Message::parse(Cursor::new(&buf[..])).err().unwrap();
 
         assert_eq!(io::ErrorKind::InvalidInput, err.kind());
-        assert_eq!("Size must include header", err.description());
+        assert_eq!("Size must include header", err.to_string());
     }
 
     #[test]
@@ -364,7 +1078,7 @@ mod tests {
         let err = Message::parse(Cursor::new(&buf[..])).err().unwrap();
 
         assert_eq!(io::ErrorKind::InvalidInput, err.kind());
-        assert_eq!("Invalid message type", err.description());
+        assert_eq!("Invalid message type", err.to_string());
     }
 
     #[test]
@@ -383,10 +1097,11 @@ mod tests {
         ];
 
         let msg = Message::DhtPut(DhtPut {
-            ttl: 12,
+            ttl: 12_000,
             replication: 4,
-            key: [3; 32],
-            value: vec![1, 2, 3, 4, 5],
+            acks: 0,
+            key: DhtKey::from([3; 32]),
+            value: DhtValue::new(vec![1, 2, 3, 4, 5]).unwrap(),
         });
 
         let mut buffer = [0; 64000];
@@ -395,4 +1110,47 @@ mod tests {
         assert_eq!(45, size);
         assert_eq!(&buf[..], &buffer[..size]);
     }
+
+    #[test]
+    fn message_dht_put_v2_roundtrip() {
+        let msg = Message::DhtPut(DhtPut {
+            ttl: 500,
+            replication: 4,
+            acks: 1,
+            key: DhtKey::from([3; 32]),
+            value: DhtValue::new(vec![1, 2, 3, 4, 5]).unwrap(),
+        });
+
+        let frame = msg.to_bytes().unwrap();
+        assert_eq!(&[0, 53, 2, 171], &frame[..codec::HEADER_SIZE]);
+
+        assert_eq!(
+            msg,
+            Message::from_bytes(Bytes::from(frame.clone())).unwrap()
+        );
+        assert_eq!(msg, Message::parse(Cursor::new(&frame[..])).unwrap());
+    }
+
+    #[test]
+    fn message_write_correlated_to() {
+        let socket_addr = "127.0.0.1:8080".parse().unwrap();
+
+        let msg = Message::Correlated(Correlated {
+            request_id: 7,
+            message: Box::new(Message::JoinPublish(JoinPublish { socket_addr })),
+        });
+
+        let mut expected = [0; 64000];
+        let expected_size = msg.write_to(Cursor::new(&mut expected[..])).unwrap();
+
+        let msg = Message::JoinPublish(JoinPublish { socket_addr });
+
+        let mut buffer = [0; 64000];
+        let size = msg
+            .write_correlated_to(7, Cursor::new(&mut buffer[..]))
+            .unwrap();
+
+        assert_eq!(30, size);
+        assert_eq!(&expected[..expected_size], &buffer[..size]);
+    }
 }
@@ -1,9 +1,19 @@
-use super::MessagePayload;
+use super::codec::{
+    self, read_load, read_optional_u32, read_socket_addr, read_socket_addrs, write_load,
+    write_optional_u32, write_socket_addr, write_socket_addrs, AUTHORITATIVE_FLAG, BUDGET_FLAG,
+    COLLISION_FLAG, HEADER_SIZE, MAX_MESSAGE_SIZE, REQUESTER_FLAG, RESUME_FLAG, TRACE_FLAG,
+};
+use super::{Message, MessagePayload};
+use crate::load::Load;
 use crate::routing::identifier::Identifier;
 use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use bytes::Bytes;
+use std::fmt;
 use std::io;
 use std::io::prelude::*;
-use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::io::Cursor;
+use std::mem;
+use std::net::SocketAddr;
 
 /// This message can be sent to a peer which is responsible for the given key
 /// to obtain the value for the given key.
@@ -11,11 +21,28 @@ use std::net::{IpAddr, Ipv6Addr, SocketAddr};
 /// Its ip address has to be known already. The peer looks whether it has stored
 /// a value for the given key and returns it in a [`StorageGetSuccess`] message.
 ///
+/// The optional `budget` is the time in milliseconds the requesting peer is
+/// willing to wait for the reply.
+///
+/// If `if_none_match` is given, the peer only returns a value newer than that
+/// version and replies with a [`StorageFailure`] for the reason
+/// [`FailureReason::NotModified`] otherwise.
+///
+/// The optional `requester` is the address the requesting peer listens on if
+/// it caches values. The peer may push a copy of a frequently requested
+/// value to it with a [`StorageCachePush`] message.
+///
 /// [`StorageGetSuccess`]: struct.StorageGetSuccess.html
+/// [`StorageFailure`]: struct.StorageFailure.html
+/// [`FailureReason::NotModified`]: enum.FailureReason.html#variant.NotModified
+/// [`StorageCachePush`]: struct.StorageCachePush.html
 #[derive(Debug, PartialEq)]
 pub struct StorageGet {
     pub replication_index: u8,
     pub raw_key: [u8; 32],
+    pub budget: Option<u32>,
+    pub if_none_match: Option<u64>,
+    pub requester: Option<SocketAddr>,
 }
 
 /// To store a message at a specific peer of which the ip address is already
@@ -25,20 +52,33 @@ pub struct StorageGet {
 /// [`StoragePutSuccess`]: struct.StoragePutSuccess.html
 #[derive(Debug, PartialEq)]
 pub struct StoragePut {
-    pub ttl: u16,
+    /// Time to live in milliseconds
+    pub ttl: u64,
     pub replication_index: u8,
+    /// Total number of replicas of the value or zero if unknown
+    pub replicas: u8,
+    /// Length of the prefix of the key which is its namespace or zero if
+    /// the key belongs to no namespace
+    pub namespace_len: u8,
     pub raw_key: [u8; 32],
-    pub value: Vec<u8>,
+    pub version: u64,
+    pub value: Bytes,
 }
 
 /// If after a [`StorageGet`] message the key was found, the peer should reply
-/// with the corresponding value attached to this message.
+/// with the corresponding value and its version attached to this message.
+///
+/// The reply also carries the remaining `ttl` of the value in milliseconds
+/// and the time the peer `stored` it in milliseconds since the unix epoch.
 ///
 /// [`StorageGet`]: struct.StorageGet.html
 #[derive(Debug, PartialEq)]
 pub struct StorageGetSuccess {
     pub raw_key: [u8; 32],
-    pub value: Vec<u8>,
+    pub version: u64,
+    pub ttl: u64,
+    pub stored: u64,
+    pub value: Bytes,
 }
 
 /// After a successful [`StoragePut`] operation, the peer should reply with this
@@ -53,15 +93,75 @@ pub struct StoragePutSuccess {
     pub raw_key: [u8; 32],
 }
 
+/// This message asks a peer to remove the value stored under the given key and
+/// replication index. The peer should answer with a [`StorageDeleteSuccess`]
+/// message if a value has been removed.
+///
+/// [`StorageDeleteSuccess`]: struct.StorageDeleteSuccess.html
+#[derive(Debug, PartialEq)]
+pub struct StorageDelete {
+    pub replication_index: u8,
+    pub raw_key: [u8; 32],
+}
+
+/// After a successful [`StorageDelete`] operation, the peer should reply with
+/// this success message.
+///
+/// [`StorageDelete`]: struct.StorageDelete.html
+#[derive(Debug, PartialEq)]
+pub struct StorageDeleteSuccess {
+    pub raw_key: [u8; 32],
+}
+
 /// If a [`StorageGet`] or [`StoragePut`] fails for some reason, this message
 /// should be sent back. However, one cannot rely on a failure message being
 /// sent back since there can also be timeouts or other issues.
 ///
+/// The `reason` is optional for compatibility with older peers.
+///
 /// [`StorageGet`]: struct.StorageGet.html
 /// [`StoragePut`]: struct.StoragePut.html
 #[derive(Debug, PartialEq)]
 pub struct StorageFailure {
     pub raw_key: [u8; 32],
+    pub reason: Option<FailureReason>,
+}
+
+/// The reason why a peer replied with a [`StorageFailure`]
+///
+/// [`StorageFailure`]: struct.StorageFailure.html
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FailureReason {
+    /// No value is stored under the key.
+    NotFound,
+    /// A value is stored under the key already.
+    Exists,
+    /// The range of the key is being transferred to a joining peer.
+    Locked,
+    /// The peer does not accept new values at the moment.
+    ReadOnly,
+    /// The authorizer of the peer denied the request.
+    Denied,
+    /// The stored value is not newer than the version of the request.
+    NotModified,
+    /// The value violates the policy of its namespace.
+    PolicyViolated,
+}
+
+impl fmt::Display for FailureReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            FailureReason::NotFound => "no value stored",
+            FailureReason::Exists => "value exists already",
+            FailureReason::Locked => "range locked by a join",
+            FailureReason::ReadOnly => "peer is read-only",
+            FailureReason::Denied => "request denied",
+            FailureReason::NotModified => "value not modified",
+            FailureReason::PolicyViolated => "policy violated",
+        };
+
+        description.fmt(f)
+    }
 }
 
 /// This message initiates a lookup for a node responsible for the given
@@ -69,21 +169,43 @@ pub struct StorageFailure {
 /// closest to the requested identifier.
 ///
 /// This can be implemented using finger tables.
+///
+/// If `trace` is set, the receiving peer appends its own address to the
+/// trace and returns it in the [`PeerFound`] reply. Passing the trace on to
+/// the next hop records the full path of a lookup.
+///
+/// The optional `budget` is the time in milliseconds left for the whole
+/// lookup. Peers do not reply once it is spent.
+///
+/// [`PeerFound`]: struct.PeerFound.html
 #[derive(Debug, PartialEq)]
 pub struct PeerFind {
     pub identifier: Identifier,
+    pub trace: Option<Vec<SocketAddr>>,
+    pub budget: Option<u32>,
 }
 
-/// If, after a [`PeerFind`] operation, a node has been found which is closest
-/// to the given identifier, the address of that peer should be included in this
-/// message. If the requested peer itself is responsible for the identifier,
-/// it should reply with its own address.
+/// Reply to a [`PeerFind`] request naming the successor of the identifier,
+/// i.e. the peer responsible for it, as far as the replying peer knows.
+///
+/// If the replying peer itself is responsible for the identifier, it replies
+/// with its own address and marks the reply as `authoritative`. Otherwise the
+/// reply names the successor of the replying peer if the identifier lies
+/// between the two, or the closest peer from its routing table to continue
+/// the lookup with. Such replies are not authoritative since a peer joins at
+/// its successor and its predecessor only learns about it when it
+/// stabilizes.
+///
+/// If the request contained a trace, the trace is returned with the address of
+/// the replying peer appended.
 ///
 /// [`PeerFind`]: struct.PeerFind.html
 #[derive(Debug, PartialEq)]
 pub struct PeerFound {
     pub identifier: Identifier,
     pub socket_addr: SocketAddr,
+    pub authoritative: bool,
+    pub trace: Option<Vec<SocketAddr>>,
 }
 
 /// This message allows to notify some other peer of a potentially new predecessor.
@@ -103,42 +225,295 @@ pub struct PredecessorReply {
     pub socket_addr: SocketAddr,
 }
 
+/// A joining peer sends this message to its future successor to lock the
+/// identifier range it is about to take over.
+///
+/// The successor either replies with a [`JoinAck`] and transfers all values in
+/// that range or refuses the join with a [`JoinNack`]. While the range is
+/// locked, no other peer can join in front of the successor.
+///
+/// If the connection broke during the transfer, the joining peer sends the
+/// message again with the key of the last value it received in
+/// `resume_after` and the successor only transfers the remaining values.
+///
+/// [`JoinAck`]: struct.JoinAck.html
+/// [`JoinNack`]: struct.JoinNack.html
+#[derive(Debug, PartialEq)]
+pub struct JoinLock {
+    pub socket_addr: SocketAddr,
+    pub resume_after: Option<TransferCursor>,
+}
+
+/// Position in a transfer of values, the key of the last value received
+///
+/// Values are transferred in the order of their keys such that a transfer
+/// which broke off can continue after the cursor.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TransferCursor {
+    pub replication_index: u8,
+    pub raw_key: [u8; 32],
+}
+
+/// Reply to a [`JoinLock`] message after the range has been locked.
+///
+/// The message contains the address of the current predecessor which becomes
+/// the predecessor of the joining peer as well as the number of values which
+/// are transferred afterwards using [`StorageBulkPut`] or [`StoragePut`]
+/// messages.
+///
+/// [`JoinLock`]: struct.JoinLock.html
+/// [`StorageBulkPut`]: struct.StorageBulkPut.html
+/// [`StoragePut`]: struct.StoragePut.html
+#[derive(Debug, PartialEq)]
+pub struct JoinAck {
+    pub socket_addr: SocketAddr,
+    pub records: u32,
+}
+
+/// Reply to a [`JoinLock`] or [`JoinPublish`] message if the join cannot
+/// proceed, for example because another peer is joining at the same time.
+///
+/// The joining peer should retry the join with the peer at the given address.
+///
+/// If the identifier of the joining peer is the identifier of another peer
+/// already, e.g. because both share an ip address, the join is refused for
+/// good. The message is marked as `collision` and names the other peer.
+///
+/// [`JoinLock`]: struct.JoinLock.html
+/// [`JoinPublish`]: struct.JoinPublish.html
+#[derive(Debug, PartialEq)]
+pub struct JoinNack {
+    pub socket_addr: SocketAddr,
+    pub collision: bool,
+}
+
+/// After all values have been transferred, the joining peer sends this message
+/// to make the successor apply the join and release its lock.
+///
+/// The successor confirms with a [`PredecessorReply`] containing its new
+/// predecessor.
+///
+/// [`PredecessorReply`]: struct.PredecessorReply.html
+#[derive(Debug, PartialEq)]
+pub struct JoinPublish {
+    pub socket_addr: SocketAddr,
+}
+
+/// A peer leaving the network sends this message to its successor and to its
+/// predecessor such that they can bypass it.
+///
+/// The successor takes over the range of the leaving peer. It receives the
+/// `records` stored by the leaving peer in [`StorageBulkPut`] or
+/// [`StoragePut`] messages on the same connection and adopts
+/// `predecessor_addr` as its new predecessor. The predecessor adopts
+/// `successor_addr` as its new successor.
+///
+/// Both reply with a [`PredecessorReply`] containing their predecessor.
+///
+/// If an earlier transfer broke off, `resume` is set and the successor first
+/// replies with a [`TransferAck`] containing the key of the last value it
+/// received. Only the values following that key are sent, and `records`
+/// still counts all values.
+///
+/// [`StorageBulkPut`]: struct.StorageBulkPut.html
+/// [`StoragePut`]: struct.StoragePut.html
+/// [`PredecessorReply`]: struct.PredecessorReply.html
+/// [`TransferAck`]: struct.TransferAck.html
+#[derive(Debug, PartialEq)]
+pub struct PeerLeave {
+    pub socket_addr: SocketAddr,
+    pub predecessor_addr: SocketAddr,
+    pub successor_addr: SocketAddr,
+    pub records: u32,
+    pub resume: bool,
+}
+
+/// Reply to a [`PeerLeave`] message which resumes a transfer.
+///
+/// The cursor is the key of the last value received before the transfer
+/// broke off or `None` if the transfer has to start over.
+///
+/// [`PeerLeave`]: struct.PeerLeave.html
+#[derive(Debug, PartialEq)]
+pub struct TransferAck {
+    pub resume_after: Option<TransferCursor>,
+}
+
+/// This message stores several values at once and replaces a series of
+/// [`StoragePut`] messages when many values are handed over, for example when
+/// a peer joins or leaves or when hints are delivered.
+///
+/// Within a transfer of values, each value counts separately towards the
+/// announced number of records. A peer receiving this message as a request
+/// replies with a [`StorageBulkPutReply`].
+///
+/// Use [`pack`] to split values into messages which fit into
+/// [`MAX_MESSAGE_SIZE`].
+///
+/// [`StoragePut`]: struct.StoragePut.html
+/// [`StorageBulkPutReply`]: struct.StorageBulkPutReply.html
+/// [`pack`]: #method.pack
+/// [`MAX_MESSAGE_SIZE`]: ../codec/constant.MAX_MESSAGE_SIZE.html
+#[derive(Debug, PartialEq)]
+pub struct StorageBulkPut {
+    pub values: Vec<StoragePut>,
+}
+
+/// Reply to a [`StorageBulkPut`] message.
+///
+/// It contains the keys of all values which the peer did not store because
+/// it is not responsible for them, their range is locked or the peer is
+/// read-only. Values which exist already are not rejected.
+///
+/// [`StorageBulkPut`]: struct.StorageBulkPut.html
+#[derive(Debug, PartialEq)]
+pub struct StorageBulkPutReply {
+    pub rejected: Vec<TransferCursor>,
+}
+
+/// This message asks a peer for its load to compare it with the rest of the
+/// ring. The peer replies with a [`PeerLoadReply`].
+///
+/// [`PeerLoadReply`]: struct.PeerLoadReply.html
+#[derive(Debug, PartialEq)]
+pub struct PeerLoad;
+
+/// Reply to a [`PeerLoad`] message with the load of the peer.
+///
+/// [`PeerLoad`]: struct.PeerLoad.html
+#[derive(Debug, PartialEq)]
+pub struct PeerLoadReply {
+    pub load: Load,
+}
+
+/// A peer responsible for a frequently requested key pushes a copy of its
+/// value with this message to a peer which requested it often.
+///
+/// The receiving peer keeps the copy for the remaining `ttl` in milliseconds
+/// and answers with a [`StoragePutSuccess`] message, or with a
+/// [`StorageFailure`] if it does not cache values.
+///
+/// [`StoragePutSuccess`]: struct.StoragePutSuccess.html
+/// [`StorageFailure`]: struct.StorageFailure.html
+#[derive(Debug, PartialEq)]
+pub struct StorageCachePush {
+    pub replication_index: u8,
+    pub raw_key: [u8; 32],
+    pub version: u64,
+    pub ttl: u64,
+    pub stored: u64,
+    pub value: Bytes,
+}
+
+/// This message wraps another p2p message together with a `request_id`.
+///
+/// A peer receiving a request in this envelope answers with its reply wrapped
+/// in the same way and with the same `request_id`. This allows several
+/// outstanding requests to share one connection while each reply can still
+/// be matched to its caller.
+///
+/// The wrapped message is transmitted including its own header. Envelopes
+/// cannot be nested.
+#[derive(Debug, PartialEq)]
+pub struct Correlated {
+    pub request_id: u32,
+    pub message: Box<Message>,
+}
+
+/// Reads the optional flags byte of a message.
+fn read_flags(reader: &mut dyn Read) -> io::Result<u8> {
+    let mut flags = [0; 1];
+
+    // the flags are optional for compatibility with older peers
+    if reader.read(&mut flags)? == 0 {
+        return Ok(0);
+    }
+
+    Ok(flags[0])
+}
+
 impl MessagePayload for StorageGet {
-    fn parse(reader: &mut Read) -> io::Result<Self> {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
         let replication_index = reader.read_u8()?;
+        let flags = reader.read_u8()?;
 
         // Skip reserved fields
         reader.read_u8()?;
         reader.read_u8()?;
-        reader.read_u8()?;
 
         let mut raw_key = [0; 32];
         reader.read_exact(&mut raw_key)?;
 
+        let if_none_match = if flags & codec::VERSION_FLAG != 0 {
+            Some(reader.read_u64::<NetworkEndian>()?)
+        } else {
+            None
+        };
+
+        let requester = if flags & REQUESTER_FLAG != 0 {
+            Some(read_socket_addr(reader)?)
+        } else {
+            None
+        };
+
+        let budget = read_optional_u32(reader)?;
+
         Ok(StorageGet {
             replication_index,
             raw_key,
+            budget,
+            if_none_match,
+            requester,
         })
     }
 
-    fn write_to(&self, writer: &mut Write) -> io::Result<()> {
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
         writer.write_u8(self.replication_index)?;
 
+        let mut flags = 0;
+
+        if self.if_none_match.is_some() {
+            flags |= codec::VERSION_FLAG;
+        }
+
+        if self.requester.is_some() {
+            flags |= REQUESTER_FLAG;
+        }
+
+        writer.write_u8(flags)?;
+
         // Fill reserved fields
         writer.write_u8(0)?;
         writer.write_u8(0)?;
-        writer.write_u8(0)?;
 
         writer.write_all(&self.raw_key)?;
 
+        if let Some(version) = self.if_none_match {
+            writer.write_u64::<NetworkEndian>(version)?;
+        }
+
+        if let Some(requester) = self.requester {
+            write_socket_addr(writer, requester)?;
+        }
+
+        write_optional_u32(writer, self.budget)?;
+
         Ok(())
     }
 }
 
 impl MessagePayload for StoragePut {
-    fn parse(reader: &mut Read) -> io::Result<Self> {
-        let ttl = reader.read_u16::<NetworkEndian>()?;
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        Self::parse_bytes(codec::read_payload(reader)?)
+    }
+
+    fn parse_bytes(payload: Bytes) -> io::Result<Self> {
+        let mut reader = Cursor::new(&payload[..]);
+
+        let ttl = reader.read_u64::<NetworkEndian>()?;
         let replication_index = reader.read_u8()?;
+        let replicas = reader.read_u8()?;
+        let namespace_len = reader.read_u8()?;
 
         // Skip reserved field
         reader.read_u8()?;
@@ -146,25 +521,31 @@ impl MessagePayload for StoragePut {
         let mut raw_key = [0; 32];
         reader.read_exact(&mut raw_key)?;
 
-        let mut value = Vec::new();
-        reader.read_to_end(&mut value)?;
+        let version = reader.read_u64::<NetworkEndian>()?;
+        let value = codec::read_remaining(&payload, &mut reader);
 
         Ok(StoragePut {
             ttl,
             replication_index,
+            replicas,
+            namespace_len,
             raw_key,
+            version,
             value,
         })
     }
 
-    fn write_to(&self, writer: &mut Write) -> io::Result<()> {
-        writer.write_u16::<NetworkEndian>(self.ttl)?;
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u64::<NetworkEndian>(self.ttl)?;
         writer.write_u8(self.replication_index)?;
+        writer.write_u8(self.replicas)?;
+        writer.write_u8(self.namespace_len)?;
 
         // Fill reserved field
         writer.write_u8(0)?;
 
         writer.write_all(&self.raw_key)?;
+        writer.write_u64::<NetworkEndian>(self.version)?;
         writer.write_all(&self.value)?;
 
         Ok(())
@@ -172,18 +553,35 @@ impl MessagePayload for StoragePut {
 }
 
 impl MessagePayload for StorageGetSuccess {
-    fn parse(reader: &mut Read) -> io::Result<Self> {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        Self::parse_bytes(codec::read_payload(reader)?)
+    }
+
+    fn parse_bytes(payload: Bytes) -> io::Result<Self> {
+        let mut reader = Cursor::new(&payload[..]);
+
         let mut raw_key = [0; 32];
         reader.read_exact(&mut raw_key)?;
 
-        let mut value = Vec::new();
-        reader.read_to_end(&mut value)?;
+        let version = reader.read_u64::<NetworkEndian>()?;
+        let ttl = reader.read_u64::<NetworkEndian>()?;
+        let stored = reader.read_u64::<NetworkEndian>()?;
+        let value = codec::read_remaining(&payload, &mut reader);
 
-        Ok(StorageGetSuccess { raw_key, value })
+        Ok(StorageGetSuccess {
+            raw_key,
+            version,
+            ttl,
+            stored,
+            value,
+        })
     }
 
-    fn write_to(&self, writer: &mut Write) -> io::Result<()> {
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
         writer.write_all(&self.raw_key)?;
+        writer.write_u64::<NetworkEndian>(self.version)?;
+        writer.write_u64::<NetworkEndian>(self.ttl)?;
+        writer.write_u64::<NetworkEndian>(self.stored)?;
         writer.write_all(&self.value)?;
 
         Ok(())
@@ -191,14 +589,61 @@ impl MessagePayload for StorageGetSuccess {
 }
 
 impl MessagePayload for StoragePutSuccess {
-    fn parse(reader: &mut Read) -> io::Result<Self> {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
         let mut raw_key = [0; 32];
         reader.read_exact(&mut raw_key)?;
 
         Ok(StoragePutSuccess { raw_key })
     }
 
-    fn write_to(&self, writer: &mut Write) -> io::Result<()> {
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&self.raw_key)?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for StorageDelete {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let replication_index = reader.read_u8()?;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u8()?;
+        reader.read_u8()?;
+
+        let mut raw_key = [0; 32];
+        reader.read_exact(&mut raw_key)?;
+
+        Ok(StorageDelete {
+            replication_index,
+            raw_key,
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u8(self.replication_index)?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        writer.write_all(&self.raw_key)?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for StorageDeleteSuccess {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let mut raw_key = [0; 32];
+        reader.read_exact(&mut raw_key)?;
+
+        Ok(StorageDeleteSuccess { raw_key })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
         writer.write_all(&self.raw_key)?;
 
         Ok(())
@@ -206,312 +651,1153 @@ impl MessagePayload for StoragePutSuccess {
 }
 
 impl MessagePayload for StorageFailure {
-    fn parse(reader: &mut Read) -> io::Result<Self> {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
         let mut raw_key = [0; 32];
         reader.read_exact(&mut raw_key)?;
 
-        Ok(StorageFailure { raw_key })
+        // the reason is optional for compatibility with older peers
+        let mut reason = [0; 1];
+
+        let reason = if reader.read(&mut reason)? == 0 {
+            None
+        } else {
+            let reason = match reason[0] {
+                codec::FAILURE_NOT_FOUND => FailureReason::NotFound,
+                codec::FAILURE_EXISTS => FailureReason::Exists,
+                codec::FAILURE_LOCKED => FailureReason::Locked,
+                codec::FAILURE_READ_ONLY => FailureReason::ReadOnly,
+                codec::FAILURE_DENIED => FailureReason::Denied,
+                codec::FAILURE_NOT_MODIFIED => FailureReason::NotModified,
+                codec::FAILURE_POLICY_VIOLATED => FailureReason::PolicyViolated,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Invalid failure reason",
+                    ))
+                }
+            };
+
+            Some(reason)
+        };
+
+        Ok(StorageFailure { raw_key, reason })
     }
 
-    fn write_to(&self, writer: &mut Write) -> io::Result<()> {
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
         writer.write_all(&self.raw_key)?;
 
+        if let Some(reason) = self.reason {
+            let reason = match reason {
+                FailureReason::NotFound => codec::FAILURE_NOT_FOUND,
+                FailureReason::Exists => codec::FAILURE_EXISTS,
+                FailureReason::Locked => codec::FAILURE_LOCKED,
+                FailureReason::ReadOnly => codec::FAILURE_READ_ONLY,
+                FailureReason::Denied => codec::FAILURE_DENIED,
+                FailureReason::NotModified => codec::FAILURE_NOT_MODIFIED,
+                FailureReason::PolicyViolated => codec::FAILURE_POLICY_VIOLATED,
+            };
+
+            writer.write_u8(reason)?;
+        }
+
         Ok(())
     }
 }
 
 impl MessagePayload for PeerFind {
-    fn parse(reader: &mut Read) -> io::Result<Self> {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
         let mut id_arr = [0; 32];
         reader.read_exact(&mut id_arr)?;
         let identifier = Identifier::new(&id_arr);
+        let flags = read_flags(reader)?;
+
+        // the budget precedes the trace which extends to the end
+        let budget = if flags & BUDGET_FLAG != 0 {
+            Some(reader.read_u32::<NetworkEndian>()?)
+        } else {
+            None
+        };
+
+        let trace = if flags & TRACE_FLAG != 0 {
+            Some(read_socket_addrs(reader)?)
+        } else {
+            None
+        };
 
-        Ok(PeerFind { identifier })
+        Ok(PeerFind {
+            identifier,
+            trace,
+            budget,
+        })
     }
 
-    fn write_to(&self, writer: &mut Write) -> io::Result<()> {
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
         writer.write_all(&self.identifier.as_bytes())?;
 
+        let mut flags = 0;
+
+        if self.trace.is_some() {
+            flags |= TRACE_FLAG;
+        }
+
+        if self.budget.is_some() {
+            flags |= BUDGET_FLAG;
+        }
+
+        if flags != 0 {
+            writer.write_u8(flags)?;
+        }
+
+        write_optional_u32(writer, self.budget)?;
+
+        if let Some(ref trace) = self.trace {
+            write_socket_addrs(writer, trace)?;
+        }
+
         Ok(())
     }
 }
 
 impl MessagePayload for PeerFound {
-    fn parse(reader: &mut Read) -> io::Result<Self> {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
         let mut id_arr = [0; 32];
         reader.read_exact(&mut id_arr)?;
         let identifier = Identifier::new(&id_arr);
+        let socket_addr = read_socket_addr(reader)?;
+        let flags = read_flags(reader)?;
 
-        let mut ip_arr = [0; 16];
-        reader.read_exact(&mut ip_arr)?;
-
-        let ipv6 = Ipv6Addr::from(ip_arr);
-
-        let ip_address = match ipv6.to_ipv4() {
-            Some(ipv4) => IpAddr::V4(ipv4),
-            None => IpAddr::V6(ipv6),
+        let trace = if flags & TRACE_FLAG != 0 {
+            Some(read_socket_addrs(reader)?)
+        } else {
+            None
         };
 
-        let port = reader.read_u16::<NetworkEndian>()?;
-
-        let socket_addr = SocketAddr::new(ip_address, port);
-
         Ok(PeerFound {
             identifier,
             socket_addr,
+            authoritative: flags & AUTHORITATIVE_FLAG != 0,
+            trace,
         })
     }
 
-    fn write_to(&self, writer: &mut Write) -> io::Result<()> {
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
         writer.write_all(&self.identifier.as_bytes())?;
+        write_socket_addr(writer, self.socket_addr)?;
 
-        let ip_address = match self.socket_addr.ip() {
-            IpAddr::V4(ipv4) => ipv4.to_ipv6_mapped(),
-            IpAddr::V6(ipv6) => ipv6,
-        };
+        let mut flags = 0;
 
-        writer.write_all(&ip_address.octets())?;
-        writer.write_u16::<NetworkEndian>(self.socket_addr.port())?;
+        if self.trace.is_some() {
+            flags |= TRACE_FLAG;
+        }
+
+        if self.authoritative {
+            flags |= AUTHORITATIVE_FLAG;
+        }
+
+        if flags != 0 {
+            writer.write_u8(flags)?;
+        }
+
+        if let Some(trace) = &self.trace {
+            write_socket_addrs(writer, trace)?;
+        }
 
         Ok(())
     }
 }
 
 impl MessagePayload for PredecessorNotify {
-    fn parse(reader: &mut Read) -> io::Result<Self> {
-        let mut ip_arr = [0; 16];
-        reader.read_exact(&mut ip_arr)?;
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let socket_addr = read_socket_addr(reader)?;
 
-        let ipv6 = Ipv6Addr::from(ip_arr);
+        Ok(PredecessorNotify { socket_addr })
+    }
 
-        let ip_address = match ipv6.to_ipv4() {
-            Some(ipv4) => IpAddr::V4(ipv4),
-            None => IpAddr::V6(ipv6),
-        };
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_socket_addr(writer, self.socket_addr)?;
 
-        let port = reader.read_u16::<NetworkEndian>()?;
+        Ok(())
+    }
+}
 
-        let socket_addr = SocketAddr::new(ip_address, port);
+impl MessagePayload for PredecessorReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let socket_addr = read_socket_addr(reader)?;
 
-        Ok(PredecessorNotify { socket_addr })
+        Ok(PredecessorReply { socket_addr })
     }
 
-    fn write_to(&self, writer: &mut Write) -> io::Result<()> {
-        let ip_address = match self.socket_addr.ip() {
-            IpAddr::V4(ipv4) => ipv4.to_ipv6_mapped(),
-            IpAddr::V6(ipv6) => ipv6,
-        };
-
-        writer.write_all(&ip_address.octets())?;
-        writer.write_u16::<NetworkEndian>(self.socket_addr.port())?;
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_socket_addr(writer, self.socket_addr)?;
 
         Ok(())
     }
 }
 
-impl MessagePayload for PredecessorReply {
-    fn parse(reader: &mut Read) -> io::Result<Self> {
-        let mut ip_arr = [0; 16];
-        reader.read_exact(&mut ip_arr)?;
+/// Reads a cursor if the reader has not reached its end yet.
+fn read_optional_cursor(reader: &mut dyn Read) -> io::Result<Option<TransferCursor>> {
+    let mut replication_index = [0; 1];
+
+    if reader.read(&mut replication_index)? == 0 {
+        return Ok(None);
+    }
 
-        let ipv6 = Ipv6Addr::from(ip_arr);
+    // Skip reserved fields
+    reader.read_u8()?;
+    reader.read_u8()?;
+    reader.read_u8()?;
 
-        let ip_address = match ipv6.to_ipv4() {
-            Some(ipv4) => IpAddr::V4(ipv4),
-            None => IpAddr::V6(ipv6),
-        };
+    let mut raw_key = [0; 32];
+    reader.read_exact(&mut raw_key)?;
 
-        let port = reader.read_u16::<NetworkEndian>()?;
+    Ok(Some(TransferCursor {
+        replication_index: replication_index[0],
+        raw_key,
+    }))
+}
 
-        let socket_addr = SocketAddr::new(ip_address, port);
+fn write_optional_cursor(writer: &mut dyn Write, cursor: Option<TransferCursor>) -> io::Result<()> {
+    if let Some(cursor) = cursor {
+        writer.write_u8(cursor.replication_index)?;
 
-        Ok(PredecessorReply { socket_addr })
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+
+        writer.write_all(&cursor.raw_key)?;
     }
 
-    fn write_to(&self, writer: &mut Write) -> io::Result<()> {
-        let ip_address = match self.socket_addr.ip() {
-            IpAddr::V4(ipv4) => ipv4.to_ipv6_mapped(),
-            IpAddr::V6(ipv6) => ipv6,
-        };
+    Ok(())
+}
+
+impl MessagePayload for JoinLock {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let socket_addr = read_socket_addr(reader)?;
+        let resume_after = read_optional_cursor(reader)?;
 
-        writer.write_all(&ip_address.octets())?;
-        writer.write_u16::<NetworkEndian>(self.socket_addr.port())?;
+        Ok(JoinLock {
+            socket_addr,
+            resume_after,
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_socket_addr(writer, self.socket_addr)?;
+        write_optional_cursor(writer, self.resume_after)?;
 
         Ok(())
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::super::tests::test_message_payload;
-    use super::*;
+impl MessagePayload for JoinAck {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let socket_addr = read_socket_addr(reader)?;
+        let records = reader.read_u32::<NetworkEndian>()?;
 
-    #[test]
-    fn storage_get() {
-        #[rustfmt::skip]
-        let buf = [
-            // replication index and reserved
-            4, 0, 0, 0,
-            // 32 bytes for key
-            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
-            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
-        ];
+        Ok(JoinAck {
+            socket_addr,
+            records,
+        })
+    }
 
-        let msg = StorageGet {
-            replication_index: 4,
-            raw_key: [3; 32],
-        };
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_socket_addr(writer, self.socket_addr)?;
+        writer.write_u32::<NetworkEndian>(self.records)?;
 
-        test_message_payload(&buf, msg);
+        Ok(())
     }
+}
 
-    #[test]
-    fn storage_put() {
-        #[rustfmt::skip]
-        let buf = [
-            // TTL, replication index and reserved
-            0, 12, 4, 0,
+impl MessagePayload for JoinNack {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let socket_addr = read_socket_addr(reader)?;
+        let collision = read_flags(reader)? & COLLISION_FLAG != 0;
+
+        Ok(JoinNack {
+            socket_addr,
+            collision,
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_socket_addr(writer, self.socket_addr)?;
+
+        if self.collision {
+            writer.write_u8(COLLISION_FLAG)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for JoinPublish {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let socket_addr = read_socket_addr(reader)?;
+
+        Ok(JoinPublish { socket_addr })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_socket_addr(writer, self.socket_addr)?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for PeerLeave {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let socket_addr = read_socket_addr(reader)?;
+        let predecessor_addr = read_socket_addr(reader)?;
+        let successor_addr = read_socket_addr(reader)?;
+        let records = reader.read_u32::<NetworkEndian>()?;
+        let resume = read_flags(reader)? & RESUME_FLAG != 0;
+
+        Ok(PeerLeave {
+            socket_addr,
+            predecessor_addr,
+            successor_addr,
+            records,
+            resume,
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_socket_addr(writer, self.socket_addr)?;
+        write_socket_addr(writer, self.predecessor_addr)?;
+        write_socket_addr(writer, self.successor_addr)?;
+        writer.write_u32::<NetworkEndian>(self.records)?;
+
+        // older peers do not know the flags
+        if self.resume {
+            writer.write_u8(RESUME_FLAG)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for TransferAck {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let resume_after = read_optional_cursor(reader)?;
+
+        Ok(TransferAck { resume_after })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_optional_cursor(writer, self.resume_after)?;
+
+        Ok(())
+    }
+}
+
+impl StorageBulkPut {
+    /// Size of a value within the message besides the value itself
+    const VALUE_OVERHEAD: usize = 54;
+
+    /// Maximum size of the payload such that the message still fits into
+    /// [`MAX_MESSAGE_SIZE`] when wrapped in a [`Correlated`] envelope
+    ///
+    /// [`MAX_MESSAGE_SIZE`]: ../codec/constant.MAX_MESSAGE_SIZE.html
+    /// [`Correlated`]: struct.Correlated.html
+    const MAX_PAYLOAD_SIZE: usize = MAX_MESSAGE_SIZE - 2 * HEADER_SIZE - 4;
+
+    /// Packs the given values into as few messages as possible.
+    ///
+    /// Every message fits into [`MAX_MESSAGE_SIZE`] even if it is sent as a
+    /// correlated request. A value which is too large to be packed is sent in
+    /// a [`StoragePut`] message of its own.
+    ///
+    /// [`MAX_MESSAGE_SIZE`]: ../codec/constant.MAX_MESSAGE_SIZE.html
+    /// [`StoragePut`]: struct.StoragePut.html
+    pub fn pack<I>(values: I) -> Vec<Message>
+    where
+        I: IntoIterator<Item = StoragePut>,
+    {
+        let mut messages = Vec::new();
+        let mut values_size = 0;
+        let mut bulk = Vec::new();
+
+        for value in values {
+            let value_size = Self::VALUE_OVERHEAD + value.value.len();
+
+            // keep the order of the values such that transfers can be resumed
+            if !bulk.is_empty() && 4 + values_size + value_size > Self::MAX_PAYLOAD_SIZE {
+                let values = mem::take(&mut bulk);
+                messages.push(Message::StorageBulkPut(StorageBulkPut { values }));
+                values_size = 0;
+            }
+
+            if 4 + value_size > Self::MAX_PAYLOAD_SIZE {
+                messages.push(Message::StoragePut(value));
+            } else {
+                values_size += value_size;
+                bulk.push(value);
+            }
+        }
+
+        if !bulk.is_empty() {
+            messages.push(Message::StorageBulkPut(StorageBulkPut { values: bulk }));
+        }
+
+        messages
+    }
+}
+
+impl MessagePayload for StorageBulkPut {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        Self::parse_bytes(codec::read_payload(reader)?)
+    }
+
+    fn parse_bytes(payload: Bytes) -> io::Result<Self> {
+        let mut reader = Cursor::new(&payload[..]);
+
+        let count = reader.read_u16::<NetworkEndian>()?;
+
+        // Skip reserved fields
+        reader.read_u16::<NetworkEndian>()?;
+
+        let mut values = Vec::with_capacity(count as usize);
+
+        for _ in 0..count {
+            let ttl = reader.read_u64::<NetworkEndian>()?;
+            let replication_index = reader.read_u8()?;
+            let replicas = reader.read_u8()?;
+            let namespace_len = reader.read_u8()?;
+
+            // Skip reserved field
+            reader.read_u8()?;
+
+            let mut raw_key = [0; 32];
+            reader.read_exact(&mut raw_key)?;
+
+            let version = reader.read_u64::<NetworkEndian>()?;
+            let size = reader.read_u16::<NetworkEndian>()?;
+            let value = codec::read_bytes(&payload, &mut reader, usize::from(size))?;
+
+            values.push(StoragePut {
+                ttl,
+                replication_index,
+                replicas,
+                namespace_len,
+                raw_key,
+                version,
+                value,
+            });
+        }
+
+        Ok(StorageBulkPut { values })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u16::<NetworkEndian>(self.values.len() as u16)?;
+
+        // Fill reserved fields
+        writer.write_u16::<NetworkEndian>(0)?;
+
+        for value in &self.values {
+            writer.write_u64::<NetworkEndian>(value.ttl)?;
+            writer.write_u8(value.replication_index)?;
+            writer.write_u8(value.replicas)?;
+            writer.write_u8(value.namespace_len)?;
+
+            // Fill reserved field
+            writer.write_u8(0)?;
+
+            writer.write_all(&value.raw_key)?;
+            writer.write_u64::<NetworkEndian>(value.version)?;
+            writer.write_u16::<NetworkEndian>(value.value.len() as u16)?;
+            writer.write_all(&value.value)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for StorageBulkPutReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let count = reader.read_u16::<NetworkEndian>()?;
+
+        // Skip reserved fields
+        reader.read_u16::<NetworkEndian>()?;
+
+        let mut rejected = Vec::with_capacity(count as usize);
+
+        for _ in 0..count {
+            let cursor = read_optional_cursor(reader)?
+                .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+
+            rejected.push(cursor);
+        }
+
+        Ok(StorageBulkPutReply { rejected })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u16::<NetworkEndian>(self.rejected.len() as u16)?;
+
+        // Fill reserved fields
+        writer.write_u16::<NetworkEndian>(0)?;
+
+        for cursor in &self.rejected {
+            write_optional_cursor(writer, Some(*cursor))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for PeerLoad {
+    fn parse(_reader: &mut dyn Read) -> io::Result<Self> {
+        Ok(PeerLoad)
+    }
+
+    fn write_to(&self, _writer: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl MessagePayload for PeerLoadReply {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        let load = read_load(reader)?;
+
+        Ok(PeerLoadReply { load })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_load(writer, &self.load)
+    }
+}
+
+impl MessagePayload for StorageCachePush {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        Self::parse_bytes(codec::read_payload(reader)?)
+    }
+
+    fn parse_bytes(payload: Bytes) -> io::Result<Self> {
+        let mut reader = Cursor::new(&payload[..]);
+
+        let replication_index = reader.read_u8()?;
+
+        // Skip reserved fields
+        reader.read_u8()?;
+        reader.read_u16::<NetworkEndian>()?;
+
+        let mut raw_key = [0; 32];
+        reader.read_exact(&mut raw_key)?;
+
+        let version = reader.read_u64::<NetworkEndian>()?;
+        let ttl = reader.read_u64::<NetworkEndian>()?;
+        let stored = reader.read_u64::<NetworkEndian>()?;
+        let value = codec::read_remaining(&payload, &mut reader);
+
+        Ok(StorageCachePush {
+            replication_index,
+            raw_key,
+            version,
+            ttl,
+            stored,
+            value,
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u8(self.replication_index)?;
+
+        // Fill reserved fields
+        writer.write_u8(0)?;
+        writer.write_u16::<NetworkEndian>(0)?;
+
+        writer.write_all(&self.raw_key)?;
+        writer.write_u64::<NetworkEndian>(self.version)?;
+        writer.write_u64::<NetworkEndian>(self.ttl)?;
+        writer.write_u64::<NetworkEndian>(self.stored)?;
+        writer.write_all(&self.value)?;
+
+        Ok(())
+    }
+}
+
+impl MessagePayload for Correlated {
+    fn parse(reader: &mut dyn Read) -> io::Result<Self> {
+        Self::parse_bytes(codec::read_payload(reader)?)
+    }
+
+    fn parse_bytes(payload: Bytes) -> io::Result<Self> {
+        let mut reader = Cursor::new(&payload[..]);
+
+        let request_id = reader.read_u32::<NetworkEndian>()?;
+        let message = Message::from_bytes(codec::read_remaining(&payload, &mut reader))?;
+
+        if let Message::Correlated(_) = message {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Nested correlated message",
+            ));
+        }
+
+        Ok(Correlated {
+            request_id,
+            message: Box::new(message),
+        })
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_correlated(writer, self.request_id, &self.message)
+    }
+}
+
+/// Writes a request ID followed by the given message including its header.
+///
+/// This allows to wrap a borrowed message without building a [`Correlated`]
+/// struct first.
+///
+/// [`Correlated`]: struct.Correlated.html
+pub(super) fn write_correlated(
+    writer: &mut dyn Write,
+    request_id: u32,
+    message: &Message,
+) -> io::Result<()> {
+    writer.write_u32::<NetworkEndian>(request_id)?;
+
+    let mut buffer = Cursor::new(Vec::new());
+    message.write_to(&mut buffer)?;
+    writer.write_all(buffer.get_ref())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::test_message_payload;
+    use super::*;
+
+    #[test]
+    fn storage_get() {
+        #[rustfmt::skip]
+        let buf = [
+            // replication index and reserved
+            4, 0, 0, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        ];
+
+        let msg = StorageGet {
+            replication_index: 4,
+            raw_key: [3; 32],
+            budget: None,
+            if_none_match: None,
+            requester: None,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn storage_get_if_none_match() {
+        #[rustfmt::skip]
+        let buf = [
+            // replication index, flags and reserved
+            4, 1, 0, 0,
             // 32 bytes for key
             3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
             3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // version
+            0, 0, 0, 0, 0, 0, 1, 2,
+            // budget
+            0, 0, 1, 244,
+        ];
+
+        let msg = StorageGet {
+            replication_index: 4,
+            raw_key: [3; 32],
+            budget: Some(500),
+            if_none_match: Some(258),
+            requester: None,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn storage_get_requester() {
+        #[rustfmt::skip]
+        let buf = [
+            // replication index, flags and reserved
+            0, 2, 0, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // requester ip address
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
+            // requester port
+            31, 144,
+        ];
+
+        let msg = StorageGet {
+            replication_index: 0,
+            raw_key: [3; 32],
+            budget: None,
+            if_none_match: None,
+            requester: Some("127.0.0.1:8080".parse().unwrap()),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn storage_delete() {
+        #[rustfmt::skip]
+        let buf = [
+            // replication index and reserved
+            4, 0, 0, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        ];
+
+        let msg = StorageDelete {
+            replication_index: 4,
+            raw_key: [3; 32],
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn storage_delete_success() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        ];
+
+        let msg = StorageDeleteSuccess { raw_key: [3; 32] };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn storage_put() {
+        #[rustfmt::skip]
+        let buf = [
+            // TTL, replication index, replicas, namespace length and reserved
+            0, 0, 0, 0, 0, 0, 0, 12, 4, 5, 2, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // version
+            0, 0, 0, 0, 0, 0, 1, 2,
+            // value
+            1, 2, 3, 4, 5
+        ];
+
+        let msg = StoragePut {
+            ttl: 12,
+            replication_index: 4,
+            replicas: 5,
+            namespace_len: 2,
+            raw_key: [3; 32],
+            version: 258,
+            value: vec![1, 2, 3, 4, 5].into(),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn storage_get_success() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // version
+            0, 0, 0, 0, 0, 0, 1, 2,
+            // TTL
+            0, 0, 0, 0, 0, 0, 0, 12,
+            // stored
+            0, 0, 0, 0, 0, 0, 1, 1,
             // value
             1, 2, 3, 4, 5
         ];
 
-        let msg = StoragePut {
-            ttl: 12,
-            replication_index: 4,
-            raw_key: [3; 32],
-            value: vec![1, 2, 3, 4, 5],
+        let msg = StorageGetSuccess {
+            raw_key: [3; 32],
+            version: 258,
+            ttl: 12,
+            stored: 257,
+            value: vec![1, 2, 3, 4, 5].into(),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn storage_put_success() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        ];
+
+        let msg = StoragePutSuccess { raw_key: [3; 32] };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn storage_failure() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        ];
+
+        let msg = StorageFailure {
+            raw_key: [3; 32],
+            reason: None,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn storage_failure_reason() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // reason
+            4,
+        ];
+
+        let msg = StorageFailure {
+            raw_key: [3; 32],
+            reason: Some(FailureReason::ReadOnly),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn peer_find() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for identifier
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+        ];
+
+        let msg = PeerFind {
+            identifier: Identifier::new(&[5; 32]),
+            trace: None,
+            budget: None,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn peer_find_budget_and_trace() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for identifier
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            // trace and budget flags
+            3,
+            // budget
+            0, 0, 1, 244,
+            // 16 bytes for ip address of first hop
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
+            // port of first hop
+            31, 144,
+        ];
+
+        let msg = PeerFind {
+            identifier: Identifier::new(&[5; 32]),
+            trace: Some(vec!["127.0.0.1:8080".parse().unwrap()]),
+            budget: Some(500),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn peer_find_trace() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for identifier
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            // trace flag
+            1,
+            // 16 bytes for ip address of first hop
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
+            // port of first hop
+            31, 144,
+        ];
+
+        let msg = PeerFind {
+            identifier: Identifier::new(&[5; 32]),
+            trace: Some(vec!["127.0.0.1:8080".parse().unwrap()]),
+            budget: None,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn peer_found_ipv4() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for identifier
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            // 16 bytes for ip address
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
+            // port
+            31, 144,
+        ];
+
+        let msg = PeerFound {
+            identifier: Identifier::new(&[5; 32]),
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            authoritative: false,
+            trace: None,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn peer_found_ipv6() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for identifier
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            // 16 bytes for ip address
+            32, 1, 13, 184, 133, 163, 0, 0, 0, 0, 138, 35, 3, 112, 115, 52,
+            // port
+            31, 144,
+        ];
+
+        let msg = PeerFound {
+            identifier: Identifier::new(&[5; 32]),
+            socket_addr: "[2001:db8:85a3::8a23:370:7334]:8080".parse().unwrap(),
+            authoritative: false,
+            trace: None,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn peer_found_trace() {
+        #[rustfmt::skip]
+        let buf = [
+            // 32 bytes for identifier
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            // 16 bytes for ip address
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
+            // port
+            31, 144,
+            // trace flag
+            1,
+            // 16 bytes for ip address of first hop
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 2,
+            // port of first hop
+            31, 144,
+            // 16 bytes for ip address of second hop
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
+            // port of second hop
+            31, 144,
+        ];
+
+        let msg = PeerFound {
+            identifier: Identifier::new(&[5; 32]),
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            authoritative: false,
+            trace: Some(vec![
+                "127.0.0.2:8080".parse().unwrap(),
+                "127.0.0.1:8080".parse().unwrap(),
+            ]),
         };
 
         test_message_payload(&buf, msg);
     }
 
     #[test]
-    fn storage_get_success() {
+    fn peer_found_authoritative() {
         #[rustfmt::skip]
         let buf = [
-            // 32 bytes for key
-            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
-            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
-            // value
-            1, 2, 3, 4, 5
+            // 32 bytes for identifier
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            // 16 bytes for ip address
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
+            // port
+            31, 144,
+            // authoritative and trace flags
+            3,
+            // 16 bytes for ip address of the only hop
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
+            // port of the only hop
+            31, 144,
         ];
 
-        let msg = StorageGetSuccess {
-            raw_key: [3; 32],
-            value: vec![1, 2, 3, 4, 5],
+        let msg = PeerFound {
+            identifier: Identifier::new(&[5; 32]),
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            authoritative: true,
+            trace: Some(vec!["127.0.0.1:8080".parse().unwrap()]),
         };
 
         test_message_payload(&buf, msg);
     }
 
     #[test]
-    fn storage_put_success() {
+    fn predecessor_notify_ipv4() {
         #[rustfmt::skip]
         let buf = [
-            // 32 bytes for key
-            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
-            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // 16 bytes for ip address
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
+            // port
+            31, 144,
         ];
 
-        let msg = StoragePutSuccess { raw_key: [3; 32] };
+        let msg = PredecessorNotify {
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+        };
 
         test_message_payload(&buf, msg);
     }
 
     #[test]
-    fn storage_failure() {
+    fn predecessor_notify_ipv6() {
         #[rustfmt::skip]
         let buf = [
-            // 32 bytes for key
-            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
-            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // 16 bytes for ip address
+            32, 1, 13, 184, 133, 163, 0, 0, 0, 0, 138, 35, 3, 112, 115, 52,
+            // port
+            31, 144,
         ];
 
-        let msg = StorageFailure { raw_key: [3; 32] };
+        let msg = PredecessorNotify {
+            socket_addr: "[2001:db8:85a3::8a23:370:7334]:8080".parse().unwrap(),
+        };
 
         test_message_payload(&buf, msg);
     }
 
     #[test]
-    fn peer_find() {
+    fn predecessor_reply_ipv4() {
         #[rustfmt::skip]
         let buf = [
-            // 32 bytes for identifier
-            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
-            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+            // 16 bytes for ip address
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
+            // port
+            31, 144,
         ];
 
-        let msg = PeerFind {
-            identifier: Identifier::new(&[5; 32]),
+        let msg = PredecessorReply {
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
         };
 
         test_message_payload(&buf, msg);
     }
 
     #[test]
-    fn peer_found_ipv4() {
+    fn predecessor_reply_ipv6() {
+        #[rustfmt::skip]
+        let buf = [
+            // 16 bytes for ip address
+            32, 1, 13, 184, 133, 163, 0, 0, 0, 0, 138, 35, 3, 112, 115, 52,
+            // port
+            31, 144,
+        ];
+
+        let msg = PredecessorReply {
+            socket_addr: "[2001:db8:85a3::8a23:370:7334]:8080".parse().unwrap(),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn join_lock() {
         #[rustfmt::skip]
         let buf = [
-            // 32 bytes for identifier
-            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
-            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
             // 16 bytes for ip address
             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
             // port
             31, 144,
         ];
 
-        let msg = PeerFound {
-            identifier: Identifier::new(&[5; 32]),
+        let msg = JoinLock {
             socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            resume_after: None,
         };
 
         test_message_payload(&buf, msg);
     }
 
     #[test]
-    fn peer_found_ipv6() {
+    fn join_lock_resumed() {
         #[rustfmt::skip]
         let buf = [
-            // 32 bytes for identifier
-            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
-            5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
             // 16 bytes for ip address
-            32, 1, 13, 184, 133, 163, 0, 0, 0, 0, 138, 35, 3, 112, 115, 52,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
             // port
             31, 144,
+            // replication index and reserved
+            2, 0, 0, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
         ];
 
-        let msg = PeerFound {
-            identifier: Identifier::new(&[5; 32]),
-            socket_addr: "[2001:db8:85a3::8a23:370:7334]:8080".parse().unwrap(),
+        let msg = JoinLock {
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            resume_after: Some(TransferCursor {
+                replication_index: 2,
+                raw_key: [3; 32],
+            }),
         };
 
         test_message_payload(&buf, msg);
     }
 
     #[test]
-    fn predecessor_notify_ipv4() {
+    fn join_ack() {
         #[rustfmt::skip]
         let buf = [
             // 16 bytes for ip address
             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
             // port
             31, 144,
+            // number of records
+            0, 0, 1, 2,
         ];
 
-        let msg = PredecessorNotify {
+        let msg = JoinAck {
             socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            records: 258,
         };
 
         test_message_payload(&buf, msg);
     }
 
     #[test]
-    fn predecessor_notify_ipv6() {
+    fn join_nack() {
         #[rustfmt::skip]
         let buf = [
             // 16 bytes for ip address
@@ -520,44 +1806,374 @@ mod tests {
             31, 144,
         ];
 
-        let msg = PredecessorNotify {
+        let msg = JoinNack {
             socket_addr: "[2001:db8:85a3::8a23:370:7334]:8080".parse().unwrap(),
+            collision: false,
         };
 
         test_message_payload(&buf, msg);
     }
 
     #[test]
-    fn predecessor_reply_ipv4() {
+    fn join_nack_collision() {
         #[rustfmt::skip]
         let buf = [
             // 16 bytes for ip address
             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
             // port
             31, 144,
+            // collision flag
+            1,
         ];
 
-        let msg = PredecessorReply {
+        let msg = JoinNack {
             socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            collision: true,
         };
 
         test_message_payload(&buf, msg);
     }
 
     #[test]
-    fn predecessor_reply_ipv6() {
+    fn join_publish() {
         #[rustfmt::skip]
         let buf = [
             // 16 bytes for ip address
-            32, 1, 13, 184, 133, 163, 0, 0, 0, 0, 138, 35, 3, 112, 115, 52,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
             // port
             31, 144,
         ];
 
-        let msg = PredecessorReply {
-            socket_addr: "[2001:db8:85a3::8a23:370:7334]:8080".parse().unwrap(),
+        let msg = JoinPublish {
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn peer_leave() {
+        #[rustfmt::skip]
+        let buf = [
+            // leaving peer
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1, 31, 144,
+            // predecessor
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 2, 31, 144,
+            // successor
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 3, 31, 144,
+            // number of records
+            0, 0, 1, 2,
+        ];
+
+        let msg = PeerLeave {
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            predecessor_addr: "127.0.0.2:8080".parse().unwrap(),
+            successor_addr: "127.0.0.3:8080".parse().unwrap(),
+            records: 258,
+            resume: false,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn peer_leave_resumed() {
+        #[rustfmt::skip]
+        let buf = [
+            // leaving peer
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1, 31, 144,
+            // predecessor
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 2, 31, 144,
+            // successor
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 3, 31, 144,
+            // number of records
+            0, 0, 1, 2,
+            // flags
+            1,
+        ];
+
+        let msg = PeerLeave {
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            predecessor_addr: "127.0.0.2:8080".parse().unwrap(),
+            successor_addr: "127.0.0.3:8080".parse().unwrap(),
+            records: 258,
+            resume: true,
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn transfer_ack() {
+        test_message_payload(&[], TransferAck { resume_after: None });
+
+        #[rustfmt::skip]
+        let buf = [
+            // replication index and reserved
+            1, 0, 0, 0,
+            // 32 bytes for key
+            4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+            4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+        ];
+
+        let msg = TransferAck {
+            resume_after: Some(TransferCursor {
+                replication_index: 1,
+                raw_key: [4; 32],
+            }),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn storage_bulk_put() {
+        #[rustfmt::skip]
+        let buf = [
+            // number of values and reserved
+            0, 2, 0, 0,
+            // TTL, replication index, replicas, namespace length and reserved
+            0, 0, 0, 0, 0, 0, 0, 12, 1, 2, 0, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // version
+            0, 0, 0, 0, 0, 0, 0, 7,
+            // size and value
+            0, 3, 1, 2, 3,
+            // TTL, replication index, replicas, namespace length and reserved
+            0, 0, 0, 0, 0, 0, 0, 13, 2, 0, 0, 0,
+            // 32 bytes for key
+            4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+            4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+            // version
+            0, 0, 0, 0, 0, 0, 0, 8,
+            // size and empty value
+            0, 0,
+        ];
+
+        let msg = StorageBulkPut {
+            values: vec![
+                StoragePut {
+                    ttl: 12,
+                    replication_index: 1,
+                    replicas: 2,
+                    namespace_len: 0,
+                    raw_key: [3; 32],
+                    version: 7,
+                    value: vec![1, 2, 3].into(),
+                },
+                StoragePut {
+                    ttl: 13,
+                    replication_index: 2,
+                    replicas: 0,
+                    namespace_len: 0,
+                    raw_key: [4; 32],
+                    version: 8,
+                    value: vec![].into(),
+                },
+            ],
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn storage_bulk_put_truncated() {
+        #[rustfmt::skip]
+        let buf = [
+            // number of values and reserved
+            0, 2, 0, 0,
+            // TTL, replication index, replicas, namespace length and reserved
+            0, 0, 0, 0, 0, 0, 0, 12, 1, 2, 0, 0,
+        ];
+
+        let err = StorageBulkPut::parse(&mut Cursor::new(&buf[..]))
+            .err()
+            .unwrap();
+
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn storage_bulk_put_pack() {
+        let storage_put = |value_size| StoragePut {
+            ttl: 12,
+            replication_index: 0,
+            replicas: 1,
+            namespace_len: 0,
+            raw_key: [3; 32],
+            version: 1,
+            value: vec![0; value_size].into(),
+        };
+
+        assert!(StorageBulkPut::pack(Vec::new()).is_empty());
+
+        let values = vec![
+            storage_put(30000),
+            storage_put(30000),
+            storage_put(10000),
+            storage_put(63934),
+            storage_put(10),
+        ];
+        let messages = StorageBulkPut::pack(values);
+
+        let kinds: Vec<&str> = messages.iter().map(Message::name).collect();
+        assert_eq!(
+            vec![
+                "STORAGE BULK PUT",
+                "STORAGE BULK PUT",
+                "STORAGE PUT",
+                "STORAGE BULK PUT"
+            ],
+            kinds
+        );
+
+        for msg in &messages {
+            let mut buffer = [0; MAX_MESSAGE_SIZE];
+            let size = msg
+                .write_correlated_to(1, Cursor::new(&mut buffer[..]))
+                .unwrap();
+
+            assert!(size <= MAX_MESSAGE_SIZE);
+        }
+
+        let values: usize = messages
+            .into_iter()
+            .map(|msg| msg.into_values().unwrap().len())
+            .sum();
+        assert_eq!(5, values);
+    }
+
+    #[test]
+    fn storage_bulk_put_reply() {
+        #[rustfmt::skip]
+        let buf = [
+            // number of keys and reserved
+            0, 1, 0, 0,
+            // replication index and reserved
+            2, 0, 0, 0,
+            // 32 bytes for key
+            4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+            4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+        ];
+
+        let msg = StorageBulkPutReply {
+            rejected: vec![TransferCursor {
+                replication_index: 2,
+                raw_key: [4; 32],
+            }],
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn peer_load() {
+        test_message_payload(&[], PeerLoad);
+    }
+
+    #[test]
+    fn peer_load_reply() {
+        #[rustfmt::skip]
+        let buf = [
+            // share
+            64, 0, 0, 0, 0, 0, 0, 0,
+            // bytes
+            0, 0, 0, 0, 0, 0, 16, 0,
+            // requests
+            0, 0, 0, 0, 0, 0, 0, 100,
+            // records
+            0, 0, 0, 40,
+        ];
+
+        let msg = PeerLoadReply {
+            load: Load {
+                share: 1 << 62,
+                records: 40,
+                bytes: 4096,
+                requests: 100,
+            },
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn storage_cache_push() {
+        #[rustfmt::skip]
+        let buf = [
+            // replication index and reserved
+            1, 0, 0, 0,
+            // 32 bytes for key
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+            // version
+            0, 0, 0, 0, 0, 0, 0, 7,
+            // ttl
+            0, 0, 0, 0, 0, 0, 19, 136,
+            // stored
+            0, 0, 0, 0, 0, 0, 1, 0,
+            // value
+            1, 2, 3, 4, 5,
+        ];
+
+        let msg = StorageCachePush {
+            replication_index: 1,
+            raw_key: [3; 32],
+            version: 7,
+            ttl: 5000,
+            stored: 256,
+            value: vec![1, 2, 3, 4, 5].into(),
+        };
+
+        test_message_payload(&buf, msg);
+    }
+
+    #[test]
+    fn correlated() {
+        #[rustfmt::skip]
+        let buf = [
+            // request id
+            0, 0, 1, 2,
+            // header of wrapped message
+            0, 22, 4, 33,
+            // 16 bytes for ip address
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
+            // port
+            31, 144,
+        ];
+
+        let msg = Correlated {
+            request_id: 258,
+            message: Box::new(Message::JoinPublish(JoinPublish {
+                socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            })),
         };
 
         test_message_payload(&buf, msg);
     }
+
+    #[test]
+    fn correlated_nested() {
+        #[rustfmt::skip]
+        let buf = [
+            // request id
+            0, 0, 1, 2,
+            // header of wrapped message
+            0, 30, 4, 34,
+            // request id of nested message
+            0, 0, 1, 3,
+            // header of nested message
+            0, 22, 4, 33,
+            // 16 bytes for ip address
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1,
+            // port
+            31, 144,
+        ];
+
+        let err = Correlated::parse(&mut Cursor::new(&buf[..])).err().unwrap();
+
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
 }
@@ -0,0 +1,891 @@
+//! Telemetry about the operations performed by this peer.
+//!
+//! The [`Metrics`] struct is shared between all components of a peer which
+//! record their operations in it. Currently, the number of hops and the total
+//! time of every lookup are recorded in a [`Histogram`] which keeps the most
+//! recent samples to provide rolling aggregates. Cheaper events like sent
+//! and received messages are counted in the atomic counters of [`Stats`].
+//! The bytes exchanged with every remote peer are accounted in
+//! [`Bandwidth`] which also decides whether a peer has to be throttled.
+//! Operations exceeding a configurable threshold are logged by the
+//! [`SlowLog`]. The worker pools of the servers report their utilization in
+//! [`PoolStats`]. Whether lookups of the own identifier of the peer resolve
+//! back to it is kept as a health flag, see [`Metrics::is_ring_consistent`].
+//! How well the finger table routes the lookups started at the peer is
+//! counted per finger in [`FingerStats`].
+//!
+//! [`Bandwidth`]: struct.Bandwidth.html
+//! [`FingerStats`]: struct.FingerStats.html
+//! [`Metrics`]: struct.Metrics.html
+//! [`Histogram`]: struct.Histogram.html
+//! [`Stats`]: struct.Stats.html
+//! [`SlowLog`]: struct.SlowLog.html
+//! [`PoolStats`]: struct.PoolStats.html
+//! [`Metrics::is_ring_consistent`]: struct.Metrics.html#method.is_ring_consistent
+
+use crate::message::Message;
+use crate::sync::MutexExt;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of recent samples kept by a histogram
+const HISTOGRAM_WINDOW: usize = 1024;
+
+/// A rolling histogram over the most recent samples
+///
+/// # Examples
+///
+/// ```
+/// # use chord::metrics::Histogram;
+/// #
+/// let mut histogram = Histogram::default();
+///
+/// histogram.record(1);
+/// histogram.record(3);
+///
+/// assert_eq!(2, histogram.summary().count);
+/// assert_eq!(3, histogram.summary().max);
+/// ```
+#[derive(Debug)]
+pub struct Histogram {
+    samples: VecDeque<u32>,
+    window: usize,
+}
+
+impl Histogram {
+    /// Creates a new histogram keeping the last `window` samples.
+    pub fn new(window: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(window),
+            window,
+        }
+    }
+
+    /// Records a new sample and drops the oldest sample if the window is full.
+    pub fn record(&mut self, value: u32) {
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(value);
+    }
+
+    /// Computes aggregates over all samples currently in the window.
+    pub fn summary(&self) -> Summary {
+        if self.samples.is_empty() {
+            return Summary::default();
+        }
+
+        let mut sorted: Vec<u32> = self.samples.iter().cloned().collect();
+        sorted.sort_unstable();
+
+        let sum: u64 = sorted.iter().map(|&value| u64::from(value)).sum();
+        let percentile = |p: usize| sorted[(sorted.len() - 1) * p / 100];
+
+        Summary {
+            count: sorted.len() as u32,
+            mean: sum as f32 / sorted.len() as f32,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(HISTOGRAM_WINDOW)
+    }
+}
+
+/// Aggregates over the samples of a [`Histogram`]
+///
+/// [`Histogram`]: struct.Histogram.html
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub count: u32,
+    pub mean: f32,
+    pub p50: u32,
+    pub p90: u32,
+    pub p99: u32,
+    pub max: u32,
+}
+
+/// Aggregated telemetry about lookups
+///
+/// The `hops` and `latency` summaries cover the most recent successful
+/// lookups. The latency is measured in microseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LookupStats {
+    pub lookups: u64,
+    pub failures: u64,
+    pub hops: Summary,
+    pub latency: Summary,
+}
+
+/// Lookups started at a peer which were routed through one of its fingers
+///
+/// A lookup is `direct` if the finger was already responsible for the
+/// identifier and the lookup thus took a single hop. Fingers which rarely
+/// route any lookups or rarely get them to their owner right away do not
+/// pay for their maintenance.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FingerStats {
+    /// Index of the finger in the finger table
+    pub index: usize,
+    pub routed: u64,
+    pub direct: u64,
+}
+
+impl FingerStats {
+    /// Returns the fraction of the routed lookups which were direct.
+    pub fn direct_ratio(&self) -> f64 {
+        if self.routed == 0 {
+            0.0
+        } else {
+            self.direct as f64 / self.routed as f64
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct LookupMetrics {
+    lookups: u64,
+    failures: u64,
+    hops: Histogram,
+    latency: Histogram,
+}
+
+/// Number of messages of a single type sent and received by a peer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MessageCount {
+    pub name: &'static str,
+    pub received: u64,
+    pub sent: u64,
+}
+
+/// A copy of the counters of [`Stats`] at some point in time
+///
+/// Only message types which have been sent or received at least once are
+/// contained in `messages`.
+///
+/// [`Stats`]: struct.Stats.html
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StatsSnapshot {
+    pub messages: Vec<MessageCount>,
+    pub handler_errors: u64,
+    pub timeouts: u64,
+    pub stabilization_rounds: u64,
+    pub stabilization_restarts: u64,
+    pub self_lookups: u64,
+    pub self_lookup_failures: u64,
+    pub storage_gets: u64,
+    pub storage_puts: u64,
+    pub storage_deletes: u64,
+}
+
+impl StatsSnapshot {
+    /// Returns the total number of received messages.
+    pub fn received(&self) -> u64 {
+        self.messages.iter().map(|count| count.received).sum()
+    }
+
+    /// Returns the ratio of handler errors to received messages.
+    pub fn error_rate(&self) -> f64 {
+        match self.received() {
+            0 => 0.0,
+            received => self.handler_errors as f64 / received as f64,
+        }
+    }
+}
+
+/// Counters of the operations performed by a peer
+///
+/// All counters only ever increase and can be updated and read without
+/// locking such that embedders can poll them frequently and forward them to
+/// their own telemetry.
+///
+/// # Examples
+///
+/// ```
+/// # use chord::message::Message;
+/// # use chord::message::api::NodeInfo;
+/// # use chord::metrics::Stats;
+/// #
+/// let stats = Stats::default();
+///
+/// stats.record_received(&Message::NodeInfo(NodeInfo));
+/// stats.record_handler_error();
+///
+/// let snapshot = stats.snapshot();
+///
+/// assert_eq!(1, snapshot.received());
+/// assert_eq!(1.0, snapshot.error_rate());
+/// ```
+#[derive(Debug)]
+pub struct Stats {
+    received: [AtomicU64; Message::KINDS],
+    sent: [AtomicU64; Message::KINDS],
+    handler_errors: AtomicU64,
+    timeouts: AtomicU64,
+    stabilization_rounds: AtomicU64,
+    stabilization_restarts: AtomicU64,
+    self_lookups: AtomicU64,
+    self_lookup_failures: AtomicU64,
+    storage_gets: AtomicU64,
+    storage_puts: AtomicU64,
+    storage_deletes: AtomicU64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            received: std::array::from_fn(|_| AtomicU64::new(0)),
+            sent: std::array::from_fn(|_| AtomicU64::new(0)),
+            handler_errors: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
+            stabilization_rounds: AtomicU64::new(0),
+            stabilization_restarts: AtomicU64::new(0),
+            self_lookups: AtomicU64::new(0),
+            self_lookup_failures: AtomicU64::new(0),
+            storage_gets: AtomicU64::new(0),
+            storage_puts: AtomicU64::new(0),
+            storage_deletes: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Stats {
+    /// Counts a message received over some connection.
+    pub fn record_received(&self, msg: &Message) {
+        self.received[msg.kind()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a message sent over some connection.
+    pub fn record_sent(&self, msg: &Message) {
+        self.sent[msg.kind()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a request which a handler failed to process.
+    pub fn record_handler_error(&self) {
+        self.handler_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a connection which timed out while waiting for a message.
+    pub fn record_timeout(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a completed stabilization round.
+    pub fn record_stabilization_round(&self) {
+        self.stabilization_rounds.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a restart of the stabilization loop after a panic.
+    pub fn record_stabilization_restart(&self) {
+        self.stabilization_restarts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a lookup of the own identifier and whether it did not resolve
+    /// back to the peer.
+    pub fn record_self_lookup(&self, failed: bool) {
+        self.self_lookups.fetch_add(1, Ordering::Relaxed);
+
+        if failed {
+            self.self_lookup_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a read from the local storage.
+    pub fn record_storage_get(&self) {
+        self.storage_gets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a write to the local storage.
+    pub fn record_storage_put(&self) {
+        self.storage_puts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a removal from the local storage.
+    pub fn record_storage_delete(&self) {
+        self.storage_deletes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the current values of all counters.
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let messages = Message::NAMES
+            .iter()
+            .zip(self.received.iter().zip(self.sent.iter()))
+            .map(|(name, (received, sent))| MessageCount {
+                name,
+                received: received.load(Ordering::Relaxed),
+                sent: sent.load(Ordering::Relaxed),
+            })
+            .filter(|count| count.received > 0 || count.sent > 0)
+            .collect();
+
+        StatsSnapshot {
+            messages,
+            handler_errors: self.handler_errors.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            stabilization_rounds: self.stabilization_rounds.load(Ordering::Relaxed),
+            stabilization_restarts: self.stabilization_restarts.load(Ordering::Relaxed),
+            self_lookups: self.self_lookups.load(Ordering::Relaxed),
+            self_lookup_failures: self.self_lookup_failures.load(Ordering::Relaxed),
+            storage_gets: self.storage_gets.load(Ordering::Relaxed),
+            storage_puts: self.storage_puts.load(Ordering::Relaxed),
+            storage_deletes: self.storage_deletes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Total number of bytes exchanged with all remote peers
+///
+/// `throttled` counts the messages which have been delayed since their
+/// sender exceeded the rate limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Traffic {
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub throttled: u64,
+}
+
+/// Number of bytes exchanged with a single remote peer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PeerTraffic {
+    pub peer: IpAddr,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub throttled: u64,
+}
+
+#[derive(Debug)]
+struct PeerBandwidth {
+    bytes_received: u64,
+    bytes_sent: u64,
+    throttled: u64,
+    /// Bytes exchanged beyond the rate limit which have not been paid off
+    debt: f64,
+    repaid: Instant,
+}
+
+impl PeerBandwidth {
+    fn new() -> Self {
+        Self {
+            bytes_received: 0,
+            bytes_sent: 0,
+            throttled: 0,
+            debt: 0.0,
+            repaid: Instant::now(),
+        }
+    }
+
+    /// Pays off the debt at `rate` bytes per second since the last time.
+    fn repay(&mut self, rate: u64) {
+        let now = Instant::now();
+        let earned = now.duration_since(self.repaid).as_secs_f64() * rate as f64;
+
+        self.debt = (self.debt - earned).max(0.0);
+        self.repaid = now;
+    }
+}
+
+/// Bytes exchanged with every remote peer
+///
+/// Peers are distinguished by their ip address since incoming connections
+/// originate from arbitrary ports. Both directions count towards the rate
+/// limit since serving values costs as much bandwidth as storing them.
+///
+/// # Examples
+///
+/// ```
+/// # use chord::metrics::Bandwidth;
+/// #
+/// let bandwidth = Bandwidth::default();
+/// let peer = "127.0.0.1".parse().unwrap();
+///
+/// bandwidth.record_received(peer, 1000);
+/// bandwidth.record_sent(peer, 500);
+///
+/// assert_eq!(1000, bandwidth.traffic().bytes_received);
+/// assert!(bandwidth.throttle(peer, 1000).is_some());
+/// ```
+#[derive(Debug, Default)]
+pub struct Bandwidth {
+    peers: Mutex<HashMap<IpAddr, PeerBandwidth>>,
+}
+
+impl Bandwidth {
+    /// Counts `bytes` received from `peer`.
+    pub fn record_received(&self, peer: IpAddr, bytes: usize) {
+        let mut peers = self.peers.lock_or_recover();
+        let entry = peers.entry(peer).or_insert_with(PeerBandwidth::new);
+
+        entry.bytes_received += bytes as u64;
+        entry.debt += bytes as f64;
+    }
+
+    /// Counts `bytes` sent to `peer`.
+    pub fn record_sent(&self, peer: IpAddr, bytes: usize) {
+        let mut peers = self.peers.lock_or_recover();
+        let entry = peers.entry(peer).or_insert_with(PeerBandwidth::new);
+
+        entry.bytes_sent += bytes as u64;
+        entry.debt += bytes as f64;
+    }
+
+    /// Returns how long to wait before serving `peer` again such that it
+    /// does not exceed `rate` bytes per second on average.
+    ///
+    /// Bursts of up to one second worth of bytes are allowed. Returns `None`
+    /// if the peer is within its limit.
+    pub fn throttle(&self, peer: IpAddr, rate: u64) -> Option<Duration> {
+        let mut peers = self.peers.lock_or_recover();
+        let entry = peers.get_mut(&peer)?;
+
+        entry.repay(rate);
+
+        if rate == 0 || entry.debt <= rate as f64 {
+            return None;
+        }
+
+        entry.throttled += 1;
+
+        Some(Duration::from_secs_f64(
+            (entry.debt - rate as f64) / rate as f64,
+        ))
+    }
+
+    /// Returns the total number of bytes exchanged with all peers.
+    pub fn traffic(&self) -> Traffic {
+        let peers = self.peers.lock_or_recover();
+
+        peers
+            .values()
+            .fold(Traffic::default(), |traffic, peer| Traffic {
+                bytes_received: traffic.bytes_received + peer.bytes_received,
+                bytes_sent: traffic.bytes_sent + peer.bytes_sent,
+                throttled: traffic.throttled + peer.throttled,
+            })
+    }
+
+    /// Returns the traffic of every peer, the peers exchanging the most
+    /// bytes first.
+    pub fn peers(&self) -> Vec<PeerTraffic> {
+        let peers = self.peers.lock_or_recover();
+
+        let mut traffic: Vec<PeerTraffic> = peers
+            .iter()
+            .map(|(&peer, bandwidth)| PeerTraffic {
+                peer,
+                bytes_received: bandwidth.bytes_received,
+                bytes_sent: bandwidth.bytes_sent,
+                throttled: bandwidth.throttled,
+            })
+            .collect();
+
+        traffic.sort_by_key(|peer| std::cmp::Reverse(peer.bytes_received + peer.bytes_sent));
+        traffic
+    }
+}
+
+/// Log of operations which took longer than a threshold
+///
+/// Slow lookups, storage operations and stabilization rounds are logged as
+/// warnings along with their full context such that the causes of high tail
+/// latencies can be found. Without a threshold nothing is logged.
+///
+/// # Examples
+///
+/// ```
+/// # use chord::metrics::SlowLog;
+/// # use std::time::Duration;
+/// #
+/// let slow_log = SlowLog::new(Some(Duration::from_millis(100)));
+///
+/// assert!(!slow_log.check("lookup", Duration::from_millis(10), || "fast"));
+/// assert!(slow_log.check("lookup", Duration::from_millis(250), || "slow"));
+/// assert_eq!(1, slow_log.count());
+/// ```
+#[derive(Debug, Default)]
+pub struct SlowLog {
+    threshold: Option<Duration>,
+    count: AtomicU64,
+}
+
+impl SlowLog {
+    /// Creates a new `SlowLog` which logs all operations taking at least
+    /// `threshold`.
+    pub fn new(threshold: Option<Duration>) -> Self {
+        Self {
+            threshold,
+            count: AtomicU64::new(0),
+        }
+    }
+
+    /// Logs `operation` if it took at least the threshold and returns
+    /// whether it did.
+    ///
+    /// The `context` is only built for slow operations.
+    pub fn check<F, D>(&self, operation: &str, elapsed: Duration, context: F) -> bool
+    where
+        F: FnOnce() -> D,
+        D: fmt::Display,
+    {
+        match self.threshold {
+            Some(threshold) if elapsed >= threshold => {
+                self.count.fetch_add(1, Ordering::Relaxed);
+
+                warn!(
+                    "Slow {} took {} ms: {}",
+                    operation,
+                    elapsed.as_millis(),
+                    context()
+                );
+
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the number of slow operations logged so far.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+/// Utilization and latencies of a pool of worker threads
+///
+/// The `wait` summary covers the time the most recent jobs have been queued
+/// until a worker picked them up, the `duration` summary the time they took
+/// to run. Both are measured in microseconds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PoolStats {
+    pub name: String,
+    pub workers: usize,
+    pub busy: usize,
+    pub queued: usize,
+    pub executed: u64,
+    pub wait: Summary,
+    pub duration: Summary,
+}
+
+impl PoolStats {
+    /// Returns the ratio of busy workers to all workers.
+    pub fn utilization(&self) -> f64 {
+        match self.workers {
+            0 => 0.0,
+            workers => self.busy as f64 / workers as f64,
+        }
+    }
+}
+
+/// Telemetry shared between all components of a peer
+#[derive(Debug, Default)]
+pub struct Metrics {
+    lookups: Mutex<LookupMetrics>,
+    fingers: Mutex<Vec<FingerStats>>,
+    stats: Stats,
+    bandwidth: Bandwidth,
+    slow_log: SlowLog,
+    ring_inconsistent: AtomicBool,
+}
+
+impl Metrics {
+    /// Creates a new `Metrics` instance without any recorded samples.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Logs all operations which take at least `threshold`.
+    ///
+    /// See [`SlowLog`] for details.
+    ///
+    /// [`SlowLog`]: struct.SlowLog.html
+    pub fn with_slow_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.slow_log = SlowLog::new(threshold);
+        self
+    }
+
+    /// Records a successful lookup which took `hops` PEER FIND requests and
+    /// `duration` in total.
+    pub fn record_lookup(&self, hops: u32, duration: Duration) {
+        let mut lookups = self.lookups.lock_or_recover();
+
+        lookups.lookups += 1;
+        lookups.hops.record(hops);
+        lookups.latency.record(duration.as_micros() as u32);
+    }
+
+    /// Records a failed lookup.
+    pub fn record_lookup_failure(&self) {
+        let mut lookups = self.lookups.lock_or_recover();
+
+        lookups.failures += 1;
+    }
+
+    /// Records a lookup which was routed through the finger with `index`
+    /// and whether that finger was already responsible for the identifier.
+    pub fn record_route(&self, index: usize, direct: bool) {
+        let mut fingers = self.fingers.lock_or_recover();
+
+        if fingers.len() <= index {
+            let len = fingers.len();
+            fingers.extend((len..=index).map(|index| FingerStats {
+                index,
+                ..FingerStats::default()
+            }));
+        }
+
+        fingers[index].routed += 1;
+        if direct {
+            fingers[index].direct += 1;
+        }
+    }
+
+    /// Records whether a lookup of the own identifier of a peer resolved
+    /// back to it.
+    ///
+    /// The outcome replaces that of the previous check and is counted in the
+    /// [`Stats`].
+    ///
+    /// [`Stats`]: struct.Stats.html
+    pub fn record_self_lookup(&self, consistent: bool) {
+        self.stats.record_self_lookup(!consistent);
+        self.ring_inconsistent.store(!consistent, Ordering::Relaxed);
+    }
+
+    /// Returns whether the last lookup of the own identifier resolved back to
+    /// the peer.
+    ///
+    /// A failed lookup indicates that the successors or fingers of some peers
+    /// on the ring are inconsistent. Peers are considered consistent until
+    /// the first check.
+    pub fn is_ring_consistent(&self) -> bool {
+        !self.ring_inconsistent.load(Ordering::Relaxed)
+    }
+
+    /// Returns the counters of this peer.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Returns the bytes exchanged with every remote peer.
+    pub fn bandwidth(&self) -> &Bandwidth {
+        &self.bandwidth
+    }
+
+    /// Returns the log of slow operations.
+    pub fn slow_log(&self) -> &SlowLog {
+        &self.slow_log
+    }
+
+    /// Returns the aggregated telemetry about lookups.
+    pub fn lookup_stats(&self) -> LookupStats {
+        let lookups = self.lookups.lock_or_recover();
+
+        LookupStats {
+            lookups: lookups.lookups,
+            failures: lookups.failures,
+            hops: lookups.hops.summary(),
+            latency: lookups.latency.summary(),
+        }
+    }
+
+    /// Returns how often lookups were routed through each finger which has
+    /// routed any, ordered by their index.
+    pub fn finger_stats(&self) -> Vec<FingerStats> {
+        let fingers = self.fingers.lock_or_recover();
+
+        fingers
+            .iter()
+            .filter(|finger| finger.routed > 0)
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_summary() {
+        let mut histogram = Histogram::new(100);
+
+        for value in 1..=100 {
+            histogram.record(value);
+        }
+
+        let summary = histogram.summary();
+
+        assert_eq!(100, summary.count);
+        assert_eq!(50.5, summary.mean);
+        assert_eq!(50, summary.p50);
+        assert_eq!(90, summary.p90);
+        assert_eq!(99, summary.p99);
+        assert_eq!(100, summary.max);
+    }
+
+    #[test]
+    fn histogram_summary_empty() {
+        let histogram = Histogram::new(100);
+
+        assert_eq!(Summary::default(), histogram.summary());
+    }
+
+    #[test]
+    fn histogram_window() {
+        let mut histogram = Histogram::new(2);
+
+        histogram.record(10);
+        histogram.record(1);
+        histogram.record(2);
+
+        let summary = histogram.summary();
+
+        assert_eq!(2, summary.count);
+        assert_eq!(2, summary.max);
+    }
+
+    #[test]
+    fn lookup_stats() {
+        let metrics = Metrics::new();
+
+        metrics.record_lookup(3, Duration::from_millis(2));
+        metrics.record_lookup_failure();
+
+        let stats = metrics.lookup_stats();
+
+        assert_eq!(1, stats.lookups);
+        assert_eq!(1, stats.failures);
+        assert_eq!(3, stats.hops.max);
+        assert_eq!(2000, stats.latency.max);
+    }
+
+    #[test]
+    fn finger_stats() {
+        let metrics = Metrics::new();
+
+        metrics.record_route(3, true);
+        metrics.record_route(3, false);
+        metrics.record_route(1, true);
+
+        let stats = metrics.finger_stats();
+
+        assert_eq!(2, stats.len());
+        assert_eq!(
+            FingerStats {
+                index: 1,
+                routed: 1,
+                direct: 1,
+            },
+            stats[0]
+        );
+        assert_eq!(0.5, stats[1].direct_ratio());
+    }
+
+    #[test]
+    fn slow_log_threshold() {
+        let slow_log = SlowLog::new(Some(Duration::from_millis(100)));
+
+        assert!(
+            !slow_log.check("lookup", Duration::from_millis(99), || -> &str {
+                panic!("context built for a fast operation")
+            })
+        );
+        assert!(slow_log.check("lookup", Duration::from_millis(100), || "slow"));
+        assert_eq!(1, slow_log.count());
+
+        let disabled = SlowLog::default();
+
+        assert!(!disabled.check("lookup", Duration::from_secs(3600), || "slow"));
+        assert_eq!(0, disabled.count());
+    }
+
+    #[test]
+    fn bandwidth_throttle() {
+        let bandwidth = Bandwidth::default();
+        let peer: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "127.0.0.2".parse().unwrap();
+
+        assert_eq!(None, bandwidth.throttle(peer, 1000));
+
+        bandwidth.record_received(peer, 1500);
+        bandwidth.record_sent(peer, 500);
+        bandwidth.record_received(other, 10);
+
+        // the peer exceeded its burst of 1000 bytes by another 1000 bytes
+        let delay = bandwidth.throttle(peer, 1000).unwrap();
+        assert!(delay > Duration::from_millis(900) && delay <= Duration::from_secs(1));
+        assert_eq!(None, bandwidth.throttle(other, 1000));
+
+        assert_eq!(
+            Traffic {
+                bytes_received: 1510,
+                bytes_sent: 500,
+                throttled: 1,
+            },
+            bandwidth.traffic()
+        );
+        assert_eq!(peer, bandwidth.peers()[0].peer);
+    }
+
+    #[test]
+    fn stats_snapshot() {
+        use crate::message::api::{DhtFailure, NodeInfo};
+
+        let dht_failure = Message::DhtFailure(DhtFailure {
+            key: [0; 32].into(),
+        });
+
+        let stats = Stats::default();
+
+        stats.record_received(&Message::NodeInfo(NodeInfo));
+        stats.record_received(&Message::NodeInfo(NodeInfo));
+        stats.record_sent(&dht_failure);
+        stats.record_stabilization_round();
+
+        let snapshot = stats.snapshot();
+
+        assert_eq!(
+            vec![
+                MessageCount {
+                    name: "DHT FAILURE",
+                    received: 0,
+                    sent: 1,
+                },
+                MessageCount {
+                    name: "NODE INFO",
+                    received: 2,
+                    sent: 0,
+                },
+            ],
+            snapshot.messages
+        );
+        assert_eq!(1, snapshot.stabilization_rounds);
+        assert_eq!(0.0, snapshot.error_rate());
+    }
+
+    #[test]
+    fn self_lookup_health() {
+        let metrics = Metrics::new();
+        assert!(metrics.is_ring_consistent());
+
+        metrics.record_self_lookup(false);
+        assert!(!metrics.is_ring_consistent());
+
+        metrics.record_self_lookup(true);
+        assert!(metrics.is_ring_consistent());
+
+        let snapshot = metrics.stats().snapshot();
+        assert_eq!(2, snapshot.self_lookups);
+        assert_eq!(1, snapshot.self_lookup_failures);
+    }
+}
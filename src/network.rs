@@ -5,19 +5,392 @@
 //! Furthermore, it includes parallel handling of incoming connections using
 //! a thread pool and the abstraction of handlers.
 //!
+//! Worker threads are named after their pool, e.g. `p2p-worker-3`, and a
+//! worker which panics is replaced by a new one. [`install_panic_hook`] logs
+//! panics along with the thread name and the address being served.
+//!
+//! The [`Multiplexer`] keeps one connection per remote peer open and shares
+//! it between all requests to that peer.
+//!
+//! Outbound connections can be tunneled through a [`Proxy`], see
+//! [`Connection::open_via`]. How the messages are laid out on the stream is
+//! up to the [`Framing`] of a connection.
+//!
 //! [`Message`]: ../message/enum.Message.html
+//! [`Multiplexer`]: struct.Multiplexer.html
+//! [`install_panic_hook`]: fn.install_panic_hook.html
+//! [`Proxy`]: ../proxy/enum.Proxy.html
+//! [`Connection::open_via`]: struct.Connection.html#method.open_via
+//! [`Framing`]: ../framing/trait.Framing.html
 
+use crate::capture::{self, Direction};
+use crate::framing::{self, Framing};
+use crate::message::api::Encoding;
+use crate::message::cbor;
+use crate::message::codec::MAX_MESSAGE_SIZE;
 use crate::message::Message;
+use crate::metrics::{Histogram, Metrics, PoolStats};
+use crate::proxy::Proxy;
+use crate::sync::MutexExt;
+use bytes::Bytes;
+use socket2::{SockRef, TcpKeepalive};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::io;
-use std::io::prelude::*;
 use std::io::Cursor;
 use std::net::*;
-use std::sync::Arc;
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, Once};
 use std::thread;
-use std::time::Duration;
-use threadpool::ThreadPool;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    static CONTEXT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Sets the context which is logged along with panics of the current thread,
+/// usually the address of the node it works for.
+pub fn set_thread_context(context: Option<String>) {
+    CONTEXT.with(|cell| *cell.borrow_mut() = context);
+}
+
+/// Installs a panic hook which logs panics with the name and context of the
+/// panicking thread before running the previous hook.
+///
+/// Calling this function more than once has no effect.
+pub fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            let name = thread::current().name().unwrap_or("<unnamed>").to_string();
+            let context = CONTEXT
+                .try_with(|cell| cell.borrow().clone())
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| "unknown node".to_string());
+
+            error!("Thread {} of {} panicked: {}", name, context, info);
+
+            previous(info);
+        }));
+    });
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// How long a worker beyond the minimum size of an autoscaling pool waits for
+/// a job before it terminates
+const WORKER_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A job along with the time it has been queued
+struct Task {
+    job: Job,
+    queued_at: Instant,
+}
+
+/// A pool of named worker threads
+///
+/// Jobs passed to [`execute`] are run on the first idle worker. A worker
+/// which panics while running a job is replaced by a new thread with the
+/// same name such that the pool keeps its size.
+///
+/// A pool created with [`autoscaling`] starts further workers up to its
+/// maximum size whenever more jobs are queued than workers are idle. Workers
+/// beyond the minimum size terminate once they have been idle for 30
+/// seconds.
+///
+/// The utilization of the workers, the time jobs wait in the queue and the
+/// time they take to run are available from [`stats`].
+///
+/// The pool can be cloned to share its workers between several servers.
+///
+/// [`execute`]: #method.execute
+/// [`autoscaling`]: #method.autoscaling
+/// [`stats`]: #method.stats
+#[derive(Clone)]
+pub struct ThreadPool {
+    sender: Sender<Task>,
+    pool: Arc<Pool>,
+}
+
+/// State shared between a pool and its workers
+struct Pool {
+    name: String,
+    jobs: Mutex<Receiver<Task>>,
+    min_workers: usize,
+    max_workers: usize,
+    idle_timeout: Duration,
+    next_index: AtomicUsize,
+    workers: AtomicUsize,
+    busy: AtomicUsize,
+    queued: AtomicUsize,
+    executed: AtomicU64,
+    wait: Mutex<Histogram>,
+    duration: Mutex<Histogram>,
+}
+
+impl ThreadPool {
+    /// Creates a pool of `num_workers` threads named `name-0`, `name-1` and
+    /// so on.
+    pub fn new(name: &str, num_workers: usize) -> Self {
+        Self::with_limits(name, num_workers, num_workers, WORKER_IDLE_TIMEOUT)
+    }
+
+    /// Creates a pool of `min_workers` threads which grows up to
+    /// `max_workers` threads when jobs are queued.
+    ///
+    /// `max_workers` is raised to `min_workers` if it is smaller.
+    pub fn autoscaling(name: &str, min_workers: usize, max_workers: usize) -> Self {
+        Self::with_limits(name, min_workers, max_workers, WORKER_IDLE_TIMEOUT)
+    }
+
+    fn with_limits(
+        name: &str,
+        min_workers: usize,
+        max_workers: usize,
+        idle_timeout: Duration,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let pool = Arc::new(Pool {
+            name: name.to_string(),
+            jobs: Mutex::new(receiver),
+            min_workers,
+            max_workers: max_workers.max(min_workers),
+            idle_timeout,
+            next_index: AtomicUsize::new(min_workers),
+            workers: AtomicUsize::new(min_workers),
+            busy: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            executed: AtomicU64::new(0),
+            wait: Mutex::new(Histogram::default()),
+            duration: Mutex::new(Histogram::default()),
+        });
+
+        for index in 0..min_workers {
+            spawn_worker(format!("{}-{}", name, index), Arc::clone(&pool));
+        }
+
+        ThreadPool { sender, pool }
+    }
+
+    /// Runs `job` on one of the workers.
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        let task = Task {
+            job: Box::new(job),
+            queued_at: Instant::now(),
+        };
+
+        let queued = self.pool.queued.fetch_add(1, Ordering::SeqCst) + 1;
+
+        if self.sender.send(task).is_err() {
+            self.pool.queued.fetch_sub(1, Ordering::SeqCst);
+            error!("Thread pool has no workers left");
+            return;
+        }
 
-const MAX_MESSAGE_SIZE: usize = 64000;
+        if queued > self.pool.idle_workers() {
+            self.pool.grow();
+        }
+    }
+
+    /// Returns the utilization and latencies of this pool.
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            name: self.pool.name.clone(),
+            workers: self.pool.workers.load(Ordering::SeqCst),
+            busy: self.pool.busy.load(Ordering::SeqCst),
+            queued: self.pool.queued.load(Ordering::SeqCst),
+            executed: self.pool.executed.load(Ordering::SeqCst),
+            wait: self.pool.wait.lock_or_recover().summary(),
+            duration: self.pool.duration.lock_or_recover().summary(),
+        }
+    }
+}
+
+impl Pool {
+    fn idle_workers(&self) -> usize {
+        let workers = self.workers.load(Ordering::SeqCst);
+
+        workers.saturating_sub(self.busy.load(Ordering::SeqCst))
+    }
+
+    /// Starts another worker unless the pool has reached its maximum size.
+    fn grow(self: &Arc<Self>) {
+        let grown = self
+            .workers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |workers| {
+                Some(workers + 1).filter(|&workers| workers <= self.max_workers)
+            });
+
+        if grown.is_ok() {
+            let index = self.next_index.fetch_add(1, Ordering::SeqCst);
+            debug!("Growing thread pool {} to meet queued jobs", self.name);
+
+            spawn_worker(format!("{}-{}", self.name, index), Arc::clone(self));
+        }
+    }
+
+    /// Returns whether an idle worker may terminate since the pool is larger
+    /// than its minimum size.
+    fn shrink(&self) -> bool {
+        self.workers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |workers| {
+                Some(workers - 1).filter(|&workers| workers >= self.min_workers)
+            })
+            .is_ok()
+    }
+
+    fn next_task(&self) -> Option<Task> {
+        // the lock is released before the job runs
+        let jobs = self.jobs.lock_or_recover();
+
+        loop {
+            if self.min_workers == self.max_workers {
+                return jobs.recv().ok();
+            }
+
+            match jobs.recv_timeout(self.idle_timeout) {
+                Ok(task) => return Some(task),
+                Err(RecvTimeoutError::Timeout) if self.shrink() => return None,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+
+    fn run(&self, task: Task) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        self.busy.fetch_add(1, Ordering::SeqCst);
+
+        let started = Instant::now();
+        record_micros(&self.wait, started - task.queued_at);
+
+        set_thread_context(None);
+        (task.job)();
+
+        record_micros(&self.duration, started.elapsed());
+        self.executed.fetch_add(1, Ordering::SeqCst);
+        self.busy.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn record_micros(histogram: &Mutex<Histogram>, duration: Duration) {
+    let micros = duration.as_micros().min(u128::from(u32::MAX)) as u32;
+
+    histogram.lock_or_recover().record(micros);
+}
+
+/// Replaces a worker if it is dropped during a panic.
+struct Sentinel {
+    name: String,
+    pool: Arc<Pool>,
+}
+
+impl Drop for Sentinel {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            warn!("Replacing panicked worker {}", self.name);
+
+            self.pool.busy.fetch_sub(1, Ordering::SeqCst);
+            spawn_worker(self.name.clone(), Arc::clone(&self.pool));
+        }
+    }
+}
+
+fn spawn_worker(name: String, pool: Arc<Pool>) {
+    let builder = thread::Builder::new().name(name.clone());
+
+    let result = builder.spawn({
+        let pool = Arc::clone(&pool);
+
+        move || {
+            let _sentinel = Sentinel {
+                name,
+                pool: Arc::clone(&pool),
+            };
+
+            while let Some(task) = pool.next_task() {
+                pool.run(task);
+            }
+        }
+    });
+
+    if let Err(err) = result {
+        error!("Could not spawn worker thread: {}", err);
+        pool.workers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Options of the TCP sockets of a peer
+///
+/// Nagle's algorithm is disabled by default since peers exchange small
+/// requests and wait for their replies, which the algorithm would delay.
+/// Keepalive probes and the sizes of the socket buffers are left to the
+/// operating system unless they are set.
+///
+/// # Examples
+///
+/// ```
+/// # use chord::network::SocketOptions;
+/// # use std::time::Duration;
+/// #
+/// let options = SocketOptions {
+///     keepalive: Some(Duration::from_secs(60)),
+///     ..SocketOptions::default()
+/// };
+///
+/// assert!(options.nodelay);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SocketOptions {
+    /// Whether `TCP_NODELAY` is set to send small messages right away
+    pub nodelay: bool,
+    /// Idle time after which keepalive probes are sent
+    pub keepalive: Option<Duration>,
+    /// Size of the send buffer in bytes
+    pub send_buffer_size: Option<usize>,
+    /// Size of the receive buffer in bytes
+    pub recv_buffer_size: Option<usize>,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            keepalive: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+        }
+    }
+}
+
+impl SocketOptions {
+    /// Applies these options to `stream`.
+    pub fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        let socket = SockRef::from(stream);
+
+        socket.set_nodelay(self.nodelay)?;
+
+        if let Some(keepalive) = self.keepalive {
+            socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(keepalive))?;
+        }
+
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+
+        Ok(())
+    }
+}
 
 /// A connection between two peers to send Message objects via TCP
 ///
@@ -34,14 +407,24 @@ const MAX_MESSAGE_SIZE: usize = 64000;
 /// ```
 pub struct Connection {
     stream: TcpStream,
-    buffer: [u8; MAX_MESSAGE_SIZE],
+    buffer: Box<[u8]>,
+    metrics: Option<Arc<Metrics>>,
+    rate_limit: Option<u64>,
+    request_id: Option<u32>,
+    encoding: Encoding,
+    /// Address of the remote peer if the stream is tunneled through a proxy
+    tunneled_to: Option<SocketAddr>,
+    framing: Arc<dyn Framing>,
+    /// Shared by all handles such that their frames are written one at a time
+    write_lock: Arc<Mutex<()>>,
 }
 
 impl Connection {
     /// Opens a TCP connection to a remote peer.
     ///
     /// This uses [`TcpStream::connect`] to create a new TCP socket to the
-    /// remote peer with address `addr`.
+    /// remote peer with address `addr`. If `addr` yields several addresses,
+    /// they are tried in parallel as described in [`connect_parallel`].
     ///
     /// `timeout_ms` is the timeout in milliseconds for both read and write
     /// operations. See [`TcpStream::set_read_timeout`] and
@@ -53,39 +436,284 @@ impl Connection {
     /// ../../std/net/struct.TcpStream.html#method.set_read_timeout
     /// [`TcpStream::set_write_timeout`]:
     /// ../../std/net/struct.TcpStream.html#method.set_write_timeout
+    /// [`connect_parallel`]: fn.connect_parallel.html
     pub fn open<A: ToSocketAddrs>(addr: A, timeout_ms: u64) -> io::Result<Self> {
-        // TODO add connection timeout
-        let stream = TcpStream::connect(addr)?;
-
-        trace!("Connection to {} - Opened", stream.peer_addr()?);
+        Self::open_via(addr, timeout_ms, None)
+    }
 
+    /// Opens a TCP connection to a remote peer through `proxy` if it is given
+    /// and directly otherwise.
+    ///
+    /// Through a proxy, the addresses `addr` yields are tried one after the
+    /// other and `timeout_ms` also limits the handshake with the proxy. The
+    /// connection then reports the remote peer as its [`peer_addr`] instead
+    /// of the proxy. See [`open`] for the direct connection.
+    ///
+    /// [`peer_addr`]: #method.peer_addr
+    /// [`open`]: #method.open
+    pub fn open_via<A: ToSocketAddrs>(
+        addr: A,
+        timeout_ms: u64,
+        proxy: Option<&Proxy>,
+    ) -> io::Result<Self> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
         let timeout = Duration::from_millis(timeout_ms);
-        stream.set_read_timeout(Some(timeout))?;
-        stream.set_write_timeout(Some(timeout))?;
 
-        Ok(Self::from_stream(stream))
+        let connection = match proxy {
+            Some(proxy) => Self::tunnel(&addrs, proxy, timeout)?,
+            None => {
+                // TODO add connection timeout
+                let stream = match addrs[..] {
+                    [addr] => TcpStream::connect(addr)?,
+                    _ => connect_parallel(&addrs)?,
+                };
+
+                Self::from_stream(stream)
+            }
+        };
+
+        trace!("Connection to {} - Opened", connection.peer_addr()?);
+
+        connection.stream.set_read_timeout(Some(timeout))?;
+        connection.stream.set_write_timeout(Some(timeout))?;
+
+        Ok(connection)
+    }
+
+    /// Tunnels a connection to the first of `addrs` reachable through `proxy`.
+    fn tunnel(addrs: &[SocketAddr], proxy: &Proxy, timeout: Duration) -> io::Result<Self> {
+        let mut last_err = None;
+
+        for &addr in addrs {
+            match proxy.connect(addr, timeout) {
+                Ok(stream) => {
+                    let mut connection = Self::from_stream(stream);
+                    connection.tunneled_to = Some(addr);
+
+                    return Ok(connection);
+                }
+                Err(err) => {
+                    debug!("Could not connect to {} through {}: {}", addr, proxy, err);
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "No address to connect to")
+        }))
+    }
+
+    /// Creates a second handle to this connection.
+    ///
+    /// Both handles share the underlying TCP stream such that one thread can
+    /// receive messages while another one sends. Messages sent by several
+    /// handles at once are written one after another. See
+    /// [`TcpStream::try_clone`] for further documentation.
+    ///
+    /// [`TcpStream::try_clone`]:
+    /// ../../std/net/struct.TcpStream.html#method.try_clone
+    pub fn try_clone(&self) -> io::Result<Self> {
+        let mut connection = Self::from_stream(self.stream.try_clone()?);
+        connection.metrics = self.metrics.clone();
+        connection.rate_limit = self.rate_limit;
+        connection.encoding = self.encoding;
+        connection.tunneled_to = self.tunneled_to;
+        connection.framing = Arc::clone(&self.framing);
+        connection.write_lock = Arc::clone(&self.write_lock);
+
+        Ok(connection)
+    }
+
+    /// Sets the timeout in milliseconds for read operations.
+    ///
+    /// `None` lets [`receive`] block until a message arrives. The timeout
+    /// applies to all handles created with [`try_clone`]. See
+    /// [`TcpStream::set_read_timeout`] for further documentation.
+    ///
+    /// [`receive`]: #method.receive
+    /// [`try_clone`]: #method.try_clone
+    /// [`TcpStream::set_read_timeout`]:
+    /// ../../std/net/struct.TcpStream.html#method.set_read_timeout
+    pub fn set_read_timeout(&self, timeout_ms: Option<u64>) -> io::Result<()> {
+        self.stream
+            .set_read_timeout(timeout_ms.map(Duration::from_millis))
+    }
+
+    /// Applies `options` to the socket of this connection.
+    ///
+    /// The options apply to all handles created with [`try_clone`].
+    ///
+    /// [`try_clone`]: #method.try_clone
+    pub fn set_socket_options(&self, options: &SocketOptions) -> io::Result<()> {
+        options.apply(&self.stream)
     }
 
     fn from_stream(stream: TcpStream) -> Self {
         // TODO set read and write timeout
-        let buffer = [0; MAX_MESSAGE_SIZE];
-        Self { stream, buffer }
+        // keep the buffer on the heap such that moving a connection is cheap
+        let buffer = vec![0; MAX_MESSAGE_SIZE].into_boxed_slice();
+        Self {
+            stream,
+            buffer,
+            metrics: None,
+            rate_limit: None,
+            request_id: None,
+            encoding: Encoding::Binary,
+            tunneled_to: None,
+            framing: framing::plain(),
+            write_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Counts the messages exchanged over this connection as well as timeouts
+    /// in the [`Stats`] of `metrics`.
+    ///
+    /// [`Stats`]: ../metrics/struct.Stats.html
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Delays the next message of a remote peer which exchanges more than
+    /// `rate_limit` bytes per second with this peer on average.
+    ///
+    /// The bytes are accounted in the [`Bandwidth`] of the metrics given to
+    /// [`with_metrics`] such that all connections to the same peer share the
+    /// limit. Without metrics, no peer is throttled.
+    ///
+    /// [`Bandwidth`]: ../metrics/struct.Bandwidth.html
+    /// [`with_metrics`]: #method.with_metrics
+    pub fn with_rate_limit(mut self, rate_limit: Option<u64>) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Exchanges the messages in frames of `framing` instead of plain.
+    ///
+    /// The remote peer has to use the same framing.
+    pub fn with_framing(mut self, framing: Arc<dyn Framing>) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Performs the handshake of the framing as the peer which opened the
+    /// connection.
+    ///
+    /// Call this after [`with_framing`] and before sending the first message.
+    ///
+    /// [`with_framing`]: #method.with_framing
+    pub fn handshake(&mut self) -> io::Result<()> {
+        self.framing.connect(&mut self.stream)
+    }
+
+    /// Returns the encoding of the messages exchanged over this handle.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Exchanges all following messages over this handle in `encoding`.
+    ///
+    /// Api connections negotiate the encoding with an `API ENCODING`
+    /// message. Only api messages can be exchanged in [`Encoding::Cbor`], see
+    /// the [`cbor`] module for further documentation.
+    ///
+    /// [`Encoding::Cbor`]: ../message/api/enum.Encoding.html#variant.Cbor
+    /// [`cbor`]: ../message/cbor/index.html
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
     }
 
     /// Receives a message from the remote peer.
     ///
-    /// This operation is blocking until a message has been received.
+    /// This operation is blocking until a message has been received. Exactly
+    /// one message is read from the stream such that several messages can be
+    /// exchanged over the same connection.
+    ///
+    /// If the message is wrapped in a [`Correlated`] envelope, the wrapped
+    /// message is returned and its request ID is available from
+    /// [`request_id`] until the next message is received.
+    ///
+    /// [`Correlated`]: ../message/p2p/struct.Correlated.html
+    /// [`request_id`]: #method.request_id
     pub fn receive(&mut self) -> io::Result<Message> {
-        // read bytes from tcp stream
-        let size = self.stream.read(self.buffer.as_mut())?;
+        let result = self.read_message().map(|msg| match msg {
+            Message::Correlated(correlated) => {
+                self.request_id = Some(correlated.request_id);
+                *correlated.message
+            }
+            msg => {
+                self.request_id = None;
+                msg
+            }
+        });
+
+        if let Some(ref metrics) = self.metrics {
+            match result {
+                Ok(ref msg) => {
+                    metrics.stats().record_received(msg);
+                    self.throttle(metrics);
+                }
+                Err(ref err)
+                    if err.kind() == io::ErrorKind::TimedOut
+                        || err.kind() == io::ErrorKind::WouldBlock =>
+                {
+                    metrics.stats().record_timeout()
+                }
+                Err(_) => {}
+            }
+        }
+
+        result
+    }
 
-        // create cursor to parse message
-        let msg = Message::parse(Cursor::new(&self.buffer[..size]))?;
+    /// Waits until the remote peer is within its rate limit again.
+    fn throttle(&self, metrics: &Metrics) {
+        let (rate_limit, peer_addr) = match (self.rate_limit, self.peer_addr()) {
+            (Some(rate_limit), Ok(peer_addr)) => (rate_limit, peer_addr),
+            _ => return,
+        };
+
+        if let Some(delay) = metrics.bandwidth().throttle(peer_addr.ip(), rate_limit) {
+            debug!("Throttling {} for {:?}", peer_addr, delay);
+            thread::sleep(delay);
+        }
+    }
+
+    fn read_message(&mut self) -> io::Result<Message> {
+        let size = self
+            .framing
+            .read_frame(&mut self.stream, &mut self.buffer)?;
+
+        // the values of a binary message refer to a copy of the frame such
+        // that the buffer can be reused for the next message
+        let msg = match self.encoding {
+            Encoding::Binary => Message::from_bytes(Bytes::copy_from_slice(&self.buffer[..size]))?,
+            Encoding::Cbor => cbor::parse(Cursor::new(&self.buffer[..size]))?,
+        };
+
+        if let (Some(metrics), Ok(peer_addr)) = (&self.metrics, self.peer_addr()) {
+            metrics
+                .bandwidth()
+                .record_received(peer_addr.ip(), size.max(4));
+        }
+
+        if capture::is_active() {
+            let (local_addr, peer_addr) = (self.stream.local_addr()?, self.peer_addr()?);
+            let bytes = &self.buffer[..size.max(4)];
+            capture::record(
+                Direction::Received,
+                local_addr,
+                peer_addr,
+                msg.name(),
+                bytes,
+            );
+        }
 
         // output debug information
         trace!(
             "Connection to {} - Received message of type {}",
-            self.stream.peer_addr()?,
+            self.peer_addr()?,
             msg
         );
 
@@ -94,30 +722,104 @@ impl Connection {
 
     /// Sends a message to the remote peer.
     ///
+    /// If the last received message carried a request ID, the message is
+    /// sent as a reply with the same request ID.
+    ///
     /// This operation is blocking until the message has been sent.
     pub fn send(&mut self, msg: &Message) -> io::Result<()> {
+        self.write_message(msg, self.request_id)
+    }
+
+    /// Sends a request with the given `request_id` to the remote peer.
+    ///
+    /// The remote peer replies with the same request ID such that several
+    /// requests can be outstanding on the same connection.
+    pub fn send_request(&mut self, request_id: u32, msg: &Message) -> io::Result<()> {
+        self.write_message(msg, Some(request_id))
+    }
+
+    /// Returns the request ID of the last received message if it had one.
+    pub fn request_id(&self) -> Option<u32> {
+        self.request_id
+    }
+
+    /// Makes [`send`] reply with `request_id` as if the last message had been
+    /// received on this handle.
+    ///
+    /// This is needed if messages are received by another handle created
+    /// with [`try_clone`].
+    ///
+    /// [`send`]: #method.send
+    /// [`try_clone`]: #method.try_clone
+    pub fn set_request_id(&mut self, request_id: Option<u32>) {
+        self.request_id = request_id;
+    }
+
+    fn write_message(&mut self, msg: &Message, request_id: Option<u32>) -> io::Result<()> {
         // create cursor to write message
-        let size = msg.write_to(Cursor::new(self.buffer.as_mut()))?;
+        let cursor = Cursor::new(self.buffer.as_mut());
+        let size = match (request_id, self.encoding) {
+            (Some(request_id), _) => msg.write_correlated_to(request_id, cursor)?,
+            (None, Encoding::Binary) => msg.write_to(cursor)?,
+            (None, Encoding::Cbor) => cbor::write_to(msg, cursor)?,
+        };
 
         // output debug information
         trace!(
             "Connection to {} - Sent message of type {}",
-            self.stream.peer_addr()?,
+            self.peer_addr()?,
             msg
         );
 
+        // record before writing such that the peer cannot capture the
+        // message as received before it has been captured as sent
+        if capture::is_active() {
+            let (local_addr, peer_addr) = (self.stream.local_addr()?, self.peer_addr()?);
+            let name = match request_id {
+                Some(_) => "CORRELATED",
+                None => msg.name(),
+            };
+            capture::record(
+                Direction::Sent,
+                local_addr,
+                peer_addr,
+                name,
+                &self.buffer[..size],
+            );
+        }
+
         // write bytes to tcp stream
-        self.stream.write_all(&self.buffer[..size])
+        {
+            let _write = self.write_lock.lock_or_recover();
+
+            self.framing
+                .write_frame(&mut self.stream, &self.buffer[..size])?;
+        }
+
+        if let Some(ref metrics) = self.metrics {
+            metrics.stats().record_sent(msg);
+
+            if let Ok(peer_addr) = self.peer_addr() {
+                metrics.bandwidth().record_sent(peer_addr.ip(), size);
+            }
+        }
+
+        Ok(())
     }
 
     /// Returns the socket address of the remote peer of this TCP connection.
     ///
-    /// See [`TcpStream::peer_addr`] for further documentation.
+    /// For a connection tunneled through a proxy, this is the peer at the
+    /// other end of the tunnel. See [`TcpStream::peer_addr`] for further
+    /// documentation.
     ///
     /// [`TcpStream::peer_addr`]:
     /// ../../std/net/struct.TcpStream.html#method.peer_addr
     pub fn peer_addr(&self) -> io::Result<SocketAddr> {
-        self.stream.peer_addr()
+        match self.tunneled_to {
+            Some(addr) => Ok(addr),
+            None => self.stream.peer_addr(),
+        }
     }
 
     /// Returns the socket address of the local half of this TCP connection.
@@ -139,6 +841,552 @@ impl Connection {
     pub fn shutdown(&mut self) -> io::Result<()> {
         self.stream.shutdown(Shutdown::Both)
     }
+
+    /// Consumes the connection and returns the underlying TCP stream.
+    ///
+    /// This allows handlers to speak a different protocol on top of the
+    /// stream, for example after a WebSocket handshake.
+    pub fn into_stream(self) -> TcpStream {
+        self.stream
+    }
+}
+
+/// A shared connection to one remote peer
+///
+/// Requests wait in `pending` for the reply with their request ID which is
+/// delivered by a separate reader thread.
+struct Channel {
+    writer: Mutex<Connection>,
+    pending: Mutex<HashMap<u32, Sender<Message>>>,
+    closed: AtomicBool,
+    retired: AtomicBool,
+    opened: Instant,
+    last_used: Mutex<Instant>,
+}
+
+impl Channel {
+    fn open(
+        peer_addr: SocketAddr,
+        timeout_ms: u64,
+        metrics: &Arc<Metrics>,
+        proxy: Option<&Proxy>,
+        framing: &Arc<dyn Framing>,
+        socket_options: &SocketOptions,
+    ) -> io::Result<Self> {
+        let mut writer = Connection::open_via(peer_addr, timeout_ms, proxy)?
+            .with_metrics(Arc::clone(metrics))
+            .with_framing(Arc::clone(framing));
+        writer.set_socket_options(socket_options)?;
+        writer.handshake()?;
+        let now = Instant::now();
+
+        Ok(Self {
+            writer: Mutex::new(writer),
+            pending: Mutex::new(HashMap::new()),
+            closed: AtomicBool::new(false),
+            retired: AtomicBool::new(false),
+            opened: now,
+            last_used: Mutex::new(now),
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether the connection has been open for longer than
+    /// `max_lifetime` or has not been used for longer than `max_idle`.
+    fn is_expired(&self, max_idle: Option<Duration>, max_lifetime: Option<Duration>) -> bool {
+        if max_lifetime.is_some_and(|max_lifetime| self.opened.elapsed() > max_lifetime) {
+            return true;
+        }
+
+        max_idle.is_some_and(|max_idle| {
+            self.pending.lock_or_recover().is_empty()
+                && self.last_used.lock_or_recover().elapsed() > max_idle
+        })
+    }
+
+    /// Checks that the socket has not reported an error like a reset by the
+    /// peer which the reader thread did not notice yet.
+    fn is_healthy(&self) -> bool {
+        if self.is_closed() {
+            return false;
+        }
+
+        match self.writer.lock_or_recover().stream.take_error() {
+            Ok(None) => true,
+            Ok(Some(err)) | Err(err) => {
+                debug!("Connection failed health check: {}", err);
+                false
+            }
+        }
+    }
+
+    /// Stops handing out this connection and closes it as soon as the
+    /// requests still waiting for their reply are done.
+    fn retire(&self) {
+        self.retired.store(true, Ordering::SeqCst);
+        self.release();
+    }
+
+    /// Marks the connection as used and closes it if it has been retired and
+    /// no more requests are waiting.
+    fn release(&self) {
+        *self.last_used.lock_or_recover() = Instant::now();
+
+        if self.retired.load(Ordering::SeqCst) && self.pending.lock_or_recover().is_empty() {
+            // the reader thread notices the shutdown and exits
+            let _ = self.writer.lock_or_recover().shutdown();
+        }
+    }
+
+    /// Receives replies and passes them to the waiting requests until the
+    /// connection is closed.
+    fn read_replies(&self, mut reader: Connection) {
+        loop {
+            match reader.receive() {
+                Ok(msg) => {
+                    let waiting = reader
+                        .request_id()
+                        .and_then(|request_id| self.pending.lock_or_recover().remove(&request_id));
+
+                    match waiting {
+                        Some(sender) => {
+                            // the request may have timed out in the meantime
+                            let _ = sender.send(msg);
+                        }
+                        None => debug!("Dropped unexpected reply of type {}", msg),
+                    }
+                }
+                Err(err) => {
+                    debug!("Multiplexed connection closed: {}", err);
+
+                    // dropping the senders wakes up all waiting requests
+                    let mut pending = self.pending.lock_or_recover();
+                    self.closed.store(true, Ordering::SeqCst);
+                    pending.clear();
+
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Shares one connection per remote peer between concurrent requests
+///
+/// Every request is sent with a unique request ID and the reply carrying the
+/// same ID is passed back to the caller. Closed connections are replaced on
+/// the next request to the same peer.
+///
+/// Connections are closed once they have been idle for [`DEFAULT_MAX_IDLE`]
+/// or open for [`DEFAULT_MAX_LIFETIME`] such that dead NAT mappings or
+/// peers which restarted with another identity are not used forever. Before
+/// an open connection is reused, its socket is checked for pending errors.
+///
+/// The remote peer has to understand [`Correlated`] messages.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chord::message::Message;
+/// # use chord::message::p2p::PredecessorNotify;
+/// # use chord::metrics::Metrics;
+/// # use chord::network::Multiplexer;
+/// # use std::sync::Arc;
+/// #
+/// # let socket_addr = "127.0.0.1:8081".parse().unwrap();
+/// # let msg = Message::PredecessorNotify(PredecessorNotify { socket_addr });
+/// #
+/// let multiplexer = Multiplexer::new(Arc::new(Metrics::new()));
+///
+/// let reply = multiplexer
+///     .request("127.0.0.1:8080".parse().unwrap(), &msg, 3600)
+///     .expect("request failed");
+/// ```
+///
+/// [`Correlated`]: ../message/p2p/struct.Correlated.html
+/// [`DEFAULT_MAX_IDLE`]: constant.DEFAULT_MAX_IDLE.html
+/// [`DEFAULT_MAX_LIFETIME`]: constant.DEFAULT_MAX_LIFETIME.html
+pub struct Multiplexer {
+    channels: Mutex<HashMap<SocketAddr, Arc<Channel>>>,
+    next_request_id: AtomicU32,
+    metrics: Arc<Metrics>,
+    max_idle: Option<Duration>,
+    max_lifetime: Option<Duration>,
+    health_check: bool,
+    proxy: Option<Proxy>,
+    framing: Arc<dyn Framing>,
+    socket_options: SocketOptions,
+}
+
+/// Time after which an unused connection of a [`Multiplexer`] is closed
+///
+/// [`Multiplexer`]: struct.Multiplexer.html
+pub const DEFAULT_MAX_IDLE: Duration = Duration::from_secs(60);
+
+/// Time after which a connection of a [`Multiplexer`] is replaced
+///
+/// [`Multiplexer`]: struct.Multiplexer.html
+pub const DEFAULT_MAX_LIFETIME: Duration = Duration::from_secs(600);
+
+impl Multiplexer {
+    /// Creates a multiplexer which counts the messages of its connections in
+    /// `metrics`.
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self {
+            channels: Mutex::new(HashMap::new()),
+            next_request_id: AtomicU32::new(0),
+            metrics,
+            max_idle: Some(DEFAULT_MAX_IDLE),
+            max_lifetime: Some(DEFAULT_MAX_LIFETIME),
+            health_check: true,
+            proxy: None,
+            framing: framing::plain(),
+            socket_options: SocketOptions::default(),
+        }
+    }
+
+    /// Closes connections which have not been used for `max_idle` or never
+    /// if `None`.
+    pub fn with_max_idle(mut self, max_idle: Option<Duration>) -> Self {
+        self.max_idle = max_idle;
+        self
+    }
+
+    /// Replaces connections which have been open for `max_lifetime` or never
+    /// if `None`.
+    ///
+    /// Requests which are waiting for their reply when the lifetime ends are
+    /// still completed on the old connection.
+    pub fn with_max_lifetime(mut self, max_lifetime: Option<Duration>) -> Self {
+        self.max_lifetime = max_lifetime;
+        self
+    }
+
+    /// Sets whether the socket of a connection is checked for errors before
+    /// the connection is reused.
+    pub fn with_health_check(mut self, health_check: bool) -> Self {
+        self.health_check = health_check;
+        self
+    }
+
+    /// Opens the connections through `proxy` if it is given.
+    pub fn with_proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Exchanges the messages in frames of `framing`.
+    pub fn with_framing(mut self, framing: Arc<dyn Framing>) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Applies `socket_options` to the connections.
+    pub fn with_socket_options(mut self, socket_options: SocketOptions) -> Self {
+        self.socket_options = socket_options;
+        self
+    }
+
+    /// Sends `msg` to the peer at `peer_addr` and waits for the reply.
+    ///
+    /// The connection to the peer is opened on first use and shared with all
+    /// other requests to this peer. `timeout_ms` limits the time to wait for
+    /// the reply.
+    ///
+    /// A request on a connection which turns out to be closed by the peer is
+    /// retried once on a new connection.
+    pub fn request(
+        &self,
+        peer_addr: SocketAddr,
+        msg: &Message,
+        timeout_ms: u64,
+    ) -> io::Result<Message> {
+        let (channel, reused) = self.channel(peer_addr, timeout_ms)?;
+
+        match self.request_on(&channel, msg, timeout_ms) {
+            Err(ref err) if reused && err.kind() != io::ErrorKind::TimedOut => {
+                debug!("Retrying request to {} on a new connection", peer_addr);
+
+                let (channel, _) = self.channel(peer_addr, timeout_ms)?;
+                self.request_on(&channel, msg, timeout_ms)
+            }
+            result => result,
+        }
+    }
+
+    /// Returns the number of open connections which have not expired yet.
+    pub fn connections(&self) -> usize {
+        let channels = self.channels.lock_or_recover();
+
+        channels
+            .values()
+            .filter(|channel| {
+                !channel.is_closed() && !channel.is_expired(self.max_idle, self.max_lifetime)
+            })
+            .count()
+    }
+
+    /// Closes all expired connections and forgets about the closed ones.
+    fn evict(&self, channels: &mut HashMap<SocketAddr, Arc<Channel>>) {
+        channels.retain(|peer_addr, channel| {
+            if channel.is_closed() {
+                return false;
+            }
+
+            if channel.is_expired(self.max_idle, self.max_lifetime) {
+                debug!("Closing expired connection to {}", peer_addr);
+                channel.retire();
+
+                return false;
+            }
+
+            true
+        });
+    }
+
+    /// Returns the open connection to `peer_addr` or opens a new one along
+    /// with whether an existing connection has been reused.
+    ///
+    /// New connections are opened without holding the lock on the open
+    /// connections so that a slow peer does not hold up requests to others.
+    fn channel(&self, peer_addr: SocketAddr, timeout_ms: u64) -> io::Result<(Arc<Channel>, bool)> {
+        if let Some(channel) = self.healthy_channel(peer_addr) {
+            return Ok((channel, true));
+        }
+
+        let channel = Arc::new(Channel::open(
+            peer_addr,
+            timeout_ms,
+            &self.metrics,
+            self.proxy.as_ref(),
+            &self.framing,
+            &self.socket_options,
+        )?);
+
+        let reader = channel.writer.lock_or_recover().try_clone()?;
+        reader.set_read_timeout(None)?;
+
+        let reading_channel = Arc::clone(&channel);
+        thread::Builder::new()
+            .name("multiplexer".to_string())
+            .spawn(move || reading_channel.read_replies(reader))?;
+
+        let mut channels = self.channels.lock_or_recover();
+
+        // another request may have opened a connection in the meantime
+        if let Some(existing) = channels.get(&peer_addr) {
+            if !existing.is_closed() {
+                let existing = Arc::clone(existing);
+                drop(channels);
+                channel.retire();
+
+                return Ok((existing, true));
+            }
+        }
+
+        if let Some(replaced) = channels.insert(peer_addr, Arc::clone(&channel)) {
+            replaced.retire();
+        }
+
+        Ok((channel, false))
+    }
+
+    /// Returns the open connection to `peer_addr` if there is a healthy one.
+    fn healthy_channel(&self, peer_addr: SocketAddr) -> Option<Arc<Channel>> {
+        let mut channels = self.channels.lock_or_recover();
+        self.evict(&mut channels);
+
+        let channel = channels.remove(&peer_addr)?;
+
+        if !self.health_check || channel.is_healthy() {
+            channels.insert(peer_addr, Arc::clone(&channel));
+
+            return Some(channel);
+        }
+
+        channel.retire();
+
+        None
+    }
+
+    fn request_on(&self, channel: &Channel, msg: &Message, timeout_ms: u64) -> io::Result<Message> {
+        let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::channel();
+
+        {
+            let mut pending = channel.pending.lock_or_recover();
+
+            if channel.is_closed() {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "Connection closed by peer",
+                ));
+            }
+
+            pending.insert(request_id, sender);
+        }
+
+        if let Err(err) = channel
+            .writer
+            .lock_or_recover()
+            .send_request(request_id, msg)
+        {
+            channel.pending.lock_or_recover().remove(&request_id);
+            channel.closed.store(true, Ordering::SeqCst);
+
+            return Err(err);
+        }
+
+        let result = match receiver.recv_timeout(Duration::from_millis(timeout_ms)) {
+            Ok(reply) => Ok(reply),
+            Err(RecvTimeoutError::Timeout) => {
+                channel.pending.lock_or_recover().remove(&request_id);
+                self.metrics.stats().record_timeout();
+
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "No reply received in time",
+                ))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "Connection closed by peer",
+            )),
+        };
+
+        channel.release();
+
+        result
+    }
+}
+
+/// Time to wait for a connection attempt before the next address is tried
+/// in parallel
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Connects to the first of several addresses of the same peer which
+/// accepts the connection.
+///
+/// Following the happy eyeballs algorithm of RFC 8305, IPv6 and IPv4
+/// addresses are tried alternately. Every [`CONNECTION_ATTEMPT_DELAY`] or as
+/// soon as an attempt fails, another attempt is started without aborting
+/// the previous ones. The first connection which succeeds is returned and
+/// connections succeeding later on are closed again.
+///
+/// [`CONNECTION_ATTEMPT_DELAY`]: constant.CONNECTION_ATTEMPT_DELAY.html
+pub fn connect_parallel(addrs: &[SocketAddr]) -> io::Result<TcpStream> {
+    let (sender, receiver) = mpsc::channel();
+    let mut attempts = 0;
+    let mut last_err = None;
+
+    let wait = |timeout: Option<Duration>, last_err: &mut Option<io::Error>| {
+        let result = match timeout {
+            Some(timeout) => receiver.recv_timeout(timeout).ok(),
+            None => receiver.recv().ok(),
+        };
+
+        match result {
+            Some((addr, Ok(stream))) => {
+                trace!("Connected to {} first", addr);
+                Some(stream)
+            }
+            Some((addr, Err(err))) => {
+                debug!("Could not connect to {}: {}", addr, err);
+                *last_err = Some(err);
+                None
+            }
+            None => None,
+        }
+    };
+
+    for addr in interleave_families(addrs) {
+        let sender = sender.clone();
+
+        thread::Builder::new()
+            .name("connect".to_string())
+            .spawn(move || {
+                // the receiver is gone if another attempt has won already
+                let _ = sender.send((addr, TcpStream::connect(addr)));
+            })?;
+
+        attempts += 1;
+
+        if let Some(stream) = wait(Some(CONNECTION_ATTEMPT_DELAY), &mut last_err) {
+            return Ok(stream);
+        }
+    }
+
+    drop(sender);
+
+    // attempts which failed early have been received already such that the
+    // channel may disconnect before all of them are counted
+    for _ in 0..attempts {
+        if let Some(stream) = wait(None, &mut last_err) {
+            return Ok(stream);
+        }
+    }
+
+    Err(last_err
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No address to connect to")))
+}
+
+/// Orders `addrs` such that IPv6 and IPv4 addresses alternate, starting with
+/// the family of the first address.
+fn interleave_families(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let first_is_ipv6 = addrs.first().is_some_and(SocketAddr::is_ipv6);
+    let (mut first, mut second): (Vec<_>, Vec<_>) = addrs
+        .iter()
+        .partition(|addr| addr.is_ipv6() == first_is_ipv6);
+
+    first.reverse();
+    second.reverse();
+
+    let mut interleaved = Vec::with_capacity(addrs.len());
+
+    while let Some(addr) = first.pop() {
+        interleaved.push(addr);
+        interleaved.extend(second.pop());
+    }
+
+    interleaved.extend(second.into_iter().rev());
+    interleaved
+}
+
+/// Binds to `addr` or, if its port is in use, to one of the following
+/// `ports` ports.
+///
+/// The first free port is taken such that several peers can be started on
+/// the same host with the same configuration. Use [`TcpListener::local_addr`]
+/// to obtain the address which has actually been bound.
+///
+/// [`TcpListener::local_addr`]:
+/// ../../std/net/struct.TcpListener.html#method.local_addr
+pub fn bind_range(addr: SocketAddr, ports: u16) -> io::Result<TcpListener> {
+    let mut result = TcpListener::bind(addr);
+
+    for offset in 1..=ports {
+        match result {
+            Err(ref err) if err.kind() == io::ErrorKind::AddrInUse => {}
+            _ => break,
+        }
+
+        let port = match addr.port().checked_add(offset) {
+            Some(port) => port,
+            None => break,
+        };
+
+        debug!("Address {} is in use, trying port {}", addr, port);
+
+        let mut next_addr = addr;
+        next_addr.set_port(port);
+
+        result = TcpListener::bind(next_addr);
+    }
+
+    result
 }
 
 /// A trait to handle incoming requests from a [`Server`].
@@ -164,23 +1412,29 @@ pub trait ServerHandler {
     /// Handles an incomming connection.
     ///
     /// Depending on the `result` this either calls [`handle_error`] or
-    /// creates a new [`Connection`] from the given [`TcpStream`] and
-    /// calls [`handle_connection`].
+    /// creates a new [`Connection`] from the given [`TcpStream`] which
+    /// exchanges messages in frames of `framing` and calls
+    /// [`handle_connection`]. If the handshake of `framing` fails, the
+    /// connection is dropped and [`handle_error`] is called instead.
     ///
     /// [`handle_error`]: #tymethod.handle_error
     /// [`Connection`]: struct.Connection.html
     /// [`TcpStream`]: ../../std/net/struct.TcpStream.html
     /// [`handle_connection`]: #tymethod.handle_connection
-    fn handle_incoming(&self, result: io::Result<TcpStream>) {
+    fn handle_incoming(&self, result: io::Result<TcpStream>, framing: &Arc<dyn Framing>) {
         match result {
-            Ok(stream) => {
+            Ok(mut stream) => {
                 trace!(
                     "Handling incoming connection from {}",
                     stream.peer_addr().unwrap()
                 );
 
+                if let Err(error) = framing.accept(&mut stream) {
+                    return self.handle_error(error);
+                }
+
                 // TODO handle timeouts
-                let connection = Connection::from_stream(stream);
+                let connection = Connection::from_stream(stream).with_framing(Arc::clone(framing));
 
                 self.handle_connection(connection)
             }
@@ -212,6 +1466,8 @@ pub trait ServerHandler {
 /// ```
 pub struct Server<T> {
     handler: Arc<T>,
+    framing: Arc<dyn Framing>,
+    socket_options: SocketOptions,
 }
 
 impl<T: ServerHandler + Send + Sync + 'static> Server<T> {
@@ -226,9 +1482,24 @@ impl<T: ServerHandler + Send + Sync + 'static> Server<T> {
     pub fn new(handler: T) -> Self {
         Self {
             handler: Arc::new(handler),
+            framing: framing::plain(),
+            socket_options: SocketOptions::default(),
         }
     }
 
+    /// Exchanges the messages of all accepted connections in frames of
+    /// `framing`.
+    pub fn with_framing(mut self, framing: Arc<dyn Framing>) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Applies `socket_options` to all accepted connections.
+    pub fn with_socket_options(mut self, socket_options: SocketOptions) -> Self {
+        self.socket_options = socket_options;
+        self
+    }
+
     /// Listens on the given socket address.
     ///
     /// `num_workers` defines the number of worker threads which handle
@@ -238,21 +1509,206 @@ impl<T: ServerHandler + Send + Sync + 'static> Server<T> {
         addr: A,
         num_workers: usize,
     ) -> io::Result<thread::JoinHandle<()>> {
-        let listener = TcpListener::bind(addr)?;
+        self.listen_on(TcpListener::bind(addr)?, num_workers)
+    }
 
-        trace!("Server listening on address {}", listener.local_addr()?);
+    /// Accepts connections from a listener which has been bound before, for
+    /// example with [`bind_range`].
+    ///
+    /// `num_workers` defines the number of worker threads which handle
+    /// incoming requests in parallel.
+    ///
+    /// [`bind_range`]: fn.bind_range.html
+    pub fn listen_on(
+        self,
+        listener: TcpListener,
+        num_workers: usize,
+    ) -> io::Result<thread::JoinHandle<()>> {
+        self.listen_pooled(listener, ThreadPool::new("worker", num_workers))
+    }
 
-        let handle = thread::spawn(move || {
-            let pool = ThreadPool::new(num_workers);
+    /// Accepts connections from a listener and handles them on the workers
+    /// of `pool`.
+    ///
+    /// The pool may be shared with other servers, e.g. those of several
+    /// nodes in the same process. Make sure it has enough workers for all of
+    /// them since connections which wait for each other could otherwise
+    /// block forever.
+    pub fn listen_pooled(
+        self,
+        listener: TcpListener,
+        pool: ThreadPool,
+    ) -> io::Result<thread::JoinHandle<()>> {
+        let local_addr = listener.local_addr()?;
+        trace!("Server listening on address {}", local_addr);
 
-            for result in listener.incoming() {
-                let handler = Arc::clone(&self.handler);
-                pool.execute(move || {
-                    handler.handle_incoming(result);
-                });
-            }
-        });
+        let handle = thread::Builder::new()
+            .name("listener".to_string())
+            .spawn(move || {
+                for result in listener.incoming() {
+                    let handler = Arc::clone(&self.handler);
+                    let framing = Arc::clone(&self.framing);
+                    let socket_options = self.socket_options;
+                    pool.execute(move || {
+                        set_thread_context(Some(local_addr.to_string()));
+                        let result = result.and_then(|stream| {
+                            socket_options.apply(&stream)?;
+                            Ok(stream)
+                        });
+                        handler.handle_incoming(result, &framing);
+                    });
+                }
+            })?;
 
         Ok(handle)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thread_pool_replaces_panicked_worker() {
+        let pool = ThreadPool::new("test-worker", 1);
+        let (sender, receiver) = mpsc::channel();
+
+        pool.execute(|| panic!("worker panic"));
+        pool.execute(move || {
+            let name = thread::current().name().map(String::from);
+            sender.send(name).unwrap();
+        });
+
+        let name = receiver
+            .recv_timeout(Duration::from_secs(1))
+            .expect("panicked worker has not been replaced");
+        assert_eq!(Some("test-worker-0".to_string()), name);
+    }
+
+    #[test]
+    fn thread_pool_stats() {
+        let pool = ThreadPool::new("test-stats", 2);
+        let (sender, receiver) = mpsc::channel();
+
+        for _ in 0..4 {
+            let sender = sender.clone();
+            pool.execute(move || sender.send(()).unwrap());
+        }
+
+        for _ in 0..4 {
+            receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        }
+
+        // the last job is only counted after it has returned
+        thread::sleep(Duration::from_millis(50));
+
+        let stats = pool.stats();
+        assert_eq!("test-stats", stats.name);
+        assert_eq!(2, stats.workers);
+        assert_eq!(0, stats.queued);
+        assert_eq!(4, stats.executed);
+        assert_eq!(4, stats.wait.count);
+        assert_eq!(4, stats.duration.count);
+        assert_eq!(0.0, stats.utilization());
+    }
+
+    #[test]
+    fn thread_pool_autoscales() {
+        let pool = ThreadPool::with_limits("test-scaling", 1, 3, Duration::from_millis(100));
+        let (release, released) = mpsc::channel::<()>();
+        let released = Arc::new(Mutex::new(released));
+        let (sender, receiver) = mpsc::channel();
+
+        for _ in 0..5 {
+            let released = Arc::clone(&released);
+            let sender = sender.clone();
+            pool.execute(move || {
+                sender.send(()).unwrap();
+                released.lock_or_recover().recv().unwrap();
+            });
+        }
+
+        for _ in 0..3 {
+            receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        }
+
+        let stats = pool.stats();
+        assert_eq!(3, stats.workers);
+        assert_eq!(3, stats.busy);
+        assert_eq!(2, stats.queued);
+        assert_eq!(1.0, stats.utilization());
+
+        for _ in 0..5 {
+            release.send(()).unwrap();
+        }
+
+        for _ in 0..2 {
+            receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        }
+
+        thread::sleep(Duration::from_millis(500));
+
+        let stats = pool.stats();
+        assert_eq!(1, stats.workers);
+        assert_eq!(0, stats.busy);
+        assert_eq!(5, stats.executed);
+    }
+
+    #[test]
+    fn socket_options_apply() {
+        let listener = TcpListener::bind("127.0.7.8:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        SocketOptions::default().apply(&stream).unwrap();
+        assert!(stream.nodelay().unwrap());
+
+        let options = SocketOptions {
+            nodelay: false,
+            keepalive: Some(Duration::from_secs(60)),
+            send_buffer_size: Some(64 * 1024),
+            recv_buffer_size: Some(64 * 1024),
+        };
+        options.apply(&stream).unwrap();
+
+        let socket = SockRef::from(&stream);
+        assert!(!stream.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        assert!(socket.send_buffer_size().unwrap() >= 64 * 1024);
+        assert!(socket.recv_buffer_size().unwrap() >= 64 * 1024);
+    }
+
+    #[test]
+    fn interleave_families_alternates() {
+        let addrs: Vec<SocketAddr> = [
+            "[::1]:1",
+            "[::1]:2",
+            "[::1]:3",
+            "127.0.0.1:4",
+            "127.0.0.1:5",
+        ]
+        .iter()
+        .map(|addr| addr.parse().unwrap())
+        .collect();
+
+        let ports: Vec<u16> = interleave_families(&addrs)
+            .iter()
+            .map(SocketAddr::port)
+            .collect();
+
+        assert_eq!(vec![1, 4, 2, 5, 3], ports);
+    }
+
+    #[test]
+    fn connect_parallel_skips_failed_address() {
+        let listener = TcpListener::bind("127.0.7.1:0").unwrap();
+        let closed = TcpListener::bind("127.0.7.2:0").unwrap();
+        let closed_addr = closed.local_addr().unwrap();
+        drop(closed);
+
+        let stream = connect_parallel(&[closed_addr, listener.local_addr().unwrap()]).unwrap();
+        assert_eq!(listener.local_addr().unwrap(), stream.peer_addr().unwrap());
+
+        assert!(connect_parallel(&[closed_addr]).is_err());
+        assert!(connect_parallel(&[]).is_err());
+    }
+}
@@ -0,0 +1,907 @@
+//! Operation of a complete peer
+//!
+//! [`Node::start`] launches the servers for the api and peer-to-peer
+//! interfaces as well as the stabilization in the background and returns a
+//! handle to the running peer. The [`run`] function starts a node and blocks
+//! until it terminates.
+//!
+//! A process may host several independent nodes. Those started with
+//! [`Node::start_in`] handle their connections on the worker threads of a
+//! shared [`Runtime`] and [`run_all`] starts one node per configuration.
+//!
+//! A node with a [`weight`] greater than one additionally registers virtual
+//! peers on the following ports which only serve peer-to-peer messages. They
+//! share the telemetry and the read-only mode of the node. Since they share
+//! its ip address as well, this requires the `ip-port` identifier scheme.
+//!
+//! A node with a [`state_file`] caches its neighbours there and rejoins the
+//! network through them after a restart, see the [`state`] module.
+//!
+//! A node with a [`hot_key_threshold`] pushes copies of its most requested
+//! values to the peers requesting them and answers reads from the copies
+//! pushed by other peers, see the [`cache`] module.
+//!
+//! Outbound peer-to-peer connections are tunneled through the [`proxy`] if
+//! one is configured. All peer-to-peer messages are exchanged in frames of the
+//! configured [`framing`].
+//!
+//! If a configured port is in use, the servers bind to one of the following
+//! [`port_range`] ports instead. The peer announces the address it has
+//! actually bound as its identity.
+//!
+//! The periodic maintenance of a node runs in a [`Scheduler`] with an
+//! interval per task: the stabilization of the routing table, the delivery of
+//! hinted values, the removal of expired records and saving the state file.
+//! The tasks are supervised. If a stabilization round panics, the poisoned
+//! locks are cleared and the stabilization continues with the next round.
+//! Every restart is logged and counted in the [`Stats`] of the node.
+//!
+//! After each round, the peer looks up its own identifier through a random
+//! finger. If the lookup does not resolve back to the peer, the ring is
+//! flagged as inconsistent in its [`Metrics`] and the following rounds run
+//! more often until the lookup succeeds again.
+//!
+//! Random decisions like the jitter of the stabilization passes are drawn
+//! from the [`seed`] if one is configured such that tests are reproducible.
+//!
+//! [`Node::start`]: struct.Node.html#method.start
+//! [`run`]: fn.run.html
+//! [`Node::start_in`]: struct.Node.html#method.start_in
+//! [`Runtime`]: struct.Runtime.html
+//! [`run_all`]: fn.run_all.html
+//! [`weight`]: ../config/struct.Config.html#structfield.weight
+//! [`port_range`]: ../config/struct.Config.html#structfield.port_range
+//! [`state_file`]: ../config/struct.Config.html#structfield.state_file
+//! [`state`]: ../state/index.html
+//! [`hot_key_threshold`]: ../config/struct.Config.html#structfield.hot_key_threshold
+//! [`cache`]: ../cache/index.html
+//! [`proxy`]: ../config/struct.Config.html#structfield.proxy
+//! [`framing`]: ../config/struct.Config.html#structfield.framing
+//! [`Scheduler`]: ../scheduler/struct.Scheduler.html
+//! [`Stats`]: ../metrics/struct.Stats.html
+//! [`Metrics`]: ../metrics/struct.Metrics.html
+//! [`seed`]: ../config/struct.Config.html#structfield.seed
+
+use crate::audit::AuditLog;
+use crate::cache::{Cache, HotKeys};
+use crate::config::Config;
+use crate::handler::{ApiHandler, P2PHandler, WebSocketHandler};
+use crate::handoff::Handoff;
+use crate::lock::InstanceLock;
+use crate::metrics::{FingerStats, Metrics, PoolStats, Stats};
+use crate::network::{self, Multiplexer, Server, ThreadPool};
+use crate::procedures::Procedures;
+use crate::random::{self, Random};
+use crate::routing::identifier::Identify;
+use crate::routing::peer::PeerInfo;
+use crate::routing::Routing;
+use crate::scheduler::{Scheduler, Task};
+use crate::stabilization::{Bootstrap, Stabilization};
+use crate::state::NodeState;
+use crate::storage::Storage;
+use crate::sync::MutexExt;
+use crate::Result;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Time in milliseconds a cached neighbour gets to reply before the next one
+/// is tried
+const REJOIN_PROBE_TIMEOUT: u64 = 2000;
+
+/// Number of stabilization rounds after which a routing entry which has not
+/// been verified again is stale
+const STALE_ROUNDS: u64 = 3;
+
+/// Factor by which the stabilization interval is shortened while lookups of
+/// the own identifier do not resolve back to the peer
+const STABILIZATION_ACCELERATION: u32 = 4;
+
+/// Worker threads which can be shared between several nodes
+///
+/// Every node started with the same runtime handles the connections of its
+/// peer-to-peer, api and WebSocket servers on the respective pool such that
+/// the number of threads does not grow with the number of nodes.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chord::config::Config;
+/// # use chord::{Node, Runtime};
+/// #
+/// let first = Config::load_from_file("first.ini").expect("invalid config");
+/// let second = Config::load_from_file("second.ini").expect("invalid config");
+///
+/// let runtime = Runtime::new(8);
+/// let node = Node::start_in(first, None, &runtime).expect("could not start node");
+/// Node::start_in(second, Some(node.listen_address()), &runtime)
+///     .expect("could not start node");
+/// ```
+#[derive(Clone)]
+pub struct Runtime {
+    p2p: ThreadPool,
+    p2p_data: ThreadPool,
+    api: ThreadPool,
+    websocket: ThreadPool,
+}
+
+impl Runtime {
+    /// Creates separate pools of `num_workers` threads for the peer-to-peer,
+    /// api and WebSocket servers.
+    ///
+    /// The pools are separate since api requests wait for the peer-to-peer
+    /// messages they cause. Each pool should have at least as many workers as
+    /// concurrent connections are expected for all nodes together.
+    ///
+    /// Peer-to-peer storage requests get another pool of `num_workers`
+    /// threads such that bulk transfers cannot occupy the workers which
+    /// maintain the ring.
+    pub fn new(num_workers: usize) -> Self {
+        Self::autoscaling(num_workers, num_workers)
+    }
+
+    /// Creates the same pools as [`new`] with `min_workers` threads each,
+    /// which grow up to `max_workers` threads while requests are queued.
+    ///
+    /// [`new`]: #method.new
+    pub fn autoscaling(min_workers: usize, max_workers: usize) -> Self {
+        let pool = |name| ThreadPool::autoscaling(name, min_workers, max_workers);
+
+        Runtime {
+            p2p: pool("p2p-worker"),
+            p2p_data: pool("p2p-data-worker"),
+            api: pool("api-worker"),
+            websocket: pool("websocket-worker"),
+        }
+    }
+
+    /// Creates the pools for the `worker_threads` of `config`, which grow up
+    /// to its `max_worker_threads` if set.
+    pub fn for_config(config: &Config) -> Self {
+        Self::autoscaling(config.worker_threads, max_worker_threads(config))
+    }
+
+    /// Creates the pools for all nodes of `configs` together, which have as
+    /// many workers as the pools of the single nodes combined.
+    pub fn for_configs(configs: &[Config]) -> Self {
+        let min_workers = configs.iter().map(|config| config.worker_threads).sum();
+        let max_workers = configs.iter().map(max_worker_threads).sum();
+
+        Self::autoscaling(min_workers, max_workers)
+    }
+
+    /// Returns the utilization of the pools of the peer-to-peer, data, api
+    /// and WebSocket servers.
+    pub fn stats(&self) -> Vec<PoolStats> {
+        [&self.p2p, &self.p2p_data, &self.api, &self.websocket]
+            .iter()
+            .map(|pool| pool.stats())
+            .collect()
+    }
+}
+
+fn max_worker_threads(config: &Config) -> usize {
+    config
+        .max_worker_threads
+        .unwrap_or(config.worker_threads)
+        .max(config.worker_threads)
+}
+
+/// Handle to a running peer
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chord::config::Config;
+/// # use chord::Node;
+/// #
+/// let config = Config::load_from_file("config.ini").expect("invalid config");
+/// let node = Node::start(config, None).expect("could not start node");
+///
+/// let snapshot = node.stats().snapshot();
+/// println!("received {} messages", snapshot.received());
+///
+/// node.join();
+/// ```
+pub struct Node {
+    listen_address: SocketAddr,
+    api_address: SocketAddr,
+    routing: Arc<Mutex<Routing<PeerInfo>>>,
+    metrics: Arc<Metrics>,
+    runtime: Runtime,
+    // released when the node is dropped
+    _lock: Option<InstanceLock>,
+    handles: Vec<(&'static str, JoinHandle<()>)>,
+    drained: Receiver<()>,
+}
+
+impl Node {
+    /// Joins the network via `bootstrap` or creates a new network and starts
+    /// all servers and the stabilization in background threads.
+    ///
+    /// The api server only starts listening after the first stabilization
+    /// round such that requests are not routed with an empty finger table.
+    ///
+    /// The identifier scheme of the configuration applies to the whole
+    /// process from now on, such that starting fails if another node of this
+    /// process uses a different scheme, see [`IdentifierScheme::set_global`].
+    ///
+    /// [`IdentifierScheme::set_global`]: routing/identifier/enum.IdentifierScheme.html#method.set_global
+    pub fn start(config: Config, bootstrap: Option<SocketAddr>) -> Result<Node> {
+        let runtime = Runtime::for_config(&config);
+
+        Node::start_in(config, bootstrap, &runtime)
+    }
+
+    /// Starts a node like [`start`] whose servers handle their connections
+    /// on the worker threads of `runtime`.
+    ///
+    /// The `worker_threads` and `max_worker_threads` of the configuration are
+    /// ignored in favor of the size of the runtime.
+    ///
+    /// [`start`]: #method.start
+    pub fn start_in(
+        config: Config,
+        bootstrap: Option<SocketAddr>,
+        runtime: &Runtime,
+    ) -> Result<Node> {
+        network::install_panic_hook();
+        config.identifier_scheme.set_global()?;
+
+        debug!(
+            "The current configuration is as follows.\n\n{:#?}\n",
+            &config
+        );
+
+        // a second node with the same address would claim the same identity,
+        // with a port range the address is only known after binding
+        let mut lock = match config.port_range {
+            0 => lock_address(&config, config.listen_address)?,
+            _ => None,
+        };
+
+        // bind first such that the peer joins with the address it is
+        // actually reachable at
+        let p2p_listener = network::bind_range(config.listen_address, config.port_range)?;
+        let listen_address = p2p_listener.local_addr()?;
+        info!("Listening for peers on {}", listen_address);
+
+        if lock.is_none() {
+            lock = lock_address(&config, listen_address)?;
+        }
+
+        let (mut routing, storage) = rejoin_network(&config, listen_address, bootstrap)?;
+        routing.set_stale_after(stale_after(&config));
+
+        let routing = Arc::new(Mutex::new(routing));
+        let storage = Arc::new(Mutex::new(storage));
+        let metrics = Arc::new(
+            Metrics::new().with_slow_threshold(config.slow_threshold.map(Duration::from_millis)),
+        );
+        let handoff = Arc::new(
+            Handoff::new(config.timeout)
+                .with_proxy(config.proxy)
+                .with_framing(Arc::clone(&config.framing))
+                .with_socket_options(config.socket_options),
+        );
+        let multiplexer = Arc::new(
+            Multiplexer::new(Arc::clone(&metrics))
+                .with_proxy(config.proxy)
+                .with_framing(Arc::clone(&config.framing))
+                .with_socket_options(config.socket_options),
+        );
+        let read_only = Arc::new(AtomicBool::new(config.read_only));
+        let audit_log = match config.audit_log {
+            Some(ref path) => Some(Arc::new(
+                AuditLog::open(path)?
+                    .with_rotation(config.audit_log_max_size, config.audit_log_files),
+            )),
+            None => None,
+        };
+        let random = random::from_seed(config.seed);
+        let (drain_notifier, drained) = mpsc::channel();
+        let hot_keys = config.hot_key_threshold.map(|threshold| {
+            Arc::new(
+                HotKeys::new(threshold, config.timeout)
+                    .with_proxy(config.proxy)
+                    .with_framing(Arc::clone(&config.framing))
+                    .with_socket_options(config.socket_options),
+            )
+        });
+        let cache = hot_keys.as_ref().map(|_| Arc::new(Cache::new()));
+
+        let mut handles = Vec::new();
+
+        let p2p_handler = P2PHandler::with_metrics(
+            Arc::clone(&routing),
+            Arc::clone(&storage),
+            config.timeout,
+            Arc::clone(&metrics),
+        )
+        .with_read_only(Arc::clone(&read_only))
+        .with_rate_limit(config.rate_limit)
+        .with_authorizer(Arc::clone(&config.authorizer))
+        .with_audit_log(audit_log.clone())
+        .with_policies(Arc::clone(&config.policies))
+        .with_hot_keys(hot_keys.clone())
+        .with_cache(cache.clone())
+        .with_data_lane(runtime.p2p_data.clone());
+        let p2p_server = Server::new(p2p_handler)
+            .with_framing(Arc::clone(&config.framing))
+            .with_socket_options(config.socket_options);
+        handles.push((
+            "p2p handler",
+            p2p_server.listen_pooled(p2p_listener, runtime.p2p.clone())?,
+        ));
+
+        // the virtual peers join through this node such that they end up in
+        // the same network
+        for virtual_address in config.virtual_addresses() {
+            let p2p_listener = network::bind_range(virtual_address, config.port_range)?;
+            let virtual_address = p2p_listener.local_addr()?;
+            info!("Registering virtual peer {}", virtual_address);
+
+            let bootstrap = bootstrap.unwrap_or(listen_address);
+            let (mut routing, storage) = join_network(&config, virtual_address, Some(bootstrap))?;
+            routing.set_stale_after(stale_after(&config));
+            let routing = Arc::new(Mutex::new(routing));
+
+            let p2p_handler = P2PHandler::with_metrics(
+                Arc::clone(&routing),
+                Arc::new(Mutex::new(storage)),
+                config.timeout,
+                Arc::clone(&metrics),
+            )
+            .with_read_only(Arc::clone(&read_only))
+            .with_rate_limit(config.rate_limit)
+            .with_authorizer(Arc::clone(&config.authorizer))
+            .with_audit_log(audit_log.clone())
+            .with_policies(Arc::clone(&config.policies))
+            .with_hot_keys(hot_keys.clone())
+            .with_cache(cache.clone())
+            .with_data_lane(runtime.p2p_data.clone());
+            let p2p_server = Server::new(p2p_handler)
+                .with_framing(Arc::clone(&config.framing))
+                .with_socket_options(config.socket_options);
+            handles.push((
+                "virtual p2p handler",
+                p2p_server.listen_pooled(p2p_listener, runtime.p2p.clone())?,
+            ));
+
+            let mut stabilization =
+                Stabilization::new(routing, Arc::clone(&metrics), config.timeout)
+                    .with_multiplexer(Arc::clone(&multiplexer))
+                    .with_proxy(config.proxy)
+                    .with_framing(Arc::clone(&config.framing))
+                    .with_socket_options(config.socket_options);
+            stabilize_once(&mut stabilization);
+            handles.push((
+                "virtual stabilization",
+                stabilize_periodically(
+                    stabilization,
+                    None,
+                    virtual_address,
+                    Arc::clone(&metrics),
+                    Arc::clone(&random),
+                    &config,
+                )?,
+            ));
+        }
+
+        // only serve api requests once the routing table has been stabilized, otherwise early
+        // requests would be routed with our own address in all fingers
+        let mut stabilization =
+            Stabilization::new(Arc::clone(&routing), Arc::clone(&metrics), config.timeout)
+                .with_multiplexer(Arc::clone(&multiplexer))
+                .with_proxy(config.proxy)
+                .with_framing(Arc::clone(&config.framing))
+                .with_socket_options(config.socket_options);
+        stabilize_once(&mut stabilization);
+
+        if let Some(ref state_file) = config.state_file {
+            save_state(&routing, state_file);
+        }
+
+        let api_handler = ApiHandler::new(
+            Arc::clone(&routing),
+            Arc::clone(&storage),
+            Arc::clone(&handoff),
+            Arc::clone(&metrics),
+            config.timeout,
+        )
+        .with_multiplexer(Arc::clone(&multiplexer))
+        .with_proxy(config.proxy)
+        .with_framing(Arc::clone(&config.framing))
+        .with_socket_options(config.socket_options)
+        .with_read_only(Arc::clone(&read_only))
+        .with_drain_notifier(drain_notifier)
+        .with_system_namespace(config.system_namespace)
+        .with_cbor(config.api_cbor)
+        .with_weight(config.weight)
+        .with_cache(cache)
+        .with_tenants(Arc::clone(&config.tenants))
+        .with_authorizer(Arc::clone(&config.authorizer))
+        .with_audit_log(audit_log.clone());
+        let api_listener = network::bind_range(config.api_address, config.port_range)?;
+        let api_address = api_listener.local_addr()?;
+        info!("Listening for api requests on {}", api_address);
+
+        let api_server = Server::new(api_handler).with_socket_options(config.socket_options);
+        handles.push((
+            "api handler",
+            api_server.listen_pooled(api_listener, runtime.api.clone())?,
+        ));
+
+        if let Some(websocket_address) = config.websocket_address {
+            let websocket_listener = network::bind_range(websocket_address, config.port_range)?;
+            info!(
+                "Listening for WebSocket connections on {}",
+                websocket_listener.local_addr()?
+            );
+
+            let websocket_handler = WebSocketHandler::new(api_address, config.timeout);
+            let websocket_server =
+                Server::new(websocket_handler).with_socket_options(config.socket_options);
+
+            handles.push((
+                "WebSocket handler",
+                websocket_server.listen_pooled(websocket_listener, runtime.websocket.clone())?,
+            ));
+        }
+
+        handles.push((
+            "maintenance",
+            stabilize_periodically(
+                stabilization,
+                Some(PeerState {
+                    handoff,
+                    routing: Arc::clone(&routing),
+                    storage,
+                }),
+                listen_address,
+                Arc::clone(&metrics),
+                random,
+                &config,
+            )?,
+        ));
+
+        Ok(Node {
+            listen_address,
+            api_address,
+            routing,
+            metrics,
+            runtime: runtime.clone(),
+            _lock: lock,
+            handles,
+            drained,
+        })
+    }
+
+    /// Returns the address this node has bound for peer-to-peer messages.
+    ///
+    /// This is the identity of the node in the network and differs from the
+    /// configured address if that was in use.
+    pub fn listen_address(&self) -> SocketAddr {
+        self.listen_address
+    }
+
+    /// Returns the address this node has bound for api requests.
+    pub fn api_address(&self) -> SocketAddr {
+        self.api_address
+    }
+
+    /// Returns the counters of this node.
+    ///
+    /// Call [`Stats::snapshot`] to read all counters at once.
+    ///
+    /// [`Stats::snapshot`]: metrics/struct.Stats.html#method.snapshot
+    pub fn stats(&self) -> &Stats {
+        self.metrics.stats()
+    }
+
+    /// Returns the telemetry shared between all components of this node.
+    pub fn metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Returns whether the last lookup of the own identifier of this node or
+    /// one of its virtual peers resolved back to it.
+    pub fn is_ring_consistent(&self) -> bool {
+        self.metrics.is_ring_consistent()
+    }
+
+    /// Returns how often the lookups started at this node were routed
+    /// through each of the fingers of its peers.
+    ///
+    /// See [`FingerStats`] for details.
+    ///
+    /// [`FingerStats`]: metrics/struct.FingerStats.html
+    pub fn finger_stats(&self) -> Vec<FingerStats> {
+        self.metrics.finger_stats()
+    }
+
+    /// Returns the utilization of the worker pools serving this node.
+    ///
+    /// Nodes started in the same [`Runtime`] share its pools and report the
+    /// same numbers.
+    ///
+    /// [`Runtime`]: struct.Runtime.html
+    pub fn pool_stats(&self) -> Vec<PoolStats> {
+        self.runtime.stats()
+    }
+
+    /// Returns the routing table of this node.
+    pub fn routing(&self) -> Arc<Mutex<Routing<PeerInfo>>> {
+        Arc::clone(&self.routing)
+    }
+
+    /// Blocks until this node has been drained or all background threads
+    /// have terminated.
+    ///
+    /// The threads of a drained node keep running until the process exits.
+    pub fn join(self) {
+        while self.handles.iter().any(|(_, handle)| !handle.is_finished()) {
+            match self.drained.recv_timeout(Duration::from_secs(1)) {
+                Ok(()) => {
+                    info!("Node has been drained");
+
+                    return;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(Duration::from_secs(1)),
+            }
+        }
+
+        for (name, handle) in self.handles {
+            if let Err(err) = handle.join() {
+                error!("Error joining {}:\n\n{:?}", name, err);
+            }
+        }
+    }
+}
+
+/// Locks `listen_address` in the `lock_dir` of `config` if it is set.
+fn lock_address(config: &Config, listen_address: SocketAddr) -> Result<Option<InstanceLock>> {
+    config
+        .lock_dir
+        .as_ref()
+        .map(|lock_dir| InstanceLock::acquire(lock_dir, listen_address))
+        .transpose()
+}
+
+/// Joins the network at `listen_address` through the first neighbour cached in
+/// the state file which still replies and falls back to [`join_network`]
+/// otherwise.
+///
+/// [`join_network`]: fn.join_network.html
+fn rejoin_network(
+    config: &Config,
+    listen_address: SocketAddr,
+    bootstrap: Option<SocketAddr>,
+) -> Result<(Routing<PeerInfo>, Storage)> {
+    let neighbours = match config.state_file {
+        Some(ref state_file) if state_file.exists() => {
+            match NodeState::load_from_file(state_file) {
+                Ok(state) => state.neighbours(),
+                Err(err) => {
+                    warn!("Ignoring state file {}: {}", state_file.display(), err);
+
+                    Vec::new()
+                }
+            }
+        }
+        _ => Vec::new(),
+    };
+
+    // neighbours which are gone are skipped without waiting for a whole join
+    let procedures = Procedures::new(config.timeout.min(REJOIN_PROBE_TIMEOUT))
+        .with_proxy(config.proxy)
+        .with_framing(Arc::clone(&config.framing))
+        .with_socket_options(config.socket_options);
+
+    for neighbour in neighbours {
+        match procedures.find_peer(listen_address.identifier(), neighbour) {
+            Ok(successor) if successor == listen_address => {
+                debug!("Cached neighbour {} still routes to this peer", neighbour);
+
+                continue;
+            }
+            Ok(_) => {}
+            Err(err) => {
+                debug!("Cached neighbour {} is unreachable: {}", neighbour, err);
+
+                continue;
+            }
+        }
+
+        info!("Rejoining via cached neighbour {}", neighbour);
+
+        let bootstrap = Bootstrap::new(listen_address, neighbour, config.fingers)
+            .with_proxy(config.proxy)
+            .with_framing(Arc::clone(&config.framing))
+            .with_socket_options(config.socket_options);
+
+        match bootstrap.bootstrap(config.timeout) {
+            Ok(joined) => return Ok(joined),
+            Err(err) => warn!("Could not rejoin via {}: {}", neighbour, err),
+        }
+    }
+
+    join_network(config, listen_address, bootstrap)
+}
+
+/// Joins the network at `listen_address` via `bootstrap` or creates a new
+/// network if no bootstrap peer is given.
+///
+/// The join is retried as configured by `bootstrap_attempts` and
+/// `bootstrap_backoff`. If it fails nevertheless, a new network is created
+/// if `bootstrap_fallback` is set.
+fn join_network(
+    config: &Config,
+    listen_address: SocketAddr,
+    bootstrap: Option<SocketAddr>,
+) -> Result<(Routing<PeerInfo>, Storage)> {
+    let bootstrap_address = match bootstrap {
+        Some(bootstrap_address) => bootstrap_address,
+        None => {
+            info!("No bootstrapping peer provided, creating new network");
+
+            return Ok(create_network(config, listen_address));
+        }
+    };
+
+    info!("Connecting to bootstrap peer {}", bootstrap_address);
+
+    let bootstrap = Bootstrap::new(listen_address, bootstrap_address, config.fingers)
+        .with_retry(
+            config.bootstrap_attempts,
+            Duration::from_millis(config.bootstrap_backoff),
+        )
+        .with_proxy(config.proxy)
+        .with_framing(Arc::clone(&config.framing))
+        .with_socket_options(config.socket_options);
+
+    match bootstrap.bootstrap(config.timeout) {
+        Ok(joined) => Ok(joined),
+        Err(err) if config.bootstrap_fallback => {
+            warn!("{}", err);
+            warn!("Creating a new network instead");
+
+            Ok(create_network(config, listen_address))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Creates a new network in which the peer at `listen_address` is alone.
+fn create_network(config: &Config, listen_address: SocketAddr) -> (Routing<PeerInfo>, Storage) {
+    let finger_table = vec![listen_address; config.fingers];
+    let routing = Routing::from_addrs(listen_address, listen_address, listen_address, finger_table);
+
+    (routing, Storage::new())
+}
+
+/// Returns the time after which a routing entry which has not been verified
+/// again is stale.
+fn stale_after(config: &Config) -> Duration {
+    Duration::from_secs(STALE_ROUNDS * config.stabilization_interval)
+}
+
+/// Saves the neighbours in `routing` to `state_file`.
+fn save_state(routing: &Mutex<Routing<PeerInfo>>, state_file: &Path) {
+    let state = NodeState::from_routing(&routing.lock_or_recover());
+
+    if let Err(err) = state.save_to_file(state_file) {
+        warn!("Could not save state to {}: {}", state_file.display(), err);
+    }
+}
+
+fn stabilize_once(stabilization: &mut Stabilization) {
+    if let Err(err) = stabilization.stabilize() {
+        error!("{}", err);
+    }
+}
+
+/// Stabilizes the routing table of a peer and runs more often while the
+/// lookup of its own identifier does not resolve back to it
+struct StabilizationTask {
+    stabilization: Stabilization,
+    random: Arc<dyn Random>,
+    metrics: Arc<Metrics>,
+    listen_address: SocketAddr,
+    consistent: bool,
+}
+
+impl Task for StabilizationTask {
+    fn run(&mut self) -> Result<()> {
+        let result = self.stabilization.stabilize();
+
+        let consistent = self.stabilization.check_self_lookup(self.random.as_ref());
+        if self.consistent && !consistent {
+            warn!("Accelerating stabilization of {}", self.listen_address);
+        }
+        self.consistent = consistent;
+
+        Ok(result?)
+    }
+
+    fn period(&self, interval: Duration) -> Duration {
+        if self.consistent {
+            interval
+        } else {
+            interval / STABILIZATION_ACCELERATION
+        }
+    }
+
+    fn recover(&mut self) {
+        self.metrics.stats().record_stabilization_restart();
+        self.stabilization.recover();
+    }
+}
+
+/// Delivers the values hinted to unreachable replicas once they are back
+struct HandoffTask(Arc<Handoff>);
+
+impl Task for HandoffTask {
+    fn run(&mut self) -> Result<()> {
+        self.0.deliver();
+
+        Ok(())
+    }
+
+    fn recover(&mut self) {
+        self.0.recover();
+    }
+}
+
+/// The state of a peer which is maintained besides its routing table
+struct PeerState {
+    handoff: Arc<Handoff>,
+    routing: Arc<Mutex<Routing<PeerInfo>>>,
+    storage: Arc<Mutex<Storage>>,
+}
+
+/// Runs `stabilization` in a [`Scheduler`] after the first pass.
+///
+/// The first pass has already been run such that the following passes start
+/// one stabilization interval later. While the lookup of the own identifier
+/// at the end of a pass does not resolve back to the peer, the passes run
+/// `STABILIZATION_ACCELERATION` times as often. A pass which panics is counted
+/// in `metrics` and the poisoned locks are cleared before the next pass.
+///
+/// Unlike its virtual peers, the peer itself additionally gets the values of
+/// its `handoff` delivered every `handoff_interval`, the expired records of
+/// its `storage` removed every `sweep_interval` and its neighbours in
+/// `routing` saved to the state file of `config` after every stabilization
+/// interval.
+///
+/// [`Scheduler`]: scheduler/struct.Scheduler.html
+fn stabilize_periodically(
+    stabilization: Stabilization,
+    peer: Option<PeerState>,
+    listen_address: SocketAddr,
+    metrics: Arc<Metrics>,
+    random: Arc<dyn Random>,
+    config: &Config,
+) -> io::Result<JoinHandle<()>> {
+    let interval = Duration::from_secs(config.stabilization_interval);
+
+    let stabilization = StabilizationTask {
+        stabilization,
+        random: Arc::clone(&random),
+        metrics,
+        listen_address,
+        consistent: true,
+    };
+
+    let mut scheduler = Scheduler::new(random)
+        .with_context(listen_address.to_string())
+        .with_task("stabilization", interval, stabilization);
+
+    if let Some(PeerState {
+        handoff,
+        routing,
+        storage,
+    }) = peer
+    {
+        scheduler = scheduler
+            .with_task(
+                "handoff",
+                Duration::from_secs(config.handoff_interval),
+                HandoffTask(handoff),
+            )
+            .with_task(
+                "sweep",
+                Duration::from_secs(config.sweep_interval),
+                move || {
+                    sweep_expired(&storage);
+                    Ok(())
+                },
+            );
+
+        if let Some(state_file) = config.state_file.clone() {
+            scheduler = scheduler.with_task("state", interval, move || {
+                save_state(&routing, &state_file);
+                Ok(())
+            });
+        }
+    }
+
+    scheduler.spawn()
+}
+
+/// Removes the expired records from `storage`.
+fn sweep_expired(storage: &Mutex<Storage>) {
+    let now = Instant::now();
+    let mut storage = storage.lock_or_recover();
+
+    let before = storage.len();
+    storage.retain(|_, record| !record.is_expired_at(now));
+
+    if storage.len() < before {
+        debug!("Removed {} expired records", before - storage.len());
+    }
+}
+
+/// Starts a node and blocks until it terminates.
+pub fn run(config: Config, bootstrap: Option<SocketAddr>) -> Result<()> {
+    println!("Distributed Hash Table based on CHORD");
+    println!("-------------------------------------\n");
+
+    if let Some(bootstrap_address) = bootstrap {
+        println!("Connecting to bootstrap peer {}...", bootstrap_address);
+    } else {
+        println!("No bootstrapping peer provided, creating new network...");
+    }
+
+    Node::start(config, bootstrap)?.join();
+
+    Ok(())
+}
+
+/// Starts one node for each configuration in a shared [`Runtime`] and blocks
+/// until all of them have terminated.
+///
+/// The first node joins the network via `bootstrap` or creates a new
+/// network. All following nodes join via `bootstrap` or the first node.
+/// Since the nodes share the identifier scheme of the process, all
+/// configurations have to use the same one.
+///
+/// [`Runtime`]: struct.Runtime.html
+pub fn run_all(configs: Vec<Config>, bootstrap: Option<SocketAddr>) -> Result<()> {
+    if let Some(first) = configs.first() {
+        let scheme = first.identifier_scheme;
+
+        if configs
+            .iter()
+            .any(|config| config.identifier_scheme != scheme)
+        {
+            return Err("all nodes of a process have to use the same identifier scheme".into());
+        }
+    }
+
+    println!("Distributed Hash Table based on CHORD");
+    println!("-------------------------------------\n");
+
+    println!("Starting {} nodes...", configs.len());
+
+    let runtime = Runtime::for_configs(&configs);
+
+    let mut nodes: Vec<Node> = Vec::new();
+
+    for config in configs {
+        let bootstrap = bootstrap.or_else(|| nodes.first().map(Node::listen_address));
+        nodes.push(Node::start_in(config, bootstrap, &runtime)?);
+    }
+
+    for node in nodes {
+        node.join();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,232 @@
+//! Limits of the values stored in a namespace
+//!
+//! Tenants sharing a ring keep their keys apart with separate
+//! [`Namespace`]s. A [`Policy`] caps what the values of one namespace may
+//! take up on every peer such that one tenant cannot crowd out the others:
+//!
+//! * `max_ttl` shortens longer times to live
+//! * `max_value_size` rejects larger values
+//! * `quota` rejects values once those of the namespace occupy that many
+//!   bytes on the peer, counting expired values until they are removed
+//! * `replication` rejects replicas with an index of at least this number
+//!
+//! The [`P2PHandler`] of the peer responsible for a key applies the policy
+//! of the longest namespace the key belongs to when it handles
+//! `STORAGE PUT` and `STORAGE BULK PUT`. Rejected values are answered with a
+//! `STORAGE FAILURE` whose reason is [`FailureReason::PolicyViolated`].
+//! Keys outside of all namespaces with a policy are not limited.
+//!
+//! Like the rules of the [`auth`] module, policies should be the same on all
+//! peers of a network since values are handed over between peers when they
+//! join or leave.
+//!
+//! # Examples
+//!
+//! ```
+//! # use chord::dht::Namespace;
+//! # use chord::policy::{Policies, Policy};
+//! #
+//! let tenant = Namespace::from_hex("7465").unwrap();
+//! let policy = Policy {
+//!     max_value_size: Some(1024),
+//!     ..Policy::default()
+//! };
+//! let policies = Policies::new().with_policy(tenant, policy);
+//!
+//! let key = tenant.key("alice");
+//! assert!(policies.policy_for(&key).unwrap().1.check(0, 2048, 0).is_err());
+//! ```
+//!
+//! [`Namespace`]: ../dht/struct.Namespace.html
+//! [`Policy`]: struct.Policy.html
+//! [`P2PHandler`]: ../handler/struct.P2PHandler.html
+//! [`FailureReason::PolicyViolated`]: ../message/p2p/enum.FailureReason.html#variant.PolicyViolated
+//! [`auth`]: ../auth/index.html
+
+use crate::dht::{DhtKey, Namespace};
+use std::fmt;
+use std::time::Duration;
+
+/// Limits of the values in one namespace
+///
+/// Limits which are `None` are not enforced.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Policy {
+    /// Longest time to live of a value
+    pub max_ttl: Option<Duration>,
+    /// Size of the largest value in bytes
+    pub max_value_size: Option<usize>,
+    /// Number of bytes all values of the namespace may occupy on one peer
+    pub quota: Option<u64>,
+    /// Largest number of replicas of a value
+    pub replication: Option<u8>,
+}
+
+/// Why a value is rejected by a policy
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Violation {
+    /// The value exceeds `max_value_size`
+    ValueTooLarge,
+    /// Storing the value would exceed the `quota`
+    QuotaExceeded,
+    /// The replication index is not below `replication`
+    TooManyReplicas,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            Violation::ValueTooLarge => "value too large",
+            Violation::QuotaExceeded => "quota exceeded",
+            Violation::TooManyReplicas => "too many replicas",
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+impl Policy {
+    /// Checks whether a value of `size` bytes may be stored as replica
+    /// `replication_index` while the namespace occupies `used` bytes already.
+    pub fn check(&self, replication_index: u8, size: usize, used: u64) -> Result<(), Violation> {
+        if self.max_value_size.is_some_and(|max| size > max) {
+            return Err(Violation::ValueTooLarge);
+        }
+
+        if self.quota.is_some_and(|quota| used + size as u64 > quota) {
+            return Err(Violation::QuotaExceeded);
+        }
+
+        if self
+            .replication
+            .is_some_and(|replication| replication_index >= replication)
+        {
+            return Err(Violation::TooManyReplicas);
+        }
+
+        Ok(())
+    }
+
+    /// Returns `ttl` shortened to `max_ttl`.
+    pub fn cap_ttl(&self, ttl: Duration) -> Duration {
+        self.max_ttl.map_or(ttl, |max_ttl| ttl.min(max_ttl))
+    }
+
+    /// Returns the number of `replicas` recorded with a value, which is
+    /// capped at `replication`.
+    pub fn cap_replicas(&self, replicas: u8) -> u8 {
+        self.replication
+            .map_or(replicas, |replication| replicas.min(replication))
+    }
+}
+
+/// The policies of all namespaces
+#[derive(Clone, Debug, Default)]
+pub struct Policies {
+    policies: Vec<(Namespace, Policy)>,
+}
+
+impl Policies {
+    /// Creates an empty set of policies which does not limit any key.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `policy` to the keys in `namespace`, replacing an earlier
+    /// policy of the same namespace.
+    pub fn with_policy(mut self, namespace: Namespace, policy: Policy) -> Self {
+        self.policies.retain(|(other, _)| *other != namespace);
+        self.policies.push((namespace, policy));
+        self
+    }
+
+    /// Returns whether no namespace has a policy.
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    /// Returns the longest namespace with a policy which contains `key`
+    /// together with its policy.
+    pub fn policy_for(&self, key: &DhtKey) -> Option<(&Namespace, &Policy)> {
+        self.policies
+            .iter()
+            .filter(|(namespace, _)| namespace.contains(key))
+            .max_by_key(|(namespace, _)| namespace.prefix().len())
+            .map(|(namespace, policy)| (namespace, policy))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn namespace(prefix: &[u8]) -> Namespace {
+        Namespace::new(prefix).unwrap()
+    }
+
+    #[test]
+    fn check() {
+        let policy = Policy {
+            max_ttl: None,
+            max_value_size: Some(10),
+            quota: Some(100),
+            replication: Some(2),
+        };
+
+        assert_eq!(Ok(()), policy.check(1, 10, 90));
+        assert_eq!(Err(Violation::ValueTooLarge), policy.check(0, 11, 0));
+        assert_eq!(Err(Violation::QuotaExceeded), policy.check(0, 10, 91));
+        assert_eq!(Err(Violation::TooManyReplicas), policy.check(2, 1, 0));
+        assert_eq!(Ok(()), Policy::default().check(255, 1 << 20, u64::MAX >> 1));
+    }
+
+    #[test]
+    fn caps() {
+        let policy = Policy {
+            max_ttl: Some(Duration::from_secs(60)),
+            replication: Some(3),
+            ..Policy::default()
+        };
+
+        assert_eq!(
+            Duration::from_secs(60),
+            policy.cap_ttl(Duration::from_secs(600))
+        );
+        assert_eq!(
+            Duration::from_secs(6),
+            policy.cap_ttl(Duration::from_secs(6))
+        );
+        assert_eq!(3, policy.cap_replicas(5));
+        assert_eq!(0, policy.cap_replicas(0));
+    }
+
+    #[test]
+    fn longest_namespace() {
+        let short = Policy {
+            max_value_size: Some(1),
+            ..Policy::default()
+        };
+        let long = Policy {
+            max_value_size: Some(2),
+            ..Policy::default()
+        };
+        let policies = Policies::new()
+            .with_policy(namespace(&[1, 2]), long)
+            .with_policy(namespace(&[1]), short);
+
+        let mut raw_key = [0; 32];
+        raw_key[0] = 1;
+        assert_eq!(
+            Some(&short),
+            policies.policy_for(&DhtKey::from(raw_key)).map(|p| p.1)
+        );
+
+        raw_key[1] = 2;
+        assert_eq!(
+            Some(&long),
+            policies.policy_for(&DhtKey::from(raw_key)).map(|p| p.1)
+        );
+
+        assert!(policies.policy_for(&DhtKey::from([0; 32])).is_none());
+    }
+}
@@ -1,75 +1,484 @@
 //! A collection of procedures used in various places.
 
-use crate::error::MessageError;
-use crate::message::p2p::{PeerFind, PredecessorNotify, StorageGet, StoragePut};
+use crate::deadline::{CancelToken, Deadline};
+use crate::error::{CollisionError, MessageError, PeerError};
+use crate::framing::{self, Framing};
+use crate::load::Load;
+use crate::message::p2p::{
+    FailureReason, JoinLock, JoinPublish, PeerFind, PeerFound, PeerLeave, PeerLoad,
+    PredecessorNotify, StorageBulkPut, StorageCachePush, StorageDelete, StorageGet,
+    StorageGetSuccess,
+};
 use crate::message::Message;
-use crate::network::Connection;
+use crate::metrics::Metrics;
+use crate::network::{Connection, Multiplexer, SocketOptions};
+use crate::proxy::Proxy;
 use crate::routing::identifier::Identifier;
-use crate::storage::Key;
+use crate::storage::{Key, Record, Storage};
+use bytes::Bytes;
+use std::io;
+use std::mem;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+/// Maximum number of PEER FIND requests during a single lookup
+///
+/// Inconsistent finger tables can route a lookup in circles which would
+/// otherwise never terminate.
+const MAX_HOPS: u32 = 256;
+
+/// Number of connections used to complete a transfer of values for a join or
+/// leave before giving up
+///
+/// Every attempt after the first resumes after the last value received.
+const TRANSFER_ATTEMPTS: u32 = 3;
+
+/// The result of an attempt to join the network in front of some successor
+pub enum JoinOutcome {
+    /// The successor handed over its range with the given predecessor and the
+    /// values stored in that range.
+    Joined(SocketAddr, Storage),
+    /// The successor refused the join and the join should be retried with the
+    /// peer at the given address.
+    Redirected(SocketAddr),
+}
+
+/// The result of reading a value from the peer responsible for it
+pub enum ReadOutcome {
+    /// The peer replied with the value.
+    Found(StorageGetSuccess),
+    /// The peer stores no value newer than the version of a conditional read.
+    NotModified,
+    /// The peer stores no value.
+    NotFound,
+}
+
+#[derive(Clone)]
 pub struct Procedures {
     timeout: u64,
+    metrics: Arc<Metrics>,
+    deadline: Option<Deadline>,
+    cancel_token: Option<CancelToken>,
+    multiplexer: Option<Arc<Multiplexer>>,
+    proxy: Option<Proxy>,
+    framing: Arc<dyn Framing>,
+    socket_options: SocketOptions,
+    requester: Option<SocketAddr>,
 }
 
 impl Procedures {
     pub fn new(timeout: u64) -> Self {
-        Self { timeout }
+        Self::with_metrics(timeout, Arc::new(Metrics::new()))
+    }
+
+    /// Creates a new `Procedures` instance which records telemetry about its
+    /// operations in `metrics`.
+    pub fn with_metrics(timeout: u64, metrics: Arc<Metrics>) -> Self {
+        Self {
+            timeout,
+            metrics,
+            deadline: None,
+            cancel_token: None,
+            multiplexer: None,
+            proxy: None,
+            framing: framing::plain(),
+            socket_options: SocketOptions::default(),
+            requester: None,
+        }
+    }
+
+    /// Returns a copy of these procedures which gives up once `deadline`
+    /// passed.
+    ///
+    /// The remaining budget is checked before each hop, passed on in the
+    /// requests and limits the timeout of every connection.
+    pub fn with_deadline(&self, deadline: Deadline) -> Self {
+        let mut procedures = self.clone();
+        procedures.deadline = Some(deadline);
+        procedures
+    }
+
+    /// Returns a copy of these procedures which gives up once `cancel_token`
+    /// has been cancelled.
+    ///
+    /// The token is checked before each hop.
+    pub fn with_cancel_token(&self, cancel_token: CancelToken) -> Self {
+        let mut procedures = self.clone();
+        procedures.cancel_token = Some(cancel_token);
+        procedures
+    }
+
+    /// Returns a copy of these procedures which sends its requests over the
+    /// shared connections of `multiplexer` instead of opening a connection
+    /// for every request.
+    ///
+    /// Joining the network still uses a dedicated connection.
+    pub fn with_multiplexer(&self, multiplexer: Arc<Multiplexer>) -> Self {
+        let mut procedures = self.clone();
+        procedures.multiplexer = Some(multiplexer);
+        procedures
+    }
+
+    /// Returns a copy of these procedures which opens its dedicated
+    /// connections through `proxy` if it is given.
+    ///
+    /// Requests sent over a multiplexer use the proxy of the multiplexer.
+    pub fn with_proxy(&self, proxy: Option<Proxy>) -> Self {
+        let mut procedures = self.clone();
+        procedures.proxy = proxy;
+        procedures
+    }
+
+    /// Returns a copy of these procedures which exchanges the messages of its
+    /// dedicated connections in frames of `framing`.
+    pub fn with_framing(&self, framing: Arc<dyn Framing>) -> Self {
+        let mut procedures = self.clone();
+        procedures.framing = framing;
+        procedures
+    }
+
+    /// Returns a copy of these procedures which applies `socket_options` to
+    /// its dedicated connections.
+    ///
+    /// Requests sent over a multiplexer use the options of the multiplexer.
+    pub fn with_socket_options(&self, socket_options: SocketOptions) -> Self {
+        let mut procedures = self.clone();
+        procedures.socket_options = socket_options;
+        procedures
+    }
+
+    /// Returns a copy of these procedures which tells the peers it reads
+    /// values from that it caches values pushed to `requester`.
+    ///
+    /// See [`StorageCachePush`] for details.
+    ///
+    /// [`StorageCachePush`]: ../message/p2p/struct.StorageCachePush.html
+    pub fn with_requester(&self, requester: SocketAddr) -> Self {
+        let mut procedures = self.clone();
+        procedures.requester = Some(requester);
+        procedures
+    }
+
+    /// Fails with a [`DeadlineError`] if the deadline of these procedures has
+    /// passed or with a [`CancelledError`] if they have been cancelled.
+    ///
+    /// [`DeadlineError`]: ../error/struct.DeadlineError.html
+    /// [`CancelledError`]: ../error/struct.CancelledError.html
+    pub fn check_aborted(&self) -> crate::Result<()> {
+        if let Some(deadline) = self.deadline {
+            deadline.check()?;
+        }
+
+        if let Some(ref cancel_token) = self.cancel_token {
+            cancel_token.check()?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the remaining budget to pass on to the next hop.
+    fn budget(&self) -> Option<u32> {
+        self.deadline.map(|deadline| deadline.budget())
+    }
+
+    /// Limits the timeout to the remaining time if a deadline is set.
+    fn limit_timeout(&self, timeout: u64) -> u64 {
+        match self.deadline {
+            Some(deadline) => deadline.limit_timeout(timeout),
+            None => timeout,
+        }
+    }
+
+    /// Opens a connection whose messages are counted in the metrics.
+    ///
+    /// The timeout is limited to the remaining time if a deadline is set.
+    fn open(&self, peer_addr: SocketAddr, timeout: u64) -> crate::Result<Connection> {
+        self.connect(peer_addr, timeout)
+            .map_err(|err| PeerError::classify(peer_addr, err))
+    }
+
+    fn connect(&self, peer_addr: SocketAddr, timeout: u64) -> io::Result<Connection> {
+        let timeout = self.limit_timeout(timeout);
+
+        let mut con = Connection::open_via(peer_addr, timeout, self.proxy.as_ref())?
+            .with_metrics(Arc::clone(&self.metrics))
+            .with_framing(Arc::clone(&self.framing));
+        con.set_socket_options(&self.socket_options)?;
+        con.handshake()?;
+
+        Ok(con)
+    }
+
+    /// Sends a request to `peer_addr` and waits for the reply.
+    ///
+    /// The request is sent over the multiplexer if one is set and over a new
+    /// connection otherwise. Errors are reported as [`PeerError`] if they
+    /// tell whether the peer is down or only slow.
+    ///
+    /// Storage requests taking longer than the threshold of the
+    /// [`SlowLog`] are logged.
+    ///
+    /// [`PeerError`]: ../error/enum.PeerError.html
+    /// [`SlowLog`]: ../metrics/struct.SlowLog.html
+    fn request(&self, peer_addr: SocketAddr, timeout: u64, msg: Message) -> crate::Result<Message> {
+        let storage_request = describe_storage_request(&msg);
+        let start = Instant::now();
+
+        let result = match self.multiplexer {
+            Some(ref multiplexer) => {
+                multiplexer.request(peer_addr, &msg, self.limit_timeout(timeout))
+            }
+            None => self.connect(peer_addr, timeout).and_then(|mut con| {
+                con.send(&msg)?;
+                con.receive()
+            }),
+        };
+
+        if let Some(storage_request) = storage_request {
+            self.metrics
+                .slow_log()
+                .check("storage operation", start.elapsed(), || {
+                    let outcome = match result {
+                        Ok(ref reply) => format!("replied with {}", reply),
+                        Err(ref err) => format!("failed: {}", err),
+                    };
+
+                    format!("{} at peer {} {}", storage_request, peer_addr, outcome)
+                });
+        }
+
+        result.map_err(|err| PeerError::classify(peer_addr, err))
     }
 
     /// Get the socket address of the peer responsible for a given identifier.
     ///
     /// This iteratively sends PEER FIND messages to successive peers,
-    /// beginning with `peer_addr` which could be taken from a finger table.
+    /// beginning with `peer_addr` which could be taken from a finger table,
+    /// until the responsible peer replies authoritatively. A peer which only
+    /// knows the responsible peer as its successor is not trusted since it
+    /// may not have learned about a peer which joined in between.
     pub fn find_peer(
         &self,
         identifier: Identifier,
-        mut peer_addr: SocketAddr,
+        peer_addr: SocketAddr,
     ) -> crate::Result<SocketAddr> {
+        self.lookup(identifier, peer_addr, None)
+            .map(|(socket_addr, _)| socket_addr)
+    }
+
+    /// Get the socket address of the peer responsible for a given identifier
+    /// along with the path of the lookup.
+    ///
+    /// This works like [`find_peer`] but asks every peer to append its own
+    /// address to the trace of the lookup.
+    ///
+    /// [`find_peer`]: #method.find_peer
+    pub fn trace_peer(
+        &self,
+        identifier: Identifier,
+        peer_addr: SocketAddr,
+    ) -> crate::Result<(SocketAddr, Vec<SocketAddr>)> {
+        self.lookup(identifier, peer_addr, Some(Vec::new()))
+            .map(|(socket_addr, trace)| (socket_addr, trace.unwrap_or_default()))
+    }
+
+    fn lookup(
+        &self,
+        identifier: Identifier,
+        peer_addr: SocketAddr,
+        trace: Option<Vec<SocketAddr>>,
+    ) -> crate::Result<(SocketAddr, Option<Vec<SocketAddr>>)> {
         debug!("Finding peer for identifier {}", identifier);
 
+        let start = Instant::now();
+        let mut contacted = Vec::new();
+
+        let result = self.lookup_from(identifier, peer_addr, trace, start, &mut contacted);
+
+        self.metrics
+            .slow_log()
+            .check("lookup", start.elapsed(), || {
+                let outcome = match result {
+                    Ok((socket_addr, _)) => format!("found {}", socket_addr),
+                    Err(ref err) => format!("failed: {}", err),
+                };
+
+                format!(
+                    "identifier {} {} after {} hops, peers contacted {:?}",
+                    identifier,
+                    outcome,
+                    contacted.len(),
+                    contacted
+                )
+            });
+
+        result
+    }
+
+    fn lookup_from(
+        &self,
+        identifier: Identifier,
+        mut peer_addr: SocketAddr,
+        mut trace: Option<Vec<SocketAddr>>,
+        start: Instant,
+        contacted: &mut Vec<SocketAddr>,
+    ) -> crate::Result<(SocketAddr, Option<Vec<SocketAddr>>)> {
+        let mut hops = 0;
+
         // TODO do not fail if one peer does not reply correctly
         loop {
-            let mut con = Connection::open(peer_addr, self.timeout)?;
-            let peer_find = PeerFind { identifier };
-            con.send(&Message::PeerFind(peer_find))?;
-            let msg = con.receive()?;
-
-            let reply_addr = if let Message::PeerFound(peer_found) = msg {
-                peer_found.socket_addr
-            } else {
-                return Err(Box::new(MessageError::new(msg)));
+            if let Err(err) = self.check_aborted() {
+                self.metrics.record_lookup_failure();
+
+                return Err(err);
+            }
+
+            let peer_found = self.peer_find(identifier, peer_addr, trace.clone());
+
+            contacted.push(peer_addr);
+            hops += 1;
+
+            let peer_found = match peer_found {
+                Ok(peer_found) => peer_found,
+                Err(err) => {
+                    self.metrics.record_lookup_failure();
+
+                    if let Some(trace) = trace {
+                        warn!(
+                            "Lookup for identifier {} failed at peer {} after path {:?}",
+                            identifier, peer_addr, trace
+                        );
+                    }
+
+                    return Err(err);
+                }
             };
 
-            if reply_addr == peer_addr {
+            let reply_addr = peer_found.socket_addr;
+
+            if trace.is_some() {
+                trace = peer_found.trace;
+            }
+
+            // only the responsible peer replies authoritatively, older peers
+            // which do not mark their replies name themselves instead
+            if peer_found.authoritative || reply_addr == peer_addr {
                 debug!(
-                    "Peer found for identifier {} with address {}",
-                    identifier, reply_addr
+                    "Peer found for identifier {} with address {} after {} hops",
+                    identifier, reply_addr, hops
                 );
 
-                return Ok(reply_addr);
+                self.metrics.record_lookup(hops, start.elapsed());
+
+                return Ok((reply_addr, trace));
+            }
+
+            if hops >= MAX_HOPS {
+                self.metrics.record_lookup_failure();
+
+                return Err(format!(
+                    "Lookup for identifier {} exceeded {} hops",
+                    identifier, MAX_HOPS
+                )
+                .into());
             }
 
             peer_addr = reply_addr;
         }
     }
 
+    fn peer_find(
+        &self,
+        identifier: Identifier,
+        peer_addr: SocketAddr,
+        trace: Option<Vec<SocketAddr>>,
+    ) -> crate::Result<PeerFound> {
+        let peer_find = PeerFind {
+            identifier,
+            trace,
+            budget: self.budget(),
+        };
+        let msg = self.request(peer_addr, self.timeout, Message::PeerFind(peer_find))?;
+
+        if let Message::PeerFound(peer_found) = msg {
+            Ok(peer_found)
+        } else {
+            Err(Box::new(
+                MessageError::new(msg)
+                    .with_expected("PEER FOUND")
+                    .with_peer(peer_addr)
+                    .with_operation("lookup"),
+            ))
+        }
+    }
+
     /// Send a storage get message to a peer with the objective to find a value for a given key.
     ///
     /// Opens a P2P connection to `peer_addr` and sends a STORAGE GET message to retrieve a value for
     /// `key` depending on the reply.
-    pub fn get_value(&self, peer_addr: SocketAddr, key: Key) -> crate::Result<Option<Vec<u8>>> {
+    pub fn get_value(&self, peer_addr: SocketAddr, key: Key) -> crate::Result<Option<Bytes>> {
+        let value = self.get_versioned_value(peer_addr, key)?;
+
+        Ok(value.map(|(_, value)| value))
+    }
+
+    /// Send a storage get message to a peer and return the value along with its version.
+    ///
+    /// Works like [`get_value`] but additionally returns the version of the value which allows to
+    /// compare values obtained from several replicas.
+    ///
+    /// [`get_value`]: #method.get_value
+    pub fn get_versioned_value(
+        &self,
+        peer_addr: SocketAddr,
+        key: Key,
+    ) -> crate::Result<Option<(u64, Bytes)>> {
+        let value = self.get_stored_value(peer_addr, key)?;
+
+        Ok(value.map(|storage_success| (storage_success.version, storage_success.value)))
+    }
+
+    /// Send a storage get message to a peer and return its whole reply.
+    ///
+    /// Works like [`get_value`] but additionally returns the version, the remaining time to live
+    /// and the time the peer stored the value which clients use to decide how long to cache it.
+    ///
+    /// [`get_value`]: #method.get_value
+    pub fn get_stored_value(
+        &self,
+        peer_addr: SocketAddr,
+        key: Key,
+    ) -> crate::Result<Option<StorageGetSuccess>> {
+        match self.read_value(peer_addr, key, None)? {
+            ReadOutcome::Found(storage_success) => Ok(Some(storage_success)),
+            ReadOutcome::NotModified | ReadOutcome::NotFound => Ok(None),
+        }
+    }
+
+    /// Send a conditional storage get message to a peer.
+    ///
+    /// If `if_none_match` is given, the peer only replies with the value if it is newer than that
+    /// version which saves transferring values the requester knows already.
+    pub fn read_value(
+        &self,
+        peer_addr: SocketAddr,
+        key: Key,
+        if_none_match: Option<u64>,
+    ) -> crate::Result<ReadOutcome> {
         debug!("Get value for key {} from peer {}", key, peer_addr);
 
+        self.check_aborted()?;
+
         let storage_get = StorageGet {
             replication_index: key.replication_index,
             raw_key: key.raw_key,
+            budget: self.budget(),
+            if_none_match,
+            requester: self.requester,
         };
 
-        let mut p2p_con = Connection::open(peer_addr, 3600)?;
-        p2p_con.send(&Message::StorageGet(storage_get))?;
-
-        let msg = p2p_con.receive()?;
+        let msg = self.request(peer_addr, 3600, Message::StorageGet(storage_get))?;
 
         if let Message::StorageGetSuccess(storage_success) = msg {
             info!(
@@ -77,37 +486,86 @@ impl Procedures {
                 key, peer_addr
             );
 
-            Ok(Some(storage_success.value))
-        } else {
+            Ok(ReadOutcome::Found(storage_success))
+        } else if let Message::StorageFailure(storage_failure) = msg {
+            if storage_failure.reason == Some(FailureReason::NotModified) {
+                info!("Value for key {} not modified at peer {}", key, peer_addr);
+
+                return Ok(ReadOutcome::NotModified);
+            }
+
             warn!("No value found for key {} at peer {}", key, peer_addr);
 
-            Ok(None)
+            Ok(ReadOutcome::NotFound)
+        } else {
+            Err(Box::new(
+                MessageError::new(msg)
+                    .with_expected("STORAGE GET SUCCESS or STORAGE FAILURE")
+                    .with_peer(peer_addr)
+                    .with_operation("get"),
+            ))
+        }
+    }
+
+    /// Remove the value for a given key from the distributed hash table.
+    ///
+    /// Opens a P2P connection to `peer_addr` and sends a STORAGE DELETE message to remove the value
+    /// stored under `key`. Returns whether the peer confirmed that it removed a value.
+    pub fn delete_value(&self, peer_addr: SocketAddr, key: Key) -> crate::Result<bool> {
+        debug!("Delete value for key {} at peer {}", key, peer_addr);
+
+        let storage_delete = StorageDelete {
+            replication_index: key.replication_index,
+            raw_key: key.raw_key,
+        };
+
+        let msg = self.request(
+            peer_addr,
+            self.timeout,
+            Message::StorageDelete(storage_delete),
+        )?;
+
+        match msg {
+            Message::StorageDeleteSuccess(_) => {
+                info!("Value for key {} removed at peer {}", key, peer_addr);
+
+                Ok(true)
+            }
+            Message::StorageFailure(storage_failure) => {
+                warn!(
+                    "No value removed for key {} at peer {} ({})",
+                    key,
+                    peer_addr,
+                    describe_failure(storage_failure.reason)
+                );
+
+                Ok(false)
+            }
+            msg => Err(Box::new(
+                MessageError::new(msg)
+                    .with_expected("STORAGE DELETE SUCCESS or STORAGE FAILURE")
+                    .with_peer(peer_addr)
+                    .with_operation("delete"),
+            )),
         }
     }
 
     /// Put a value for a given key into the distributed hash table.
     ///
-    /// Opens a P2P connection to `peer_addr` and sends a STORAGE PUT message to store `value` under `key`.
+    /// Opens a P2P connection to `peer_addr` and sends a STORAGE PUT message to store `record`
+    /// under `key` along with its version, remaining time to live and replication metadata.
+    /// Returns whether the peer confirmed that it stored the value.
     pub fn put_value(
         &self,
         peer_addr: SocketAddr,
         key: Key,
-        ttl: u16,
-        value: Vec<u8>,
-    ) -> crate::Result<()> {
+        record: Record,
+    ) -> crate::Result<bool> {
         debug!("Put value for key {} to peer {}", key, peer_addr);
 
-        let storage_put = StoragePut {
-            ttl,
-            replication_index: key.replication_index,
-            raw_key: key.raw_key,
-            value,
-        };
+        let storage_put = record.into_storage_put(key, Instant::now());
 
-        let mut p2p_con = Connection::open(peer_addr, 3600)?;
-        p2p_con.send(&Message::StoragePut(storage_put))?;
-
-        let msg = p2p_con.receive()?;
+        let msg = self.request(peer_addr, 3600, Message::StoragePut(storage_put))?;
 
         if let Message::StoragePutSuccess(_) = msg {
             info!(
@@ -115,19 +573,135 @@ impl Procedures {
                 key, peer_addr
             );
 
-            return Ok(());
+            return Ok(true);
         }
 
-        if let Message::StorageFailure(_) = msg {
+        if let Message::StorageFailure(storage_failure) = msg {
             warn!(
-                "Key {} exists already in storage of peer {}",
-                key, peer_addr
+                "Could not store key {} at peer {} ({})",
+                key,
+                peer_addr,
+                describe_failure(storage_failure.reason)
             );
 
-            return Ok(());
+            return Ok(false);
+        }
+
+        Err(Box::new(
+            MessageError::new(msg)
+                .with_expected("STORAGE PUT SUCCESS or STORAGE FAILURE")
+                .with_peer(peer_addr)
+                .with_operation("put"),
+        ))
+    }
+
+    /// Push a copy of a frequently requested value to a peer which caches it.
+    ///
+    /// Opens a P2P connection to `peer_addr` and sends a STORAGE CACHE PUSH message with the value
+    /// of `record` under `key` which the peer keeps for `ttl`. Returns whether the peer confirmed
+    /// that it cached the value.
+    pub fn push_cached_value(
+        &self,
+        peer_addr: SocketAddr,
+        key: Key,
+        record: &Record,
+        ttl: Duration,
+    ) -> crate::Result<bool> {
+        debug!("Push cached value for key {} to peer {}", key, peer_addr);
+
+        let storage_cache_push = StorageCachePush {
+            replication_index: key.replication_index,
+            raw_key: key.raw_key,
+            version: record.version,
+            ttl: ttl.as_millis() as u64,
+            stored: record.stored,
+            value: record.value.clone(),
+        };
+
+        let msg = self.request(
+            peer_addr,
+            self.timeout,
+            Message::StorageCachePush(storage_cache_push),
+        )?;
+
+        match msg {
+            Message::StoragePutSuccess(_) => Ok(true),
+            Message::StorageFailure(storage_failure) => {
+                debug!(
+                    "Peer {} did not cache key {} ({})",
+                    peer_addr,
+                    key,
+                    describe_failure(storage_failure.reason)
+                );
+
+                Ok(false)
+            }
+            msg => Err(Box::new(
+                MessageError::new(msg)
+                    .with_expected("STORAGE PUT SUCCESS or STORAGE FAILURE")
+                    .with_peer(peer_addr)
+                    .with_operation("cache push"),
+            )),
+        }
+    }
+
+    /// Put several values into the distributed hash table at once.
+    ///
+    /// Sends the `values` to `peer_addr` packed into as few STORAGE BULK PUT messages as
+    /// possible. Returns the keys of all values which the peer rejected, for example because it is
+    /// not responsible for them. Values which exist at the peer already are not rejected.
+    pub fn put_values(
+        &self,
+        peer_addr: SocketAddr,
+        values: Vec<(Key, Record)>,
+    ) -> crate::Result<Vec<Key>> {
+        debug!("Put {} values to peer {}", values.len(), peer_addr);
+
+        let now = Instant::now();
+        let values = values
+            .into_iter()
+            .map(|(key, record)| record.into_storage_put(key, now));
+
+        let mut rejected = Vec::new();
+
+        for request in StorageBulkPut::pack(values) {
+            // a value too large to be packed is sent as STORAGE PUT
+            let key = match request {
+                Message::StoragePut(ref storage_put) => Some(Key {
+                    raw_key: storage_put.raw_key,
+                    replication_index: storage_put.replication_index,
+                }),
+                _ => None,
+            };
+
+            match (self.request(peer_addr, 3600, request)?, key) {
+                (Message::StorageBulkPutReply(reply), _) => {
+                    rejected.extend(reply.rejected.into_iter().map(Key::from));
+                }
+                (Message::StoragePutSuccess(_), Some(_)) => {}
+                (Message::StorageFailure(storage_failure), Some(key)) => {
+                    if storage_failure.reason != Some(FailureReason::Exists) {
+                        rejected.push(key);
+                    }
+                }
+                (msg, _) => {
+                    return Err(Box::new(
+                        MessageError::new(msg)
+                            .with_expected("STORAGE BULK PUT REPLY")
+                            .with_peer(peer_addr)
+                            .with_operation("put"),
+                    ))
+                }
+            }
         }
 
-        Err(Box::new(MessageError::new(msg)))
+        info!(
+            "Peer {} rejected {} values of a bulk put",
+            peer_addr,
+            rejected.len()
+        );
+
+        Ok(rejected)
     }
 
     /// Notify the successor of a potential predecessor and asks to reply with the current predecessor.
@@ -141,13 +715,12 @@ impl Procedures {
     ) -> crate::Result<SocketAddr> {
         debug!("Getting predecessor of peer {}", peer_addr);
 
-        let mut con = Connection::open(peer_addr, self.timeout)?;
-
-        con.send(&Message::PredecessorNotify(PredecessorNotify {
-            socket_addr,
-        }))?;
-
-        let msg = con.receive()?;
+        let predecessor_notify = PredecessorNotify { socket_addr };
+        let msg = self.request(
+            peer_addr,
+            self.timeout,
+            Message::PredecessorNotify(predecessor_notify),
+        )?;
 
         if let Message::PredecessorReply(predecessor_reply) = msg {
             info!("Predecessor received from peer {}", peer_addr);
@@ -156,7 +729,315 @@ impl Procedures {
         } else {
             warn!("No predecessor received from peer {}", peer_addr);
 
-            Err(Box::new(MessageError::new(msg)))
+            Err(Box::new(
+                MessageError::new(msg)
+                    .with_expected("PREDECESSOR REPLY")
+                    .with_peer(peer_addr)
+                    .with_operation("predecessor notification"),
+            ))
+        }
+    }
+
+    /// Ask the peer `peer_addr` for its load.
+    ///
+    /// Opens a P2P connection and sends a PEER LOAD message to receive the share of the identifier
+    /// space, the stored records and the handled requests of the peer.
+    pub fn peer_load(&self, peer_addr: SocketAddr) -> crate::Result<Load> {
+        debug!("Getting load of peer {}", peer_addr);
+
+        let msg = self.request(peer_addr, self.timeout, Message::PeerLoad(PeerLoad))?;
+
+        if let Message::PeerLoadReply(peer_load_reply) = msg {
+            Ok(peer_load_reply.load)
+        } else {
+            Err(Box::new(
+                MessageError::new(msg)
+                    .with_expected("PEER LOAD REPLY")
+                    .with_peer(peer_addr)
+                    .with_operation("load report"),
+            ))
+        }
+    }
+
+    /// Tell the neighbour `peer_addr` that the peer `socket_addr` leaves the network.
+    ///
+    /// Opens a P2P connection and sends a PEER LEAVE message with the predecessor and successor
+    /// of the leaving peer, followed by STORAGE BULK PUT messages with the given `records` which
+    /// the neighbour takes over. Returns the predecessor of the neighbour from its
+    /// PREDECESSOR REPLY.
+    ///
+    /// The records are sent in the order of their keys. If the connection breaks, the transfer is
+    /// resumed on a new connection after the last key the neighbour confirms with TRANSFER ACK.
+    pub fn leave(
+        &self,
+        socket_addr: SocketAddr,
+        predecessor_addr: SocketAddr,
+        successor_addr: SocketAddr,
+        peer_addr: SocketAddr,
+        records: &[(Key, Record)],
+    ) -> crate::Result<SocketAddr> {
+        debug!("Leaving the network via peer {}", peer_addr);
+
+        let mut records: Vec<&(Key, Record)> = records.iter().collect();
+        records.sort_by_key(|(key, _)| *key);
+
+        let mut peer_leave = PeerLeave {
+            socket_addr,
+            predecessor_addr,
+            successor_addr,
+            records: records.len() as u32,
+            resume: false,
+        };
+
+        let mut attempt = 1;
+
+        loop {
+            match self.leave_once(&peer_leave, peer_addr, &records) {
+                Err(ref err) if attempt < TRANSFER_ATTEMPTS && err.is::<io::Error>() => {
+                    warn!("Transfer to {} broke off, resuming: {}", peer_addr, err);
+
+                    peer_leave.resume = true;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn leave_once(
+        &self,
+        peer_leave: &PeerLeave,
+        peer_addr: SocketAddr,
+        records: &[&(Key, Record)],
+    ) -> crate::Result<SocketAddr> {
+        let mut con = self.open(peer_addr, self.timeout)?;
+
+        con.send(&Message::PeerLeave(PeerLeave { ..*peer_leave }))?;
+
+        // skip the records the neighbour received already
+        let mut offset = 0;
+
+        if peer_leave.resume {
+            match con.receive()? {
+                Message::TransferAck(transfer_ack) => {
+                    if let Some(resume_after) = transfer_ack.resume_after {
+                        let resume_after = Key::from(resume_after);
+                        offset = records.partition_point(|(key, _)| *key <= resume_after);
+                    }
+
+                    info!(
+                        "Resuming transfer to {} after {} records",
+                        peer_addr, offset
+                    );
+                }
+                msg => {
+                    return Err(Box::new(
+                        MessageError::new(msg)
+                            .with_expected("TRANSFER ACK")
+                            .with_peer(peer_addr)
+                            .with_operation("leave"),
+                    ))
+                }
+            }
+        }
+
+        let now = Instant::now();
+        let values = records[offset..]
+            .iter()
+            .map(|(key, record)| record.clone().into_storage_put(*key, now));
+
+        for msg in StorageBulkPut::pack(values) {
+            con.send(&msg)?;
+        }
+
+        match con.receive()? {
+            Message::PredecessorReply(predecessor_reply) => {
+                info!("Peer {} acknowledged that we leave", peer_addr);
+
+                Ok(predecessor_reply.socket_addr)
+            }
+            msg => Err(Box::new(
+                MessageError::new(msg)
+                    .with_expected("PREDECESSOR REPLY")
+                    .with_peer(peer_addr)
+                    .with_operation("leave"),
+            )),
+        }
+    }
+
+    /// Join the network in front of the peer `peer_addr` which becomes our successor.
+    ///
+    /// This implements the ordered join protocol. First, a JOIN LOCK message is sent to make the
+    /// successor lock its range up to the identifier of `socket_addr`. After the JOIN ACK reply,
+    /// the successor transfers all values in that range using STORAGE BULK PUT messages. Finally, a
+    /// JOIN PUBLISH message makes the successor apply the join and release the lock.
+    ///
+    /// If the connection breaks during the transfer, the values received so far are kept and the
+    /// JOIN LOCK message is repeated on a new connection with the last key received such that the
+    /// successor only transfers the remaining values.
+    ///
+    /// If the successor refuses the join with a JOIN NACK message, the suggested peer is returned
+    /// as [`JoinOutcome::Redirected`]. A refusal because another peer has the identifier of
+    /// `socket_addr` already fails with a [`CollisionError`].
+    ///
+    /// [`JoinOutcome::Redirected`]: enum.JoinOutcome.html#variant.Redirected
+    /// [`CollisionError`]: ../error/struct.CollisionError.html
+    pub fn join(
+        &self,
+        socket_addr: SocketAddr,
+        peer_addr: SocketAddr,
+    ) -> crate::Result<JoinOutcome> {
+        debug!("Joining in front of peer {}", peer_addr);
+
+        let mut storage = Storage::new();
+        let mut join_lock = JoinLock {
+            socket_addr,
+            resume_after: None,
+        };
+
+        let mut attempt = 1;
+
+        loop {
+            match self.join_once(&mut join_lock, peer_addr, &mut storage) {
+                Err(ref err) if attempt < TRANSFER_ATTEMPTS && err.is::<io::Error>() => {
+                    warn!(
+                        "Transfer from {} broke off after {} values, resuming: {}",
+                        peer_addr,
+                        storage.len(),
+                        err
+                    );
+
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Runs the join protocol on a single connection and adds the transferred values to
+    /// `storage` which is handed over once the join is published. The cursor of `join_lock` is
+    /// advanced with every value received.
+    fn join_once(
+        &self,
+        join_lock: &mut JoinLock,
+        peer_addr: SocketAddr,
+        storage: &mut Storage,
+    ) -> crate::Result<JoinOutcome> {
+        let socket_addr = join_lock.socket_addr;
+
+        let mut con = self.open(peer_addr, self.timeout)?;
+        con.send(&Message::JoinLock(JoinLock { ..*join_lock }))?;
+
+        let join_ack = match con.receive()? {
+            Message::JoinAck(join_ack) => join_ack,
+            Message::JoinNack(join_nack) if join_nack.collision => {
+                return Err(Box::new(CollisionError::new(
+                    socket_addr,
+                    join_nack.socket_addr,
+                )));
+            }
+            Message::JoinNack(join_nack) => {
+                warn!(
+                    "Peer {} refused join, retrying with {}",
+                    peer_addr, join_nack.socket_addr
+                );
+
+                return Ok(JoinOutcome::Redirected(join_nack.socket_addr));
+            }
+            msg => {
+                return Err(Box::new(
+                    MessageError::new(msg)
+                        .with_expected("JOIN ACK or JOIN NACK")
+                        .with_peer(peer_addr)
+                        .with_operation("join"),
+                ))
+            }
+        };
+
+        info!(
+            "Peer {} locked its range, receiving {} values",
+            peer_addr, join_ack.records
+        );
+
+        let mut received = 0;
+
+        while received < join_ack.records {
+            let values = con.receive()?.into_values().map_err(|msg| {
+                MessageError::new(*msg)
+                    .with_expected("STORAGE PUT or STORAGE BULK PUT")
+                    .with_peer(peer_addr)
+                    .with_operation("join transfer")
+            })?;
+
+            for storage_put in values {
+                let key = Key {
+                    raw_key: storage_put.raw_key,
+                    replication_index: storage_put.replication_index,
+                };
+
+                let record = Record::from_storage_put(storage_put, Instant::now());
+
+                storage.insert(key, record);
+                join_lock.resume_after = Some(key.into());
+                received += 1;
+            }
+        }
+
+        con.send(&Message::JoinPublish(JoinPublish { socket_addr }))?;
+
+        match con.receive()? {
+            Message::PredecessorReply(_) => {
+                info!("Joined network in front of peer {}", peer_addr);
+
+                Ok(JoinOutcome::Joined(
+                    join_ack.socket_addr,
+                    mem::take(storage),
+                ))
+            }
+            Message::JoinNack(join_nack) => {
+                warn!("Peer {} refused to publish join", peer_addr);
+
+                Ok(JoinOutcome::Redirected(join_nack.socket_addr))
+            }
+            msg => Err(Box::new(
+                MessageError::new(msg)
+                    .with_expected("PREDECESSOR REPLY or JOIN NACK")
+                    .with_peer(peer_addr)
+                    .with_operation("join"),
+            )),
         }
     }
 }
+
+/// Returns a description of `msg` if it is a storage request.
+fn describe_storage_request(msg: &Message) -> Option<String> {
+    let (raw_key, replication_index) = match msg {
+        Message::StorageGet(storage_get) => (storage_get.raw_key, storage_get.replication_index),
+        Message::StoragePut(storage_put) => (storage_put.raw_key, storage_put.replication_index),
+        Message::StorageDelete(storage_delete) => {
+            (storage_delete.raw_key, storage_delete.replication_index)
+        }
+        Message::StorageBulkPut(storage_bulk_put) => {
+            return Some(format!(
+                "{} of {} values",
+                msg,
+                storage_bulk_put.values.len()
+            ))
+        }
+        _ => return None,
+    };
+
+    let key = Key {
+        raw_key,
+        replication_index,
+    };
+
+    Some(format!("{} for key {}", msg, key))
+}
+
+fn describe_failure(reason: Option<FailureReason>) -> String {
+    reason.map_or_else(
+        || "no reason given".to_string(),
+        |reason| reason.to_string(),
+    )
+}
@@ -0,0 +1,341 @@
+//! Outbound connections through a SOCKS5 or HTTP proxy
+//!
+//! Deployments which must not connect to other peers directly, for example
+//! because their traffic is routed via Tor, open their peer-to-peer
+//! connections through a [`Proxy`]. The proxy is asked to open a plain TCP
+//! tunnel to the peer and the messages are exchanged over that tunnel as
+//! usual:
+//!
+//! * `socks5://host:port` uses the CONNECT command of [RFC 1928] without
+//!   authentication.
+//! * `http://host:port` sends an HTTP `CONNECT` request as described in
+//!   [RFC 7231] and expects a `2xx` status.
+//!
+//! Failures reported by the proxy are mapped to the error kinds of a direct
+//! connection such that a peer behind the proxy which refuses the
+//! connection is still recognized as down.
+//!
+//! [`Proxy`]: enum.Proxy.html
+//! [RFC 1928]: https://tools.ietf.org/html/rfc1928
+//! [RFC 7231]: https://tools.ietf.org/html/rfc7231#section-4.3.6
+
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Maximum size of the response header of an HTTP proxy
+const MAX_HEADER_SIZE: usize = 8192;
+
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_NO_AUTHENTICATION: u8 = 0;
+const SOCKS_CONNECT: u8 = 1;
+const SOCKS_IPV4: u8 = 1;
+const SOCKS_DOMAIN: u8 = 3;
+const SOCKS_IPV6: u8 = 4;
+
+/// A proxy which outbound connections are tunneled through
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Proxy {
+    /// A SOCKS5 proxy like Tor listening at the given address
+    Socks5(SocketAddr),
+    /// An HTTP proxy supporting `CONNECT` listening at the given address
+    Http(SocketAddr),
+}
+
+impl Proxy {
+    /// Returns the address of the proxy itself.
+    pub fn addr(&self) -> SocketAddr {
+        match *self {
+            Proxy::Socks5(addr) | Proxy::Http(addr) => addr,
+        }
+    }
+
+    /// Opens a TCP tunnel to `target` through this proxy.
+    ///
+    /// `timeout` limits connecting to the proxy as well as every read and
+    /// write of the handshake.
+    pub fn connect(&self, target: SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+        let mut stream = TcpStream::connect_timeout(&self.addr(), timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        match self {
+            Proxy::Socks5(_) => socks5_handshake(&mut stream, target)?,
+            Proxy::Http(_) => http_handshake(&mut stream, target)?,
+        }
+
+        trace!("Tunneled connection to {} through {}", target, self);
+
+        Ok(stream)
+    }
+}
+
+impl FromStr for Proxy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, addr) = s
+            .split_once("://")
+            .ok_or_else(|| format!("missing scheme in proxy `{}`", s))?;
+
+        let addr = addr
+            .trim_end_matches('/')
+            .parse()
+            .map_err(|err| format!("invalid proxy address `{}`: {}", addr, err))?;
+
+        match scheme {
+            "socks5" => Ok(Proxy::Socks5(addr)),
+            "http" => Ok(Proxy::Http(addr)),
+            _ => Err(format!("unsupported proxy scheme `{}`", scheme)),
+        }
+    }
+}
+
+impl fmt::Display for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Proxy::Socks5(addr) => write!(f, "socks5://{}", addr),
+            Proxy::Http(addr) => write!(f, "http://{}", addr),
+        }
+    }
+}
+
+fn socks5_handshake(stream: &mut TcpStream, target: SocketAddr) -> io::Result<()> {
+    stream.write_all(&[SOCKS_VERSION, 1, SOCKS_NO_AUTHENTICATION])?;
+
+    let mut choice = [0; 2];
+    stream.read_exact(&mut choice)?;
+
+    if choice != [SOCKS_VERSION, SOCKS_NO_AUTHENTICATION] {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "SOCKS5 proxy requires authentication",
+        ));
+    }
+
+    let mut request = vec![SOCKS_VERSION, SOCKS_CONNECT, 0];
+
+    match target.ip() {
+        IpAddr::V4(ip) => {
+            request.push(SOCKS_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            request.push(SOCKS_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+    }
+
+    request.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&request)?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply)?;
+
+    if reply[0] != SOCKS_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Invalid SOCKS5 reply",
+        ));
+    }
+
+    if reply[1] != 0 {
+        return Err(socks5_error(reply[1]));
+    }
+
+    // skip the address the proxy bound to
+    let addr_len = match reply[3] {
+        SOCKS_IPV4 => 4,
+        SOCKS_IPV6 => 16,
+        SOCKS_DOMAIN => {
+            let mut len = [0; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid address type in SOCKS5 reply",
+            ))
+        }
+    };
+
+    let mut bound = vec![0; addr_len + 2];
+    stream.read_exact(&mut bound)?;
+
+    Ok(())
+}
+
+/// Maps a SOCKS5 reply code to the error of a direct connection.
+fn socks5_error(code: u8) -> io::Error {
+    let (kind, reason) = match code {
+        3 => (io::ErrorKind::NetworkUnreachable, "network unreachable"),
+        4 => (io::ErrorKind::HostUnreachable, "host unreachable"),
+        5 => (io::ErrorKind::ConnectionRefused, "connection refused"),
+        6 => (io::ErrorKind::TimedOut, "TTL expired"),
+        _ => (io::ErrorKind::Other, "general failure"),
+    };
+
+    io::Error::new(kind, format!("SOCKS5 proxy failed: {}", reason))
+}
+
+fn http_handshake(stream: &mut TcpStream, target: SocketAddr) -> io::Result<()> {
+    write!(
+        stream,
+        "CONNECT {} HTTP/1.1\r\nHost: {}\r\n\r\n",
+        target, target
+    )?;
+
+    // read byte by byte such that no data of the tunnel is consumed
+    let mut header = Vec::new();
+    let mut byte = [0; 1];
+
+    while !header.ends_with(b"\r\n\r\n") {
+        if header.len() == MAX_HEADER_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "HTTP proxy response exceeds maximum size",
+            ));
+        }
+
+        stream.read_exact(&mut byte)?;
+        header.push(byte[0]);
+    }
+
+    let status = String::from_utf8_lossy(&header)
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid HTTP proxy response"))?;
+
+    let kind = match status {
+        200..=299 => return Ok(()),
+        407 => io::ErrorKind::PermissionDenied,
+        502 | 503 => io::ErrorKind::ConnectionRefused,
+        504 => io::ErrorKind::TimedOut,
+        _ => io::ErrorKind::Other,
+    };
+
+    Err(io::Error::new(
+        kind,
+        format!("HTTP proxy failed with status {}", status),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    const HTTP_REQUEST: &str = "CONNECT 10.0.0.1:8080 HTTP/1.1\r\nHost: 10.0.0.1:8080\r\n\r\n";
+
+    /// Runs a proxy for a single connection which answers each step of the
+    /// handshake with its reply after reading its number of bytes and echoes
+    /// the tunnel afterwards.
+    fn fake_proxy(steps: Vec<(usize, &'static [u8])>) -> (SocketAddr, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+
+            for (len, reply) in steps {
+                let mut bytes = vec![0; len];
+                stream.read_exact(&mut bytes).unwrap();
+                stream.write_all(reply).unwrap();
+
+                request.extend(bytes);
+            }
+
+            let mut echo = [0; 4];
+            if stream.read_exact(&mut echo).is_ok() {
+                stream.write_all(&echo).unwrap();
+            }
+
+            request
+        });
+
+        (addr, handle)
+    }
+
+    fn target() -> SocketAddr {
+        "10.0.0.1:8080".parse().unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let addr: SocketAddr = "127.0.0.1:9050".parse().unwrap();
+
+        assert_eq!(Ok(Proxy::Socks5(addr)), "socks5://127.0.0.1:9050".parse());
+        assert_eq!(Ok(Proxy::Http(addr)), "http://127.0.0.1:9050/".parse());
+        assert!("ftp://127.0.0.1:9050".parse::<Proxy>().is_err());
+        assert!("127.0.0.1:9050".parse::<Proxy>().is_err());
+    }
+
+    #[test]
+    fn socks5_connect() {
+        let (addr, handle) = fake_proxy(vec![
+            // no authentication
+            (3, &[5, 0]),
+            // succeeded, bound to 127.0.0.1:1080
+            (10, &[5, 0, 0, 1, 127, 0, 0, 1, 4, 56]),
+        ]);
+
+        let mut stream = Proxy::Socks5(addr).connect(target(), TIMEOUT).unwrap();
+        stream.write_all(b"ping").unwrap();
+
+        let mut echo = [0; 4];
+        stream.read_exact(&mut echo).unwrap();
+        assert_eq!(b"ping", &echo);
+
+        #[rustfmt::skip]
+        let expected = [
+            // greeting
+            5, 1, 0,
+            // connect to 10.0.0.1:8080
+            5, 1, 0, 1, 10, 0, 0, 1, 31, 144,
+        ];
+        assert_eq!(&expected[..], &handle.join().unwrap()[..]);
+    }
+
+    #[test]
+    fn socks5_refused() {
+        let (addr, _) = fake_proxy(vec![(3, &[5, 0]), (10, &[5, 5, 0, 1, 0, 0, 0, 0, 0, 0])]);
+
+        let err = Proxy::Socks5(addr).connect(target(), TIMEOUT).unwrap_err();
+
+        assert_eq!(io::ErrorKind::ConnectionRefused, err.kind());
+    }
+
+    #[test]
+    fn http_connect() {
+        let reply = b"HTTP/1.1 200 Connection established\r\n\r\n";
+        let (addr, handle) = fake_proxy(vec![(HTTP_REQUEST.len(), reply)]);
+
+        let mut stream = Proxy::Http(addr).connect(target(), TIMEOUT).unwrap();
+        stream.write_all(b"ping").unwrap();
+
+        let mut echo = [0; 4];
+        stream.read_exact(&mut echo).unwrap();
+        assert_eq!(b"ping", &echo);
+
+        assert_eq!(HTTP_REQUEST.as_bytes(), &handle.join().unwrap()[..]);
+    }
+
+    #[test]
+    fn http_bad_gateway() {
+        let reply = b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\n\r\n";
+        let (addr, _) = fake_proxy(vec![(HTTP_REQUEST.len(), reply)]);
+
+        let err = Proxy::Http(addr).connect(target(), TIMEOUT).unwrap_err();
+
+        assert_eq!(io::ErrorKind::ConnectionRefused, err.kind());
+    }
+}
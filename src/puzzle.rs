@@ -0,0 +1,261 @@
+//! Client puzzles against floods of connections
+//!
+//! Peers of networks which are open to the internet can make every peer
+//! connecting to them solve a small proof-of-work puzzle before a single
+//! message is handled. Opening a connection then costs the connecting peer
+//! about `2^difficulty` hash operations while the accepting peer only has to
+//! check a single hash, such that a flood of lookups or storage requests
+//! becomes expensive for the sender.
+//!
+//! Right after accepting a connection, the peer sends a challenge with the
+//! difficulty and a random nonce:
+//!
+//! ```text
+//! +--------------+--------------------------------+
+//! | difficulty   |          nonce (16)            |
+//! +--------------+--------------------------------+
+//! ```
+//!
+//! The connecting peer replies with a `solution: u64` such that the SHA-256
+//! hash of the nonce followed by the solution starts with at least
+//! `difficulty` zero bits. Connections with a wrong solution are closed
+//! without reading any message. The nonce is never reused, thus no state has
+//! to be kept about solved puzzles.
+//!
+//! The puzzle wraps the [`Framing`] of the connection and is enabled with
+//! the `puzzle_difficulty` of the configuration. All peers of a network have
+//! to use the same setting.
+//!
+//! [`Framing`]: ../framing/trait.Framing.html
+
+use crate::framing::Framing;
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::io;
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Size of the nonce of a challenge in bytes
+pub const NONCE_SIZE: usize = 16;
+
+/// Highest difficulty a peer solves or asks for
+///
+/// This protects connecting peers from challenges which would take forever.
+pub const MAX_DIFFICULTY: u8 = 32;
+
+/// Time the connecting peer has to send its solution
+const SOLUTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A framing which makes connecting peers solve a puzzle first
+///
+/// The messages themselves are exchanged in frames of the inner framing.
+#[derive(Debug)]
+pub struct Puzzle {
+    inner: Arc<dyn Framing>,
+    difficulty: u8,
+}
+
+impl Puzzle {
+    /// Wraps `inner` with puzzles of `difficulty` leading zero bits.
+    ///
+    /// The difficulty is limited to [`MAX_DIFFICULTY`].
+    ///
+    /// [`MAX_DIFFICULTY`]: constant.MAX_DIFFICULTY.html
+    pub fn new(inner: Arc<dyn Framing>, difficulty: u8) -> Self {
+        Self {
+            inner,
+            difficulty: difficulty.min(MAX_DIFFICULTY),
+        }
+    }
+
+    /// Returns the number of leading zero bits a solution must have.
+    pub fn difficulty(&self) -> u8 {
+        self.difficulty
+    }
+}
+
+impl Framing for Puzzle {
+    fn write_frame(&self, writer: &mut dyn Write, message: &[u8]) -> io::Result<()> {
+        self.inner.write_frame(writer, message)
+    }
+
+    fn read_frame(&self, reader: &mut dyn Read, buffer: &mut [u8]) -> io::Result<usize> {
+        self.inner.read_frame(reader, buffer)
+    }
+
+    fn connect(&self, stream: &mut TcpStream) -> io::Result<()> {
+        self.inner.connect(stream)?;
+
+        let mut challenge = [0; 1 + NONCE_SIZE];
+        stream.read_exact(&mut challenge)?;
+
+        let difficulty = challenge[0];
+
+        if difficulty > MAX_DIFFICULTY {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Puzzle exceeds maximum difficulty",
+            ));
+        }
+
+        let solution = solve(&challenge[1..], difficulty);
+
+        trace!("Solved puzzle of difficulty {}", difficulty);
+
+        stream.write_all(&solution.to_be_bytes())
+    }
+
+    fn accept(&self, stream: &mut TcpStream) -> io::Result<()> {
+        self.inner.accept(stream)?;
+
+        let mut challenge = [0; 1 + NONCE_SIZE];
+        challenge[0] = self.difficulty;
+
+        SystemRandom::new()
+            .fill(&mut challenge[1..])
+            .map_err(|_| io::Error::other("could not generate random bytes"))?;
+
+        stream.write_all(&challenge)?;
+
+        // do not let peers which never reply block the worker
+        let read_timeout = stream.read_timeout()?;
+        stream.set_read_timeout(Some(SOLUTION_TIMEOUT))?;
+
+        let mut solution = [0; 8];
+        stream.read_exact(&mut solution)?;
+
+        stream.set_read_timeout(read_timeout)?;
+
+        if !verify(
+            &challenge[1..],
+            self.difficulty,
+            u64::from_be_bytes(solution),
+        ) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Invalid puzzle solution",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the smallest solution of the puzzle with `nonce` and
+/// `difficulty`.
+pub fn solve(nonce: &[u8], difficulty: u8) -> u64 {
+    let mut solution = 0;
+
+    while !verify(nonce, difficulty, solution) {
+        solution += 1;
+    }
+
+    solution
+}
+
+/// Checks whether the hash of `nonce` and `solution` starts with at least
+/// `difficulty` zero bits.
+pub fn verify(nonce: &[u8], difficulty: u8, solution: u64) -> bool {
+    let mut context = digest::Context::new(&digest::SHA256);
+    context.update(nonce);
+    context.update(&solution.to_be_bytes());
+
+    leading_zeros(context.finish().as_ref()) >= u32::from(difficulty)
+}
+
+fn leading_zeros(bytes: &[u8]) -> u32 {
+    let mut zeros = 0;
+
+    for &byte in bytes {
+        zeros += byte.leading_zeros();
+
+        if byte != 0 {
+            break;
+        }
+    }
+
+    zeros
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn leading_zeros_of_bytes() {
+        assert_eq!(0, leading_zeros(&[0x80, 0]));
+        assert_eq!(11, leading_zeros(&[0, 0x10, 0xff]));
+        assert_eq!(16, leading_zeros(&[0, 0]));
+    }
+
+    #[test]
+    fn solve_and_verify() {
+        let nonce = [7; NONCE_SIZE];
+        let solution = solve(&nonce, 8);
+
+        assert!(verify(&nonce, 8, solution));
+        assert!((0..solution).all(|wrong| !verify(&nonce, 8, wrong)));
+    }
+
+    #[test]
+    fn handshake() {
+        let listener = TcpListener::bind("127.0.7.5:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            Puzzle::new(framing::plain(), 8).accept(&mut stream)
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        Puzzle::new(framing::plain(), 8)
+            .connect(&mut stream)
+            .unwrap();
+
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn wrong_solution() {
+        let listener = TcpListener::bind("127.0.7.6:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            Puzzle::new(framing::plain(), MAX_DIFFICULTY).accept(&mut stream)
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut challenge = [0; 1 + NONCE_SIZE];
+        stream.read_exact(&mut challenge).unwrap();
+        stream.write_all(&[0; 8]).unwrap();
+
+        let err = handle.join().unwrap().unwrap_err();
+        assert_eq!(io::ErrorKind::PermissionDenied, err.kind());
+    }
+
+    #[test]
+    fn refuse_excessive_difficulty() {
+        let listener = TcpListener::bind("127.0.7.7:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut challenge = [0; 1 + NONCE_SIZE];
+            challenge[0] = MAX_DIFFICULTY + 1;
+            stream.write_all(&challenge).unwrap();
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let err = Puzzle::new(framing::plain(), 8)
+            .connect(&mut stream)
+            .unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+}
@@ -0,0 +1,195 @@
+//! Api client for Python
+//!
+//! The `python` feature adds a `chord` extension module which wraps the
+//! [`ApiClient`] such that experiments can be scripted in Python without
+//! implementing the api messages again. Build it with [maturin] or directly
+//! with cargo and rename the library to `chord.so`:
+//!
+//! ```text
+//! PYO3_BUILD_EXTENSION_MODULE=1 cargo rustc --lib --release \
+//!     --no-default-features --features python --crate-type cdylib
+//! cp target/release/libchord.so chord.so
+//! ```
+//!
+//! Keys are given either as names which are hashed like
+//! [`DhtKey::from_name`] or as at most 32 bytes. Values are `bytes` and
+//! failed operations raise a `chord.ChordError`.
+//!
+//! ```text
+//! import chord
+//!
+//! client = chord.connect("127.0.0.1:8080")
+//! client.put("alice/profile", b"hello", ttl=60, replication=2)
+//!
+//! assert client.get("alice/profile") == b"hello"
+//! address, identifier = client.resolve("alice/profile")
+//! ```
+//!
+//! The GIL is released while the client waits for the peer such that other
+//! Python threads can keep running.
+//!
+//! [`ApiClient`]: ../client/struct.ApiClient.html
+//! [`DhtKey::from_name`]: ../dht/struct.DhtKey.html#method.from_name
+//! [maturin]: https://www.maturin.rs
+
+use crate::client::ApiClient;
+use crate::dht::{DhtKey, DhtValue};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::net::SocketAddr;
+
+create_exception!(
+    chord,
+    ChordError,
+    PyException,
+    "An operation of the DHT failed."
+);
+
+/// A key given as name or as raw bytes
+#[derive(FromPyObject)]
+enum Key {
+    Name(String),
+    Raw(Vec<u8>),
+}
+
+impl Key {
+    fn into_dht_key(self) -> PyResult<DhtKey> {
+        match self {
+            Key::Name(name) => Ok(DhtKey::from_name(&name)),
+            Key::Raw(bytes) => {
+                DhtKey::from_slice(&bytes).map_err(|err| PyValueError::new_err(err.to_string()))
+            }
+        }
+    }
+}
+
+/// Client for the api interface of a DHT peer
+#[pyclass(name = "Client", module = "chord", frozen)]
+struct Client {
+    address: SocketAddr,
+    client: ApiClient,
+}
+
+#[pymethods]
+impl Client {
+    /// Creates a client for the peer listening on `address` which waits
+    /// `timeout` milliseconds for every connection.
+    #[new]
+    #[pyo3(signature = (address, timeout = 3600))]
+    fn new(address: &str, timeout: u64) -> PyResult<Self> {
+        let address: SocketAddr = address
+            .parse()
+            .map_err(|_| PyValueError::new_err(format!("invalid address {}", address)))?;
+
+        Ok(Client {
+            address,
+            client: ApiClient::new(address, timeout),
+        })
+    }
+
+    /// Stores `value` under `key` for `ttl` seconds on `replication`
+    /// replicas.
+    ///
+    /// Returns as soon as the request has been sent unless `acks` is given,
+    /// then waits until that many replicas confirmed and returns their
+    /// number.
+    #[pyo3(signature = (key, value, ttl = 60, replication = 1, acks = None))]
+    fn put(
+        &self,
+        py: Python<'_>,
+        key: Key,
+        value: Vec<u8>,
+        ttl: u16,
+        replication: u8,
+        acks: Option<u8>,
+    ) -> PyResult<Option<u8>> {
+        let key = key.into_dht_key()?;
+        let value = DhtValue::new(value).map_err(|err| PyValueError::new_err(err.to_string()))?;
+
+        let acks = match acks {
+            Some(acks) => acks,
+            None => {
+                py.detach(|| failure(self.client.put(key, value, ttl, replication)))?;
+
+                return Ok(None);
+            }
+        };
+
+        let confirmed = py.detach(|| {
+            failure(
+                self.client
+                    .put_acknowledged(key, value, ttl, replication, acks),
+            )
+        })?;
+
+        match confirmed {
+            Some(confirmed) => Ok(Some(confirmed)),
+            None => Err(ChordError::new_err("too few replicas stored the value")),
+        }
+    }
+
+    /// Obtains the value stored under `key` or `None` if there is none.
+    fn get<'py>(&self, py: Python<'py>, key: Key) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        let key = key.into_dht_key()?;
+        let value = py.detach(|| failure(self.client.get(key)))?;
+
+        Ok(value.map(|value| PyBytes::new(py, value.as_bytes())))
+    }
+
+    /// Removes the value stored under `key` from `replication` replicas and
+    /// returns the number of removed replicas.
+    #[pyo3(signature = (key, replication = 1))]
+    fn delete(&self, py: Python<'_>, key: Key, replication: u8) -> PyResult<u8> {
+        let key = key.into_dht_key()?;
+
+        py.detach(|| failure(self.client.delete(key, replication)))
+    }
+
+    /// Finds the peer responsible for `key`.
+    ///
+    /// Returns its address and its identifier as 64 hex digits or `None` if
+    /// the DHT could not find it.
+    fn resolve(&self, py: Python<'_>, key: Key) -> PyResult<Option<(String, String)>> {
+        let key = key.into_dht_key()?;
+        let peer = py.detach(|| failure(self.client.resolve(key)))?;
+
+        Ok(peer.map(|(address, identifier)| (address.to_string(), format!("{:x}", identifier))))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("chord.Client('{}')", self.address)
+    }
+}
+
+/// Creates a client for the peer listening on `address`.
+#[pyfunction]
+#[pyo3(signature = (address, timeout = 3600))]
+fn connect(address: &str, timeout: u64) -> PyResult<Client> {
+    Client::new(address, timeout)
+}
+
+/// Hashes `name` into the 32 bytes of a key.
+#[pyfunction]
+fn key_from_name<'py>(py: Python<'py>, name: &str) -> Bound<'py, PyBytes> {
+    PyBytes::new(py, &DhtKey::from_name(name).raw())
+}
+
+/// Turns the error of an operation into a `ChordError`.
+///
+/// The error is converted while the GIL is still released since the boxed
+/// errors of the client cannot be sent between threads.
+fn failure<T>(result: crate::Result<T>) -> PyResult<T> {
+    result.map_err(|err| ChordError::new_err(err.to_string()))
+}
+
+#[pymodule]
+fn chord(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Client>()?;
+    m.add_function(wrap_pyfunction!(connect, m)?)?;
+    m.add_function(wrap_pyfunction!(key_from_name, m)?)?;
+    m.add("ChordError", m.py().get_type::<ChordError>())?;
+
+    Ok(())
+}
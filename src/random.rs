@@ -0,0 +1,170 @@
+//! Randomness of a peer which can be replayed
+//!
+//! Decisions of a peer which only have to be spread out but not kept secret,
+//! like the jitter of the stabilization interval or the peers sampled for a
+//! request, draw their numbers from a shared [`Random`]. By default this is
+//! [`OsRandom`] which asks the operating system.
+//!
+//! Tests and simulations configure a `seed` instead such that every run makes
+//! the same decisions. They get a [`SeededRandom`] which generates its
+//! numbers with [SplitMix64] and yields the same sequence for the same seed
+//! on every platform. Together with the identifiers of peers which are
+//! derived from their addresses, a ring started with the same addresses and
+//! seed behaves the same from run to run.
+//!
+//! Nonces and padding which must not be predictable, like the challenges of
+//! the [`puzzle`] module, keep using the operating system directly.
+//!
+//! # Examples
+//!
+//! ```
+//! # use chord::random::{Random, SeededRandom};
+//! #
+//! let first = SeededRandom::new(42);
+//! let second = SeededRandom::new(42);
+//!
+//! assert_eq!(first.next_u64(), second.next_u64());
+//! assert!(first.below(10) < 10);
+//! ```
+//!
+//! [`Random`]: trait.Random.html
+//! [`OsRandom`]: struct.OsRandom.html
+//! [`SeededRandom`]: struct.SeededRandom.html
+//! [`puzzle`]: ../puzzle/index.html
+//! [SplitMix64]: http://xoshiro.di.unimi.it/splitmix64.c
+
+use crate::sync::MutexExt;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A source of random numbers
+pub trait Random: fmt::Debug + Send + Sync {
+    /// Returns the next random number.
+    fn next_u64(&self) -> u64;
+
+    /// Returns a random number in `[0, 1)`.
+    fn next_f64(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns a random number in `[0, bound)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bound` is zero.
+    fn below(&self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    /// Returns a random duration in `[0, duration * fraction)` which is
+    /// added to `duration` to spread out periodic work.
+    fn jitter(&self, duration: Duration, fraction: f64) -> Duration {
+        duration.mul_f64(fraction * self.next_f64())
+    }
+}
+
+/// Random numbers of the operating system
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OsRandom;
+
+impl Random for OsRandom {
+    fn next_u64(&self) -> u64 {
+        let mut bytes = [0; 8];
+
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .expect("could not generate random bytes");
+
+        u64::from_be_bytes(bytes)
+    }
+}
+
+/// A generator which yields the same numbers for the same seed
+#[derive(Debug)]
+pub struct SeededRandom {
+    state: Mutex<u64>,
+}
+
+impl SeededRandom {
+    /// Creates a generator starting at `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: Mutex::new(seed),
+        }
+    }
+}
+
+impl Random for SeededRandom {
+    fn next_u64(&self) -> u64 {
+        let mut state = self.state.lock_or_recover();
+        *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Returns the shared randomness of the operating system.
+pub fn system() -> Arc<dyn Random> {
+    Arc::new(OsRandom)
+}
+
+/// Returns a seeded generator if `seed` is given and the randomness of the
+/// operating system otherwise.
+pub fn from_seed(seed: Option<u64>) -> Arc<dyn Random> {
+    match seed {
+        Some(seed) => Arc::new(SeededRandom::new(seed)),
+        None => system(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_random_is_reproducible() {
+        let first = SeededRandom::new(7);
+        let second = SeededRandom::new(7);
+        let other = SeededRandom::new(8);
+
+        let sequence: Vec<_> = (0..16).map(|_| first.next_u64()).collect();
+
+        assert_eq!(
+            sequence,
+            (0..16).map(|_| second.next_u64()).collect::<Vec<_>>()
+        );
+        assert_ne!(
+            sequence,
+            (0..16).map(|_| other.next_u64()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn seeded_random_reference_values() {
+        // first outputs of the reference implementation for seed 0
+        let random = SeededRandom::new(0);
+
+        assert_eq!(0xe220_a839_7b1d_cdaf, random.next_u64());
+        assert_eq!(0x6e78_9e6a_a1b9_65f4, random.next_u64());
+    }
+
+    #[test]
+    fn ranges() {
+        let random = SeededRandom::new(3);
+
+        for _ in 0..1000 {
+            assert!(random.below(5) < 5);
+
+            let value = random.next_f64();
+            assert!((0.0..1.0).contains(&value));
+
+            let jitter = random.jitter(Duration::from_secs(60), 0.1);
+            assert!(jitter < Duration::from_secs(6));
+        }
+    }
+}
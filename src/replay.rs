@@ -0,0 +1,157 @@
+//! Replay of captured messages against a peer-to-peer handler
+//!
+//! A capture file written with [`capture::start`] contains the requests a
+//! peer received along with the replies it sent. [`replay`] sends the same
+//! requests to another handler, usually a fresh one running in-process, and
+//! compares its replies with the recorded ones. This allows to check that a
+//! change of the protocol implementation does not alter the behavior on
+//! historical traces.
+//!
+//! Only connections accepted by the recorded peer are replayed, i.e. records
+//! whose local address is the address of the peer. Requests the peer sent to
+//! other peers itself are not part of the replay.
+//!
+//! [`capture::start`]: ../capture/fn.start.html
+//! [`replay`]: fn.replay.html
+
+use crate::capture::{Direction, Record};
+use crate::message::Message;
+use crate::network::Connection;
+use std::fmt;
+use std::net::SocketAddr;
+
+/// A reply which differs from the recorded one
+#[derive(Debug)]
+pub struct Divergence {
+    /// Address of the remote end of the recorded connection
+    pub connection: SocketAddr,
+    /// Name of the request which caused the reply
+    pub request: &'static str,
+    /// The recorded reply
+    pub expected: Message,
+    /// The reply of the handler or the error which occurred instead
+    pub actual: Result<Message, String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} replied to {} from {} with ",
+            self.expected, self.request, self.connection
+        )?;
+
+        match self.actual {
+            Ok(ref msg) if msg.name() == self.expected.name() => {
+                write!(
+                    f,
+                    "different contents: {:?} instead of {:?}",
+                    msg, self.expected
+                )
+            }
+            Ok(ref msg) => write!(f, "{} instead", msg),
+            Err(ref err) => write!(f, "no reply: {}", err),
+        }
+    }
+}
+
+/// Outcome of a replay
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    /// Number of replayed connections
+    pub connections: usize,
+    /// Number of requests sent to the handler
+    pub requests: usize,
+    /// Replies which differ from the recorded ones
+    pub divergences: Vec<Divergence>,
+}
+
+/// Replays the connections which `peer_addr` accepted in `records` against
+/// the handler listening on `target`.
+///
+/// Every recorded connection is replayed over its own connection to the
+/// handler in the order in which they have been opened. `timeout_ms` is the
+/// time to wait for each reply.
+pub fn replay(
+    records: &[Record],
+    peer_addr: SocketAddr,
+    target: SocketAddr,
+    timeout_ms: u64,
+) -> ReplayReport {
+    let mut connections: Vec<(SocketAddr, Vec<&Record>)> = Vec::new();
+
+    for record in records
+        .iter()
+        .filter(|record| record.local_addr == peer_addr)
+    {
+        match connections
+            .iter_mut()
+            .find(|(remote_addr, _)| *remote_addr == record.peer_addr)
+        {
+            Some((_, connection)) => connection.push(record),
+            None => connections.push((record.peer_addr, vec![record])),
+        }
+    }
+
+    let mut report = ReplayReport::default();
+
+    for (remote_addr, records) in connections {
+        info!("Replaying {} messages from {}", records.len(), remote_addr);
+
+        report.connections += 1;
+
+        if let Err(err) = replay_connection(&records, remote_addr, target, timeout_ms, &mut report)
+        {
+            warn!("Could not replay connection from {}: {}", remote_addr, err);
+        }
+    }
+
+    report
+}
+
+fn replay_connection(
+    records: &[&Record],
+    remote_addr: SocketAddr,
+    target: SocketAddr,
+    timeout_ms: u64,
+    report: &mut ReplayReport,
+) -> crate::Result<()> {
+    let mut con = Connection::open(target, timeout_ms)?;
+    let mut request = "no request";
+
+    for record in records {
+        match (record.direction, record.message()?) {
+            (Direction::Received, Message::Correlated(correlated)) => {
+                request = correlated.message.name();
+                report.requests += 1;
+
+                con.send_request(correlated.request_id, &correlated.message)?;
+            }
+            (Direction::Received, msg) => {
+                request = msg.name();
+                report.requests += 1;
+
+                con.send(&msg)?;
+            }
+            (Direction::Sent, expected) => {
+                // replies are compared without their request ID
+                let expected = match expected {
+                    Message::Correlated(correlated) => *correlated.message,
+                    msg => msg,
+                };
+                let actual = con.receive().map_err(|err| err.to_string());
+
+                if actual.as_ref() != Ok(&expected) {
+                    report.divergences.push(Divergence {
+                        connection: remote_addr,
+                        request,
+                        expected,
+                        actual,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -8,22 +8,49 @@
 //! can be associated with an identifier and stored accordingly.
 //!
 //! The [`IdentifierValue`] struct stores the identifier along with the original
-//! value to avoid having to recalculate the hash value multiple times.
+//! value to avoid having to recalculate the hash value multiple times. Socket
+//! addresses which are not stored this way are looked up in the global
+//! [`IdentifierCache`] instead. How socket addresses are hashed depends on
+//! the [`IdentifierScheme`] of the process.
 //!
 //! [`Identifier`]: struct.Identifier.html
 //! [`Identify`]: trait.Identify.html
 //! [`IdentifierValue`]: struct.IdentifierValue.html
+//! [`IdentifierCache`]: struct.IdentifierCache.html
+//! [`IdentifierScheme`]: enum.IdentifierScheme.html
+//!
+//! # Ring semantics
+//!
+//! Identifiers wrap around at 2^256 such that every range of identifiers is
+//! given by a start and an end which are walked clockwise. The
+//! [`IdentifierInterval`] struct makes explicit whether the ends are included.
+//! A peer is responsible for the interval `(predecessor, current]` while a
+//! better successor has to lie in `(current, successor)`.
+//!
+//! [`IdentifierInterval`]: struct.IdentifierInterval.html
+
+use super::uint::U256;
+use crate::error::ConversionError;
+use alloc::format;
+use core::fmt;
+use core::ops::Deref;
+use core::ops::{Add, Sub};
+use core::str::FromStr;
 
-use crate::storage::Key;
-use bigint::U256;
-use ring::digest;
-use std::fmt;
-use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
-use std::ops::Deref;
-use std::ops::{Add, Sub};
+#[cfg(feature = "network")]
+mod hashing;
+
+#[cfg(feature = "network")]
+pub use self::hashing::{IdentifierCache, IdentifierScheme};
 
 /// A 256 bit identifier on an identifier circle
-#[derive(Copy, Clone, Debug, PartialEq)]
+///
+/// Identifiers are ordered by their numeric value which ignores the circle.
+/// Use [`IdentifierInterval`] to check whether identifiers are in a range on
+/// the circle.
+///
+/// [`IdentifierInterval`]: struct.IdentifierInterval.html
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Identifier(U256);
 
 impl Identifier {
@@ -48,14 +75,54 @@ impl Identifier {
         Identifier(U256::one() << index)
     }
 
-    fn generate(bytes: &[u8]) -> Self {
-        let dig = digest::digest(&digest::SHA256, bytes);
-        Self::new(dig.as_ref())
+    /// Returns the clockwise distance from this identifier to `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chord::routing::identifier::Identifier;
+    /// #
+    /// let id1 = Identifier::new(&[1; 32]);
+    /// let id2 = Identifier::new(&[2; 32]);
+    ///
+    /// assert_eq!(Identifier::new(&[1; 32]), id1.distance_to(id2));
+    /// assert_eq!(Identifier::new(&[0xfe; 32]) + Identifier::with_bit(0), id2.distance_to(id1));
+    /// ```
+    pub fn distance_to(&self, other: Identifier) -> Identifier {
+        other - *self
+    }
+
+    /// Returns the identifier halfway clockwise from this identifier to
+    /// `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chord::routing::identifier::Identifier;
+    /// #
+    /// let id2 = Identifier::new(&[2; 32]);
+    /// let id4 = Identifier::new(&[4; 32]);
+    ///
+    /// assert_eq!(Identifier::new(&[3; 32]), id2.midpoint(id4));
+    /// ```
+    pub fn midpoint(&self, other: Identifier) -> Identifier {
+        *self + Identifier(self.distance_to(other).0 >> 1)
+    }
+
+    /// Returns the identifier `2^index` steps clockwise from this identifier
+    /// which is the start of the finger with that index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` exceeds the bit width of the number.
+    pub fn successor_id(&self, index: usize) -> Identifier {
+        *self + Identifier::with_bit(index)
     }
 
     /// Returns whether this identifier is between `first` and `second` on the
     /// identifier circle.
     ///
+    /// This is a shorthand for [`IdentifierInterval::contains_open_closed`].
     /// The first identifier is excluded from the range while the second one is included.
     /// This method can be written as `self ∈ (first, second]` if `first ≤ second` and as
     /// `self ∈ [0, first) ∪ [second, 0]` if `first > second`.
@@ -74,10 +141,7 @@ impl Identifier {
     /// assert!(!id3.is_between(&id1, &id2));
     /// ```
     pub fn is_between(&self, first: &Identifier, second: &Identifier) -> bool {
-        let (diff1, _) = second.0.overflowing_sub(self.0);
-        let (diff2, _) = second.0.overflowing_sub(first.0);
-
-        diff1 < diff2
+        IdentifierInterval::new(*first, *second).contains_open_closed(*self)
     }
 
     /// Returns the binary logarithm of this identifier minus the given offset.
@@ -118,6 +182,85 @@ impl Identifier {
     }
 }
 
+/// An interval on the identifier circle walked clockwise from start to end
+///
+/// The methods of this struct differ in whether they include the ends of the
+/// interval. If start and end are equal, the interval only contains this
+/// identifier if both ends are included and is empty otherwise.
+///
+/// # Examples
+///
+/// ```
+/// # use chord::routing::identifier::{Identifier, IdentifierInterval};
+/// #
+/// let id1 = Identifier::new(&[1; 32]);
+/// let id2 = Identifier::new(&[2; 32]);
+/// let id3 = Identifier::new(&[3; 32]);
+///
+/// // the interval wraps around at 2^256
+/// let interval = IdentifierInterval::new(id3, id2);
+///
+/// assert!(interval.contains_open_closed(id1));
+/// assert!(interval.contains_open_closed(id2));
+/// assert!(!interval.contains_open_closed(id3));
+/// assert!(interval.contains_closed_open(id3));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct IdentifierInterval {
+    start: Identifier,
+    end: Identifier,
+}
+
+impl IdentifierInterval {
+    /// Creates the interval from `start` to `end` on the identifier circle.
+    pub fn new(start: Identifier, end: Identifier) -> Self {
+        Self { start, end }
+    }
+
+    /// Returns the first identifier of the interval.
+    pub fn start(&self) -> Identifier {
+        self.start
+    }
+
+    /// Returns the last identifier of the interval.
+    pub fn end(&self) -> Identifier {
+        self.end
+    }
+
+    /// Returns the distances of `identifier` and the end from the start.
+    fn offsets(&self, identifier: Identifier) -> (U256, U256) {
+        ((identifier - self.start).0, (self.end - self.start).0)
+    }
+
+    /// Returns whether `identifier ∈ (start, end)`.
+    pub fn contains_open(&self, identifier: Identifier) -> bool {
+        let (offset, len) = self.offsets(identifier);
+
+        !offset.is_zero() && offset < len
+    }
+
+    /// Returns whether `identifier ∈ [start, end]`.
+    pub fn contains_closed(&self, identifier: Identifier) -> bool {
+        let (offset, len) = self.offsets(identifier);
+
+        offset <= len
+    }
+
+    /// Returns whether `identifier ∈ (start, end]`.
+    pub fn contains_open_closed(&self, identifier: Identifier) -> bool {
+        let (offset, len) = self.offsets(identifier);
+
+        !offset.is_zero() && offset <= len
+    }
+
+    /// Returns whether `identifier ∈ [start, end)`.
+    pub fn contains_closed_open(&self, identifier: Identifier) -> bool {
+        let (offset, len) = self.offsets(identifier);
+
+        offset < len
+    }
+}
+
 /// Implement overflowing addition for identifiers
 impl Add for Identifier {
     type Output = Self;
@@ -146,44 +289,54 @@ impl fmt::Display for Identifier {
     }
 }
 
-/// Trait to obtain an identifier from a data structure
-pub trait Identify {
-    /// Generates an identifier for this object.
-    fn identifier(&self) -> Identifier;
-}
-
-/// Obtains an identifier by hashing the four octets of the ip address.
-impl Identify for SocketAddrV4 {
-    fn identifier(&self) -> Identifier {
-        Identifier::generate(self.ip().octets().as_ref())
-    }
-}
+/// Formats the identifier as 64 hex digits.
+impl fmt::LowerHex for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
 
-/// Obtains an identifier by hashing the first eight octets of the ip address.
-impl Identify for SocketAddrV6 {
-    fn identifier(&self) -> Identifier {
-        Identifier::generate(self.ip().octets()[..8].as_ref())
+        write!(f, "{}", hex::encode(self.as_bytes()))
     }
 }
 
-/// Get the identifier for a V4 or V6 socket address.
-impl Identify for SocketAddr {
-    fn identifier(&self) -> Identifier {
-        match self {
-            SocketAddr::V4(v4) => v4.identifier(),
-            SocketAddr::V6(v6) => v6.identifier(),
+/// Parses an identifier from up to 64 hex digits with an optional `0x` prefix.
+///
+/// # Examples
+///
+/// ```
+/// # use chord::routing::identifier::Identifier;
+/// #
+/// let id: Identifier = "0x05".parse().unwrap();
+///
+/// assert_eq!(Identifier::with_bit(0) + Identifier::with_bit(2), id);
+/// assert_eq!(id, format!("{:x}", id).parse().unwrap());
+/// ```
+impl FromStr for Identifier {
+    type Err = ConversionError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let digits = input.strip_prefix("0x").unwrap_or(input);
+
+        if digits.is_empty() || digits.len() > 64 {
+            return Err(ConversionError::new(format!(
+                "identifier {} needs 1 to 64 hex digits",
+                input
+            )));
         }
+
+        let padded = format!("{:0>64}", digits);
+        let bytes = hex::decode(padded)
+            .map_err(|err| ConversionError::new(format!("invalid hex {}: {}", input, err)))?;
+
+        Ok(Identifier::new(&bytes))
     }
 }
 
-/// Hashes the raw key and its replication index.
-impl Identify for Key {
-    fn identifier(&self) -> Identifier {
-        let mut bytes = [0; 33];
-        bytes[..32].copy_from_slice(&self.raw_key);
-        bytes[32] = self.replication_index;
-        Identifier::generate(&bytes)
-    }
+/// Trait to obtain an identifier from a data structure
+pub trait Identify {
+    /// Generates an identifier for this object.
+    fn identifier(&self) -> Identifier;
 }
 
 /// Container for a value and its identifier
@@ -207,6 +360,7 @@ impl<T: Identify> IdentifierValue<T> {
     /// # Examples
     ///
     /// ```
+    /// # #[cfg(feature = "network")] {
     /// # use chord::routing::identifier::{IdentifierValue, Identify};
     /// # use std::net::SocketAddr;
     /// #
@@ -215,6 +369,7 @@ impl<T: Identify> IdentifierValue<T> {
     /// let idv = IdentifierValue::new(value);
     ///
     /// assert_eq!(value.identifier(), idv.identifier());
+    /// # }
     /// ```
     pub fn identifier(&self) -> Identifier {
         self.identifier
@@ -336,4 +491,104 @@ mod tests {
 
         assert_eq!(id1, id3 - id2);
     }
+
+    fn interval(start: u8, end: u8) -> IdentifierInterval {
+        IdentifierInterval::new(Identifier::new(&[start; 32]), Identifier::new(&[end; 32]))
+    }
+
+    #[test]
+    fn interval_ends() {
+        let id1 = Identifier::new(&[1; 32]);
+        let id2 = Identifier::new(&[2; 32]);
+        let interval = interval(1, 2);
+
+        assert!(!interval.contains_open(id1));
+        assert!(!interval.contains_open(id2));
+
+        assert!(interval.contains_closed(id1));
+        assert!(interval.contains_closed(id2));
+
+        assert!(!interval.contains_open_closed(id1));
+        assert!(interval.contains_open_closed(id2));
+
+        assert!(interval.contains_closed_open(id1));
+        assert!(!interval.contains_closed_open(id2));
+    }
+
+    #[test]
+    fn interval_inner_and_outer() {
+        let inner = Identifier::new(&[2; 32]);
+        let outer = Identifier::new(&[4; 32]);
+        let interval = interval(1, 3);
+
+        assert!(interval.contains_open(inner));
+        assert!(interval.contains_closed(inner));
+        assert!(interval.contains_open_closed(inner));
+        assert!(interval.contains_closed_open(inner));
+
+        assert!(!interval.contains_open(outer));
+        assert!(!interval.contains_closed(outer));
+        assert!(!interval.contains_open_closed(outer));
+        assert!(!interval.contains_closed_open(outer));
+    }
+
+    #[test]
+    fn interval_wraps_around() {
+        let interval = interval(3, 1);
+
+        assert!(interval.contains_open(Identifier::new(&[0xff; 32])));
+        assert!(interval.contains_open(Identifier::new(&[0; 32])));
+        assert!(!interval.contains_open(Identifier::new(&[2; 32])));
+    }
+
+    #[test]
+    fn interval_empty() {
+        let id1 = Identifier::new(&[1; 32]);
+        let interval = interval(1, 1);
+
+        assert!(!interval.contains_open(id1));
+        assert!(!interval.contains_open_closed(id1));
+        assert!(!interval.contains_closed_open(id1));
+        assert!(interval.contains_closed(id1));
+        assert!(!interval.contains_closed(Identifier::new(&[2; 32])));
+    }
+
+    #[test]
+    fn identifier_ord() {
+        let id1 = Identifier::new(&[1; 32]);
+        let id2 = Identifier::new(&[2; 32]);
+
+        assert!(id1 < id2);
+        assert_eq!(Some(id2), [id2, id1].iter().copied().max());
+    }
+
+    #[test]
+    fn midpoint_wraps_around() {
+        let start = Identifier::new(&[0xff; 32]);
+        let end = Identifier::with_bit(0) + Identifier::with_bit(1);
+
+        assert_eq!(Identifier::with_bit(0), start.midpoint(end));
+    }
+
+    #[test]
+    fn successor_id() {
+        let id = Identifier::new(&[0xff; 32]);
+
+        assert_eq!(Identifier::new(&[0; 32]), id.successor_id(0));
+    }
+
+    #[test]
+    fn from_str_invalid() {
+        assert!("".parse::<Identifier>().is_err());
+        assert!("0xzz".parse::<Identifier>().is_err());
+        assert!("0".repeat(65).parse::<Identifier>().is_err());
+    }
+
+    #[test]
+    fn lower_hex() {
+        let id = Identifier::new(&[0xab; 32]);
+
+        assert_eq!("ab".repeat(32), format!("{:x}", id));
+        assert_eq!(format!("0x{}", "ab".repeat(32)), format!("{:#x}", id));
+    }
 }
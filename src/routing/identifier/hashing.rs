@@ -0,0 +1,295 @@
+//! Identifiers obtained by hashing socket addresses and storage keys
+//!
+//! This requires the `network` feature since the identifiers are computed
+//! with the SHA256 implementation of `ring`.
+//!
+//! Socket addresses are hashed according to the [`IdentifierScheme`] of the
+//! process. By default, only the ip address is hashed such that peers behind
+//! the same NAT get the same identifier. The `ip-port` scheme includes the
+//! port as well but has to be used by all peers of a network.
+//!
+//! [`IdentifierScheme`]: enum.IdentifierScheme.html
+
+use super::{Identifier, Identify};
+use crate::storage::Key;
+use crate::sync::MutexExt;
+use ring::digest;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+fn generate(bytes: &[u8]) -> Identifier {
+    let dig = digest::digest(&digest::SHA256, bytes);
+    Identifier::new(dig.as_ref())
+}
+
+/// Marks that no scheme has been chosen for the process yet
+const UNSET: u8 = u8::MAX;
+
+/// Scheme of the process, see [`IdentifierScheme::global`]
+///
+/// [`IdentifierScheme::global`]: enum.IdentifierScheme.html#method.global
+static SCHEME: AtomicU8 = AtomicU8::new(UNSET);
+
+/// How the identifier of a peer is obtained from its socket address
+///
+/// All peers of a network have to use the same scheme since every peer
+/// computes the identifiers of the others from their addresses.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum IdentifierScheme {
+    /// Hashes the ip address, or the first eight octets of an ipv6 address,
+    /// such that peers sharing an ip address collide.
+    #[default]
+    Ip = 0,
+    /// Hashes the ip address like `Ip` followed by the port in network byte
+    /// order.
+    IpPort = 1,
+}
+
+impl IdentifierScheme {
+    /// Returns the scheme socket addresses are hashed with in this process,
+    /// which is `Ip` until another one is chosen with [`set_global`].
+    ///
+    /// [`set_global`]: #method.set_global
+    pub fn global() -> Self {
+        match SCHEME.load(Ordering::SeqCst) {
+            1 => IdentifierScheme::IpPort,
+            _ => IdentifierScheme::Ip,
+        }
+    }
+
+    /// Makes this process hash socket addresses with this scheme.
+    ///
+    /// Since all peers of a process share the identifiers of their
+    /// addresses, the scheme can only be chosen once. Choosing a different
+    /// scheme afterwards fails.
+    pub fn set_global(self) -> Result<(), String> {
+        match SCHEME.compare_exchange(UNSET, self as u8, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => Ok(()),
+            Err(current) if current == self as u8 => Ok(()),
+            Err(_) => Err(format!(
+                "identifier scheme `{}` conflicts with the scheme `{}` of this process",
+                self,
+                IdentifierScheme::global()
+            )),
+        }
+    }
+
+    /// Returns the identifier of `socket_addr` according to this scheme.
+    pub fn identifier(self, socket_addr: SocketAddr) -> Identifier {
+        match (self, socket_addr) {
+            (IdentifierScheme::Ip, SocketAddr::V4(v4)) => v4.identifier(),
+            (IdentifierScheme::Ip, SocketAddr::V6(v6)) => v6.identifier(),
+            (IdentifierScheme::IpPort, SocketAddr::V4(v4)) => {
+                let mut bytes = [0; 6];
+                bytes[..4].copy_from_slice(&v4.ip().octets());
+                bytes[4..].copy_from_slice(&v4.port().to_be_bytes());
+                generate(&bytes)
+            }
+            (IdentifierScheme::IpPort, SocketAddr::V6(v6)) => {
+                let mut bytes = [0; 10];
+                bytes[..8].copy_from_slice(&v6.ip().octets()[..8]);
+                bytes[8..].copy_from_slice(&v6.port().to_be_bytes());
+                generate(&bytes)
+            }
+        }
+    }
+}
+
+impl FromStr for IdentifierScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ip" => Ok(IdentifierScheme::Ip),
+            "ip-port" => Ok(IdentifierScheme::IpPort),
+            _ => Err(format!("unknown identifier scheme `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for IdentifierScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IdentifierScheme::Ip => write!(f, "ip"),
+            IdentifierScheme::IpPort => write!(f, "ip-port"),
+        }
+    }
+}
+
+/// Obtains an identifier by hashing the four octets of the ip address.
+impl Identify for SocketAddrV4 {
+    fn identifier(&self) -> Identifier {
+        generate(self.ip().octets().as_ref())
+    }
+}
+
+/// Obtains an identifier by hashing the first eight octets of the ip address.
+impl Identify for SocketAddrV6 {
+    fn identifier(&self) -> Identifier {
+        generate(self.ip().octets()[..8].as_ref())
+    }
+}
+
+/// Get the identifier for a V4 or V6 socket address according to the
+/// [`IdentifierScheme`] of the process.
+///
+/// The identifier is taken from the global [`IdentifierCache`] if possible.
+///
+/// [`IdentifierScheme`]: enum.IdentifierScheme.html
+/// [`IdentifierCache`]: struct.IdentifierCache.html
+impl Identify for SocketAddr {
+    fn identifier(&self) -> Identifier {
+        IdentifierCache::global().identifier(*self)
+    }
+}
+
+/// Maximum number of ip addresses in the global identifier cache
+const CACHE_CAPACITY: usize = 4096;
+
+/// A bounded cache for the identifiers of socket addresses
+///
+/// Routing decisions need the identifiers of the peers involved which are
+/// obtained by hashing their ip addresses. The cache avoids hashing the
+/// addresses of frequently contacted peers again and again. Entries are
+/// stored per scheme and per ip address, or per socket address if the scheme
+/// includes the port.
+pub struct IdentifierCache {
+    capacity: usize,
+    entries: Mutex<HashMap<(IdentifierScheme, SocketAddr), Identifier>>,
+}
+
+impl IdentifierCache {
+    /// Creates an empty cache holding at most `capacity` identifiers.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the cache shared by all socket addresses of this process.
+    pub fn global() -> &'static IdentifierCache {
+        static CACHE: OnceLock<IdentifierCache> = OnceLock::new();
+
+        CACHE.get_or_init(|| IdentifierCache::new(CACHE_CAPACITY))
+    }
+
+    /// Returns the identifier of `socket_addr` according to the global
+    /// scheme, hashing the address only if it is not cached yet.
+    ///
+    /// If the cache is full, an arbitrary entry is evicted.
+    pub fn identifier(&self, socket_addr: SocketAddr) -> Identifier {
+        self.identifier_with(IdentifierScheme::global(), socket_addr)
+    }
+
+    /// Returns the identifier of `socket_addr` according to `scheme`.
+    pub fn identifier_with(&self, scheme: IdentifierScheme, socket_addr: SocketAddr) -> Identifier {
+        let mut entry = (scheme, socket_addr);
+
+        if scheme == IdentifierScheme::Ip {
+            entry.1.set_port(0);
+        }
+
+        if let Some(identifier) = self.entries.lock_or_recover().get(&entry) {
+            return *identifier;
+        }
+
+        let identifier = scheme.identifier(socket_addr);
+
+        let mut entries = self.entries.lock_or_recover();
+
+        if entries.len() >= self.capacity {
+            if let Some(evicted) = entries.keys().next().copied() {
+                entries.remove(&evicted);
+            }
+        }
+
+        entries.insert(entry, identifier);
+
+        identifier
+    }
+
+    /// Returns the number of cached identifiers.
+    pub fn len(&self) -> usize {
+        self.entries.lock_or_recover().len()
+    }
+
+    /// Returns whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock_or_recover().is_empty()
+    }
+}
+
+/// Hashes the raw key and its replication index.
+impl Identify for Key {
+    fn identifier(&self) -> Identifier {
+        let mut bytes = [0; 33];
+        bytes[..32].copy_from_slice(&self.raw_key);
+        bytes[32] = self.replication_index;
+        generate(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifier_cache() {
+        let cache = IdentifierCache::new(2);
+        let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+
+        let SocketAddr::V4(v4) = addr else {
+            unreachable!()
+        };
+
+        assert_eq!(v4.identifier(), cache.identifier(addr));
+        assert_eq!(v4.identifier(), cache.identifier(addr));
+        assert_eq!(1, cache.len());
+    }
+
+    #[test]
+    fn identifier_cache_schemes() {
+        let cache = IdentifierCache::new(4);
+        let first: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let second: SocketAddr = "127.0.0.1:8081".parse().unwrap();
+
+        assert_eq!(
+            cache.identifier_with(IdentifierScheme::Ip, first),
+            cache.identifier_with(IdentifierScheme::Ip, second)
+        );
+        assert_ne!(
+            cache.identifier_with(IdentifierScheme::IpPort, first),
+            cache.identifier_with(IdentifierScheme::IpPort, second)
+        );
+        assert_ne!(
+            cache.identifier_with(IdentifierScheme::Ip, first),
+            cache.identifier_with(IdentifierScheme::IpPort, first)
+        );
+        assert_eq!(3, cache.len());
+    }
+
+    #[test]
+    fn identifier_scheme_names() {
+        for scheme in [IdentifierScheme::Ip, IdentifierScheme::IpPort] {
+            assert_eq!(Ok(scheme), scheme.to_string().parse());
+        }
+
+        assert!("port".parse::<IdentifierScheme>().is_err());
+    }
+
+    #[test]
+    fn identifier_cache_capacity() {
+        let cache = IdentifierCache::new(2);
+
+        for i in 1..=3 {
+            cache.identifier(SocketAddr::new([127, 0, 0, i].into(), 8080));
+        }
+
+        assert_eq!(2, cache.len());
+    }
+}
@@ -13,17 +13,49 @@
 //! allows us to find the responsible peer for an arbitrary identifier in
 //! O(log(N)) steps where N is the size of the whole network.
 //!
+//! [`Routing::check_fingers`] compares every finger with the identifier range
+//! it should cover to diagnose a routing table which is out of date.
+//!
+//! The routing table also remembers when the successor and each finger were
+//! last verified, i.e. set after a lookup or seen replying. Fingers which have
+//! not been verified for longer than [`DEFAULT_STALE_AFTER`] are stale: lookups
+//! route around them through closer fingers which were verified recently and
+//! stabilization refreshes them first.
+//!
+//! Peers are usually described by a [`PeerInfo`] which keeps the identifier
+//! of the peer along with information collected while talking to it.
+//!
 //! [`Identifier`]: identifier/struct.Identifier.html
 //! [`Routing`]: struct.Routing.html
+//! [`PeerInfo`]: peer/struct.PeerInfo.html
+//! [`Routing::check_fingers`]: struct.Routing.html#method.check_fingers
+//! [`DEFAULT_STALE_AFTER`]: constant.DEFAULT_STALE_AFTER.html
 
+#[cfg(feature = "std")]
 use self::identifier::*;
+#[cfg(feature = "std")]
+use self::peer::PeerInfo;
+#[cfg(feature = "std")]
+use std::net::SocketAddr;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
 
 pub mod identifier;
+#[cfg(feature = "std")]
+pub mod peer;
+mod uint;
+
+/// Time after which an entry which has not been verified again is stale
+#[cfg(feature = "std")]
+pub const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(300);
 
 /// This struct stores routing information about other peers.
 ///
-/// The type parameter `T` is used to describe the identifying property of a
-/// peer, for example its socket address.
+/// The type parameter `T` is used to describe a peer, for example by its
+/// socket address or by a [`PeerInfo`].
+///
+/// [`PeerInfo`]: peer/struct.PeerInfo.html
+#[cfg(feature = "std")]
 #[derive(Debug)]
 pub struct Routing<T> {
     /// Address where this peer is listening for peer-to-peer messages
@@ -35,39 +67,104 @@ pub struct Routing<T> {
     pub successor: IdentifierValue<T>,
     /// The finger table of this peer with pointers accross the network
     finger_table: Vec<IdentifierValue<T>>,
+    /// When the successor was last verified
+    successor_verified: Instant,
+    /// When each finger was last verified
+    fingers_verified: Vec<Instant>,
+    /// Time after which an entry which has not been verified again is stale
+    stale_after: Duration,
+}
+
+/// Entry of the routing table a lookup is routed through
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slot {
+    /// The peer itself is responsible for the identifier
+    Current,
+    Predecessor,
+    Successor,
+    /// The finger with the given index in the finger table
+    Finger(usize),
+}
+
+/// The identifier range a finger should cover and how well it does so
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct FingerCheck<T> {
+    /// Index of the finger in the finger table
+    pub index: usize,
+    /// Identifiers `[start, end)` which are routed via this finger
+    pub range: IdentifierInterval,
+    /// The peer currently stored as finger
+    pub finger: IdentifierValue<T>,
+    /// When the finger was last verified
+    pub verified: Instant,
+    /// The reason why the finger does not cover its range, if any
+    pub discrepancy: Option<FingerDiscrepancy<T>>,
+}
+
+/// Reason why a finger does not cover its identifier range
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub enum FingerDiscrepancy<T> {
+    /// The finger lies between this peer and the start of its range, thus it
+    /// is not responsible for any identifier in the range.
+    BeforeRange,
+    /// Another known peer follows the start of the range more closely and
+    /// should be the finger instead.
+    CloserPeer(IdentifierValue<T>),
 }
 
-impl<T: Identify + Copy + Clone> Routing<T> {
+#[cfg(feature = "std")]
+impl<T: Identify + Clone> Routing<T> {
     /// Creates a new `Routing` instance for the given initial values.
+    ///
+    /// The initial values count as verified now.
     pub fn new(current: T, predecessor: T, successor: T, finger_table: Vec<T>) -> Self {
+        let now = Instant::now();
+
         Self {
             current: IdentifierValue::new(current),
             predecessor: IdentifierValue::new(predecessor),
             successor: IdentifierValue::new(successor),
+            fingers_verified: vec![now; finger_table.len()],
             finger_table: finger_table.into_iter().map(IdentifierValue::new).collect(),
+            successor_verified: now,
+            stale_after: DEFAULT_STALE_AFTER,
         }
     }
 
+    /// Sets the time after which an entry which has not been verified again
+    /// is stale.
+    pub fn set_stale_after(&mut self, stale_after: Duration) {
+        self.stale_after = stale_after;
+    }
+
     /// Sets the predecessor's address.
     pub fn set_predecessor(&mut self, new_pred: T) {
         self.predecessor = IdentifierValue::new(new_pred);
     }
 
-    /// Sets the current successor.
+    /// Sets the current successor which counts as verified now.
     pub fn set_successor(&mut self, new_succ: T) {
+        let now = Instant::now();
+
         self.successor = IdentifierValue::new(new_succ);
+        self.successor_verified = now;
 
         // update finger table so that all fingers closer than successor point to successor
         let diff = self.successor.identifier() - self.current.identifier();
 
         for i in diff.leading_zeros() as usize..self.finger_table.len() {
-            self.finger_table[i] = self.successor;
+            self.finger_table[i] = self.successor.clone();
+            self.fingers_verified[i] = now;
         }
     }
 
-    /// Sets the finger for the given index.
+    /// Sets the finger for the given index which counts as verified now.
     pub fn set_finger(&mut self, index: usize, finger: T) {
         self.finger_table[index] = IdentifierValue::new(finger);
+        self.fingers_verified[index] = Instant::now();
     }
 
     /// Returns the number of fingers.
@@ -75,20 +172,619 @@ impl<T: Identify + Copy + Clone> Routing<T> {
         self.finger_table.len()
     }
 
+    /// Returns the finger with the given index if there is one.
+    pub fn finger(&self, index: usize) -> Option<&IdentifierValue<T>> {
+        self.finger_table.get(index)
+    }
+
+    /// Returns when the successor was last verified.
+    pub fn successor_verified(&self) -> Instant {
+        self.successor_verified
+    }
+
+    /// Returns when the finger with the given index was last verified if
+    /// there is one.
+    pub fn finger_verified(&self, index: usize) -> Option<Instant> {
+        self.fingers_verified.get(index).copied()
+    }
+
+    /// Checks whether the finger with the given index has not been verified
+    /// for longer than the stale period at `now`.
+    pub fn is_stale(&self, index: usize, now: Instant) -> bool {
+        self.fingers_verified
+            .get(index)
+            .is_some_and(|&verified| self.is_stale_since(verified, now))
+    }
+
+    /// Returns the indices of all fingers in the order they should be
+    /// refreshed at `now`.
+    ///
+    /// Stale fingers come first starting with the one verified the longest
+    /// time ago, followed by the remaining fingers in the order of their
+    /// index.
+    pub fn refresh_order(&self, now: Instant) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.fingers()).collect();
+
+        // the sort is stable, thus fresh fingers keep the order of their index
+        order.sort_by_key(|&index| {
+            let verified = self.fingers_verified[index];
+
+            if self.is_stale_since(verified, now) {
+                (0, Some(verified))
+            } else {
+                (1, None)
+            }
+        });
+
+        order
+    }
+
+    fn is_stale_since(&self, verified: Instant, now: Instant) -> bool {
+        now.saturating_duration_since(verified) > self.stale_after
+    }
+
+    /// Returns the interval `(predecessor, current]` this peer is
+    /// responsible for.
+    pub fn range(&self) -> IdentifierInterval {
+        IdentifierInterval::new(self.predecessor.identifier(), self.current.identifier())
+    }
+
     /// Checks whether this peer is responsible for the given identifier.
+    ///
+    /// A peer which is its own predecessor is the only peer in the network
+    /// and thus responsible for all identifiers.
     pub fn responsible_for(&self, identifier: Identifier) -> bool {
-        identifier.is_between(&self.predecessor.identifier(), &self.current.identifier())
+        let range = self.range();
+
+        range.start() == range.end() || range.contains_open_closed(identifier)
+    }
+
+    /// Computes the identifier range of each finger and checks whether the
+    /// stored peer covers it.
+    ///
+    /// The finger with index `i` is used for all identifiers in
+    /// `[current + 2^(255 - i), current + 2^(256 - i))`. It should be the
+    /// first peer at or after the start of that range. Since only the peers
+    /// in this routing table are known, a finger is reported if one of them
+    /// follows the start more closely.
+    pub fn check_fingers(&self) -> Vec<FingerCheck<T>> {
+        let current_id = self.current.identifier();
+
+        let known_peers: Vec<&IdentifierValue<T>> = std::iter::once(&self.current)
+            .chain(std::iter::once(&self.predecessor))
+            .chain(std::iter::once(&self.successor))
+            .chain(self.finger_table.iter())
+            .collect();
+
+        self.finger_table
+            .iter()
+            .enumerate()
+            .map(|(index, finger)| {
+                // TODO do not hardcode for 256 bits here
+                let start = current_id.successor_id(255 - index);
+                let end = match index {
+                    0 => current_id,
+                    _ => current_id.successor_id(256 - index),
+                };
+                let range = IdentifierInterval::new(start, end);
+
+                let closest = known_peers
+                    .iter()
+                    .min_by_key(|peer| start.distance_to(peer.identifier()))
+                    .expect("routing table always contains the current peer");
+
+                let discrepancy = if IdentifierInterval::new(current_id, start)
+                    .contains_open(finger.identifier())
+                {
+                    Some(FingerDiscrepancy::BeforeRange)
+                } else if start.distance_to(closest.identifier())
+                    < start.distance_to(finger.identifier())
+                {
+                    Some(FingerDiscrepancy::CloserPeer((*closest).clone()))
+                } else {
+                    None
+                };
+
+                FingerCheck {
+                    index,
+                    range,
+                    finger: finger.clone(),
+                    verified: self.fingers_verified[index],
+                    discrepancy,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the successor of the given identifier, i.e. the peer
+    /// responsible for it, as far as this peer knows, along with whether the
+    /// answer is authoritative.
+    ///
+    /// Only the answer of the responsible peer itself is authoritative. Peers
+    /// join at their successor, which takes them as predecessor right away,
+    /// while their predecessor keeps its old successor until it stabilizes.
+    /// For an identifier between this peer and its successor, the successor
+    /// is returned as the owner this peer knows of. Otherwise the closest
+    /// peer is returned to continue the lookup with.
+    pub fn find_successor(&self, identifier: Identifier) -> (&IdentifierValue<T>, bool) {
+        if self.responsible_for(identifier) {
+            return (&self.current, true);
+        }
+
+        let successor_range =
+            IdentifierInterval::new(self.current.identifier(), self.successor.identifier());
+
+        if successor_range.contains_open_closed(identifier) {
+            return (&self.successor, false);
+        }
+
+        (self.closest_peer(identifier), false)
     }
 
     /// Returns the peer closest to the given identifier.
+    ///
+    /// The predecessor is preferred over the finger if it lies between the
+    /// identifier and the finger. This happens if peers joined in front of
+    /// us before the fingers have been stabilized and avoids that lookups
+    /// bounce between peers which do not know about the new peers yet.
     pub fn closest_peer(&self, identifier: Identifier) -> &IdentifierValue<T> {
+        self.closest_peer_at(identifier, Instant::now())
+    }
+
+    /// Returns the peer closest to the given identifier at `now`.
+    ///
+    /// A stale finger is skipped in favour of the next closer finger, or the
+    /// successor, which has been verified recently and does not lie beyond
+    /// the identifier. The lookup then takes more steps but is less likely to
+    /// run into a peer which has left.
+    pub fn closest_peer_at(&self, identifier: Identifier, now: Instant) -> &IdentifierValue<T> {
+        self.route_at(identifier, now).1
+    }
+
+    /// Returns the peer closest to the given identifier along with the entry
+    /// of the routing table it was taken from.
+    ///
+    /// See [`closest_peer`] for how the peer is selected.
+    ///
+    /// [`closest_peer`]: #method.closest_peer
+    pub fn route(&self, identifier: Identifier) -> (Slot, &IdentifierValue<T>) {
+        self.route_at(identifier, Instant::now())
+    }
+
+    /// Returns the peer closest to the given identifier at `now` along with
+    /// the entry of the routing table it was taken from.
+    pub fn route_at(&self, identifier: Identifier, now: Instant) -> (Slot, &IdentifierValue<T>) {
         if self.responsible_for(identifier) {
-            return &self.current;
+            return (Slot::Current, &self.current);
         }
 
         let diff = identifier - self.current.identifier();
         let zeros = diff.leading_zeros() as usize;
 
-        self.finger_table.get(zeros).unwrap_or(&self.successor)
+        let (slot, finger) = match self.finger_table.get(zeros) {
+            Some(finger) => (Slot::Finger(zeros), finger),
+            None => (Slot::Successor, &self.successor),
+        };
+
+        if self.is_stale(zeros, now) {
+            let before = IdentifierInterval::new(self.current.identifier(), identifier);
+
+            let fresh = (zeros + 1..self.fingers())
+                .map(|index| {
+                    (
+                        Slot::Finger(index),
+                        &self.finger_table[index],
+                        self.fingers_verified[index],
+                    )
+                })
+                .chain(std::iter::once((
+                    Slot::Successor,
+                    &self.successor,
+                    self.successor_verified,
+                )))
+                .find(|&(_, peer, verified)| {
+                    !self.is_stale_since(verified, now)
+                        && before.contains_open_closed(peer.identifier())
+                });
+
+            // the alternative lies in front of the identifier, thus the
+            // predecessor cannot be any closer
+            if let Some((slot, peer, _)) = fresh {
+                return (slot, peer);
+            }
+        }
+
+        let interval = IdentifierInterval::new(identifier, finger.identifier());
+
+        if interval.contains_closed_open(self.predecessor.identifier()) {
+            return (Slot::Predecessor, &self.predecessor);
+        }
+
+        (slot, finger)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Routing<PeerInfo> {
+    /// Creates a new `Routing` instance from socket addresses only.
+    #[cfg(feature = "network")]
+    pub fn from_addrs(
+        current: SocketAddr,
+        predecessor: SocketAddr,
+        successor: SocketAddr,
+        finger_table: Vec<SocketAddr>,
+    ) -> Self {
+        Self::new(
+            PeerInfo::new(current),
+            PeerInfo::new(predecessor),
+            PeerInfo::new(successor),
+            finger_table.into_iter().map(PeerInfo::new).collect(),
+        )
+    }
+
+    /// Returns the information about the peer at `socket_addr`.
+    ///
+    /// Known peers are taken from the routing table such that their
+    /// identifier does not have to be computed again.
+    #[cfg(feature = "network")]
+    pub fn peer(&self, socket_addr: SocketAddr) -> PeerInfo {
+        self.peers()
+            .find(|peer| peer.socket_addr() == socket_addr)
+            .map_or_else(|| PeerInfo::new(socket_addr), |peer| (**peer).clone())
+    }
+
+    /// Records that the peer at `socket_addr` replied to a request after
+    /// `rtt` in all entries referring to it.
+    ///
+    /// The successor and fingers referring to it count as verified now.
+    pub fn seen(&mut self, socket_addr: SocketAddr, rtt: Duration) {
+        let now = Instant::now();
+
+        if self.successor.socket_addr() == socket_addr {
+            self.successor_verified = now;
+        }
+
+        for (finger, verified) in self.finger_table.iter().zip(&mut self.fingers_verified) {
+            if finger.socket_addr() == socket_addr {
+                *verified = now;
+            }
+        }
+
+        let entries = std::iter::once(&mut self.current)
+            .chain(std::iter::once(&mut self.predecessor))
+            .chain(std::iter::once(&mut self.successor))
+            .chain(self.finger_table.iter_mut());
+
+        for entry in entries {
+            if entry.socket_addr() == socket_addr {
+                let mut peer = (**entry).clone();
+                peer.seen(rtt);
+
+                *entry = IdentifierValue::new(peer);
+            }
+        }
+    }
+
+    /// Replaces all fingers referring to the peer at `socket_addr` by the
+    /// peer at `replacement`, for example after the former left the network.
+    ///
+    /// The replaced fingers keep when they were last verified since the
+    /// replacement has not been looked up for them.
+    #[cfg(feature = "network")]
+    pub fn replace_finger(&mut self, socket_addr: SocketAddr, replacement: SocketAddr) {
+        let peer = self.peer(replacement);
+
+        for finger in self.finger_table.iter_mut() {
+            if finger.socket_addr() == socket_addr {
+                *finger = IdentifierValue::new(peer.clone());
+            }
+        }
+    }
+
+    /// Removes the peer at `socket_addr` which is down from the successor
+    /// and the fingers.
+    ///
+    /// The closest finger pointing to another peer takes its place, or this
+    /// peer itself if no other peer is known.
+    #[cfg(feature = "network")]
+    pub fn remove_peer(&mut self, socket_addr: SocketAddr) {
+        let current_addr = self.current.socket_addr();
+
+        let replacement = self
+            .finger_table
+            .iter()
+            .rev()
+            .map(|finger| finger.socket_addr())
+            .find(|&finger_addr| finger_addr != socket_addr && finger_addr != current_addr)
+            .unwrap_or(current_addr);
+
+        self.replace_finger(socket_addr, replacement);
+
+        if self.successor.socket_addr() == socket_addr {
+            let peer = self.peer(replacement);
+            self.set_successor(peer);
+        }
+    }
+
+    #[cfg(feature = "network")]
+    fn peers(&self) -> impl Iterator<Item = &IdentifierValue<PeerInfo>> {
+        std::iter::once(&self.current)
+            .chain(std::iter::once(&self.predecessor))
+            .chain(std::iter::once(&self.successor))
+            .chain(self.finger_table.iter())
+    }
+}
+
+#[cfg(all(test, feature = "network"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responsible_for_single_peer() {
+        let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let routing = Routing::new(addr, addr, addr, vec![addr; 4]);
+
+        assert!(routing.responsible_for(Identifier::new(&[0; 32])));
+        assert!(routing.responsible_for(addr.identifier()));
+    }
+
+    #[test]
+    fn responsible_for() {
+        let current: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let predecessor: SocketAddr = "127.0.0.2:8080".parse().unwrap();
+        let routing = Routing::new(current, predecessor, predecessor, vec![predecessor; 4]);
+
+        assert!(routing.responsible_for(current.identifier()));
+        assert!(!routing.responsible_for(predecessor.identifier()));
+    }
+
+    #[test]
+    fn find_successor() {
+        let mut addrs: Vec<SocketAddr> = vec![
+            "127.0.0.1:8080".parse().unwrap(),
+            "127.0.0.2:8080".parse().unwrap(),
+            "127.0.0.3:8080".parse().unwrap(),
+        ];
+        addrs.sort_by_key(|addr| addr.identifier());
+        let (predecessor, current, successor) = (addrs[0], addrs[1], addrs[2]);
+        let routing = Routing::new(current, predecessor, successor, vec![successor; 4]);
+
+        let (peer, authoritative) = routing.find_successor(current.identifier());
+        assert_eq!(current, **peer);
+        assert!(authoritative);
+
+        let (peer, authoritative) = routing.find_successor(successor.identifier());
+        assert_eq!(successor, **peer);
+        assert!(!authoritative);
+
+        let (peer, authoritative) = routing.find_successor(predecessor.identifier());
+        assert_eq!(predecessor, **peer);
+        assert!(!authoritative);
+    }
+
+    #[test]
+    fn find_successor_with_stale_fingers() {
+        let current: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let successor: SocketAddr = "127.0.0.2:8080".parse().unwrap();
+
+        // a peer which created the network learns about the first joining
+        // peer but its fingers still point to itself
+        let routing = Routing::new(current, successor, successor, vec![current; 4]);
+
+        let (peer, authoritative) = routing.find_successor(successor.identifier());
+        assert_eq!(successor, **peer);
+        assert!(!authoritative);
+    }
+
+    #[test]
+    fn closest_peer_prefers_predecessor() {
+        let current: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let successor: SocketAddr = "127.0.0.2:8080".parse().unwrap();
+        let predecessor: SocketAddr = "127.0.0.3:8080".parse().unwrap();
+
+        // the fingers do not know about the predecessor which joined recently
+        let routing = Routing::new(current, predecessor, successor, vec![successor; 4]);
+
+        let closest_peer = routing.closest_peer(predecessor.identifier());
+
+        assert_eq!(predecessor, **closest_peer);
+    }
+
+    #[test]
+    fn route_reports_slot() {
+        let mut routing = stale_routing();
+        let identifier = peer(0x90).identifier();
+
+        assert_eq!(Slot::Current, routing.route(peer(0).identifier()).0);
+        assert_eq!(Slot::Finger(0), routing.route(identifier).0);
+        assert_eq!(Slot::Predecessor, routing.route(peer(0xb0).identifier()).0);
+
+        // the stale first finger is skipped in favour of the second one
+        let now = Instant::now() + DEFAULT_STALE_AFTER * 2;
+        routing.fingers_verified[1] = now;
+
+        assert_eq!(Slot::Finger(1), routing.route_at(identifier, now).0);
+    }
+
+    fn peer(first_byte: u8) -> PeerInfo {
+        let mut id = [0; 32];
+        id[0] = first_byte;
+
+        let socket_addr = SocketAddr::from(([127, 0, 0, first_byte], 8080));
+
+        PeerInfo::with_identifier(socket_addr, Identifier::new(&id))
+    }
+
+    /// A routing table whose first finger overshoots the identifier `0x90..`
+    /// while all others point to the successor in front of it
+    fn stale_routing() -> Routing<PeerInfo> {
+        let mut fingers = vec![peer(0x40); 256];
+        fingers[0] = peer(0xa0);
+
+        Routing::new(peer(0), peer(0xc0), peer(0x40), fingers)
+    }
+
+    #[test]
+    fn closest_peer_skips_stale_finger() {
+        let mut routing = stale_routing();
+        let identifier = peer(0x90).identifier();
+
+        assert_eq!(
+            peer(0xa0).socket_addr(),
+            routing.closest_peer(identifier).socket_addr()
+        );
+
+        // every entry but the first finger was verified again later
+        let now = Instant::now() + DEFAULT_STALE_AFTER * 2;
+        routing.successor_verified = now;
+        for verified in &mut routing.fingers_verified[1..] {
+            *verified = now;
+        }
+
+        assert!(routing.is_stale(0, now));
+        assert_eq!(
+            peer(0x40).socket_addr(),
+            routing.closest_peer_at(identifier, now).socket_addr()
+        );
+    }
+
+    #[test]
+    fn closest_peer_keeps_stale_finger_without_alternative() {
+        let routing = stale_routing();
+        let identifier = peer(0x90).identifier();
+
+        let now = Instant::now() + DEFAULT_STALE_AFTER * 2;
+
+        assert_eq!(
+            peer(0xa0).socket_addr(),
+            routing.closest_peer_at(identifier, now).socket_addr()
+        );
+    }
+
+    #[test]
+    fn refresh_order_stale_first() {
+        let mut routing = stale_routing();
+        let now = Instant::now() + DEFAULT_STALE_AFTER * 2;
+
+        for verified in &mut routing.fingers_verified {
+            *verified = now;
+        }
+        routing.fingers_verified[7] = now - DEFAULT_STALE_AFTER * 2;
+        routing.fingers_verified[3] = now - DEFAULT_STALE_AFTER * 3 / 2;
+
+        let order = routing.refresh_order(now);
+
+        assert_eq!(&[7, 3, 0, 1, 2, 4], &order[..6]);
+        assert_eq!(256, order.len());
+    }
+
+    #[test]
+    fn seen_verifies_entries() {
+        let mut routing = stale_routing();
+        let before = Instant::now();
+
+        routing.seen(peer(0x40).socket_addr(), Duration::from_millis(3));
+
+        assert!(routing.successor_verified() >= before);
+        assert!(routing.finger_verified(1).unwrap() >= before);
+        assert!(routing.finger_verified(0).unwrap() < before);
+    }
+
+    #[test]
+    fn seen_updates_all_entries() {
+        let current: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let successor: SocketAddr = "127.0.0.2:8080".parse().unwrap();
+        let mut routing = Routing::from_addrs(current, successor, successor, vec![successor; 4]);
+
+        routing.seen(successor, Duration::from_millis(3));
+
+        assert_eq!(Some(Duration::from_millis(3)), routing.successor.rtt());
+        assert_eq!(
+            Some(Duration::from_millis(3)),
+            routing.peer(successor).rtt()
+        );
+        assert_eq!(None, routing.current.rtt());
+    }
+
+    #[test]
+    fn replace_finger() {
+        let current: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let leaving: SocketAddr = "127.0.0.2:8080".parse().unwrap();
+        let successor: SocketAddr = "127.0.0.3:8080".parse().unwrap();
+        let mut routing = Routing::from_addrs(
+            current,
+            successor,
+            successor,
+            vec![leaving, successor, leaving],
+        );
+
+        routing.replace_finger(leaving, successor);
+
+        assert!(routing.peers().all(|peer| peer.socket_addr() != leaving));
+    }
+
+    #[test]
+    fn remove_peer() {
+        let current: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let dead: SocketAddr = "127.0.0.2:8080".parse().unwrap();
+        let finger: SocketAddr = "127.0.0.3:8080".parse().unwrap();
+        let mut routing =
+            Routing::from_addrs(current, finger, dead, vec![finger, finger, dead, dead]);
+
+        routing.remove_peer(dead);
+
+        assert_eq!(finger, routing.successor.socket_addr());
+        assert!(routing.peers().all(|peer| peer.socket_addr() != dead));
+    }
+
+    #[test]
+    fn check_fingers_single_peer() {
+        let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let routing = Routing::new(addr, addr, addr, vec![addr; 4]);
+
+        let checks = routing.check_fingers();
+
+        assert_eq!(4, checks.len());
+        assert!(checks.iter().all(|check| check.discrepancy.is_none()));
+        assert_eq!(addr.identifier(), checks[0].range.end());
+        assert_eq!(checks[0].range.start(), checks[1].range.end());
+    }
+
+    #[test]
+    fn check_fingers_closer_peer() {
+        let current: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let successor: SocketAddr = "127.0.0.2:8080".parse().unwrap();
+
+        // the successor is known but the fingers still point to ourselves
+        let routing = Routing::new(current, successor, successor, vec![current; 256]);
+
+        let checks = routing.check_fingers();
+
+        // the last finger covers only the identifier after our own
+        match checks[255].discrepancy {
+            Some(FingerDiscrepancy::CloserPeer(ref peer)) => assert_eq!(successor, **peer),
+            ref other => panic!("unexpected discrepancy {:?}", other),
+        }
+    }
+
+    #[test]
+    fn check_fingers_before_range() {
+        let current: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let successor: SocketAddr = "127.0.0.2:8080".parse().unwrap();
+
+        let routing = Routing::new(current, successor, successor, vec![successor; 256]);
+
+        // the successor is used for the fingers from this index on
+        let diff = successor.identifier() - current.identifier();
+        let zeros = diff.leading_zeros() as usize;
+        assert!(zeros > 0);
+
+        for check in routing.check_fingers() {
+            match check.discrepancy {
+                Some(FingerDiscrepancy::BeforeRange) => assert!(check.index < zeros),
+                None => assert!(check.index >= zeros),
+                ref other => panic!("unexpected discrepancy {:?}", other),
+            }
+        }
     }
 }
@@ -0,0 +1,109 @@
+//! Information about other peers kept in the routing table
+//!
+//! A [`PeerInfo`] bundles the socket address of a peer with its identifier
+//! such that the identifier only has to be computed once when the peer is
+//! first seen. Messages only carry the socket address of a peer, the
+//! remaining fields are collected locally while talking to the peer.
+//!
+//! [`PeerInfo`]: struct.PeerInfo.html
+
+use super::identifier::{Identifier, Identify};
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Everything we know about another peer
+#[derive(Clone, Debug)]
+pub struct PeerInfo {
+    socket_addr: SocketAddr,
+    identifier: Identifier,
+    last_seen: Option<Instant>,
+    rtt: Option<Duration>,
+}
+
+impl PeerInfo {
+    /// Creates information about the peer listening on `socket_addr` which
+    /// has not been contacted yet.
+    #[cfg(feature = "network")]
+    pub fn new(socket_addr: SocketAddr) -> Self {
+        Self::with_identifier(socket_addr, socket_addr.identifier())
+    }
+
+    /// Creates information about a peer whose identifier has been obtained
+    /// elsewhere, for example from the transport it is reachable with.
+    pub fn with_identifier(socket_addr: SocketAddr, identifier: Identifier) -> Self {
+        Self {
+            socket_addr,
+            identifier,
+            last_seen: None,
+            rtt: None,
+        }
+    }
+
+    /// Records that the peer replied to a request after `rtt`.
+    pub fn seen(&mut self, rtt: Duration) {
+        self.last_seen = Some(Instant::now());
+        self.rtt = Some(rtt);
+    }
+
+    /// Returns the address where the peer listens for peer-to-peer messages.
+    pub fn socket_addr(&self) -> SocketAddr {
+        self.socket_addr
+    }
+
+    /// Returns when the peer replied to a request for the last time.
+    pub fn last_seen(&self) -> Option<Instant> {
+        self.last_seen
+    }
+
+    /// Returns the round-trip time of the last request to the peer.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+}
+
+impl Identify for PeerInfo {
+    fn identifier(&self) -> Identifier {
+        self.identifier
+    }
+}
+
+#[cfg(feature = "network")]
+impl From<SocketAddr> for PeerInfo {
+    fn from(socket_addr: SocketAddr) -> Self {
+        Self::new(socket_addr)
+    }
+}
+
+impl fmt::Display for PeerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.socket_addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "network")]
+    fn identifier_of_address() {
+        let socket_addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let peer = PeerInfo::new(socket_addr);
+
+        assert_eq!(socket_addr.identifier(), peer.identifier());
+    }
+
+    #[test]
+    fn seen() {
+        let identifier = Identifier::new(&[1; 32]);
+        let mut peer = PeerInfo::with_identifier("127.0.0.1:8080".parse().unwrap(), identifier);
+
+        assert_eq!(None, peer.last_seen());
+
+        peer.seen(Duration::from_millis(5));
+
+        assert!(peer.last_seen().is_some());
+        assert_eq!(Some(Duration::from_millis(5)), peer.rtt());
+    }
+}
@@ -0,0 +1,285 @@
+//! Unsigned 256 bit integers with the operations needed for identifiers
+//!
+//! The limbs are stored with the most significant limb first such that the
+//! derived ordering compares the numeric values.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Shl, Shr};
+
+/// An unsigned 256 bit integer made of four 64 bit limbs
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct U256([u64; 4]);
+
+impl U256 {
+    /// Interprets exactly 32 bytes as big endian number.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slice does not contain exactly 32 elements.
+    pub fn from_big_endian(bytes: &[u8]) -> Self {
+        assert_eq!(32, bytes.len(), "a 256 bit integer needs 32 bytes");
+
+        let mut limbs = [0; 4];
+
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks(8)) {
+            let mut buf = [0; 8];
+            buf.copy_from_slice(chunk);
+            *limb = u64::from_be_bytes(buf);
+        }
+
+        U256(limbs)
+    }
+
+    /// Writes the number as big endian into exactly 32 bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slice does not contain exactly 32 elements.
+    pub fn to_big_endian(self, bytes: &mut [u8]) {
+        assert_eq!(32, bytes.len(), "a 256 bit integer needs 32 bytes");
+
+        for (limb, chunk) in self.0.iter().zip(bytes.chunks_mut(8)) {
+            chunk.copy_from_slice(&limb.to_be_bytes());
+        }
+    }
+
+    pub fn one() -> Self {
+        U256([0, 0, 0, 1])
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    pub fn leading_zeros(&self) -> u32 {
+        let mut zeros = 0;
+
+        for limb in &self.0 {
+            zeros += limb.leading_zeros();
+
+            if *limb != 0 {
+                break;
+            }
+        }
+
+        zeros
+    }
+
+    /// Adds `other` modulo 2^256 and returns whether an overflow occurred.
+    pub fn overflowing_add(self, other: U256) -> (U256, bool) {
+        let mut limbs = [0; 4];
+        let mut carry = false;
+
+        for i in (0..4).rev() {
+            let (sum, overflow1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, overflow2) = sum.overflowing_add(u64::from(carry));
+
+            limbs[i] = sum;
+            carry = overflow1 || overflow2;
+        }
+
+        (U256(limbs), carry)
+    }
+
+    /// Subtracts `other` modulo 2^256 and returns whether an underflow
+    /// occurred.
+    pub fn overflowing_sub(self, other: U256) -> (U256, bool) {
+        let mut limbs = [0; 4];
+        let mut borrow = false;
+
+        for i in (0..4).rev() {
+            let (diff, overflow1) = self.0[i].overflowing_sub(other.0[i]);
+            let (diff, overflow2) = diff.overflowing_sub(u64::from(borrow));
+
+            limbs[i] = diff;
+            borrow = overflow1 || overflow2;
+        }
+
+        (U256(limbs), borrow)
+    }
+
+    /// Divides by a small divisor and returns the quotient and remainder.
+    fn div_rem_u64(self, divisor: u64) -> (U256, u64) {
+        let mut limbs = [0; 4];
+        let mut rem = 0u128;
+
+        for (i, limb) in self.0.iter().enumerate() {
+            let value = (rem << 64) | u128::from(*limb);
+
+            limbs[i] = (value / u128::from(divisor)) as u64;
+            rem = value % u128::from(divisor);
+        }
+
+        (U256(limbs), rem as u64)
+    }
+}
+
+/// Shifts the bits to the left, dropping bits shifted out.
+///
+/// # Panics
+///
+/// Panics if `shift` is not below 256 like the shift of built-in integers.
+impl Shl<usize> for U256 {
+    type Output = U256;
+
+    fn shl(self, shift: usize) -> U256 {
+        assert!(shift < 256, "attempt to shift left with overflow");
+
+        let limb_shift = shift / 64;
+        let bit_shift = shift % 64;
+        let mut limbs = [0; 4];
+
+        for (i, limb) in limbs.iter_mut().take(4 - limb_shift).enumerate() {
+            let src = i + limb_shift;
+            *limb = self.0[src] << bit_shift;
+
+            if bit_shift > 0 && src + 1 < 4 {
+                *limb |= self.0[src + 1] >> (64 - bit_shift);
+            }
+        }
+
+        U256(limbs)
+    }
+}
+
+/// Shifts the bits to the right, dropping bits shifted out.
+///
+/// # Panics
+///
+/// Panics if `shift` is not below 256 like the shift of built-in integers.
+impl Shr<usize> for U256 {
+    type Output = U256;
+
+    fn shr(self, shift: usize) -> U256 {
+        assert!(shift < 256, "attempt to shift right with overflow");
+
+        let limb_shift = shift / 64;
+        let bit_shift = shift % 64;
+        let mut limbs = [0; 4];
+
+        for (src, limb) in limbs.iter_mut().skip(limb_shift).enumerate() {
+            *limb = self.0[src] >> bit_shift;
+
+            if bit_shift > 0 && src > 0 {
+                *limb |= self.0[src - 1] << (64 - bit_shift);
+            }
+        }
+
+        U256(limbs)
+    }
+}
+
+/// Formats the number in decimal.
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // split into chunks of 19 decimal digits which fit into an u64
+        const CHUNK: u64 = 10_000_000_000_000_000_000;
+
+        let mut chunks = Vec::new();
+        let mut value = *self;
+
+        loop {
+            let (quotient, rem) = value.div_rem_u64(CHUNK);
+            chunks.push(rem);
+            value = quotient;
+
+            if value.is_zero() {
+                break;
+            }
+        }
+
+        let mut chunks = chunks.iter().rev();
+
+        if let Some(first) = chunks.next() {
+            write!(f, "{}", first)?;
+        }
+
+        for chunk in chunks {
+            write!(f, "{:019}", chunk)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn max() -> U256 {
+        U256([u64::MAX; 4])
+    }
+
+    #[test]
+    fn big_endian_roundtrip() {
+        let bytes: Vec<u8> = (0..32).collect();
+        let mut buf = [0; 32];
+
+        U256::from_big_endian(&bytes).to_big_endian(&mut buf);
+
+        let mut one = [0; 32];
+        one[31] = 1;
+
+        assert_eq!(&bytes[..], &buf[..]);
+        assert_eq!(U256::one(), U256::from_big_endian(&one));
+    }
+
+    #[test]
+    fn add_carries_across_limbs() {
+        let (sum, overflow) = U256([0, 0, 0, u64::MAX]).overflowing_add(U256::one());
+
+        assert_eq!(U256([0, 0, 1, 0]), sum);
+        assert!(!overflow);
+
+        let (sum, overflow) = max().overflowing_add(U256::one());
+
+        assert!(sum.is_zero());
+        assert!(overflow);
+    }
+
+    #[test]
+    fn sub_borrows_across_limbs() {
+        let (diff, overflow) = U256([0, 0, 1, 0]).overflowing_sub(U256::one());
+
+        assert_eq!(U256([0, 0, 0, u64::MAX]), diff);
+        assert!(!overflow);
+
+        let (diff, overflow) = U256::default().overflowing_sub(U256::one());
+
+        assert_eq!(max(), diff);
+        assert!(overflow);
+    }
+
+    #[test]
+    fn shifts() {
+        assert_eq!(U256([1 << 63, 0, 0, 0]), U256::one() << 255);
+        assert_eq!(U256([0, 0, 1, 0]), U256::one() << 64);
+        assert_eq!(U256([0, 0, 0, 1 << 63]), U256([0, 0, 1, 0]) >> 1);
+        assert_eq!(U256::one(), (U256::one() << 200) >> 200);
+    }
+
+    #[test]
+    fn ordering() {
+        assert!(U256([0, 0, 1, 0]) > U256([0, 0, 0, u64::MAX]));
+        assert!(U256([1, 0, 0, 0]) > U256([0, u64::MAX, u64::MAX, u64::MAX]));
+    }
+
+    #[test]
+    fn leading_zeros() {
+        assert_eq!(256, U256::default().leading_zeros());
+        assert_eq!(255, U256::one().leading_zeros());
+        assert_eq!(0, max().leading_zeros());
+        assert_eq!(191, U256([0, 0, 1, 0]).leading_zeros());
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!("0", U256::default().to_string());
+        assert_eq!("18446744073709551616", U256([0, 0, 1, 0]).to_string());
+        assert_eq!(
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935",
+            max().to_string()
+        );
+    }
+}
@@ -0,0 +1,320 @@
+//! Periodic maintenance of a peer
+//!
+//! A [`Scheduler`] runs registered [`Task`]s in a background thread, each
+//! with its own interval. Runs are scheduled on fixed deadlines such that the
+//! time a run takes does not delay the following ones. Every run is delayed
+//! by a little jitter such that peers started together do not contact their
+//! neighbours in lockstep.
+//!
+//! Tasks are isolated from each other. An error is logged and the task runs
+//! again after its interval. A panic is logged as well and the task is asked
+//! to [`recover`] before its next run. A task which falls behind, e.g. because
+//! another task blocked the thread for a while, skips the runs it missed
+//! instead of running them in a burst.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use chord::random;
+//! # use chord::scheduler::Scheduler;
+//! # use std::time::Duration;
+//! #
+//! let mut rounds = 0;
+//!
+//! Scheduler::new(random::system())
+//!     .with_task("count", Duration::from_secs(1), move || {
+//!         rounds += 1;
+//!         Ok(())
+//!     })
+//!     .spawn()
+//!     .expect("could not start scheduler");
+//! ```
+//!
+//! [`Scheduler`]: struct.Scheduler.html
+//! [`Task`]: trait.Task.html
+//! [`recover`]: trait.Task.html#method.recover
+
+use crate::network;
+use crate::random::Random;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Fraction of the interval by which a run is delayed at most
+pub const DEFAULT_JITTER: f64 = 0.1;
+
+/// Periodic work of a peer
+///
+/// Closures returning a [`Result`] are tasks as well.
+///
+/// [`Result`]: ../type.Result.html
+pub trait Task: Send {
+    /// Runs the task once.
+    fn run(&mut self) -> crate::Result<()>;
+
+    /// Returns the time until the next run for the configured `interval`.
+    ///
+    /// Tasks may shorten the period while there is more work to do.
+    fn period(&self, interval: Duration) -> Duration {
+        interval
+    }
+
+    /// Prepares the task to run again after [`run`] panicked.
+    ///
+    /// [`run`]: #tymethod.run
+    fn recover(&mut self) {}
+}
+
+impl<F> Task for F
+where
+    F: FnMut() -> crate::Result<()> + Send,
+{
+    fn run(&mut self) -> crate::Result<()> {
+        self()
+    }
+}
+
+struct Entry {
+    name: &'static str,
+    interval: Duration,
+    task: Box<dyn Task>,
+    /// Deadline of the next run without jitter
+    next: Instant,
+    /// Deadline of the next run including jitter
+    due: Instant,
+}
+
+/// Runs periodic tasks with independent intervals
+pub struct Scheduler {
+    tasks: Vec<Entry>,
+    random: Arc<dyn Random>,
+    jitter: f64,
+    context: Option<String>,
+}
+
+impl Scheduler {
+    /// Creates a scheduler without tasks which draws the jitter of the runs
+    /// from `random`.
+    pub fn new(random: Arc<dyn Random>) -> Self {
+        Self {
+            tasks: Vec::new(),
+            random,
+            jitter: DEFAULT_JITTER,
+            context: None,
+        }
+    }
+
+    /// Runs `task` every `interval`, starting one interval from now.
+    pub fn with_task<T>(mut self, name: &'static str, interval: Duration, task: T) -> Self
+    where
+        T: Task + 'static,
+    {
+        let next = Instant::now() + interval;
+        let due = next + self.random.jitter(interval, self.jitter);
+
+        self.tasks.push(Entry {
+            name,
+            interval,
+            task: Box::new(task),
+            next,
+            due,
+        });
+        self
+    }
+
+    /// Delays every run by up to `jitter` times its period instead of
+    /// [`DEFAULT_JITTER`].
+    ///
+    /// [`DEFAULT_JITTER`]: constant.DEFAULT_JITTER.html
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Logs panics of the tasks along with `context`, usually the address of
+    /// the peer they maintain.
+    pub fn with_context(mut self, context: String) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Returns the names of the registered tasks.
+    pub fn tasks(&self) -> Vec<&'static str> {
+        self.tasks.iter().map(|entry| entry.name).collect()
+    }
+
+    /// Runs the tasks in a background thread.
+    ///
+    /// The thread only terminates if there are no tasks.
+    pub fn spawn(self) -> io::Result<JoinHandle<()>> {
+        thread::Builder::new()
+            .name("scheduler".to_string())
+            .spawn(move || self.run())
+    }
+
+    /// Runs the tasks in the current thread.
+    ///
+    /// Only returns if there are no tasks.
+    pub fn run(mut self) {
+        network::set_thread_context(self.context.clone());
+
+        while let Some(index) = self.next_index() {
+            let due = self.tasks[index].due;
+            thread::sleep(due.saturating_duration_since(Instant::now()));
+
+            self.run_task(index);
+        }
+    }
+
+    fn next_index(&self) -> Option<usize> {
+        self.tasks
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, entry)| entry.due)
+            .map(|(index, _)| index)
+    }
+
+    fn run_task(&mut self, index: usize) {
+        let entry = &mut self.tasks[index];
+        let task = &mut entry.task;
+
+        match panic::catch_unwind(AssertUnwindSafe(|| task.run())) {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => error!("Task {} failed: {}", entry.name, err),
+            Err(_) => {
+                error!("Task {} died, restarting it", entry.name);
+
+                entry.task.recover();
+            }
+        }
+
+        let period = entry.task.period(entry.interval);
+        let now = Instant::now();
+
+        entry.next += period;
+        if entry.next < now {
+            debug!("Task {} fell behind, skipping missed runs", entry.name);
+
+            entry.next = now;
+        }
+
+        entry.due = entry.next + self.random.jitter(period, self.jitter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::SeededRandom;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn scheduler() -> Scheduler {
+        Scheduler::new(Arc::new(SeededRandom::new(1)))
+    }
+
+    fn counter() -> (Arc<AtomicUsize>, impl Task + 'static) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let task_count = Arc::clone(&count);
+
+        let task = move || {
+            task_count.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        };
+
+        (count, task)
+    }
+
+    struct Panicking {
+        recovered: Arc<AtomicUsize>,
+    }
+
+    impl Task for Panicking {
+        fn run(&mut self) -> crate::Result<()> {
+            panic!("task panic");
+        }
+
+        fn recover(&mut self) {
+            self.recovered.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    struct Accelerated;
+
+    impl Task for Accelerated {
+        fn run(&mut self) -> crate::Result<()> {
+            Ok(())
+        }
+
+        fn period(&self, interval: Duration) -> Duration {
+            interval / 10
+        }
+    }
+
+    #[test]
+    fn independent_intervals() {
+        let (fast, fast_task) = counter();
+        let (slow, slow_task) = counter();
+
+        scheduler()
+            .with_task("fast", Duration::from_millis(10), fast_task)
+            .with_task("slow", Duration::from_millis(100), slow_task)
+            .spawn()
+            .unwrap();
+
+        thread::sleep(Duration::from_millis(350));
+
+        let fast = fast.load(Ordering::SeqCst);
+        let slow = slow.load(Ordering::SeqCst);
+
+        assert!((2..=3).contains(&slow), "slow task ran {} times", slow);
+        assert!(fast > 5 * slow, "fast task ran {} times", fast);
+    }
+
+    #[test]
+    fn failing_tasks_are_isolated() {
+        let (count, task) = counter();
+        let recovered = Arc::new(AtomicUsize::new(0));
+
+        scheduler()
+            .with_task("error", Duration::from_millis(10), || {
+                Err("task error".into())
+            })
+            .with_task(
+                "panic",
+                Duration::from_millis(10),
+                Panicking {
+                    recovered: Arc::clone(&recovered),
+                },
+            )
+            .with_task("count", Duration::from_millis(10), task)
+            .spawn()
+            .unwrap();
+
+        thread::sleep(Duration::from_millis(200));
+
+        assert!(count.load(Ordering::SeqCst) > 5);
+        assert!(recovered.load(Ordering::SeqCst) > 5);
+    }
+
+    #[test]
+    fn task_shortens_period() {
+        let mut scheduler =
+            scheduler().with_task("accelerated", Duration::from_secs(10), Accelerated);
+        let start = scheduler.tasks[0].next;
+
+        scheduler.run_task(0);
+
+        assert_eq!(start + Duration::from_secs(1), scheduler.tasks[0].next);
+        assert!(scheduler.tasks[0].due < start + Duration::from_millis(1100));
+    }
+
+    #[test]
+    fn no_tasks() {
+        let scheduler = scheduler();
+
+        assert!(scheduler.tasks().is_empty());
+        scheduler.run();
+    }
+}
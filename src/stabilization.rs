@@ -5,18 +5,51 @@
 //!
 //! [`Stabilization`]: struct.Stabilization.html
 
-use crate::procedures::Procedures;
+use crate::clock::{self, Clock};
+use crate::error::{BootstrapError, BootstrapFailure, PeerError};
+use crate::framing::{self, Framing};
+use crate::metrics::Metrics;
+use crate::network::{Multiplexer, SocketOptions};
+use crate::procedures::{JoinOutcome, Procedures};
+use crate::proxy::Proxy;
+use crate::random::Random;
 use crate::routing::identifier::*;
-use crate::routing::Routing;
+use crate::routing::peer::PeerInfo;
+use crate::routing::{FingerDiscrepancy, Routing};
+use crate::storage::Storage;
+use crate::sync::MutexExt;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of attempts to join the network before giving up
+pub const JOIN_ATTEMPTS: u32 = 10;
+
+/// Time to wait after the first failed attempt to join the network, which
+/// doubles with every further failed attempt
+pub const JOIN_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Longest time to wait between two attempts to join the network
+const MAX_JOIN_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Time during which a successor which refused the connection is not adopted again
+const UNREACHABLE_PERIOD: Duration = Duration::from_secs(300);
 
 /// Basic information needed to connect to the network using a bootstrap peer
 pub struct Bootstrap {
     current_addr: SocketAddr,
     boot_addr: SocketAddr,
     fingers: usize,
+    attempts: u32,
+    backoff: Duration,
+    proxy: Option<Proxy>,
+    framing: Arc<dyn Framing>,
+    socket_options: SocketOptions,
 }
 
 impl Bootstrap {
@@ -28,51 +61,257 @@ impl Bootstrap {
             current_addr,
             boot_addr,
             fingers,
+            attempts: JOIN_ATTEMPTS,
+            backoff: JOIN_BACKOFF,
+            proxy: None,
+            framing: framing::plain(),
+            socket_options: SocketOptions::default(),
         }
     }
 
+    /// Makes up to `attempts` attempts to join the network instead of
+    /// [`JOIN_ATTEMPTS`] and waits `backoff` instead of [`JOIN_BACKOFF`]
+    /// after the first failed attempt.
+    ///
+    /// [`JOIN_ATTEMPTS`]: constant.JOIN_ATTEMPTS.html
+    /// [`JOIN_BACKOFF`]: constant.JOIN_BACKOFF.html
+    pub fn with_retry(mut self, attempts: u32, backoff: Duration) -> Self {
+        self.attempts = attempts.max(1);
+        self.backoff = backoff;
+        self
+    }
+
+    /// Connects to the other peers through `proxy` if it is given.
+    pub fn with_proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Exchanges the messages with the other peers in frames of `framing`.
+    pub fn with_framing(mut self, framing: Arc<dyn Framing>) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Applies `socket_options` to the connections to the other peers.
+    pub fn with_socket_options(mut self, socket_options: SocketOptions) -> Self {
+        self.socket_options = socket_options;
+        self
+    }
+
     /// Creates a new routing table by asking the bootstrap peer for all relevant information.
     ///
     /// This first finds the peer which is currently responsible for our identifier range and
-    /// will become our successor. After that we obtain the current predecessor of that peer
-    /// and set it as our predecessor which also updates the predecessor information of the
-    /// scucessor peer. Finally, we initialize the finger table with our own address.
-    pub fn bootstrap(&self, timeout: u64) -> crate::Result<Routing<SocketAddr>> {
-        let procedures = Procedures::new(timeout);
+    /// will become our successor. After that we join the network in front of that peer using
+    /// the ordered join protocol which locks the range of the successor, transfers the values
+    /// we become responsible for and finally publishes us as the new predecessor. Finally, we
+    /// populate the finger table by looking up each finger such that requests are routed
+    /// across the network right from the start.
+    ///
+    /// Since only one peer can join in front of a successor at the same time, the join is
+    /// retried if the successor is busy or if another peer joined in between.
+    ///
+    /// Failed attempts are retried via the bootstrap peer after a backoff which
+    /// doubles with every attempt. After the last attempt, a [`BootstrapError`]
+    /// tells whether the peers were unreachable, too slow or belong to another
+    /// network. A peer whose identifier is taken by another peer already gives
+    /// up right away.
+    ///
+    /// [`BootstrapError`]: ../error/struct.BootstrapError.html
+    pub fn bootstrap(&self, timeout: u64) -> crate::Result<(Routing<PeerInfo>, Storage)> {
+        let procedures = Procedures::new(timeout)
+            .with_proxy(self.proxy)
+            .with_framing(Arc::clone(&self.framing))
+            .with_socket_options(self.socket_options);
+        let current_id = self.current_addr.identifier();
+
+        let mut peer_addr = self.boot_addr;
+        let mut failures = 0;
+        let mut attempted = 0;
+        let mut last_error = None;
+
+        for attempt in 1..=self.attempts {
+            attempted = attempt;
+
+            let result = procedures
+                .find_peer(current_id, peer_addr)
+                .and_then(|successor| {
+                    let outcome = procedures.join(self.current_addr, successor)?;
+
+                    Ok((successor, outcome))
+                });
+
+            match result {
+                Ok((successor, JoinOutcome::Joined(predecessor, storage))) => {
+                    let finger_table = self.finger_table(&procedures, successor);
+                    let routing = Routing::from_addrs(
+                        self.current_addr,
+                        predecessor,
+                        successor,
+                        finger_table,
+                    );
+
+                    return Ok((routing, storage));
+                }
+                Ok((successor, JoinOutcome::Redirected(redirect_addr))) => {
+                    if redirect_addr == successor {
+                        // the successor is busy with another join
+                        thread::sleep(self.backoff * attempt);
+                    }
+
+                    peer_addr = redirect_addr;
+                }
+                Err(err) => {
+                    let failure = BootstrapFailure::classify(err.as_ref());
+
+                    warn!("Join attempt {} failed ({:?}): {}", attempt, failure, err);
+
+                    failures += 1;
+                    last_error = Some(err);
+
+                    // retrying cannot change the identifier
+                    if failure == BootstrapFailure::Collision {
+                        break;
+                    }
+
+                    if attempt < self.attempts {
+                        thread::sleep(self.backoff(failures));
+                    }
+
+                    peer_addr = self.boot_addr;
+                }
+            }
+        }
+
+        let last_error =
+            last_error.unwrap_or_else(|| "the successors kept redirecting the join".into());
+
+        Err(Box::new(BootstrapError::new(
+            self.boot_addr,
+            attempted,
+            last_error,
+        )))
+    }
+
+    /// Returns the time to wait after the `failures`th failed attempt.
+    fn backoff(&self, failures: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+
+        self.backoff
+            .checked_mul(factor)
+            .unwrap_or(MAX_JOIN_BACKOFF)
+            .min(MAX_JOIN_BACKOFF)
+    }
+
+    /// Looks up the peer responsible for each finger starting at the successor.
+    ///
+    /// Our own servers are not running yet, therefore a finger whose lookup fails falls back to
+    /// the successor which is still a better guess than our own address.
+    fn finger_table(&self, procedures: &Procedures, successor: SocketAddr) -> Vec<SocketAddr> {
         let current_id = self.current_addr.identifier();
 
-        let successor = procedures.find_peer(current_id, self.boot_addr)?;
-        let predecessor = procedures.notify_predecessor(self.current_addr, successor)?;
-        let finger_table = vec![self.current_addr; self.fingers];
+        (0..self.fingers)
+            .map(|i| {
+                // TODO do not hardcode for 256 bits here
+                let identifier = current_id.successor_id(255 - i);
 
-        Ok(Routing::new(
-            self.current_addr,
-            predecessor,
-            successor,
-            finger_table,
-        ))
+                procedures
+                    .find_peer(identifier, successor)
+                    .unwrap_or_else(|err| {
+                        warn!("Could not find peer for finger {}: {}", i, err);
+
+                        successor
+                    })
+            })
+            .collect()
     }
 }
 
 /// Stabilize the [`Routing`] table in regular intervals
 ///
+/// A successor which refuses the connection is considered down and replaced by the closest
+/// finger, while a successor which does not reply in time is kept since it may only be
+/// overloaded. Peers considered down are not adopted as successor again for some time even if
+/// other peers still report them.
+///
 /// [`Routing`]: ../routing/struct.Routing.html
 pub struct Stabilization {
     procedures: Procedures,
-    routing: Arc<Mutex<Routing<SocketAddr>>>,
+    routing: Arc<Mutex<Routing<PeerInfo>>>,
+    metrics: Arc<Metrics>,
+    clock: Arc<dyn Clock>,
+    /// Peers considered down along with the deadline until they are avoided
+    unreachable: HashMap<SocketAddr, Instant>,
 }
 
 impl Stabilization {
-    /// Initializes the stabilization struct with a routing object and the connection timeout.
-    pub fn new(routing: Arc<Mutex<Routing<SocketAddr>>>, timeout: u64) -> Self {
-        let procedures = Procedures::new(timeout);
+    /// Initializes the stabilization struct with a routing object, the metrics to record lookups
+    /// in and the connection timeout.
+    pub fn new(
+        routing: Arc<Mutex<Routing<PeerInfo>>>,
+        metrics: Arc<Metrics>,
+        timeout: u64,
+    ) -> Self {
+        let procedures = Procedures::with_metrics(timeout, Arc::clone(&metrics));
 
         Self {
             procedures,
             routing,
+            metrics,
+            clock: clock::system(),
+            unreachable: HashMap::new(),
         }
     }
 
+    /// Takes the time from `clock` to decide when unreachable peers may be
+    /// adopted again.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Sends the requests to other peers over the shared connections of
+    /// `multiplexer`.
+    pub fn with_multiplexer(mut self, multiplexer: Arc<Multiplexer>) -> Self {
+        self.procedures = self.procedures.with_multiplexer(multiplexer);
+        self
+    }
+
+    /// Opens the dedicated connections to other peers through `proxy` if it
+    /// is given.
+    pub fn with_proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.procedures = self.procedures.with_proxy(proxy);
+        self
+    }
+
+    /// Exchanges the messages of the dedicated connections to other peers in
+    /// frames of `framing`.
+    pub fn with_framing(mut self, framing: Arc<dyn Framing>) -> Self {
+        self.procedures = self.procedures.with_framing(framing);
+        self
+    }
+
+    /// Applies `socket_options` to the dedicated connections to other peers.
+    pub fn with_socket_options(mut self, socket_options: SocketOptions) -> Self {
+        self.procedures = self.procedures.with_socket_options(socket_options);
+        self
+    }
+
+    /// Prepares this stabilization to run again after a round panicked.
+    ///
+    /// A panic while holding the routing lock poisons it. The routing table is
+    /// used as is since the following rounds replace the successor and all
+    /// fingers anyway.
+    pub fn recover(&mut self) {
+        if self.routing.is_poisoned() {
+            warn!("Clearing poisoned routing table");
+
+            self.routing.clear_poison();
+        }
+
+        self.unreachable.clear();
+    }
+
     /// Updates the successor and finger tables
     ///
     /// The current successor is asked for its predecessor. If the predecessor would be a closer
@@ -80,24 +319,140 @@ impl Stabilization {
     ///
     /// After that the finger tables are updated by iterating through each entry and finding the
     /// peer responsible for that finger.
-    pub fn stabilize(&mut self) -> crate::Result<()> {
+    ///
+    /// All steps are attempted even if some of them fail, such that one unreachable peer does not
+    /// stall the maintenance of the remaining routing table. The errors of all failed steps are
+    /// collected in a [`StabilizationReport`].
+    ///
+    /// [`StabilizationReport`]: struct.StabilizationReport.html
+    pub fn stabilize(&mut self) -> Result<(), StabilizationReport> {
         info!("Stabilizing routing information");
 
-        let update_successor = self.update_successor();
-        let update_fingers = self.update_fingers();
+        let start = self.clock.now();
+        let mut report = StabilizationReport::default();
 
-        let routing = self.routing.lock().unwrap();
+        if let Err(err) = self.update_successor() {
+            report.successor = Some(err);
+        }
+
+        report.fingers = self.update_fingers();
+
+        self.metrics.stats().record_stabilization_round();
+
+        let routing = self.routing.lock_or_recover();
+
+        self.metrics
+            .slow_log()
+            .check("stabilization round", self.clock.now() - start, || {
+                format!(
+                    "peer {} with successor {}, {} finger lookups, {} failed steps",
+                    *routing.current,
+                    *routing.successor,
+                    routing.fingers(),
+                    report.fingers.len() + report.successor.iter().count()
+                )
+            });
 
         debug!("Current routing information:\n\n{:#?}", *routing);
 
-        update_successor.and(update_fingers)
+        let now = self.clock.now();
+
+        for check in routing.check_fingers() {
+            if routing.is_stale(check.index, now) {
+                warn!(
+                    "Finger {} at {} has not been verified for {}s",
+                    check.index,
+                    *check.finger,
+                    now.saturating_duration_since(check.verified).as_secs()
+                );
+            }
+
+            match check.discrepancy {
+                Some(FingerDiscrepancy::BeforeRange) => warn!(
+                    "Finger {} at {} lies before its range starting at {}",
+                    check.index,
+                    *check.finger,
+                    check.range.start()
+                ),
+                Some(FingerDiscrepancy::CloserPeer(peer)) => warn!(
+                    "Finger {} at {} should be {} which is closer to its range starting at {}",
+                    check.index,
+                    *check.finger,
+                    *peer,
+                    check.range.start()
+                ),
+                None => {}
+            }
+        }
+
+        if report.successor.is_none() && report.fingers.is_empty() {
+            Ok(())
+        } else {
+            Err(report)
+        }
     }
 
-    fn update_successor(&self) -> crate::Result<()> {
+    /// Looks up the identifier of this peer through a random finger and
+    /// returns whether the lookup resolves back to this peer.
+    ///
+    /// Every peer on a consistent ring routes the identifier to this peer, so
+    /// a lookup which ends at another peer or fails reveals stale successors
+    /// or fingers somewhere on the way. The outcome is recorded in the
+    /// metrics. A peer without fingers pointing to other peers has nothing to
+    /// check and is considered consistent.
+    pub fn check_self_lookup(&self, random: &dyn Random) -> bool {
+        let (current, fingers) = {
+            let routing = self.routing.lock_or_recover();
+
+            let current = routing.current.socket_addr();
+            let mut fingers: Vec<SocketAddr> = (0..routing.fingers())
+                .filter_map(|i| routing.finger(i))
+                .map(|finger| finger.socket_addr())
+                .filter(|&finger| finger != current)
+                .collect();
+            fingers.dedup();
+
+            (current, fingers)
+        };
+
+        if fingers.is_empty() {
+            return true;
+        }
+
+        let finger = fingers[random.below(fingers.len())];
+
+        info!("Looking up own identifier through finger {}", finger);
+
+        let consistent = match self.procedures.find_peer(current.identifier(), finger) {
+            Ok(peer_addr) if peer_addr == current => true,
+            Ok(peer_addr) => {
+                warn!(
+                    "Lookup of own identifier through {} resolved to {}, ring is inconsistent",
+                    finger, peer_addr
+                );
+
+                false
+            }
+            Err(err) => {
+                warn!(
+                    "Lookup of own identifier through {} failed: {}",
+                    finger, err
+                );
+
+                false
+            }
+        };
+
+        self.metrics.record_self_lookup(consistent);
+
+        consistent
+    }
+
+    fn update_successor(&mut self) -> crate::Result<()> {
         let (current, successor) = {
-            let routing = self.routing.lock().unwrap();
+            let routing = self.routing.lock_or_recover();
 
-            (routing.current, routing.successor)
+            (routing.current.clone(), routing.successor.clone())
         };
 
         info!(
@@ -105,42 +460,199 @@ impl Stabilization {
             *successor
         );
 
-        let new_successor = self.procedures.notify_predecessor(*current, *successor)?;
+        let start = self.clock.now();
+        let result = self
+            .procedures
+            .notify_predecessor(current.socket_addr(), successor.socket_addr());
+
+        let new_successor = match result {
+            Ok(new_successor) => new_successor,
+            Err(err) => return self.handle_successor_error(successor.socket_addr(), err),
+        };
+
+        self.routing
+            .lock_or_recover()
+            .seen(successor.socket_addr(), self.clock.now() - start);
 
-        let current_id = current.identifier();
-        let successor_id = successor.identifier();
+        // a closer successor lies in (current, successor)
+        let interval = IdentifierInterval::new(current.identifier(), successor.identifier());
 
-        if new_successor
-            .identifier()
-            .is_between(&current_id, &successor_id)
+        if interval.contains_open(new_successor.identifier()) && !self.is_unreachable(new_successor)
         {
             info!("Updating successor to address {}", new_successor);
 
-            let mut routing = self.routing.lock().unwrap();
-            routing.set_successor(new_successor);
+            let mut routing = self.routing.lock_or_recover();
+            let peer = routing.peer(new_successor);
+            routing.set_successor(peer);
         }
 
         Ok(())
     }
 
-    fn update_fingers(&self) -> crate::Result<()> {
-        let (current, successor, fingers) = {
-            let routing = self.routing.lock().unwrap();
+    fn handle_successor_error(
+        &mut self,
+        successor_addr: SocketAddr,
+        err: Box<dyn Error>,
+    ) -> crate::Result<()> {
+        match err.downcast_ref::<PeerError>() {
+            Some(PeerError::Unreachable(..)) => {
+                warn!(
+                    "Successor {} is down, thus routing around it: {}",
+                    successor_addr, err
+                );
+
+                self.mark_unreachable(successor_addr);
+                self.routing.lock_or_recover().remove_peer(successor_addr);
+
+                Ok(())
+            }
+            Some(PeerError::TimedOut(..)) => {
+                warn!(
+                    "Successor {} is slow, thus keeping it: {}",
+                    successor_addr, err
+                );
+
+                Err(err)
+            }
+            None => Err(err),
+        }
+    }
+
+    fn mark_unreachable(&mut self, peer_addr: SocketAddr) {
+        let until = self.clock.deadline(UNREACHABLE_PERIOD);
+
+        self.unreachable.insert(peer_addr, until);
+    }
+
+    fn is_unreachable(&mut self, peer_addr: SocketAddr) -> bool {
+        let clock = &self.clock;
+
+        self.unreachable.retain(|_, until| !clock.is_past(*until));
+
+        self.unreachable.contains_key(&peer_addr)
+    }
+
+    /// Updates all fingers and returns the indices of the fingers which could not be updated.
+    ///
+    /// Stale fingers are updated first such that they are refreshed even if the remaining
+    /// lookups take long.
+    fn update_fingers(&self) -> Vec<(usize, Box<dyn Error>)> {
+        let (current, successor, order) = {
+            let routing = self.routing.lock_or_recover();
+
+            let current = routing.current.clone();
+            let successor = routing.successor.clone();
 
-            (routing.current, routing.successor, routing.fingers())
+            (current, successor, routing.refresh_order(self.clock.now()))
         };
 
         info!("Update fingers using successor with address {}", *successor);
 
-        for i in 0..fingers {
+        let mut errors = Vec::new();
+
+        for i in order {
             // TODO do not hardcode for 256 bits here
-            let identifier = current.identifier() + Identifier::with_bit(255 - i);
-            let peer = self.procedures.find_peer(identifier, *successor)?;
+            let identifier = current.identifier().successor_id(255 - i);
+            let peer_addr = match self
+                .procedures
+                .find_peer(identifier, successor.socket_addr())
+            {
+                Ok(peer_addr) => peer_addr,
+                Err(err) => {
+                    // keep the previous finger and continue with the remaining ones
+                    warn!("Could not update finger {}: {}", i, err);
+
+                    errors.push((i, err));
 
-            let mut routing = self.routing.lock().unwrap();
+                    continue;
+                }
+            };
+
+            let mut routing = self.routing.lock_or_recover();
+            let peer = routing.peer(peer_addr);
             routing.set_finger(i, peer);
         }
 
+        errors
+    }
+}
+
+/// Errors collected during a stabilization round which did not complete all steps
+#[derive(Debug, Default)]
+pub struct StabilizationReport {
+    successor: Option<Box<dyn Error>>,
+    fingers: Vec<(usize, Box<dyn Error>)>,
+}
+
+impl StabilizationReport {
+    /// Returns the error which occurred while updating the successor.
+    pub fn successor_error(&self) -> Option<&dyn Error> {
+        self.successor.as_deref()
+    }
+
+    /// Returns the indices of the fingers which could not be updated along with the errors.
+    pub fn finger_errors(&self) -> &[(usize, Box<dyn Error>)] {
+        &self.fingers
+    }
+}
+
+impl fmt::Display for StabilizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Stabilization incomplete")?;
+
+        if let Some(ref err) = self.successor {
+            write!(f, "\n  successor: {}", err)?;
+        }
+
+        for (i, err) in &self.fingers {
+            write!(f, "\n  finger {}: {}", i, err)?;
+        }
+
         Ok(())
     }
 }
+
+impl Error for StabilizationReport {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn unreachable_period() {
+        let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let other: SocketAddr = "127.0.0.2:8080".parse().unwrap();
+        let routing = Routing::from_addrs(addr, addr, addr, vec![addr; 8]);
+        let clock = Arc::new(ManualClock::new());
+
+        let mut stabilization = Stabilization::new(
+            Arc::new(Mutex::new(routing)),
+            Arc::new(Metrics::new()),
+            1000,
+        )
+        .with_clock(Arc::clone(&clock) as Arc<dyn Clock>);
+
+        stabilization.mark_unreachable(other);
+        assert!(stabilization.is_unreachable(other));
+        assert!(!stabilization.is_unreachable(addr));
+
+        clock.advance(UNREACHABLE_PERIOD - Duration::from_secs(1));
+        assert!(stabilization.is_unreachable(other));
+
+        clock.advance(Duration::from_secs(1));
+        assert!(!stabilization.is_unreachable(other));
+    }
+
+    #[test]
+    fn join_backoff_doubles() {
+        let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let bootstrap = Bootstrap::new(addr, addr, 8).with_retry(5, Duration::from_millis(100));
+
+        assert_eq!(Duration::from_millis(100), bootstrap.backoff(1));
+        assert_eq!(Duration::from_millis(200), bootstrap.backoff(2));
+        assert_eq!(Duration::from_millis(800), bootstrap.backoff(4));
+        assert_eq!(MAX_JOIN_BACKOFF, bootstrap.backoff(20));
+        assert_eq!(MAX_JOIN_BACKOFF, bootstrap.backoff(100));
+    }
+}
@@ -0,0 +1,184 @@
+//! Neighbours of a peer cached across restarts
+//!
+//! A peer with a [`state_file`] saves its predecessor, successor and fingers
+//! after joining and after every stabilization pass. On restart, it rejoins
+//! the network through the first of these peers which still replies and only
+//! falls back to the bootstrap peer if none does. After a rolling restart the
+//! old neighbours are usually still around and route the join to the range of
+//! the peer right away.
+//!
+//! The file uses the ini format of the configuration:
+//!
+//! ```text
+//! [state]
+//! listen_address = 127.0.0.1:8080
+//! predecessor = 127.0.0.1:8090
+//! successor = 127.0.0.1:8070
+//! fingers = 127.0.0.1:8070,127.0.0.1:8060
+//! ```
+//!
+//! [`state_file`]: ../config/struct.Config.html#structfield.state_file
+
+use crate::routing::peer::PeerInfo;
+use crate::routing::Routing;
+use ini::Ini;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+
+/// Neighbours of a peer as saved in its state file
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeState {
+    pub listen_address: SocketAddr,
+    pub predecessor: SocketAddr,
+    pub successor: SocketAddr,
+    pub fingers: Vec<SocketAddr>,
+}
+
+impl NodeState {
+    /// Captures the neighbours of the peer owning `routing`.
+    pub fn from_routing(routing: &Routing<PeerInfo>) -> Self {
+        let fingers = (0..routing.fingers())
+            .filter_map(|index| routing.finger(index))
+            .map(|finger| finger.socket_addr())
+            .collect();
+
+        NodeState {
+            listen_address: routing.current.socket_addr(),
+            predecessor: routing.predecessor.socket_addr(),
+            successor: routing.successor.socket_addr(),
+            fingers,
+        }
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(filename: P) -> crate::Result<NodeState> {
+        let conf = Ini::load_from_file(filename)?;
+
+        let state = conf
+            .section(Some("state"))
+            .ok_or("missing section `state`")?;
+
+        let listen_address = state
+            .get("listen_address")
+            .ok_or("missing value `listen_address`")?
+            .parse()?;
+
+        let predecessor = state
+            .get("predecessor")
+            .ok_or("missing value `predecessor`")?
+            .parse()?;
+
+        let successor = state
+            .get("successor")
+            .ok_or("missing value `successor`")?
+            .parse()?;
+
+        let fingers = state
+            .get("fingers")
+            .map_or("", String::as_str)
+            .split(',')
+            .filter(|finger| !finger.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+
+        Ok(NodeState {
+            listen_address,
+            predecessor,
+            successor,
+            fingers,
+        })
+    }
+
+    /// Writes the state to `filename`.
+    ///
+    /// The state is written to a temporary file first and moved into place
+    /// afterwards such that a crash never leaves a truncated file behind.
+    pub fn save_to_file<P: AsRef<Path>>(&self, filename: P) -> crate::Result<()> {
+        let filename = filename.as_ref();
+
+        let fingers = self
+            .fingers
+            .iter()
+            .map(SocketAddr::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let mut conf = Ini::new();
+        conf.with_section(Some("state"))
+            .set("listen_address", self.listen_address.to_string())
+            .set("predecessor", self.predecessor.to_string())
+            .set("successor", self.successor.to_string())
+            .set("fingers", fingers);
+
+        let mut temp_name = filename.as_os_str().to_owned();
+        temp_name.push(".tmp");
+
+        conf.write_to_file(&temp_name)?;
+        fs::rename(&temp_name, filename)?;
+
+        Ok(())
+    }
+
+    /// Returns the peers to rejoin through, closest neighbours first.
+    ///
+    /// The peer itself and duplicates are left out.
+    pub fn neighbours(&self) -> Vec<SocketAddr> {
+        let mut neighbours = Vec::new();
+
+        let closest = [self.successor, self.predecessor];
+
+        for &candidate in closest.iter().chain(&self.fingers) {
+            if candidate != self.listen_address && !neighbours.contains(&candidate) {
+                neighbours.push(candidate);
+            }
+        }
+
+        neighbours
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn save_and_load() {
+        let state = NodeState {
+            listen_address: addr(8080),
+            predecessor: addr(8090),
+            successor: addr(8070),
+            fingers: vec![addr(8070), addr(8060)],
+        };
+
+        let path = env::temp_dir().join("chord-state-save-and-load.ini");
+        state.save_to_file(&path).unwrap();
+
+        assert_eq!(state, NodeState::load_from_file(&path).unwrap());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn neighbours_skip_self_and_duplicates() {
+        let state = NodeState {
+            listen_address: addr(8080),
+            predecessor: addr(8090),
+            successor: addr(8070),
+            fingers: vec![addr(8070), addr(8080), addr(8060), addr(8090)],
+        };
+
+        assert_eq!(vec![addr(8070), addr(8090), addr(8060)], state.neighbours());
+    }
+
+    #[test]
+    fn neighbours_of_single_peer() {
+        let routing = Routing::from_addrs(addr(8080), addr(8080), addr(8080), vec![addr(8080); 4]);
+
+        assert!(NodeState::from_routing(&routing).neighbours().is_empty());
+    }
+}
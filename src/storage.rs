@@ -1,11 +1,532 @@
+use crate::dht::{DhtKey, Namespace};
+use crate::message::p2p::{StoragePut, TransferCursor};
+use crate::sync::MutexExt;
+use bytes::Bytes;
+use std::collections::{hash_map, HashMap};
 use std::fmt;
+use std::iter::FromIterator;
+use std::ops::Index;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+/// Number of records copied while holding the storage lock once
+const SNAPSHOT_BATCH: usize = 256;
+
+/// Local key-value store of a peer
+///
+/// Besides the records, the storage counts the bytes the values of a
+/// namespace occupy once their size has been asked for with
+/// [`namespace_size`]. The counts are kept up to date by every change to the
+/// records such that quotas can be checked without scanning all of them.
+///
+/// [`namespace_size`]: #method.namespace_size
+#[derive(Debug, Default)]
+pub struct Storage {
+    records: HashMap<Key, Record>,
+    /// Bytes of the values in each counted namespace
+    namespace_sizes: HashMap<Namespace, u64>,
+}
+
+impl Storage {
+    /// Creates an empty storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the record stored under `key`.
+    pub fn get(&self, key: &Key) -> Option<&Record> {
+        self.records.get(key)
+    }
+
+    /// Returns whether a record is stored under `key`.
+    pub fn contains_key(&self, key: &Key) -> bool {
+        self.records.contains_key(key)
+    }
+
+    /// Stores `record` under `key` and returns the record it replaces.
+    pub fn insert(&mut self, key: Key, record: Record) -> Option<Record> {
+        self.count(&key, record.value.len(), true);
+
+        let replaced = self.records.insert(key, record);
+
+        if let Some(ref replaced) = replaced {
+            self.count(&key, replaced.value.len(), false);
+        }
+
+        replaced
+    }
+
+    /// Removes the record stored under `key` and returns it.
+    pub fn remove(&mut self, key: &Key) -> Option<Record> {
+        let removed = self.records.remove(key);
+
+        if let Some(ref removed) = removed {
+            self.count(key, removed.value.len(), false);
+        }
+
+        removed
+    }
+
+    /// Keeps only the records for which `keep` returns `true`.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&Key, &Record) -> bool,
+    {
+        let mut removed = Vec::new();
+
+        self.records.retain(|key, record| {
+            let kept = keep(key, record);
+
+            if !kept {
+                removed.push((*key, record.value.len()));
+            }
+
+            kept
+        });
+
+        for (key, size) in removed {
+            self.count(&key, size, false);
+        }
+    }
+
+    /// Removes all records.
+    pub fn clear(&mut self) {
+        self.records.clear();
+
+        for size in self.namespace_sizes.values_mut() {
+            *size = 0;
+        }
+    }
+
+    /// Returns an iterator over all keys and their records.
+    pub fn iter(&self) -> hash_map::Iter<'_, Key, Record> {
+        self.records.iter()
+    }
+
+    /// Returns an iterator over all keys.
+    pub fn keys(&self) -> hash_map::Keys<'_, Key, Record> {
+        self.records.keys()
+    }
+
+    /// Returns an iterator over all records.
+    pub fn values(&self) -> hash_map::Values<'_, Key, Record> {
+        self.records.values()
+    }
+
+    /// Returns the number of records.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns whether there are no records.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Returns the number of bytes the values of `namespace` occupy,
+    /// including those of expired records which have not been removed yet.
+    ///
+    /// The first call for a namespace counts its values once, later calls
+    /// return the count kept up to date since.
+    pub fn namespace_size(&mut self, namespace: &Namespace) -> u64 {
+        let records = &self.records;
+
+        *self.namespace_sizes.entry(*namespace).or_insert_with(|| {
+            records
+                .iter()
+                .filter(|(key, _)| namespace.contains(&DhtKey::from(key.raw_key)))
+                .map(|(_, record)| record.value.len() as u64)
+                .sum()
+        })
+    }
+
+    /// Adds `size` bytes to or removes them from the counts of the
+    /// namespaces `key` belongs to.
+    fn count(&mut self, key: &Key, size: usize, added: bool) {
+        let dht_key = DhtKey::from(key.raw_key);
+
+        for (namespace, count) in &mut self.namespace_sizes {
+            if namespace.contains(&dht_key) {
+                if added {
+                    *count += size as u64;
+                } else {
+                    *count = count.saturating_sub(size as u64);
+                }
+            }
+        }
+    }
+}
+
+impl FromIterator<(Key, Record)> for Storage {
+    fn from_iter<I: IntoIterator<Item = (Key, Record)>>(iter: I) -> Self {
+        Self {
+            records: iter.into_iter().collect(),
+            namespace_sizes: HashMap::new(),
+        }
+    }
+}
+
+impl Index<&Key> for Storage {
+    type Output = Record;
+
+    /// Returns the record stored under `key`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no record is stored under `key`.
+    fn index(&self, key: &Key) -> &Record {
+        &self.records[key]
+    }
+}
+
+impl<'a> IntoIterator for &'a Storage {
+    type Item = (&'a Key, &'a Record);
+    type IntoIter = hash_map::Iter<'a, Key, Record>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.records.iter()
+    }
+}
+
+/// Copies all records whose key matches `filter` out of `storage`.
+///
+/// Only the keys are collected at once. The records are copied in small
+/// batches and the lock is released in between such that requests are not
+/// stalled by a large storage. Therefore, the snapshot is not
+/// atomic: records removed in the meantime are skipped and later writes may
+/// or may not be contained.
+pub fn snapshot<F>(storage: &Mutex<Storage>, filter: F) -> Vec<(Key, Record)>
+where
+    F: Fn(&Key) -> bool,
+{
+    let keys: Vec<Key> = storage
+        .lock_or_recover()
+        .keys()
+        .filter(|key| filter(key))
+        .cloned()
+        .collect();
+
+    let mut records = Vec::with_capacity(keys.len());
+
+    for batch in keys.chunks(SNAPSHOT_BATCH) {
+        let storage = storage.lock_or_recover();
+
+        records.extend(
+            batch
+                .iter()
+                .filter_map(|key| storage.get(key).map(|record| (*key, record.clone()))),
+        );
+    }
+
+    records
+}
+
+/// Stores `record` under `key` unless `storage` holds the same or a newer
+/// version already.
+///
+/// Returns whether the record has been stored.
+pub fn put_newer(storage: &mut Storage, key: Key, record: Record) -> bool {
+    if let Some(existing) = storage.get(&key) {
+        if existing.version >= record.version {
+            return false;
+        }
+    }
+
+    storage.insert(key, record);
+
+    true
+}
+
+/// Shares a read of the storage between concurrent requests for the same key
+///
+/// The first request for a key becomes the leader of a read while requests
+/// for the same key arriving in the meantime wait for its result instead of
+/// queueing for the storage lock themselves. A popular key is thus read once
+/// for a whole burst of requests, which share the value of the record.
+///
+/// The leader stops taking on requests once it holds the storage lock, thus
+/// every request receives a read which happened after it arrived and sees
+/// all writes completed before.
+#[derive(Default)]
+pub struct ReadCoalescer {
+    in_flight: Mutex<HashMap<Key, Arc<Read>>>,
+}
+
+/// A read in flight whose `result` is `None` until it finished
+#[derive(Default)]
+struct Read {
+    result: Mutex<Option<Option<Record>>>,
+    done: Condvar,
+}
+
+impl ReadCoalescer {
+    /// Creates a coalescer without reads in flight.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the record stored under `key` in `storage`, taken from the
+    /// read of another request if one is about to happen.
+    ///
+    /// Also returns whether the record has been read by another request.
+    pub fn get(&self, storage: &Mutex<Storage>, key: Key) -> (Option<Record>, bool) {
+        let (read, leader) = {
+            let mut in_flight = self.in_flight.lock_or_recover();
+
+            match in_flight.get(&key) {
+                Some(read) => (Arc::clone(read), false),
+                None => {
+                    let read = Arc::new(Read::default());
+                    in_flight.insert(key, Arc::clone(&read));
+
+                    (read, true)
+                }
+            }
+        };
+
+        if !leader {
+            let mut result = read.result.lock_or_recover();
+
+            while result.is_none() {
+                result = read
+                    .done
+                    .wait(result)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+            }
+
+            return (result.clone().flatten(), true);
+        }
+
+        let record = {
+            let storage = storage.lock_or_recover();
+
+            // later requests have to read again to see later writes
+            self.in_flight.lock_or_recover().remove(&key);
+
+            storage.get(&key).cloned()
+        };
+
+        *read.result.lock_or_recover() = Some(record.clone());
+        read.done.notify_all();
+
+        (record, false)
+    }
+}
+
+/// Maximum amount by which the clock of another peer may run ahead of the
+/// local clock
+///
+/// Versions are taken from the wall clock of the peer which received a write,
+/// thus a peer whose clock runs ahead creates versions which win against
+/// later writes of other peers. Versions further in the future than this
+/// tolerance are clamped when a record is created such that such a write
+/// shadows later writes for at most this long.
+pub const MAX_CLOCK_SKEW: Duration = Duration::from_secs(60);
+
+/// Returns a version for a newly written value.
+///
+/// Versions are the milliseconds since the unix epoch at the time the value
+/// was written such that later writes have higher versions as long as the
+/// clocks of the peers differ by less than [`MAX_CLOCK_SKEW`].
+///
+/// [`MAX_CLOCK_SKEW`]: constant.MAX_CLOCK_SKEW.html
+pub fn current_version() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Limits `version` to the current version plus [`MAX_CLOCK_SKEW`].
+///
+/// [`MAX_CLOCK_SKEW`]: constant.MAX_CLOCK_SKEW.html
+pub fn clamp_version(version: u64) -> u64 {
+    let now = current_version();
+    let latest = now.saturating_add(MAX_CLOCK_SKEW.as_millis() as u64);
+
+    if version > latest {
+        warn!(
+            "Clamping version {} which is {} ms ahead of the local clock",
+            version,
+            version - now
+        );
+
+        return latest;
+    }
+
+    version
+}
+
+/// A value stored along with its version and the time it expires
+///
+/// The expiry is a deadline of the local monotonic clock such that it is not
+/// affected by the wall clock of this or any other peer. Records are handed to
+/// other peers with their remaining time to live in milliseconds which the
+/// receiving peer turns into a deadline of its own clock again.
+///
+/// The time to live is only a hint such that expired records are not removed
+/// automatically but may be evicted by the peer. A time to live of zero
+/// expires immediately: the record still replaces older versions but counts
+/// as expired right away, such that the next flush of expired records
+/// removes it.
+///
+/// Every record also knows the total number of `replicas` its value was
+/// written with and the [`Namespace`] its key belongs to, such that repairs
+/// restore the right number of copies without probing the replication
+/// indices. Both travel with the record whenever it is handed to another
+/// peer. Records of older peers have zero replicas, which means unknown.
+///
+/// The time a peer `stored` its copy of a record is kept in milliseconds
+/// since the unix epoch and reported to clients along with the value.
+///
+/// The value is shared with the readers of the record and the messages
+/// sending it to other peers or clients, thus none of them copies it. A value
+/// received from another peer still refers to the message it arrived in.
+///
+/// [`Namespace`]: ../dht/struct.Namespace.html
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub value: Bytes,
+    pub version: u64,
+    pub expires: Instant,
+    pub replicas: u8,
+    pub namespace: Option<Namespace>,
+    pub stored: u64,
+}
+
+impl Record {
+    /// Creates a new record with the given version which expires after `ttl`.
+    ///
+    /// Versions further in the future than [`MAX_CLOCK_SKEW`] are clamped.
+    ///
+    /// [`MAX_CLOCK_SKEW`]: constant.MAX_CLOCK_SKEW.html
+    pub fn new<V: Into<Bytes>>(value: V, ttl: Duration, version: u64) -> Self {
+        Self::new_at(value, ttl, version, Instant::now())
+    }
+
+    /// Creates a new record like [`new`] whose time to live starts at `now`.
+    ///
+    /// [`new`]: #method.new
+    pub fn new_at<V: Into<Bytes>>(value: V, ttl: Duration, version: u64, now: Instant) -> Self {
+        Self {
+            value: value.into(),
+            version: clamp_version(version),
+            expires: now + ttl,
+            replicas: 0,
+            namespace: None,
+            stored: current_version(),
+        }
+    }
+
+    /// Records that the value has been written with `replicas` replicas in
+    /// total.
+    pub fn with_replicas(mut self, replicas: u8) -> Self {
+        self.replicas = replicas;
+        self
+    }
+
+    /// Records that the key belongs to `namespace`.
+    pub fn with_namespace(mut self, namespace: Option<Namespace>) -> Self {
+        self.namespace = namespace;
+        self
+    }
+
+    /// Restores a record handed over by another peer in `storage_put` whose
+    /// time to live starts at `now`.
+    ///
+    /// A namespace which does not fit the key is dropped.
+    pub fn from_storage_put(storage_put: StoragePut, now: Instant) -> Self {
+        let namespace = match usize::from(storage_put.namespace_len) {
+            0 => None,
+            len => Namespace::new(&storage_put.raw_key[..len.min(storage_put.raw_key.len())]).ok(),
+        };
+
+        Self::new_at(
+            storage_put.value,
+            Duration::from_millis(storage_put.ttl),
+            storage_put.version,
+            now,
+        )
+        .with_replicas(storage_put.replicas)
+        .with_namespace(namespace)
+    }
+
+    /// Converts the record into a `STORAGE PUT` message for `key` with the
+    /// time to live remaining at `now`.
+    pub fn into_storage_put(self, key: Key, now: Instant) -> StoragePut {
+        StoragePut {
+            ttl: self.ttl_at(now).as_millis() as u64,
+            replication_index: key.replication_index,
+            replicas: self.replicas,
+            namespace_len: self
+                .namespace
+                .map_or(0, |namespace| namespace.prefix().len() as u8),
+            raw_key: key.raw_key,
+            version: self.version,
+            value: self.value,
+        }
+    }
+
+    /// Returns whether the time to live of this record has passed.
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(Instant::now())
+    }
+
+    /// Returns whether the time to live of this record has passed at `now`.
+    pub fn is_expired_at(&self, now: Instant) -> bool {
+        self.expires <= now
+    }
+
+    /// Returns the remaining time to live.
+    ///
+    /// The time is rounded down to whole milliseconds such that a copy of the
+    /// record handed to another peer never outlives the original.
+    pub fn ttl(&self) -> Duration {
+        self.ttl_at(Instant::now())
+    }
+
+    /// Returns the remaining time to live at `now`.
+    pub fn ttl_at(&self, now: Instant) -> Duration {
+        let remaining = self.expires.saturating_duration_since(now);
+
+        Duration::from_millis(remaining.as_millis().min(u128::from(u64::MAX)) as u64)
+    }
+
+    /// Returns the remaining time to live in milliseconds as carried by the
+    /// messages.
+    pub fn ttl_millis(&self) -> u64 {
+        self.ttl().as_millis() as u64
+    }
+}
+
+/// Key of a record
+///
+/// Keys are ordered by their raw key first such that transfers of records
+/// can be resumed after the last key transferred.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub struct Key {
     pub raw_key: [u8; 32],
     pub replication_index: u8,
 }
 
+impl From<TransferCursor> for Key {
+    fn from(cursor: TransferCursor) -> Self {
+        Key {
+            raw_key: cursor.raw_key,
+            replication_index: cursor.replication_index,
+        }
+    }
+}
+
+impl From<Key> for TransferCursor {
+    fn from(key: Key) -> Self {
+        TransferCursor {
+            replication_index: key.replication_index,
+            raw_key: key.raw_key,
+        }
+    }
+}
+
 impl fmt::Display for Key {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut iter = self.raw_key.iter();
@@ -19,3 +540,223 @@ impl fmt::Display for Key {
         write!(f, "]:{}", self.replication_index)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, ManualClock};
+    use std::thread;
+
+    #[test]
+    fn record_clamps_future_version() {
+        let skew = MAX_CLOCK_SKEW.as_millis() as u64;
+
+        let version = current_version() + skew / 2;
+        assert_eq!(
+            version,
+            Record::new(vec![], Duration::from_secs(60), version).version
+        );
+
+        let record = Record::new(vec![], Duration::from_secs(60), u64::MAX);
+        assert!(record.version <= current_version() + skew);
+        assert!(record.version > current_version());
+    }
+
+    #[test]
+    fn record_ttl_remaining() {
+        let clock = ManualClock::new();
+        let record = Record::new_at(vec![], Duration::from_millis(1500), 0, clock.now());
+
+        assert_eq!(Duration::from_millis(1500), record.ttl_at(clock.now()));
+        assert!(!record.is_expired_at(clock.now()));
+
+        clock.advance(Duration::from_micros(1_499_100));
+        assert_eq!(Duration::from_millis(0), record.ttl_at(clock.now()));
+        assert!(!record.is_expired_at(clock.now()));
+
+        clock.advance(Duration::from_micros(900));
+        assert!(record.is_expired_at(clock.now()));
+    }
+
+    #[test]
+    fn record_zero_ttl_expires_immediately() {
+        let clock = ManualClock::new();
+        let record = Record::new_at(vec![], Duration::from_secs(0), 0, clock.now());
+
+        assert!(record.is_expired_at(clock.now()));
+        assert_eq!(Duration::from_secs(0), record.ttl_at(clock.now()));
+    }
+
+    #[test]
+    fn record_ttl_beyond_api_range() {
+        let record = Record::new(vec![], Duration::from_secs(7 * 24 * 3600), 0);
+
+        assert!(record.ttl() > Duration::from_secs(u64::from(u16::MAX)));
+        assert!(record.ttl_millis() > 1000 * u64::from(u16::MAX));
+    }
+
+    #[test]
+    fn record_storage_put_roundtrip() {
+        let clock = ManualClock::new();
+        let namespace = Namespace::new(b"users").unwrap();
+        let key = Key {
+            raw_key: namespace.key("alice").raw(),
+            replication_index: 2,
+        };
+
+        let record = Record::new_at(vec![1, 2, 3], Duration::from_secs(60), 9, clock.now());
+        let value = record.value.clone();
+
+        let storage_put = record
+            .with_replicas(3)
+            .with_namespace(Some(namespace))
+            .into_storage_put(key, clock.now());
+
+        assert_eq!(3, storage_put.replicas);
+        assert_eq!(5, storage_put.namespace_len);
+
+        let record = Record::from_storage_put(storage_put, clock.now());
+
+        // the value travels without being copied
+        assert_eq!(value.as_ptr(), record.value.as_ptr());
+
+        assert_eq!(3, record.replicas);
+        assert!(record.namespace == Some(namespace));
+        assert_eq!(Duration::from_secs(60), record.ttl_at(clock.now()));
+    }
+
+    #[test]
+    fn storage_counts_namespace_sizes() {
+        let namespace = Namespace::new(b"users").unwrap();
+        let key = |name: &str| Key {
+            raw_key: namespace.key(name).raw(),
+            replication_index: 0,
+        };
+        let record = |size| Record::new(vec![0; size], Duration::from_secs(60), 0);
+
+        let mut storage = Storage::new();
+        storage.insert(key("alice"), record(3));
+        storage.insert(key("bob"), record(4));
+
+        // values outside of the namespace do not count
+        let other = Key {
+            raw_key: [0xff; 32],
+            replication_index: 0,
+        };
+        storage.insert(other, record(100));
+
+        assert_eq!(7, storage.namespace_size(&namespace));
+
+        storage.insert(key("alice"), record(5));
+        assert_eq!(9, storage.namespace_size(&namespace));
+
+        storage.remove(&key("bob"));
+        storage.remove(&other);
+        assert_eq!(5, storage.namespace_size(&namespace));
+
+        storage.insert(key("carol"), record(2));
+        storage.retain(|key, _| key.raw_key != namespace.key("alice").raw());
+        assert_eq!(2, storage.namespace_size(&namespace));
+
+        storage.clear();
+        assert_eq!(0, storage.namespace_size(&namespace));
+    }
+
+    #[test]
+    fn snapshot_filters_records() {
+        // more records than fit into a single batch
+        let storage: Storage = (0..=u8::MAX)
+            .flat_map(|byte| (0..3).map(move |replication_index| (byte, replication_index)))
+            .map(|(byte, replication_index)| {
+                let key = Key {
+                    raw_key: [byte; 32],
+                    replication_index,
+                };
+
+                (key, Record::new(vec![byte], Duration::from_secs(60), 0))
+            })
+            .collect();
+        let storage = Mutex::new(storage);
+
+        let records = snapshot(&storage, |key| key.replication_index > 0);
+
+        assert!(records.len() > SNAPSHOT_BATCH);
+        assert_eq!(512, records.len());
+        assert!(records
+            .iter()
+            .all(|(key, record)| *record.value == [key.raw_key[0]]));
+    }
+
+    fn record_key(byte: u8) -> Key {
+        Key {
+            raw_key: [byte; 32],
+            replication_index: 0,
+        }
+    }
+
+    #[test]
+    fn read_coalescer_reads_storage() {
+        let reads = ReadCoalescer::new();
+        let mut storage = Storage::new();
+        storage.insert(
+            record_key(1),
+            Record::new(vec![1], Duration::from_secs(60), 0),
+        );
+        let storage = Mutex::new(storage);
+
+        let (record, coalesced) = reads.get(&storage, record_key(1));
+        assert_eq!([1], *record.unwrap().value);
+        assert!(!coalesced);
+
+        assert!(reads.get(&storage, record_key(2)).0.is_none());
+
+        // a read after a write sees the new value
+        storage.lock().unwrap().insert(
+            record_key(1),
+            Record::new(vec![2], Duration::from_secs(60), 1),
+        );
+        assert_eq!([2], *reads.get(&storage, record_key(1)).0.unwrap().value);
+        assert!(reads.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn read_coalescer_shares_reads() {
+        let reads = Arc::new(ReadCoalescer::new());
+        let mut storage = Storage::new();
+        storage.insert(
+            record_key(1),
+            Record::new(vec![0; 1024], Duration::from_secs(60), 0),
+        );
+        let storage = Arc::new(Mutex::new(storage));
+
+        // the first reader waits for the storage lock while the others join it
+        let guard = storage.lock().unwrap();
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let reads = Arc::clone(&reads);
+                let storage = Arc::clone(&storage);
+
+                thread::spawn(move || reads.get(&storage, record_key(1)))
+            })
+            .collect();
+
+        thread::sleep(Duration::from_millis(100));
+        drop(guard);
+
+        let results: Vec<_> = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+
+        assert_eq!(
+            1,
+            results.iter().filter(|(_, coalesced)| !coalesced).count()
+        );
+
+        let stored = &storage.lock().unwrap()[&record_key(1)];
+        assert!(results
+            .iter()
+            .all(|(record, _)| stored.value.as_ptr() == record.as_ref().unwrap().value.as_ptr()));
+        assert!(reads.in_flight.lock().unwrap().is_empty());
+    }
+}
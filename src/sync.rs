@@ -0,0 +1,49 @@
+//! Locking which survives panics of other threads
+//!
+//! A thread which panics while holding a [`Mutex`] poisons it such that every
+//! following `lock().unwrap()` panics as well. A single failing request would
+//! therefore take down all requests which need the routing table or the
+//! storage afterwards.
+//!
+//! The routing table and the storage are only ever changed by single calls
+//! which leave them in a consistent state, thus [`MutexExt::lock_or_recover`]
+//! simply continues with the data of a poisoned lock.
+//!
+//! [`Mutex`]: https://doc.rust-lang.org/std/sync/struct.Mutex.html
+//! [`MutexExt::lock_or_recover`]: trait.MutexExt.html#tymethod.lock_or_recover
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Extension of [`Mutex`] to recover the data of poisoned locks
+///
+/// # Examples
+///
+/// ```
+/// # use chord::sync::MutexExt;
+/// # use std::sync::{Arc, Mutex};
+/// # use std::thread;
+/// #
+/// let mutex = Arc::new(Mutex::new(1));
+///
+/// let poisoning = Arc::clone(&mutex);
+/// let _ = thread::spawn(move || {
+///     let _guard = poisoning.lock().unwrap();
+///     panic!();
+/// })
+/// .join();
+///
+/// assert!(mutex.is_poisoned());
+/// assert_eq!(1, *mutex.lock_or_recover());
+/// ```
+///
+/// [`Mutex`]: https://doc.rust-lang.org/std/sync/struct.Mutex.html
+pub trait MutexExt<T> {
+    /// Acquires the lock and returns the guard even if the lock is poisoned.
+    fn lock_or_recover(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> MutexExt<T> for Mutex<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
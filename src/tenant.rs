@@ -0,0 +1,155 @@
+//! Applications sharing the api interface of a peer
+//!
+//! Several local applications can use the api port of the same peer with
+//! isolated key spaces. Each [`Tenant`] owns a [`Namespace`] and
+//! authenticates with a token which the client sends along with the
+//! `API ENCODING` message opening its connection. The [`ApiHandler`] then
+//! restricts the connection to the tenant:
+//!
+//! * `DHT GET`, `DHT RESOLVE`, `DHT PUT` and `DHT DELETE` are denied for
+//!   keys outside of the namespace of the tenant
+//! * administrative requests like `DHT FLUSH` or `DHT EXPORT`, which reach
+//!   into the key spaces of other tenants, are dropped
+//!
+//! Denied requests are answered like those denied by the [`auth`] module.
+//! Connections with an unknown token are closed right away. Unless tokens
+//! are [required], connections without a token are not restricted.
+//!
+//! The quota and the other limits of a tenant are the [`Policy`] of its
+//! namespace.
+//!
+//! # Examples
+//!
+//! ```
+//! # use chord::auth::Operation;
+//! # use chord::dht::{DhtKey, Namespace};
+//! # use chord::tenant::{Tenant, Tenants};
+//! #
+//! let namespace = Namespace::from_hex("6170").unwrap();
+//! let tenants = Tenants::new().with_tenant("secret", Tenant::new("app", namespace));
+//!
+//! let tenant = tenants.authenticate("secret").unwrap();
+//! assert!(tenant.permits(Operation::Get(namespace.key("alice"))));
+//! assert!(!tenant.permits(Operation::Get(DhtKey::from_name("alice"))));
+//! assert!(tenants.authenticate("guess").is_none());
+//! ```
+//!
+//! [`Tenant`]: struct.Tenant.html
+//! [`Namespace`]: ../dht/struct.Namespace.html
+//! [`ApiHandler`]: ../handler/struct.ApiHandler.html
+//! [`auth`]: ../auth/index.html
+//! [required]: struct.Tenants.html#method.with_required
+//! [`Policy`]: ../policy/struct.Policy.html
+
+use crate::auth::Operation;
+use crate::dht::Namespace;
+use ring::digest;
+use std::collections::HashMap;
+
+/// An application with its own key space
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tenant {
+    pub name: String,
+    pub namespace: Namespace,
+}
+
+impl Tenant {
+    /// Creates a tenant called `name` which owns the keys of `namespace`.
+    pub fn new(name: &str, namespace: Namespace) -> Self {
+        Self {
+            name: name.to_string(),
+            namespace,
+        }
+    }
+
+    /// Returns whether the tenant may perform `operation`.
+    ///
+    /// Tenants may only access keys in their namespace and no
+    /// administrative requests.
+    pub fn permits(&self, operation: Operation) -> bool {
+        match operation {
+            Operation::Get(key) | Operation::Put(key) | Operation::Delete(key) => {
+                self.namespace.contains(&key)
+            }
+            Operation::Admin(_) => false,
+        }
+    }
+}
+
+/// The tenants of a peer by their tokens
+///
+/// Only the SHA-256 hashes of the tokens are kept.
+#[derive(Clone, Debug, Default)]
+pub struct Tenants {
+    tenants: HashMap<Vec<u8>, Tenant>,
+    required: bool,
+}
+
+impl Tenants {
+    /// Creates an empty set of tenants.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `tenant` which authenticates with `token`.
+    pub fn with_tenant(mut self, token: &str, tenant: Tenant) -> Self {
+        self.tenants.insert(hash(token), tenant);
+        self
+    }
+
+    /// Restricts connections without a token to negotiating their encoding
+    /// if `required` is set.
+    pub fn with_required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
+    }
+
+    /// Returns whether connections have to authenticate as a tenant.
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    /// Returns the tenant which authenticates with `token` if there is one.
+    pub fn authenticate(&self, token: &str) -> Option<&Tenant> {
+        self.tenants.get(&hash(token))
+    }
+}
+
+fn hash(token: &str) -> Vec<u8> {
+    digest::digest(&digest::SHA256, token.as_bytes())
+        .as_ref()
+        .to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::DhtKey;
+
+    #[test]
+    fn permits() {
+        let namespace = Namespace::new(b"app").unwrap();
+        let tenant = Tenant::new("app", namespace);
+        let key = namespace.key("alice");
+
+        assert!(tenant.permits(Operation::Get(key)));
+        assert!(tenant.permits(Operation::Put(key)));
+        assert!(tenant.permits(Operation::Delete(key)));
+        assert!(!tenant.permits(Operation::Put(DhtKey::from([3; 32]))));
+        assert!(!tenant.permits(Operation::Admin("DHT FLUSH")));
+    }
+
+    #[test]
+    fn authenticate() {
+        let first = Tenant::new("first", Namespace::new(b"1").unwrap());
+        let second = Tenant::new("second", Namespace::new(b"2").unwrap());
+        let tenants = Tenants::new()
+            .with_tenant("one", first.clone())
+            .with_tenant("two", second.clone());
+
+        assert_eq!(Some(&first), tenants.authenticate("one"));
+        assert_eq!(Some(&second), tenants.authenticate("two"));
+        assert_eq!(None, tenants.authenticate("three"));
+        assert!(!tenants.is_required());
+    }
+}
@@ -0,0 +1,194 @@
+//! Api client for browsers
+//!
+//! Building the crate for `wasm32-unknown-unknown` with only the `wasm`
+//! feature yields a [`DhtClient`] which dashboards running in a browser can
+//! use to GET and PUT values through the WebSocket gateway of a node, see the
+//! `websocket_address` of its configuration:
+//!
+//! ```text
+//! cargo rustc --lib --release --target wasm32-unknown-unknown \
+//!     --no-default-features --features wasm --crate-type cdylib
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/chord.wasm
+//! ```
+//!
+//! The client encodes the api messages with the [`gateway`] module and sends
+//! them over the WebSocket of the browser in binary frames. The gateway
+//! answers the requests of a connection in order, so the client keeps its
+//! pending requests in a queue and settles the oldest one with every reply.
+//!
+//! ```text
+//! const client = new DhtClient("ws://127.0.0.1:8082");
+//! await client.ready();
+//!
+//! await client.put(key, new TextEncoder().encode("hello"), 60, 2);
+//! const value = await client.get(key);
+//! ```
+//!
+//! [`DhtClient`]: struct.DhtClient.html
+//! [`gateway`]: ../gateway/index.html
+
+use crate::dht::{DhtKey, DhtValue};
+use crate::gateway::{self, Reply};
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use js_sys::{Function, Promise, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{BinaryType, CloseEvent, MessageEvent, WebSocket};
+
+/// A request waiting for its reply
+struct Pending {
+    /// Whether a `DHT FAILURE` means that there is no value rather than an
+    /// error
+    get: bool,
+    resolve: Function,
+    reject: Function,
+}
+
+type Queue = Rc<RefCell<VecDeque<Pending>>>;
+
+/// Client for the WebSocket gateway of a node
+///
+/// Keys are given as 32 bytes and values as byte arrays.
+#[wasm_bindgen]
+pub struct DhtClient {
+    socket: WebSocket,
+    pending: Queue,
+    ready: Promise,
+    // the callbacks have to live as long as the socket
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_close: Closure<dyn FnMut(CloseEvent)>,
+}
+
+#[wasm_bindgen]
+impl DhtClient {
+    /// Opens a connection to the WebSocket gateway at `url`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(url: &str) -> Result<DhtClient, JsValue> {
+        let socket = WebSocket::new(url)?;
+        socket.set_binary_type(BinaryType::Arraybuffer);
+
+        let ready = Promise::new(&mut |resolve, reject| {
+            socket.set_onopen(Some(&resolve));
+            socket.set_onerror(Some(&reject));
+        });
+
+        let pending: Queue = Rc::default();
+
+        let queue = Rc::clone(&pending);
+        let on_message = Closure::wrap(Box::new(move |event: MessageEvent| {
+            if let Some(request) = queue.borrow_mut().pop_front() {
+                settle(request, event.data());
+            }
+        }) as Box<dyn FnMut(MessageEvent)>);
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        let queue = Rc::clone(&pending);
+        let on_close = Closure::wrap(Box::new(move |event: CloseEvent| {
+            let reason = JsValue::from_str(&format!("connection closed: {}", event.code()));
+
+            for request in queue.borrow_mut().drain(..) {
+                let _ = request.reject.call1(&JsValue::NULL, &reason);
+            }
+        }) as Box<dyn FnMut(CloseEvent)>);
+        socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+        Ok(DhtClient {
+            socket,
+            pending,
+            ready,
+            _on_message: on_message,
+            _on_close: on_close,
+        })
+    }
+
+    /// Returns a promise which resolves once the connection is open.
+    pub fn ready(&self) -> Promise {
+        self.ready.clone()
+    }
+
+    /// Obtains the value stored under `key`.
+    ///
+    /// The promise resolves to the value or `undefined` if no value was
+    /// found.
+    pub fn get(&self, key: &[u8]) -> Promise {
+        match DhtKey::from_slice(key) {
+            Ok(key) => self.request(gateway::encode_get(&key), true),
+            Err(err) => Promise::reject(&JsValue::from_str(&err.to_string())),
+        }
+    }
+
+    /// Stores `value` under `key` for `ttl` seconds on `replication`
+    /// replicas.
+    ///
+    /// The promise resolves to the number of replicas which acknowledged the
+    /// value and rejects if the DHT could not store it.
+    pub fn put(&self, key: &[u8], value: Vec<u8>, ttl: u16, replication: u8) -> Promise {
+        let request = DhtKey::from_slice(key).and_then(|key| {
+            let value = DhtValue::new(value)?;
+
+            gateway::encode_put(&key, &value, ttl, replication)
+        });
+
+        match request {
+            Ok(request) => self.request(request, false),
+            Err(err) => Promise::reject(&JsValue::from_str(&err.to_string())),
+        }
+    }
+
+    /// Closes the connection and rejects all pending requests.
+    pub fn close(&self) -> Result<(), JsValue> {
+        self.socket.close()
+    }
+}
+
+impl DhtClient {
+    fn request(&self, bytes: Vec<u8>, get: bool) -> Promise {
+        let socket = &self.socket;
+        let pending = &self.pending;
+
+        Promise::new(
+            &mut |resolve, reject| match socket.send_with_u8_array(&bytes) {
+                Ok(()) => pending.borrow_mut().push_back(Pending {
+                    get,
+                    resolve,
+                    reject,
+                }),
+                Err(err) => {
+                    let _ = reject.call1(&JsValue::NULL, &err);
+                }
+            },
+        )
+    }
+}
+
+/// Settles `request` with the reply in `data`.
+///
+/// The gateway sends a text frame describing the error if it could not
+/// forward the request.
+fn settle(request: Pending, data: JsValue) {
+    let outcome = match data.as_string() {
+        Some(error) => Err(JsValue::from_str(&error)),
+        None => {
+            let bytes = Uint8Array::new(&data).to_vec();
+
+            match gateway::decode_reply(&bytes) {
+                Ok(Reply::Value(value)) => Ok(Uint8Array::from(value.as_bytes()).into()),
+                Ok(Reply::Stored { acks, .. }) => Ok(JsValue::from(acks)),
+                Ok(Reply::Failure) if request.get => Ok(JsValue::UNDEFINED),
+                Ok(Reply::Failure) => Err(JsValue::from_str("too few replicas stored the value")),
+                Err(err) => Err(JsValue::from_str(&err.to_string())),
+            }
+        }
+    };
+
+    let _ = match outcome {
+        Ok(value) => request.resolve.call1(&JsValue::NULL, &value),
+        Err(err) => request.reject.call1(&JsValue::NULL, &err),
+    };
+}
@@ -0,0 +1,424 @@
+//! Minimal WebSocket transport as described in RFC 6455
+//!
+//! The [`WebSocket`] struct performs the opening handshake on top of a TCP
+//! stream and exchanges [`Frame`] objects afterwards. Fragmented messages are
+//! reassembled and pings are answered transparently. Extensions and
+//! subprotocols are not supported.
+//!
+//! [`WebSocket`]: struct.WebSocket.html
+//! [`Frame`]: struct.Frame.html
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::io;
+use std::io::prelude::*;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Magic string appended to the key of the client during the handshake
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Maximum size of the header of the opening handshake
+const MAX_HEADER_SIZE: usize = 8192;
+
+/// Maximum size of a reassembled message
+const MAX_PAYLOAD_SIZE: usize = 1 << 20;
+
+/// The type of a frame
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl Opcode {
+    fn from_u8(opcode: u8) -> io::Result<Self> {
+        match opcode {
+            0x0 => Ok(Opcode::Continuation),
+            0x1 => Ok(Opcode::Text),
+            0x2 => Ok(Opcode::Binary),
+            0x8 => Ok(Opcode::Close),
+            0x9 => Ok(Opcode::Ping),
+            0xa => Ok(Opcode::Pong),
+            _ => Err(invalid_data(format!("unknown opcode {}", opcode))),
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            Opcode::Continuation => 0x0,
+            Opcode::Text => 0x1,
+            Opcode::Binary => 0x2,
+            Opcode::Close => 0x8,
+            Opcode::Ping => 0x9,
+            Opcode::Pong => 0xa,
+        }
+    }
+}
+
+/// A single frame of a WebSocket connection
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub fin: bool,
+    pub opcode: Opcode,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Reads a frame and removes the mask if one is given.
+    pub fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+        let mut header = [0; 2];
+        reader.read_exact(&mut header)?;
+
+        let fin = header[0] & 0x80 != 0;
+        let opcode = Opcode::from_u8(header[0] & 0x0f)?;
+        let masked = header[1] & 0x80 != 0;
+
+        let len = match header[1] & 0x7f {
+            126 => {
+                let mut len = [0; 2];
+                reader.read_exact(&mut len)?;
+                u64::from(u16::from_be_bytes(len))
+            }
+            127 => {
+                let mut len = [0; 8];
+                reader.read_exact(&mut len)?;
+                u64::from_be_bytes(len)
+            }
+            len => u64::from(len),
+        };
+
+        if len > MAX_PAYLOAD_SIZE as u64 {
+            return Err(invalid_data(format!("frame of {} bytes is too large", len)));
+        }
+
+        let mut mask = [0; 4];
+
+        if masked {
+            reader.read_exact(&mut mask)?;
+        }
+
+        let mut payload = vec![0; len as usize];
+        reader.read_exact(&mut payload)?;
+
+        if masked {
+            apply_mask(&mut payload, mask);
+        }
+
+        Ok(Frame {
+            fin,
+            opcode,
+            payload,
+        })
+    }
+
+    /// Writes the frame and masks the payload if `mask` is given.
+    pub fn write_to(&self, writer: &mut dyn Write, mask: Option<[u8; 4]>) -> io::Result<()> {
+        let mut buffer = Vec::with_capacity(self.payload.len() + 14);
+
+        buffer.push(if self.fin { 0x80 } else { 0 } | self.opcode.as_u8());
+
+        let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+        let len = self.payload.len();
+
+        if len < 126 {
+            buffer.push(mask_bit | len as u8);
+        } else if len <= usize::from(u16::MAX) {
+            buffer.push(mask_bit | 126);
+            buffer.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            buffer.push(mask_bit | 127);
+            buffer.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+
+        let mut payload = self.payload.clone();
+
+        if let Some(mask) = mask {
+            buffer.extend_from_slice(&mask);
+            apply_mask(&mut payload, mask);
+        }
+
+        buffer.extend_from_slice(&payload);
+
+        writer.write_all(&buffer)
+    }
+}
+
+fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Computes the `Sec-WebSocket-Accept` header for the key of a client.
+pub fn accept_key(key: &str) -> String {
+    let digest = digest::digest(&digest::SHA1, format!("{}{}", key.trim(), GUID).as_bytes());
+
+    BASE64.encode(digest.as_ref())
+}
+
+/// Reads the header of an HTTP request or response up to the empty line.
+fn read_header(stream: &mut TcpStream) -> io::Result<String> {
+    let mut header = Vec::new();
+    let mut byte = [0; 1];
+
+    while !header.ends_with(b"\r\n\r\n") {
+        if header.len() >= MAX_HEADER_SIZE {
+            return Err(invalid_data("handshake header is too large".to_string()));
+        }
+
+        stream.read_exact(&mut byte)?;
+        header.push(byte[0]);
+    }
+
+    String::from_utf8(header).map_err(|err| invalid_data(err.to_string()))
+}
+
+/// Returns the value of the header field `name` ignoring its case.
+fn header_field<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    header.lines().skip(1).find_map(|line| {
+        let (field, value) = line.split_once(':')?;
+
+        if field.trim().eq_ignore_ascii_case(name) {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+/// A message based connection on top of a TCP stream
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chord::websocket::{Opcode, WebSocket};
+/// #
+/// let mut ws = WebSocket::connect("127.0.0.1:8080", 3600)
+///     .expect("could not connect");
+///
+/// ws.send(Opcode::Text, br#"{"type":"info"}"#.to_vec())
+///     .expect("could not send message");
+///
+/// let (opcode, payload) = ws.receive().expect("could not receive message");
+/// ```
+pub struct WebSocket {
+    stream: TcpStream,
+    client: bool,
+}
+
+impl WebSocket {
+    /// Performs the server side of the handshake on an incoming stream.
+    pub fn accept(mut stream: TcpStream) -> io::Result<Self> {
+        let header = read_header(&mut stream)?;
+
+        let upgrade = header_field(&header, "Upgrade")
+            .is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+
+        let key = match header_field(&header, "Sec-WebSocket-Key") {
+            Some(key) if upgrade => key,
+            _ => {
+                stream.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+
+                return Err(invalid_data("not a WebSocket handshake".to_string()));
+            }
+        };
+
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(key)
+        );
+        stream.write_all(response.as_bytes())?;
+
+        Ok(Self {
+            stream,
+            client: false,
+        })
+    }
+
+    /// Connects to a WebSocket server and performs the client side of the
+    /// handshake.
+    ///
+    /// `timeout_ms` is the timeout in milliseconds for read and write
+    /// operations.
+    pub fn connect<A: ToSocketAddrs>(addr: A, timeout_ms: u64) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(addr)?;
+
+        let timeout = Duration::from_millis(timeout_ms);
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        let mut nonce = [0; 16];
+        random(&mut nonce)?;
+        let key = BASE64.encode(nonce);
+
+        let request = format!(
+            "GET / HTTP/1.1\r\n\
+             Host: {}\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\n\
+             Sec-WebSocket-Version: 13\r\n\r\n",
+            stream.peer_addr()?,
+            key
+        );
+        stream.write_all(request.as_bytes())?;
+
+        let header = read_header(&mut stream)?;
+
+        if header_field(&header, "Sec-WebSocket-Accept") != Some(accept_key(&key).as_str()) {
+            return Err(invalid_data("server refused the handshake".to_string()));
+        }
+
+        Ok(Self {
+            stream,
+            client: true,
+        })
+    }
+
+    /// Receives the next text or binary message.
+    ///
+    /// Fragmented messages are reassembled and pings are answered. A close
+    /// frame is answered and reported as an error of kind
+    /// `ConnectionAborted`.
+    pub fn receive(&mut self) -> io::Result<(Opcode, Vec<u8>)> {
+        let mut message: Option<(Opcode, Vec<u8>)> = None;
+
+        loop {
+            let frame = Frame::read_from(&mut self.stream)?;
+
+            match frame.opcode {
+                Opcode::Ping => self.send(Opcode::Pong, frame.payload)?,
+                Opcode::Pong => {}
+                Opcode::Close => {
+                    // the close frame may fail if the peer already left
+                    let _ = self.send(Opcode::Close, frame.payload);
+
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionAborted,
+                        "WebSocket closed by peer",
+                    ));
+                }
+                Opcode::Continuation => match message {
+                    Some((_, ref mut payload)) => {
+                        if payload.len() + frame.payload.len() > MAX_PAYLOAD_SIZE {
+                            return Err(invalid_data("message is too large".to_string()));
+                        }
+
+                        payload.extend_from_slice(&frame.payload);
+                    }
+                    None => return Err(invalid_data("unexpected continuation".to_string())),
+                },
+                opcode => message = Some((opcode, frame.payload)),
+            }
+
+            if frame.fin {
+                if let Some(message) = message.take() {
+                    return Ok(message);
+                }
+            }
+        }
+    }
+
+    /// Sends a single unfragmented frame.
+    pub fn send(&mut self, opcode: Opcode, payload: Vec<u8>) -> io::Result<()> {
+        let mask = if self.client {
+            let mut mask = [0; 4];
+            random(&mut mask)?;
+            Some(mask)
+        } else {
+            None
+        };
+
+        let frame = Frame {
+            fin: true,
+            opcode,
+            payload,
+        };
+
+        frame.write_to(&mut self.stream, mask)
+    }
+
+    /// Sends a close frame to end the connection.
+    pub fn close(&mut self) -> io::Result<()> {
+        self.send(Opcode::Close, Vec::new())
+    }
+}
+
+fn random(dest: &mut [u8]) -> io::Result<()> {
+    SystemRandom::new()
+        .fill(dest)
+        .map_err(|_| io::Error::other("could not generate random bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn accept_key_rfc_example() {
+        assert_eq!(
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=",
+            accept_key("dGhlIHNhbXBsZSBub25jZQ==")
+        );
+    }
+
+    #[test]
+    fn masked_frame_rfc_example() {
+        #[rustfmt::skip]
+        let buf = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d,
+            0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+
+        let frame = Frame::read_from(&mut Cursor::new(&buf[..])).unwrap();
+
+        assert!(frame.fin);
+        assert_eq!(Opcode::Text, frame.opcode);
+        assert_eq!(b"Hello".to_vec(), frame.payload);
+
+        let mut written = Vec::new();
+        frame
+            .write_to(&mut written, Some([0x37, 0xfa, 0x21, 0x3d]))
+            .unwrap();
+
+        assert_eq!(&buf[..], &written[..]);
+    }
+
+    #[test]
+    fn extended_length() {
+        let frame = Frame {
+            fin: true,
+            opcode: Opcode::Binary,
+            payload: vec![7; 300],
+        };
+
+        let mut written = Vec::new();
+        frame.write_to(&mut written, None).unwrap();
+
+        assert_eq!(&[0x82, 126, 1, 44], &written[..4]);
+        assert_eq!(frame, Frame::read_from(&mut Cursor::new(written)).unwrap());
+    }
+
+    #[test]
+    fn header_field_ignores_case() {
+        let header = "GET / HTTP/1.1\r\nsec-websocket-key: abc \r\n\r\n";
+
+        assert_eq!(Some("abc"), header_field(header, "Sec-WebSocket-Key"));
+        assert_eq!(None, header_field(header, "Upgrade"));
+    }
+}
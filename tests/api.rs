@@ -0,0 +1,1415 @@
+extern crate chord;
+
+use chord::auth::{self, Authorizer, Operation, Requester};
+use chord::client::{ApiClient, Conditional, Session};
+use chord::dht::{DhtKey, DhtValue, Namespace};
+use chord::error::StaleError;
+use chord::framing::{self, Framing};
+use chord::handler::{ApiHandler, P2PHandler};
+use chord::handoff::Handoff;
+use chord::message::api::{
+    ApiEncoding, DhtCancel, DhtFailure, DhtGet, DhtPut, Encoding, FlushScope, NodeInfo,
+};
+use chord::message::p2p::{
+    FailureReason, PeerFind, PeerFound, StorageFailure, StorageGet, StoragePut,
+};
+use chord::message::Message;
+use chord::metrics::Metrics;
+use chord::network::{Connection, Multiplexer, Server, ThreadPool};
+use chord::policy::{Policies, Policy};
+use chord::procedures::Procedures;
+use chord::puzzle::Puzzle;
+use chord::routing::identifier::Identify;
+use chord::routing::Routing;
+use chord::storage::{self, Key, Record, Storage};
+use chord::tenant::{Tenant, Tenants};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: u64 = 5000;
+const FINGERS: usize = 8;
+
+fn key(byte: u8) -> DhtKey {
+    DhtKey::from([byte; 32])
+}
+
+fn value(bytes: &[u8]) -> DhtValue {
+    DhtValue::new(bytes.to_vec()).unwrap()
+}
+
+fn create_network(p2p_addr: SocketAddr, api_addr: SocketAddr) -> ApiClient {
+    create_network_with_metrics(p2p_addr, api_addr, Arc::new(Metrics::new()), 1)
+}
+
+fn create_network_with_metrics(
+    p2p_addr: SocketAddr,
+    api_addr: SocketAddr,
+    metrics: Arc<Metrics>,
+    api_workers: usize,
+) -> ApiClient {
+    create_network_with(
+        p2p_addr,
+        api_addr,
+        metrics,
+        api_workers,
+        false,
+        auth::allow_all(),
+    )
+}
+
+fn create_network_with(
+    p2p_addr: SocketAddr,
+    api_addr: SocketAddr,
+    metrics: Arc<Metrics>,
+    api_workers: usize,
+    cbor: bool,
+    authorizer: Arc<dyn Authorizer>,
+) -> ApiClient {
+    let routing = Routing::from_addrs(p2p_addr, p2p_addr, p2p_addr, vec![p2p_addr; FINGERS]);
+    let routing = Arc::new(Mutex::new(routing));
+    let storage = Arc::new(Mutex::new(Storage::new()));
+    let read_only = Arc::new(AtomicBool::new(false));
+
+    let p2p_handler = P2PHandler::with_metrics(
+        Arc::clone(&routing),
+        Arc::clone(&storage),
+        TIMEOUT,
+        Arc::clone(&metrics),
+    )
+    .with_read_only(Arc::clone(&read_only))
+    .with_authorizer(Arc::clone(&authorizer));
+    Server::new(p2p_handler)
+        .listen(p2p_addr, 4)
+        .expect("could not bind to port");
+
+    let multiplexer = Arc::new(Multiplexer::new(Arc::clone(&metrics)));
+
+    let api_handler = ApiHandler::new(
+        routing,
+        storage,
+        Arc::new(Handoff::new(TIMEOUT)),
+        metrics,
+        TIMEOUT,
+    )
+    .with_multiplexer(multiplexer)
+    .with_read_only(read_only)
+    .with_cbor(cbor)
+    .with_authorizer(authorizer);
+    Server::new(api_handler)
+        .listen(api_addr, api_workers)
+        .expect("could not bind to port");
+
+    ApiClient::new(api_addr, TIMEOUT)
+}
+
+#[test]
+fn put_and_get() {
+    let client = create_network(
+        "127.0.3.1:38100".parse().unwrap(),
+        "127.0.3.1:38101".parse().unwrap(),
+    );
+
+    client.put(key(3), value(&[1, 2, 3]), 60, 0).unwrap();
+
+    assert_eq!(Some(value(&[1, 2, 3])), client.get(key(3)).unwrap());
+}
+
+#[test]
+fn zero_ttl_expires_immediately() {
+    let client = create_network(
+        "127.0.3.32:38100".parse().unwrap(),
+        "127.0.3.32:38101".parse().unwrap(),
+    );
+
+    client.put(key(3), value(&[1, 2, 3]), 0, 0).unwrap();
+
+    assert_eq!(None, client.get(key(3)).unwrap());
+
+    // the expired record does not block a new value either
+    client.put(key(3), value(&[4, 5, 6]), 60, 0).unwrap();
+
+    assert_eq!(Some(value(&[4, 5, 6])), client.get(key(3)).unwrap());
+}
+
+#[test]
+fn millisecond_ttls() {
+    let client = create_network(
+        "127.0.3.33:38100".parse().unwrap(),
+        "127.0.3.33:38101".parse().unwrap(),
+    );
+
+    let dht_put = DhtPut::builder()
+        .ttl(Duration::from_millis(300))
+        .replication(0)
+        .acks(1)
+        .key(key(3))
+        .value(value(&[1, 2, 3]))
+        .build();
+    assert!(client.put_message(dht_put).unwrap().is_some());
+
+    assert_eq!(Some(value(&[1, 2, 3])), client.get(key(3)).unwrap());
+
+    thread::sleep(Duration::from_millis(600));
+
+    assert_eq!(None, client.get(key(3)).unwrap());
+
+    // beyond the 18 hours which fit into the original DHT PUT
+    let dht_put = DhtPut::builder()
+        .ttl(Duration::from_secs(100_000))
+        .replication(0)
+        .acks(1)
+        .key(key(4))
+        .value(value(&[4, 5, 6]))
+        .build();
+    assert!(client.put_message(dht_put).unwrap().is_some());
+
+    assert_eq!(Some(value(&[4, 5, 6])), client.get(key(4)).unwrap());
+}
+
+#[test]
+fn resolve() {
+    let p2p_addr: SocketAddr = "127.0.3.2:38100".parse().unwrap();
+    let client = create_network(p2p_addr, "127.0.3.2:38101".parse().unwrap());
+
+    let (socket_addr, identifier) = client.resolve(key(3)).unwrap().unwrap();
+
+    assert_eq!(p2p_addr, socket_addr);
+    assert_eq!(p2p_addr.identifier(), identifier);
+}
+
+#[test]
+fn trace() {
+    let p2p_addr: SocketAddr = "127.0.3.3:38100".parse().unwrap();
+    let client = create_network(p2p_addr, "127.0.3.3:38101".parse().unwrap());
+
+    let path = client.trace(key(3)).unwrap().unwrap();
+
+    assert_eq!(vec![p2p_addr], path);
+}
+
+#[test]
+fn node_info() {
+    let p2p_addr: SocketAddr = "127.0.3.4:38100".parse().unwrap();
+    let client = create_network(p2p_addr, "127.0.3.4:38101".parse().unwrap());
+
+    client.resolve(key(3)).unwrap().unwrap();
+
+    let node_info = client.node_info().unwrap();
+
+    assert_eq!(p2p_addr, node_info.socket_addr);
+    assert_eq!(p2p_addr.identifier(), node_info.identifier);
+    assert_eq!(1, node_info.lookup_stats.lookups);
+    assert_eq!(1, node_info.lookup_stats.hops.max);
+}
+
+#[test]
+fn flush() {
+    let client = create_network(
+        "127.0.3.5:38100".parse().unwrap(),
+        "127.0.3.5:38101".parse().unwrap(),
+    );
+
+    client.put(key(1), value(&[1]), 60, 0).unwrap();
+    client.put(key(2), value(&[2]), 60, 0).unwrap();
+    client.put(key(3), value(&[3]), 0, 0).unwrap();
+
+    assert_eq!(1, client.flush(FlushScope::Expired).unwrap());
+    assert_eq!(1, client.flush(FlushScope::Namespace(vec![1, 1])).unwrap());
+    assert_eq!(None, client.get(key(1)).unwrap());
+    assert_eq!(Some(value(&[2])), client.get(key(2)).unwrap());
+
+    assert_eq!(1, client.flush(FlushScope::All).unwrap());
+    assert_eq!(None, client.get(key(2)).unwrap());
+}
+
+#[test]
+fn list_local() {
+    let client = create_network(
+        "127.0.3.20:38100".parse().unwrap(),
+        "127.0.3.20:38101".parse().unwrap(),
+    );
+
+    for byte in 1..=3 {
+        let bytes = vec![byte; byte as usize];
+
+        assert_eq!(
+            Some(1),
+            client
+                .put_acknowledged(key(byte), value(&bytes), 60, 0, 1)
+                .unwrap()
+        );
+    }
+
+    let first = client.list_local(2, None).unwrap();
+
+    assert_eq!(3, first.total_records);
+    assert_eq!(6, first.total_bytes);
+    assert_eq!(1, first.remaining);
+    assert_eq!(
+        vec![(key(1), 1), (key(2), 2)],
+        first
+            .records
+            .iter()
+            .map(|record| (record.key, record.size))
+            .collect::<Vec<_>>()
+    );
+    assert!(first.records.iter().all(|record| record.ttl <= 60_000));
+
+    let second = client.list_local(2, first.next_cursor()).unwrap();
+
+    assert_eq!(0, second.remaining);
+    assert_eq!(1, second.records.len());
+    assert_eq!(key(3), second.records[0].key);
+    assert_eq!(None, second.next_cursor());
+}
+
+#[test]
+fn export_import() {
+    let client = create_network(
+        "127.0.3.23:38100".parse().unwrap(),
+        "127.0.3.23:38101".parse().unwrap(),
+    );
+
+    // values too large to be exported in a single reply
+    for byte in 1..=3 {
+        assert_eq!(
+            Some(1),
+            client
+                .put_acknowledged(key(byte), value(&vec![byte; 30000]), 60, 0, 1)
+                .unwrap()
+        );
+    }
+
+    let first = client.export(10, None).unwrap();
+    assert_eq!(2, first.records.len());
+    assert_eq!(1, first.remaining);
+
+    let mut export = Vec::new();
+    assert_eq!(3, client.export_to(&mut export, None).unwrap());
+
+    let namespace = Namespace::new(&[2]).unwrap();
+    let mut partial = Vec::new();
+    assert_eq!(1, client.export_to(&mut partial, Some(&namespace)).unwrap());
+
+    assert_eq!(3, client.flush(FlushScope::All).unwrap());
+
+    let reply = client.import_from(&export[..]).unwrap();
+    assert_eq!((3, 0), (reply.stored, reply.skipped));
+
+    for byte in 1..=3 {
+        assert_eq!(
+            Some(value(&vec![byte; 30000])),
+            client.get(key(byte)).unwrap()
+        );
+    }
+
+    // the same versions are stored already
+    let reply = client.import_from(&partial[..]).unwrap();
+    assert_eq!((0, 1), (reply.stored, reply.skipped));
+}
+
+#[test]
+fn cbor_encoding() {
+    let api_addr = "127.0.3.24:38101".parse().unwrap();
+    let client = create_network_with(
+        "127.0.3.24:38100".parse().unwrap(),
+        api_addr,
+        Arc::new(Metrics::new()),
+        1,
+        true,
+        auth::allow_all(),
+    )
+    .with_encoding(Encoding::Cbor);
+
+    assert_eq!(
+        Some(1),
+        client
+            .put_acknowledged(key(3), value(&[1, 2, 3]), 60, 0, 1)
+            .unwrap()
+    );
+    assert_eq!(Some(value(&[1, 2, 3])), client.get(key(3)).unwrap());
+
+    let (_, metadata) = client.get_with_metadata(key(3)).unwrap().unwrap();
+    let metadata = metadata.unwrap();
+    assert_eq!(0, metadata.replication_index);
+    assert!(metadata.ttl > 59_000 && metadata.ttl <= 60_000);
+    assert!(metadata.stored >= metadata.version);
+
+    assert_eq!(
+        Conditional::NotModified,
+        client.get_if_newer(key(3), metadata.version).unwrap()
+    );
+    assert!(matches!(
+        client.get_if_newer(key(3), metadata.version - 1).unwrap(),
+        Conditional::Modified(..)
+    ));
+    assert_eq!(
+        Conditional::NotFound,
+        client.get_if_newer(key(4), metadata.version).unwrap()
+    );
+
+    let listing = client.list_local(10, None).unwrap();
+    assert_eq!(1, listing.total_records);
+
+    let mut export = Vec::new();
+    assert_eq!(1, client.export_to(&mut export, None).unwrap());
+    assert_eq!(1, client.flush(FlushScope::All).unwrap());
+
+    let reply = client.import_from(&export[..]).unwrap();
+    assert_eq!((1, 0), (reply.stored, reply.skipped));
+
+    // binary clients are still served by the same peer
+    let binary_client = ApiClient::new(api_addr, TIMEOUT);
+    assert_eq!(Some(value(&[1, 2, 3])), binary_client.get(key(3)).unwrap());
+    assert_eq!(
+        Some((value(&[1, 2, 3]), None)),
+        binary_client.get_with_metadata(key(3)).unwrap()
+    );
+}
+
+#[test]
+fn cbor_encoding_disabled() {
+    let api_addr = "127.0.3.25:38101".parse().unwrap();
+    let client = create_network("127.0.3.25:38100".parse().unwrap(), api_addr);
+
+    let mut con = Connection::open(api_addr, TIMEOUT).unwrap();
+    let api_encoding = ApiEncoding {
+        encoding: Encoding::Cbor,
+        token: None,
+    };
+    con.send(&Message::ApiEncoding(api_encoding)).unwrap();
+
+    // the peer keeps the binary encoding
+    let reply = con.receive().unwrap();
+    assert_eq!(
+        Message::ApiEncoding(ApiEncoding {
+            encoding: Encoding::Binary,
+            token: None,
+        }),
+        reply
+    );
+
+    con.send(&Message::NodeInfo(NodeInfo)).unwrap();
+    assert_eq!("NODE INFO REPLY", con.receive().unwrap().name());
+
+    assert!(client.with_encoding(Encoding::Cbor).get(key(3)).is_err());
+}
+
+#[test]
+fn peers() {
+    let p2p_addr = "127.0.3.21:38100".parse().unwrap();
+    let client = create_network(p2p_addr, "127.0.3.21:38101".parse().unwrap());
+
+    let first = client.peers(3, None).unwrap();
+
+    assert_eq!(FINGERS as u16, first.total_peers);
+    assert_eq!(FINGERS as u16 - 3, first.remaining);
+    assert_eq!(
+        vec![(0, p2p_addr), (1, p2p_addr), (2, p2p_addr)],
+        first
+            .peers
+            .iter()
+            .map(|peer| (peer.index, peer.socket_addr))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(p2p_addr.identifier(), first.peers[0].identifier);
+
+    let second = client.peers(100, first.next_cursor()).unwrap();
+
+    assert_eq!(0, second.remaining);
+    assert_eq!(FINGERS - 3, second.peers.len());
+    assert_eq!(3, second.peers[0].index);
+    assert_eq!(None, second.next_cursor());
+}
+
+#[test]
+fn ring_walk() {
+    let p2p_addr = "127.0.3.22:38100".parse().unwrap();
+    let client = create_network(p2p_addr, "127.0.3.22:38101".parse().unwrap());
+
+    let first = client.ring_walk(1, None).unwrap();
+
+    assert!(!first.complete);
+    assert_eq!(1, first.peers.len());
+    assert_eq!(p2p_addr, first.peers[0].socket_addr);
+    assert_eq!(Some(p2p_addr), first.next_cursor());
+
+    // the successor of the only peer is the peer itself
+    let second = client.ring_walk(1, first.next_cursor()).unwrap();
+
+    assert!(second.complete);
+    assert!(second.peers.is_empty());
+    assert_eq!(None, second.next_cursor());
+
+    let whole = client.ring_walk(100, None).unwrap();
+
+    assert!(whole.complete);
+    assert_eq!(1, whole.peers.len());
+}
+
+#[test]
+fn put_acknowledged() {
+    let client = create_network(
+        "127.0.3.6:38100".parse().unwrap(),
+        "127.0.3.6:38101".parse().unwrap(),
+    );
+
+    assert_eq!(
+        Some(1),
+        client
+            .put_acknowledged(key(3), value(&[1]), 60, 0, 1)
+            .unwrap()
+    );
+
+    // the value exists already such that no replica confirms
+    assert_eq!(
+        None,
+        client
+            .put_acknowledged(key(3), value(&[2]), 60, 0, 1)
+            .unwrap()
+    );
+}
+
+#[test]
+fn get_quorum() {
+    let client = create_network(
+        "127.0.3.7:38100".parse().unwrap(),
+        "127.0.3.7:38101".parse().unwrap(),
+    );
+
+    client.put(key(3), value(&[1, 2, 3]), 60, 1).unwrap();
+
+    assert_eq!(
+        Some(value(&[1, 2, 3])),
+        client.get_quorum(key(3), 2, 2).unwrap()
+    );
+    assert_eq!(None, client.get_quorum(key(4), 2, 2).unwrap());
+}
+
+#[test]
+fn delete() {
+    let client = create_network(
+        "127.0.3.8:38100".parse().unwrap(),
+        "127.0.3.8:38101".parse().unwrap(),
+    );
+
+    client.put(key(3), value(&[1, 2, 3]), 60, 1).unwrap();
+
+    assert_eq!(2, client.delete(key(3), 1).unwrap());
+    assert_eq!(None, client.get(key(3)).unwrap());
+    assert_eq!(0, client.delete(key(3), 1).unwrap());
+}
+
+#[test]
+fn stats() {
+    let metrics = Arc::new(Metrics::new());
+    let client = create_network_with_metrics(
+        "127.0.3.9:38100".parse().unwrap(),
+        "127.0.3.9:38101".parse().unwrap(),
+        Arc::clone(&metrics),
+        1,
+    );
+
+    client
+        .put_acknowledged(key(3), value(&[1]), 60, 0, 1)
+        .unwrap();
+    client.get(key(3)).unwrap();
+    client.get(key(4)).unwrap();
+
+    let snapshot = metrics.stats().snapshot();
+    let count = |name| {
+        snapshot
+            .messages
+            .iter()
+            .find(|count| count.name == name)
+            .copied()
+            .unwrap()
+    };
+
+    // replies are counted after they have been written such that only
+    // received messages are reliable here
+    assert_eq!(1, count("DHT PUT").received);
+    assert_eq!(2, count("DHT GET").received);
+    assert_eq!(1, count("STORAGE PUT").received);
+    assert_eq!(1, snapshot.storage_puts);
+    assert_eq!(0, snapshot.handler_errors);
+}
+
+#[test]
+fn get_within_budget() {
+    let client = create_network(
+        "127.0.3.10:38100".parse().unwrap(),
+        "127.0.3.10:38101".parse().unwrap(),
+    );
+
+    client.put(key(3), value(&[1, 2, 3]), 60, 0).unwrap();
+
+    assert_eq!(
+        Some(value(&[1, 2, 3])),
+        client.get_within(key(3), Duration::from_secs(5)).unwrap()
+    );
+
+    // the request is dropped without a reply once the budget is spent
+    assert!(client.get_within(key(3), Duration::from_secs(0)).is_err());
+}
+
+#[test]
+fn cancel_get() {
+    let metrics = Arc::new(Metrics::new());
+    let client = Arc::new(create_network_with_metrics(
+        "127.0.3.11:38100".parse().unwrap(),
+        "127.0.3.11:38101".parse().unwrap(),
+        Arc::clone(&metrics),
+        2,
+    ));
+
+    // searching a missing key queries all 255 replication indices
+    let get_client = Arc::clone(&client);
+    let handle = thread::spawn(move || {
+        get_client
+            .get_cancellable(key(4), 7)
+            .map_err(|err| err.to_string())
+    });
+
+    while !handle.is_finished() {
+        client.cancel(7).unwrap();
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    assert_eq!(None, handle.join().unwrap().unwrap());
+
+    let snapshot = metrics.stats().snapshot();
+    let storage_gets = snapshot
+        .messages
+        .iter()
+        .find(|count| count.name == "STORAGE GET")
+        .map_or(0, |count| count.received);
+
+    assert!(storage_gets < 255);
+}
+
+#[test]
+fn cancel_get_of_other_connection() {
+    let metrics = Arc::new(Metrics::new());
+    let api_addr: SocketAddr = "127.0.3.35:38101".parse().unwrap();
+    let client = create_network_with_metrics(
+        "127.0.3.35:38100".parse().unwrap(),
+        api_addr,
+        Arc::clone(&metrics),
+        2,
+    );
+
+    let handle = thread::spawn(move || {
+        client
+            .get_cancellable(key(4), 7)
+            .map_err(|err| err.to_string())
+    });
+
+    // another client guessing the request ID cannot abort the search
+    while !handle.is_finished() {
+        let mut con = Connection::open(api_addr, TIMEOUT).unwrap();
+        con.send(&Message::DhtCancel(DhtCancel { request_id: 7 }))
+            .unwrap();
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    assert_eq!(None, handle.join().unwrap().unwrap());
+
+    let snapshot = metrics.stats().snapshot();
+    let storage_gets = snapshot
+        .messages
+        .iter()
+        .find(|count| count.name == "STORAGE GET")
+        .map_or(0, |count| count.received);
+
+    assert_eq!(255, storage_gets);
+}
+
+#[test]
+fn correlated_request() {
+    let p2p_addr: SocketAddr = "127.0.3.12:38100".parse().unwrap();
+    create_network(p2p_addr, "127.0.3.12:38101".parse().unwrap());
+
+    let identifier = p2p_addr.identifier();
+    let peer_find = PeerFind {
+        identifier,
+        trace: None,
+        budget: None,
+    };
+
+    let mut con = Connection::open(p2p_addr, TIMEOUT).unwrap();
+    con.send_request(42, &Message::PeerFind(peer_find)).unwrap();
+
+    let msg = con.receive().unwrap();
+
+    assert_eq!(Some(42), con.request_id());
+    assert_eq!(
+        Message::PeerFound(PeerFound {
+            identifier,
+            socket_addr: p2p_addr,
+            authoritative: true,
+            trace: None,
+        }),
+        msg
+    );
+}
+
+#[test]
+fn multiplexed_requests() {
+    let p2p_addr: SocketAddr = "127.0.3.13:38100".parse().unwrap();
+    create_network(p2p_addr, "127.0.3.13:38101".parse().unwrap());
+
+    let multiplexer = Arc::new(Multiplexer::new(Arc::new(Metrics::new())));
+
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            let multiplexer = Arc::clone(&multiplexer);
+
+            thread::spawn(move || {
+                let identifier = Key {
+                    raw_key: [i; 32],
+                    replication_index: 0,
+                }
+                .identifier();
+                let peer_find = PeerFind {
+                    identifier,
+                    trace: None,
+                    budget: None,
+                };
+
+                let msg = multiplexer
+                    .request(p2p_addr, &Message::PeerFind(peer_find), TIMEOUT)
+                    .map_err(|err| err.to_string());
+
+                (identifier, msg)
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        let (identifier, msg) = handle.join().unwrap();
+
+        assert_eq!(
+            Message::PeerFound(PeerFound {
+                identifier,
+                socket_addr: p2p_addr,
+                authoritative: true,
+                trace: None,
+            }),
+            msg.unwrap()
+        );
+    }
+
+    assert_eq!(1, multiplexer.connections());
+}
+
+#[test]
+fn data_lane() {
+    let p2p_addr: SocketAddr = "127.0.3.18:38100".parse().unwrap();
+    let routing = Routing::from_addrs(p2p_addr, p2p_addr, p2p_addr, vec![p2p_addr; FINGERS]);
+    let data_lane = ThreadPool::new("test-data-worker", 1);
+
+    let p2p_handler = P2PHandler::new(
+        Arc::new(Mutex::new(routing)),
+        Arc::new(Mutex::new(Storage::new())),
+        TIMEOUT,
+    )
+    .with_data_lane(data_lane.clone());
+    Server::new(p2p_handler)
+        .listen(p2p_addr, 1)
+        .expect("could not bind to port");
+
+    // occupy the only worker of the data lane
+    let (release, blocked) = std::sync::mpsc::channel::<()>();
+    data_lane.execute(move || {
+        let _ = blocked.recv();
+    });
+
+    let multiplexer = Arc::new(Multiplexer::new(Arc::new(Metrics::new())));
+
+    let storage_get = Message::StorageGet(StorageGet {
+        replication_index: 0,
+        raw_key: [1; 32],
+        budget: None,
+        if_none_match: None,
+        requester: None,
+    });
+    let storage_multiplexer = Arc::clone(&multiplexer);
+    let storage_handle =
+        thread::spawn(move || storage_multiplexer.request(p2p_addr, &storage_get, TIMEOUT));
+
+    thread::sleep(Duration::from_millis(100));
+
+    // the ring is still maintained on the same connection
+    let peer_find = Message::PeerFind(PeerFind {
+        identifier: p2p_addr.identifier(),
+        trace: None,
+        budget: None,
+    });
+    let reply = multiplexer.request(p2p_addr, &peer_find, TIMEOUT).unwrap();
+    assert_eq!("PEER FOUND", reply.name());
+    assert!(!storage_handle.is_finished());
+
+    release.send(()).unwrap();
+
+    let reply = storage_handle.join().unwrap().unwrap();
+    assert_eq!("STORAGE FAILURE", reply.name());
+    assert_eq!(1, multiplexer.connections());
+}
+
+/// Holds back every write until it is released
+#[derive(Debug)]
+struct SlowPuts(Mutex<std::sync::mpsc::Receiver<()>>);
+
+impl Authorizer for SlowPuts {
+    fn authorize(&self, _: Requester, operation: Operation) -> bool {
+        if let Operation::Put(_) = operation {
+            let _ = self.0.lock().unwrap().recv();
+        }
+
+        true
+    }
+}
+
+#[test]
+fn data_lane_replies_out_of_order() {
+    let p2p_addr: SocketAddr = "127.0.3.36:38100".parse().unwrap();
+    let routing = Routing::from_addrs(p2p_addr, p2p_addr, p2p_addr, vec![p2p_addr; FINGERS]);
+    let (release, blocked) = std::sync::mpsc::channel();
+
+    let p2p_handler = P2PHandler::new(
+        Arc::new(Mutex::new(routing)),
+        Arc::new(Mutex::new(Storage::new())),
+        TIMEOUT,
+    )
+    .with_authorizer(Arc::new(SlowPuts(Mutex::new(blocked))))
+    .with_data_lane(ThreadPool::new("test-data-worker", 2));
+    Server::new(p2p_handler)
+        .listen(p2p_addr, 1)
+        .expect("could not bind to port");
+
+    let multiplexer = Arc::new(Multiplexer::new(Arc::new(Metrics::new())));
+
+    let storage_put = Message::StoragePut(
+        StoragePut::builder()
+            .key(Key {
+                raw_key: [1; 32],
+                replication_index: 0,
+            })
+            .value(vec![1])
+            .build(),
+    );
+    let put_multiplexer = Arc::clone(&multiplexer);
+    let put_handle =
+        thread::spawn(move || put_multiplexer.request(p2p_addr, &storage_put, TIMEOUT));
+
+    thread::sleep(Duration::from_millis(100));
+
+    // the slow write does not hold up the reply to a read on the data lane
+    let storage_get = Message::StorageGet(StorageGet {
+        replication_index: 0,
+        raw_key: [2; 32],
+        budget: None,
+        if_none_match: None,
+        requester: None,
+    });
+    let reply = multiplexer
+        .request(p2p_addr, &storage_get, TIMEOUT)
+        .unwrap();
+    assert_eq!("STORAGE FAILURE", reply.name());
+    assert!(!put_handle.is_finished());
+
+    release.send(()).unwrap();
+
+    let reply = put_handle.join().unwrap().unwrap();
+    assert_eq!("STORAGE PUT SUCCESS", reply.name());
+    assert_eq!(1, multiplexer.connections());
+}
+
+#[test]
+fn multiplexer_opens_connections_concurrently() {
+    let puzzle: Arc<dyn Framing> = Arc::new(Puzzle::new(framing::plain(), 1));
+
+    let p2p_addr: SocketAddr = "127.0.3.37:38100".parse().unwrap();
+    let routing = Routing::from_addrs(p2p_addr, p2p_addr, p2p_addr, vec![p2p_addr; FINGERS]);
+    let p2p_handler = P2PHandler::new(
+        Arc::new(Mutex::new(routing)),
+        Arc::new(Mutex::new(Storage::new())),
+        TIMEOUT,
+    );
+    Server::new(p2p_handler)
+        .with_framing(Arc::clone(&puzzle))
+        .listen(p2p_addr, 1)
+        .expect("could not bind to port");
+
+    // accepts connections but never sends the challenge of the handshake
+    let blackhole = TcpListener::bind("127.0.3.37:38101").unwrap();
+    let blackhole_addr = blackhole.local_addr().unwrap();
+    thread::spawn(move || {
+        let _streams: Vec<_> = blackhole.incoming().collect();
+    });
+
+    let multiplexer = Arc::new(Multiplexer::new(Arc::new(Metrics::new())).with_framing(puzzle));
+
+    let peer_find = move || {
+        Message::PeerFind(PeerFind {
+            identifier: p2p_addr.identifier(),
+            trace: None,
+            budget: None,
+        })
+    };
+    let stalled_multiplexer = Arc::clone(&multiplexer);
+    let stalled_handle =
+        thread::spawn(move || stalled_multiplexer.request(blackhole_addr, &peer_find(), TIMEOUT));
+
+    thread::sleep(Duration::from_millis(100));
+
+    // the handshake with the unresponsive peer does not hold up others
+    let start = Instant::now();
+    let reply = multiplexer
+        .request(p2p_addr, &peer_find(), TIMEOUT)
+        .unwrap();
+    assert_eq!("PEER FOUND", reply.name());
+    assert!(start.elapsed() < Duration::from_millis(TIMEOUT / 2));
+    assert!(!stalled_handle.is_finished());
+    assert_eq!(1, multiplexer.connections());
+
+    assert!(stalled_handle.join().unwrap().is_err());
+}
+
+#[test]
+fn multiplexer_expires_connections() {
+    let p2p_addr: SocketAddr = "127.0.3.17:38100".parse().unwrap();
+    create_network(p2p_addr, "127.0.3.17:38101".parse().unwrap());
+
+    let peer_find = Message::PeerFind(PeerFind {
+        identifier: p2p_addr.identifier(),
+        trace: None,
+        budget: None,
+    });
+
+    let idle = Multiplexer::new(Arc::new(Metrics::new()))
+        .with_max_idle(Some(Duration::from_millis(100)))
+        .with_max_lifetime(None);
+
+    idle.request(p2p_addr, &peer_find, TIMEOUT).unwrap();
+    assert_eq!(1, idle.connections());
+
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(0, idle.connections());
+
+    idle.request(p2p_addr, &peer_find, TIMEOUT).unwrap();
+    assert_eq!(1, idle.connections());
+
+    let short_lived = Multiplexer::new(Arc::new(Metrics::new()))
+        .with_max_idle(None)
+        .with_max_lifetime(Some(Duration::from_millis(100)));
+
+    short_lived.request(p2p_addr, &peer_find, TIMEOUT).unwrap();
+    thread::sleep(Duration::from_millis(60));
+    short_lived.request(p2p_addr, &peer_find, TIMEOUT).unwrap();
+    assert_eq!(1, short_lived.connections());
+
+    // expires although it has been used recently
+    thread::sleep(Duration::from_millis(60));
+    assert_eq!(0, short_lived.connections());
+    short_lived.request(p2p_addr, &peer_find, TIMEOUT).unwrap();
+    assert_eq!(1, short_lived.connections());
+}
+
+#[test]
+fn read_only() {
+    let client = create_network(
+        "127.0.3.14:38100".parse().unwrap(),
+        "127.0.3.14:38101".parse().unwrap(),
+    );
+
+    assert_eq!(
+        Some(1),
+        client
+            .put_acknowledged(key(3), value(&[1]), 60, 0, 1)
+            .unwrap()
+    );
+
+    assert!(client.set_read_only(true).unwrap());
+
+    // new values are refused while existing ones can still be read
+    assert_eq!(
+        None,
+        client
+            .put_acknowledged(key(4), value(&[2]), 60, 0, 1)
+            .unwrap()
+    );
+    assert_eq!(Some(value(&[1])), client.get(key(3)).unwrap());
+
+    assert!(!client.set_read_only(false).unwrap());
+
+    assert_eq!(
+        Some(1),
+        client
+            .put_acknowledged(key(4), value(&[2]), 60, 0, 1)
+            .unwrap()
+    );
+}
+
+#[test]
+fn reserved_namespace() {
+    let p2p_addr: SocketAddr = "127.0.3.19:38100".parse().unwrap();
+    let client = create_network(p2p_addr, "127.0.3.19:38101".parse().unwrap());
+
+    let reserved = Namespace::SYSTEM.key("lease/alice");
+
+    // applications can neither write nor remove internal records
+    assert_eq!(
+        None,
+        client
+            .put_acknowledged(reserved, value(&[1, 2, 3]), 60, 0, 1)
+            .unwrap()
+    );
+    assert_eq!(None, client.get(reserved).unwrap());
+
+    let key = Key {
+        raw_key: reserved.raw(),
+        replication_index: 0,
+    };
+    let record = Record::new(
+        vec![4, 5],
+        Duration::from_secs(60),
+        storage::current_version(),
+    );
+    Procedures::new(TIMEOUT)
+        .put_value(p2p_addr, key, record)
+        .unwrap();
+
+    assert_eq!(0, client.delete(reserved, 0).unwrap());
+    assert_eq!(Some(value(&[4, 5])), client.get(reserved).unwrap());
+}
+
+#[test]
+fn session_requests() {
+    let p2p_addr: SocketAddr = "127.0.3.15:38100".parse().unwrap();
+    let api_addr: SocketAddr = "127.0.3.15:38101".parse().unwrap();
+    create_network(p2p_addr, api_addr);
+
+    let dht_get = DhtGet {
+        key: key(4),
+        quorum: None,
+        budget: None,
+        request_id: Some(9),
+        if_none_match: None,
+    };
+
+    // a single worker serves all requests sent over the same connection
+    let mut con = Connection::open(api_addr, TIMEOUT).unwrap();
+    con.send(&Message::DhtGet(dht_get)).unwrap();
+    con.send(&Message::DhtCancel(DhtCancel { request_id: 9 }))
+        .unwrap();
+    con.send(&Message::NodeInfo(NodeInfo)).unwrap();
+
+    assert_eq!(
+        Message::DhtFailure(DhtFailure { key: key(4) }),
+        con.receive().unwrap()
+    );
+
+    match con.receive().unwrap() {
+        Message::NodeInfoReply(node_info_reply) => {
+            assert_eq!(p2p_addr, node_info_reply.socket_addr)
+        }
+        msg => panic!("unexpected message {}", msg),
+    }
+}
+
+#[test]
+fn poisoned_locks() {
+    let p2p_addr: SocketAddr = "127.0.3.16:38100".parse().unwrap();
+    let api_addr: SocketAddr = "127.0.3.16:38101".parse().unwrap();
+
+    let routing = Routing::from_addrs(p2p_addr, p2p_addr, p2p_addr, vec![p2p_addr; FINGERS]);
+    let routing = Arc::new(Mutex::new(routing));
+    let storage = Arc::new(Mutex::new(Storage::new()));
+
+    // a handler which panics while holding the locks poisons them
+    let (poisoned_routing, poisoned_storage) = (Arc::clone(&routing), Arc::clone(&storage));
+    let _ = thread::spawn(move || {
+        let _routing = poisoned_routing.lock().unwrap();
+        let _storage = poisoned_storage.lock().unwrap();
+        panic!("handler panic");
+    })
+    .join();
+    assert!(routing.is_poisoned() && storage.is_poisoned());
+
+    let p2p_handler = P2PHandler::new(Arc::clone(&routing), Arc::clone(&storage), TIMEOUT);
+    Server::new(p2p_handler)
+        .listen(p2p_addr, 4)
+        .expect("could not bind to port");
+
+    let api_handler = ApiHandler::new(
+        routing,
+        storage,
+        Arc::new(Handoff::new(TIMEOUT)),
+        Arc::new(Metrics::new()),
+        TIMEOUT,
+    );
+    Server::new(api_handler)
+        .listen(api_addr, 1)
+        .expect("could not bind to port");
+
+    let client = ApiClient::new(api_addr, TIMEOUT);
+    client.put(key(4), value(&[4]), 60, 0).unwrap();
+
+    assert_eq!(Some(value(&[4])), client.get(key(4)).unwrap());
+}
+
+/// Allows writes outside of a protected namespace and no administration
+#[derive(Debug)]
+struct ProtectedNamespace(Namespace);
+
+impl Authorizer for ProtectedNamespace {
+    fn authorize(&self, _: Requester, operation: Operation) -> bool {
+        match operation {
+            Operation::Get(_) => true,
+            Operation::Put(key) | Operation::Delete(key) => !self.0.contains(&key),
+            Operation::Admin(_) => false,
+        }
+    }
+}
+
+#[test]
+fn authorizer() {
+    let p2p_addr: SocketAddr = "127.0.3.26:38100".parse().unwrap();
+    let api_addr: SocketAddr = "127.0.3.26:38101".parse().unwrap();
+    let namespace = Namespace::new(b"protected").unwrap();
+
+    let client = create_network_with(
+        p2p_addr,
+        api_addr,
+        Arc::new(Metrics::new()),
+        1,
+        false,
+        Arc::new(ProtectedNamespace(namespace)),
+    );
+
+    let protected = namespace.key("config");
+    assert_eq!(
+        None,
+        client
+            .put_acknowledged(protected, value(&[1]), 60, 0, 1)
+            .unwrap()
+    );
+    assert_eq!(0, client.delete(protected, 0).unwrap());
+    assert_eq!(
+        Some(1),
+        client
+            .put_acknowledged(key(3), value(&[1]), 60, 0, 1)
+            .unwrap()
+    );
+    assert_eq!(Some(value(&[1])), client.get(key(3)).unwrap());
+
+    // administrative requests are dropped without reply
+    assert!(ApiClient::new(api_addr, 500).node_info().is_err());
+
+    // other peers cannot store values in the namespace either
+    let mut con = Connection::open(p2p_addr, TIMEOUT).unwrap();
+    let storage_put = StoragePut::builder()
+        .key(Key {
+            raw_key: protected.raw(),
+            replication_index: 0,
+        })
+        .replicas(1)
+        .value(vec![1])
+        .build();
+    con.send(&Message::StoragePut(storage_put)).unwrap();
+
+    assert_eq!(
+        Message::StorageFailure(StorageFailure {
+            raw_key: protected.raw(),
+            reason: Some(FailureReason::Denied),
+        }),
+        con.receive().unwrap()
+    );
+}
+
+#[test]
+fn namespace_policy() {
+    let p2p_addr: SocketAddr = "127.0.3.27:38100".parse().unwrap();
+    let tenant = Namespace::new(b"tenant").unwrap();
+    let policy = Policy {
+        max_ttl: Some(Duration::from_secs(10)),
+        max_value_size: Some(4),
+        quota: Some(6),
+        replication: Some(2),
+    };
+
+    let routing = Routing::from_addrs(p2p_addr, p2p_addr, p2p_addr, vec![p2p_addr; FINGERS]);
+    let storage = Arc::new(Mutex::new(Storage::new()));
+    let p2p_handler = P2PHandler::new(Arc::new(Mutex::new(routing)), Arc::clone(&storage), TIMEOUT)
+        .with_policies(Arc::new(Policies::new().with_policy(tenant, policy)));
+    Server::new(p2p_handler)
+        .listen(p2p_addr, 1)
+        .expect("could not bind to port");
+
+    let put = |key: DhtKey, replication_index: u8, size: usize| {
+        let mut con = Connection::open(p2p_addr, TIMEOUT).unwrap();
+        let storage_put = StoragePut::builder()
+            .key(Key {
+                raw_key: key.raw(),
+                replication_index,
+            })
+            .replicas(3)
+            .value(vec![1; size])
+            .build();
+        con.send(&Message::StoragePut(storage_put)).unwrap();
+
+        match con.receive().unwrap() {
+            Message::StoragePutSuccess(_) => None,
+            Message::StorageFailure(storage_failure) => storage_failure.reason,
+            msg => panic!("unexpected reply {:?}", msg),
+        }
+    };
+
+    let first = tenant.key("first");
+    assert_eq!(None, put(first, 0, 4));
+    assert_eq!(Some(FailureReason::PolicyViolated), put(first, 1, 5));
+    assert_eq!(Some(FailureReason::PolicyViolated), put(first, 2, 1));
+    assert_eq!(
+        Some(FailureReason::PolicyViolated),
+        put(tenant.key("second"), 0, 4)
+    );
+    assert_eq!(None, put(tenant.key("second"), 0, 2));
+
+    // keys outside of the namespace are not limited
+    assert_eq!(None, put(key(3), 5, 100));
+
+    let storage = storage.lock().unwrap();
+    let record = &storage[&Key {
+        raw_key: first.raw(),
+        replication_index: 0,
+    }];
+    assert!(record.ttl() <= Duration::from_secs(10));
+    assert_eq!(2, record.replicas);
+}
+
+#[test]
+fn namespace_quota_under_concurrent_puts() {
+    let p2p_addr: SocketAddr = "127.0.3.34:38100".parse().unwrap();
+    let tenant = Namespace::new(b"tenant").unwrap();
+    let policy = Policy {
+        max_ttl: None,
+        max_value_size: None,
+        quota: Some(6),
+        replication: None,
+    };
+
+    let routing = Routing::from_addrs(p2p_addr, p2p_addr, p2p_addr, vec![p2p_addr; FINGERS]);
+
+    // records outside of the namespace make counting its size take a while
+    let storage: Storage = (0..50_000u32)
+        .map(|index| {
+            let mut raw_key = [0xff; 32];
+            raw_key[..4].copy_from_slice(&index.to_be_bytes());
+
+            let key = Key {
+                raw_key,
+                replication_index: 0,
+            };
+
+            (key, Record::new(vec![1], Duration::from_secs(60), 1))
+        })
+        .collect();
+    let storage = Arc::new(Mutex::new(storage));
+    let p2p_handler = P2PHandler::new(Arc::new(Mutex::new(routing)), Arc::clone(&storage), TIMEOUT)
+        .with_policies(Arc::new(Policies::new().with_policy(tenant, policy)));
+    Server::new(p2p_handler)
+        .listen(p2p_addr, 16)
+        .expect("could not bind to port");
+
+    // all requests are sent at once to race for the quota
+    let barrier = Arc::new(Barrier::new(16));
+    let handles: Vec<_> = (0..16u8)
+        .map(|byte| {
+            let barrier = Arc::clone(&barrier);
+
+            thread::spawn(move || {
+                let mut con = Connection::open(p2p_addr, TIMEOUT).unwrap();
+                let storage_put = StoragePut::builder()
+                    .key(Key {
+                        raw_key: tenant.key(&byte.to_string()).raw(),
+                        replication_index: 0,
+                    })
+                    .value(vec![byte; 4])
+                    .build();
+
+                barrier.wait();
+                con.send(&Message::StoragePut(storage_put)).unwrap();
+
+                matches!(con.receive().unwrap(), Message::StoragePutSuccess(_))
+            })
+        })
+        .collect();
+
+    let stored = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .filter(|stored| *stored)
+        .count();
+
+    // only one value of four bytes fits into the quota of six bytes
+    assert_eq!(1, stored);
+    assert_eq!(50_001, storage.lock().unwrap().len());
+}
+
+#[test]
+fn tenants() {
+    let p2p_addr: SocketAddr = "127.0.3.28:38100".parse().unwrap();
+    let api_addr: SocketAddr = "127.0.3.28:38101".parse().unwrap();
+    let namespace = Namespace::new(b"app").unwrap();
+    let tenants = Tenants::new()
+        .with_tenant("secret", Tenant::new("app", namespace))
+        .with_required(true);
+
+    let routing = Routing::from_addrs(p2p_addr, p2p_addr, p2p_addr, vec![p2p_addr; FINGERS]);
+    let routing = Arc::new(Mutex::new(routing));
+    let storage = Arc::new(Mutex::new(Storage::new()));
+    Server::new(P2PHandler::new(
+        Arc::clone(&routing),
+        Arc::clone(&storage),
+        TIMEOUT,
+    ))
+    .listen(p2p_addr, 2)
+    .expect("could not bind to port");
+
+    let api_handler = ApiHandler::new(
+        routing,
+        storage,
+        Arc::new(Handoff::new(TIMEOUT)),
+        Arc::new(Metrics::new()),
+        TIMEOUT,
+    )
+    .with_tenants(Arc::new(tenants));
+    Server::new(api_handler)
+        .listen(api_addr, 2)
+        .expect("could not bind to port");
+
+    let tenant = ApiClient::new(api_addr, 500).with_token("secret");
+    let own = namespace.key("config");
+
+    assert_eq!(
+        Some(1),
+        tenant.put_acknowledged(own, value(&[1]), 60, 0, 1).unwrap()
+    );
+    assert_eq!(Some(value(&[1])), tenant.get(own).unwrap());
+
+    // keys of other tenants and administrative requests are denied
+    assert_eq!(
+        None,
+        tenant
+            .put_acknowledged(key(3), value(&[1]), 60, 0, 1)
+            .unwrap()
+    );
+    assert!(tenant.node_info().is_err());
+
+    // connections without a token or with an unknown one are refused
+    assert_eq!(None, ApiClient::new(api_addr, 500).get(own).unwrap());
+    assert!(ApiClient::new(api_addr, 500)
+        .with_token("guess")
+        .get(own)
+        .is_err());
+}
+
+#[test]
+fn session_reads_own_writes() {
+    let p2p_addr: SocketAddr = "127.0.3.29:38100".parse().unwrap();
+    let client = create_network(p2p_addr, "127.0.3.29:38101".parse().unwrap());
+    let session = Session::new(client);
+
+    assert!(session.put(key(4), value(&[2]), 60, 1).unwrap());
+    assert_eq!(Some(value(&[2])), session.get(key(4)).unwrap());
+
+    // replace both replicas with an older value behind the back of the session
+    assert_eq!(2, session.client().delete(key(4), 1).unwrap());
+
+    for replication_index in 0..2 {
+        let mut con = Connection::open(p2p_addr, TIMEOUT).unwrap();
+        let storage_put = StoragePut::builder()
+            .key(Key {
+                raw_key: key(4).raw(),
+                replication_index,
+            })
+            .replicas(2)
+            .value(vec![1])
+            .build();
+        con.send(&Message::StoragePut(storage_put)).unwrap();
+        assert_eq!("STORAGE PUT SUCCESS", con.receive().unwrap().name());
+    }
+
+    assert_eq!(Some(value(&[1])), session.client().get(key(4)).unwrap());
+
+    let err = session.get(key(4)).unwrap_err();
+    assert!(err.is::<StaleError>());
+
+    // keys which were not written through the session are read as usual
+    assert_eq!(None, session.get(key(5)).unwrap());
+}
+
+#[test]
+fn prefetch() {
+    let metrics = Arc::new(Metrics::new());
+    let client = create_network_with_metrics(
+        "127.0.3.30:38100".parse().unwrap(),
+        "127.0.3.30:38101".parse().unwrap(),
+        Arc::clone(&metrics),
+        2,
+    );
+
+    let storage_gets = || {
+        metrics
+            .stats()
+            .snapshot()
+            .messages
+            .iter()
+            .find(|count| count.name == "STORAGE GET")
+            .map_or(0, |count| count.received)
+    };
+
+    client
+        .put_acknowledged(key(3), value(&[1, 2, 3]), 60, 0, 1)
+        .unwrap();
+    client.prefetch(&[key(3)]).unwrap();
+
+    while storage_gets() == 0 {
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    // wait for the prefetched value to be kept
+    thread::sleep(Duration::from_millis(50));
+
+    // the first read is answered with the prefetched value
+    assert_eq!(Some(value(&[1, 2, 3])), client.get(key(3)).unwrap());
+    assert_eq!(1, storage_gets());
+
+    assert_eq!(Some(value(&[1, 2, 3])), client.get(key(3)).unwrap());
+    assert_eq!(2, storage_gets());
+}
+
+#[test]
+fn node_load() {
+    let client = create_network(
+        "127.0.3.31:38100".parse().unwrap(),
+        "127.0.3.31:38101".parse().unwrap(),
+    );
+
+    client.put(key(3), value(&[1, 2, 3]), 60, 0).unwrap();
+
+    let reply = client.node_load(8).unwrap();
+
+    // a single peer is responsible for everything and cannot compare itself
+    assert_eq!(0, reply.sampled);
+    assert_eq!(u64::MAX, reply.local.share);
+    assert_eq!(1, reply.local.records);
+    assert_eq!(3, reply.local.bytes);
+    assert_eq!(reply.local, reply.average);
+    assert_eq!(1, reply.target_weight);
+}
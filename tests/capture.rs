@@ -0,0 +1,111 @@
+extern crate chord;
+
+use chord::capture;
+use chord::handler::P2PHandler;
+use chord::network::Server;
+use chord::procedures::Procedures;
+use chord::routing::identifier::Identify;
+use chord::routing::Routing;
+use chord::storage::Storage;
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+const TIMEOUT: u64 = 5000;
+
+#[test]
+fn capture_messages() {
+    let path = std::env::temp_dir().join("chord-capture-messages.jsonl");
+    let _ = fs::remove_file(&path);
+
+    capture::start(&path).expect("could not start capture");
+    assert!(capture::start(&path).is_err());
+
+    let peer_addr: SocketAddr = "127.0.6.1:38100".parse().unwrap();
+    let routing = Routing::from_addrs(peer_addr, peer_addr, peer_addr, vec![peer_addr; 8]);
+
+    Server::new(P2PHandler::new(
+        Arc::new(Mutex::new(routing)),
+        Arc::new(Mutex::new(Storage::new())),
+        TIMEOUT,
+    ))
+    .listen(peer_addr, 1)
+    .expect("could not bind to port");
+
+    Procedures::new(TIMEOUT)
+        .find_peer(peer_addr.identifier(), peer_addr)
+        .expect("could not find peer");
+
+    let capture = fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = capture.lines().collect();
+
+    // both ends of the connection record the request and the reply
+    assert_eq!(4, lines.len());
+    assert_eq!(
+        2,
+        lines
+            .iter()
+            .filter(|line| line.contains("\"PEER FIND\""))
+            .count()
+    );
+    assert_eq!(
+        2,
+        lines
+            .iter()
+            .filter(|line| line.contains("\"PEER FOUND\""))
+            .count()
+    );
+    assert!(lines[0].contains("\"direction\":\"sent\""));
+    assert!(lines
+        .iter()
+        .all(|line| line.contains(&format!("\"{}\"", peer_addr))));
+
+    #[cfg(feature = "node")]
+    replay_messages(&path, peer_addr);
+}
+
+/// Replays the capture against a second handler which identifies as the
+/// recorded peer and against one with another identity.
+///
+/// This runs as part of `capture_messages` since the capture is global.
+#[cfg(feature = "node")]
+fn replay_messages(path: &std::path::Path, peer_addr: SocketAddr) {
+    use chord::replay;
+
+    let records = capture::read(path).expect("could not read capture");
+    assert_eq!(4, records.len());
+
+    let target: SocketAddr = "127.0.6.2:38100".parse().unwrap();
+    let routing = Routing::from_addrs(peer_addr, peer_addr, peer_addr, vec![peer_addr; 8]);
+
+    Server::new(P2PHandler::new(
+        Arc::new(Mutex::new(routing)),
+        Arc::new(Mutex::new(Storage::new())),
+        TIMEOUT,
+    ))
+    .listen(target, 1)
+    .expect("could not bind to port");
+
+    let report = replay::replay(&records, peer_addr, target, TIMEOUT);
+
+    assert_eq!(1, report.connections);
+    assert_eq!(1, report.requests);
+    assert!(report.divergences.is_empty());
+
+    // a peer with another address finds itself instead
+    let other: SocketAddr = "127.0.6.3:38100".parse().unwrap();
+    let routing = Routing::from_addrs(other, other, other, vec![other; 8]);
+
+    Server::new(P2PHandler::new(
+        Arc::new(Mutex::new(routing)),
+        Arc::new(Mutex::new(Storage::new())),
+        TIMEOUT,
+    ))
+    .listen(other, 1)
+    .expect("could not bind to port");
+
+    let report = replay::replay(&records, peer_addr, other, TIMEOUT);
+
+    assert_eq!(1, report.divergences.len());
+    assert_eq!("PEER FIND", report.divergences[0].request);
+}
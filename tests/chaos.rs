@@ -0,0 +1,22 @@
+extern crate chord;
+
+use chord::chaos::{Chaos, ChaosConfig};
+
+#[test]
+fn stable_ring_without_churn() {
+    let config = ChaosConfig {
+        nodes: 6,
+        kill_rate: 0.0,
+        puts: 8,
+        stabilizations: 2,
+        fingers: 16,
+        seed: 42,
+        timeout: 5000,
+        stabilization_interval: 60,
+    };
+
+    let mut chaos = Chaos::new(config).expect("could not launch ring");
+
+    assert_eq!(6, chaos.alive().len());
+    assert_eq!(Vec::<String>::new(), chaos.round());
+}
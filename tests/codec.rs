@@ -0,0 +1,86 @@
+extern crate chord;
+
+use chord::message::codec;
+use chord::message::Message;
+use std::collections::HashSet;
+use std::io::Cursor;
+
+const VECTORS: &str = include_str!("vectors/messages.txt");
+
+fn decode_hex(hex: &str) -> Vec<u8> {
+    assert_eq!(0, hex.len() % 2, "odd number of hex digits");
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("invalid hex digit"))
+        .collect()
+}
+
+fn vectors() -> Vec<(&'static str, Vec<u8>)> {
+    VECTORS
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (name, hex) = line.split_once(": ").expect("invalid vector");
+
+            (name, decode_hex(hex))
+        })
+        .collect()
+}
+
+#[test]
+fn vectors_cover_all_message_types() {
+    let names: HashSet<&str> = vectors().into_iter().map(|(name, _)| name).collect();
+
+    for name in Message::NAMES.iter() {
+        assert!(names.contains(name), "no vector for {}", name);
+    }
+}
+
+#[test]
+fn vectors_have_valid_header() {
+    for (name, bytes) in vectors() {
+        let (size, _) = codec::read_header(&mut &bytes[..]).unwrap();
+
+        assert_eq!(bytes.len(), usize::from(size), "size of {}", name);
+        assert!(bytes.len() <= codec::MAX_MESSAGE_SIZE);
+    }
+}
+
+#[test]
+fn vectors_parse() {
+    for (name, bytes) in vectors() {
+        let msg = Message::parse(Cursor::new(&bytes[..]))
+            .unwrap_or_else(|err| panic!("could not parse {}: {}", name, err));
+
+        assert_eq!(name, msg.name());
+    }
+}
+
+#[test]
+fn vectors_write_to() {
+    for (name, bytes) in vectors() {
+        let msg = Message::parse(Cursor::new(&bytes[..])).unwrap();
+
+        let mut buffer = vec![0; codec::MAX_MESSAGE_SIZE];
+        let size = msg.write_to(Cursor::new(&mut buffer[..])).unwrap();
+
+        assert_eq!(&bytes[..], &buffer[..size], "encoding of {}", name);
+        assert_eq!(bytes, msg.to_bytes().unwrap(), "encoding of {}", name);
+    }
+}
+
+#[test]
+fn vectors_message_type() {
+    for (name, bytes) in vectors() {
+        let msg = Message::parse(Cursor::new(&bytes[..])).unwrap();
+
+        assert_eq!(
+            u16::from_be_bytes([bytes[2], bytes[3]]),
+            msg.message_type(),
+            "message type of {}",
+            name
+        );
+    }
+}
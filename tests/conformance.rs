@@ -0,0 +1,84 @@
+extern crate chord;
+
+use chord::conformance::{Conformance, Step};
+use chord::handler::P2PHandler;
+use chord::network::Server;
+use chord::routing::Routing;
+use chord::storage::{self, Key, Record, Storage};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TIMEOUT: u64 = 5000;
+const FINGERS: usize = 8;
+
+#[test]
+fn conformant_peer() {
+    let peer_addr: SocketAddr = "127.0.7.1:38100".parse().unwrap();
+    let probe_addr: SocketAddr = "127.0.7.2:38100".parse().unwrap();
+
+    let routing = Routing::from_addrs(peer_addr, peer_addr, peer_addr, vec![peer_addr; FINGERS]);
+    let routing = Arc::new(Mutex::new(routing));
+
+    let storage: Storage = (0..32)
+        .map(|i| {
+            let key = Key {
+                raw_key: [i; 32],
+                replication_index: 0,
+            };
+
+            (
+                key,
+                Record::new(vec![i], Duration::from_secs(60), storage::current_version()),
+            )
+        })
+        .collect();
+    let storage = Arc::new(Mutex::new(storage));
+
+    Server::new(P2PHandler::new(
+        Arc::clone(&routing),
+        Arc::clone(&storage),
+        TIMEOUT,
+    ))
+    .listen(peer_addr, 4)
+    .expect("could not bind to port");
+
+    let report = Conformance::new(peer_addr, probe_addr, TIMEOUT).run();
+
+    assert!(report.is_conformant(), "{:?}", report.violations);
+    assert_eq!(
+        vec![
+            Step::Lookup,
+            Step::Join,
+            Step::Put,
+            Step::Get,
+            Step::Delete,
+            Step::Notify,
+            Step::Leave
+        ],
+        report.passed
+    );
+
+    // the peer is left in its previous state
+    let routing = routing.lock().unwrap();
+    assert_eq!(peer_addr, routing.predecessor.socket_addr());
+    assert_eq!(peer_addr, routing.successor.socket_addr());
+
+    let storage = storage.lock().unwrap();
+    assert_eq!(32, storage.len());
+    assert!(storage
+        .iter()
+        .all(|(key, record)| *record.value == [key.raw_key[0]]));
+}
+
+#[test]
+fn report_unreachable_peer() {
+    let peer_addr: SocketAddr = "127.0.7.3:38100".parse().unwrap();
+    let probe_addr: SocketAddr = "127.0.7.4:38100".parse().unwrap();
+
+    let report = Conformance::new(peer_addr, probe_addr, TIMEOUT).run();
+
+    assert!(!report.is_conformant());
+    assert!(report.passed.is_empty());
+    assert_eq!(Step::Lookup, report.violations[0].step);
+}
@@ -0,0 +1,95 @@
+extern crate chord;
+
+use chord::ffi::{
+    dht_node_get, dht_node_put, dht_node_start, dht_node_stop, DhtNode, DHT_ERROR, DHT_NOT_FOUND,
+};
+use std::ffi::CString;
+use std::fs;
+use std::ptr;
+
+fn start(listen_address: &str, api_address: &str, bootstrap: Option<&str>) -> *mut DhtNode {
+    let path = std::env::temp_dir().join(format!("chord-ffi-{}.ini", listen_address));
+    fs::write(
+        &path,
+        format!(
+            "[dht]\nlisten_address = {}\napi_address = {}\ntimeout = 5000\nfingers = 8\n",
+            listen_address, api_address
+        ),
+    )
+    .unwrap();
+
+    let config_path = CString::new(path.to_str().unwrap()).unwrap();
+    let bootstrap = bootstrap.map(|bootstrap| CString::new(bootstrap).unwrap());
+    let bootstrap_ptr = bootstrap.as_ref().map_or(ptr::null(), |b| b.as_ptr());
+
+    let node = unsafe { dht_node_start(config_path.as_ptr(), bootstrap_ptr) };
+    fs::remove_file(path).unwrap();
+
+    assert!(!node.is_null(), "could not start node");
+    node
+}
+
+#[test]
+fn embedded_nodes_store_values() {
+    let first = start("127.0.9.30:38300", "127.0.9.30:38301", None);
+    let second = start(
+        "127.0.9.31:38300",
+        "127.0.9.31:38301",
+        Some("127.0.9.30:38300"),
+    );
+
+    let key = [7; 32];
+    let value = b"embedded";
+
+    let acks = unsafe {
+        dht_node_put(
+            second,
+            key.as_ptr(),
+            key.len(),
+            value.as_ptr(),
+            value.len(),
+            60,
+            2,
+        )
+    };
+    assert!(acks >= 1);
+
+    let mut buffer = [0; 64];
+    let len = unsafe { dht_node_get(first, key.as_ptr(), 32, buffer.as_mut_ptr(), buffer.len()) };
+    assert_eq!(value.len() as i64, len);
+    assert_eq!(&value[..], &buffer[..value.len()]);
+
+    // a short buffer receives the beginning of the value and its full length
+    let mut short = [0; 3];
+    let len = unsafe { dht_node_get(first, key.as_ptr(), 32, short.as_mut_ptr(), short.len()) };
+    assert_eq!(value.len() as i64, len);
+    assert_eq!(b"emb", &short);
+
+    let missing = [8; 32];
+    let len = unsafe { dht_node_get(first, missing.as_ptr(), 32, ptr::null_mut(), 0) };
+    assert_eq!(i64::from(DHT_NOT_FOUND), len);
+
+    let too_long = [1; 33];
+    let len = unsafe { dht_node_get(first, too_long.as_ptr(), 33, ptr::null_mut(), 0) };
+    assert_eq!(i64::from(DHT_ERROR), len);
+
+    // the records of the stopped node remain available through the other one
+    assert_eq!(0, unsafe { dht_node_stop(second) });
+
+    let len = unsafe { dht_node_get(first, key.as_ptr(), 32, buffer.as_mut_ptr(), buffer.len()) };
+    assert_eq!(value.len() as i64, len);
+
+    assert_eq!(0, unsafe { dht_node_stop(first) });
+}
+
+#[test]
+fn invalid_arguments() {
+    let config_path = CString::new("/nonexistent/chord.ini").unwrap();
+
+    assert!(unsafe { dht_node_start(ptr::null(), ptr::null()) }.is_null());
+    assert!(unsafe { dht_node_start(config_path.as_ptr(), ptr::null()) }.is_null());
+    assert_eq!(DHT_ERROR, unsafe { dht_node_stop(ptr::null_mut()) });
+    assert_eq!(DHT_ERROR, unsafe {
+        dht_node_put(ptr::null(), ptr::null(), 0, ptr::null(), 0, 60, 1)
+    });
+}
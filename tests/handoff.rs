@@ -0,0 +1,158 @@
+extern crate chord;
+
+use chord::clock::{Clock, ManualClock};
+use chord::handler::P2PHandler;
+use chord::handoff::Handoff;
+use chord::network::Server;
+use chord::procedures::Procedures;
+use chord::routing::identifier::Identify;
+use chord::routing::Routing;
+use chord::storage::{self, Key, Record, Storage};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TIMEOUT: u64 = 5000;
+const FINGERS: usize = 8;
+
+fn create_network(addr: SocketAddr) {
+    let routing = Routing::from_addrs(addr, addr, addr, vec![addr; FINGERS]);
+    let routing = Arc::new(Mutex::new(routing));
+    let storage = Arc::new(Mutex::new(Storage::new()));
+
+    Server::new(P2PHandler::new(routing, storage, TIMEOUT))
+        .listen(addr, 4)
+        .expect("could not bind to port");
+}
+
+#[test]
+fn deliver_once_reachable() {
+    let peer_addr = "127.0.4.1:38100".parse().unwrap();
+    let key = Key {
+        raw_key: [3; 32],
+        replication_index: 0,
+    };
+
+    let handoff = Handoff::new(TIMEOUT);
+    handoff.hint(
+        peer_addr,
+        key,
+        Record::new(
+            vec![1, 2, 3],
+            Duration::from_secs(60),
+            storage::current_version(),
+        ),
+    );
+
+    // the peer is not reachable yet
+    assert_eq!(0, handoff.deliver());
+    assert_eq!(1, handoff.pending());
+
+    create_network(peer_addr);
+
+    assert_eq!(1, handoff.deliver());
+    assert_eq!(0, handoff.pending());
+
+    let value = Procedures::new(TIMEOUT).get_value(peer_addr, key).unwrap();
+
+    assert_eq!(Some(&[1, 2, 3][..]), value.as_deref());
+}
+
+#[test]
+fn drop_expired_hints() {
+    let handoff = Handoff::new(TIMEOUT);
+    let key = Key {
+        raw_key: [3; 32],
+        replication_index: 0,
+    };
+
+    handoff.hint(
+        "127.0.4.2:38100".parse().unwrap(),
+        key,
+        Record::new(
+            vec![1, 2, 3],
+            Duration::from_secs(0),
+            storage::current_version(),
+        ),
+    );
+
+    assert_eq!(0, handoff.deliver());
+    assert_eq!(0, handoff.pending());
+}
+
+#[test]
+fn drop_hints_expired_later() {
+    let clock = Arc::new(ManualClock::new());
+    let handoff = Handoff::new(TIMEOUT).with_clock(Arc::clone(&clock) as Arc<dyn Clock>);
+    let key = Key {
+        raw_key: [4; 32],
+        replication_index: 0,
+    };
+
+    handoff.hint(
+        "127.0.4.2:38100".parse().unwrap(),
+        key,
+        Record::new_at(
+            vec![1, 2, 3],
+            Duration::from_secs(60),
+            storage::current_version(),
+            clock.now(),
+        ),
+    );
+
+    clock.advance(Duration::from_secs(61));
+
+    assert_eq!(0, handoff.deliver());
+    assert_eq!(0, handoff.pending());
+}
+
+#[test]
+fn keep_rejected_hints() {
+    let peer_addr: SocketAddr = "127.0.4.3:38100".parse().unwrap();
+    let predecessor_addr: SocketAddr = "127.0.4.4:38100".parse().unwrap();
+
+    let routing = Routing::from_addrs(
+        peer_addr,
+        predecessor_addr,
+        peer_addr,
+        vec![peer_addr; FINGERS],
+    );
+
+    let keys: Vec<Key> = (0..16)
+        .map(|i| Key {
+            raw_key: [i; 32],
+            replication_index: 0,
+        })
+        .collect();
+    let responsible = keys
+        .iter()
+        .filter(|key| routing.responsible_for(key.identifier()))
+        .count();
+
+    Server::new(P2PHandler::new(
+        Arc::new(Mutex::new(routing)),
+        Arc::new(Mutex::new(Storage::new())),
+        TIMEOUT,
+    ))
+    .listen(peer_addr, 4)
+    .expect("could not bind to port");
+
+    let handoff = Handoff::new(TIMEOUT);
+
+    for key in &keys {
+        handoff.hint(
+            peer_addr,
+            *key,
+            Record::new(
+                vec![1, 2, 3],
+                Duration::from_secs(60),
+                storage::current_version(),
+            ),
+        );
+    }
+
+    // the hints are delivered together and only those outside of the range
+    // of the peer are kept
+    assert_eq!(responsible, handoff.deliver());
+    assert_eq!(keys.len() - responsible, handoff.pending());
+}
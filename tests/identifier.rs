@@ -0,0 +1,150 @@
+extern crate chord;
+
+use chord::config::Config;
+use chord::handler::P2PHandler;
+use chord::network::Server;
+use chord::procedures::Procedures;
+use chord::routing::identifier::{IdentifierScheme, Identify};
+use chord::routing::Routing;
+use chord::stabilization::Bootstrap;
+use chord::storage::Storage;
+use chord::{run_all, Node};
+use std::error::Error;
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+const TIMEOUT: u64 = 5000;
+const FINGERS: usize = 8;
+
+// all peers of this process hash their port as well
+fn ip_port() {
+    IdentifierScheme::IpPort.set_global().unwrap();
+}
+
+fn load_config(name: &str, settings: &str) -> Result<Config, Box<dyn Error>> {
+    let path = std::env::temp_dir().join(format!("chord-identifier-{}.ini", name));
+    fs::write(&path, format!("[dht]\n{}", settings)).unwrap();
+
+    let config = Config::load_from_file(&path);
+    fs::remove_file(path).unwrap();
+
+    config
+}
+
+fn create_network(addr: SocketAddr) {
+    let routing = Routing::from_addrs(addr, addr, addr, vec![addr; FINGERS]);
+    let routing = Arc::new(Mutex::new(routing));
+    let storage = Arc::new(Mutex::new(Storage::new()));
+
+    Server::new(P2PHandler::new(routing, storage, TIMEOUT))
+        .listen(addr, 4)
+        .expect("could not bind to port");
+}
+
+#[test]
+fn peers_sharing_ip_address_join() {
+    ip_port();
+
+    let boot_addr: SocketAddr = "127.0.10.1:38100".parse().unwrap();
+    let join_addr: SocketAddr = "127.0.10.1:38102".parse().unwrap();
+    assert_ne!(boot_addr.identifier(), join_addr.identifier());
+
+    create_network(boot_addr);
+
+    let (routing, _) = Bootstrap::new(join_addr, boot_addr, FINGERS)
+        .bootstrap(TIMEOUT)
+        .expect("could not join network");
+
+    assert_eq!(boot_addr, routing.successor.socket_addr());
+    assert_eq!(boot_addr, routing.predecessor.socket_addr());
+}
+
+#[test]
+fn weight_registers_virtual_peers() {
+    ip_port();
+
+    let config = load_config(
+        "weight",
+        "listen_address = 127.0.10.2:38200\n\
+         api_address = 127.0.10.2:38101\n\
+         timeout = 5000\n\
+         fingers = 8\n\
+         weight = 3\n\
+         identifier_scheme = ip-port\n",
+    )
+    .expect("invalid config");
+
+    let virtual_addrs = config.virtual_addresses();
+    assert_eq!(2, virtual_addrs.len());
+
+    let node = Node::start(config, None).expect("could not start node");
+    let procedures = Procedures::new(TIMEOUT);
+
+    // every virtual peer is responsible for its own identifier
+    for &virtual_addr in &virtual_addrs {
+        let peer_addr = procedures
+            .find_peer(virtual_addr.identifier(), virtual_addr)
+            .expect("could not reach virtual peer");
+
+        assert_eq!(virtual_addr, peer_addr);
+    }
+
+    let routing = node.routing();
+    let predecessor = routing.lock().unwrap().predecessor.socket_addr();
+
+    assert!(virtual_addrs.contains(&predecessor));
+}
+
+#[test]
+fn weight_requires_ip_port_scheme() {
+    let err = load_config(
+        "scheme",
+        "listen_address = 127.0.10.3:38100\n\
+         api_address = 127.0.10.3:38101\n\
+         weight = 2\n",
+    )
+    .unwrap_err();
+
+    assert!(err.to_string().contains("ip-port"));
+}
+
+#[test]
+fn node_with_other_scheme_does_not_start() {
+    ip_port();
+
+    let config = load_config(
+        "other",
+        "listen_address = 127.0.10.4:38100\n\
+         api_address = 127.0.10.4:38101\n\
+         identifier_scheme = ip\n",
+    )
+    .expect("invalid config");
+
+    let err = Node::start(config, None)
+        .err()
+        .expect("started node with a different scheme");
+
+    assert!(err.to_string().contains("conflicts"));
+}
+
+#[test]
+fn nodes_of_a_process_share_the_scheme() {
+    let ip = load_config(
+        "shared-ip",
+        "listen_address = 127.0.10.5:38100\n\
+         api_address = 127.0.10.5:38101\n",
+    )
+    .expect("invalid config");
+    let ip_port = load_config(
+        "shared-ip-port",
+        "listen_address = 127.0.10.6:38100\n\
+         api_address = 127.0.10.6:38101\n\
+         identifier_scheme = ip-port\n",
+    )
+    .expect("invalid config");
+
+    let err = run_all(vec![ip, ip_port], None).unwrap_err();
+
+    assert!(err.to_string().contains("same identifier scheme"));
+}
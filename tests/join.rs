@@ -0,0 +1,1093 @@
+extern crate chord;
+
+use chord::auth;
+use chord::client::ApiClient;
+use chord::config::Config;
+use chord::dht::{DhtKey, DhtValue, Namespace};
+use chord::error::{BootstrapError, BootstrapFailure, LockedError};
+use chord::framing::{self, Framing};
+use chord::handler::{ApiHandler, P2PHandler};
+use chord::handoff::Handoff;
+use chord::message::p2p::{
+    JoinLock, PeerFind, PeerFound, PeerLeave, StoragePut, TransferAck, TransferCursor,
+};
+use chord::message::Message;
+use chord::metrics::Metrics;
+use chord::network::{Connection, Server, SocketOptions};
+use chord::policy::Policies;
+use chord::procedures::Procedures;
+use chord::proxy::Proxy;
+use chord::puzzle::Puzzle;
+use chord::random::SeededRandom;
+use chord::routing::identifier::{Identifier, IdentifierInterval, IdentifierScheme, Identify};
+use chord::routing::peer::PeerInfo;
+use chord::routing::Routing;
+use chord::stabilization::{Bootstrap, Stabilization};
+use chord::state::NodeState;
+use chord::storage::{self, Key, Record, Storage};
+use chord::tenant::Tenants;
+use chord::{Node, Runtime};
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: u64 = 5000;
+const FINGERS: usize = 8;
+
+fn listen(addr: SocketAddr, routing: Routing<PeerInfo>, storage: Storage) {
+    let routing = Arc::new(Mutex::new(routing));
+    let storage = Arc::new(Mutex::new(storage));
+
+    let handler = P2PHandler::new(routing, storage, TIMEOUT);
+
+    Server::new(handler)
+        .listen(addr, 4)
+        .expect("could not bind to port");
+}
+
+fn create_network(addr: SocketAddr) {
+    let routing = Routing::from_addrs(addr, addr, addr, vec![addr; FINGERS]);
+
+    listen(addr, routing, Storage::new());
+}
+
+fn join_network(addr: SocketAddr, boot_addr: SocketAddr) {
+    let (routing, storage) = Bootstrap::new(addr, boot_addr, FINGERS)
+        .bootstrap(TIMEOUT)
+        .expect("could not join network");
+
+    listen(addr, routing, storage);
+}
+
+fn node_config(listen_address: &str, api_address: &str, weight: u16) -> Config {
+    Config {
+        listen_address: listen_address.parse().unwrap(),
+        api_address: api_address.parse().unwrap(),
+        worker_threads: 2,
+        max_worker_threads: None,
+        timeout: TIMEOUT,
+        fingers: FINGERS,
+        stabilization_interval: 60,
+        handoff_interval: 60,
+        sweep_interval: 60,
+        bootstrap_attempts: 10,
+        bootstrap_backoff: 100,
+        bootstrap_fallback: false,
+        websocket_address: None,
+        read_only: false,
+        weight,
+        identifier_scheme: IdentifierScheme::Ip,
+        port_range: 0,
+        rate_limit: None,
+        system_namespace: Namespace::SYSTEM,
+        slow_threshold: None,
+        hot_key_threshold: None,
+        api_cbor: false,
+        state_file: None,
+        lock_dir: None,
+        proxy: None,
+        authorizer: auth::allow_all(),
+        audit_log: None,
+        audit_log_max_size: None,
+        audit_log_files: 10,
+        seed: Some(1),
+        policies: Arc::new(Policies::new()),
+        tenants: Arc::new(Tenants::new()),
+        framing: framing::plain(),
+        socket_options: SocketOptions::default(),
+    }
+}
+
+fn keys() -> Vec<Key> {
+    (0..64)
+        .map(|i| Key {
+            raw_key: [i; 32],
+            replication_index: 0,
+        })
+        .collect()
+}
+
+fn responsible_peer(key: &Key, peers: &[SocketAddr]) -> SocketAddr {
+    let key_id = key.identifier();
+
+    *peers
+        .iter()
+        .find(|peer| {
+            let interval = IdentifierInterval::new(key_id, peer.identifier());
+
+            peers
+                .iter()
+                .all(|other| other == *peer || !interval.contains_open_closed(other.identifier()))
+        })
+        .unwrap()
+}
+
+fn find_successor(peer_addr: SocketAddr, identifier: Identifier) -> PeerFound {
+    let mut connection = Connection::open(peer_addr, TIMEOUT).unwrap();
+    connection
+        .send(&Message::PeerFind(PeerFind {
+            identifier,
+            trace: None,
+            budget: None,
+        }))
+        .unwrap();
+
+    match connection.receive().unwrap() {
+        Message::PeerFound(peer_found) => peer_found,
+        msg => panic!("expected PEER FOUND but got {}", msg),
+    }
+}
+
+fn assert_values_stored(peers: &[SocketAddr]) {
+    let procedures = Procedures::new(TIMEOUT);
+
+    for key in keys() {
+        let peer_addr = responsible_peer(&key, peers);
+        let value = procedures
+            .get_value(peer_addr, key)
+            .expect("responsible peer did not reply");
+
+        assert_eq!(Some(&[key.raw_key[0]][..]), value.as_deref(), "key {}", key);
+    }
+}
+
+fn put_values(peer_addr: SocketAddr) {
+    let procedures = Procedures::new(TIMEOUT);
+
+    for key in keys() {
+        let record = Record::new(
+            vec![key.raw_key[0]],
+            Duration::from_secs(60),
+            storage::current_version(),
+        );
+
+        procedures
+            .put_value(peer_addr, key, record)
+            .expect("could not store value");
+    }
+}
+
+#[test]
+fn join_transfers_values() {
+    let boot_addr = "127.0.1.1:38100".parse().unwrap();
+    let join_addr = "127.0.1.2:38100".parse().unwrap();
+
+    create_network(boot_addr);
+    put_values(boot_addr);
+
+    join_network(join_addr, boot_addr);
+
+    assert_values_stored(&[boot_addr, join_addr]);
+}
+
+#[test]
+fn concurrent_joins() {
+    let boot_addr = "127.0.2.1:38100".parse().unwrap();
+    let join_addrs: Vec<SocketAddr> = vec![
+        "127.0.2.2:38100".parse().unwrap(),
+        "127.0.2.3:38100".parse().unwrap(),
+        "127.0.2.4:38100".parse().unwrap(),
+    ];
+
+    create_network(boot_addr);
+    put_values(boot_addr);
+
+    let handles: Vec<_> = join_addrs
+        .iter()
+        .map(|&join_addr| thread::spawn(move || join_network(join_addr, boot_addr)))
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let mut peers = join_addrs;
+    peers.push(boot_addr);
+
+    assert_values_stored(&peers);
+}
+
+#[test]
+fn bootstrap_populates_fingers() {
+    let boot_addr = "127.0.2.5:38100".parse().unwrap();
+    let join_addr = "127.0.2.6:38100".parse().unwrap();
+
+    create_network(boot_addr);
+
+    let (routing, _) = Bootstrap::new(join_addr, boot_addr, FINGERS)
+        .bootstrap(TIMEOUT)
+        .expect("could not join network");
+
+    for i in 0..FINGERS {
+        let identifier = join_addr.identifier().successor_id(255 - i);
+
+        if !routing.responsible_for(identifier) {
+            assert_eq!(boot_addr, routing.closest_peer(identifier).socket_addr());
+        }
+    }
+}
+
+#[test]
+fn drain_transfers_values() {
+    let boot_addr = "127.0.2.7:38100".parse().unwrap();
+    let drain_addr = "127.0.2.8:38100".parse().unwrap();
+    let api_addr = "127.0.2.8:38101".parse().unwrap();
+
+    create_network(boot_addr);
+    put_values(boot_addr);
+
+    let (routing, storage) = Bootstrap::new(drain_addr, boot_addr, FINGERS)
+        .bootstrap(TIMEOUT)
+        .expect("could not join network");
+
+    let routing = Arc::new(Mutex::new(routing));
+    let storage = Arc::new(Mutex::new(storage));
+    let read_only = Arc::new(AtomicBool::new(false));
+    let (drain_notifier, drained) = mpsc::channel();
+
+    let p2p_handler = P2PHandler::new(Arc::clone(&routing), Arc::clone(&storage), TIMEOUT)
+        .with_read_only(Arc::clone(&read_only));
+    Server::new(p2p_handler)
+        .listen(drain_addr, 4)
+        .expect("could not bind to port");
+
+    let api_handler = ApiHandler::new(
+        routing,
+        Arc::clone(&storage),
+        Arc::new(Handoff::new(TIMEOUT)),
+        Arc::new(Metrics::new()),
+        TIMEOUT,
+    )
+    .with_read_only(read_only)
+    .with_drain_notifier(drain_notifier);
+    Server::new(api_handler)
+        .listen(api_addr, 1)
+        .expect("could not bind to port");
+
+    let records = ApiClient::new(api_addr, TIMEOUT)
+        .drain()
+        .expect("could not drain peer");
+
+    assert!(records > 0);
+    assert!(storage.lock().unwrap().is_empty());
+    // the notification is sent after the reply
+    assert!(drained.recv_timeout(Duration::from_secs(1)).is_ok());
+
+    assert_values_stored(&[boot_addr]);
+}
+
+#[test]
+fn stabilization_routes_around_dead_successor() {
+    let current_addr = "127.0.2.10:38100".parse().unwrap();
+    let dead_addr = "127.0.2.11:38100".parse().unwrap();
+    let alive_addr = "127.0.2.12:38100".parse().unwrap();
+
+    create_network(alive_addr);
+
+    // nobody listens on the address of the successor
+    let routing = Routing::from_addrs(
+        current_addr,
+        alive_addr,
+        dead_addr,
+        vec![alive_addr, dead_addr, dead_addr],
+    );
+    let routing = Arc::new(Mutex::new(routing));
+
+    Stabilization::new(Arc::clone(&routing), Arc::new(Metrics::new()), TIMEOUT)
+        .stabilize()
+        .expect("could not stabilize");
+
+    assert_eq!(alive_addr, routing.lock().unwrap().successor.socket_addr());
+}
+
+#[test]
+fn stabilization_continues_after_errors() {
+    let current_addr = "127.0.2.13:38100".parse().unwrap();
+    let slow_addr: SocketAddr = "127.0.2.14:38100".parse().unwrap();
+
+    // connections are accepted by the kernel but never answered
+    let _listener = TcpListener::bind(slow_addr).expect("could not bind to port");
+
+    let routing = Routing::from_addrs(
+        current_addr,
+        current_addr,
+        slow_addr,
+        vec![slow_addr; FINGERS],
+    );
+    let routing = Arc::new(Mutex::new(routing));
+
+    let report = Stabilization::new(Arc::clone(&routing), Arc::new(Metrics::new()), 100)
+        .stabilize()
+        .expect_err("stabilization should fail");
+
+    // every finger is attempted and the slow successor is kept
+    assert!(report.successor_error().is_some());
+    assert_eq!(FINGERS, report.finger_errors().len());
+    assert_eq!(slow_addr, routing.lock().unwrap().successor.socket_addr());
+}
+
+#[test]
+fn node_stabilizes_before_serving_api() {
+    let boot_config = node_config("127.0.2.15:38100", "127.0.2.15:38101", 1);
+    let join_config = node_config("127.0.2.16:38100", "127.0.2.16:38101", 1);
+
+    let boot_node = Node::start(boot_config.clone(), None).expect("could not start node");
+    Node::start(join_config.clone(), Some(boot_config.listen_address))
+        .expect("could not start node");
+
+    // the first stabilization of the joined peer already notified the bootstrap peer
+    let routing = boot_node.routing();
+    assert_eq!(
+        join_config.listen_address,
+        routing.lock().unwrap().successor.socket_addr()
+    );
+
+    let node_info = ApiClient::new(join_config.api_address, TIMEOUT)
+        .node_info()
+        .expect("api is not served");
+    assert_eq!(boot_config.listen_address, node_info.successor);
+}
+
+#[test]
+fn ring_walk_visits_every_peer() {
+    let configs = [
+        node_config("127.0.2.25:38100", "127.0.2.25:38101", 1),
+        node_config("127.0.2.26:38100", "127.0.2.26:38101", 1),
+        node_config("127.0.2.27:38100", "127.0.2.27:38101", 1),
+    ];
+
+    let boot_node = Node::start(configs[0].clone(), None).expect("could not start node");
+
+    let _nodes: Vec<Node> = configs[1..]
+        .iter()
+        .map(|config| {
+            Node::start(config.clone(), Some(boot_node.listen_address()))
+                .expect("could not start node")
+        })
+        .collect();
+
+    let client = ApiClient::new(boot_node.api_address(), TIMEOUT);
+
+    let first = client.ring_walk(2, None).expect("ring walk failed");
+    assert!(!first.complete);
+    assert_eq!(boot_node.listen_address(), first.peers[0].socket_addr);
+
+    let second = client
+        .ring_walk(2, first.next_cursor())
+        .expect("ring walk failed");
+    assert!(second.complete);
+
+    let mut walked: Vec<SocketAddr> = first
+        .peers
+        .iter()
+        .chain(second.peers.iter())
+        .map(|peer| peer.socket_addr)
+        .collect();
+    walked.sort();
+
+    let mut expected: Vec<SocketAddr> =
+        configs.iter().map(|config| config.listen_address).collect();
+    expected.sort();
+
+    assert_eq!(expected, walked);
+}
+
+#[test]
+fn port_range_skips_occupied_port() {
+    let occupied = TcpListener::bind("127.0.2.17:38100").unwrap();
+    let mut config = node_config("127.0.2.17:38100", "127.0.2.17:38110", 1);
+    config.port_range = 2;
+
+    let node = Node::start(config.clone(), None).expect("could not start node");
+
+    let bound_addr: SocketAddr = "127.0.2.17:38101".parse().unwrap();
+    assert_eq!(bound_addr, node.listen_address());
+    assert_eq!(config.api_address, node.api_address());
+
+    // the peer announces the address it is actually reachable at
+    let routing = node.routing();
+    assert_eq!(bound_addr, routing.lock().unwrap().current.socket_addr());
+
+    drop(occupied);
+}
+
+#[test]
+fn nodes_share_runtime() {
+    let runtime = Runtime::new(4);
+
+    let boot_config = node_config("127.0.2.18:38100", "127.0.2.18:38101", 1);
+    let join_config = node_config("127.0.2.19:38100", "127.0.2.19:38101", 1);
+
+    let boot_node = Node::start_in(boot_config, None, &runtime).expect("could not start node");
+    let join_node = Node::start_in(join_config, Some(boot_node.listen_address()), &runtime)
+        .expect("could not start node");
+
+    let routing = boot_node.routing();
+    assert_eq!(
+        join_node.listen_address(),
+        routing.lock().unwrap().successor.socket_addr()
+    );
+
+    for node in &[boot_node, join_node] {
+        let node_info = ApiClient::new(node.api_address(), TIMEOUT)
+            .node_info()
+            .expect("api is not served");
+        assert_ne!(node.listen_address(), node_info.successor);
+    }
+}
+
+#[test]
+fn stabilization_recovers_poisoned_routing() {
+    let current_addr = "127.0.2.20:38100".parse().unwrap();
+
+    let routing = Routing::from_addrs(
+        current_addr,
+        current_addr,
+        current_addr,
+        vec![current_addr; FINGERS],
+    );
+    let routing = Arc::new(Mutex::new(routing));
+
+    // a panic while holding the lock poisons the routing table
+    let poisoning = Arc::clone(&routing);
+    let _ = thread::spawn(move || {
+        let _routing = poisoning.lock().unwrap();
+        panic!("stabilization panic");
+    })
+    .join();
+    assert!(routing.is_poisoned());
+
+    let mut stabilization =
+        Stabilization::new(Arc::clone(&routing), Arc::new(Metrics::new()), TIMEOUT);
+    stabilization.recover();
+
+    assert!(routing.lock().is_ok());
+}
+
+#[test]
+fn join_resumes_transfer() {
+    let boot_addr: SocketAddr = "127.0.2.21:38100".parse().unwrap();
+    let join_addr: SocketAddr = "127.0.2.22:38100".parse().unwrap();
+
+    create_network(boot_addr);
+    put_values(boot_addr);
+
+    let range = IdentifierInterval::new(boot_addr.identifier(), join_addr.identifier());
+    let mut transferred: Vec<Key> = keys()
+        .into_iter()
+        .filter(|key| range.contains_open_closed(key.identifier()))
+        .collect();
+    transferred.sort();
+    assert!(transferred.len() > 1);
+
+    // pretend the first half has been received before the connection broke
+    let resume_after = transferred[transferred.len() / 2];
+
+    let mut con = Connection::open(boot_addr, TIMEOUT).unwrap();
+    con.send(&Message::JoinLock(JoinLock {
+        socket_addr: join_addr,
+        resume_after: Some(resume_after.into()),
+    }))
+    .unwrap();
+
+    let records = match con.receive().unwrap() {
+        Message::JoinAck(join_ack) => join_ack.records as usize,
+        msg => panic!("unexpected reply {}", msg),
+    };
+    assert_eq!(transferred.len() - transferred.len() / 2 - 1, records);
+
+    let mut raw_keys = Vec::new();
+
+    while raw_keys.len() < records {
+        match con.receive().unwrap().into_values() {
+            Ok(values) => raw_keys.extend(values.iter().map(|value| value.raw_key)),
+            Err(msg) => panic!("unexpected message {}", msg),
+        }
+    }
+
+    let expected: Vec<[u8; 32]> = transferred[transferred.len() / 2 + 1..]
+        .iter()
+        .map(|key| key.raw_key)
+        .collect();
+    assert_eq!(expected, raw_keys);
+}
+
+#[test]
+fn leave_resumes_transfer() {
+    let peer_addr: SocketAddr = "127.0.2.23:38100".parse().unwrap();
+    let leaving_addr: SocketAddr = "127.0.2.24:38100".parse().unwrap();
+
+    let routing = Routing::from_addrs(peer_addr, leaving_addr, leaving_addr, vec![peer_addr; 8]);
+    let storage = Arc::new(Mutex::new(Storage::new()));
+
+    Server::new(P2PHandler::new(
+        Arc::new(Mutex::new(routing)),
+        Arc::clone(&storage),
+        TIMEOUT,
+    ))
+    .listen(peer_addr, 2)
+    .expect("could not bind to port");
+
+    let records: Vec<(Key, Record)> = keys()
+        .into_iter()
+        .take(3)
+        .map(|key| {
+            (
+                key,
+                Record::new(vec![key.raw_key[0]], Duration::from_secs(60), 1),
+            )
+        })
+        .collect();
+
+    let storage_put = |(key, record): &(Key, Record)| {
+        Message::StoragePut(StoragePut {
+            ttl: 60_000,
+            replication_index: key.replication_index,
+            replicas: record.replicas,
+            namespace_len: 0,
+            raw_key: key.raw_key,
+            version: record.version,
+            value: record.value.clone(),
+        })
+    };
+    let peer_leave = |resume| {
+        Message::PeerLeave(PeerLeave {
+            socket_addr: leaving_addr,
+            predecessor_addr: peer_addr,
+            successor_addr: peer_addr,
+            records: 3,
+            resume,
+        })
+    };
+
+    // the connection breaks after two values
+    let mut con = Connection::open(peer_addr, TIMEOUT).unwrap();
+    con.send(&peer_leave(false)).unwrap();
+    con.send(&storage_put(&records[0])).unwrap();
+    con.send(&storage_put(&records[1])).unwrap();
+    con.shutdown().unwrap();
+
+    thread::sleep(Duration::from_millis(200));
+    assert!(storage.lock().unwrap().is_empty());
+
+    let mut con = Connection::open(peer_addr, TIMEOUT).unwrap();
+    con.send(&peer_leave(true)).unwrap();
+
+    let expected = TransferAck {
+        resume_after: Some(TransferCursor::from(records[1].0)),
+    };
+    assert_eq!(Message::TransferAck(expected), con.receive().unwrap());
+
+    con.send(&storage_put(&records[2])).unwrap();
+
+    match con.receive().unwrap() {
+        Message::PredecessorReply(predecessor_reply) => {
+            assert_eq!(peer_addr, predecessor_reply.socket_addr)
+        }
+        msg => panic!("unexpected reply {}", msg),
+    }
+
+    assert_eq!(3, storage.lock().unwrap().len());
+}
+
+#[test]
+fn node_rejoins_via_cached_neighbours() {
+    let state_file = env::temp_dir().join("chord-join-rejoin-state.ini");
+    let cached_file = env::temp_dir().join("chord-join-rejoin-cached.ini");
+
+    let boot_config = node_config("127.0.2.28:38100", "127.0.2.28:38101", 1);
+    let mut join_config = node_config("127.0.2.29:38100", "127.0.2.29:38101", 1);
+    join_config.state_file = Some(state_file.clone());
+
+    let boot_node = Node::start(boot_config, None).expect("could not start node");
+    Node::start(join_config, Some(boot_node.listen_address())).expect("could not start node");
+
+    let state = NodeState::load_from_file(&state_file).expect("state was not saved");
+    assert_eq!(boot_node.listen_address(), state.successor);
+
+    // the bootstrap peer is unreachable and only the cached neighbours help
+    fs::copy(&state_file, &cached_file).unwrap();
+    let mut rejoin_config = node_config("127.0.2.30:38100", "127.0.2.30:38101", 1);
+    rejoin_config.state_file = Some(cached_file.clone());
+
+    let unreachable = "127.0.2.31:38100".parse().unwrap();
+    let rejoin_node = Node::start(rejoin_config, Some(unreachable)).expect("could not rejoin");
+
+    let routing = rejoin_node.routing();
+    assert_ne!(
+        rejoin_node.listen_address(),
+        routing.lock().unwrap().successor.socket_addr()
+    );
+
+    fs::remove_file(state_file).unwrap();
+    fs::remove_file(cached_file).unwrap();
+}
+
+/// Runs a SOCKS5 proxy which tunnels IPv4 connections and counts them.
+fn socks5_relay(addr: &str) -> Arc<AtomicUsize> {
+    let listener = TcpListener::bind(addr).unwrap();
+    let tunnels = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&tunnels);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut client = stream.unwrap();
+            counter.fetch_add(1, Ordering::SeqCst);
+
+            thread::spawn(move || {
+                let mut greeting = [0; 3];
+                client.read_exact(&mut greeting).unwrap();
+                client.write_all(&[5, 0]).unwrap();
+
+                let mut request = [0; 10];
+                client.read_exact(&mut request).unwrap();
+                let ip = Ipv4Addr::new(request[4], request[5], request[6], request[7]);
+                let port = u16::from_be_bytes([request[8], request[9]]);
+
+                let mut target = TcpStream::connect((ip, port)).unwrap();
+                client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+
+                let mut client_reader = client.try_clone().unwrap();
+                let mut target_writer = target.try_clone().unwrap();
+                thread::spawn(move || io::copy(&mut client_reader, &mut target_writer));
+
+                let _ = io::copy(&mut target, &mut client);
+            });
+        }
+    });
+
+    tunnels
+}
+
+#[test]
+fn node_joins_through_proxy() {
+    let tunnels = socks5_relay("127.0.2.34:38100");
+
+    let boot_config = node_config("127.0.2.32:38100", "127.0.2.32:38101", 1);
+    let mut join_config = node_config("127.0.2.33:38100", "127.0.2.33:38101", 1);
+    join_config.proxy = Some(Proxy::Socks5("127.0.2.34:38100".parse().unwrap()));
+
+    let boot_node = Node::start(boot_config, None).expect("could not start node");
+    let join_node = Node::start(join_config, Some(boot_node.listen_address()))
+        .expect("could not join through proxy");
+
+    let routing = join_node.routing();
+    assert_eq!(
+        boot_node.listen_address(),
+        routing.lock().unwrap().successor.socket_addr()
+    );
+
+    assert!(tunnels.load(Ordering::SeqCst) > 0);
+}
+
+#[test]
+fn node_joins_with_obfuscated_framing() {
+    let obfuscated = framing::from_name("obfuscated").unwrap();
+
+    let mut boot_config = node_config("127.0.2.35:38100", "127.0.2.35:38101", 1);
+    boot_config.framing = Arc::clone(&obfuscated);
+    let mut join_config = node_config("127.0.2.36:38100", "127.0.2.36:38101", 1);
+    join_config.framing = Arc::clone(&obfuscated);
+
+    let boot_node = Node::start(boot_config, None).expect("could not start node");
+    let join_node = Node::start(join_config, Some(boot_node.listen_address()))
+        .expect("could not join with obfuscated framing");
+
+    let routing = join_node.routing();
+    assert_eq!(
+        boot_node.listen_address(),
+        routing.lock().unwrap().successor.socket_addr()
+    );
+
+    // a peer with plain framing cannot talk to them
+    let mut connection = Connection::open(boot_node.listen_address(), TIMEOUT).unwrap();
+    connection
+        .send(&Message::PeerFind(PeerFind {
+            identifier: boot_node.listen_address().identifier(),
+            trace: None,
+            budget: None,
+        }))
+        .unwrap();
+    assert!(connection.receive().is_err());
+}
+
+#[test]
+fn node_joins_by_solving_puzzles() {
+    let puzzle: Arc<dyn Framing> = Arc::new(Puzzle::new(framing::plain(), 8));
+
+    let mut boot_config = node_config("127.0.2.37:38100", "127.0.2.37:38101", 1);
+    boot_config.framing = Arc::clone(&puzzle);
+    let mut join_config = node_config("127.0.2.38:38100", "127.0.2.38:38101", 1);
+    join_config.framing = Arc::clone(&puzzle);
+
+    let boot_node = Node::start(boot_config, None).expect("could not start node");
+    let join_node = Node::start(join_config, Some(boot_node.listen_address()))
+        .expect("could not join by solving puzzles");
+
+    let routing = join_node.routing();
+    assert_eq!(
+        boot_node.listen_address(),
+        routing.lock().unwrap().successor.socket_addr()
+    );
+
+    // a peer which does not solve the puzzle is not served
+    let mut connection = Connection::open(boot_node.listen_address(), TIMEOUT).unwrap();
+    connection
+        .send(&Message::PeerFind(PeerFind {
+            identifier: boot_node.listen_address().identifier(),
+            trace: None,
+            budget: None,
+        }))
+        .unwrap();
+    assert!(connection.receive().is_err());
+}
+
+#[test]
+fn node_refuses_second_instance() {
+    let lock_dir = env::temp_dir().join("chord-join-second-instance");
+    fs::create_dir_all(&lock_dir).unwrap();
+
+    let mut config = node_config("127.0.2.39:8080", "127.0.2.39:8081", 1);
+    config.lock_dir = Some(lock_dir);
+
+    let _node = Node::start(config.clone(), None).expect("could not start node");
+
+    let err = Node::start(config, None)
+        .err()
+        .expect("second node with the same address started");
+    let locked = err
+        .downcast_ref::<LockedError>()
+        .expect("second node not refused by its lock");
+    assert_eq!(Some(process::id()), locked.pid());
+}
+
+#[test]
+fn self_lookup_resolves_to_self() {
+    let boot_config = node_config("127.0.2.40:38100", "127.0.2.40:38101", 1);
+    let join_config = node_config("127.0.2.41:38100", "127.0.2.41:38101", 1);
+
+    let boot_node = Node::start(boot_config.clone(), None).expect("could not start node");
+    let join_node =
+        Node::start(join_config, Some(boot_config.listen_address)).expect("could not start node");
+
+    let metrics = Arc::new(Metrics::new());
+    let stabilization = Stabilization::new(join_node.routing(), Arc::clone(&metrics), TIMEOUT);
+
+    assert!(stabilization.check_self_lookup(&SeededRandom::new(1)));
+    assert!(metrics.is_ring_consistent());
+    assert!(boot_node.is_ring_consistent());
+}
+
+#[test]
+fn self_lookup_detects_inconsistent_ring() {
+    let current_addr = "127.0.2.42:38100".parse().unwrap();
+    let other_addr = "127.0.2.43:38100".parse().unwrap();
+
+    // the other peer does not know about the current one and considers
+    // itself responsible for all identifiers
+    create_network(other_addr);
+
+    let routing = Routing::from_addrs(
+        current_addr,
+        other_addr,
+        other_addr,
+        vec![other_addr; FINGERS],
+    );
+    let metrics = Arc::new(Metrics::new());
+    let stabilization =
+        Stabilization::new(Arc::new(Mutex::new(routing)), Arc::clone(&metrics), TIMEOUT);
+
+    assert!(!stabilization.check_self_lookup(&SeededRandom::new(1)));
+    assert!(!metrics.is_ring_consistent());
+    assert_eq!(1, metrics.stats().snapshot().self_lookup_failures);
+}
+
+#[test]
+fn load_compared_with_other_peers() {
+    let boot_config = node_config("127.0.2.44:38100", "127.0.2.44:38101", 1);
+    let join_config = node_config("127.0.2.45:38100", "127.0.2.45:38101", 1);
+
+    let _boot_node = Node::start(boot_config.clone(), None).expect("could not start node");
+    let join_node =
+        Node::start(join_config, Some(boot_config.listen_address)).expect("could not start node");
+
+    let reply = ApiClient::new(join_node.api_address(), TIMEOUT)
+        .node_load(8)
+        .unwrap();
+
+    assert_eq!(1, reply.sampled);
+    assert_eq!(1, reply.weight);
+    assert!(reply.target_weight >= 1);
+    assert!(reply.local.share < u64::MAX);
+    assert!(reply.average.share > 0);
+}
+
+#[test]
+fn hot_keys_are_cached_at_requesting_peers() {
+    let mut boot_config = node_config("127.0.2.48:38100", "127.0.2.48:38101", 1);
+    boot_config.hot_key_threshold = Some(2);
+    let mut join_config = node_config("127.0.2.49:38100", "127.0.2.49:38101", 1);
+    join_config.hot_key_threshold = Some(2);
+
+    let boot_node = Node::start(boot_config.clone(), None).expect("could not start node");
+    let join_node =
+        Node::start(join_config, Some(boot_config.listen_address)).expect("could not start node");
+
+    let key = DhtKey::from([3; 32]);
+    let value = DhtValue::new(vec![1, 2, 3]).unwrap();
+
+    // read through the peer which is not responsible for the key
+    let (responsible, _) = ApiClient::new(boot_node.api_address(), TIMEOUT)
+        .resolve(key)
+        .unwrap()
+        .unwrap();
+    let requester = if responsible == boot_node.listen_address() {
+        &join_node
+    } else {
+        &boot_node
+    };
+    let client = ApiClient::new(requester.api_address(), TIMEOUT);
+
+    client
+        .put_acknowledged(key, value.clone(), 60, 0, 1)
+        .unwrap();
+
+    // the second read makes the key hot
+    for _ in 0..2 {
+        assert_eq!(Some(value.clone()), client.get(key).unwrap());
+    }
+
+    // wait for the copy to be pushed
+    thread::sleep(Duration::from_millis(100));
+
+    let replica = Key {
+        raw_key: [3; 32],
+        replication_index: 0,
+    };
+    assert!(Procedures::new(TIMEOUT)
+        .delete_value(responsible, replica)
+        .unwrap());
+
+    // the copy still answers reads until it expires
+    assert_eq!(Some(value), client.get(key).unwrap());
+}
+
+#[test]
+fn lookups_counted_per_finger() {
+    let boot_config = node_config("127.0.2.50:38100", "127.0.2.50:38101", 1);
+    let join_config = node_config("127.0.2.51:38100", "127.0.2.51:38101", 1);
+
+    let boot_node = Node::start(boot_config.clone(), None).expect("could not start node");
+    let join_node =
+        Node::start(join_config, Some(boot_config.listen_address)).expect("could not start node");
+
+    let key = DhtKey::from([5; 32]);
+
+    // resolve through the peer which is not responsible for the key
+    let (responsible, _) = ApiClient::new(boot_node.api_address(), TIMEOUT)
+        .resolve(key)
+        .unwrap()
+        .unwrap();
+    let requester = if responsible == boot_node.listen_address() {
+        &join_node
+    } else {
+        &boot_node
+    };
+    let before: u64 = requester
+        .finger_stats()
+        .iter()
+        .map(|finger| finger.routed)
+        .sum();
+
+    ApiClient::new(requester.api_address(), TIMEOUT)
+        .resolve(key)
+        .unwrap()
+        .unwrap();
+
+    // with two peers, every finger points to the responsible peer
+    let stats = requester.finger_stats();
+    let routed: u64 = stats.iter().map(|finger| finger.routed).sum();
+    assert_eq!(before + 1, routed);
+    assert!(stats.iter().all(|finger| finger.direct == finger.routed));
+}
+
+#[test]
+fn bootstrap_reports_unreachable_peer() {
+    let addr = "127.0.2.60:38100".parse().unwrap();
+    // nothing listens on this address
+    let boot_addr = "127.0.2.61:38100".parse().unwrap();
+
+    let err = Bootstrap::new(addr, boot_addr, FINGERS)
+        .with_retry(3, Duration::from_millis(10))
+        .bootstrap(TIMEOUT)
+        .unwrap_err();
+
+    let err = err
+        .downcast_ref::<BootstrapError>()
+        .expect("expected a bootstrap error");
+    assert_eq!(BootstrapFailure::Unreachable, err.failure());
+    assert_eq!(3, err.attempts());
+    assert!(err.to_string().contains("127.0.2.61:38100"));
+}
+
+#[test]
+fn bootstrap_reports_wrong_network() {
+    // a service which is not a peer answers with something else
+    let listener = TcpListener::bind("127.0.2.62:38100").unwrap();
+    let boot_addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n");
+        }
+    });
+
+    let addr = "127.0.2.63:38100".parse().unwrap();
+    let err = Bootstrap::new(addr, boot_addr, FINGERS)
+        .with_retry(2, Duration::from_millis(10))
+        .bootstrap(TIMEOUT)
+        .unwrap_err();
+
+    let err = err
+        .downcast_ref::<BootstrapError>()
+        .expect("expected a bootstrap error");
+    assert_eq!(BootstrapFailure::WrongNetwork, err.failure());
+}
+
+#[test]
+fn node_falls_back_to_new_network() {
+    let mut config = node_config("127.0.2.64:38100", "127.0.2.64:38101", 1);
+    config.bootstrap_attempts = 2;
+    config.bootstrap_backoff = 10;
+    config.bootstrap_fallback = true;
+
+    let node = Node::start(config, Some("127.0.2.65:38100".parse().unwrap()))
+        .expect("could not fall back to a new network");
+
+    let routing = node.routing();
+    assert_eq!(
+        node.listen_address(),
+        routing.lock().unwrap().successor.socket_addr()
+    );
+}
+
+#[test]
+fn lookups_find_owner_right_after_joins() {
+    let boot_addr = "127.0.2.66:38100".parse().unwrap();
+    let join_addrs: Vec<SocketAddr> = vec![
+        "127.0.2.67:38100".parse().unwrap(),
+        "127.0.2.68:38100".parse().unwrap(),
+        "127.0.2.69:38100".parse().unwrap(),
+        "127.0.2.70:38100".parse().unwrap(),
+        "127.0.2.71:38100".parse().unwrap(),
+        "127.0.2.72:38100".parse().unwrap(),
+        "127.0.2.73:38100".parse().unwrap(),
+        "127.0.2.74:38100".parse().unwrap(),
+        "127.0.2.75:38100".parse().unwrap(),
+    ];
+
+    create_network(boot_addr);
+
+    let mut peers = vec![boot_addr];
+    let procedures = Procedures::new(TIMEOUT);
+
+    // no peer stabilizes, thus the routing tables only contain what the
+    // joins told them
+    for join_addr in join_addrs {
+        join_network(join_addr, boot_addr);
+        peers.push(join_addr);
+
+        for key in keys() {
+            let owner = responsible_peer(&key, &peers);
+
+            for &peer_addr in &peers {
+                let found = procedures
+                    .find_peer(key.identifier(), peer_addr)
+                    .expect("lookup failed");
+
+                assert_eq!(owner, found, "key {} from {}", key, peer_addr);
+
+                // only the owner replies authoritatively
+                let peer_found = find_successor(peer_addr, key.identifier());
+                assert_eq!(peer_addr == owner, peer_found.authoritative);
+            }
+        }
+    }
+}
+
+#[test]
+fn stale_successor_does_not_decide_owner() {
+    let mut addrs: Vec<SocketAddr> = vec![
+        "127.0.2.76:38100".parse().unwrap(),
+        "127.0.2.77:38100".parse().unwrap(),
+        "127.0.2.78:38100".parse().unwrap(),
+    ];
+    addrs.sort_by_key(|addr| addr.identifier());
+    let (first, joined, last) = (addrs[0], addrs[1], addrs[2]);
+
+    // the joined peer took over part of the range of the last peer, which
+    // updated its predecessor, but the first peer did not stabilize yet
+    let routings = vec![
+        (
+            first,
+            Routing::from_addrs(first, last, last, vec![last; FINGERS]),
+        ),
+        (
+            joined,
+            Routing::from_addrs(joined, first, last, vec![last; FINGERS]),
+        ),
+        (
+            last,
+            Routing::from_addrs(last, joined, first, vec![first; FINGERS]),
+        ),
+    ];
+
+    for (addr, routing) in routings {
+        listen(addr, routing, Storage::new());
+    }
+
+    let peer_found = find_successor(first, joined.identifier());
+    assert_eq!(last, peer_found.socket_addr);
+    assert!(!peer_found.authoritative);
+
+    let owner = Procedures::new(TIMEOUT)
+        .find_peer(joined.identifier(), first)
+        .unwrap();
+    assert_eq!(joined, owner);
+}
+
+#[test]
+fn identifier_collision_is_rejected() {
+    let boot_addr = "127.0.2.79:38100".parse().unwrap();
+    // a second peer behind the same ip address
+    let join_addr = "127.0.2.79:38102".parse().unwrap();
+
+    create_network(boot_addr);
+
+    let err = Bootstrap::new(join_addr, boot_addr, FINGERS)
+        .with_retry(3, Duration::from_millis(10))
+        .bootstrap(TIMEOUT)
+        .unwrap_err();
+
+    let err = err
+        .downcast_ref::<BootstrapError>()
+        .expect("expected a bootstrap error");
+    assert_eq!(BootstrapFailure::Collision, err.failure());
+    assert_eq!(1, err.attempts());
+    assert!(err.to_string().contains("ip-port"));
+
+    // the ring still consists of the first peer only
+    assert_eq!(
+        boot_addr,
+        Procedures::new(TIMEOUT)
+            .find_peer(join_addr.identifier(), boot_addr)
+            .unwrap()
+    );
+}
@@ -0,0 +1,42 @@
+extern crate chord;
+
+use chord::chaos::{Chaos, ChaosConfig};
+use chord::clock::Clock;
+use std::time::{Duration, Instant};
+
+#[test]
+fn values_expire_in_virtual_time() {
+    let config = ChaosConfig {
+        nodes: 4,
+        kill_rate: 0.0,
+        puts: 8,
+        stabilizations: 2,
+        fingers: 16,
+        seed: 7,
+        timeout: 5000,
+        stabilization_interval: 3600,
+    };
+
+    let mut chaos = Chaos::new(config).expect("could not launch ring");
+    assert_eq!(Vec::<String>::new(), chaos.round());
+
+    let clock = chaos.clock();
+    let virtual_start = clock.now();
+    let real_start = Instant::now();
+
+    // values are stored with the maximum time to live
+    chaos.advance(Duration::from_secs(u64::from(u16::MAX) - 1));
+    assert_eq!(0, chaos.expired());
+
+    chaos.advance(Duration::from_secs(1));
+    assert_eq!(8, chaos.expired());
+
+    assert_eq!(
+        Duration::from_secs(u64::from(u16::MAX)),
+        clock.now() - virtual_start
+    );
+    assert!(real_start.elapsed() < Duration::from_secs(60));
+
+    // the ring kept stabilizing while the time passed
+    assert_eq!(Vec::<String>::new(), chaos.round());
+}
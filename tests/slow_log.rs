@@ -0,0 +1,38 @@
+extern crate chord;
+
+use chord::metrics::Metrics;
+use chord::procedures::Procedures;
+use chord::routing::identifier::Identifier;
+use chord::storage::Key;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+const TIMEOUT: u64 = 5000;
+
+#[test]
+fn log_slow_operations() {
+    // nobody listens at this address, thus every request fails
+    let peer_addr: SocketAddr = "127.0.8.1:38100".parse().unwrap();
+    let key = Key {
+        raw_key: [6; 32],
+        replication_index: 0,
+    };
+
+    let metrics = Arc::new(Metrics::new().with_slow_threshold(Some(Duration::from_secs(0))));
+    let procedures = Procedures::with_metrics(TIMEOUT, Arc::clone(&metrics));
+
+    assert!(procedures.get_value(peer_addr, key).is_err());
+    assert_eq!(1, metrics.slow_log().count());
+
+    assert!(procedures
+        .find_peer(Identifier::new(&[6; 32]), peer_addr)
+        .is_err());
+    assert_eq!(2, metrics.slow_log().count());
+
+    let metrics = Arc::new(Metrics::new());
+    let procedures = Procedures::with_metrics(TIMEOUT, Arc::clone(&metrics));
+
+    assert!(procedures.get_value(peer_addr, key).is_err());
+    assert_eq!(0, metrics.slow_log().count());
+}
@@ -0,0 +1,173 @@
+extern crate chord;
+extern crate serde_json;
+
+use chord::dht::{DhtKey, DhtValue};
+use chord::gateway::{self, Reply};
+use chord::handler::{ApiHandler, P2PHandler, WebSocketHandler};
+use chord::handoff::Handoff;
+use chord::message::api::{DhtGet, DhtPut};
+use chord::message::Message;
+use chord::metrics::Metrics;
+use chord::network::Server;
+use chord::routing::Routing;
+use chord::storage::Storage;
+use chord::websocket::{Opcode, WebSocket};
+use serde_json::{json, Value};
+use std::io::Cursor;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+const TIMEOUT: u64 = 5000;
+const FINGERS: usize = 8;
+
+fn create_network(ip: &str) -> WebSocket {
+    let p2p_addr: SocketAddr = format!("{}:38100", ip).parse().unwrap();
+    let api_addr: SocketAddr = format!("{}:38101", ip).parse().unwrap();
+    let websocket_addr: SocketAddr = format!("{}:38102", ip).parse().unwrap();
+
+    let routing = Routing::from_addrs(p2p_addr, p2p_addr, p2p_addr, vec![p2p_addr; FINGERS]);
+    let routing = Arc::new(Mutex::new(routing));
+    let storage = Arc::new(Mutex::new(Storage::new()));
+
+    let p2p_handler = P2PHandler::new(Arc::clone(&routing), Arc::clone(&storage), TIMEOUT);
+    Server::new(p2p_handler)
+        .listen(p2p_addr, 4)
+        .expect("could not bind to port");
+
+    let api_handler = ApiHandler::new(
+        routing,
+        storage,
+        Arc::new(Handoff::new(TIMEOUT)),
+        Arc::new(Metrics::new()),
+        TIMEOUT,
+    );
+    Server::new(api_handler)
+        .listen(api_addr, 1)
+        .expect("could not bind to port");
+
+    Server::new(WebSocketHandler::new(api_addr, TIMEOUT))
+        .listen(websocket_addr, 2)
+        .expect("could not bind to port");
+
+    WebSocket::connect(websocket_addr, TIMEOUT).expect("could not connect")
+}
+
+fn send_json(ws: &mut WebSocket, request: Value) -> Value {
+    ws.send(Opcode::Text, request.to_string().into_bytes())
+        .unwrap();
+
+    let (opcode, payload) = ws.receive().unwrap();
+    assert_eq!(Opcode::Text, opcode);
+
+    serde_json::from_slice(&payload).unwrap()
+}
+
+fn send_binary(ws: &mut WebSocket, msg: Message) {
+    let mut buffer = Cursor::new(Vec::new());
+    msg.write_to(&mut buffer).unwrap();
+
+    ws.send(Opcode::Binary, buffer.into_inner()).unwrap();
+}
+
+#[test]
+fn json_put_and_get() {
+    let mut ws = create_network("127.0.5.1");
+    let key = DhtKey::from_name("dashboard");
+
+    let reply = send_json(
+        &mut ws,
+        json!({ "type": "put", "key": key.to_base64(), "value": "aGVsbG8=", "replication": 0 }),
+    );
+    assert_eq!(json!({ "type": "success" }), reply);
+
+    let reply = send_json(&mut ws, json!({ "type": "get", "key": key.to_base64() }));
+    assert_eq!(json!({ "type": "success", "value": "aGVsbG8=" }), reply);
+
+    let reply = send_json(
+        &mut ws,
+        json!({ "type": "get", "key": DhtKey::from_name("missing").to_base64() }),
+    );
+    assert_eq!(json!({ "type": "failure" }), reply);
+}
+
+#[test]
+fn json_invalid_request() {
+    let mut ws = create_network("127.0.5.2");
+
+    let reply = send_json(&mut ws, json!({ "type": "get" }));
+    assert_eq!("error", reply["type"]);
+
+    let reply = send_json(&mut ws, json!({ "type": "list", "key": "AA==" }));
+    assert_eq!("error", reply["type"]);
+}
+
+#[test]
+fn binary_put_and_get() {
+    let mut ws = create_network("127.0.5.3");
+    let key = DhtKey::from([3; 32]);
+    let value = DhtValue::new(vec![1, 2, 3]).unwrap();
+
+    send_binary(
+        &mut ws,
+        Message::DhtPut(DhtPut {
+            ttl: 60_000,
+            replication: 0,
+            acks: 0,
+            key,
+            value: value.clone(),
+        }),
+    );
+
+    send_binary(
+        &mut ws,
+        Message::DhtGet(DhtGet {
+            key,
+            quorum: None,
+            budget: None,
+            request_id: None,
+            if_none_match: None,
+        }),
+    );
+
+    let (opcode, payload) = ws.receive().unwrap();
+    assert_eq!(Opcode::Binary, opcode);
+
+    match Message::parse(Cursor::new(payload)).unwrap() {
+        Message::DhtSuccess(dht_success) => {
+            assert_eq!(key, dht_success.key);
+            assert_eq!(value, dht_success.value);
+        }
+        msg => panic!("unexpected message {:?}", msg),
+    }
+}
+
+#[test]
+fn gateway_requests_in_order() {
+    let mut ws = create_network("127.0.5.4");
+    let key = DhtKey::from([4; 32]);
+    let value = DhtValue::new(vec![1, 2, 3]).unwrap();
+
+    // the requests are sent before any reply has been read
+    let requests = vec![
+        gateway::encode_put(&key, &value, 60, 1).unwrap(),
+        gateway::encode_get(&key),
+        gateway::encode_get(&DhtKey::from([5; 32])),
+    ];
+    for request in requests {
+        ws.send(Opcode::Binary, request).unwrap();
+    }
+
+    let mut reply = || {
+        let (opcode, payload) = ws.receive().unwrap();
+        assert_eq!(Opcode::Binary, opcode);
+
+        gateway::decode_reply(&payload).unwrap()
+    };
+
+    match reply() {
+        Reply::Stored { acks, .. } => assert!(acks >= 1),
+        reply => panic!("unexpected reply {:?}", reply),
+    }
+    assert_eq!(Reply::Value(value), reply());
+    assert_eq!(Reply::Failure, reply());
+}